@@ -0,0 +1,20 @@
+out vec4 FragColor;
+
+in vec2 TexCoord;
+
+uniform sampler2D screenTexture;
+// One texel across, along the direction this pass blurs in
+uniform vec2 direction;
+
+// 9 tap gaussian, the outer taps read between texels so linear filtering blends two at once
+const float offsets[3] = float[](0.0, 1.3846153846, 3.2307692308);
+const float weights[3] = float[](0.2270270270, 0.3162162162, 0.0702702703);
+
+void main() {
+    vec3 color = texture(screenTexture, TexCoord).rgb * weights[0];
+    for (int i = 1; i < 3; i++) {
+        color += texture(screenTexture, TexCoord + direction * offsets[i]).rgb * weights[i];
+        color += texture(screenTexture, TexCoord - direction * offsets[i]).rgb * weights[i];
+    }
+    FragColor = vec4(color, 1.0);
+}
@@ -0,0 +1,15 @@
+out vec4 FragColor;
+
+in vec2 TexCoord;
+
+uniform sampler2D screenTexture;
+// Brightness past which pixels bloom
+uniform float threshold;
+
+void main() {
+    vec3 color = texture(screenTexture, TexCoord).rgb;
+    float brightness = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    // Soft knee so pixels don't pop in at the threshold
+    float amount = clamp((brightness - threshold) / max(brightness, 0.0001), 0.0, 1.0);
+    FragColor = vec4(color * amount, 1.0);
+}
@@ -2,11 +2,15 @@ layout (location = 0) in vec3 aPos;
 layout (location = 1) in vec3 aColor;
 layout (location = 2) in vec2 aTexCoord;
 layout (location = 3) in vec3 aNormal;
+// Brush face alignment, zero when a mesh doesn't have it
+layout (location = 4) in vec3 aFaceOffset;
+layout (location = 5) in vec2 aFaceScale;
 
 out vec3 vertexColor;
 out vec2 TexCoord;
 flat out uint fullbright;
 flat out uint cutout;
+flat out uint animated;
 out vec3 normal;
 out vec3 fragPos;
 
@@ -15,6 +19,8 @@ uniform mat4 model;
 uniform mat3 normal_matrix;
 uniform mat4 view;
 uniform mat4 projection;
+// Only used while the reflection is drawn, keeps what is behind the reflection plane out of it
+uniform vec4 clipPlane;
 
 const float TEXTURE_LOOP_DIV = 2.0f;
 
@@ -23,10 +29,12 @@ void main() {
     vertexColor = aColor;
     uint extend_texture = flags & 1;
     fullbright = flags & 2;
-    // skip is unused for this shader
+    // skip is handled on the cpu for this shader
     cutout = flags & 8;
+    animated = flags & 32;
     
     fragPos = vec3(model * vec4(aPos, 1.0));
+    gl_ClipDistance[0] = dot(vec4(fragPos, 1.0), clipPlane);
     normal = normal_matrix * aNormal;
     // normal = mat3(transpose(inverse(model))) * aNormal;
 
@@ -48,4 +56,9 @@ void main() {
     } else {
         TexCoord = aTexCoord;
     }
+
+    // Scale is stored as a power of two, rotation in radians
+    float c = cos(aFaceOffset.z);
+    float s = sin(aFaceOffset.z);
+    TexCoord = mat2(c, s, -s, c) * (TexCoord / exp2(aFaceScale)) + aFaceOffset.xy;
 }
\ No newline at end of file
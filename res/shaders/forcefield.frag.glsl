@@ -0,0 +1,38 @@
+// Material shader for the forcefield brush type, drawn with the flat vertex shader
+out vec4 FragColor;
+
+in vec3 vertexColor;
+in vec2 TexCoord;
+flat in uint fullbright;
+flat in uint cutout;
+in vec3 normal;
+in vec3 fragPos;
+
+struct Material {
+    sampler2D diffuse;
+    sampler2D specular;
+    float shininess;
+};
+
+uniform Material material;
+uniform vec3 viewPos;
+uniform vec4 tint;
+uniform float time;
+
+// From the brush type's "uniforms"
+uniform vec3 color;
+uniform float speed;
+uniform float bands;
+
+void main() {
+    vec3 norm = normalize(normal);
+    vec3 viewDir = normalize(viewPos - fragPos);
+
+    // Bright at glancing angles, with bands scrolling up the surface
+    float rim = pow(1.0 - abs(dot(norm, viewDir)), 2.0);
+    float band = 0.5 + 0.5 * sin((fragPos.y * bands - time * speed) * 6.2831);
+    float sparkle = texture(material.diffuse, TexCoord + vec2(0.0, time * speed * 0.1)).r;
+
+    FragColor = vec4(color * (0.25 + rim + 0.35 * band + 0.2 * sparkle), 1.0);
+    FragColor.rgb = mix(FragColor.rgb, tint.rgb, tint.a);
+}
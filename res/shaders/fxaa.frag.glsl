@@ -0,0 +1,51 @@
+out vec4 FragColor;
+
+in vec2 TexCoord;
+
+uniform sampler2D screenTexture;
+uniform vec2 screenSize;
+
+// Fast approximate antialiasing, blurs along edges found from luma, used where multisampling isn't available
+// https://github.com/mattdesl/glsl-fxaa
+const float FXAA_REDUCE_MIN = 1.0 / 128.0;
+const float FXAA_REDUCE_MUL = 1.0 / 8.0;
+const float FXAA_SPAN_MAX = 8.0;
+
+void main() {
+    vec2 texel = 1.0 / screenSize;
+    vec3 rgbNW = texture(screenTexture, TexCoord + vec2(-1.0, -1.0) * texel).rgb;
+    vec3 rgbNE = texture(screenTexture, TexCoord + vec2(1.0, -1.0) * texel).rgb;
+    vec3 rgbSW = texture(screenTexture, TexCoord + vec2(-1.0, 1.0) * texel).rgb;
+    vec3 rgbSE = texture(screenTexture, TexCoord + vec2(1.0, 1.0) * texel).rgb;
+    vec3 rgbM = texture(screenTexture, TexCoord).rgb;
+
+    vec3 luma = vec3(0.299, 0.587, 0.114);
+    float lumaNW = dot(rgbNW, luma);
+    float lumaNE = dot(rgbNE, luma);
+    float lumaSW = dot(rgbSW, luma);
+    float lumaSE = dot(rgbSE, luma);
+    float lumaM = dot(rgbM, luma);
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+
+    vec2 dir = vec2(
+        -((lumaNW + lumaNE) - (lumaSW + lumaSE)),
+        (lumaNW + lumaSW) - (lumaNE + lumaSE)
+    );
+    float dirReduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * (0.25 * FXAA_REDUCE_MUL), FXAA_REDUCE_MIN);
+    float rcpDirMin = 1.0 / (min(abs(dir.x), abs(dir.y)) + dirReduce);
+    dir = clamp(dir * rcpDirMin, vec2(-FXAA_SPAN_MAX), vec2(FXAA_SPAN_MAX)) * texel;
+
+    vec3 rgbA = 0.5 * (
+        texture(screenTexture, TexCoord + dir * (1.0 / 3.0 - 0.5)).rgb +
+        texture(screenTexture, TexCoord + dir * (2.0 / 3.0 - 0.5)).rgb
+    );
+    vec3 rgbB = rgbA * 0.5 + 0.25 * (
+        texture(screenTexture, TexCoord + dir * -0.5).rgb +
+        texture(screenTexture, TexCoord + dir * 0.5).rgb
+    );
+
+    // The wider sample reached past the edge, keep the narrow one
+    float lumaB = dot(rgbB, luma);
+    FragColor = vec4((lumaB < lumaMin || lumaB > lumaMax) ? rgbA : rgbB, 1.0);
+}
@@ -3,6 +3,8 @@ out vec4 FragColor;
 in vec3 vertexColor;
 in vec2 TexCoord;
 flat in uint fullbright;
+flat in uint cutout;
+flat in uint animated;
 in vec3 normal;
 in vec3 fragPos;
 
@@ -10,6 +12,11 @@ struct Material {
     sampler2D diffuse;
     sampler2D specular;
     float shininess;
+    // How much of the reflection covers the surface
+    float reflectivity;
+    // Added after lighting, emission tints the texture and is zero for materials that don't glow
+    sampler2D emissive;
+    vec3 emission;
 };
 
 uniform Material material;
@@ -33,34 +40,137 @@ struct PointLight {
     vec3 ambient;
     vec3 diffuse;
     vec3 specular;
+
+    // Index into shadowMaps, below 0 for lights without a shadow
+    int shadow;
 };
 #define MAX_POINT_LIGHTS 64
 uniform PointLight pointLights[MAX_POINT_LIGHTS];
 uniform int pointLightCount;
 
+// Distance to the nearest surface from each shadow casting light, over that light's far plane
+#define MAX_SHADOW_LIGHTS 4
+uniform samplerCube shadowMaps[MAX_SHADOW_LIGHTS];
+uniform float shadowFarPlanes[MAX_SHADOW_LIGHTS];
+const float SHADOW_BIAS = 0.05;
+
+// Point lights sorted into clusters of screen tiles and depth slices, used in place of pointLights when set
+uniform bool clustered;
+// 5 texels a light, position and shadow map, constant linear quadratic, ambient, diffuse, specular
+uniform sampler2D clusterLights;
+// Offset into clusterIndices and light count of each cluster, a row per depth slice
+uniform usampler2D clusterGrid;
+#define CLUSTER_INDEX_WIDTH 1024u
+uniform usampler2D clusterIndices;
+uniform ivec3 clusterCounts;
+// Near and far plane the depth slices are spread between
+uniform vec2 clusterDepth;
+uniform vec2 screenSize;
+uniform mat4 view;
+
+struct SpotLight {
+    vec3 position;
+    vec3 direction;
+    float innerCutoff;
+    float outerCutoff;
+
+    float constant;
+    float linear;
+    float quadratic;
+
+    vec3 ambient;
+    vec3 diffuse;
+    vec3 specular;
+};
+#define MAX_SPOT_LIGHTS 16
+uniform SpotLight spotLights[MAX_SPOT_LIGHTS];
+uniform int spotLightCount;
+
 uniform vec3 viewPos;
+// Level distance fog, linear from start to end, or exponential when density is above 0
+struct DistanceFog {
+    bool enabled;
+    vec3 color;
+    float start;
+    float end;
+    float density;
+};
+uniform DistanceFog distanceFog;
+// The level mirrored about the reflection plane, drawn from the same view so it lines up on screen
+struct Reflection {
+    bool enabled;
+    vec2 screenSize;
+};
+uniform Reflection reflection;
+uniform sampler2D reflectionTexture;
+// Editor brush view color, alpha is how much of it covers the surface
+uniform vec4 tint;
+// Frame count and current frame of a sprite sheet laid out left to right, used when the animated flag is set
+uniform vec2 flipbook;
+// Texture coordinates moved onto the current flipbook frame
+vec2 uv;
 
 vec3 calcDirLight(DirLight light, vec3 normal, vec3 viewDir);
 vec3 calcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir);
+PointLight clusterLight(uint index);
+ivec2 clusterOf(vec3 fragPos);
+float pointShadow(int map, vec3 fromLight);
+vec3 calcSpotLight(SpotLight light, vec3 normal, vec3 fragPos, vec3 viewDir);
 vec3 applyFog(vec3 color, float dist);
 
 void main() {
     vec3 norm = normalize(normal);
     vec3 viewDir = normalize(viewPos - fragPos);
 
-    vec4 diffusePx = texture(material.diffuse, TexCoord);
+    uv = TexCoord;
+    if (animated > 0 && flipbook.x > 1.0) {
+        uv.x = (fract(uv.x) + flipbook.y) / flipbook.x;
+    }
+
+    vec4 diffusePx = texture(material.diffuse, uv);
     FragColor = vec4(vertexColor * vec3(diffusePx), 1.0f);
     // FragColor = vec4(vertexColor, 1.0f);
 
+    if (cutout > 0) {
+        if (diffusePx.a < 0.1) {
+            discard;
+            return;
+        }
+    }
+
     if (fullbright == 0) {
         vec3 result = calcDirLight(dirLight, norm, viewDir);
 
-        for (int i = 0; i < pointLightCount; i++) {
-            result += calcPointLight(pointLights[i], norm, fragPos, viewDir);
+        if (clustered) {
+            ivec2 cluster = clusterOf(fragPos);
+            for (uint i = uint(cluster.x); i < uint(cluster.x + cluster.y); i++) {
+                uint index = texelFetch(clusterIndices, ivec2(i % CLUSTER_INDEX_WIDTH, i / CLUSTER_INDEX_WIDTH), 0).r;
+                result += calcPointLight(clusterLight(index), norm, fragPos, viewDir);
+            }
+        } else {
+            for (int i = 0; i < pointLightCount; i++) {
+                result += calcPointLight(pointLights[i], norm, fragPos, viewDir);
+            }
+        }
+
+        for (int i = 0; i < spotLightCount; i++) {
+            result += calcSpotLight(spotLights[i], norm, fragPos, viewDir);
         }
 
         FragColor = vec4(result * vertexColor, 1.0);
     }
+
+    if (reflection.enabled && material.reflectivity > 0.0) {
+        vec3 reflected = texture(reflectionTexture, gl_FragCoord.xy / reflection.screenSize).rgb;
+        FragColor.rgb = mix(FragColor.rgb, reflected, material.reflectivity);
+    }
+
+    FragColor.rgb += texture(material.emissive, uv).rgb * material.emission;
+
+    FragColor.rgb = applyFog(FragColor.rgb, length(viewPos - fragPos));
+
+    // Editor brush view, keeps a little of the shading so faces stay readable
+    FragColor.rgb = mix(FragColor.rgb, tint.rgb * (0.5 + 0.5 * max(dot(norm, vec3(0.3, 0.8, 0.5)), 0.0)), tint.a);
 }
 
 vec3 calcDirLight(DirLight light, vec3 normal, vec3 viewDir) {
@@ -71,9 +181,9 @@ vec3 calcDirLight(DirLight light, vec3 normal, vec3 viewDir) {
     vec3 reflectDir = reflect(-lightDir, normal);
     float spec = pow(max(dot(viewDir, reflectDir), 0.0), material.shininess);
 
-    vec3 ambient = light.ambient * vec3(texture(material.diffuse, TexCoord));
-    vec3 diffuse = light.diffuse * diff * vec3(texture(material.diffuse, TexCoord));
-    vec3 specular = light.specular * spec * vec3(texture(material.specular, TexCoord));
+    vec3 ambient = light.ambient * vec3(texture(material.diffuse, uv));
+    vec3 diffuse = light.diffuse * diff * vec3(texture(material.diffuse, uv));
+    vec3 specular = light.specular * spec * vec3(texture(material.specular, uv));
     return (ambient + diffuse + specular);
 }
 
@@ -88,11 +198,90 @@ vec3 calcPointLight(PointLight light, vec3 normal, vec3 fragPos, vec3 viewDir) {
     float distance = length(light.position - fragPos);
     float attenuation = 1.0 / (light.constant + light.linear * distance + light.quadratic * (distance * distance));
 
-    vec3 ambient = light.ambient * vec3(texture(material.diffuse, TexCoord));
-    vec3 diffuse = light.diffuse * diff * vec3(texture(material.diffuse, TexCoord));
-    vec3 specular = light.specular * spec * vec3(texture(material.specular, TexCoord));
+    vec3 ambient = light.ambient * vec3(texture(material.diffuse, uv));
+    vec3 diffuse = light.diffuse * diff * vec3(texture(material.diffuse, uv));
+    vec3 specular = light.specular * spec * vec3(texture(material.specular, uv));
     ambient *= attenuation;
     diffuse *= attenuation;
     specular *= attenuation;
+
+    if (light.shadow >= 0) {
+        float lit = 1.0 - pointShadow(light.shadow, fragPos - light.position);
+        diffuse *= lit;
+        specular *= lit;
+    }
+    return (ambient + diffuse + specular);
+}
+
+PointLight clusterLight(uint index) {
+    int row = int(index);
+    vec4 position = texelFetch(clusterLights, ivec2(0, row), 0);
+    vec4 attenuation = texelFetch(clusterLights, ivec2(1, row), 0);
+    return PointLight(
+        position.xyz,
+        attenuation.x, attenuation.y, attenuation.z,
+        texelFetch(clusterLights, ivec2(2, row), 0).rgb,
+        texelFetch(clusterLights, ivec2(3, row), 0).rgb,
+        texelFetch(clusterLights, ivec2(4, row), 0).rgb,
+        int(position.w)
+    );
+}
+
+// Offset into clusterIndices and light count of the cluster the fragment is in
+ivec2 clusterOf(vec3 fragPos) {
+    float depth = max(-(view * vec4(fragPos, 1.0)).z, clusterDepth.x);
+    int slice = int(log(depth / clusterDepth.x) / log(clusterDepth.y / clusterDepth.x) * float(clusterCounts.z));
+    ivec2 tile = ivec2(gl_FragCoord.xy / screenSize * vec2(clusterCounts.xy));
+    tile = clamp(tile, ivec2(0), clusterCounts.xy - 1);
+    slice = clamp(slice, 0, clusterCounts.z - 1);
+    return ivec2(texelFetch(clusterGrid, ivec2(tile.x + tile.y * clusterCounts.x, slice), 0).rg);
+}
+
+// 1 if something is between the light and the fragment, samplers can only be indexed by constants here
+float pointShadow(int map, vec3 fromLight) {
+    float closest;
+    if (map == 0) {
+        closest = texture(shadowMaps[0], fromLight).r;
+    } else if (map == 1) {
+        closest = texture(shadowMaps[1], fromLight).r;
+    } else if (map == 2) {
+        closest = texture(shadowMaps[2], fromLight).r;
+    } else {
+        closest = texture(shadowMaps[3], fromLight).r;
+    }
+    return length(fromLight) - SHADOW_BIAS > closest * shadowFarPlanes[map] ? 1.0 : 0.0;
+}
+
+vec3 calcSpotLight(SpotLight light, vec3 normal, vec3 fragPos, vec3 viewDir) {
+    vec3 lightDir = normalize(light.position - fragPos);
+
+    float diff = max(dot(normal, lightDir), 0.0);
+
+    vec3 reflectDir = reflect(-lightDir, normal);
+    float spec = pow(max(dot(viewDir, reflectDir), 0.0), material.shininess);
+
+    float distance = length(light.position - fragPos);
+    float attenuation = 1.0 / (light.constant + light.linear * distance + light.quadratic * (distance * distance));
+
+    // Fade out between the inner and outer cone
+    float theta = dot(lightDir, normalize(-light.direction));
+    float intensity = clamp((theta - light.outerCutoff) / (light.innerCutoff - light.outerCutoff), 0.0, 1.0);
+
+    vec3 ambient = light.ambient * vec3(texture(material.diffuse, uv));
+    vec3 diffuse = light.diffuse * diff * vec3(texture(material.diffuse, uv));
+    vec3 specular = light.specular * spec * vec3(texture(material.specular, uv));
+    ambient *= attenuation * intensity;
+    diffuse *= attenuation * intensity;
+    specular *= attenuation * intensity;
     return (ambient + diffuse + specular);
-}
\ No newline at end of file
+}
+
+vec3 applyFog(vec3 color, float dist) {
+    if (!distanceFog.enabled) {
+        return color;
+    }
+    float amount = distanceFog.density > 0.0
+        ? 1.0 - exp(-distanceFog.density * dist)
+        : clamp((dist - distanceFog.start) / max(distanceFog.end - distanceFog.start, 0.0001), 0.0, 1.0);
+    return mix(color, distanceFog.color, amount);
+}
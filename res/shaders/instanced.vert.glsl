@@ -2,19 +2,26 @@ layout (location = 0) in vec3 aPos;
 layout (location = 1) in vec3 aColor;
 layout (location = 2) in vec2 aTexCoord;
 layout (location = 3) in vec3 aNormal;
+// Brush face alignment, zero when a mesh doesn't have it
+layout (location = 4) in vec3 aFaceOffset;
+layout (location = 5) in vec2 aFaceScale;
 
-layout (location = 4) in uint instanceFlags;
-layout (location = 5) in mat4 instanceMatrix;
-layout (location = 9) in mat3 instanceNormalMatrix;
+layout (location = 8) in uint instanceFlags;
+layout (location = 9) in mat4 instanceMatrix;
+layout (location = 13) in mat3 instanceNormalMatrix;
 
 out vec3 vertexColor;
 out vec2 TexCoord;
 flat out uint fullbright;
+flat out uint cutout;
+flat out uint animated;
 out vec3 normal;
 out vec3 fragPos;
 
 uniform mat4 view;
 uniform mat4 projection;
+// Only used while the reflection is drawn, keeps what is behind the reflection plane out of it
+uniform vec4 clipPlane;
 
 const float TEXTURE_LOOP_DIV = 2.0f;
 
@@ -29,8 +36,11 @@ void main() {
     vertexColor = aColor;
     uint extend_texture = instanceFlags & 1;
     fullbright = instanceFlags & 2;
+    cutout = instanceFlags & 8;
+    animated = instanceFlags & 32;
 
     fragPos = vec3(instanceMatrix * vec4(aPos, 1.0));
+    gl_ClipDistance[0] = dot(vec4(fragPos, 1.0), clipPlane);
     normal = instanceNormalMatrix * aNormal;
 
     // Texture coordinate
@@ -49,4 +59,9 @@ void main() {
     } else {
         TexCoord = aTexCoord;
     }
+
+    // Scale is stored as a power of two, rotation in radians
+    float c = cos(aFaceOffset.z);
+    float s = sin(aFaceOffset.z);
+    TexCoord = mat2(c, s, -s, c) * (TexCoord / exp2(aFaceScale)) + aFaceOffset.xy;
 }
\ No newline at end of file
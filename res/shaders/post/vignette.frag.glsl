@@ -0,0 +1,18 @@
+out vec4 FragColor;
+
+in vec2 TexCoord;
+
+uniform sampler2D screenTexture;
+uniform vec2 screenSize;
+
+uniform float strength;
+uniform float radius;
+uniform vec3 tint;
+
+void main() {
+    vec3 color = vec3(texture(screenTexture, TexCoord));
+    // Keep the vignette round on wide windows
+    vec2 centered = (TexCoord - vec2(0.5)) * vec2(screenSize.x / screenSize.y, 1.0);
+    float edge = smoothstep(radius, radius + 0.5, length(centered));
+    FragColor = vec4(mix(color, tint, edge * strength), 1.0);
+}
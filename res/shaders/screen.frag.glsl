@@ -21,8 +21,34 @@ struct Kernel {
 };
 uniform Kernel kernel;
 
+struct Hdr {
+    float exposure;
+    int toneMapping;
+    // 0 without bloom
+    float bloomIntensity;
+};
+uniform Hdr hdr;
+
 uniform sampler2D screenTexture;
 uniform sampler2D depthTexture;
+// Blurred bright parts of the frame at half size
+uniform sampler2D bloomTexture;
+// Ambient occlusion, 1 is unoccluded
+uniform sampler2D occlusionTexture;
+uniform bool occlusion;
+uniform float nearPlane;
+uniform float farPlane;
+
+// Fog strengths were tuned for these clip planes
+const float FOG_NEAR = 0.1;
+const float FOG_FAR = 100.0;
+
+// The depth a fragment would have with the clip planes fog was tuned for, so fog looks the same at any far plane
+float fogDepth(float depth) {
+    float ndc = depth * 2.0 - 1.0;
+    float z = (2.0 * nearPlane * farPlane) / (farPlane + nearPlane - ndc * (farPlane - nearPlane));
+    return clamp((1.0 / FOG_NEAR - 1.0 / z) / (1.0 / FOG_NEAR - 1.0 / FOG_FAR), 0.0, 1.0);
+}
 
 void main() {
     vec3 color = vec3(texture(screenTexture, TexCoord));
@@ -59,10 +85,23 @@ void main() {
             color += sampleTex[i] * user_kernel[i];
     }
 
+    if (occlusion) {
+        color *= texture(occlusionTexture, TexCoord).r;
+    }
+
     if (fog_enabled == 1) {
-        float fog_strength = min(fog.max, pow(texture(depthTexture, TexCoord).r, fog.strength));
+        float fog_strength = min(fog.max, pow(fogDepth(texture(depthTexture, TexCoord).r), fog.strength));
         color = mix(color, fog.color, fog_strength);
     }
 
+    if (hdr.bloomIntensity > 0.0) {
+        color += texture(bloomTexture, TexCoord).rgb * hdr.bloomIntensity;
+    }
+
+    color *= hdr.exposure;
+    if (hdr.toneMapping == 1) {
+        color = vec3(1.0) - exp(-color);
+    }
+
     FragColor = vec4(color, 1.0);
 }
\ No newline at end of file
@@ -0,0 +1,9 @@
+in vec3 fragPos;
+
+uniform vec3 lightPos;
+uniform float farPlane;
+
+void main() {
+    // Linear distance so the lighting shaders can compare it against their own
+    gl_FragDepth = length(fragPos - lightPos) / farPlane;
+}
@@ -0,0 +1,20 @@
+layout (location = 0) in vec3 aPos;
+
+layout (location = 8) in uint instanceFlags;
+layout (location = 9) in mat4 instanceMatrix;
+
+out vec3 fragPos;
+
+// Projection and view of the cubemap face being drawn
+uniform mat4 faceMatrix;
+
+void main() {
+    // Skipped and editor only meshes don't cast shadows
+    if ((instanceFlags & 20u) > 0u) {
+        gl_Position = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    fragPos = vec3(instanceMatrix * vec4(aPos, 1.0));
+    gl_Position = faceMatrix * vec4(fragPos, 1.0);
+}
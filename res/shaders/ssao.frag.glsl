@@ -0,0 +1,79 @@
+out vec4 FragColor;
+
+in vec2 TexCoord;
+
+uniform sampler2D depthTexture;
+uniform mat4 projection;
+uniform mat4 inverseProjection;
+// How far around each point is looked at for occluders, in world units
+uniform float radius;
+// Keeps flat surfaces from shadowing themselves
+uniform float bias;
+// Occlusion is raised to this, higher darkens creases more
+uniform float strength;
+
+#define KERNEL_SIZE 16
+// Points in the hemisphere over the surface, more of them close to it
+const vec3 kernel[KERNEL_SIZE] = vec3[](
+    vec3(-0.023, 0.002, 0.010),
+    vec3(-0.019, 0.090, 0.004),
+    vec3(-0.026, -0.043, 0.007),
+    vec3(0.014, -0.013, 0.027),
+    vec3(-0.018, -0.046, 0.073),
+    vec3(-0.030, 0.044, 0.052),
+    vec3(0.030, 0.010, 0.175),
+    vec3(-0.047, 0.148, 0.044),
+    vec3(0.043, 0.223, 0.093),
+    vec3(0.122, 0.103, 0.295),
+    vec3(-0.144, 0.213, 0.014),
+    vec3(0.152, -0.211, 0.070),
+    vec3(0.227, -0.138, 0.129),
+    vec3(-0.303, -0.251, 0.109),
+    vec3(0.148, -0.395, 0.003),
+    vec3(-0.176, 0.089, 0.641)
+);
+
+vec3 viewPosAt(vec2 uv) {
+    float depth = texture(depthTexture, uv).r;
+    vec4 pos = inverseProjection * vec4(vec3(uv, depth) * 2.0 - 1.0, 1.0);
+    return pos.xyz / pos.w;
+}
+
+// Random rotation per pixel in a 4x4 pattern, the blur pass evens it out
+vec3 noiseAt(vec2 fragCoord) {
+    vec2 cell = mod(floor(fragCoord), 4.0);
+    float angle = fract(sin(dot(cell, vec2(12.9898, 78.233))) * 43758.5453) * 6.2831853;
+    return vec3(cos(angle), sin(angle), 0.0);
+}
+
+void main() {
+    if (texture(depthTexture, TexCoord).r >= 1.0) {
+        FragColor = vec4(1.0);
+        return;
+    }
+
+    vec3 pos = viewPosAt(TexCoord);
+    // Faceted normals from the depth buffer, there are no smooth surfaces to lose
+    vec3 normal = normalize(cross(dFdx(pos), dFdy(pos)));
+
+    vec3 random = noiseAt(gl_FragCoord.xy);
+    vec3 tangent = normalize(random - normal * dot(random, normal));
+    mat3 tbn = mat3(tangent, cross(normal, tangent), normal);
+
+    float occlusion = 0.0;
+    for (int i = 0; i < KERNEL_SIZE; i++) {
+        vec3 samplePos = pos + tbn * kernel[i] * radius;
+        vec4 offset = projection * vec4(samplePos, 1.0);
+        vec2 sampleUv = offset.xy / offset.w * 0.5 + 0.5;
+        if (sampleUv.x < 0.0 || sampleUv.x > 1.0 || sampleUv.y < 0.0 || sampleUv.y > 1.0) {
+            continue;
+        }
+
+        float sceneDepth = viewPosAt(sampleUv).z;
+        // Occluders much further away than the radius are behind, not in the crease
+        float range = smoothstep(0.0, 1.0, radius / abs(pos.z - sceneDepth));
+        occlusion += (sceneDepth >= samplePos.z + bias ? 1.0 : 0.0) * range;
+    }
+
+    FragColor = vec4(vec3(pow(1.0 - occlusion / float(KERNEL_SIZE), strength)), 1.0);
+}
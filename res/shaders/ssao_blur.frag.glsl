@@ -0,0 +1,17 @@
+out vec4 FragColor;
+
+in vec2 TexCoord;
+
+uniform sampler2D screenTexture;
+
+// Box blur over the 4x4 noise pattern of the occlusion pass
+void main() {
+    vec2 texel = 1.0 / vec2(textureSize(screenTexture, 0));
+    float result = 0.0;
+    for (int x = -2; x < 2; x++) {
+        for (int y = -2; y < 2; y++) {
+            result += texture(screenTexture, TexCoord + vec2(float(x), float(y)) * texel).r;
+        }
+    }
+    FragColor = vec4(vec3(result / 16.0), 1.0);
+}
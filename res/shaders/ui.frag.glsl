@@ -3,11 +3,12 @@ out vec4 FragColor;
 in vec2 TexCoord;
 
 uniform sampler2D tex;
+uniform vec4 tint;
 
 void main() {
     vec4 texColor = vec4(texture(tex, TexCoord));
     if (texColor.a < 0.1) {
         discard;
     }
-    FragColor = texColor;
+    FragColor = texColor * tint;
 }
\ No newline at end of file
@@ -0,0 +1,355 @@
+use std::{collections::{HashMap, HashSet}, fs::File, io::BufReader};
+
+use cgmath::{EuclideanSpace, InnerSpace, Vector3};
+use rodio::{source::Buffered, Decoder, OutputStream, OutputStreamHandle, Sink, Source, SpatialSink};
+use serde::{Deserialize, Serialize};
+
+use crate::{common, component::Component, world::World};
+
+/// Seconds to crossfade when a level starts
+pub const LEVEL_CROSSFADE: f32 = 2.0;
+/// Distance from the listener to each ear
+const EAR_DISTANCE: f32 = 0.1;
+
+type SoundData = Buffered<Decoder<BufReader<File>>>;
+
+fn default_volume() -> f32 { 1.0 }
+
+/// Volume of each bus from 0 to 1, the final volume of a sound is `master` times its bus
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+pub struct VolumeSettings {
+    #[serde(default="default_volume")]
+    pub master: f32,
+    #[serde(default="default_volume")]
+    pub music: f32,
+    #[serde(default="default_volume")]
+    pub sfx: f32
+}
+
+impl Default for VolumeSettings {
+    fn default() -> Self {
+        Self { master: 1.0, music: 1.0, sfx: 1.0 }
+    }
+}
+
+/// Tracks a level starts with, paths are relative to `res/audio/`
+#[derive(Clone, Serialize, Deserialize, Debug, Default)]
+pub struct LevelAudio {
+    #[serde(default)]
+    pub music: Option<String>,
+    #[serde(default)]
+    pub ambience: Option<String>
+}
+
+/// Loops a sound from the model's position, quieter the further the camera is
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct SoundEmitter {
+    /// Relative to `res/audio/`
+    pub sound: String,
+    pub volume: f32,
+    /// Can't be heard from further than this
+    pub radius: f32
+}
+
+impl SoundEmitter {
+    pub fn new(sound: &str) -> Self {
+        Self { sound: sound.to_string(), volume: 1.0, radius: 10.0 }
+    }
+
+    /// Volume heard `distance` away, falls off to nothing at `radius`
+    fn gain(&self, distance: f32) -> f32 {
+        let falloff = (1.0 - distance / self.radius.max(0.001)).clamp(0.0, 1.0);
+        self.volume * falloff * falloff
+    }
+}
+
+/// Sounds decoded once and kept in memory, for sounds that play more than once
+#[derive(Default)]
+pub struct AudioBank {
+    sounds: HashMap<String, SoundData>
+}
+
+impl AudioBank {
+    /// The decoded sound, the path is relative to `res/audio/`
+    pub fn get(&mut self, sound: &str) -> Result<SoundData, String> {
+        if let Some(data) = self.sounds.get(sound) {
+            return Ok(data.clone());
+        }
+
+        let file = File::open(format!("res/audio/{}", sound)).map_err(|e| format!("failed to open sound {}: {}", sound, e))?;
+        let data = Decoder::new(BufReader::new(file)).map_err(|e| format!("failed to decode sound {}: {}", sound, e))?.buffered();
+        self.sounds.insert(sound.to_string(), data.clone());
+        Ok(data)
+    }
+}
+
+struct PlayingEmitter {
+    sound: String,
+    sink: SpatialSink
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Debug)]
+pub enum MusicChannel {
+    Music,
+    Ambience
+}
+
+struct Fade {
+    sink: Sink,
+    elapsed: f32,
+    duration: f32
+}
+
+impl Fade {
+    fn progress(&self) -> f32 {
+        if self.duration <= 0.0 { 1.0 } else { (self.elapsed / self.duration).min(1.0) }
+    }
+}
+
+/// One looping track at a time, with older tracks fading out underneath
+#[derive(Default)]
+struct Channel {
+    track: Option<String>,
+    /// Fading in, or playing once the fade is done
+    current: Option<Fade>,
+    fading_out: Vec<Fade>
+}
+
+impl Channel {
+    fn stop(&mut self, crossfade: f32) {
+        if let Some(mut current) = self.current.take() {
+            // Fade out from wherever the fade in got to
+            current.elapsed = (1.0 - current.progress()) * crossfade;
+            current.duration = crossfade;
+            self.fading_out.push(current);
+        }
+        self.track = None;
+    }
+
+    fn update(&mut self, delta_time: f32, volume: f32) {
+        if let Some(current) = &mut self.current {
+            current.elapsed += delta_time;
+            current.sink.set_volume(current.progress() * volume);
+        }
+
+        for fade in self.fading_out.iter_mut() {
+            fade.elapsed += delta_time;
+            fade.sink.set_volume((1.0 - fade.progress()) * volume);
+        }
+
+        self.fading_out.retain(|fade| {
+            let done = fade.progress() >= 1.0;
+            if done {
+                fade.sink.stop();
+            }
+            !done
+        });
+    }
+}
+
+/// Streams looping music and ambience tracks and crossfades between them<br>
+/// The audio device is opened the first time something is played
+pub struct MusicManager {
+    output: Option<(OutputStream, OutputStreamHandle)>,
+    music: Channel,
+    ambience: Channel,
+    /// One shot sounds that are still playing
+    sounds: Vec<Sink>,
+    bank: AudioBank,
+    /// Sound emitters in hearing range, by model
+    emitters: HashMap<usize, PlayingEmitter>,
+    /// Emitter sounds that failed to play, so the error is only shown once
+    failed: HashSet<String>,
+    /// Volume from the last update, new sounds start at this
+    volume: VolumeSettings
+}
+
+impl MusicManager {
+    pub fn new() -> Self {
+        Self {
+            output: None,
+            music: Channel::default(),
+            ambience: Channel::default(),
+            sounds: Vec::new(),
+            bank: AudioBank::default(),
+            emitters: HashMap::new(),
+            failed: HashSet::new(),
+            volume: VolumeSettings::default()
+        }
+    }
+
+    fn output(&mut self) -> Result<&OutputStreamHandle, String> {
+        if self.output.is_none() {
+            self.output = Some(OutputStream::try_default().map_err(|e| format!("failed to open audio device: {}", e))?);
+        }
+        Ok(&self.output.as_ref().unwrap().1)
+    }
+
+    fn channel(&mut self, channel: MusicChannel) -> &mut Channel {
+        match channel {
+            MusicChannel::Music => &mut self.music,
+            MusicChannel::Ambience => &mut self.ambience
+        }
+    }
+
+    pub fn current_track(&self, channel: MusicChannel) -> Option<&str> {
+        match channel {
+            MusicChannel::Music => self.music.track.as_deref(),
+            MusicChannel::Ambience => self.ambience.track.as_deref()
+        }
+    }
+
+    /// Crossfade to `track` over `crossfade` seconds, `None` fades the channel out<br>
+    /// Does nothing if the track is already playing
+    pub fn play(&mut self, channel: MusicChannel, track: Option<&str>, crossfade: f32) -> Result<(), String> {
+        if self.current_track(channel) == track {
+            return Ok(());
+        }
+
+        self.channel(channel).stop(crossfade);
+        let Some(track) = track else { return Ok(()); };
+
+        let handle = self.output()?;
+
+        // The decoder reads from the file as it plays instead of loading the whole track
+        let file = File::open(format!("res/audio/{}", track)).map_err(|e| format!("failed to open track {}: {}", track, e))?;
+        let source = Decoder::new_looped(BufReader::new(file)).map_err(|e| format!("failed to decode track {}: {}", track, e))?;
+        let sink = Sink::try_new(handle).map_err(|e| format!("failed to play track {}: {}", track, e))?;
+        sink.set_volume(0.0);
+        sink.append(source);
+
+        let channel = self.channel(channel);
+        channel.current = Some(Fade { sink, elapsed: 0.0, duration: crossfade });
+        channel.track = Some(track.to_string());
+        Ok(())
+    }
+
+    /// Play `sound` once on the sfx bus, the path is relative to `res/audio/`
+    pub fn play_sound(&mut self, sound: &str) -> Result<(), String> {
+        let volume = self.volume.master * self.volume.sfx;
+        let source = self.bank.get(sound)?;
+        let handle = self.output()?;
+        let sink = Sink::try_new(handle).map_err(|e| format!("failed to play sound {}: {}", sound, e))?;
+        sink.set_volume(volume);
+        sink.append(source);
+        self.sounds.push(sink);
+        Ok(())
+    }
+
+    /// Advance fades and apply the volume buses, call once per frame
+    pub fn update(&mut self, delta_time: f32, volume: &VolumeSettings) {
+        let music_volume = volume.master * volume.music;
+        self.music.update(delta_time, music_volume);
+        self.ambience.update(delta_time, music_volume);
+
+        self.sounds.retain(|sink| !sink.empty());
+        for sink in self.sounds.iter() {
+            sink.set_volume(volume.master * volume.sfx);
+        }
+        self.volume = *volume;
+    }
+
+    /// Start, move and stop emitter sounds for a listener at `listener` whose left is `left`<br>
+    /// `emitters` is every emitter in the level by model, with its position
+    pub fn update_emitters(&mut self, emitters: &[(usize, SoundEmitter, Vector3<f32>)], listener: Vector3<f32>, left: Vector3<f32>) -> Vec<String> {
+        let mut errors = Vec::new();
+        let sfx = self.volume.master * self.volume.sfx;
+        let left_ear = listener + left * EAR_DISTANCE;
+        let right_ear = listener - left * EAR_DISTANCE;
+
+        // Out of range emitters are stopped and start over when they come back into range
+        let audible: Vec<(usize, &SoundEmitter, Vector3<f32>, f32)> = emitters.iter()
+            .map(|(model, emitter, position)| (*model, emitter, *position, emitter.gain((position - listener).magnitude())))
+            .filter(|(.., gain)| *gain > 0.0)
+            .collect();
+        self.emitters.retain(|model, playing| {
+            let keep = audible.iter().any(|(other, emitter, ..)| other == model && emitter.sound == playing.sound);
+            if !keep {
+                playing.sink.stop();
+            }
+            keep
+        });
+
+        for (model, emitter, position, gain) in audible {
+            if !self.emitters.contains_key(&model) {
+                if self.failed.contains(&emitter.sound) {
+                    continue;
+                }
+                match self.start_emitter(emitter) {
+                    Ok(sink) => { self.emitters.insert(model, PlayingEmitter { sound: emitter.sound.clone(), sink }); },
+                    Err(e) => {
+                        self.failed.insert(emitter.sound.clone());
+                        errors.push(e);
+                        continue;
+                    }
+                }
+            }
+
+            // Distance is handled by the volume, the emitter is put next to the listener so it only pans
+            let offset = position - listener;
+            let direction = if offset.magnitude2() > 0.0001 { offset.normalize() } else { common::vec3_zero() };
+            let sink = &self.emitters[&model].sink;
+            sink.set_emitter_position((listener + direction).into());
+            sink.set_left_ear_position(left_ear.into());
+            sink.set_right_ear_position(right_ear.into());
+            sink.set_volume(gain * sfx);
+        }
+
+        errors
+    }
+
+    fn start_emitter(&mut self, emitter: &SoundEmitter) -> Result<SpatialSink, String> {
+        let source = self.bank.get(&emitter.sound)?;
+        let handle = self.output()?;
+        let sink = SpatialSink::try_new(handle, [0.0; 3], [-EAR_DISTANCE, 0.0, 0.0], [EAR_DISTANCE, 0.0, 0.0])
+            .map_err(|e| format!("failed to play sound {}: {}", emitter.sound, e))?;
+        sink.set_volume(0.0);
+        sink.append(source.repeat_infinite());
+        Ok(sink)
+    }
+}
+
+impl World {
+    /// Move the listener to the camera and update every sound emitter, call once per frame after the music
+    pub fn update_sound_emitters(&mut self) {
+        let emitters: Vec<(usize, SoundEmitter, Vector3<f32>)> = self.models.iter().enumerate().filter_map(|(i, model)| {
+            let model = model.as_ref()?;
+            model.components.iter().find_map(|component| match component {
+                // Emitters are added without a sound and filled in from the inspector
+                Component::SoundEmitter(emitter) if !emitter.sound.is_empty() => Some((i, emitter.clone(), common::translation(model.transform))),
+                _ => None
+            })
+        }).collect();
+
+        let camera = &self.scene.camera;
+        // The camera's right vector points to the left of the screen
+        let errors = self.music.update_emitters(&emitters, camera.pos.to_vec(), camera.right);
+        self.editor_data.show_debug.extend(errors);
+    }
+
+    pub fn get_sound_emitter(&self, model: usize) -> Option<SoundEmitter> {
+        self.models.get(model)?.as_ref()?.components.iter().find_map(|component| match component {
+            Component::SoundEmitter(emitter) => Some(emitter.clone()),
+            _ => None
+        })
+    }
+
+    pub fn set_sound_emitter(&mut self, model: usize, emitter: SoundEmitter) {
+        let Some(target) = self.models[model].as_mut() else { return; };
+        for component in target.components.iter_mut() {
+            if let Component::SoundEmitter(old) = component {
+                *old = emitter.clone();
+            }
+        }
+    }
+
+    /// Crossfade to the level's music and ambience, call after a level is loaded
+    pub fn start_level_audio(&mut self) {
+        let audio = self.audio.clone();
+        for (channel, track) in [(MusicChannel::Music, audio.music), (MusicChannel::Ambience, audio.ambience)] {
+            if let Err(e) = self.music.play(channel, track.as_deref(), LEVEL_CROSSFADE) {
+                self.editor_data.show_debug.push(e);
+            }
+        }
+    }
+}
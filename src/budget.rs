@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{component::Component, texture::TextureBank, world::World};
+
+/// Seconds between stat counts while editing
+const STATS_INTERVAL: f32 = 0.5;
+/// Usage past this fraction of a budget shows as close
+const CLOSE_TO_BUDGET: f32 = 0.8;
+/// Offenders listed for each budget
+const MAX_OFFENDERS: usize = 5;
+const MEBIBYTE: u64 = 1024 * 1024;
+
+/// Limits a level should stay under to run well, saved with the level
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct PerformanceBudgets {
+    pub draw_calls: u32,
+    /// Point and spot lights
+    pub lights: u32,
+    pub colliders: u32,
+    /// MiB, mipmaps included
+    pub texture_memory: u32
+}
+
+impl Default for PerformanceBudgets {
+    fn default() -> Self {
+        Self {
+            draw_calls: 500,
+            lights: 48,
+            colliders: 2000,
+            texture_memory: 256
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Budget {
+    DrawCalls,
+    Lights,
+    Colliders,
+    TextureMemory
+}
+
+impl Budget {
+    pub const ALL: [Budget; 4] = [Budget::DrawCalls, Budget::Lights, Budget::Colliders, Budget::TextureMemory];
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::DrawCalls => "Draws",
+            Self::Lights => "Lights",
+            Self::Colliders => "Colliders",
+            Self::TextureMemory => "Textures"
+        }
+    }
+
+    /// `amount` with its unit
+    pub fn format(&self, amount: u64) -> String {
+        match self {
+            Self::TextureMemory => format!("{:.1}MiB", amount as f32 / MEBIBYTE as f32),
+            _ => amount.to_string()
+        }
+    }
+
+    pub fn limit(&self, budgets: &PerformanceBudgets) -> u64 {
+        match self {
+            Self::DrawCalls => budgets.draw_calls as u64,
+            Self::Lights => budgets.lights as u64,
+            Self::Colliders => budgets.colliders as u64,
+            Self::TextureMemory => budgets.texture_memory as u64 * MEBIBYTE
+        }
+    }
+
+    pub fn limit_mut<'a>(&self, budgets: &'a mut PerformanceBudgets) -> &'a mut u32 {
+        match self {
+            Self::DrawCalls => &mut budgets.draw_calls,
+            Self::Lights => &mut budgets.lights,
+            Self::Colliders => &mut budgets.colliders,
+            Self::TextureMemory => &mut budgets.texture_memory
+        }
+    }
+
+    /// How much Level Properties changes the limit by
+    pub fn step(&self) -> f32 {
+        match self {
+            Self::DrawCalls => 50.0,
+            Self::Lights => 4.0,
+            Self::Colliders => 100.0,
+            Self::TextureMemory => 32.0
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BudgetStatus {
+    Fine,
+    Close,
+    Over
+}
+
+impl BudgetStatus {
+    pub fn color(&self) -> [f32; 4] {
+        match self {
+            Self::Fine => [0.3, 0.85, 0.35, 1.0],
+            Self::Close => [0.95, 0.8, 0.2, 1.0],
+            Self::Over => [0.95, 0.25, 0.2, 1.0]
+        }
+    }
+}
+
+/// How much of one budget the level uses
+pub struct BudgetUsage {
+    pub budget: Budget,
+    pub used: u64,
+    pub limit: u64,
+    /// What uses the most, largest first
+    pub offenders: Vec<(String, u64)>
+}
+
+impl BudgetUsage {
+    fn new(budget: Budget, budgets: &PerformanceBudgets, mut parts: Vec<(String, u64)>) -> Self {
+        let used = parts.iter().map(|(_, amount)| amount).sum();
+        parts.sort_by(|a, b| b.1.cmp(&a.1));
+        parts.truncate(MAX_OFFENDERS);
+        Self { budget, used, limit: budget.limit(budgets), offenders: parts }
+    }
+
+    pub fn status(&self) -> BudgetStatus {
+        if self.used > self.limit {
+            BudgetStatus::Over
+        } else if self.used as f32 > self.limit as f32 * CLOSE_TO_BUDGET {
+            BudgetStatus::Close
+        } else {
+            BudgetStatus::Fine
+        }
+    }
+}
+
+impl World {
+    /// Count what the level uses against its budgets every `STATS_INTERVAL`, only while editing
+    pub fn update_stats(&mut self, textures: &TextureBank, delta_time: f32) {
+        if !self.editor_data.active {
+            return;
+        }
+
+        self.editor_data.stats_timer -= delta_time;
+        if self.editor_data.stats_timer > 0.0 {
+            return;
+        }
+        self.editor_data.stats_timer = STATS_INTERVAL;
+
+        let budgets = &self.budgets;
+        self.editor_data.stats = vec![
+            BudgetUsage::new(Budget::DrawCalls, budgets, self.scene.draw_calls()),
+            BudgetUsage::new(Budget::Lights, budgets, self.light_usage()),
+            BudgetUsage::new(Budget::Colliders, budgets, self.collider_usage()),
+            BudgetUsage::new(Budget::TextureMemory, budgets, texture_usage(textures))
+        ];
+    }
+
+    /// Lights by the model they are on
+    fn light_usage(&self) -> Vec<(String, u64)> {
+        let mut on_models = 0;
+        let mut parts: Vec<(String, u64)> = self.models.iter().enumerate().filter_map(|(index, model)| {
+            let model = model.as_ref()?;
+            let spots = model.components.iter().filter(|component| matches!(component, Component::SpotLight(_))).count();
+            let count = (model.lights.len() + spots) as u64;
+            on_models += model.lights.len();
+            (count > 0).then(|| (self.model_label(index), count))
+        }).collect();
+
+        let loose = self.scene.point_lights.len().saturating_sub(on_models) as u64;
+        if loose > 0 {
+            parts.push((String::from("placed lights"), loose));
+        }
+        parts
+    }
+
+    /// Colliders by the model they belong to
+    fn collider_usage(&self) -> Vec<(String, u64)> {
+        let mut counts: HashMap<usize, u64> = HashMap::new();
+        for collider in self.physical_scene.colliders() {
+            // The player's collider is always there
+            let Some(model) = collider.model else { continue; };
+            *counts.entry(model).or_default() += 1;
+        }
+        counts.into_iter().map(|(model, count)| (self.model_label(model), count)).collect()
+    }
+}
+
+/// Memory of every loaded texture, mipmaps add a third
+fn texture_usage(textures: &TextureBank) -> Vec<(String, u64)> {
+    textures.textures.iter()
+        .map(|(name, texture)| (name.clone(), texture.width as u64 * texture.height as u64 * 4 * 4 / 3))
+        .collect()
+}
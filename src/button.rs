@@ -0,0 +1,161 @@
+use cgmath::{Matrix4, MetricSpace, Transform, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{collision::RaycastParameters, common, component::{Component, TargetAction}, events::EventKind, input::{Action, Input}, world::{Model, World}};
+
+/// Nothing further than this is looked for, whatever the buttons' use ranges are
+const MAX_USE_RANGE: f32 = 16.0;
+
+/// Pressed by looking at it and using `Action::Interact`, fires `OnPressed` and its target<br>
+/// Buttons need to be solid to be looked at
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Button {
+    /// How close the player's eye has to be to the spot they are looking at
+    pub use_range: f32,
+    /// How far the button goes in when pressed, relative to the model's rotation
+    pub press_offset: [f32; 3],
+    /// Seconds the press takes, the button can't be pressed again until it's back out
+    pub press_time: f32,
+    /// Name of a model that gets `action` when the button is pressed, see `World::fire_target`
+    #[serde(default)]
+    pub target: Option<String>,
+    pub action: TargetAction,
+    #[serde(skip)]
+    origin: [f32; 3],
+    /// Pressed since the last update
+    #[serde(skip)]
+    pressed: bool,
+    /// Seconds left of the press
+    #[serde(skip)]
+    pressing: f32,
+    /// Turned off by a `Lock` input while playing
+    #[serde(skip)]
+    pub locked: bool
+}
+
+impl Button {
+    pub fn new() -> Self {
+        Self {
+            use_range: 2.5, press_offset: [0.0, 0.0, -0.1], press_time: 0.4, target: None, action: TargetAction::OpenDoor,
+            origin: [0.0; 3], pressed: false, pressing: 0.0, locked: false
+        }
+    }
+
+    /// Whether it can be pressed right now
+    pub fn ready(&self) -> bool {
+        !self.locked && !self.pressed && self.pressing <= 0.0
+    }
+
+    /// Start the press, the outputs and target are fired in the next `update`
+    pub fn press(&mut self) {
+        if self.ready() {
+            self.pressed = true;
+        }
+    }
+
+    pub fn on_input(&mut self, input: &str) {
+        match input {
+            "Press" => self.press(),
+            "Lock" => self.locked = true,
+            "Unlock" => self.locked = false,
+            _ => ()
+        }
+    }
+
+    /// Called from `Component::on_update`, animates the press and fires it
+    pub fn update(&mut self, mut model: Model, world: &mut World, delta_time: f32) -> Model {
+        if self.pressing <= 0.0 {
+            self.origin = model.origin().into();
+        }
+
+        if !world.do_game_logic {
+            self.locked = false;
+            self.pressed = false;
+            if self.pressing > 0.0 {
+                self.pressing = 0.0;
+                let transform = Matrix4::from_translation(self.origin.into()) * common::mat4_remove_translation(model.transform);
+                model = world.set_model_transform_external(model, transform);
+            }
+            return model;
+        }
+
+        if world.interaction.used.is_some() && world.interaction.used == model.index {
+            self.press();
+        }
+        let press_time = self.press_time.max(0.01);
+        if self.pressed {
+            self.pressed = false;
+            self.pressing = press_time;
+            world.log_event(EventKind::ButtonPressed, model.label(), self.target.clone(), String::new());
+            world.fire_output(&mut model, "OnPressed");
+            if let Some(target) = &self.target {
+                let detail = match world.fire_target(target, self.action) {
+                    Ok(()) => self.action.name().to_string(),
+                    Err(e) => {
+                        world.editor_data.show_debug.push(e.clone());
+                        e
+                    }
+                };
+                world.log_event(EventKind::TargetFired, model.label(), Some(target.clone()), detail);
+            }
+        }
+        if self.pressing <= 0.0 {
+            return model;
+        }
+
+        self.pressing = (self.pressing - delta_time).max(0.0);
+        // In for the first half of the press and back out for the second
+        let depth = 1.0 - (self.pressing / press_time * 2.0 - 1.0).abs();
+        let rotation = common::mat4_remove_translation(model.transform);
+        let offset = rotation.transform_vector(Vector3::from(self.press_offset) * depth);
+        model = world.set_model_transform_external(model, Matrix4::from_translation(Vector3::from(self.origin) + offset) * rotation);
+        model
+    }
+}
+
+/// What the player is looking at while playing, updated before the components
+#[derive(Default)]
+pub struct Interaction {
+    /// Button in use range of the crosshair that can be pressed
+    pub looked_at: Option<usize>,
+    /// `looked_at`, if interact was pressed this update
+    pub used: Option<usize>
+}
+
+impl World {
+    pub fn get_button(&self, model: usize) -> Option<Button> {
+        self.models.get(model)?.as_ref()?.components.iter().find_map(|component| match component {
+            Component::Button(button) => Some(button.clone()),
+            _ => None
+        })
+    }
+
+    pub fn set_button(&mut self, model: usize, button: Button) {
+        let Some(target) = self.models[model].as_mut() else { return; };
+        for component in target.components.iter_mut() {
+            if let Component::Button(old) = component {
+                *old = button.clone();
+            }
+        }
+    }
+
+    /// Find the button the player is looking at, and whether they used it
+    pub fn update_interaction(&mut self, input: &Input) {
+        self.interaction = Interaction::default();
+        if !self.do_game_logic || self.spectating() {
+            return;
+        }
+
+        let eye = self.player_eye();
+        let params = RaycastParameters::new().ignore(vec![self.player.collider]).respect_solid();
+        let Some(hit) = self.physical_scene.raycast(eye, self.scene.camera.direction, MAX_USE_RANGE, &params) else { return; };
+        let Some(model) = hit.model else { return; };
+        let Some(button) = self.get_button(model) else { return; };
+        if button.ready() && eye.distance(hit.pos) <= button.use_range {
+            self.interaction.looked_at = Some(model);
+            if input.action_just_pressed(Action::Interact) {
+                self.interaction.used = Some(model);
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use cgmath::{EuclideanSpace, Matrix4, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{collision::RaycastParameters, common, save::{BrushData, ModelData}, session::EditKind, world::{Renderable, Selection, World}};
+
+/// Pasted objects go this far in front of the camera if it isn't aimed at anything closer
+const PASTE_DISTANCE: f32 = 8.0;
+
+/// Copied models and brushes, stored the same way levels store them
+#[derive(Serialize, Deserialize)]
+struct ClipboardData {
+    /// Middle of the copied objects, it ends up at the aim point when pasted
+    center: [f32; 3],
+    models: Vec<ModelData>,
+    brushes: Vec<BrushData>
+}
+
+impl World {
+    /// Serialize the selection into the editor clipboard, returns how many objects were copied
+    pub fn copy_selection(&mut self) -> Result<usize, String> {
+        let items = self.editor_data.selected_object.as_ref().map(|selection| selection.items()).unwrap_or_default();
+        if items.is_empty() {
+            return Err(String::from("nothing is selected"));
+        }
+
+        let center = items.iter().map(|item| self.selection_item_origin(item)).sum::<Vector3<f32>>() / items.len() as f32;
+        let mut data = ClipboardData { center: center.into(), models: Vec::new(), brushes: Vec::new() };
+        let brushes = self.models[self.internal.brushes].as_ref().unwrap();
+        for item in items.iter() {
+            match item {
+                Selection::Brush(brush) => data.brushes.extend(BrushData::from_renderable(&brushes.render[*brush])),
+                Selection::Model(model) => data.models.push(ModelData::from_model(self, self.models[*model].as_ref().unwrap())),
+                Selection::Multiple(_) => unreachable!()
+            }
+        }
+
+        let json = serde_json::to_string(&data).map_err(|e| format!("failed to copy: {}", e))?;
+        self.editor_data.clipboard = Some(json);
+        Ok(items.len())
+    }
+
+    /// Insert the clipboard's objects around where the camera is aimed and select them<br>
+    /// Pasted models lose their names, since names have to be unique
+    pub fn paste_clipboard(&mut self) -> Result<usize, String> {
+        let Some(json) = &self.editor_data.clipboard else { return Err(String::from("nothing has been copied")); };
+        let data: ClipboardData = serde_json::from_str(json).map_err(|e| format!("failed to paste: {}", e))?;
+        let offset = self.aim_point() - Vector3::from(data.center);
+
+        let mut pasted = Vec::new();
+        for brush in data.brushes.iter() {
+            let mut brush = brush.as_renderable();
+            if let Renderable::Brush(material, origin, ..) = &mut brush {
+                if !self.scene.materials.contains_key(material) {
+                    return Err(format!("the material {} isn't loaded", material));
+                }
+                *origin += offset;
+            }
+            pasted.push(Selection::Brush(self.insert_brush(brush)));
+        }
+        for model in data.models.iter() {
+            let index = model.insert(self);
+            let transform = self.models[index].as_ref().unwrap().transform;
+            self.set_model_transform(index, Matrix4::from_translation(offset) * transform);
+            self.models[index].as_mut().unwrap().name = None;
+            pasted.push(Selection::Model(index));
+        }
+
+        self.record_selection_edit(EditKind::Created, &pasted);
+        self.select_items(&pasted);
+        Ok(pasted.len())
+    }
+
+    /// Replace the selection with `items`
+    pub fn select_items(&mut self, items: &[Selection]) {
+        self.deselect();
+        for item in items.iter() {
+            match item {
+                Selection::Brush(brush) => self.select_or_append_brush(*brush),
+                Selection::Model(model) => self.select_or_append_model(*model),
+                Selection::Multiple(_) => unreachable!()
+            }
+        }
+        self.set_arrows_visible(true);
+    }
+
+    /// Where the camera looks, on the first surface within `PASTE_DISTANCE`, snapped to the increment
+    pub fn aim_point(&mut self) -> Vector3<f32> {
+        let origin = self.scene.camera.pos.to_vec();
+        let direction = self.scene.camera.direction;
+        let hit = self.physical_scene.raycast(origin, direction, PASTE_DISTANCE, &RaycastParameters::new().ignore(vec![self.player.collider]));
+        let point = hit.map_or(origin + direction * PASTE_DISTANCE, |hit| hit.pos);
+        let increment = self.editor_data.increment;
+        point.map(|axis| common::round_to(axis, increment))
+    }
+}
@@ -0,0 +1,194 @@
+use cgmath::{vec3, vec4, Matrix4, Vector3, Vector4};
+use glow::{HasContext, NativeTexture};
+
+use crate::{render::{Camera, PointLight, Scene}, shader::Program};
+
+/// Tiles across and down the screen and slices of depth the view is split into
+pub const CLUSTER_COUNTS: [usize; 3] = [16, 9, 24];
+/// Point lights past this many aren't drawn outside safe mode
+pub const MAX_CLUSTERED_LIGHTS: usize = 1024;
+/// Position and shadow map, attenuation, ambient, diffuse and specular
+const LIGHT_TEXELS: usize = 5;
+/// Has to match `CLUSTER_INDEX_WIDTH` in the shaders
+const INDEX_WIDTH: usize = 1024;
+/// Texture unit of the light texture, the grid and the index textures use the two after it
+pub const CLUSTER_TEXTURE_UNIT: u32 = 6;
+
+/// Point lights sorted into a grid of clusters over the view, each fragment only loops over the lights of its own cluster<br>
+/// Rebuilt on the CPU every frame and stored in textures, without the uniform limit on how many lights there can be
+#[derive(Default)]
+pub struct LightClusters {
+    /// `LIGHT_TEXELS` RGBA texels per light
+    lights: Option<NativeTexture>,
+    /// Offset into `indices` and light count of each cluster, a row per depth slice
+    grid: Option<NativeTexture>,
+    /// Light indices of every cluster one after another, wrapped at `INDEX_WIDTH`
+    indices: Option<NativeTexture>,
+    /// Near and far plane the depth slices are spread between
+    depth: (f32, f32),
+    /// Lights in the last build and how many clusters they reached in total
+    pub light_count: usize,
+    pub index_count: usize
+}
+
+/// Depth slice that view space depth `depth` falls in, slices get thicker further away
+fn slice_of(depth: f32, near: f32, far: f32) -> usize {
+    let slice = (depth.max(near) / near).ln() / (far / near).ln() * CLUSTER_COUNTS[2] as f32;
+    (slice.max(0.0) as usize).min(CLUSTER_COUNTS[2] - 1)
+}
+
+/// Range of tiles covered by a sphere at view space `center`, from the corners of the box around it<br>
+/// Spheres reaching behind the near plane cover the whole screen
+fn tiles_of(center: Vector3<f32>, radius: f32, projection: Matrix4<f32>, near: f32) -> ([usize; 2], [usize; 2]) {
+    let full = ([0, CLUSTER_COUNTS[0] - 1], [0, CLUSTER_COUNTS[1] - 1]);
+    if -center.z - radius <= near {
+        return full;
+    }
+
+    let (mut min, mut max) = ([f32::MAX; 2], [f32::MIN; 2]);
+    for corner in 0..8 {
+        let offset = vec3(
+            if corner & 1 == 0 { -radius } else { radius },
+            if corner & 2 == 0 { -radius } else { radius },
+            if corner & 4 == 0 { -radius } else { radius }
+        );
+        let clip: Vector4<f32> = projection * (center + offset).extend(1.0);
+        for axis in 0..2 {
+            let ndc = clip[axis] / clip.w;
+            min[axis] = min[axis].min(ndc);
+            max[axis] = max[axis].max(ndc);
+        }
+    }
+
+    let tile = |ndc: f32, axis: usize| (((ndc + 1.0) / 2.0 * CLUSTER_COUNTS[axis] as f32).max(0.0) as usize).min(CLUSTER_COUNTS[axis] - 1);
+    ([tile(min[0], 0), tile(max[0], 0)], [tile(min[1], 1), tile(max[1], 1)])
+}
+
+impl LightClusters {
+    pub fn ready(&self) -> bool {
+        self.lights.is_some()
+    }
+
+    /// Sort `lights` into the clusters of `camera`'s view and upload them, `shadow_of` gives a light's shadow map
+    pub unsafe fn build(&mut self, lights: &[PointLight], shadow_of: impl Fn(usize) -> Option<usize>, camera: &Camera, gl: &glow::Context) {
+        let (near, far) = camera.clip_planes();
+        let cluster_count = CLUSTER_COUNTS[0] * CLUSTER_COUNTS[1] * CLUSTER_COUNTS[2];
+        let mut clusters: Vec<Vec<u32>> = vec![Vec::new(); cluster_count];
+        let mut light_data: Vec<f32> = Vec::with_capacity(lights.len().min(MAX_CLUSTERED_LIGHTS) * LIGHT_TEXELS * 4);
+
+        for (i, light) in lights.iter().take(MAX_CLUSTERED_LIGHTS).enumerate() {
+            let shadow = shadow_of(i).map_or(-1.0, |map| map as f32);
+            let texels = [
+                light.position.extend(shadow),
+                vec4(light.constant, light.linear, light.quadratic, 0.0),
+                light.ambient.extend(0.0),
+                light.diffuse.extend(0.0),
+                light.specular.extend(0.0)
+            ];
+            for texel in texels {
+                light_data.extend_from_slice(&<[f32; 4]>::from(texel));
+            }
+
+            let radius = light.user_attenuation_or_default();
+            let center = (camera.view * light.position.extend(1.0)).truncate();
+            let depth = -center.z;
+            if depth + radius < near || depth - radius > far {
+                continue;
+            }
+            let (x, y) = tiles_of(center, radius, camera.projection, near);
+            for z in slice_of(depth - radius, near, far)..=slice_of(depth + radius, near, far) {
+                for y in y[0]..=y[1] {
+                    for x in x[0]..=x[1] {
+                        clusters[x + y * CLUSTER_COUNTS[0] + z * CLUSTER_COUNTS[0] * CLUSTER_COUNTS[1]].push(i as u32);
+                    }
+                }
+            }
+        }
+
+        let mut grid: Vec<u32> = Vec::with_capacity(cluster_count * 2);
+        let mut indices: Vec<u32> = Vec::new();
+        for cluster in clusters.iter() {
+            grid.push(indices.len() as u32);
+            grid.push(cluster.len() as u32);
+            indices.extend_from_slice(cluster);
+        }
+        self.light_count = light_data.len() / (LIGHT_TEXELS * 4);
+        self.index_count = indices.len();
+        self.depth = (near, far);
+
+        // Textures can't be empty
+        if light_data.is_empty() {
+            light_data.resize(LIGHT_TEXELS * 4, 0.0);
+        }
+        let index_rows = indices.len().div_ceil(INDEX_WIDTH).max(1);
+        indices.resize(index_rows * INDEX_WIDTH, 0);
+
+        let lights_texture = *self.lights.get_or_insert_with(|| create_texture(gl));
+        upload(lights_texture, glow::RGBA32F, glow::RGBA, glow::FLOAT, (LIGHT_TEXELS, light_data.len() / (LIGHT_TEXELS * 4)), as_bytes(&light_data), gl);
+        let grid_texture = *self.grid.get_or_insert_with(|| create_texture(gl));
+        upload(grid_texture, glow::RG32UI, glow::RG_INTEGER, glow::UNSIGNED_INT, (CLUSTER_COUNTS[0] * CLUSTER_COUNTS[1], CLUSTER_COUNTS[2]), as_bytes(&grid), gl);
+        let indices_texture = *self.indices.get_or_insert_with(|| create_texture(gl));
+        upload(indices_texture, glow::R32UI, glow::RED_INTEGER, glow::UNSIGNED_INT, (INDEX_WIDTH, index_rows), as_bytes(&indices), gl);
+        gl.bind_texture(glow::TEXTURE_2D, None);
+    }
+
+    /// Set the cluster uniforms of a lighting program, with `clustered` off the program loops over `pointLights` instead<br>
+    /// The sampler units are set either way, samplers of different types can't share one
+    pub unsafe fn uniform(&self, program: &mut Program, clustered: bool, window_size: (u32, u32), gl: &glow::Context) {
+        program.uniform_1i32("clusterLights", CLUSTER_TEXTURE_UNIT as i32, gl);
+        program.uniform_1i32("clusterGrid", CLUSTER_TEXTURE_UNIT as i32 + 1, gl);
+        program.uniform_1i32("clusterIndices", CLUSTER_TEXTURE_UNIT as i32 + 2, gl);
+        program.uniform_1i32("clustered", clustered as i32, gl);
+        if !clustered {
+            return;
+        }
+
+        program.uniform_3i32("clusterCounts", vec3(CLUSTER_COUNTS[0] as i32, CLUSTER_COUNTS[1] as i32, CLUSTER_COUNTS[2] as i32), gl);
+        program.uniform_2f32("clusterDepth", self.depth.into(), gl);
+        program.uniform_2f32("screenSize", (window_size.0 as f32, window_size.1 as f32).into(), gl);
+        for (unit, texture) in [self.lights, self.grid, self.indices].into_iter().enumerate() {
+            gl.active_texture(glow::TEXTURE0 + CLUSTER_TEXTURE_UNIT + unit as u32);
+            gl.bind_texture(glow::TEXTURE_2D, texture);
+        }
+        gl.active_texture(glow::TEXTURE0);
+    }
+}
+
+unsafe fn create_texture(gl: &glow::Context) -> NativeTexture {
+    let texture = gl.create_texture().unwrap();
+    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+    // Integer textures are incomplete with linear filtering
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::NEAREST as i32);
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+    texture
+}
+
+unsafe fn upload(texture: NativeTexture, internal_format: u32, format: u32, kind: u32, size: (usize, usize), data: &[u8], gl: &glow::Context) {
+    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+    gl.tex_image_2d(
+        glow::TEXTURE_2D, 0, internal_format as i32,
+        size.0 as i32, size.1 as i32,
+        0, format, kind,
+        glow::PixelUnpackData::Slice(Some(data))
+    );
+}
+
+fn as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(data.as_ptr() as *const u8, core::mem::size_of_val(data)) }
+}
+
+impl Scene {
+    /// Whether the lighting programs use the clusters, the orthographic views, offscreen renders and safe mode loop over uniforms instead
+    pub fn clustered_lighting(&self) -> bool {
+        !self.safe_mode && self.camera.ortho.is_none() && !self.drawing_offscreen && self.clusters.ready()
+    }
+
+    /// Sort the point lights into clusters for this frame's camera, call after `render_shadows`
+    pub unsafe fn prepare_lights(&mut self, gl: &glow::Context) {
+        if self.safe_mode {
+            return;
+        }
+        let shadows = &self.shadows;
+        self.clusters.build(&self.point_lights, |light| shadows.map_of(light), &self.camera, gl);
+    }
+}
@@ -1,56 +1,179 @@
 use core::f32;
+use std::{collections::HashMap, path::PathBuf};
 
 use cgmath::{vec3, vec4, InnerSpace, Matrix3, Matrix4, Vector3, Zero};
-use parry3d::{bounding_volume::{Aabb, BoundingVolume}, na::{self, Isometry3, Point3}, query::{self, Contact, Ray}, shape::{Cuboid, Shape}};
+use parry3d::{bounding_volume::{Aabb, BoundingVolume}, na::{self, Isometry3, Point3}, partitioning::{Qbvh, QbvhUpdateWorkspace}, query::{self, visitors::RayIntersectionsVisitor, Contact, Ray}, shape::{ConvexPolyhedron, Cuboid, Shape, TriMesh}};
 use serde::{Deserialize, Serialize};
 
 use crate::{common, world::{Model, ModelCollider, Renderable, World}};
 
 pub const STAIR_MAX_SIZE: f32 = 0.55;
+/// Bounding boxes in the tree are loosened by this much, so small moves don't need the tree changed
+const TREE_MARGIN: f32 = 0.1;
 
 pub struct PhysicalScene {
-    pub colliders: Vec<Option<Collider>>
+    colliders: Vec<Option<Collider>>,
+    /// Bounding volume hierarchy over the colliders' bounding boxes, leaves are collider indices
+    tree: Qbvh<usize>,
+    tree_workspace: QbvhUpdateWorkspace,
+    /// Colliders added, removed or changed since the tree was last updated
+    dirty: Vec<usize>,
+    /// Shapes made from obj files, by file and whether it is the convex hull
+    mesh_shapes: HashMap<(String, bool), ColliderShape>
 }
 
 impl PhysicalScene {
     pub fn new() -> Self {
         Self {
-            colliders: Vec::new()
+            colliders: Vec::new(),
+            tree: Qbvh::new(),
+            tree_workspace: QbvhUpdateWorkspace::default(),
+            dirty: Vec::new(),
+            mesh_shapes: HashMap::new()
         }
     }
 
+    /// Triangles of `res/models/{obj}.obj`, or their convex hull, with every object in the file merged<br>
+    /// Shapes are kept after the first time, so each file is only read once
+    pub fn mesh_shape(&mut self, obj: &str, hull: bool) -> Result<ColliderShape, String> {
+        let key = (obj.to_string(), hull);
+        if let Some(shape) = self.mesh_shapes.get(&key) {
+            return Ok(shape.clone());
+        }
+
+        let path = PathBuf::from(format!("res/models/{}.obj", obj));
+        let (models, _) = tobj::load_obj(&path, &tobj::LoadOptions { triangulate: true, single_index: true, ..Default::default() })
+            .map_err(|e| format!("failed to load collider {}: {}", obj, e))?;
+        let mut vertices = Vec::new();
+        let mut triangles = Vec::new();
+        for model in models.iter() {
+            let first = vertices.len() as u32;
+            vertices.extend(model.mesh.positions.chunks(3).map(|p| Point3::new(p[0], p[1], p[2])));
+            triangles.extend(model.mesh.indices.chunks(3).map(|t| [first + t[0], first + t[1], first + t[2]]));
+        }
+
+        let shape = if hull {
+            ConvexPolyhedron::from_convex_hull(&vertices).map(ColliderShape::ConvexHull)
+                .ok_or_else(|| format!("{} is flat, so it has no convex hull", obj))?
+        } else {
+            TriMesh::new(vertices, triangles).map(ColliderShape::TriMesh)
+                .map_err(|e| format!("{} can't be a collider: {:?}", obj, e))?
+        };
+        self.mesh_shapes.insert(key, shape.clone());
+        Ok(shape)
+    }
+
     pub fn add_collider(&mut self, collider: Collider) -> usize {
         for (i, maybe_empty) in self.colliders.iter_mut().enumerate() {
             if maybe_empty.is_none() {
                 *maybe_empty = Some(collider);
+                self.dirty.push(i);
                 return i;
             }
         }
 
         self.colliders.push(Some(collider));
+        self.dirty.push(self.colliders.len() - 1);
         self.colliders.len() - 1
     }
 
     pub fn remove_collider(&mut self, index: usize) -> Result<(), String> {
         if let Some(collider) = self.colliders.get_mut(index) {
             *collider = None;
+            self.dirty.push(index);
             Ok(())
         } else {
             Err("Index out of bounds".to_string())
         }
     }
 
+    pub fn collider(&self, index: usize) -> Option<&Collider> {
+        self.colliders.get(index)?.as_ref()
+    }
+
+    /// Marks the collider as changed, so go through this for anything that moves it
+    pub fn collider_mut(&mut self, index: usize) -> Option<&mut Collider> {
+        self.dirty.push(index);
+        self.colliders.get_mut(index)?.as_mut()
+    }
+
+    /// Replace the collider at an index that is already in use
+    pub fn set_collider(&mut self, index: usize, collider: Collider) {
+        self.colliders[index] = Some(collider);
+        self.dirty.push(index);
+    }
+
+    pub fn colliders(&self) -> impl Iterator<Item = &Collider> {
+        self.colliders.iter().flatten()
+    }
+
     pub fn set_collider_pos(&mut self, index: usize, pos: Vector3<f32>) {
-        self.colliders.get_mut(index).unwrap().as_mut().unwrap().set_pos(pos.x, pos.y, pos.z);
+        self.collider_mut(index).unwrap().set_pos(pos.x, pos.y, pos.z);
+    }
+
+    /// Bring the tree up to date with the colliders changed since the last query
+    fn update_tree(&mut self) {
+        if self.dirty.is_empty() {
+            return;
+        }
+
+        self.dirty.sort_unstable();
+        self.dirty.dedup();
+        for index in self.dirty.drain(..) {
+            if self.colliders.get(index).is_some_and(|collider| collider.is_some()) {
+                self.tree.pre_update_or_insert(index);
+            } else {
+                self.tree.remove(index);
+            }
+        }
+
+        let colliders = &self.colliders;
+        self.tree.refit(TREE_MARGIN, &mut self.tree_workspace, |index| colliders[*index].as_ref().unwrap().bounding);
+        self.tree.rebalance(TREE_MARGIN, &mut self.tree_workspace);
+    }
+
+    /// Indices of the colliders whose bounding boxes might touch `aabb`, in ascending order
+    fn colliders_near(&mut self, aabb: &Aabb) -> Vec<usize> {
+        self.update_tree();
+        let mut near = Vec::new();
+        self.tree.intersect_aabb(aabb, &mut near);
+        near.sort_unstable();
+        near
+    }
+
+    /// Indices of the colliders whose bounding boxes the ray passes through, in ascending order
+    fn colliders_along(&mut self, ray: &Ray, distance: f32) -> Vec<usize> {
+        self.update_tree();
+        let mut along = Vec::new();
+        let mut visit = |index: &usize| {
+            along.push(*index);
+            true
+        };
+        self.tree.traverse_depth_first(&mut RayIntersectionsVisitor::new(ray, distance, &mut visit));
+        along.sort_unstable();
+        along
+    }
+
+    /// Whether `collider` would be inside any solid collider other than `ignore`, it doesn't have to be in the scene
+    pub fn overlaps_solid(&mut self, collider: &Collider, ignore: usize) -> bool {
+        let near = self.colliders_near(&collider.bounding);
+        near.into_iter().filter(|i| *i != ignore).any(|i| {
+            self.colliders[i].as_ref().is_some_and(|other| other.solid && collider.get_contact(other).is_some())
+        })
     }
 
     pub fn move_and_slide(&mut self, index: usize, vel: Vector3<f32>) -> MoveSlideResult {
-        self.colliders.get_mut(index).unwrap().as_mut().unwrap().shift(vel.x, vel.y, vel.z);
+        // The collider never ends up further than `vel` from where it started, or a stair step above that
+        let reach = vel.magnitude() + STAIR_MAX_SIZE;
+        let reachable = self.colliders[index].as_ref().unwrap().bounding.loosened(reach);
+        let near = self.colliders_near(&reachable);
+
+        self.collider_mut(index).unwrap().shift(vel.x, vel.y, vel.z);
         let mut final_velocity = vel;
         let mut normals = Vec::new();
         let mut materials = Vec::new();
 
-        for i in 0..self.colliders.len() {
+        for i in near {
             if i != index {
                 if self.colliders[i].is_none() { continue; }
                 if !self.colliders.get(i).unwrap().as_ref().unwrap().solid { continue; }
@@ -75,7 +198,7 @@ impl PhysicalScene {
                             let projected = final_velocity.project_on(hit_normal);
                             final_velocity -= projected;
                             normals.push(hit_normal);
-                            materials.push(self.colliders.get(i).unwrap().as_ref().unwrap().physical_properties);
+                            materials.push(self.colliders.get(i).unwrap().as_ref().unwrap().physical_properties.clone());
 
                             self.colliders.get_mut(index).unwrap().as_mut().unwrap().shift(-initial_velocity.x, -initial_velocity.y, -initial_velocity.z);
                             self.colliders.get_mut(index).unwrap().as_mut().unwrap().shift(final_velocity.x, final_velocity.y, final_velocity.z);
@@ -100,10 +223,11 @@ impl PhysicalScene {
         let mut closest_intersection = f32::MAX;
         let mut result: Option<RaycastResult> = None;
         let ray = Ray::new(Point3::new(origin.x, origin.y, origin.z), parry3d::na::Vector3::new(direction.x, direction.y, direction.z).normalize());
+        let along = self.colliders_along(&ray, distance);
         
         // Check for meshes in the foreground (e.g. movement arrows)
         if params.select_foreground {
-            for i in 0..self.colliders.len() {
+            for &i in along.iter() {
                 if params.ignore.contains(&i) { continue; }
                 
                 if let Some(collider) = &self.colliders[i] {
@@ -131,13 +255,13 @@ impl PhysicalScene {
             }
         }
 
-        for i in 0..self.colliders.len() {
+        for i in along {
             if params.ignore.contains(&i) { continue; }
 
             if let Some(collider) = &self.colliders[i] {
                 if params.respect_solid && !collider.solid { continue; }
-                // Ignore colliders the point is inside of
-                if collider.bounding.contains_local_point(&na::Point3::new(origin.x, origin.y, origin.z)) { continue; }
+                // Ignore colliders the point is inside of, triangle meshes are hollow so only their surface counts
+                if collider.shape.is_solid() && collider.bounding.contains_local_point(&na::Point3::new(origin.x, origin.y, origin.z)) { continue; }
                 if let Some(intersection) = collider.shape.as_shape().cast_ray_and_get_normal(&collider.iso, &ray, distance, true) {
                     if intersection.time_of_impact < closest_intersection {
                         closest_intersection = intersection.time_of_impact;
@@ -210,6 +334,23 @@ impl Model {
 
                 self.colliders.push(Some(world.physical_scene.add_collider(collider)));
             },
+            ModelCollider::TriMesh { offset, obj } | ModelCollider::ConvexHull { offset, obj } => {
+                let hull = matches!(model_collider, ModelCollider::ConvexHull { .. });
+                match world.physical_scene.mesh_shape(obj, hull) {
+                    Ok(shape) => {
+                        let mut collider = Collider::mesh(shape, *offset, model_transform);
+                        collider.model = self.index;
+                        collider.foreground = self.foreground;
+                        collider.solid = self.solid;
+                        self.colliders.push(Some(world.physical_scene.add_collider(collider)));
+                    },
+                    Err(e) => {
+                        // Keeps the collider indices lined up with `insert_collider`
+                        world.editor_data.show_debug.push(e);
+                        self.colliders.push(None);
+                    }
+                }
+            },
             ModelCollider::Multiple { colliders } => {
                 for collider in colliders.iter() {
                     self.insert_model_collider(collider, model_transform, world);
@@ -224,8 +365,8 @@ impl Model {
 
         for (i, renderable) in self.render.iter().enumerate() {
             match renderable {
-                Renderable::Brush(material, position, size, _) => {
-                    let properties = world.scene.materials.get(material).unwrap().physical_properties;
+                Renderable::Brush(material, position, size, ..) => {
+                    let properties = world.scene.materials.get(material).unwrap().physical_properties.clone();
                     let mut collider = Collider::cuboid(*position, *size, Vector3::zero(), self.transform);
                     collider.physical_properties = properties;
                     collider.renderable = Some(i);
@@ -251,14 +392,14 @@ impl Model {
 impl World {
     fn update_model_collider(&mut self, model_collider: &ModelCollider, model_transform: Matrix4<f32>, model: usize, i: usize) {
         match model_collider {
-            ModelCollider::Cuboid { offset, half_extents } => {
-                let collider_index = self.models[model].as_ref().unwrap().colliders[i].unwrap();
-                self.physical_scene.colliders[collider_index].as_mut().unwrap().set_transform(model_transform);
-            },
             ModelCollider::Multiple { colliders } => {
                 for (j, collider) in colliders.iter().enumerate() {
                     self.update_model_collider(collider, model_transform, model, i + j);
                 }
+            },
+            // Mesh colliders whose file failed to load don't have one
+            _ => if let Some(collider_index) = self.models[model].as_ref().unwrap().colliders[i] {
+                self.physical_scene.collider_mut(collider_index).unwrap().set_transform(model_transform);
             }
         }
     }
@@ -268,8 +409,8 @@ impl World {
         let model_transform = self.models[model].as_ref().unwrap().transform;
 
         for i in 0..self.models[model].as_ref().unwrap().render.len() {
-            if let Renderable::Brush(material, position, size, _) = &self.models[model].as_ref().unwrap().render[i] {
-                let properties = self.scene.materials.get(material).unwrap().physical_properties;
+            if let Renderable::Brush(material, position, size, ..) = &self.models[model].as_ref().unwrap().render[i] {
+                let properties = self.scene.materials.get(material).unwrap().physical_properties.clone();
                 let mut collider = Collider::cuboid(*position, *size, Vector3::zero(), model_transform);
                 collider.physical_properties = properties;
                 collider.renderable = Some(i);
@@ -277,7 +418,7 @@ impl World {
                 collider.foreground = self.models[model].as_ref().unwrap().foreground;
                 collider.solid = self.models[model].as_ref().unwrap().solid;
                 let collider_index = self.models[model].as_ref().unwrap().colliders[i].unwrap();
-                self.physical_scene.colliders[collider_index] = Some(collider);
+                self.physical_scene.set_collider(collider_index, collider);
             }
         }
 
@@ -295,9 +436,9 @@ impl World {
         let model_transform = self.models[model].as_ref().unwrap().transform;
 
         for i in 0..self.models[model].as_ref().unwrap().render.len() {
-            if let Renderable::Brush(_, _, _, _) = &self.models[model].as_ref().unwrap().render[i] {
+            if let Renderable::Brush(..) = &self.models[model].as_ref().unwrap().render[i] {
                 let collider_index = self.models[model].as_ref().unwrap().colliders[i].unwrap();
-                self.physical_scene.colliders[collider_index].as_mut().unwrap().set_transform(model_transform);
+                self.physical_scene.collider_mut(collider_index).unwrap().set_transform(model_transform);
             }
         }
 
@@ -318,12 +459,16 @@ pub struct MoveSlideResult {
     pub final_position: Vector3<f32>
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PhysicalProperties {
     pub friction: f32,
     pub control: f32,
     #[serde(default)]
-    pub jump: f32
+    pub jump: f32,
+    /// Folder in `res/audio/footsteps` the player's footsteps on this are played from, see `footstep`<br>
+    /// Only comes from the brush types, it isn't saved with levels so binary levels still load
+    #[serde(skip)]
+    pub sound_set: Option<String>
 }
 
 pub const DEFAULT_FRICTION: f32 = 0.8;
@@ -335,7 +480,8 @@ impl Default for PhysicalProperties {
         Self {
             friction: DEFAULT_FRICTION,
             control: DEFAULT_CONTROL,
-            jump: DEFAULT_JUMP
+            jump: DEFAULT_JUMP,
+            sound_set: None
         }
     }
 }
@@ -355,27 +501,48 @@ fn decompose_matrix(mat: Matrix4<f32>) -> (Vector3<f32>, na::Isometry3<f32>) {
     );
 
     let translation = na::Translation3::new(t.x, t.y, t.z);
+    // cgmath is built from columns and nalgebra from rows
     let rotation = na::Rotation3::from_matrix_unchecked(na::Matrix3::new(
-        rot3.x.x, rot3.x.y, rot3.x.z,
-        rot3.y.x, rot3.y.y, rot3.y.z,
-        rot3.z.x, rot3.z.y, rot3.z.z,
+        rot3.x.x, rot3.y.x, rot3.z.x,
+        rot3.x.y, rot3.y.y, rot3.z.y,
+        rot3.x.z, rot3.y.z, rot3.z.z,
     ));
 
     (scale, na::Isometry3::from_parts(translation, rotation.into()))
 }
 
+/// Where a collider `local_iso` from its model ends up, the offset is scaled with the model like its shape
+fn place_collider(model_iso: &Isometry3<f32>, local_iso: &Isometry3<f32>, scale: Vector3<f32>) -> Isometry3<f32> {
+    let offset = local_iso.translation.vector.component_mul(&na::Vector3::new(scale.x, scale.y, scale.z));
+    model_iso * Isometry3::from_parts(offset.into(), local_iso.rotation)
+}
+
+/// Bounds of `shape` at `iso`, a little bigger so touching colliders are still checked
+fn collider_bounds(shape: &ColliderShape, iso: &Isometry3<f32>) -> Aabb {
+    shape.as_shape().compute_aabb(iso).scaled_wrt_center(&na::Vector3::new(1.02, 1.02, 1.02))
+}
+
 #[derive(Clone)]
 pub enum ColliderShape {
-    Cuboid(Cuboid)
+    Cuboid(Cuboid),
+    TriMesh(TriMesh),
+    ConvexHull(ConvexPolyhedron)
 }
 
 impl ColliderShape {
     pub fn as_shape(&self) -> &dyn Shape {
         match self {
-            Self::Cuboid(c) => c
+            Self::Cuboid(c) => c,
+            Self::TriMesh(mesh) => mesh,
+            Self::ConvexHull(hull) => hull
         }
     }
 
+    /// Triangle meshes are only a surface, anything inside them isn't touching them
+    pub fn is_solid(&self) -> bool {
+        !matches!(self, Self::TriMesh(_))
+    }
+
     /// This does scaling that an `Isometry3` cannot represent<br>
     /// Translation and rotation should be represented in the collider's `Isometry3`
     pub fn scaled(&self, scale: Vector3<f32>) -> Self {
@@ -387,14 +554,16 @@ impl ColliderShape {
                     c.half_extents.y * scale.y,
                     c.half_extents.z * scale.z
                 )))
-            }
+            },
+            Self::TriMesh(mesh) => Self::TriMesh(mesh.clone().scaled(&na::Vector3::new(scale.x, scale.y, scale.z))),
+            // Fails if a scale is zero, the hull is left as it was
+            Self::ConvexHull(hull) => hull.clone().scaled(&na::Vector3::new(scale.x, scale.y, scale.z)).map_or_else(|| self.clone(), Self::ConvexHull)
         }
     }
 }
 
 pub struct Collider {
     pub bounding: Aabb,
-    original_bounding: Aabb,
     pub solid: bool,
 
     /// Only affects mouse raycasting, if this is true raycast will prioritize this
@@ -404,6 +573,8 @@ pub struct Collider {
     pub shape: ColliderShape,
     pub iso: Isometry3<f32>,
     local_iso: Isometry3<f32>,
+    /// Scale `shape` was made with, mesh shapes are only rebuilt when it changes
+    scale: Vector3<f32>,
     pub physical_properties: PhysicalProperties,
     pub model: Option<usize>,
     pub renderable: Option<usize>
@@ -426,16 +597,19 @@ impl Collider {
 
     pub fn set_transform(&mut self, transform: Matrix4<f32>) {
         let (scale, iso) = decompose_matrix(transform);
-        self.shape = self.original_shape.scaled(scale);
-        self.iso = iso * self.local_iso;
-        // these might need to be flipped
-        self.bounding = self.original_bounding.scaled(&na::Vector3::new(scale.x, scale.y, scale.z)).transform_by(&self.iso);
+        if scale != self.scale {
+            self.shape = self.original_shape.scaled(scale);
+            self.scale = scale;
+        }
+        self.iso = place_collider(&iso, &self.local_iso, scale);
+        self.bounding = collider_bounds(&self.shape, &self.iso);
     }
 
     pub fn get_contact(&self, other: &Collider) -> Option<Contact> {
         if self.bounding.intersects(&other.bounding) {
-            let contact = query::contact(&self.iso, self.shape.as_shape(), &other.iso, other.shape.as_shape(), 1.0).unwrap();
-            if let Some(contact) = contact {
+            // Unsupported between two triangle meshes, which is taken as no contact
+            let contact = query::contact(&self.iso, self.shape.as_shape(), &other.iso, other.shape.as_shape(), 1.0);
+            if let Ok(Some(contact)) = contact {
                 if contact.dist < 0.0 {
                     return Some(contact);
                 }
@@ -445,27 +619,49 @@ impl Collider {
         None
     }
 
+    /// Whether the colliders are within `margin` of each other, so solid colliders the player is pressed against count
+    pub fn touches(&self, other: &Collider, margin: f32) -> bool {
+        self.bounding.loosened(margin).intersects(&other.bounding)
+            && query::distance(&self.iso, self.shape.as_shape(), &other.iso, other.shape.as_shape()).is_ok_and(|distance| distance <= margin)
+    }
+
     pub fn cuboid(center: Vector3<f32>, full_extents: Vector3<f32>, axis_angle: Vector3<f32>, model_transform: Matrix4<f32>,) -> Self {
         let (scale, model_iso) = decompose_matrix(model_transform);
         let original_shape = ColliderShape::Cuboid(Cuboid::new(parry3d::na::Vector3::new(full_extents.x / 2.0, full_extents.y / 2.0, full_extents.z / 2.0)));
-        let original_bounding = Aabb::from_half_extents(
-                Point3::new(center.x, center.y, center.z), 
-                parry3d::na::Vector3::new(full_extents.x / 2.0, full_extents.y / 2.0, full_extents.z / 2.0)
-            ).scaled_wrt_center(&parry3d::na::Vector3::new(1.02, 1.02, 1.02));
         let local_iso = Isometry3::new(
                 parry3d::na::Vector3::new(center.x, center.y, center.z),
                 parry3d::na::Vector3::new(axis_angle.x, axis_angle.y, axis_angle.z)
             );
-        let iso = local_iso * model_iso;
-        let bounding = original_bounding.scaled(&na::Vector3::new(scale.x, scale.y, scale.z)).transform_by(&model_iso);
+        let iso = place_collider(&model_iso, &local_iso, scale);
+        let shape = original_shape.scaled(scale);
+        Self {
+            bounding: collider_bounds(&shape, &iso),
+            original_shape,
+            shape,
+            iso,
+            local_iso,
+            scale,
+            physical_properties: PhysicalProperties::default(),
+            model: None,
+            renderable: None,
+            foreground: false,
+            solid: true
+        }
+    }
+
+    /// Collider with a shape made by `PhysicalScene::mesh_shape`, `offset` from the model
+    pub fn mesh(original_shape: ColliderShape, offset: Vector3<f32>, model_transform: Matrix4<f32>) -> Self {
+        let (scale, model_iso) = decompose_matrix(model_transform);
+        let local_iso = Isometry3::translation(offset.x, offset.y, offset.z);
+        let iso = place_collider(&model_iso, &local_iso, scale);
         let shape = original_shape.scaled(scale);
         Self {
-            bounding,
-            original_bounding,
+            bounding: collider_bounds(&shape, &iso),
             original_shape,
             shape,
             iso,
             local_iso,
+            scale,
             physical_properties: PhysicalProperties::default(),
             model: None,
             renderable: None,
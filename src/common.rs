@@ -1,6 +1,6 @@
 use core::f32;
 
-use cgmath::{vec3, Matrix, Matrix3, Matrix4, SquareMatrix, Vector3};
+use cgmath::{vec3, Deg, Euler, InnerSpace, Matrix, Matrix3, Matrix4, Quaternion, SquareMatrix, Vector3};
 
 pub fn round_to(num: f32, to: f32) -> f32 {
     (num / to).round() * to
@@ -48,6 +48,53 @@ pub fn translation(mat: Matrix4<f32>) -> Vector3<f32> {
     mat.w.xyz()
 }
 
+/// Euler rotation in degrees and scale of a transform, assumes there is no shear
+pub fn rotation_scale(mat: Matrix4<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let rotation = mat4_to_mat3(mat);
+    let scale = vec3(rotation.x.magnitude(), rotation.y.magnitude(), rotation.z.magnitude());
+    let rotation = Matrix3::from_cols(rotation.x / scale.x, rotation.y / scale.y, rotation.z / scale.z);
+    let euler = Euler::from(Quaternion::from(rotation));
+    (vec3(Deg::from(euler.x).0, Deg::from(euler.y).0, Deg::from(euler.z).0), scale)
+}
+
+/// The opposite of `rotation_scale`, rotation is in degrees
+pub fn compose_transform(position: Vector3<f32>, rotation: Vector3<f32>, scale: Vector3<f32>) -> Matrix4<f32> {
+    Matrix4::from_translation(position) *
+    Matrix4::from(Euler::new(Deg(rotation.x), Deg(rotation.y), Deg(rotation.z))) *
+    Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z)
+}
+
+/// Whether two boxes share some volume, touching faces don't count
+pub fn boxes_overlap(a: (Vector3<f32>, Vector3<f32>), b: (Vector3<f32>, Vector3<f32>)) -> bool {
+    (0..3).all(|axis| a.0[axis] < b.1[axis] && b.0[axis] < a.1[axis])
+}
+
+/// The parts of the box `min`..`max` outside of `cut`, as up to six boxes
+pub fn box_difference(min: Vector3<f32>, max: Vector3<f32>, cut: (Vector3<f32>, Vector3<f32>)) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+    if !boxes_overlap((min, max), cut) {
+        return vec![(min, max)];
+    }
+
+    // Slice off the part on each side of the cut, one axis at a time, what is left in the middle is cut away
+    let mut pieces = Vec::new();
+    let (mut rest_min, mut rest_max) = (min, max);
+    for axis in 0..3 {
+        if cut.0[axis] > rest_min[axis] {
+            let mut piece_max = rest_max;
+            piece_max[axis] = cut.0[axis];
+            pieces.push((rest_min, piece_max));
+            rest_min[axis] = cut.0[axis];
+        }
+        if cut.1[axis] < rest_max[axis] {
+            let mut piece_min = rest_min;
+            piece_min[axis] = cut.1[axis];
+            pieces.push((piece_min, rest_max));
+            rest_max[axis] = cut.1[axis];
+        }
+    }
+    pieces
+}
+
 // https://learnopengl.com/Lighting/Basic-Lighting
 pub fn normal_matrix(mat: Matrix4<f32>) -> Matrix3<f32> {
     mat4_to_mat3(mat.invert().unwrap().transpose())
@@ -93,10 +140,39 @@ pub fn vec3_div_compwise(a: Vector3<f32>, b: Vector3<f32>) -> Vector3<f32> {
     vec3(a.x / b.x, a.y / b.y, a.z / b.z)
 }
 
+/// Hash an integer into 0 - 1
+fn hash_unit(n: i32) -> f32 {
+    let mut x = n as u32;
+    x = (x ^ 61) ^ (x >> 16);
+    x = x.wrapping_mul(9);
+    x ^= x >> 4;
+    x = x.wrapping_mul(0x27d4eb2d);
+    x ^= x >> 15;
+    x as f32 / u32::MAX as f32
+}
+
+/// Smooth 1D noise in 0 - 1, changes about once per unit of `x`
+pub fn value_noise(x: f32) -> f32 {
+    let i = x.floor();
+    let t = x - i;
+    let t = t * t * (3.0 - 2.0 * t);
+    let a = hash_unit(i as i32);
+    let b = hash_unit(i as i32 + 1);
+    a + (b - a) * t
+}
+
 pub fn towards(a: f32, b: f32, by: f32) -> f32 {
     (b - a).signum() * by
 }
 
 pub fn fuzzy_eq(a: f32, b: f32, epsilon: f32) -> bool {
     (a - b).abs() < epsilon
-}
\ No newline at end of file
+}
+/// `name` trimmed, if it stays inside the directory it is used as a file name in
+pub fn plain_file_name(name: &str) -> Result<&str, String> {
+    let name = name.trim();
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\', ':', '\0']) {
+        return Err(format!("\"{}\" can't be used as a file name, names can't be empty or have path separators", name));
+    }
+    Ok(name)
+}
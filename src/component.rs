@@ -1,9 +1,9 @@
 use std::mem;
 
-use cgmath::{vec3, EuclideanSpace, Matrix4, MetricSpace, Point3, Transform, Vector3};
+use cgmath::{vec3, Deg, InnerSpace, Matrix4, MetricSpace, Transform, Vector3};
 use serde::{Deserialize, Serialize};
 
-use crate::{common, effects::{FogEffect, KernelEffect}, world::{Model, Renderable, World}};
+use crate::{audio::{MusicChannel, SoundEmitter}, button::Button, common, effects::{FogEffect, KernelEffect}, events::EventKind, feed::CameraFeed, goal::Goal, impostor::DrawDistance, mover::Mover, notes::Note, objective::Objective, reflection::ReflectionPlane, render::SpotLight, rules, script::Script, wiring::LinkKind, world::{Model, Renderable, World}};
 
 fn zero_vec_slice() -> [f32; 3] {
     [0.0; 3]
@@ -14,22 +14,207 @@ pub struct Door {
     pub radius: f32,
     pub height: f32,
     pub open_time: u32,
+    /// Name of a trigger the door opens while the player is in, instead of opening when they are near
+    #[serde(default)]
+    pub trigger: Option<String>,
     #[serde(skip, default="zero_vec_slice")]
     origin: [f32; 3],
     #[serde(skip)]
     open_progress: u32,
     #[serde(skip)]
-    opened: bool
+    opened: bool,
+    /// Opened by a target trigger or an input, stays open until play stops or it gets `Close`
+    #[serde(skip)]
+    pub held_open: bool,
+    /// Closed by an input, stays shut until play stops or it gets `Open`
+    #[serde(skip)]
+    pub held_closed: bool
 }
 
 impl Door {
     pub fn new(radius: f32, height: f32, open_time: u32) -> Self {
         Self {
             radius, height, opened: false,
-            open_time, origin: [0.0; 3],
-            open_progress: 0
+            open_time, trigger: None, origin: [0.0; 3],
+            open_progress: 0, held_open: false, held_closed: false
+        }
+    }
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum LightAnimation {
+    /// Random flickering
+    Flicker,
+    /// Smooth sine wave
+    Pulse,
+    /// Hard on and off
+    Strobe,
+    /// Soft flickering that gets redder as it dims
+    Candle
+}
+
+impl LightAnimation {
+    pub const ALL: [LightAnimation; 4] = [Self::Flicker, Self::Pulse, Self::Strobe, Self::Candle];
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Flicker => "flicker",
+            Self::Pulse => "pulse",
+            Self::Strobe => "strobe",
+            Self::Candle => "candle"
         }
     }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|animation| animation.name() == name)
+    }
+}
+
+/// Drives the intensity and color of every light on its model
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct LightAnimator {
+    pub animation: LightAnimation,
+    /// How far the intensity swings away from normal, 1 goes fully dark
+    pub amplitude: f32,
+    /// Cycles per second
+    pub speed: f32,
+    #[serde(skip)]
+    time: f32
+}
+
+impl LightAnimator {
+    pub fn new(animation: LightAnimation, amplitude: f32, speed: f32) -> Self {
+        Self { animation, amplitude, speed, time: 0.0 }
+    }
+
+    /// Animator with parameters that suit the animation
+    pub fn preset(animation: LightAnimation) -> Self {
+        match animation {
+            LightAnimation::Flicker => Self::new(animation, 0.6, 8.0),
+            LightAnimation::Pulse => Self::new(animation, 0.5, 0.5),
+            LightAnimation::Strobe => Self::new(animation, 1.0, 4.0),
+            LightAnimation::Candle => Self::new(animation, 0.25, 3.0)
+        }
+    }
+
+    /// Per channel multiplier for the light's color at the current time
+    pub fn color_multiplier(&self) -> Vector3<f32> {
+        let t = self.time * self.speed;
+        let wave = match self.animation {
+            LightAnimation::Flicker => common::value_noise(t) * 2.0 - 1.0,
+            LightAnimation::Pulse => (t * std::f32::consts::TAU).sin(),
+            LightAnimation::Strobe => if t.fract() < 0.5 { 1.0 } else { -1.0 },
+            LightAnimation::Candle => (common::value_noise(t) * 2.0 - 1.0) * 0.7 + (common::value_noise(t * 3.1 + 17.0) * 2.0 - 1.0) * 0.3
+        };
+        let intensity = (1.0 + wave * self.amplitude).max(0.0);
+
+        if self.animation == LightAnimation::Candle {
+            vec3(intensity, intensity.powf(1.3), intensity.powf(1.6))
+        } else {
+            common::vec3_all(intensity)
+        }
+    }
+}
+
+/// Cone of light shining from the model, it turns with the model
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+pub struct SpotLightSource {
+    pub color: [f32; 3],
+    pub radius: f32,
+    /// Relative to the model's rotation
+    pub direction: [f32; 3],
+    /// Degrees from the center of the cone
+    pub inner_angle: f32,
+    pub outer_angle: f32
+}
+
+impl SpotLightSource {
+    pub fn new(direction: Vector3<f32>) -> Self {
+        Self { color: [1.0; 3], radius: 12.0, direction: direction.into(), inner_angle: 20.0, outer_angle: 30.0 }
+    }
+
+    /// The light for a model at `transform`
+    pub fn light(&self, transform: Matrix4<f32>) -> SpotLight {
+        let direction = transform.transform_vector(self.direction.into());
+        SpotLight::new(common::translation(transform), direction, self.color.into(), self.radius, self.inner_angle, self.outer_angle)
+    }
+}
+
+/// Spins the model around its origin while playing
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Rotator {
+    /// Relative to the model's rotation
+    pub axis: [f32; 3],
+    /// Degrees per second, negative spins the other way
+    pub speed: f32,
+    #[serde(skip)]
+    angle: f32,
+    /// Transform before spinning, put back when play stops
+    #[serde(skip)]
+    placed: Option<Matrix4<f32>>,
+    /// Stopped by a `Stop` input while playing
+    #[serde(skip)]
+    pub stopped: bool
+}
+
+impl Rotator {
+    pub fn new(axis: Vector3<f32>, speed: f32) -> Self {
+        Self { axis: axis.into(), speed, angle: 0.0, placed: None, stopped: false }
+    }
+
+    pub fn update(&mut self, mut model: Model, world: &mut World, delta_time: f32) -> Model {
+        if !world.do_game_logic {
+            if let Some(placed) = self.placed.take() {
+                self.angle = 0.0;
+                self.stopped = false;
+                model = world.set_model_transform_external(model, placed);
+            }
+            return model;
+        }
+
+        let placed = *self.placed.get_or_insert(model.transform);
+        let axis = Vector3::from(self.axis);
+        if self.stopped || axis.magnitude2() < 0.0001 {
+            return model;
+        }
+        self.angle = (self.angle + self.speed * delta_time) % 360.0;
+        let rotation = common::mat4_remove_translation(placed);
+        let axis = rotation.transform_vector(axis).normalize();
+        let transform = Matrix4::from_translation(common::translation(placed)) * Matrix4::from_axis_angle(axis, Deg(self.angle)) * rotation;
+        world.set_model_transform_external(model, transform)
+    }
+}
+
+/// What a target trigger does to the model it names
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum TargetAction {
+    /// Open the door on the target and keep it open
+    OpenDoor,
+    /// Turn the target's lights off, or back on
+    ToggleLights,
+    /// Move the player to the target's origin
+    Teleport,
+    /// Only works on mobile models
+    Hide,
+    Show
+}
+
+impl TargetAction {
+    pub const ALL: [TargetAction; 5] = [Self::OpenDoor, Self::ToggleLights, Self::Teleport, Self::Hide, Self::Show];
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::OpenDoor => "open_door",
+            Self::ToggleLights => "toggle_lights",
+            Self::Teleport => "teleport",
+            Self::Hide => "hide",
+            Self::Show => "show"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|action| action.name() == name)
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -38,7 +223,10 @@ pub struct Trigger {
     #[serde(skip)]
     pub player_within: bool,
     #[serde(skip)]
-    pub invalid: bool
+    pub invalid: bool,
+    /// Turned off by a `Disable` input while playing
+    #[serde(skip)]
+    pub disabled: bool
 }
 
 #[derive(Clone, Serialize, Deserialize, Debug)]
@@ -49,14 +237,37 @@ pub enum TriggerType {
         max_tween: f32
     },
     SetKernelEffect { enabled: bool, kernel: [f32; 9], offset: f32 },
+    /// Crossfade a channel to `track` over `crossfade` seconds, `None` fades it out
+    SetMusic { channel: MusicChannel, track: Option<String>, crossfade: f32 },
+    /// Complete the objective with this title, or whichever is active if `None`
+    CompleteObjective { title: Option<String> },
+    /// Do `action` to the model named `target_name` while playing, see `World::fire_target`
+    Target { target_name: String, action: TargetAction },
+    /// Start line of a timed level, starts the stopwatch, see `stopwatch`
+    StartTimer,
     Test { enter: String, update: String, exit: String }
 }
 
+impl TriggerType {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::SetFogEffect { .. } => "fog",
+            Self::SetKernelEffect { .. } => "kernel",
+            Self::SetMusic { .. } => "music",
+            Self::CompleteObjective { .. } => "objective",
+            Self::Target { .. } => "target",
+            Self::StartTimer => "start",
+            Self::Test { .. } => "test"
+        }
+    }
+}
+
 impl Trigger {
     pub fn new(kind: TriggerType) -> Self {
         Self {
             invalid: true,
             player_within: false,
+            disabled: false,
             kind
         }
     }
@@ -91,7 +302,29 @@ impl Trigger {
                             offset: *offset
                         })
                     }
-                }
+                },
+                TriggerType::SetMusic { channel, track, crossfade } => {
+                    if let Err(e) = world.music.play(*channel, track.as_deref(), *crossfade) {
+                        world.editor_data.show_debug.push(e);
+                    }
+                },
+                TriggerType::CompleteObjective { title } => match title {
+                    Some(title) => world.complete_objective(title),
+                    None => world.advance_objective()
+                },
+                TriggerType::Target { target_name, action } => {
+                    if world.do_game_logic {
+                        let detail = match world.fire_target(target_name, *action) {
+                            Ok(()) => action.name().to_string(),
+                            Err(e) => {
+                                world.editor_data.show_debug.push(e.clone());
+                                e
+                            }
+                        };
+                        world.log_event(EventKind::TargetFired, model.label(), Some(target_name.clone()), detail);
+                    }
+                },
+                TriggerType::StartTimer => world.start_stopwatch(model.label())
             }
         }
     }
@@ -171,10 +404,119 @@ pub enum Component {
     Dummy,
     /// Behavior on entry, exit<br>
     /// Trigger is expected to be placed on a model with a single brush inside
-    Trigger(Trigger)
+    Trigger(Trigger),
+    /// Animates the model's lights
+    LightAnimator(LightAnimator),
+    /// Behavior from a script file, runs while playing
+    Script(Script),
+    /// Comment for other mappers, see `notes`
+    Note(Note),
+    SpotLight(SpotLightSource),
+    /// Positional sound, see `audio`
+    SoundEmitter(SoundEmitter),
+    /// Fades the model out when far away, see `impostor`
+    DrawDistance(DrawDistance),
+    /// Something for the player to do, see `objective`
+    Objective(Objective),
+    /// Pressed by the player looking at it, see `button`
+    Button(Button),
+    /// Travels between stops while playing, see `mover`
+    Mover(Mover),
+    /// Spins the model while playing
+    Rotator(Rotator),
+    /// Hurts the player while they touch the model's colliders, negative `dps` heals
+    Hurt {
+        /// Health per second
+        dps: f32,
+        #[serde(skip)]
+        touching: bool
+    },
+    /// Ends the level when the player touches it, see `goal`
+    Goal(Goal),
+    /// Picked up and hidden when the player gets close, goals can require them
+    Collectible,
+    /// Renders what the model sees into a texture materials can show, see `feed`
+    CameraFeed(CameraFeed),
+    /// Mirrors the level for reflective materials, see `reflection`
+    ReflectionPlane(ReflectionPlane)
 }
 
 impl Component {
+    /// Names of other models this component refers to, see `wiring`
+    pub fn references(&self) -> Vec<(&str, LinkKind)> {
+        match self {
+            Component::Door(door) => door.trigger.iter().map(|name| (name.as_str(), LinkKind::OpenedBy)).collect(),
+            Component::Objective(objective) => objective.target.iter().map(|name| (name.as_str(), LinkKind::Marks))
+                .chain(objective.trigger.iter().map(|name| (name.as_str(), LinkKind::CompletedBy)))
+                .collect(),
+            Component::Trigger(Trigger { kind: TriggerType::Target { target_name, .. }, .. }) => vec![(target_name.as_str(), LinkKind::Fires)],
+            Component::Button(button) => button.target.iter().map(|name| (name.as_str(), LinkKind::Fires)).collect(),
+            _ => Vec::new()
+        }
+    }
+
+    /// Outputs this component fires, see `connection`
+    pub fn outputs(&self) -> &'static [&'static str] {
+        match self {
+            Component::Door(_) => &["OnOpened", "OnClosed"],
+            Component::Trigger(_) => &["OnStartTouch", "OnEndTouch"],
+            Component::Script(_) => &["OnUser"],
+            Component::Button(_) => &["OnPressed"],
+            Component::Mover(_) => &["OnArrived"],
+            _ => &[]
+        }
+    }
+
+    /// Inputs this component takes on top of the ones every model takes, see `connection`
+    pub fn inputs(&self) -> &'static [&'static str] {
+        match self {
+            Component::Door(_) => &["Open", "Close", "Toggle"],
+            Component::Trigger(_) => &["Enable", "Disable"],
+            Component::Objective(_) => &["Complete"],
+            Component::Button(_) => &["Press", "Lock", "Unlock"],
+            Component::Mover(_) | Component::Rotator(_) => &["Start", "Stop"],
+            _ => &[]
+        }
+    }
+
+    /// Scripts take any input and pass it to their `on_input` hook
+    pub fn takes_input(&self, input: &str) -> bool {
+        matches!(self, Component::Script(_)) || self.inputs().contains(&input)
+    }
+
+    /// Called for each input that arrived for the model, before the component updates
+    pub fn on_input(component: &mut Component, world: &mut World, input: &str) {
+        match component {
+            Component::Door(door) => {
+                let open = match input {
+                    "Open" => true,
+                    "Close" => false,
+                    "Toggle" => !door.opened,
+                    _ => return
+                };
+                door.held_open = open;
+                door.held_closed = !open;
+            },
+            Component::Trigger(trigger) => match input {
+                "Enable" => trigger.disabled = false,
+                "Disable" => trigger.disabled = true,
+                _ => ()
+            },
+            Component::Objective(objective) => if input == "Complete" {
+                world.complete_objective(&objective.title);
+            },
+            Component::Button(button) => button.on_input(input),
+            Component::Mover(mover) => mover.on_input(input),
+            Component::Rotator(rotator) => match input {
+                "Start" => rotator.stopped = false,
+                "Stop" => rotator.stopped = true,
+                _ => ()
+            },
+            // Scripts get their inputs in `Script::update`
+            _ => ()
+        }
+    }
+
     /// Called before the model is put into the scene
     pub fn on_insert(this: usize, model: &mut Model, world: &mut World) {
         match &mut model.components[this] {
@@ -184,6 +526,30 @@ impl Component {
                     world.editor_data.show_debug.push(String::from("made model mobile because it had a Door component"));
                 }
             },
+            Component::Button(_) => {
+                if !model.mobile {
+                    model.mobile = true;
+                    world.editor_data.show_debug.push(String::from("made model mobile because it had a Button component"));
+                }
+            },
+            Component::Mover(_) => {
+                if !model.mobile {
+                    model.mobile = true;
+                    world.editor_data.show_debug.push(String::from("made model mobile because it had a Mover component"));
+                }
+            },
+            Component::Rotator(_) => {
+                if !model.mobile {
+                    model.mobile = true;
+                    world.editor_data.show_debug.push(String::from("made model mobile because it had a Rotator component"));
+                }
+            },
+            Component::Collectible => {
+                if !model.mobile {
+                    model.mobile = true;
+                    world.editor_data.show_debug.push(String::from("made model mobile because it had a Collectible component"));
+                }
+            },
             Component::Trigger(trigger) => {
                 if model.render.len() != 1 {
                     world.editor_data.show_debug.push(String::from("Expected only one element"));
@@ -194,15 +560,20 @@ impl Component {
                 }
                 trigger.invalid = false;
                 trigger.player_within = false;
-            }
+            },
+            Component::Script(script) => script.load(world),
             _ => ()
         }
     }
 
     /// Called on each update loop
-    pub fn on_update(this: usize, mut model: Model, world: &mut World) -> Model {
+    pub fn on_update(this: usize, mut model: Model, world: &mut World, delta_time: f32) -> Model {
         let mut component = mem::replace(&mut model.components[this], Component::Dummy);
 
+        for input in world.arrived_inputs(model.index) {
+            Component::on_input(&mut component, world, &input);
+        }
+
         match &mut component {
             Component::Door(door) => {
                 if !door.opened {
@@ -210,18 +581,28 @@ impl Component {
                 }
                 if world.do_game_logic {
                     let origin: Vector3<f32> = door.origin.into();
-                    let dist2 = world.scene.camera.pos.distance2(Point3::from_vec(origin));
-                    if dist2 < door.radius.powf(2.0) {
+                    let open = !door.held_closed && (door.held_open || match &door.trigger {
+                        Some(name) => world.find_by_name(name).is_some_and(|trigger| world.player_in_trigger(trigger)),
+                        None => world.player_eye().distance2(origin) < door.radius.powf(2.0)
+                    });
+                    if open {
                         if door.open_progress < door.open_time {
                             door.open_progress += 1;
                         }
                         if !door.opened {
                             door.opened = true;
+                            let cause = if door.held_open { String::from("held open") } else { door.trigger.clone().map_or(String::from("player nearby"), |trigger| format!("by {}", trigger)) };
+                            world.log_event(EventKind::DoorOpened, model.label(), None, cause);
+                            world.fire_output(&mut model, "OnOpened");
                         }
                     } else {
                         if door.open_progress > 0 {
                             door.open_progress -= 1;
                         } else {
+                            if door.opened {
+                                world.log_event(EventKind::DoorClosed, model.label(), None, String::new());
+                                world.fire_output(&mut model, "OnClosed");
+                            }
                             door.opened = false;
                             let original_transform = Matrix4::from_translation(origin) * common::mat4_remove_translation(model.transform);
                             model = world.set_model_transform_external(model, original_transform);
@@ -234,6 +615,8 @@ impl Component {
                         model = world.set_model_transform_external(model, new_transform);
                     }
                 } else {
+                    door.held_open = false;
+                    door.held_closed = false;
                     if door.opened {
                         door.opened = false;
                         door.open_progress = 0;
@@ -245,10 +628,52 @@ impl Component {
             Component::Dummy => {
                 world.editor_data.show_debug.push(String::from("Dummy component found in model"));
             },
+            Component::LightAnimator(animator) => {
+                if world.do_game_logic || world.editor_data.preview_light_animation {
+                    animator.time += delta_time;
+                    let multiplier = animator.color_multiplier();
+                    for (_, light) in model.lights.iter() {
+                        world.scene.point_lights[*light].set_animated_color(multiplier);
+                    }
+                } else if animator.time != 0.0 {
+                    // Stopped previewing, put the lights back to normal
+                    animator.time = 0.0;
+                    for (_, light) in model.lights.iter() {
+                        world.scene.point_lights[*light].set_animated_color(common::vec3_all(1.0));
+                    }
+                }
+            },
+            Component::Script(script) => {
+                if world.do_game_logic {
+                    model = script.update(model, world, delta_time);
+                }
+            },
+            Component::Button(button) => {
+                model = button.update(model, world, delta_time);
+            },
+            Component::Mover(mover) => {
+                model = mover.update(model, world, delta_time);
+            },
+            Component::Rotator(rotator) => {
+                model = rotator.update(model, world, delta_time);
+            },
+            Component::Hurt { dps, touching } => {
+                let player = world.physical_scene.collider(world.player.collider);
+                let now_touching = world.do_game_logic && player.is_some_and(|player| model.colliders.iter().flatten()
+                    .filter_map(|collider| world.physical_scene.collider(*collider))
+                    .any(|collider| collider.touches(player, rules::HURT_MARGIN)));
+                if now_touching {
+                    if !*touching {
+                        world.log_event(EventKind::Damaged, model.label(), None, format!("{} per second", dps));
+                    }
+                    world.damage_player(*dps * delta_time, &model.label());
+                }
+                *touching = now_touching;
+            },
             Component::Trigger(trigger) => {
                 // this was checked on insert
                 let (mut brush_origin, mut brush_extents) = 
-                    if let Renderable::Brush(_, origin, extents, _) = model.render[0] { 
+                    if let Renderable::Brush(_, origin, extents, ..) = model.render[0] { 
                         (origin, extents) 
                     } else {
                         panic!("First (supposedly only) element in trigger model was not a brush");
@@ -259,14 +684,19 @@ impl Component {
                 let min = (brush_origin - brush_extents / 2.0);
                 let max = (brush_origin + brush_extents / 2.0);
 
-                let within_brush = {
-                    let pp = &world.scene.camera.pos;
+                if !world.do_game_logic {
+                    trigger.disabled = false;
+                }
+                let within_brush = !trigger.disabled && {
+                    let pp = world.player_eye();
                     pp.x > min.x && pp.y > min.y && pp.z > min.z && pp.x < max.x && pp.y < max.y && pp.z < max.z
                 };
 
                 if trigger.player_within {
                     if !within_brush {
                         trigger.player_within = false;
+                        world.log_event(EventKind::TriggerExited, model.label(), None, trigger.kind.name().to_string());
+                        world.fire_output(&mut model, "OnEndTouch");
                         Trigger::on_exit(&mut component, &mut model, world);
                     } else {
                         Trigger::update_inside(&mut component, &mut model, world);
@@ -274,6 +704,8 @@ impl Component {
                 } else {
                     if within_brush {
                         trigger.player_within = true;
+                        world.log_event(EventKind::TriggerEntered, model.label(), None, trigger.kind.name().to_string());
+                        world.fire_output(&mut model, "OnStartTouch");
                         Trigger::on_enter(&mut component, &mut model, world);
                     } else {
                         Trigger::update_outside(&mut component, &mut model, world);
@@ -0,0 +1,165 @@
+use std::{collections::BTreeMap, fs, io::{Read, Write}};
+
+use cgmath::{vec3, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{audio::VolumeSettings, input::KeyBindings, render::{self, PointLight}, ui::{self, UI}, world::{self, World}};
+
+const USER_CONFIG_PATH: &str = "res/data/user_config.json";
+const DEFAULT_UNDO_DEPTH: u32 = 50;
+
+/// A reusable light setup shown in the light palette
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct LightPreset {
+    pub name: String,
+    pub color: [f32; 3],
+    pub radius: f32
+}
+
+impl LightPreset {
+    pub fn from_light(name: &str, light: &PointLight) -> Self {
+        Self {
+            name: name.to_string(),
+            color: light.user_color_or_default().into(),
+            radius: light.user_attenuation_or_default()
+        }
+    }
+
+    /// Create a configured point light at `position`
+    pub fn light(&self, position: Vector3<f32>) -> PointLight {
+        let mut light = PointLight::default(position);
+        light.set_color(self.color.into());
+        light.set_attenuation(self.radius);
+        light
+    }
+}
+
+fn default_light_presets() -> Vec<LightPreset> {
+    vec![
+        LightPreset { name: String::from("warm lamp"), color: vec3(1.0, 0.8, 0.55).into(), radius: 10.0 },
+        LightPreset { name: String::from("cold fluorescent"), color: vec3(0.8, 0.9, 1.0).into(), radius: 16.0 },
+        LightPreset { name: String::from("alarm red"), color: vec3(1.0, 0.1, 0.05).into(), radius: 8.0 }
+    ]
+}
+
+/// Editor options that don't belong to any level
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct EditorPreferences {
+    pub ui_scale: f32,
+    pub camera_speed: f32,
+    pub camera_sensitivity: f32,
+    /// Snapping increment the editor starts with
+    pub default_increment: f32,
+    /// Name of a theme in `res/themes/`
+    pub theme: String,
+    /// Save JSON levels indented, one value per line, so version control diffs stay readable
+    pub pretty_levels: bool,
+    /// Minutes between autosaves while editing, 0 turns them off
+    pub autosave_minutes: u32,
+    /// The editor camera sees at least this far, levels with a shorter far plane are still previewed with theirs in play mode
+    pub view_distance: f32,
+    /// Show the inspector, materials, notes and light palette in a second OS window, for another monitor
+    pub panel_window: bool,
+    /// Front, right and top view presets end in the orthographic view looking the same way
+    pub ortho_presets: bool,
+    /// Merge touching brushes of the same type when saving, see `World::merge_all_brushes`
+    pub merge_on_save: bool,
+    /// Multisampling of the 3D view, one of `effects::MSAA_SAMPLE_COUNTS`, GPUs without it get FXAA instead
+    pub msaa_samples: u32,
+    /// Grid lines in orthographic views and on the ground
+    pub grid_color: [f32; 3],
+    /// Every 8th grid line
+    pub grid_major_color: [f32; 3],
+    /// Edits that can be undone, 0 turns undo off
+    pub undo_depth: u32
+}
+
+impl Default for EditorPreferences {
+    fn default() -> Self {
+        Self {
+            ui_scale: 1.0,
+            camera_speed: render::DEFAULT_CAMERA_SPEED,
+            camera_sensitivity: render::DEFAULT_CAMERA_SENSITIVITY,
+            default_increment: world::DEFAULT_INCREMENT,
+            theme: ui::DEFAULT_THEME.to_string(),
+            pretty_levels: false,
+            autosave_minutes: 5,
+            view_distance: 0.0,
+            panel_window: false,
+            ortho_presets: false,
+            merge_on_save: false,
+            msaa_samples: 0,
+            grid_color: render::DEFAULT_GRID_COLOR,
+            grid_major_color: render::DEFAULT_GRID_MAJOR_COLOR,
+            undo_depth: DEFAULT_UNDO_DEPTH
+        }
+    }
+}
+
+impl EditorPreferences {
+    /// Apply the options that live on the camera, UI and editor, call again after a level is loaded
+    pub fn apply(&self, world: &mut World, ui: &mut UI) {
+        world.scene.camera.speed = self.camera_speed;
+        world.scene.camera.sensitivity = self.camera_sensitivity;
+        world.scene.camera.ortho_presets = self.ortho_presets;
+        ui.set_scale(self.ui_scale);
+        world.editor_data.pretty_levels = self.pretty_levels;
+        world.editor_data.autosave_interval = self.autosave_minutes as f32 * 60.0;
+        world.editor_data.view_distance = self.view_distance;
+        world.editor_data.merge_on_save = self.merge_on_save;
+        world.scene.post_process.samples = self.msaa_samples;
+        world.editor_data.grid_color = self.grid_color.into();
+        world.editor_data.grid_major_color = self.grid_major_color.into();
+        world.editor_data.undo_depth = self.undo_depth as usize;
+    }
+}
+
+/// Settings that belong to the user rather than to a level
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct UserConfig {
+    #[serde(default="default_light_presets")]
+    pub light_presets: Vec<LightPreset>,
+    #[serde(default)]
+    pub volume: VolumeSettings,
+    #[serde(default)]
+    pub preferences: EditorPreferences,
+    /// Applied to the input on startup, edit the config file to rebind keys
+    #[serde(default)]
+    pub key_bindings: KeyBindings,
+    /// Best stopwatch times in timed levels by level path, see `stopwatch`
+    #[serde(default)]
+    pub best_times: BTreeMap<String, f32>
+}
+
+impl Default for UserConfig {
+    fn default() -> Self {
+        Self {
+            light_presets: default_light_presets(),
+            volume: VolumeSettings::default(),
+            preferences: EditorPreferences::default(),
+            key_bindings: KeyBindings::default(),
+            best_times: BTreeMap::new()
+        }
+    }
+}
+
+impl UserConfig {
+    /// Load the user config, falling back to defaults if it is missing or invalid
+    pub fn load() -> Self {
+        let mut src = String::new();
+        match fs::File::open(USER_CONFIG_PATH).and_then(|mut file| file.read_to_string(&mut src)) {
+            Ok(_) => serde_json::from_str(&src).unwrap_or_else(|e| {
+                eprintln!("Failed to parse user config: {}", e);
+                Self::default()
+            }),
+            Err(_) => Self::default()
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        let mut file = fs::File::create(USER_CONFIG_PATH).map_err(|e| format!("failed to save user config: {}", e))?;
+        file.write_all(json.as_bytes()).map_err(|e| format!("failed to save user config: {}", e))
+    }
+}
@@ -0,0 +1,149 @@
+use std::mem;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{component::{Component, TargetAction}, events::EventKind, world::{Model, World}};
+
+/// Inputs every model takes whatever its components are, with the action they do, see `World::fire_at`
+const MODEL_INPUTS: [(&str, TargetAction); 4] = [
+    ("Hide", TargetAction::Hide),
+    ("Show", TargetAction::Show),
+    ("ToggleLights", TargetAction::ToggleLights),
+    ("Teleport", TargetAction::Teleport)
+];
+
+/// When `output` fires on the model, send `input` to the model named `target`<br>
+/// Components list what they fire and take in `Component::outputs` and `Component::inputs`
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Connection {
+    pub output: String,
+    /// Name of the model that gets the input
+    pub target: String,
+    pub input: String,
+    /// Seconds before the input arrives
+    #[serde(default)]
+    pub delay: f32,
+    /// Only send the input the first time the output fires while playing
+    #[serde(default)]
+    pub once: bool,
+    #[serde(skip)]
+    pub fired: bool
+}
+
+impl Connection {
+    pub fn new(output: &str) -> Self {
+        Self { output: output.to_string(), target: String::new(), input: String::from("Show"), delay: 0.0, once: false, fired: false }
+    }
+}
+
+/// Input on its way to a model
+struct PendingInput {
+    target: String,
+    input: String,
+    /// Seconds until it arrives
+    delay: f32,
+    /// Label of the model that sent it
+    source: String
+}
+
+/// Inputs sent while playing, see `World::fire_output`
+#[derive(Default)]
+pub struct ConnectionQueue {
+    pending: Vec<PendingInput>,
+    /// Model and input, picked up by the model's components during `Component::on_update`
+    arrived: Vec<(usize, String)>,
+    playing: bool
+}
+
+impl World {
+    /// Send the inputs connected to `output` on `model`, only while playing<br>
+    /// Takes the model since components fire outputs while their model is out of the world
+    pub fn fire_output(&mut self, model: &mut Model, output: &str) {
+        if !self.do_game_logic {
+            return;
+        }
+        let source = model.label();
+        for connection in model.connections.iter_mut().filter(|connection| connection.output == output) {
+            if connection.once && connection.fired {
+                continue;
+            }
+            connection.fired = true;
+            self.connections.pending.push(PendingInput {
+                target: connection.target.clone(), input: connection.input.clone(), delay: connection.delay, source: source.clone()
+            });
+        }
+        self.log_event(EventKind::OutputFired, source, None, output.to_string());
+    }
+
+    /// Deliver the inputs that are due, call before the components update<br>
+    /// Inputs every model takes are done here, the rest wait in the queue for the target's components
+    pub fn update_connections(&mut self, delta_time: f32) {
+        self.connections.arrived.clear();
+        if !self.do_game_logic {
+            if self.connections.playing {
+                self.connections.playing = false;
+                self.connections.pending.clear();
+                for model in self.models.iter_mut().flatten() {
+                    model.connections.iter_mut().for_each(|connection| connection.fired = false);
+                }
+            }
+            return;
+        }
+        self.connections.playing = true;
+
+        let (due, pending): (Vec<PendingInput>, Vec<PendingInput>) = mem::take(&mut self.connections.pending).into_iter()
+            .map(|mut input| {
+                input.delay -= delta_time;
+                input
+            })
+            .partition(|input| input.delay <= 0.0);
+        self.connections.pending = pending;
+
+        for input in due {
+            let result = match self.find_by_name(&input.target) {
+                None => Err(format!("{} sent {} to {}, but no model has that name", input.source, input.input, input.target)),
+                Some(target) => match MODEL_INPUTS.iter().find(|(name, _)| *name == input.input) {
+                    Some((_, action)) => self.fire_at(target, *action),
+                    None if self.takes_input(target, &input.input) => {
+                        self.connections.arrived.push((target, input.input.clone()));
+                        Ok(())
+                    },
+                    None => Err(format!("{} doesn't take {}", input.target, input.input))
+                }
+            };
+            let detail = match result {
+                Ok(()) => input.input,
+                Err(e) => {
+                    self.editor_data.show_debug.push(e.clone());
+                    e
+                }
+            };
+            self.log_event(EventKind::InputReceived, input.source, Some(input.target), detail);
+        }
+    }
+
+    /// Inputs for `model` that arrived this update
+    pub fn arrived_inputs(&self, model: Option<usize>) -> Vec<String> {
+        let Some(model) = model else { return Vec::new(); };
+        self.connections.arrived.iter().filter(|(target, _)| *target == model).map(|(_, input)| input.clone()).collect()
+    }
+
+    /// Outputs the components on `model` fire
+    pub fn model_outputs(&self, model: usize) -> Vec<&'static str> {
+        let Some(Some(model)) = self.models.get(model) else { return Vec::new(); };
+        model.components.iter().flat_map(|component| component.outputs().iter().copied()).collect()
+    }
+
+    /// Inputs `model` takes, starting with the ones every model takes
+    pub fn model_inputs(&self, model: usize) -> Vec<&'static str> {
+        let Some(Some(model)) = self.models.get(model) else { return Vec::new(); };
+        MODEL_INPUTS.iter().map(|(name, _)| *name)
+            .chain(model.components.iter().flat_map(|component| component.inputs().iter().copied()))
+            .collect()
+    }
+
+    fn takes_input(&self, model: usize, input: &str) -> bool {
+        let Some(Some(model)) = self.models.get(model) else { return false; };
+        model.components.iter().any(|component| component.takes_input(input))
+    }
+}
@@ -0,0 +1,197 @@
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+use crate::save::LevelData;
+
+/// Numbers closer than this are treated as equal
+const EPSILON: f64 = 0.0001;
+
+/// Read a level file, going through `LevelData` so missing fields get their defaults
+pub fn load_level_value(path: &Path) -> Result<Value, String> {
+    LevelData::read_from_file(path).and_then(|level| level.to_stable_value()).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+pub fn diff_level_files(old: &Path, new: &Path) -> Result<Vec<String>, String> {
+    Ok(diff_levels(&load_level_value(old)?, &load_level_value(new)?))
+}
+
+/// Readable report of what changed between two serialized levels, one change per line<br>
+/// `+` added, `-` removed, `~` changed
+pub fn diff_levels(old: &Value, new: &Value) -> Vec<String> {
+    let mut report = Vec::new();
+
+    diff_list(&mut report, list(old, "models"), list(new, "models"), &[model_contents], describe_model);
+    diff_list(&mut report, list(old, "brushes"), list(new, "brushes"), &[brush_shape, brush_origin], describe_brush);
+    diff_list(&mut report, list(old, "materials"), list(new, "materials"), &[material_name], describe_material);
+
+    let empty = Map::new();
+    let old_fields = old.as_object().unwrap_or(&empty);
+    let new_fields = new.as_object().unwrap_or(&empty);
+    let other = |key: &&String| !matches!(key.as_str(), "models" | "brushes" | "materials");
+    for key in old_fields.keys().chain(new_fields.keys().filter(|key| !old_fields.contains_key(*key))).filter(other) {
+        diff_value(&mut report, key, old_fields.get(key).unwrap_or(&Value::Null), new_fields.get(key).unwrap_or(&Value::Null));
+    }
+
+    report
+}
+
+/// A model that was moved or had its components changed still looks the same
+fn model_contents(model: &Value) -> Option<Value> {
+    model.get("renderables").cloned()
+}
+
+/// Matches brushes that were moved
+fn brush_shape(brush: &Value) -> Option<Value> {
+    Some(Value::Array(vec![brush.get("material")?.clone(), brush.get("extents")?.clone()]))
+}
+
+/// Matches brushes that were resized or repainted in place
+fn brush_origin(brush: &Value) -> Option<Value> {
+    brush.get("origin").cloned()
+}
+
+fn material_name(material: &Value) -> Option<Value> {
+    material.get("name").cloned()
+}
+
+fn list<'a>(level: &'a Value, key: &str) -> &'a [Value] {
+    level.get(key).and_then(Value::as_array).map(Vec::as_slice).unwrap_or(&[])
+}
+
+/// Pair up items that are equal first, then items with the same `keys` in order, the rest were added or removed
+fn diff_list(report: &mut Vec<String>, old: &[Value], new: &[Value], keys: &[fn(&Value) -> Option<Value>], describe: fn(&Value) -> String) {
+    let mut old_matched = vec![false; old.len()];
+    let mut new_matched = vec![false; new.len()];
+    let mut pairs = Vec::new();
+
+    let mut pair_by = |key: &dyn Fn(&Value) -> Option<Value>, pairs: &mut Vec<(usize, usize)>| {
+        for (i, old_item) in old.iter().enumerate() {
+            if old_matched[i] {
+                continue;
+            }
+            let Some(old_key) = key(old_item) else { continue; };
+            let found = (0..new.len()).find(|&j| !new_matched[j] && key(&new[j]).is_some_and(|new_key| values_close(&old_key, &new_key)));
+            if let Some(j) = found {
+                old_matched[i] = true;
+                new_matched[j] = true;
+                pairs.push((i, j));
+            }
+        }
+    };
+
+    pair_by(&|item: &Value| Some(item.clone()), &mut pairs);
+    for key in keys {
+        pair_by(key, &mut pairs);
+    }
+
+    for (i, item) in old.iter().enumerate().filter(|(i, _)| !old_matched[*i]) {
+        report.push(format!("- {} (was #{})", describe(item), i));
+    }
+    for (j, item) in new.iter().enumerate().filter(|(j, _)| !new_matched[*j]) {
+        report.push(format!("+ {} (#{})", describe(item), j));
+    }
+
+    pairs.sort();
+    for (i, j) in pairs {
+        diff_value(report, &describe(&old[i]), &old[i], &new[j]);
+    }
+}
+
+fn diff_value(report: &mut Vec<String>, path: &str, old: &Value, new: &Value) {
+    match (old, new) {
+        (Value::Object(old_fields), Value::Object(new_fields)) => {
+            for (key, old_value) in old_fields.iter() {
+                match new_fields.get(key) {
+                    Some(new_value) => diff_value(report, &format!("{}.{}", path, key), old_value, new_value),
+                    None => report.push(format!("~ {}.{} removed", path, key))
+                }
+            }
+            for (key, new_value) in new_fields.iter().filter(|(key, _)| !old_fields.contains_key(*key)) {
+                report.push(format!("~ {}.{} added: {}", path, key, format_value(new_value)));
+            }
+        },
+        (Value::Array(old_rows), Value::Array(new_rows)) if is_matrix(old_rows) && is_matrix(new_rows) => {
+            diff_transform(report, path, old_rows, new_rows);
+        },
+        (Value::Array(old_items), Value::Array(new_items)) if old_items.len() == new_items.len() && !is_number_list(old_items) => {
+            for (i, (old_item, new_item)) in old_items.iter().zip(new_items.iter()).enumerate() {
+                diff_value(report, &format!("{}[{}]", path, i), old_item, new_item);
+            }
+        },
+        _ => {
+            if !values_close(old, new) {
+                report.push(format!("~ {}: {} -> {}", path, format_value(old), format_value(new)));
+            }
+        }
+    }
+}
+
+/// Matrices are reported as a move and/or a rotation or scale instead of 16 numbers
+fn diff_transform(report: &mut Vec<String>, path: &str, old: &[Value], new: &[Value]) {
+    let old_translation = &old[3].as_array().unwrap()[..3];
+    let new_translation = &new[3].as_array().unwrap()[..3];
+    if !values_close(&Value::from(old_translation), &Value::from(new_translation)) {
+        report.push(format!("~ {} moved {} -> {}", path, format_numbers(old_translation), format_numbers(new_translation)));
+    }
+    if !values_close(&Value::from(&old[..3]), &Value::from(&new[..3])) {
+        report.push(format!("~ {} rotated or scaled", path));
+    }
+}
+
+fn is_number_list(items: &[Value]) -> bool {
+    !items.is_empty() && items.iter().all(Value::is_number)
+}
+
+fn is_matrix(rows: &[Value]) -> bool {
+    rows.len() == 4 && rows.iter().all(|row| row.as_array().is_some_and(|row| row.len() == 4 && is_number_list(row)))
+}
+
+/// Equality that ignores float noise
+fn values_close(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => (a.as_f64().unwrap_or(0.0) - b.as_f64().unwrap_or(0.0)).abs() < EPSILON,
+        (Value::Array(a), Value::Array(b)) => a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| values_close(a, b)),
+        (Value::Object(a), Value::Object(b)) => a.len() == b.len() && a.iter().all(|(key, a)| b.get(key).is_some_and(|b| values_close(a, b))),
+        _ => a == b
+    }
+}
+
+fn format_numbers(numbers: &[Value]) -> String {
+    let numbers: Vec<String> = numbers.iter().map(format_value).collect();
+    format!("({})", numbers.join(", "))
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        // Levels store f32s, printing them as f32 avoids 0.10000000149011612
+        Value::Number(n) => format!("{}", n.as_f64().unwrap_or(0.0) as f32),
+        Value::String(s) => s.clone(),
+        Value::Array(items) if is_number_list(items) => format_numbers(items),
+        Value::Null => String::from("none"),
+        _ => value.to_string()
+    }
+}
+
+/// Enums are stored as `{ "Variant": [fields] }`
+fn variant(value: &Value) -> Option<(&str, &Value)> {
+    value.as_object().and_then(|fields| fields.iter().next()).map(|(name, inner)| (name.as_str(), inner))
+}
+
+fn describe_model(model: &Value) -> String {
+    let kind = model.get("renderables")
+        .and_then(|renderables| renderables.get(0))
+        .and_then(variant)
+        .map(|(name, fields)| format!("{} {}", name.to_lowercase(), fields.get(0).map(format_value).unwrap_or_default()))
+        .unwrap_or(String::from("empty"));
+    let position = model.get("transform").and_then(|transform| transform.get(3)).and_then(Value::as_array).map(|column| format_numbers(&column[..3])).unwrap_or_default();
+    format!("model {} at {}", kind, position)
+}
+
+fn describe_brush(brush: &Value) -> String {
+    format!("brush {} at {}", brush.get("material").map(format_value).unwrap_or_default(), brush.get("origin").map(format_value).unwrap_or_default())
+}
+
+fn describe_material(material: &Value) -> String {
+    format!("material {}", material.get("name").map(format_value).unwrap_or_default())
+}
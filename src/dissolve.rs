@@ -0,0 +1,161 @@
+use std::f32;
+
+use cgmath::{vec3, Vector3};
+
+use crate::{flipbook::Flipbook, mesh::flags, render::BillboardRenderData, world::World};
+
+/// Seconds a dissolve takes when spawning through `Spawner`
+pub const DEFAULT_DISSOLVE_TIME: f32 = 0.6;
+/// Burst particles are squares of this texture
+pub const PARTICLE_TEXTURE: &str = "magic_pixel";
+const BURST_PARTICLES: usize = 24;
+const PARTICLE_SPEED: f32 = 3.0;
+/// Seconds until a particle is gone
+const PARTICLE_LIFETIME: f32 = 0.8;
+const PARTICLE_SIZE: f32 = 0.08;
+const PARTICLE_GRAVITY: f32 = 6.0;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum DissolveDirection {
+    /// From nothing to fully drawn
+    In,
+    /// From fully drawn to nothing, then the model is despawned
+    Out
+}
+
+struct Dissolve {
+    model: usize,
+    direction: DissolveDirection,
+    elapsed: f32,
+    duration: f32
+}
+
+struct Particle {
+    position: Vector3<f32>,
+    velocity: Vector3<f32>,
+    age: f32
+}
+
+/// Models dissolving in or out while playing, and the particles of their bursts<br>
+/// Only mobile models dissolve, static ones are drawn instanced and just pop in and out
+#[derive(Default)]
+pub struct Dissolves {
+    active: Vec<Dissolve>,
+    particles: Vec<Particle>
+}
+
+impl World {
+    /// Dissolve a model in over `duration` seconds, with a burst of particles if `burst`<br>
+    /// Does nothing while editing
+    pub fn dissolve_in(&mut self, model: usize, duration: f32, burst: bool) {
+        self.start_dissolve(model, DissolveDirection::In, duration, burst);
+    }
+
+    /// Dissolve a model out over `duration` seconds and despawn it, with a burst of particles if `burst`<br>
+    /// Models are despawned right away while editing
+    pub fn dissolve_out(&mut self, model: usize, duration: f32, burst: bool) {
+        if !self.do_game_logic {
+            self.despawn_at_end_of_frame(model);
+            return;
+        }
+        self.start_dissolve(model, DissolveDirection::Out, duration, burst);
+    }
+
+    pub fn is_dissolving(&self, model: usize) -> bool {
+        self.dissolves.active.iter().any(|dissolve| dissolve.model == model)
+    }
+
+    fn start_dissolve(&mut self, model: usize, direction: DissolveDirection, duration: f32, burst: bool) {
+        if !self.do_game_logic || !self.models.get(model).is_some_and(Option::is_some) {
+            return;
+        }
+        self.dissolves.active.retain(|dissolve| dissolve.model != model);
+        self.dissolves.active.push(Dissolve { model, direction, elapsed: 0.0, duration: duration.max(0.01) });
+        if direction == DissolveDirection::In {
+            self.set_dissolve(model, 1.0);
+        }
+        if burst {
+            self.burst(self.model_center(model));
+        }
+    }
+
+    /// Particles flying out of `center` in every direction
+    fn burst(&mut self, center: Vector3<f32>) {
+        for i in 0..BURST_PARTICLES {
+            // Spread evenly over a sphere, with the speed varied by the golden ratio so it doesn't look like a shell
+            let y = 1.0 - 2.0 * (i as f32 + 0.5) / BURST_PARTICLES as f32;
+            let radius = (1.0 - y * y).sqrt();
+            let angle = i as f32 * f32::consts::PI * (3.0 - 5.0f32.sqrt());
+            let speed = PARTICLE_SPEED * (0.5 + (i as f32 * 0.618).fract());
+            let velocity = vec3(angle.cos() * radius, y.abs(), angle.sin() * radius) * speed;
+            self.dissolves.particles.push(Particle { position: center, velocity, age: 0.0 });
+        }
+    }
+
+    /// Amount each mesh of `model` is dissolved, 0 is fully drawn
+    fn set_dissolve(&mut self, index: usize, amount: f32) {
+        let Some(Some(model)) = self.models.get(index) else { return; };
+        if !model.mobile {
+            return;
+        }
+        let meshes = if model.foreground { &mut self.scene.foreground_meshes } else { &mut self.scene.mobile_meshes };
+        for (renderable, data_index) in model.render.iter().zip(model.renderable_indices.iter()) {
+            let Some(mesh) = renderable.get_mesh() else { continue; };
+            if let Some(data) = meshes.get_mut(&mesh).and_then(|data| data.get_mut(*data_index)) {
+                data.dissolve = amount;
+            }
+        }
+    }
+
+    /// Advance dissolves and particles, call after the components update so no model is taken out<br>
+    /// Leaving play mode puts every dissolving model back to normal
+    pub fn update_dissolves(&mut self, delta_time: f32) {
+        if !self.do_game_logic {
+            if !self.dissolves.active.is_empty() || !self.dissolves.particles.is_empty() {
+                for dissolve in std::mem::take(&mut self.dissolves.active) {
+                    self.set_dissolve(dissolve.model, 0.0);
+                }
+                self.dissolves.particles.clear();
+                self.scene.particles.clear();
+            }
+            return;
+        }
+
+        let mut active = std::mem::take(&mut self.dissolves.active);
+        active.retain(|dissolve| self.models.get(dissolve.model).is_some_and(Option::is_some));
+        for dissolve in active.iter_mut() {
+            dissolve.elapsed += delta_time;
+            let progress = (dissolve.elapsed / dissolve.duration).min(1.0);
+            let amount = match dissolve.direction {
+                DissolveDirection::In => 1.0 - progress,
+                DissolveDirection::Out => progress
+            };
+            self.set_dissolve(dissolve.model, amount);
+        }
+
+        let (finished, active): (Vec<Dissolve>, Vec<Dissolve>) = active.into_iter().partition(|dissolve| dissolve.elapsed >= dissolve.duration);
+        self.dissolves.active.extend(active);
+        for dissolve in finished {
+            if dissolve.direction == DissolveDirection::Out {
+                // Pooled models come back later, so they can't stay dissolved
+                self.set_dissolve(dissolve.model, 0.0);
+                self.despawn(dissolve.model);
+            }
+        }
+
+        for particle in self.dissolves.particles.iter_mut() {
+            particle.velocity.y -= PARTICLE_GRAVITY * delta_time;
+            particle.position += particle.velocity * delta_time;
+            particle.age += delta_time;
+        }
+        self.dissolves.particles.retain(|particle| particle.age < PARTICLE_LIFETIME);
+        self.scene.particles = self.dissolves.particles.iter().map(|particle| {
+            let life = 1.0 - particle.age / PARTICLE_LIFETIME;
+            BillboardRenderData {
+                flags: flags::FULLBRIGHT, position: particle.position, draw: true, follow_vertical: true,
+                size: (PARTICLE_SIZE * life, PARTICLE_SIZE * life), show_hidden: false, fixed_rotation: None, fade: life,
+                flipbook: Flipbook::default(), frame: 0
+            }
+        }).collect();
+    }
+}
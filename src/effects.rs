@@ -1,14 +1,34 @@
-use cgmath::{vec3, Vector3};
-use glow::{HasContext, NativeFramebuffer, NativeTexture, NativeVertexArray};
+use cgmath::{vec2, vec3, Matrix4, SquareMatrix, Vector3};
+use glow::{HasContext, NativeFramebuffer, NativeRenderbuffer, NativeTexture, NativeVertexArray};
+use serde::{Deserialize, Serialize};
 
-use crate::shader::{Program, ProgramBank};
+use crate::{post::{PostEffect, PostEffectSettings}, render, shader::{Program, ProgramBank}};
 
-#[derive(Clone)]
+/// Texture unit of the blurred bright parts in the `screen` program, after the color and depth
+const BLOOM_TEXTURE_UNIT: u32 = 2;
+const OCCLUSION_TEXTURE_UNIT: u32 = 3;
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct KernelEffect {
     pub kernel: [f32; 9],
     pub offset: f32
 }
 
+/// Kernels offered in the effects window
+pub const KERNEL_PRESETS: [(&str, [f32; 9]); 4] = [
+    ("sharpen", [0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0]),
+    ("blur", [1.0 / 16.0, 2.0 / 16.0, 1.0 / 16.0, 2.0 / 16.0, 4.0 / 16.0, 2.0 / 16.0, 1.0 / 16.0, 2.0 / 16.0, 1.0 / 16.0]),
+    ("edges", [1.0, 1.0, 1.0, 1.0, -8.0, 1.0, 1.0, 1.0, 1.0]),
+    ("emboss", [-2.0, -1.0, 0.0, -1.0, 1.0, 1.0, 0.0, 1.0, 2.0])
+];
+
+impl KernelEffect {
+    /// Name of the preset this kernel is, None if it was set some other way
+    pub fn preset(&self) -> Option<&'static str> {
+        KERNEL_PRESETS.iter().find(|(_, kernel)| *kernel == self.kernel).map(|(name, _)| *name)
+    }
+}
+
 #[derive(Clone)]
 pub struct FogEffect {
     pub color: Vector3<f32>,
@@ -16,6 +36,67 @@ pub struct FogEffect {
     pub max: f32
 }
 
+/// Screen space ambient occlusion, darkens creases and where things touch
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct SsaoEffect {
+    /// How far around each point is looked at for occluders
+    pub radius: f32,
+    /// Keeps flat surfaces from shadowing themselves
+    pub bias: f32,
+    /// Occlusion is raised to this power
+    pub strength: f32
+}
+
+impl Default for SsaoEffect {
+    fn default() -> Self {
+        Self {
+            radius: 0.5,
+            bias: 0.025,
+            strength: 1.5
+        }
+    }
+}
+
+/// How the HDR frame is brought down to the screen, set per level in the environment window
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct HdrEffect {
+    /// Colors are scaled by this before tone mapping
+    pub exposure: f32,
+    /// Off clips colors over 1 the way levels looked before HDR
+    pub tone_mapping: bool,
+    pub bloom: bool,
+    /// Brightness past which pixels bloom
+    pub bloom_threshold: f32,
+    pub bloom_intensity: f32,
+    /// Each pass blurs across and then down again, more passes spread the glow further
+    pub bloom_passes: u32
+}
+
+impl Default for HdrEffect {
+    fn default() -> Self {
+        Self {
+            exposure: 1.0,
+            tone_mapping: false,
+            bloom: false,
+            bloom_threshold: 1.0,
+            bloom_intensity: 0.6,
+            bloom_passes: 4
+        }
+    }
+}
+
+/// Multisampled framebuffer the scene is drawn to when MSAA is on, resolved into the post processing textures
+struct MultisampleTarget {
+    fbo: NativeFramebuffer,
+    color: NativeRenderbuffer,
+    depth: NativeRenderbuffer
+}
+
+/// Sample counts offered in the preferences, 0 is off
+pub const MSAA_SAMPLE_COUNTS: [u32; 4] = [0, 2, 4, 8];
+
 pub struct PostProcessing {
     pub fbo: NativeFramebuffer,
     pub texture_color: Option<NativeTexture>,
@@ -23,19 +104,50 @@ pub struct PostProcessing {
     pub error: Vec<String>,
     pub dummy_vao: NativeVertexArray,
     pub kernel: Option<KernelEffect>,
-    pub fog: Option<FogEffect>
+    pub fog: Option<FogEffect>,
+    pub ssao: Option<SsaoEffect>,
+    /// Projection of the camera, ambient occlusion finds positions from depth with it
+    pub projection: Matrix4<f32>,
+    /// Copied from the level's environment every frame
+    pub hdr: HdrEffect,
+    /// Near and far plane of the camera, fog needs them to find distances from depth
+    pub clip_planes: (f32, f32),
+    /// User effects from `res/shaders/post`, drawn in order after fog and the kernel
+    pub chain: Vec<PostEffect>,
+    /// Two color targets the chain draws back and forth between, made in `resize`
+    chain_targets: Vec<(NativeFramebuffer, NativeTexture)>,
+    /// Two half size HDR targets the bloom blur draws back and forth between, made in `resize`
+    bloom_targets: Vec<(NativeFramebuffer, NativeTexture)>,
+    /// Raw and blurred ambient occlusion, made in `resize`
+    occlusion_targets: Vec<(NativeFramebuffer, NativeTexture)>,
+    /// MSAA samples asked for in the preferences, the target is remade in `begin` when this changes
+    pub samples: u32,
+    /// Samples `msaa` was made with
+    built_samples: u32,
+    msaa: Option<MultisampleTarget>,
+    /// `GL_MAX_SAMPLES`, more samples than this fall back to FXAA
+    max_samples: u32,
+    window_size: (u32, u32),
+    /// Seconds since the level loaded, the `time` uniform of chain effects
+    pub time: f32
 }
 
+/// Effects the level starts with, triggers that change them go back to these, edited in the effects window
 pub struct DefaultEffects {
     pub kernel: Option<KernelEffect>,
-    pub fog: Option<FogEffect>
+    pub fog: Option<FogEffect>,
+    pub ssao: Option<SsaoEffect>,
+    /// Order and settings of the chain effects the level was saved with, applied with `post::apply_chain_settings`
+    pub chain: Vec<PostEffectSettings>
 }
 
 impl DefaultEffects {
     pub fn new() -> Self {
         Self {
             kernel: None,
-            fog: None
+            fog: None,
+            ssao: None,
+            chain: Vec::new()
         }
     }
 }
@@ -52,19 +164,34 @@ impl PostProcessing {
             error: Vec::new(),
             dummy_vao: vao,
             fog: None,
-            kernel: None
+            kernel: None,
+            ssao: None,
+            projection: Matrix4::identity(),
+            hdr: HdrEffect::default(),
+            clip_planes: (render::DEFAULT_NEAR_PLANE, render::DEFAULT_FAR_PLANE),
+            chain: Vec::new(),
+            chain_targets: Vec::new(),
+            bloom_targets: Vec::new(),
+            occlusion_targets: Vec::new(),
+            samples: 0,
+            built_samples: 0,
+            msaa: None,
+            max_samples: gl.get_parameter_i32(glow::MAX_SAMPLES).max(0) as u32,
+            window_size: (0, 0),
+            time: 0.0
         }
     }
 
     pub unsafe fn resize(&mut self, window_size: (u32, u32), gl: &glow::Context) {
         gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.fbo));
 
+        // Floating point so lights can go past 1, brought back down by tone mapping in `end_to`
         let color_attachment = gl.create_texture().unwrap();
         gl.bind_texture(glow::TEXTURE_2D, Some(color_attachment));
         gl.tex_image_2d(
-            glow::TEXTURE_2D, 0, glow::RGB as i32, 
+            glow::TEXTURE_2D, 0, glow::RGBA16F as i32, 
             window_size.0 as i32, window_size.1 as i32, 
-            0, glow::RGB, glow::UNSIGNED_BYTE,
+            0, glow::RGBA, glow::FLOAT,
             glow::PixelUnpackData::Slice(None)
         );
         gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::LINEAR as i32);
@@ -101,14 +228,107 @@ impl PostProcessing {
         }
         gl.bind_framebuffer(glow::FRAMEBUFFER, None);
         self.texture_depth = Some(depth_attachment);
+
+        for (fbo, texture) in self.chain_targets.drain(..).chain(self.bloom_targets.drain(..)).chain(self.occlusion_targets.drain(..)) {
+            gl.delete_framebuffer(fbo);
+            gl.delete_texture(texture);
+        }
+        let bloom_size = ((window_size.0 / 2).max(1), (window_size.1 / 2).max(1));
+        for _ in 0..2 {
+            self.chain_targets.push(Self::create_target(window_size, glow::RGB, glow::RGB, glow::UNSIGNED_BYTE, gl));
+            self.bloom_targets.push(Self::create_target(bloom_size, glow::RGBA16F, glow::RGBA, glow::FLOAT, gl));
+            self.occlusion_targets.push(Self::create_target(window_size, glow::R8, glow::RED, glow::UNSIGNED_BYTE, gl));
+        }
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        self.window_size = window_size;
+        self.rebuild_msaa(gl);
     }
 
-    pub unsafe fn begin(&self, gl: &glow::Context) {
-        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.fbo));
+    /// Remake the multisampled target for `samples` at the current size, left out if MSAA is off or unsupported
+    unsafe fn rebuild_msaa(&mut self, gl: &glow::Context) {
+        if let Some(msaa) = self.msaa.take() {
+            gl.delete_framebuffer(msaa.fbo);
+            gl.delete_renderbuffer(msaa.color);
+            gl.delete_renderbuffer(msaa.depth);
+        }
+        self.built_samples = self.samples;
+        if self.samples < 2 || self.samples > self.max_samples || self.window_size.0 == 0 {
+            return;
+        }
+
+        let fbo = gl.create_framebuffer().unwrap();
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+        let color = gl.create_renderbuffer().unwrap();
+        gl.bind_renderbuffer(glow::RENDERBUFFER, Some(color));
+        gl.renderbuffer_storage_multisample(glow::RENDERBUFFER, self.samples as i32, glow::RGBA16F, self.window_size.0 as i32, self.window_size.1 as i32);
+        gl.framebuffer_renderbuffer(glow::FRAMEBUFFER, glow::COLOR_ATTACHMENT0, glow::RENDERBUFFER, Some(color));
+        let depth = gl.create_renderbuffer().unwrap();
+        gl.bind_renderbuffer(glow::RENDERBUFFER, Some(depth));
+        gl.renderbuffer_storage_multisample(glow::RENDERBUFFER, self.samples as i32, glow::DEPTH24_STENCIL8, self.window_size.0 as i32, self.window_size.1 as i32);
+        gl.framebuffer_renderbuffer(glow::FRAMEBUFFER, glow::DEPTH_STENCIL_ATTACHMENT, glow::RENDERBUFFER, Some(depth));
+        gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+
+        let complete = gl.check_framebuffer_status(glow::FRAMEBUFFER) == glow::FRAMEBUFFER_COMPLETE;
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        if complete {
+            self.msaa = Some(MultisampleTarget { fbo, color, depth });
+        } else {
+            self.error.push(format!("{}x MSAA framebuffer was not complete, using FXAA", self.samples));
+            gl.delete_framebuffer(fbo);
+            gl.delete_renderbuffer(color);
+            gl.delete_renderbuffer(depth);
+        }
+    }
+
+    /// MSAA was asked for but couldn't be made, FXAA is drawn last instead
+    pub fn fxaa(&self) -> bool {
+        self.samples > 1 && self.msaa.is_none()
+    }
+
+    /// Framebuffer drawing to a new color texture of `size`
+    unsafe fn create_target(size: (u32, u32), internal_format: u32, format: u32, kind: u32, gl: &glow::Context) -> (NativeFramebuffer, NativeTexture) {
+        let fbo = gl.create_framebuffer().unwrap();
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+        let texture = gl.create_texture().unwrap();
+        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+        gl.tex_image_2d(
+            glow::TEXTURE_2D, 0, internal_format as i32,
+            size.0 as i32, size.1 as i32,
+            0, format, kind,
+            glow::PixelUnpackData::Slice(None)
+        );
+        gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::LINEAR as i32);
+        gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::LINEAR as i32);
+        gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE as i32);
+        gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE as i32);
+        gl.framebuffer_texture_2d(glow::FRAMEBUFFER, glow::COLOR_ATTACHMENT0, glow::TEXTURE_2D, Some(texture), 0);
+        (fbo, texture)
+    }
+
+    pub unsafe fn begin(&mut self, gl: &glow::Context) {
+        if self.samples != self.built_samples {
+            self.rebuild_msaa(gl);
+        }
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.msaa.as_ref().map_or(self.fbo, |msaa| msaa.fbo)));
     }
 
     pub unsafe fn end(&self, programs: &mut ProgramBank, gl: &glow::Context) {
-        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        self.end_to(None, programs, gl);
+    }
+
+    /// Draw the processed frame to `target` instead of the window
+    pub unsafe fn end_to(&self, target: Option<NativeFramebuffer>, programs: &mut ProgramBank, gl: &glow::Context) {
+        let chain: Vec<&PostEffect> = self.chain.iter()
+            .filter(|effect| effect.enabled && programs.programs.contains_key(&effect.program_name()))
+            .collect();
+        let fxaa = self.fxaa() && programs.programs.contains_key("fxaa");
+        let passes = chain.len() + fxaa as usize;
+        let first_target = if passes == 0 { target } else { Some(self.chain_targets[0].0) };
+        self.resolve_msaa(gl);
+        let bloom = self.render_bloom(programs, gl);
+        let occlusion = self.render_occlusion(programs, gl);
+
+        gl.bind_framebuffer(glow::FRAMEBUFFER, first_target);
         gl.clear_color(1.0, 1.0, 1.0, 1.0);
         gl.clear_depth(1.0);
         gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT | glow::STENCIL_BUFFER_BIT);
@@ -123,11 +343,141 @@ impl PostProcessing {
         gl.active_texture(glow::TEXTURE1);
         gl.bind_texture(glow::TEXTURE_2D, Some(self.texture_depth.unwrap()));
 
+        screen_program.uniform_1i32("bloomTexture", BLOOM_TEXTURE_UNIT as i32, gl);
+        gl.active_texture(glow::TEXTURE0 + BLOOM_TEXTURE_UNIT);
+        gl.bind_texture(glow::TEXTURE_2D, bloom);
+
+        screen_program.uniform_1i32("occlusionTexture", OCCLUSION_TEXTURE_UNIT as i32, gl);
+        screen_program.uniform_1i32("occlusion", occlusion.is_some() as i32, gl);
+        gl.active_texture(glow::TEXTURE0 + OCCLUSION_TEXTURE_UNIT);
+        gl.bind_texture(glow::TEXTURE_2D, occlusion);
+
         self.uniform_fog(screen_program, gl);
         self.uniform_kernel(screen_program, gl);
+        self.uniform_hdr(screen_program, bloom.is_some(), gl);
 
         gl.bind_vertex_array(Some(self.dummy_vao));
         gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+
+        // Each effect reads the last one's output, the last one draws to the target
+        for (i, effect) in chain.iter().enumerate() {
+            let output = if i + 1 == passes { target } else { Some(self.chain_targets[(i + 1) % 2].0) };
+            gl.bind_framebuffer(glow::FRAMEBUFFER, output);
+            gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT | glow::STENCIL_BUFFER_BIT);
+
+            let program = programs.get_mut(&effect.program_name()).unwrap();
+            gl.use_program(Some(program.inner));
+            program.uniform_1i32("screenTexture", 0, gl);
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.chain_targets[i % 2].1));
+            program.uniform_1i32("depthTexture", 1, gl);
+            gl.active_texture(glow::TEXTURE1);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture_depth.unwrap()));
+            program.uniform_1f32("time", self.time, gl);
+            program.uniform_2f32("screenSize", vec2(self.window_size.0 as f32, self.window_size.1 as f32), gl);
+            effect.apply_uniforms(program, gl);
+
+            gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+        }
+
+        if fxaa {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, target);
+            gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT | glow::STENCIL_BUFFER_BIT);
+            let program = programs.get_mut("fxaa").unwrap();
+            gl.use_program(Some(program.inner));
+            program.uniform_1i32("screenTexture", 0, gl);
+            program.uniform_2f32("screenSize", vec2(self.window_size.0 as f32, self.window_size.1 as f32), gl);
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.chain_targets[chain.len() % 2].1));
+            gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+        }
+        gl.active_texture(glow::TEXTURE0);
+    }
+
+    /// Copy the multisampled frame into the textures the passes read, averaging the samples
+    unsafe fn resolve_msaa(&self, gl: &glow::Context) {
+        let Some(msaa) = &self.msaa else { return; };
+        let (width, height) = (self.window_size.0 as i32, self.window_size.1 as i32);
+        gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(msaa.fbo));
+        gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, Some(self.fbo));
+        gl.blit_framebuffer(0, 0, width, height, 0, 0, width, height, glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT | glow::STENCIL_BUFFER_BIT, glow::NEAREST);
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+    }
+
+    /// Blur the parts of the frame brighter than the threshold at half size, None if bloom is off
+    unsafe fn render_bloom(&self, programs: &mut ProgramBank, gl: &glow::Context) -> Option<NativeTexture> {
+        if !self.hdr.bloom || self.hdr.bloom_passes == 0 || self.bloom_targets.len() < 2 {
+            return None;
+        }
+        let bloom_size = ((self.window_size.0 / 2).max(1), (self.window_size.1 / 2).max(1));
+        gl.viewport(0, 0, bloom_size.0 as i32, bloom_size.1 as i32);
+        gl.disable(glow::DEPTH_TEST);
+        gl.bind_vertex_array(Some(self.dummy_vao));
+        gl.active_texture(glow::TEXTURE0);
+
+        let bright_program = programs.get_mut("bloom_bright").unwrap();
+        gl.use_program(Some(bright_program.inner));
+        bright_program.uniform_1i32("screenTexture", 0, gl);
+        bright_program.uniform_1f32("threshold", self.hdr.bloom_threshold, gl);
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.bloom_targets[0].0));
+        gl.bind_texture(glow::TEXTURE_2D, self.texture_color);
+        gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+
+        // Across into the second target, then down back into the first
+        let blur_program = programs.get_mut("bloom_blur").unwrap();
+        gl.use_program(Some(blur_program.inner));
+        blur_program.uniform_1i32("screenTexture", 0, gl);
+        for pass in 0..self.hdr.bloom_passes * 2 {
+            let (source, output) = (pass as usize % 2, (pass as usize + 1) % 2);
+            let direction = if source == 0 { vec2(1.0 / bloom_size.0 as f32, 0.0) } else { vec2(0.0, 1.0 / bloom_size.1 as f32) };
+            blur_program.uniform_2f32("direction", direction, gl);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.bloom_targets[output].0));
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.bloom_targets[source].1));
+            gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+        }
+
+        gl.viewport(0, 0, self.window_size.0 as i32, self.window_size.1 as i32);
+        gl.enable(glow::DEPTH_TEST);
+        Some(self.bloom_targets[0].1)
+    }
+
+    /// Ambient occlusion from the depth of the frame, blurred, None if it's off
+    unsafe fn render_occlusion(&self, programs: &mut ProgramBank, gl: &glow::Context) -> Option<NativeTexture> {
+        let ssao = self.ssao.as_ref()?;
+        if self.occlusion_targets.len() < 2 {
+            return None;
+        }
+        gl.disable(glow::DEPTH_TEST);
+        gl.bind_vertex_array(Some(self.dummy_vao));
+        gl.active_texture(glow::TEXTURE0);
+
+        let ssao_program = programs.get_mut("ssao").unwrap();
+        gl.use_program(Some(ssao_program.inner));
+        ssao_program.uniform_1i32("depthTexture", 0, gl);
+        ssao_program.uniform_matrix4f32("projection", self.projection, gl);
+        ssao_program.uniform_matrix4f32("inverseProjection", self.projection.invert().unwrap_or(Matrix4::identity()), gl);
+        ssao_program.uniform_1f32("radius", ssao.radius, gl);
+        ssao_program.uniform_1f32("bias", ssao.bias, gl);
+        ssao_program.uniform_1f32("strength", ssao.strength, gl);
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.occlusion_targets[0].0));
+        gl.bind_texture(glow::TEXTURE_2D, self.texture_depth);
+        gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+
+        let blur_program = programs.get_mut("ssao_blur").unwrap();
+        gl.use_program(Some(blur_program.inner));
+        blur_program.uniform_1i32("screenTexture", 0, gl);
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.occlusion_targets[1].0));
+        gl.bind_texture(glow::TEXTURE_2D, Some(self.occlusion_targets[0].1));
+        gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
+
+        gl.enable(glow::DEPTH_TEST);
+        Some(self.occlusion_targets[1].1)
+    }
+
+    unsafe fn uniform_hdr(&self, program: &mut Program, bloom: bool, gl: &glow::Context) {
+        program.uniform_1f32("hdr.exposure", self.hdr.exposure, gl);
+        program.uniform_1i32("hdr.toneMapping", self.hdr.tone_mapping as i32, gl);
+        program.uniform_1f32("hdr.bloomIntensity", if bloom { self.hdr.bloom_intensity } else { 0.0 }, gl);
     }
 
     unsafe fn uniform_kernel(&self, program: &mut Program, gl: &glow::Context) {
@@ -148,6 +498,8 @@ impl PostProcessing {
             program.uniform_3f32("fog.color", fog.color, gl);
             program.uniform_1f32("fog.strength", fog.strength, gl);
             program.uniform_1f32("fog.max", fog.max, gl);
+            program.uniform_1f32("nearPlane", self.clip_planes.0, gl);
+            program.uniform_1f32("farPlane", self.clip_planes.1, gl);
         } else {
             program.uniform_1i32("fog.flags", 0, gl);
         }
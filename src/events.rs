@@ -0,0 +1,143 @@
+use std::collections::VecDeque;
+
+use crate::world::World;
+
+/// The oldest events are dropped past this many
+const MAX_EVENTS: usize = 500;
+
+/// What happened, events can be filtered by kind
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EventKind {
+    TriggerEntered,
+    TriggerExited,
+    DoorOpened,
+    DoorClosed,
+    /// A target trigger did something to the model it names
+    TargetFired,
+    ObjectiveCompleted,
+    Respawned,
+    /// A component fired an output, see `connection`
+    OutputFired,
+    /// An input from a connection reached its target
+    InputReceived,
+    ButtonPressed,
+    /// The player started touching a hurt volume
+    Damaged,
+    Died,
+    /// The player picked up a collectible
+    Collected,
+    /// The player reached a goal, see `goal`
+    LevelCompleted,
+    /// A footstep, jump or landing, see `footstep`
+    SurfaceSound,
+    /// The stopwatch of a timed level started, see `stopwatch`
+    StopwatchStarted
+}
+
+impl EventKind {
+    pub const ALL: [EventKind; 16] = [
+        Self::TriggerEntered, Self::TriggerExited, Self::DoorOpened, Self::DoorClosed, Self::ButtonPressed,
+        Self::TargetFired, Self::ObjectiveCompleted, Self::Damaged, Self::Died, Self::Respawned, Self::StopwatchStarted, Self::Collected, Self::LevelCompleted, Self::OutputFired, Self::InputReceived,
+        Self::SurfaceSound
+    ];
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::TriggerEntered => "trigger entered",
+            Self::TriggerExited => "trigger exited",
+            Self::DoorOpened => "door opened",
+            Self::DoorClosed => "door closed",
+            Self::TargetFired => "target fired",
+            Self::ObjectiveCompleted => "objective",
+            Self::Respawned => "respawned",
+            Self::OutputFired => "output",
+            Self::InputReceived => "input",
+            Self::ButtonPressed => "button pressed",
+            Self::Damaged => "damaged",
+            Self::Died => "died",
+            Self::Collected => "collected",
+            Self::LevelCompleted => "level completed",
+            Self::SurfaceSound => "surface sound",
+            Self::StopwatchStarted => "stopwatch started"
+        }
+    }
+}
+
+pub struct GameEvent {
+    /// Seconds since play mode started
+    pub time: f32,
+    pub kind: EventKind,
+    /// Label of the model the event came from
+    pub source: String,
+    /// Name of the model it was aimed at, if any
+    pub target: Option<String>,
+    pub detail: String
+}
+
+impl GameEvent {
+    pub fn describe(&self) -> String {
+        let target = self.target.as_ref().map_or(String::new(), |target| format!(" -> {}", target));
+        let detail = if self.detail.is_empty() { String::new() } else { format!(" ({})", self.detail) };
+        format!("{:7.2}s {}: {}{}{}", self.time, self.kind.name(), self.source, target, detail)
+    }
+}
+
+/// Events from the current or last play session, for debugging level logic in the editor<br>
+/// Nothing is logged while editing
+pub struct EventLog {
+    pub events: VecDeque<GameEvent>,
+    /// Kinds left out of the events window and overlay
+    pub hidden: Vec<EventKind>,
+    /// Show the newest events over the game while playing from the editor
+    pub overlay: bool,
+    time: f32,
+    playing: bool
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        // Footsteps would bury everything else
+        Self { events: VecDeque::new(), hidden: vec![EventKind::SurfaceSound], overlay: false, time: 0.0, playing: false }
+    }
+
+    /// Events that pass the filter, newest first
+    pub fn visible(&self) -> impl Iterator<Item = &GameEvent> {
+        self.events.iter().rev().filter(|event| !self.hidden.contains(&event.kind))
+    }
+
+    pub fn toggle_kind(&mut self, kind: EventKind) {
+        match self.hidden.iter().position(|hidden| *hidden == kind) {
+            Some(i) => { self.hidden.remove(i); },
+            None => self.hidden.push(kind)
+        }
+    }
+}
+
+impl World {
+    /// Add an event to the log, only while playing
+    pub fn log_event(&mut self, kind: EventKind, source: String, target: Option<String>, detail: String) {
+        if !self.do_game_logic {
+            return;
+        }
+        let log = &mut self.events;
+        if log.events.len() >= MAX_EVENTS {
+            log.events.pop_front();
+        }
+        log.events.push_back(GameEvent { time: log.time, kind, source, target, detail });
+    }
+
+    /// Keep the event clock running while playing, entering play mode starts a new log
+    pub fn update_events(&mut self, delta_time: f32) {
+        let log = &mut self.events;
+        if !self.do_game_logic {
+            log.playing = false;
+            return;
+        }
+        if !log.playing {
+            log.playing = true;
+            log.events.clear();
+            log.time = 0.0;
+        }
+        log.time += delta_time;
+    }
+}
@@ -0,0 +1,187 @@
+use std::{collections::BTreeSet, env, fs, path::{Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::save::{BrushMaterialsFile, LevelData, LevelFormat, BINARY_LEVEL_EXTENSION};
+
+/// Files the game loads on startup no matter which level is played, relative to `res/`
+const ENGINE_ASSETS: &[&str] = &[
+    "data/brush_types.json",
+    "models/arrow.obj",
+    "textures/magic_pixel.png",
+    "textures/evil_pixel.png",
+    "textures/test.png",
+    "textures/komari.png",
+    "textures/stencil_hidden.png",
+    "textures/note.png",
+    "textures/ui_buttons.png",
+    "textures/important.png",
+    "textures/ui_frame.png",
+    "textures/slider.png",
+    "textures/font.png"
+];
+/// Loaded as the default skybox
+const ENGINE_CUBEMAP: &str = "heaven";
+/// Only exported bundles have one, relative to the game's directory
+const LAUNCH_CONFIG_PATH: &str = "res/data/launch.json";
+
+/// How an exported game starts, written next to the exported levels
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LaunchConfig {
+    /// Played on startup instead of the test level, relative to the game's directory
+    pub level: String
+}
+
+impl LaunchConfig {
+    /// `None` when the game wasn't started from an export
+    pub fn load() -> Option<Result<Self, String>> {
+        let src = fs::read_to_string(LAUNCH_CONFIG_PATH).ok()?;
+        Some(serde_json::from_str(&src).map_err(|e| format!("failed to parse {}: {}", LAUNCH_CONFIG_PATH, e)))
+    }
+
+    fn write(&self, out: &Path) -> Result<(), String> {
+        let path = out.join(LAUNCH_CONFIG_PATH);
+        create_parent(&path)?;
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(&path, json).map_err(|e| format!("failed to write {}: {}", path.display(), e))
+    }
+}
+
+/// What an export did, the missing files are worth fixing before shipping
+pub struct ExportReport {
+    pub levels: Vec<String>,
+    pub copied: usize,
+    pub missing: Vec<String>
+}
+
+impl ExportReport {
+    pub fn summary(&self) -> String {
+        let mut summary = format!("exported {} level(s) and {} files", self.levels.len(), self.copied);
+        if let Some(first) = self.levels.first() {
+            summary += &format!(", the game starts with {}", first);
+        }
+        if !self.missing.is_empty() {
+            summary += &format!(", {} missing: {}", self.missing.len(), self.missing.join(", "));
+        }
+        if cfg!(debug_assertions) {
+            summary += ", the executable is a debug build";
+        }
+        summary
+    }
+}
+
+/// Copy `levels` and only the assets they reference into `out`, laid out like `res/` next to the game executable<br>
+/// Levels are stripped of editor data and written in the binary format, the game starts with the first one
+pub fn export_project(levels: &[PathBuf], out: &Path) -> Result<ExportReport, String> {
+    let mut assets: BTreeSet<String> = ENGINE_ASSETS.iter().map(|asset| asset.to_string()).collect();
+    for face in ["px", "nx", "py", "ny", "pz", "nz"] {
+        assets.insert(format!("textures/cubemap/{}/{}.png", ENGINE_CUBEMAP, face));
+    }
+    assets.extend(shaders()?);
+    // Every brush type is loaded on startup, so all of their textures are needed
+    assets.extend(brush_type_textures());
+
+    let res = out.join("res");
+    let mut report = ExportReport { levels: Vec::new(), copied: 0, missing: Vec::new() };
+
+    for path in levels {
+        let mut level = LevelData::read_from_file(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        level.strip_editor_data();
+        assets.extend(level.referenced_assets());
+
+        let name = path.file_stem().ok_or(format!("{} is not a level file", path.display()))?.to_string_lossy().to_string();
+        let level_path = res.join("levels").join(format!("{}.{}", name, BINARY_LEVEL_EXTENSION));
+        create_parent(&level_path)?;
+        level.write_to_file(&level_path, LevelFormat::Binary)?;
+        report.levels.push(name);
+    }
+
+    // Sounds a script plays can only be found by reading it
+    let scripts: Vec<String> = assets.iter().filter(|asset| asset.starts_with("scripts/")).cloned().collect();
+    for script in scripts {
+        if let Ok(src) = fs::read_to_string(Path::new("res").join(&script)) {
+            assets.extend(script_sounds(&src).into_iter().map(|sound| format!("audio/{}", sound)));
+        }
+    }
+
+    // Materials for .obj files are optional
+    let materials: Vec<String> = assets.iter().filter_map(|asset| asset.strip_suffix(".obj")).map(|model| format!("{}.mtl", model)).collect();
+    assets.extend(materials.into_iter().filter(|material| Path::new("res").join(material).exists()));
+
+    for asset in assets.iter() {
+        let from = Path::new("res").join(asset);
+        if !from.exists() {
+            report.missing.push(asset.clone());
+            continue;
+        }
+
+        let to = res.join(asset);
+        create_parent(&to)?;
+        fs::copy(&from, &to).map_err(|e| format!("failed to copy {}: {}", from.display(), e))?;
+        report.copied += 1;
+    }
+
+    if let Some(first) = report.levels.first() {
+        LaunchConfig { level: format!("res/levels/{}.{}", first, BINARY_LEVEL_EXTENSION) }.write(out)?;
+    }
+    copy_executable(out)?;
+
+    Ok(report)
+}
+
+fn create_parent(path: &Path) -> Result<(), String> {
+    match path.parent() {
+        Some(parent) => fs::create_dir_all(parent).map_err(|e| format!("failed to create {}: {}", parent.display(), e)),
+        None => Ok(())
+    }
+}
+
+/// Programs are loaded by name from all over the renderer, so every shader is shipped<br>
+/// Includes the post effects in `shaders/post`, levels can use them through their effects chain
+fn shaders() -> Result<Vec<String>, String> {
+    let mut files = Vec::new();
+    let mut directories = vec![String::from("shaders")];
+    while let Some(directory) = directories.pop() {
+        let dir = fs::read_dir(Path::new("res").join(&directory)).map_err(|e| format!("failed to read res/{}: {}", directory, e))?;
+        for entry in dir.filter_map(|entry| entry.ok()) {
+            let path = format!("{}/{}", directory, entry.file_name().to_string_lossy());
+            if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                directories.push(path);
+            } else {
+                files.push(path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Textures of the brush types that load, the same ones the game will see
+fn brush_type_textures() -> Vec<String> {
+    let (brush_types, _) = BrushMaterialsFile::load();
+    brush_types.materials.iter()
+        .flat_map(|material| [&material.diffuse, &material.specular])
+        .map(|texture| format!("textures/{}.png", texture))
+        .collect()
+}
+
+/// Literal paths passed to `play_sound` and `play_music`
+fn script_sounds(src: &str) -> Vec<String> {
+    let mut sounds = Vec::new();
+    for function in ["play_sound(\"", "play_music(\""] {
+        for (start, _) in src.match_indices(function) {
+            let rest = &src[start + function.len()..];
+            if let Some(end) = rest.find('"') {
+                sounds.push(rest[..end].to_string());
+            }
+        }
+    }
+    sounds
+}
+
+/// The running executable goes next to `res/`, export from a release build to ship one
+fn copy_executable(out: &Path) -> Result<(), String> {
+    let exe = env::current_exe().map_err(|e| format!("failed to find the game executable: {}", e))?;
+    let name = exe.file_name().ok_or(String::from("failed to find the game executable"))?;
+    fs::copy(&exe, out.join(name)).map_err(|e| format!("failed to copy the game executable: {}", e))?;
+    Ok(())
+}
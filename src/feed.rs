@@ -0,0 +1,161 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use glow::HasContext;
+use serde::{Deserialize, Serialize};
+
+use crate::{common, component::Component, mesh::MeshBank, render::Camera, shader::ProgramBank, target::RenderTarget, texture::TextureBank, world::World};
+
+/// Materials with a diffuse of `feed:<name>` show the camera feed called `name`
+pub const FEED_TEXTURE_PREFIX: &str = "feed:";
+/// Feeds can't be bigger than this on either side
+pub const MAX_FEED_RESOLUTION: u32 = 1024;
+
+/// Renders the level from the model into a texture for monitors and portal views, looking along the model's -Z<br>
+/// The model's own meshes are in the view, so it is best placed on an empty model or in front of a camera mesh
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct CameraFeed {
+    /// Materials show the feed with a diffuse of `feed:<name>`
+    pub name: String,
+    /// Vertical field of view in degrees
+    pub fov: f32,
+    pub resolution: (u32, u32),
+    /// The feed is rendered again every this many frames, higher is cheaper but choppier
+    pub interval: u32
+}
+
+impl Default for CameraFeed {
+    fn default() -> Self {
+        Self { name: String::from("camera"), fov: 70.0, resolution: (256, 256), interval: 1 }
+    }
+}
+
+pub fn texture_name(name: &str) -> String {
+    format!("{}{}", FEED_TEXTURE_PREFIX, name)
+}
+
+pub fn is_feed_texture(texture: &str) -> bool {
+    texture.starts_with(FEED_TEXTURE_PREFIX)
+}
+
+/// Offscreen target of a feed in the scene
+pub struct FeedView {
+    target: RenderTarget,
+    /// Slots in `World::models` get reused
+    model_id: u64,
+    /// Frames since the feed was last rendered
+    frames: u32
+}
+
+fn camera_feed(components: &[Component]) -> Option<&CameraFeed> {
+    components.iter().find_map(|component| match component {
+        Component::CameraFeed(feed) => Some(feed),
+        _ => None
+    })
+}
+
+impl World {
+    pub fn get_camera_feed(&self, model: usize) -> Option<CameraFeed> {
+        camera_feed(&self.models.get(model)?.as_ref()?.components).cloned()
+    }
+
+    pub fn set_camera_feed(&mut self, model: usize, settings: CameraFeed) {
+        let Some(target) = self.models[model].as_mut() else { return; };
+        for component in target.components.iter_mut() {
+            if let Component::CameraFeed(old) = component {
+                *old = settings.clone();
+            }
+        }
+    }
+
+    /// Render every camera feed that is due into its texture, call after `render_shadows` and before the scene is rendered<br>
+    /// Feeds light the scene with the uniform lights, the clusters are built for the main camera<br>
+    /// Static meshes are culled and given detail levels for each feed's camera, and for the main camera again after
+    pub unsafe fn render_feeds(&mut self, meshes: &mut MeshBank, programs: &mut ProgramBank, textures: &mut TextureBank, gl: &glow::Context) {
+        let feeds: Vec<(usize, u64, CameraFeed, Point3<f32>, Vector3<f32>)> = self.models.iter().enumerate()
+            .filter_map(|(index, model)| {
+                let model = model.as_ref()?;
+                let feed = camera_feed(&model.components)?;
+                let direction = (model.transform * -Vector3::unit_z().extend(0.0)).truncate();
+                Some((index, model.id, feed.clone(), Point3::from_vec(common::translation(model.transform)), direction))
+            })
+            .collect();
+
+        let mut gone: Vec<usize> = self.scene.feeds.keys().filter(|index| !feeds.iter().any(|feed| feed.0 == **index)).copied().collect();
+        for (index, id, feed, ..) in feeds.iter() {
+            let size = (feed.resolution.0.clamp(1, MAX_FEED_RESOLUTION), feed.resolution.1.clamp(1, MAX_FEED_RESOLUTION));
+            if self.scene.feeds.get(index).is_some_and(|view| view.model_id != *id || view.target.size != size) {
+                gone.push(*index);
+            }
+        }
+        for index in gone {
+            if let Some(view) = self.scene.feeds.remove(&index) {
+                view.target.delete(gl);
+            }
+        }
+
+        let show_hidden = self.scene.show_hidden_objects;
+        self.scene.show_hidden_objects = false;
+        self.scene.drawing_offscreen = true;
+        let mut rendered = false;
+        for (index, id, feed, position, direction) in feeds {
+            if direction.magnitude2() < 0.0001 {
+                continue;
+            }
+            let size = (feed.resolution.0.clamp(1, MAX_FEED_RESOLUTION), feed.resolution.1.clamp(1, MAX_FEED_RESOLUTION));
+            if !self.scene.feeds.contains_key(&index) {
+                match RenderTarget::new(size, gl) {
+                    Ok(target) => { self.scene.feeds.insert(index, FeedView { target, model_id: id, frames: u32::MAX }); },
+                    Err(e) => {
+                        self.editor_data.show_debug.push(format!("failed to make camera feed {}: {}", feed.name, e));
+                        continue;
+                    }
+                }
+            }
+            let view = self.scene.feeds.get_mut(&index).unwrap();
+            view.frames = view.frames.saturating_add(1);
+            if view.frames < feed.interval.max(1) {
+                continue;
+            }
+            view.frames = 0;
+
+            let name = texture_name(&feed.name);
+            if textures.get(&name).map_or(true, |texture| (texture.width, texture.height) != size) {
+                let color = gl.create_texture().unwrap();
+                gl.bind_texture(glow::TEXTURE_2D, Some(color));
+                gl.tex_image_2d(
+                    glow::TEXTURE_2D, 0, glow::RGBA8 as i32,
+                    size.0 as i32, size.1 as i32,
+                    0, glow::RGBA, glow::UNSIGNED_BYTE,
+                    glow::PixelUnpackData::Slice(None)
+                );
+                textures.insert_rendered(&name, color, size.0, size.1, gl);
+            }
+
+            let mut camera = Camera::new();
+            camera.on_window_resized(size.0 as f32, size.1 as f32);
+            camera.set_fov(feed.fov.clamp(1.0, 170.0));
+            let (near, far) = self.scene.camera.clip_planes();
+            camera.set_clip_planes(near, far);
+            camera.look_along(position, direction.normalize());
+            let main = std::mem::replace(&mut self.scene.camera, camera);
+            self.scene.prepare_statics_for_camera(meshes, gl);
+            rendered = true;
+
+            let view = &self.scene.feeds[&index];
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(view.target.fbo));
+            gl.viewport(0, 0, size.0 as i32, size.1 as i32);
+            self.scene.render(meshes, programs, textures, gl);
+            view.target.copy_to_texture(textures.get(&name).unwrap().inner, gl);
+
+            self.scene.camera = main;
+        }
+        self.scene.drawing_offscreen = false;
+        self.scene.show_hidden_objects = show_hidden;
+        if rendered {
+            self.scene.prepare_statics_for_camera(meshes, gl);
+        }
+
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        gl.viewport(0, 0, self.scene.window_size.0 as i32, self.scene.window_size.1 as i32);
+    }
+}
@@ -0,0 +1,36 @@
+use cgmath::{vec2, Vector2, Zero};
+use serde::{Deserialize, Serialize};
+
+/// Sprite sheet animation, the texture is `frames` frames side by side played at `fps`<br>
+/// Billboards have their own, brush types set one in the brush types file for brushes with `flags::FLIPBOOK`
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct Flipbook {
+    pub frames: u32,
+    pub fps: f32
+}
+
+impl Default for Flipbook {
+    fn default() -> Self {
+        Self { frames: 1, fps: 8.0 }
+    }
+}
+
+impl Flipbook {
+    pub fn animated(&self) -> bool {
+        self.frames > 1
+    }
+
+    /// Frame shown `time` seconds in, looping
+    pub fn frame_at(&self, time: f32) -> u32 {
+        if !self.animated() || self.fps <= 0.0 {
+            return 0;
+        }
+        (time * self.fps) as u32 % self.frames
+    }
+
+    /// The `flipbook` uniform, frame count and current frame, zero leaves the texture alone
+    pub fn uniform(&self, frame: u32) -> Vector2<f32> {
+        if self.animated() { vec2(self.frames as f32, frame as f32) } else { Vector2::zero() }
+    }
+}
@@ -0,0 +1,64 @@
+use std::path::Path;
+
+use crate::{events::EventKind, world::World};
+
+/// Distance walked on the ground between footsteps
+pub const STRIDE: f32 = 1.8;
+/// Landings from falls slower than this are too soft to hear
+pub const LAND_SPEED: f32 = 4.0;
+/// Sound sets are folders in here, with a sound for each kind like `metal/footstep.ogg`
+pub const SOUND_SET_DIRECTORY: &str = "footsteps";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SurfaceSoundKind {
+    Footstep,
+    Jump,
+    Land
+}
+
+impl SurfaceSoundKind {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Footstep => "footstep",
+            Self::Jump => "jump",
+            Self::Land => "land"
+        }
+    }
+}
+
+/// A sound the player made against the ground, emitted by `Player::update`
+#[derive(Clone, Debug)]
+pub struct SurfaceSound {
+    pub kind: SurfaceSoundKind,
+    /// `PhysicalProperties::sound_set` of the ground the player was on
+    pub sound_set: Option<String>
+}
+
+impl SurfaceSound {
+    /// Path relative to `res/audio/` of the sound to play, sets don't need a sound for every kind
+    pub fn file(&self) -> Option<String> {
+        let file = format!("{}/{}/{}.ogg", SOUND_SET_DIRECTORY, self.sound_set.as_ref()?, self.kind.name());
+        Path::new("res/audio").join(&file).exists().then_some(file)
+    }
+}
+
+impl World {
+    /// Log and play the player's surface sounds from the last update, only while playing
+    pub fn play_surface_sounds(&mut self) {
+        if !self.do_game_logic {
+            return;
+        }
+        for sound in self.player.surface_sounds.clone() {
+            let detail = match &sound.sound_set {
+                Some(set) => format!("{} on {}", sound.kind.name(), set),
+                None => sound.kind.name().to_string()
+            };
+            self.log_event(EventKind::SurfaceSound, String::from("player"), None, detail);
+            if let Some(file) = sound.file() {
+                if let Err(e) = self.music.play_sound(&file) {
+                    self.editor_data.show_debug.push(e);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use std::{f32, fs, path::{Path, PathBuf}};
+
+use cgmath::{EuclideanSpace, Point3, Vector3, Zero};
+use serde::{Deserialize, Serialize};
+
+use crate::{common, ghost::GhostRun, input::Input, mesh::MeshBank, readback::{Readback, ReadbackId}, regression, render::CameraControlScheme, save::LevelData, shader::ProgramBank, spectator::EYE_HEIGHT, target::RenderTarget, texture::TextureBank, window, world::{PlayerMovementMode, World}};
+
+pub const CAMERA_PATH_DIRECTORY: &str = "res/camera_paths";
+pub const DEFAULT_FRAME_SIZE: (u32, u32) = (1280, 720);
+pub const DEFAULT_FPS: f32 = 30.0;
+/// Frames drawn before the first one is written, so anything set up during the first update is in it
+const WARMUP_FRAMES: u32 = 3;
+/// Ghost replays look this many seconds ahead along the run
+const LOOK_AHEAD: f32 = 0.3;
+/// Ghost replays keep facing the same way while moving slower than this
+const MIN_TURN_DISTANCE: f32 = 0.05;
+
+/// Where the camera is at `time` seconds into a camera path
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct CameraKey {
+    pub time: f32,
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32
+}
+
+/// Camera flight written by hand in `res/camera_paths/<name>.json`, keys are in order of time
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct CameraPath {
+    pub keys: Vec<CameraKey>
+}
+
+impl CameraPath {
+    pub fn load(name: &str) -> Result<Self, String> {
+        let src = fs::read_to_string(format!("{}/{}.json", CAMERA_PATH_DIRECTORY, name)).map_err(|e| format!("failed to open camera path {}: {}", name, e))?;
+        let path: Self = serde_json::from_str(&src).map_err(|e| format!("failed to parse camera path {}: {}", name, e))?;
+        if path.keys.is_empty() {
+            return Err(format!("camera path {} has no keys", name));
+        }
+        if path.keys.windows(2).any(|pair| pair[1].time < pair[0].time) {
+            return Err(format!("keys of camera path {} are out of order", name));
+        }
+        Ok(path)
+    }
+
+    /// Position, yaw and pitch between the keys around `time`, turning the short way round
+    fn camera_at(&self, time: f32) -> (Vector3<f32>, f32, f32) {
+        let next = self.keys.iter().position(|key| key.time > time).unwrap_or(self.keys.len() - 1);
+        let (a, b) = (&self.keys[next.saturating_sub(1)], &self.keys[next]);
+        let t = if b.time > a.time { ((time - a.time) / (b.time - a.time)).clamp(0.0, 1.0) } else { 1.0 };
+        let yaw_change = (b.yaw - a.yaw + f32::consts::PI).rem_euclid(f32::consts::TAU) - f32::consts::PI;
+        let position = common::vec3_mix(a.position.into(), b.position.into(), t);
+        (position, a.yaw + yaw_change * t, a.pitch + (b.pitch - a.pitch) * t)
+    }
+}
+
+/// What the camera follows while the frames are rendered
+pub enum FrameSource {
+    /// From the ghost's eye, facing the way it moves
+    Ghost(GhostRun),
+    Path(CameraPath)
+}
+
+impl FrameSource {
+    fn duration(&self) -> f32 {
+        match self {
+            Self::Ghost(run) => run.duration(),
+            Self::Path(path) => path.keys.last().map_or(0.0, |key| key.time)
+        }
+    }
+
+    /// Position, yaw and pitch of the camera at `time`, `yaw` is where a ghost replay was last facing
+    fn camera_at(&self, time: f32, yaw: f32) -> (Vector3<f32>, f32, f32) {
+        match self {
+            Self::Ghost(run) => {
+                let position = run.position_at(time).unwrap_or(Vector3::zero());
+                let ahead = run.position_at(time + LOOK_AHEAD).unwrap_or(position) - position;
+                let yaw = if ahead.x.hypot(ahead.z) > MIN_TURN_DISTANCE { ahead.z.atan2(ahead.x) } else { yaw };
+                (position + Vector3::unit_y() * EYE_HEIGHT, yaw, 0.0)
+            },
+            Self::Path(path) => path.camera_at(time)
+        }
+    }
+}
+
+/// An offline render of `level`, one numbered PNG per step of `1 / fps` seconds
+pub struct FrameExport {
+    pub level: PathBuf,
+    pub out_dir: PathBuf,
+    pub source: FrameSource,
+    pub size: (u32, u32),
+    pub fps: f32
+}
+
+/// Write the captures that have been read back, returns how many were written
+fn write_frames(readback: &mut Readback, requested: &mut Vec<(ReadbackId, u32)>, out_dir: &Path) -> Result<u32, String> {
+    let mut written = 0;
+    let mut i = 0;
+    while i < requested.len() {
+        let (id, frame) = requested[i];
+        let Some(capture) = readback.take(id) else {
+            i += 1;
+            continue;
+        };
+        requested.swap_remove(i);
+        let mut image = capture.image;
+        for pixel in image.pixels_mut() {
+            pixel.0[3] = 255;
+        }
+        let path = out_dir.join(format!("frame_{:05}.png", frame));
+        image.save(&path).map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+impl FrameExport {
+    /// Play the level at a fixed timestep with the camera on `source`, returns a summary of what was written<br>
+    /// The level runs like it does while playing, with the player following the camera so triggers and doors react to it
+    pub unsafe fn run(&self) -> Result<String, String> {
+        if self.fps <= 0.0 || self.size.0 == 0 || self.size.1 == 0 {
+            return Err(String::from("the frame rate and size have to be above 0"));
+        }
+        let (gl, _surface, _context, _window, _event_loop) = window::create_gl_context(false);
+        let capabilities = window::GpuCapabilities::detect(&gl);
+        let mut programs = ProgramBank::new();
+        programs.version = capabilities.shader_version();
+        let mut textures = TextureBank::new();
+        let mut meshes = MeshBank::new();
+        textures.load_by_name("magic_pixel", &gl).map_err(|e| e.to_string())?;
+        textures.load_by_name("evil_pixel", &gl).map_err(|e| e.to_string())?;
+        World::load_basic_meshes(&mut meshes, &gl);
+
+        let data = LevelData::read_from_file(&self.level)?;
+        let mut world = World::from_save_data(data, &mut textures, &mut meshes, &mut programs, &gl);
+        world.scene.safe_mode = !capabilities.missing_requirements().is_empty();
+        world.scene.window_size = self.size;
+        world.scene.post_process.resize(self.size, &gl);
+        world.scene.camera.on_window_resized(self.size.0 as f32, self.size.1 as f32);
+        world.scene.camera.control_sceme = CameraControlScheme::FirstPerson(false);
+        world.scene.show_hidden_objects = false;
+        world.scene.cull_statics = true;
+        world.editor_data.active = false;
+        world.do_game_logic = true;
+        world.player.movement = PlayerMovementMode::FollowCamera;
+        let target = RenderTarget::new(self.size, &gl)?;
+        fs::create_dir_all(&self.out_dir).map_err(|e| format!("failed to create {}: {}", self.out_dir.display(), e))?;
+
+        let input = Input::new();
+        let step = 1.0 / self.fps;
+        let frames = (self.source.duration() * self.fps).ceil() as u32 + 1;
+        let mut readback = Readback::default();
+        let mut requested = Vec::new();
+        let mut written = 0;
+        let mut yaw = world.scene.camera.yaw;
+        for frame in 0..frames + WARMUP_FRAMES {
+            let time = frame.saturating_sub(WARMUP_FRAMES) as f32 * step;
+            let (position, new_yaw, pitch) = self.source.camera_at(time, yaw);
+            yaw = new_yaw;
+            world.scene.camera.look_from(Point3::from_vec(position), yaw, pitch);
+            world.update(&input, (Vector3::zero(), Vector3::zero()), if frame < WARMUP_FRAMES { 0.0 } else { step });
+            regression::render_frame(&mut world, &target, &mut meshes, &mut programs, &mut textures, &gl);
+
+            if frame >= WARMUP_FRAMES {
+                let id = readback.request(Some(target.fbo), (0, 0, self.size.0, self.size.1), &gl)?;
+                requested.push((id, frame - WARMUP_FRAMES));
+            }
+            readback.poll(&gl);
+            written += write_frames(&mut readback, &mut requested, &self.out_dir)?;
+        }
+        while readback.pending() > 0 {
+            readback.poll(&gl);
+            written += write_frames(&mut readback, &mut requested, &self.out_dir)?;
+        }
+
+        target.delete(&gl);
+        Ok(format!("wrote {} frames at {}x{} to {}", written, self.size.0, self.size.1, self.out_dir.display()))
+    }
+}
@@ -0,0 +1,308 @@
+use std::{cell::RefCell, fs, path::Path, rc::Rc};
+
+use cgmath::{vec3, Matrix4, Vector3};
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, INT};
+
+use crate::{component::Component, mesh::{flags, BrushFaces, MeshBank}, render::PointLight, session::EditKind, texture::TextureBank, world::{Model, Renderable, Selection, World}};
+
+/// Generators build whole levels, so they get far more room than level scripts
+const MAX_OPERATIONS: u64 = 10_000_000;
+const MAX_CALL_LEVELS: usize = 64;
+/// Where `prefab` looks for prefab files
+const PREFAB_DIRECTORY: &str = "res/data/prefabs";
+
+/// A brush placed by a generator, relative to where the output is inserted or to its model
+#[derive(Clone, Debug)]
+pub struct GeneratedBrush {
+    pub material: String,
+    pub origin: Vector3<f32>,
+    pub size: Vector3<f32>
+}
+
+/// A model built from brushes and components, like a trigger volume
+#[derive(Clone, Debug)]
+pub struct GeneratedModel {
+    pub position: Vector3<f32>,
+    pub name: Option<String>,
+    pub tags: Vec<String>,
+    pub brushes: Vec<GeneratedBrush>,
+    pub components: Vec<Component>
+}
+
+/// Level pieces built from code, checked and inserted all at once by `World::insert_generated`<br>
+/// Positions are relative to where the output ends up
+#[derive(Clone, Default, Debug)]
+pub struct LevelBuilder {
+    pub brushes: Vec<GeneratedBrush>,
+    /// Position, color and radius of light fixtures
+    pub lights: Vec<(Vector3<f32>, Vector3<f32>, f32)>,
+    pub models: Vec<GeneratedModel>,
+    /// Prefab files in `res/data/prefabs` and their positions
+    pub prefabs: Vec<(String, Vector3<f32>)>,
+    /// Everything the generator printed
+    pub log: Vec<String>
+}
+
+impl LevelBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn brush(&mut self, material: &str, origin: Vector3<f32>, size: Vector3<f32>) -> &mut Self {
+        self.brushes.push(GeneratedBrush { material: material.to_string(), origin, size });
+        self
+    }
+
+    pub fn light(&mut self, position: Vector3<f32>, color: Vector3<f32>, radius: f32) -> &mut Self {
+        self.lights.push((position, color, radius));
+        self
+    }
+
+    pub fn prefab(&mut self, file: &str, position: Vector3<f32>) -> &mut Self {
+        self.prefabs.push((file.to_string(), position));
+        self
+    }
+
+    /// Start an empty model, returns its index for `model_brush` and `model_component`
+    pub fn model(&mut self, position: Vector3<f32>) -> usize {
+        self.models.push(GeneratedModel { position, name: None, tags: Vec::new(), brushes: Vec::new(), components: Vec::new() });
+        self.models.len() - 1
+    }
+
+    pub fn model_brush(&mut self, model: usize, material: &str, origin: Vector3<f32>, size: Vector3<f32>) -> Result<&mut Self, String> {
+        self.generated_model(model)?.brushes.push(GeneratedBrush { material: material.to_string(), origin, size });
+        Ok(self)
+    }
+
+    pub fn model_component(&mut self, model: usize, component: Component) -> Result<&mut Self, String> {
+        self.generated_model(model)?.components.push(component);
+        Ok(self)
+    }
+
+    pub fn model_name(&mut self, model: usize, name: &str) -> Result<&mut Self, String> {
+        self.generated_model(model)?.name = Some(name.to_string());
+        Ok(self)
+    }
+
+    pub fn model_tag(&mut self, model: usize, tag: &str) -> Result<&mut Self, String> {
+        self.generated_model(model)?.tags.push(tag.to_string());
+        Ok(self)
+    }
+
+    fn generated_model(&mut self, model: usize) -> Result<&mut GeneratedModel, String> {
+        self.models.get_mut(model).ok_or_else(|| format!("there is no model {}", model))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.brushes.is_empty() && self.lights.is_empty() && self.models.is_empty() && self.prefabs.is_empty()
+    }
+}
+
+fn number(value: &Dynamic) -> Result<f32, Box<EvalAltResult>> {
+    value.as_float().or_else(|_| value.as_int().map(|n| n as f32))
+        .map_err(|kind| format!("expected a number but got {}", kind).into())
+}
+
+/// `[x, y, z]` from a script, integers are allowed
+fn vector(array: Array) -> Result<Vector3<f32>, Box<EvalAltResult>> {
+    if array.len() != 3 {
+        return Err(format!("expected [x, y, z] but got {} numbers", array.len()).into());
+    }
+    Ok(vec3(number(&array[0])?, number(&array[1])?, number(&array[2])?))
+}
+
+/// Component written the way prefabs write it, like `#{ type: "trigger", trigger: "objective", title: "Exit" }`
+fn component(map: Map) -> Result<Component, Box<EvalAltResult>> {
+    let json = serde_json::from_str(&rhai::format_map_as_json(&map)).map_err(|e| e.to_string())?;
+    Ok(Component::parse_from_prefab(&json)?)
+}
+
+fn model_index(model: INT) -> Result<usize, Box<EvalAltResult>> {
+    usize::try_from(model).map_err(|_| format!("there is no model {}", model).into())
+}
+
+/// Runs a generator script and collects what it builds<br>
+/// Generators are rhai scripts that run from top to bottom, calling:
+/// - `brush(material, [x, y, z], [sx, sy, sz])`
+/// - `light([x, y, z], [r, g, b], radius)`
+/// - `prefab(file, [x, y, z])`
+/// - `model([x, y, z])`, which returns a model for `model_brush(model, material, origin, size)`, `model_component(model, #{ ... })`, `model_name(model, name)` and `model_tag(model, tag)`
+pub fn run_generator<P: AsRef<Path>>(path: P) -> Result<LevelBuilder, String> {
+    let path = path.as_ref();
+    let src = fs::read_to_string(path).map_err(|e| format!("failed to open generator {}: {}", path.display(), e))?;
+
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(MAX_CALL_LEVELS);
+
+    let builder = Rc::new(RefCell::new(LevelBuilder::new()));
+    let b = builder.clone();
+    engine.register_fn("brush", move |material: &str, origin: Array, size: Array| -> Result<(), Box<EvalAltResult>> {
+        b.borrow_mut().brush(material, vector(origin)?, vector(size)?);
+        Ok(())
+    });
+    let b = builder.clone();
+    engine.register_fn("light", move |position: Array, color: Array, radius: Dynamic| -> Result<(), Box<EvalAltResult>> {
+        b.borrow_mut().light(vector(position)?, vector(color)?, number(&radius)?);
+        Ok(())
+    });
+    let b = builder.clone();
+    engine.register_fn("prefab", move |file: &str, position: Array| -> Result<(), Box<EvalAltResult>> {
+        b.borrow_mut().prefab(file, vector(position)?);
+        Ok(())
+    });
+    let b = builder.clone();
+    engine.register_fn("model", move |position: Array| -> Result<INT, Box<EvalAltResult>> {
+        Ok(b.borrow_mut().model(vector(position)?) as INT)
+    });
+    let b = builder.clone();
+    engine.register_fn("model_brush", move |model: INT, material: &str, origin: Array, size: Array| -> Result<(), Box<EvalAltResult>> {
+        b.borrow_mut().model_brush(model_index(model)?, material, vector(origin)?, vector(size)?)?;
+        Ok(())
+    });
+    let b = builder.clone();
+    engine.register_fn("model_component", move |model: INT, map: Map| -> Result<(), Box<EvalAltResult>> {
+        b.borrow_mut().model_component(model_index(model)?, component(map)?)?;
+        Ok(())
+    });
+    let b = builder.clone();
+    engine.register_fn("model_name", move |model: INT, name: &str| -> Result<(), Box<EvalAltResult>> {
+        b.borrow_mut().model_name(model_index(model)?, name)?;
+        Ok(())
+    });
+    let b = builder.clone();
+    engine.register_fn("model_tag", move |model: INT, tag: &str| -> Result<(), Box<EvalAltResult>> {
+        b.borrow_mut().model_tag(model_index(model)?, tag)?;
+        Ok(())
+    });
+    let b = builder.clone();
+    engine.on_print(move |text| b.borrow_mut().log.push(text.to_string()));
+
+    let ast = engine.compile(src).map_err(|e| format!("error in generator {}: {}", path.display(), e))?;
+    engine.run_ast_with_scope(&mut Scope::new(), &ast).map_err(|e| format!("error in generator {}: {}", path.display(), e))?;
+    drop(engine);
+
+    Ok(Rc::try_unwrap(builder).map_err(|_| String::from("generator is still running"))?.into_inner())
+}
+
+fn check_vector(vector: Vector3<f32>, what: &str) -> Result<(), String> {
+    if vector.x.is_finite() && vector.y.is_finite() && vector.z.is_finite() {
+        Ok(())
+    } else {
+        Err(format!("{} is not a number", what))
+    }
+}
+
+impl World {
+    fn check_generated_brush(&self, brush: &GeneratedBrush) -> Result<(), String> {
+        if !self.scene.materials.contains_key(&brush.material) {
+            return Err(format!("the material {} isn't loaded", brush.material));
+        }
+        check_vector(brush.origin, "brush origin")?;
+        check_vector(brush.size, "brush size")?;
+        if brush.size.x <= 0.0 || brush.size.y <= 0.0 || brush.size.z <= 0.0 {
+            return Err(format!("brush of {} has size {:?}, sizes have to be positive", brush.material, <[f32; 3]>::from(brush.size)));
+        }
+        Ok(())
+    }
+
+    /// Check generated pieces the way the editor checks what it places, nothing is inserted if any of them are wrong
+    pub fn validate_generated(&self, builder: &LevelBuilder) -> Result<(), String> {
+        for brush in builder.brushes.iter() {
+            self.check_generated_brush(brush)?;
+        }
+
+        for (position, color, radius) in builder.lights.iter() {
+            check_vector(*position, "light position")?;
+            check_vector(*color, "light color")?;
+            if color.x < 0.0 || color.y < 0.0 || color.z < 0.0 {
+                return Err(String::from("light colors can't be negative"));
+            }
+            if radius.is_nan() || *radius <= 0.0 {
+                return Err(format!("light radius {} has to be positive", radius));
+            }
+        }
+
+        for (i, model) in builder.models.iter().enumerate() {
+            check_vector(model.position, "model position")?;
+            for brush in model.brushes.iter() {
+                self.check_generated_brush(brush)?;
+            }
+            let triggers = model.components.iter().filter(|component| matches!(component, Component::Trigger(_))).count();
+            if triggers > 0 && model.brushes.len() != 1 {
+                return Err(format!("model {} has a trigger, so it needs exactly one brush", i));
+            }
+            if let Some(name) = &model.name {
+                if self.find_by_name(name).is_some() || builder.models[..i].iter().any(|other| other.name.as_ref() == Some(name)) {
+                    return Err(format!("another model is already named {}", name));
+                }
+            }
+        }
+
+        for (file, position) in builder.prefabs.iter() {
+            check_vector(*position, "prefab position")?;
+            if !Path::new(PREFAB_DIRECTORY).join(file).is_file() {
+                return Err(format!("there is no prefab {}", file));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Insert everything `builder` made around `offset` and select it, returns how many objects were inserted
+    pub fn insert_generated(&mut self, builder: &LevelBuilder, offset: Vector3<f32>, textures: &mut TextureBank, meshes: &mut MeshBank, gl: &glow::Context) -> Result<usize, String> {
+        if builder.is_empty() {
+            return Err(String::from("the generator didn't make anything"));
+        }
+        self.validate_generated(builder)?;
+
+        // Prefabs can still fail to parse, so load them before anything is inserted
+        let mut prefabs = Vec::new();
+        for (file, position) in builder.prefabs.iter() {
+            let prefab = self.load_prefab_from_file(textures, meshes, gl, Path::new(PREFAB_DIRECTORY).join(file))
+                .map_err(|e| format!("failed to load prefab {}: {}", file, e))?;
+            prefabs.push((prefab, *position));
+        }
+
+        let mut inserted = Vec::new();
+        for brush in builder.brushes.iter() {
+            inserted.push(Selection::Brush(self.insert_brush(Renderable::Brush(
+                brush.material.clone(), brush.origin + offset, brush.size, flags::EXTEND_TEXTURE, BrushFaces::default()
+            ))));
+        }
+
+        for (position, color, radius) in builder.lights.iter() {
+            let mut light = PointLight::default(vec3(0.0, 0.0, 0.0));
+            light.set_color(*color);
+            light.set_attenuation(*radius);
+            inserted.push(Selection::Model(self.place_light(*position + offset, light)));
+        }
+
+        for generated in builder.models.iter() {
+            let render = generated.brushes.iter()
+                .map(|brush| Renderable::Brush(brush.material.clone(), brush.origin, brush.size, flags::EXTEND_TEXTURE, BrushFaces::default()))
+                .collect();
+            let mut model = Model::new(false, Matrix4::from_translation(generated.position + offset), render);
+            model.name = generated.name.clone();
+            model.tags = generated.tags.clone();
+            model.components = generated.components.clone();
+            // Trigger volumes are walked into and only drawn in the editor, like the ones placed by hand
+            if model.components.iter().any(|component| matches!(component, Component::Trigger(_))) {
+                model = model.non_solid().insert_hidden();
+            }
+            inserted.push(Selection::Model(self.insert_model(model)));
+        }
+
+        for ((mut model, lights), position) in prefabs {
+            for (light_offset, light) in lights {
+                model = model.with_light(self.scene.add_point_light(light), light_offset);
+            }
+            model.transform = Matrix4::from_translation(position + offset) * model.transform;
+            inserted.push(Selection::Model(self.insert_model(model)));
+        }
+
+        self.record_selection_edit(EditKind::Created, &inserted);
+        self.select_items(&inserted);
+        Ok(inserted.len())
+    }
+}
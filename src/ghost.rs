@@ -0,0 +1,168 @@
+use std::fs;
+
+use cgmath::{Matrix4, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{common::{self, normal_matrix}, mesh::MeshBank, render::MobileRenderData, shader::ProgramBank, texture::TextureBank, world::World};
+
+pub const GHOST_DIRECTORY: &str = "res/ghosts";
+/// Seconds between recorded positions
+const GHOST_TICK: f32 = 0.05;
+const GHOST_ALPHA: f32 = 0.35;
+/// Same as the player's collider
+const GHOST_SIZE: Vector3<f32> = Vector3 { x: 0.5, y: 2.0, z: 0.5 };
+
+/// The player's position every tick of one play session, saved to `res/ghosts/<name>.json`
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct GhostRun {
+    pub tick: f32,
+    pub frames: Vec<[f32; 3]>,
+    /// Path of the level the run was recorded on, `None` for unsaved levels<br>
+    /// Runs are only raced on the level they were recorded on
+    #[serde(default)]
+    pub level: Option<String>
+}
+
+impl GhostRun {
+    pub fn load(name: &str) -> Result<Self, String> {
+        let src = fs::read_to_string(format!("{}/{}.json", GHOST_DIRECTORY, name)).map_err(|e| format!("failed to open ghost {}: {}", name, e))?;
+        serde_json::from_str(&src).map_err(|e| format!("failed to parse ghost {}: {}", name, e))
+    }
+
+    pub fn save(&self, name: &str) -> Result<(), String> {
+        let name = common::plain_file_name(name)?;
+        fs::create_dir_all(GHOST_DIRECTORY).map_err(|e| format!("failed to save ghost: {}", e))?;
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(format!("{}/{}.json", GHOST_DIRECTORY, name), json).map_err(|e| format!("failed to save ghost: {}", e))
+    }
+
+    /// Names of the saved ghosts
+    pub fn list() -> Vec<String> {
+        let Ok(dir) = fs::read_dir(GHOST_DIRECTORY) else { return Vec::new(); };
+        let mut found = dir
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".json").map(str::to_string))
+            .collect::<Vec<_>>();
+        found.sort();
+        found
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.frames.len().saturating_sub(1) as f32 * self.tick
+    }
+
+    /// Where the run was `time` seconds in, it stays at the end once finished
+    pub fn position_at(&self, time: f32) -> Option<Vector3<f32>> {
+        let last = self.frames.len().checked_sub(1)?;
+        let frame = (time / self.tick).max(0.0);
+        let index = (frame as usize).min(last);
+        let next = (index + 1).min(last);
+        Some(common::vec3_mix(self.frames[index].into(), self.frames[next].into(), frame.fract()))
+    }
+}
+
+/// Recording of the current play session and the run being raced against
+#[derive(Default)]
+pub struct Ghosts {
+    /// Play sessions are only recorded while this is on
+    pub record: bool,
+    recording: Option<Vec<[f32; 3]>>,
+    record_timer: f32,
+    /// Play mode was on last frame
+    playing: bool,
+    /// The run recorded the last time play mode was left
+    pub last_run: Option<GhostRun>,
+    /// Drawn while playing, from the start of the play session
+    pub playback: Option<GhostRun>,
+    playback_time: f32,
+    /// Names of the saved runs of this level, read the first time they are listed
+    saved: Option<Vec<String>>
+}
+
+impl World {
+    /// Path ghost runs of this level are saved with
+    pub fn ghost_level(&self) -> Option<String> {
+        self.editor_data.save_to.as_ref().map(|path| path.to_string_lossy().into_owned())
+    }
+
+    /// Names of the saved runs that were recorded on this level
+    pub fn saved_ghosts(&mut self) -> &[String] {
+        let level = self.ghost_level();
+        self.ghosts.saved.get_or_insert_with(|| {
+            GhostRun::list().into_iter().filter(|name| GhostRun::load(name).is_ok_and(|run| run.level == level)).collect()
+        })
+    }
+
+    /// Save the last run as `name`, it shows up in `saved_ghosts` afterwards
+    pub fn save_last_run(&mut self, name: &str) -> Result<(), String> {
+        let run = self.ghosts.last_run.as_ref().ok_or(String::from("no run recorded yet"))?;
+        run.save(name)?;
+        self.ghosts.saved = None;
+        Ok(())
+    }
+
+    /// Record the player while playing and move the ghost along its run, a recording ends when play mode is left
+    pub fn update_ghost(&mut self, delta_time: f32) {
+        let level = self.ghost_level();
+        let ghosts = &mut self.ghosts;
+        if !self.do_game_logic {
+            ghosts.playing = false;
+            if let Some(frames) = ghosts.recording.take() {
+                if frames.len() > 1 {
+                    ghosts.last_run = Some(GhostRun { tick: GHOST_TICK, frames, level });
+                }
+            }
+            return;
+        }
+
+        // A run from another level would go through walls that aren't there
+        if ghosts.playback.as_ref().is_some_and(|run| run.level != level) {
+            ghosts.playback = None;
+        }
+
+        // Entering play mode restarts the ghost and starts a new recording
+        if !ghosts.playing {
+            ghosts.playing = true;
+            ghosts.playback_time = 0.0;
+            ghosts.record_timer = 0.0;
+            if ghosts.record {
+                ghosts.recording = Some(Vec::new());
+            }
+        }
+        ghosts.playback_time += delta_time;
+
+        if let Some(recording) = &mut ghosts.recording {
+            ghosts.record_timer -= delta_time;
+            while ghosts.record_timer <= 0.0 {
+                ghosts.record_timer += GHOST_TICK;
+                recording.push(self.player.position.into());
+            }
+        }
+    }
+
+    /// Draw the ghost see through, call after the scene while playing
+    pub unsafe fn render_ghost(&self, meshes: &MeshBank, programs: &mut ProgramBank, textures: &TextureBank, gl: &glow::Context) {
+        if !self.do_game_logic {
+            return;
+        }
+        let Some(position) = self.ghosts.playback.as_ref().and_then(|run| run.position_at(self.ghosts.playback_time)) else { return; };
+        let (Some(mesh), Some(material)) = (meshes.get("blank_cube"), self.scene.materials.get("default")) else { return; };
+
+        let transform = Matrix4::from_translation(position) * Matrix4::from_nonuniform_scale(GHOST_SIZE.x, GHOST_SIZE.y, GHOST_SIZE.z);
+        let data = MobileRenderData { flags: 0, transform, normal_matrix: normal_matrix(transform), draw: true, show_hidden: false, fade: 1.0, dissolve: 0.0 };
+
+        let flat_program = programs.get_mut("flat").unwrap();
+        gl.use_program(Some(flat_program.inner));
+        gl.enable(glow::BLEND);
+        gl.blend_color(0.0, 0.0, 0.0, GHOST_ALPHA);
+        gl.blend_func(glow::CONSTANT_ALPHA, glow::ONE_MINUS_CONSTANT_ALPHA);
+        // The ghost shouldn't hide what's behind it from the depth buffer
+        gl.depth_mask(false);
+
+        self.scene.render_single_mesh(&data, textures, flat_program, material, mesh, gl);
+
+        gl.depth_mask(true);
+        gl.disable(glow::BLEND);
+    }
+}
+
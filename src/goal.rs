@@ -0,0 +1,182 @@
+use std::path::Path;
+
+use cgmath::{MetricSpace, Vector3, Zero};
+use serde::{Deserialize, Serialize};
+
+use crate::{component::Component, events::EventKind, input::{Action, Input}, save::LevelData, stopwatch::StopwatchRun, wiring::TargetUndo, world::World};
+
+/// How close the player has to get to a goal's colliders to finish the level
+const GOAL_MARGIN: f32 = 0.05;
+/// Collectibles are picked up when the player's center is this close to the middle of the model
+pub const COLLECT_DISTANCE: f32 = 1.5;
+
+/// Finish line, touching the model's colliders ends the level once the requirements are met
+#[derive(Clone, Default, Serialize, Deserialize, Debug)]
+pub struct Goal {
+    /// Titles of objectives that have to be complete first
+    #[serde(default)]
+    pub objectives: Vec<String>,
+    /// Collectibles that have to be picked up first
+    #[serde(default)]
+    pub collectibles: u32
+}
+
+/// Shown by the play mode UI once a goal is reached
+#[derive(Clone, Debug)]
+pub struct LevelCompletion {
+    /// Seconds since play started
+    pub time: f32,
+    pub collected: usize,
+    pub collectibles: usize,
+    /// Level after this one in the playlist, the level restarts if `None`
+    pub next_level: Option<String>,
+    /// Stopwatch time in timed levels, see `stopwatch`
+    pub stopwatch: Option<StopwatchRun>
+}
+
+impl World {
+    pub fn get_goal(&self, model: usize) -> Option<Goal> {
+        self.models.get(model)?.as_ref()?.components.iter().find_map(|component| match component {
+            Component::Goal(goal) => Some(goal.clone()),
+            _ => None
+        })
+    }
+
+    pub fn set_goal(&mut self, model: usize, goal: Goal) {
+        let Some(target) = self.models[model].as_mut() else { return; };
+        for component in target.components.iter_mut() {
+            if let Component::Goal(old) = component {
+                *old = goal.clone();
+            }
+        }
+    }
+
+    /// How many models in the level have a collectible component
+    pub fn collectible_count(&self) -> usize {
+        self.models.iter().flatten().filter(|model| model.components.iter().any(|c| matches!(c, Component::Collectible))).count()
+    }
+
+    /// Why the player can't finish at `goal` yet, `None` if they can
+    pub fn goal_blocked(&self, goal: &Goal) -> Option<String> {
+        let missing: Vec<&str> = goal.objectives.iter()
+            .filter(|title| !self.completed_objectives.contains(title))
+            .map(String::as_str)
+            .collect();
+        if !missing.is_empty() {
+            return Some(format!("needs {}", missing.join(", ")));
+        }
+        if self.collected.len() < goal.collectibles as usize {
+            return Some(format!("needs {} more collectibles", goal.collectibles as usize - self.collected.len()));
+        }
+        None
+    }
+
+    /// The level after the one being played in the playlist, or the first entry if the level isn't in it
+    pub fn next_level(&self) -> Option<String> {
+        let current = self.editor_data.save_to.as_deref();
+        match current.and_then(|current| self.playlist.iter().position(|entry| Path::new(entry) == current)) {
+            Some(index) => self.playlist.get(index + 1).cloned(),
+            None => self.playlist.first().cloned()
+        }
+    }
+
+    /// Pick up collectibles the player is near and finish the level at a goal, only while playing
+    pub fn update_goals(&mut self) {
+        if !self.do_game_logic || self.completion.is_some() || self.player.dead {
+            return;
+        }
+
+        for model in 0..self.models.len() {
+            let Some(Some(target)) = self.models.get(model) else { continue; };
+            if target.hidden || self.collected.contains(&model) || !target.components.iter().any(|c| matches!(c, Component::Collectible)) {
+                continue;
+            }
+            if self.model_center(model).distance(self.player.position) < COLLECT_DISTANCE {
+                let label = target.label();
+                self.collected.push(model);
+                self.target_undo.push(TargetUndo::Hidden(model, false));
+                self.set_model_hidden(model, true);
+                self.log_event(EventKind::Collected, label, None, format!("{} of {}", self.collected.len(), self.collectible_count()));
+            }
+        }
+
+        let Some(player) = self.physical_scene.collider(self.player.collider) else { return; };
+        let reached = self.models.iter().flatten().find_map(|model| {
+            let goal = model.components.iter().find_map(|component| match component {
+                Component::Goal(goal) => Some(goal),
+                _ => None
+            })?;
+            let touching = model.colliders.iter().flatten()
+                .filter_map(|collider| self.physical_scene.collider(*collider))
+                .any(|collider| collider.touches(player, GOAL_MARGIN));
+            touching.then(|| (model.label(), goal.clone()))
+        });
+        let Some((label, goal)) = reached else {
+            self.goal_blocked_message = None;
+            return;
+        };
+
+        if let Some(reason) = self.goal_blocked(&goal) {
+            // Only said once per touch
+            if self.goal_blocked_message.as_ref() != Some(&reason) {
+                self.editor_data.show_debug.push(format!("can't finish yet, {}", reason));
+                self.goal_blocked_message = Some(reason);
+            }
+            return;
+        }
+        self.goal_blocked_message = None;
+        let completion = LevelCompletion {
+            time: self.level_time,
+            collected: self.collected.len(),
+            collectibles: self.collectible_count(),
+            next_level: self.next_level(),
+            stopwatch: self.stop_stopwatch().map(StopwatchRun::new)
+        };
+        self.log_event(EventKind::LevelCompleted, label, None, format!("{:.2}s", completion.time));
+        self.player.velocity = Vector3::zero();
+        self.completion = Some(completion);
+    }
+
+    /// Start the level over from the spawnpoint, with collectibles back in place
+    pub fn restart_level(&mut self) {
+        self.completion = None;
+        self.goal_blocked_message = None;
+        self.collected.clear();
+        self.level_time = 0.0;
+        self.reset_stopwatch();
+        self.reset_targets();
+        // Back to the spawnpoint even if the player would usually respawn where they last stood
+        self.player.last_ground = None;
+        self.respawn_player();
+        self.player.health = self.rules.max_health();
+        self.player.dead = false;
+    }
+
+    /// Leave the completion screen when jump is pressed, loading the next level or restarting this one
+    pub fn update_completion(&mut self, input: &Input) {
+        let Some(completion) = &self.completion else { return; };
+        if !input.action_just_pressed(Action::Jump) {
+            return;
+        }
+        let Some(next) = completion.next_level.clone() else {
+            self.restart_level();
+            return;
+        };
+        // Loading would throw away edits to the level being tested
+        if self.has_unsaved_changes() {
+            self.editor_data.show_debug.push(format!("save the level before going on to {}", next));
+            self.restart_level();
+            return;
+        }
+        match LevelData::read_from_file(Path::new(&next)) {
+            Ok(level) => {
+                self.load_new = Some(level);
+                self.editor_data.save_to = Some(next.into());
+            },
+            Err(e) => {
+                self.editor_data.show_debug.push(format!("failed to load {}: {}", next, e));
+                self.restart_level();
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use cgmath::{ortho, Angle, Deg, EuclideanSpace, InnerSpace, Matrix4, MetricSpace, Point3, Vector3, Zero};
+use glow::HasContext;
+use serde::{Deserialize, Serialize};
+
+use crate::{common, component::Component, flipbook::Flipbook, mesh::{flags, MeshBank}, render::BillboardRenderData, shader::ProgramBank, texture::TextureBank, world::{Model, World}};
+
+pub const DEFAULT_DRAW_DISTANCE: f32 = 40.0;
+/// Models are partly drawn for this far past their draw distance
+const FADE_RANGE: f32 = 4.0;
+/// Width and height of impostor textures
+const IMPOSTOR_RESOLUTION: i32 = 128;
+/// Impostors are taken again once the camera has swung this far around them
+const IMPOSTOR_ANGLE: Deg<f32> = Deg(15.0);
+const IMPOSTOR_FLAGS: u32 = flags::FULLBRIGHT | flags::CUTOUT;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Debug)]
+pub enum FarMode {
+    /// Dithered away
+    Fade,
+    /// Crossfaded to a picture of the model on a billboard
+    Impostor
+}
+
+/// How far from the camera a mobile model is drawn while playing, see `World::update_draw_distances`
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Debug)]
+pub struct DrawDistance {
+    pub distance: f32,
+    pub far: FarMode
+}
+
+impl DrawDistance {
+    pub fn new() -> Self {
+        Self { distance: DEFAULT_DRAW_DISTANCE, far: FarMode::Fade }
+    }
+
+    /// 1 within the draw distance, down to 0 over `FADE_RANGE` past it
+    fn visibility(&self, distance: f32) -> f32 {
+        ((self.distance + FADE_RANGE - distance) / FADE_RANGE).clamp(0.0, 1.0)
+    }
+}
+
+/// Picture of a model drawn in its place when it is far away<br>
+/// Only the model's meshes are in the picture, lighting is baked in when it is taken
+pub struct Impostor {
+    pub data: BillboardRenderData,
+    /// Slots in `World::models` get reused
+    model_id: u64,
+    /// Rotation and scale of the model when the picture was taken
+    shape: Matrix4<f32>,
+    /// Horizontal direction the picture was taken looking along, `None` if it needs to be taken again
+    direction: Option<Vector3<f32>>
+}
+
+impl Impostor {
+    fn new(model: &Model) -> Self {
+        Self {
+            data: BillboardRenderData {
+                flags: IMPOSTOR_FLAGS,
+                position: Vector3::zero(),
+                draw: true,
+                follow_vertical: false,
+                size: (1.0, 1.0),
+                show_hidden: false,
+                fixed_rotation: None,
+                fade: 0.0,
+                flipbook: Flipbook::default(),
+                frame: 0
+            },
+            model_id: model.id,
+            shape: common::mat4_remove_translation(model.transform),
+            direction: None
+        }
+    }
+}
+
+pub fn texture_name(model: usize) -> String {
+    format!("Impostor_{}", model)
+}
+
+fn draw_distance(model: &Model) -> Option<DrawDistance> {
+    model.components.iter().find_map(|component| match component {
+        Component::DrawDistance(settings) => Some(*settings),
+        _ => None
+    })
+}
+
+impl World {
+    pub fn get_draw_distance(&self, model: usize) -> Option<DrawDistance> {
+        draw_distance(self.models.get(model)?.as_ref()?)
+    }
+
+    pub fn set_draw_distance(&mut self, model: usize, settings: DrawDistance) {
+        let Some(target) = self.models[model].as_mut() else { return; };
+        for component in target.components.iter_mut() {
+            if let Component::DrawDistance(old) = component {
+                *old = settings;
+            }
+        }
+    }
+
+    /// Fade out far models and crossfade impostors in, everything is fully drawn while editing
+    pub fn update_draw_distances(&mut self) {
+        let camera = self.scene.camera.pos.to_vec();
+        let playing = !self.editor_data.active;
+        let mut kept = Vec::new();
+
+        for (index, model) in self.models.iter().enumerate() {
+            let Some(model) = model else { continue; };
+            // Static meshes are drawn instanced, foreground ones are always close
+            if !model.mobile || model.foreground { continue; }
+            let Some(settings) = draw_distance(model) else { continue; };
+
+            let (offset, half_extents) = model.extents.unwrap_or((Vector3::zero(), common::vec3_all(0.5)));
+            let center = common::translation(model.transform) + offset;
+            let visibility = if playing { settings.visibility(center.distance(camera)) } else { 1.0 };
+
+            for (renderable, data_index) in model.render.iter().zip(model.renderable_indices.iter()) {
+                if let Some(mesh) = renderable.get_mesh() {
+                    self.scene.mobile_meshes.get_mut(&mesh).unwrap()[*data_index].fade = visibility;
+                }
+                if let Some(texture) = renderable.billboard_texture() {
+                    self.scene.billboards.get_mut(&texture).unwrap()[*data_index].fade = visibility;
+                }
+            }
+
+            if settings.far == FarMode::Impostor {
+                let impostor = self.scene.impostors.entry(index).or_insert_with(|| Impostor::new(model));
+                let shape = common::mat4_remove_translation(model.transform);
+                if impostor.model_id != model.id || impostor.shape != shape {
+                    *impostor = Impostor::new(model);
+                }
+                let radius = half_extents.magnitude().max(0.01);
+                impostor.data.position = center;
+                impostor.data.size = (radius * 2.0, radius * 2.0);
+                impostor.data.draw = !model.hidden;
+                impostor.data.fade = 1.0 - visibility;
+                kept.push(index);
+            }
+        }
+
+        self.scene.impostors.retain(|index, _| kept.contains(index));
+    }
+
+    /// Take the pictures of showing impostors that are out of date, call before the scene is rendered
+    pub unsafe fn prepare_impostors(&mut self, meshes: &MeshBank, programs: &mut ProgramBank, textures: &mut TextureBank, gl: &glow::Context) {
+        let mut look = self.scene.camera.direction;
+        look.y = 0.0;
+        // Looking straight up or down, the old pictures will do
+        if look.magnitude2() < 0.0001 {
+            return;
+        }
+        let look = look.normalize();
+
+        let stale: Vec<usize> = self.scene.impostors.iter()
+            .filter(|(_, impostor)| impostor.data.draw && impostor.data.fade > 0.0)
+            .filter(|(_, impostor)| impostor.direction.map_or(true, |direction| direction.dot(look) < IMPOSTOR_ANGLE.cos()))
+            .map(|(index, _)| *index)
+            .collect();
+
+        for index in stale {
+            self.take_impostor(index, look, meshes, programs, textures, gl);
+        }
+    }
+
+    /// Render model `index` alone into a new texture for its impostor
+    unsafe fn take_impostor(&mut self, index: usize, look: Vector3<f32>, meshes: &MeshBank, programs: &mut ProgramBank, textures: &mut TextureBank, gl: &glow::Context) {
+        let Some(model) = self.models[index].as_ref() else { return; };
+        let Some(impostor) = self.scene.impostors.get(&index) else { return; };
+        let center = impostor.data.position;
+        let radius = impostor.data.size.0 / 2.0;
+
+        // Fits the model's bounding sphere, looking the way billboards face
+        let eye = Point3::from_vec(center - look * radius * 2.0);
+        let view = Matrix4::look_at_rh(eye, Point3::from_vec(center), Vector3::unit_y());
+        let projection = ortho(-radius, radius, -radius, radius, radius, radius * 3.0);
+
+        let color = gl.create_texture().unwrap();
+        gl.bind_texture(glow::TEXTURE_2D, Some(color));
+        gl.tex_image_2d(
+            glow::TEXTURE_2D, 0, glow::RGBA8 as i32,
+            IMPOSTOR_RESOLUTION, IMPOSTOR_RESOLUTION,
+            0, glow::RGBA, glow::UNSIGNED_BYTE,
+            glow::PixelUnpackData::Slice(None)
+        );
+        let depth = gl.create_renderbuffer().unwrap();
+        gl.bind_renderbuffer(glow::RENDERBUFFER, Some(depth));
+        gl.renderbuffer_storage(glow::RENDERBUFFER, glow::DEPTH_COMPONENT24, IMPOSTOR_RESOLUTION, IMPOSTOR_RESOLUTION);
+
+        let fbo = gl.create_framebuffer().unwrap();
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+        gl.framebuffer_texture_2d(glow::FRAMEBUFFER, glow::COLOR_ATTACHMENT0, glow::TEXTURE_2D, Some(color), 0);
+        gl.framebuffer_renderbuffer(glow::FRAMEBUFFER, glow::DEPTH_ATTACHMENT, glow::RENDERBUFFER, Some(depth));
+
+        if gl.check_framebuffer_status(glow::FRAMEBUFFER) == glow::FRAMEBUFFER_COMPLETE {
+            gl.viewport(0, 0, IMPOSTOR_RESOLUTION, IMPOSTOR_RESOLUTION);
+            // Cutout leaves out everything the model doesn't cover
+            gl.clear_color(0.0, 0.0, 0.0, 0.0);
+            gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
+            gl.enable(glow::DEPTH_TEST);
+            self.scene.render_model_only(model, eye, view, projection, meshes, programs, textures, gl);
+        } else {
+            self.editor_data.show_debug.push(format!("failed to take impostor of model {}", index));
+        }
+
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        gl.viewport(0, 0, self.scene.window_size.0 as i32, self.scene.window_size.1 as i32);
+        gl.delete_framebuffer(fbo);
+        gl.delete_renderbuffer(depth);
+
+        textures.insert_rendered(&texture_name(index), color, IMPOSTOR_RESOLUTION as u32, IMPOSTOR_RESOLUTION as u32, gl);
+        self.scene.impostors.get_mut(&index).unwrap().direction = Some(look);
+    }
+}
@@ -1,6 +1,182 @@
-use std::collections::HashMap;
+use std::{cell::RefCell, collections::HashMap};
 
-use winit::{event::MouseButton, keyboard::Key};
+use serde::{Deserialize, Serialize};
+use winit::{event::{ElementState, Ime, MouseButton, MouseScrollDelta, WindowEvent}, keyboard::{Key, NamedKey}, platform::modifier_supplement::KeyEventExtModifierSupplement};
+
+/// Named keys bindings can use, anything else has to be a single character
+const NAMED_KEYS: &[(&str, NamedKey)] = &[
+    ("Space", NamedKey::Space), ("Escape", NamedKey::Escape), ("Enter", NamedKey::Enter), ("Tab", NamedKey::Tab),
+    ("Delete", NamedKey::Delete), ("Backspace", NamedKey::Backspace), ("Insert", NamedKey::Insert),
+    ("Home", NamedKey::Home), ("End", NamedKey::End), ("PageUp", NamedKey::PageUp), ("PageDown", NamedKey::PageDown),
+    ("ArrowUp", NamedKey::ArrowUp), ("ArrowDown", NamedKey::ArrowDown), ("ArrowLeft", NamedKey::ArrowLeft), ("ArrowRight", NamedKey::ArrowRight),
+    ("Shift", NamedKey::Shift), ("Alt", NamedKey::Alt),
+    ("F1", NamedKey::F1), ("F2", NamedKey::F2), ("F3", NamedKey::F3), ("F4", NamedKey::F4), ("F5", NamedKey::F5), ("F6", NamedKey::F6),
+    ("F7", NamedKey::F7), ("F8", NamedKey::F8), ("F9", NamedKey::F9), ("F10", NamedKey::F10), ("F11", NamedKey::F11), ("F12", NamedKey::F12)
+];
+
+/// Something the user does with the keyboard, see `KeyBindings`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    /// Editor and spectator camera only
+    MoveUp,
+    MoveDown,
+    Jump,
+    /// Held to crouch while playing, the player stays crouched until there is room to stand
+    Crouch,
+    /// Held to run faster while playing, in levels that allow it
+    Sprint,
+    /// Press the button the player is looking at, see `button`
+    Interact,
+    ToggleEditor,
+    /// Detaches the camera from the player while playing, pressing it again returns to the player
+    Spectate,
+    /// Unlocks the cursor while playing, returns a spectator camera to the player, drops the picked material or stamp while editing
+    Cancel,
+    CycleOrthoView,
+    /// Turn the editor camera to an axis aligned view, see `ViewPreset`
+    ViewFront,
+    ViewBack,
+    ViewRight,
+    ViewLeft,
+    ViewTop,
+    ViewBottom,
+    ToggleGizmoSpace,
+    MoveTool,
+    ScaleTool,
+    /// Drag single faces of the selected brush
+    FaceTool,
+    /// Held while dragging with the movement gizmo to snap the nearest corner onto a corner of other brushes and models
+    VertexSnap,
+    DeleteSelection,
+    StampSelection,
+    RotateStamp,
+    Duplicate,
+    CopySelection,
+    PasteClipboard,
+    LogMeshes,
+    DebugBrushes,
+    /// Saved to `res/screenshots`, see `readback`
+    Screenshot
+}
+
+impl Action {
+    pub const ALL: [Action; 34] = [
+        Self::MoveForward, Self::MoveBack, Self::MoveLeft, Self::MoveRight, Self::MoveUp, Self::MoveDown, Self::Jump, Self::Crouch, Self::Sprint, Self::Interact,
+        Self::ToggleEditor, Self::Spectate, Self::Cancel, Self::CycleOrthoView, Self::ToggleGizmoSpace, Self::MoveTool, Self::ScaleTool, Self::FaceTool, Self::VertexSnap,
+        Self::ViewFront, Self::ViewBack, Self::ViewRight, Self::ViewLeft, Self::ViewTop, Self::ViewBottom,
+        Self::DeleteSelection, Self::StampSelection, Self::RotateStamp, Self::Duplicate, Self::CopySelection, Self::PasteClipboard, Self::LogMeshes, Self::DebugBrushes, Self::Screenshot
+    ];
+
+    fn default_bindings(&self) -> Vec<KeyBinding> {
+        let keys: &[&str] = match self {
+            Self::MoveForward => &["w"],
+            Self::MoveBack => &["s"],
+            Self::MoveLeft => &["a"],
+            Self::MoveRight => &["d"],
+            Self::MoveUp => &["e"],
+            Self::MoveDown => &["q"],
+            Self::Jump => &["Space"],
+            Self::Crouch => &["c"],
+            Self::Sprint => &["Shift"],
+            Self::Interact => &["e"],
+            Self::ToggleEditor => &["ctrl+e"],
+            Self::Spectate => &["f"],
+            Self::Cancel => &["Escape"],
+            Self::CycleOrthoView => &["o"],
+            Self::ViewFront => &["ctrl+1"],
+            Self::ViewBack => &["ctrl+2"],
+            Self::ViewRight => &["ctrl+3"],
+            Self::ViewLeft => &["ctrl+4"],
+            Self::ViewTop => &["ctrl+5"],
+            Self::ViewBottom => &["ctrl+6"],
+            Self::ToggleGizmoSpace => &["l"],
+            Self::MoveTool => &["1"],
+            Self::ScaleTool => &["2"],
+            Self::FaceTool => &["3"],
+            Self::VertexSnap => &["Alt"],
+            Self::DeleteSelection => &["Delete", "Backspace"],
+            Self::StampSelection => &["t"],
+            Self::RotateStamp => &["r"],
+            Self::Duplicate => &["ctrl+d"],
+            Self::CopySelection => &["ctrl+c"],
+            Self::PasteClipboard => &["ctrl+v"],
+            Self::LogMeshes => &["ctrl+m"],
+            Self::DebugBrushes => &["ctrl+b"],
+            Self::Screenshot => &["F12"]
+        };
+        keys.iter().map(|key| KeyBinding::try_from(key.to_string()).unwrap()).collect()
+    }
+}
+
+/// A key, written like `w`, `Space` or `ctrl+d` in the config<br>
+/// Bindings without ctrl don't fire while it is held, so ctrl+d doesn't also move right
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(try_from = "String", into = "String")]
+pub struct KeyBinding {
+    pub key: Key,
+    pub ctrl: bool
+}
+
+impl TryFrom<String> for KeyBinding {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let (ctrl, name) = match value.strip_prefix("ctrl+") {
+            Some(name) => (true, name),
+            None => (false, value.as_str())
+        };
+        let key = if let Some((_, named)) = NAMED_KEYS.iter().find(|(key_name, _)| *key_name == name) {
+            Key::Named(*named)
+        } else if name.chars().count() == 1 {
+            // Keys are reported without modifiers, so letters are always lowercase
+            Key::Character(name.to_lowercase().into())
+        } else {
+            return Err(format!("unknown key {}", name));
+        };
+        Ok(Self { key, ctrl })
+    }
+}
+
+impl From<KeyBinding> for String {
+    fn from(binding: KeyBinding) -> Self {
+        let name = match &binding.key {
+            Key::Named(named) => NAMED_KEYS.iter().find(|(_, key)| key == named).map_or("", |(name, _)| name).to_string(),
+            Key::Character(c) => c.to_string(),
+            _ => String::new()
+        };
+        if binding.ctrl { format!("ctrl+{}", name) } else { name }
+    }
+}
+
+/// Keys for every `Action`, actions missing from the config keep their default keys
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(from = "HashMap<Action, Vec<KeyBinding>>", into = "HashMap<Action, Vec<KeyBinding>>")]
+pub struct KeyBindings(HashMap<Action, Vec<KeyBinding>>);
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self(Action::ALL.iter().map(|action| (*action, action.default_bindings())).collect())
+    }
+}
+
+impl From<HashMap<Action, Vec<KeyBinding>>> for KeyBindings {
+    fn from(mut bindings: HashMap<Action, Vec<KeyBinding>>) -> Self {
+        for action in Action::ALL {
+            bindings.entry(action).or_insert_with(|| action.default_bindings());
+        }
+        Self(bindings)
+    }
+}
+
+impl From<KeyBindings> for HashMap<Action, Vec<KeyBinding>> {
+    fn from(bindings: KeyBindings) -> Self {
+        bindings.0
+    }
+}
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum KeyState {
@@ -16,7 +192,17 @@ pub struct Input {
     pub mouse_buttons: HashMap<MouseButton, KeyState>,
     pub needs_update: bool,
     pub mouse_pos: (f64, f64),
-    pub scroll: f32
+    pub scroll: f32,
+    /// Text typed this frame, including text committed by an IME
+    pub text: String,
+    /// Text the IME is currently composing, not yet committed
+    pub ime_preedit: String,
+    /// A text field has keyboard focus, regular key queries report nothing pressed while this is set
+    pub keyboard_captured: bool,
+    /// Created the first time it's used, `arboard` keeps ownership of copied text alive on some platforms
+    clipboard: RefCell<Option<arboard::Clipboard>>,
+    /// Set from the user config
+    pub bindings: KeyBindings
 }
 
 impl Input {
@@ -26,7 +212,12 @@ impl Input {
             mouse_buttons: HashMap::new(),
             needs_update: false,
             mouse_pos: (0.0, 0.0),
-            scroll: 0.0
+            scroll: 0.0,
+            text: String::new(),
+            ime_preedit: String::new(),
+            keyboard_captured: false,
+            clipboard: RefCell::new(None),
+            bindings: KeyBindings::default()
         }
     }
 
@@ -36,6 +227,46 @@ impl Input {
         self.needs_update = true;
     }
 
+    /// Call with the text of a pressed key event, control characters are ignored
+    pub fn on_text(&mut self, text: &str) {
+        // Keys typed while composing belong to the IME
+        if !self.ime_preedit.is_empty() {
+            return;
+        }
+
+        self.text.extend(text.chars().filter(|c| !c.is_control()));
+        self.needs_update = true;
+    }
+
+    pub fn on_ime(&mut self, ime: &Ime) {
+        match ime {
+            Ime::Preedit(text, _) => self.ime_preedit = text.clone(),
+            Ime::Commit(text) => {
+                self.ime_preedit.clear();
+                self.on_text(text);
+            },
+            Ime::Disabled => self.ime_preedit.clear(),
+            Ime::Enabled => ()
+        }
+    }
+
+    /// Returns false if the system clipboard couldn't be opened
+    pub fn copy_to_clipboard(&self, text: &str) -> bool {
+        self.with_clipboard(|clipboard| clipboard.set_text(text).is_ok()).unwrap_or(false)
+    }
+
+    pub fn paste_from_clipboard(&self) -> Option<String> {
+        self.with_clipboard(|clipboard| clipboard.get_text().ok()).flatten()
+    }
+
+    fn with_clipboard<T>(&self, f: impl FnOnce(&mut arboard::Clipboard) -> T) -> Option<T> {
+        let mut clipboard = self.clipboard.borrow_mut();
+        if clipboard.is_none() {
+            *clipboard = arboard::Clipboard::new().ok();
+        }
+        clipboard.as_mut().map(f)
+    }
+
     pub fn on_mouse_moved(&mut self, x: f64, y: f64) {
         self.mouse_pos = (x, y);
     }
@@ -60,6 +291,30 @@ impl Input {
         self.needs_update = true;
     }
 
+    /// Feed the input events of a window that has nothing else to do with them
+    pub fn on_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => match event.state {
+                ElementState::Pressed => {
+                    self.on_key_pressed(event.key_without_modifiers());
+                    if let Some(text) = &event.text {
+                        self.on_text(text);
+                    }
+                },
+                ElementState::Released => self.on_key_released(event.key_without_modifiers())
+            },
+            WindowEvent::Ime(ime) => self.on_ime(ime),
+            WindowEvent::MouseInput { state: ElementState::Pressed, button, .. } => self.on_mouse_button_pressed(*button),
+            WindowEvent::MouseInput { state: ElementState::Released, button, .. } => self.on_mouse_button_released(*button),
+            WindowEvent::MouseWheel { delta, .. } => self.set_scroll(match delta {
+                MouseScrollDelta::LineDelta(_, y) => -*y * 40.0,
+                MouseScrollDelta::PixelDelta(px) => px.y as f32
+            }),
+            WindowEvent::CursorMoved { position, .. } => self.on_mouse_moved(position.x, position.y),
+            _ => ()
+        }
+    }
+
     /// Call every frame after this struct is done being used, resets `JustPressed` keystates to `Pressed`
     pub fn update(&mut self) {
         if self.needs_update {
@@ -77,12 +332,23 @@ impl Input {
                 }
             }
             self.scroll = 0.0;
+            self.text.clear();
             self.needs_update = false;
         }
     }
 
     /// Return true if `key` is `Pressed` or `JustPressed`
     pub fn get_key_pressed(&self, key: Key) -> bool {
+        !self.keyboard_captured && self.get_text_key_pressed(key)
+    }
+
+    /// Return true only if `key` is `JustPressed`
+    pub fn get_key_just_pressed(&self, key: Key) -> bool {
+        !self.keyboard_captured && self.get_text_key_just_pressed(key)
+    }
+
+    /// `get_key_pressed` for text fields, ignores `keyboard_captured`
+    pub fn get_text_key_pressed(&self, key: Key) -> bool {
         if let Some(state) = self.keys.get(&key) {
             return *state == KeyState::JustPressed || *state == KeyState::Pressed;
         }
@@ -90,8 +356,8 @@ impl Input {
         false
     }
 
-    /// Return true only if `key` is `JustPressed`
-    pub fn get_key_just_pressed(&self, key: Key) -> bool {
+    /// `get_key_just_pressed` for text fields, ignores `keyboard_captured`
+    pub fn get_text_key_just_pressed(&self, key: Key) -> bool {
         if let Some(state) = self.keys.get(&key) {
             return *state == KeyState::JustPressed;
         }
@@ -99,8 +365,32 @@ impl Input {
         false
     }
 
+    /// A key bound to `action` is held
+    pub fn action_pressed(&self, action: Action) -> bool {
+        self.binding_matches(action, |key| self.get_key_pressed(key))
+    }
+
+    /// A key bound to `action` was pressed this frame
+    pub fn action_just_pressed(&self, action: Action) -> bool {
+        self.binding_matches(action, |key| self.get_key_just_pressed(key))
+    }
+
+    /// Name of the first key bound to `action`, like `e` or `ctrl+d`
+    pub fn action_key_name(&self, action: Action) -> String {
+        self.bindings.0.get(&action).and_then(|bindings| bindings.first()).map_or(String::from("unbound"), |binding| String::from(binding.clone()))
+    }
+
+    fn binding_matches(&self, action: Action, key_state: impl Fn(Key) -> bool) -> bool {
+        let ctrl = self.get_key_pressed(Key::Named(NamedKey::Control));
+        self.bindings.0.get(&action).is_some_and(|bindings| bindings.iter().any(|binding| binding.ctrl == ctrl && key_state(binding.key.clone())))
+    }
+
     /// Return true only if `key` is `Released`
     pub fn get_key_released(&self, key: Key) -> bool {
+        if self.keyboard_captured {
+            return true;
+        }
+
         if let Some(state) = self.keys.get(&key) {
             return *state == KeyState::Released || *state == KeyState::JustReleased;
         }
@@ -0,0 +1,126 @@
+use std::fs;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Vector3};
+use serde::Deserialize;
+
+use crate::{common, mesh::{Mesh, MeshBank, VertexComponent}, render::Scene};
+
+/// Static meshes with detail levels are sorted into levels again after the camera moves this far
+pub const LOD_REBUCKET_DISTANCE: f32 = 2.0;
+
+/// A lower detail version of a mesh, drawn from `distance` away until the next level's distance
+#[derive(Clone, Debug)]
+pub struct MeshLod {
+    pub mesh: String,
+    pub distance: f32
+}
+
+/// A level in `res/models/<model>.lod.json`, the file is a list of these
+#[derive(Deserialize, Debug)]
+struct LodFileEntry {
+    /// .obj in `res/models`, made of the same parts in the same order as the full detail model
+    model: String,
+    distance: f32
+}
+
+impl MeshBank {
+    /// Draw `lod` in place of `name` from `distance` away
+    pub fn add_lod(&mut self, name: &str, lod: &str, distance: f32) {
+        let levels = self.lods.entry(name.to_string()).or_default();
+        if levels.iter().any(|level| level.mesh == lod) {
+            return;
+        }
+        levels.push(MeshLod { mesh: lod.to_string(), distance });
+        levels.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    }
+
+    pub fn has_lods(&self, name: &str) -> bool {
+        self.lods.get(name).is_some_and(|levels| !levels.is_empty())
+    }
+
+    /// Meshes of every detail level of `name`, starting with `name` itself
+    pub fn lod_meshes(&self, name: &str) -> Vec<String> {
+        let mut names = vec![name.to_string()];
+        if let Some(levels) = self.lods.get(name) {
+            names.extend(levels.iter().map(|level| level.mesh.clone()));
+        }
+        names
+    }
+
+    /// Index into `lod_meshes` of the level drawn at `distance`
+    pub fn lod_level(&self, name: &str, distance: f32) -> usize {
+        self.lods.get(name).map_or(0, |levels| levels.iter().take_while(|level| distance >= level.distance).count())
+    }
+
+    /// Name of the mesh drawn for `name` at `distance`, falls back to `name` if the level's mesh isn't loaded
+    pub fn lod_mesh_name<'a>(&'a self, name: &'a str, distance: f32) -> &'a str {
+        let level = self.lod_level(name, distance);
+        if level == 0 {
+            return name;
+        }
+        let lod = &self.lods[name][level - 1].mesh;
+        if self.get(lod).is_some() { lod.as_str() } else { name }
+    }
+
+    /// The mesh drawn for `name` at `distance`
+    pub fn lod_mesh(&self, name: &str, distance: f32) -> Option<&Mesh> {
+        self.get(self.lod_mesh_name(name, distance))
+    }
+
+    /// Load the detail levels listed next to `file`, each part `File_{name}{i}` gets the matching part of each level<br>
+    /// Levels are loaded with the same vertex color as the full detail model, colored copies get their own levels<br>
+    /// Returns problems with the file, a model without one has no levels
+    pub fn load_lods(&mut self, file: &str, name: &str, color: [VertexComponent; 3], gl: &glow::Context) -> Vec<String> {
+        let Ok(src) = fs::read_to_string(format!("res/models/{}.lod.json", file)) else { return Vec::new(); };
+        let entries: Vec<LodFileEntry> = match serde_json::from_str(&src) {
+            Ok(entries) => entries,
+            Err(e) => return vec![format!("failed to parse detail levels of {}: {}", file, e)]
+        };
+
+        let mut problems = Vec::new();
+        let parts = (0..).take_while(|i| self.get(&format!("File_{}{}", name, i)).is_some()).count();
+        for entry in entries {
+            let lod_parts = match Mesh::load_from_obj_vcolor(&entry.model, color[0], color[1], color[2], gl) {
+                Ok(lod_parts) => lod_parts,
+                Err(e) => {
+                    problems.push(format!("failed to load detail level {} of {}: {}", entry.model, file, e));
+                    continue;
+                }
+            };
+            if lod_parts.len() != parts {
+                problems.push(format!("detail level {} has {} parts but {} has {}, skipped", entry.model, lod_parts.len(), file, parts));
+                continue;
+            }
+            let lod = if name == file { entry.model.clone() } else { format!("{}_{}", entry.model, name) };
+            for (i, mesh) in lod_parts.into_iter().enumerate() {
+                let lod_name = format!("File_{}{}", lod, i);
+                self.add(mesh, &lod_name);
+                self.add_lod(&format!("File_{}{}", name, i), &lod_name, entry.distance);
+            }
+        }
+        problems
+    }
+}
+
+impl Scene {
+    /// Distance detail levels are picked by, orthographic views always get full detail
+    pub fn lod_distance(&self, transform: Matrix4<f32>) -> f32 {
+        if self.camera.ortho.is_some() {
+            return 0.0;
+        }
+        (common::translation(transform) - self.camera.pos.to_vec()).magnitude()
+    }
+
+    /// Sort static meshes with detail levels again if the camera has moved far enough since they last were
+    pub fn update_lods(&mut self, meshes: &MeshBank) {
+        let camera: Vector3<f32> = self.camera.pos.to_vec();
+        if self.lod_origin.is_some_and(|origin| (origin - camera).magnitude() < LOD_REBUCKET_DISTANCE) {
+            return;
+        }
+        self.lod_origin = Some(camera);
+        let lodded: Vec<String> = self.static_meshes.keys().filter(|name| meshes.has_lods(name)).cloned().collect();
+        for name in lodded.iter() {
+            self.mark_static(name);
+        }
+    }
+}
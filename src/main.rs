@@ -1,39 +1,162 @@
-use std::{sync::{Mutex, Arc}, thread, time::{Duration, Instant}};
+use std::{mem, path::{Path, PathBuf}, sync::{Mutex, Arc}, thread, time::{Duration, Instant}};
 
 use cgmath::{vec3, Matrix, Matrix4, SquareMatrix, Vector3, Zero};
 use glow::{HasContext};
-use glutin::surface::GlSurface;
-use winit::{event::{DeviceEvent, ElementState, Event, MouseButton, MouseScrollDelta, WindowEvent}, keyboard::{Key, NamedKey}, platform::modifier_supplement::KeyEventExtModifierSupplement, window::CursorGrabMode};
+use glutin::{prelude::PossiblyCurrentGlContext, surface::GlSurface};
+use winit::{dpi::{PhysicalPosition, PhysicalSize}, event::{DeviceEvent, ElementState, Event, MouseButton, MouseScrollDelta, WindowEvent}, keyboard::{Key, NamedKey}, platform::modifier_supplement::KeyEventExtModifierSupplement, window::CursorGrabMode};
 
-use crate::{collision::{ColliderShape, RaycastParameters}, component::{Component, Trigger, TriggerType}, mesh::flags, render::CameraControlScheme, world::{Model, PlayerMovementMode, Renderable, World}};
+use crate::{audio::MusicManager, collision::{ColliderShape, RaycastParameters}, component::{Component, Trigger, TriggerType}, flipbook::Flipbook, input::Action, mesh::{flags, BrushFaces}, render::CameraControlScheme, save::LevelData, tint::BrushTint, world::{Model, PlayerMovementMode, Renderable, World}};
 
 mod ui;
+mod diff;
 mod mesh;
+mod mover;
 mod save;
+mod tint;
+mod trim;
+mod post;
+mod audio;
+mod events;
 mod input;
+mod notes;
+mod rules;
 mod world;
+mod spawn;
+mod stamp;
+mod ghost;
+mod goal;
+mod merge;
+mod budget;
+mod button;
 mod common;
+mod config;
 mod dialog;
+mod export;
 mod prefab;
 mod render;
+mod readback;
+mod reference;
+mod script;
+mod session;
 mod shader;
 mod window;
+mod wiring;
 mod effects;
 mod texture;
+mod migrate;
+mod impostor;
+mod generate;
+mod footstep;
+mod stopwatch;
+mod dissolve;
+mod objective;
 mod collision;
+mod traversal;
+mod connection;
+mod clipboard;
+mod spectator;
+mod visibility;
+mod regression;
+mod frames;
+mod flipbook;
+mod lod;
+mod shadow;
+mod cluster;
+mod feed;
+mod reflection;
+mod snap;
+mod target;
 mod component;
 
 const MS_PER_FRAME: u64 = 8;
 
 fn main() {
-    let (mut gl, gl_surface, gl_context, window, event_loop) = unsafe { window::create_gl_context() };
+    // `viceptica diff old.json new.json` prints what changed between two levels
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() == 4 && args[1] == "diff" {
+        match diff::diff_level_files(Path::new(&args[2]), Path::new(&args[3])) {
+            Ok(report) if report.is_empty() => println!("levels are identical"),
+            Ok(report) => report.iter().for_each(|line| println!("{}", line)),
+            Err(e) => eprintln!("{}", e)
+        }
+        return;
+    }
+    // `viceptica export out_dir level.json...` packs levels and their assets for players
+    if args.len() >= 4 && args[1] == "export" {
+        let levels: Vec<PathBuf> = args[3..].iter().map(PathBuf::from).collect();
+        match export::export_project(&levels, Path::new(&args[2])) {
+            Ok(report) => println!("{}", report.summary()),
+            Err(e) => eprintln!("{}", e)
+        }
+        return;
+    }
+
+    // `viceptica render-test [--bless]` compares renders of a reference level against golden images
+    if args.len() >= 2 && args[1] == "render-test" {
+        let bless = args.iter().any(|arg| arg == "--bless");
+        match unsafe { regression::run(bless) } {
+            Ok(report) => report.iter().for_each(|line| println!("{}", line)),
+            Err(report) => {
+                report.iter().for_each(|line| eprintln!("{}", line));
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // `viceptica render-frames level.json out_dir (--ghost name | --path name) [--size 1280x720] [--fps 30]` renders numbered PNGs offline
+    if args.len() >= 4 && args[1] == "render-frames" {
+        let option = |name: &str| args.iter().position(|arg| arg == name).and_then(|i| args.get(i + 1));
+        let source = match (option("--ghost"), option("--path")) {
+            (Some(ghost), _) => ghost::GhostRun::load(ghost).map(frames::FrameSource::Ghost),
+            (None, Some(path)) => frames::CameraPath::load(path).map(frames::FrameSource::Path),
+            (None, None) => Err(String::from("give a ghost to replay with --ghost or a camera path with --path"))
+        };
+        let size = match option("--size") {
+            Some(size) => size.split_once('x').and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?))),
+            None => Some(frames::DEFAULT_FRAME_SIZE)
+        };
+        let fps = option("--fps").map_or(Some(frames::DEFAULT_FPS), |fps| fps.parse().ok());
+        let result = match (source, size, fps) {
+            (Ok(source), Some(size), Some(fps)) => {
+                let export = frames::FrameExport { level: PathBuf::from(&args[2]), out_dir: PathBuf::from(&args[3]), source, size, fps };
+                unsafe { export.run() }
+            },
+            (Err(e), ..) => Err(e),
+            (_, None, _) => Err(String::from("--size should look like 1280x720")),
+            (.., None) => Err(String::from("--fps should be a number"))
+        };
+        match result {
+            Ok(summary) => println!("{}", summary),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let (mut gl, gl_surface, gl_context, window, event_loop) = unsafe { window::create_gl_context(true) };
+    let capabilities = unsafe { window::GpuCapabilities::detect(&gl) };
+    println!("{}", capabilities.summary());
+    let missing = capabilities.missing_requirements();
     let mut program_bank = shader::ProgramBank::new();
+    program_bank.version = capabilities.shader_version();
     let mut texture_bank = texture::TextureBank::new();
     let mut mesh_bank = mesh::MeshBank::new();
     let mut input = input::Input::new();
     let mut world = world::World::new(&gl);
     let mut ui = ui::implement::VicepticaUI::new(&gl);
     world.scene.ui_vao = Some(ui.inner.vao);
+    ui.config.preferences.apply(&mut world, &mut ui.inner);
+    input.bindings = ui.config.key_bindings.clone();
+    world.editor_data.increment = ui.config.preferences.default_increment;
+    world.scene.safe_mode = args.iter().any(|arg| arg == "--safe-mode") || !missing.is_empty();
+    if !missing.is_empty() {
+        world.editor_data.show_debug.push(format!("GPU is missing {}, starting in safe mode", missing.join(", ")));
+    } else if world.scene.safe_mode {
+        world.editor_data.show_debug.push(String::from("Starting in safe mode"));
+    }
     let opengl_debug = Arc::new(Mutex::new(Vec::new()));
 
     let tokenizer = dialog::parse::DialogTokenizer::new(include_str!("../res/dialog/dialog_spec.dlg").to_string());
@@ -46,17 +169,19 @@ fn main() {
     }
 
     unsafe {
-        gl.enable(glow::DEBUG_OUTPUT);
-        let debug_clone = opengl_debug.clone();
-        gl.debug_message_callback(move |_, _, _, severity, msg| {
-            if severity == glow::DEBUG_SEVERITY_HIGH {
-                debug_clone.lock().unwrap().push(format!("[OpenGL, high severity] {}", msg));
-                println!("[OpenGL, high severity] {}", msg);
-            } else if severity == glow::DEBUG_SEVERITY_MEDIUM {
-                //debug_clone.lock().unwrap().push(format!("[OpenGL, medium severity] {}", msg));
-                //println!("[OpenGL, medium severity] {}", msg);
-            }
-        });
+        if capabilities.debug_output {
+            gl.enable(glow::DEBUG_OUTPUT);
+            let debug_clone = opengl_debug.clone();
+            gl.debug_message_callback(move |_, _, _, severity, msg| {
+                if severity == glow::DEBUG_SEVERITY_HIGH {
+                    debug_clone.lock().unwrap().push(format!("[OpenGL, high severity] {}", msg));
+                    println!("[OpenGL, high severity] {}", msg);
+                } else if severity == glow::DEBUG_SEVERITY_MEDIUM {
+                    //debug_clone.lock().unwrap().push(format!("[OpenGL, medium severity] {}", msg));
+                    //println!("[OpenGL, medium severity] {}", msg);
+                }
+            });
+        }
 
         ui.init(&mut texture_bank, &mut program_bank, &gl);
         world.scene.load_texture_to_material("test", &mut texture_bank, &gl);
@@ -78,16 +203,16 @@ fn main() {
         false,
         Matrix4::identity(),
         vec![
-            Renderable::Brush("ice".to_string(), vec3(0.0, -5.0, 0.0), vec3(20.0, 1.0, 20.0), flags::EXTEND_TEXTURE),
-            Renderable::Brush("concrete".to_string(), vec3(0.0, -4.0, 0.0), vec3(8.0, 1.0, 8.0), flags::EXTEND_TEXTURE),
-            Renderable::Brush("pillows_old_floor".to_string(), vec3(5.0, 0.0, 0.0), vec3(1.0, 4.0, 4.0), flags::EXTEND_TEXTURE),
-            // Renderable::Brush("end_sky".to_string(), vec3(0.0, 5.0, 0.0), vec3(2.0, 2.0, 2.0), flags::EXTEND_TEXTURE),
-            Renderable::Brush("evilwatering".to_string(), vec3(3.0, 0.0, 0.0), vec3(2.0, 2.0, 2.0), flags::EXTEND_TEXTURE),
-            Renderable::Brush("container".to_string(), vec3(-5.0, 0.0, 0.0), vec3(1.0, 10.0, 10.0), flags::EXTEND_TEXTURE),
-            Renderable::Brush("sky".to_string(), vec3(2.0, 0.0, 0.0), vec3(1.0, 7.0, 7.0), flags::EXTEND_TEXTURE),
-            Renderable::Brush("concrete".to_string(), vec3(1.0, -2.0, 0.0), vec3(1.0, 2.0, 1.0), flags::EXTEND_TEXTURE),
-            Renderable::Brush("tar".to_string(), vec3(0.0, -5.0, 15.0), vec3(10.0, 1.0, 10.0), flags::EXTEND_TEXTURE),
-            Renderable::Brush("watering".to_string(), vec3(0.0, -4.5, 0.0), vec3(10.0, 1.0, 10.0), flags::EXTEND_TEXTURE)
+            Renderable::Brush("ice".to_string(), vec3(0.0, -5.0, 0.0), vec3(20.0, 1.0, 20.0), flags::EXTEND_TEXTURE, BrushFaces::default()),
+            Renderable::Brush("concrete".to_string(), vec3(0.0, -4.0, 0.0), vec3(8.0, 1.0, 8.0), flags::EXTEND_TEXTURE, BrushFaces::default()),
+            Renderable::Brush("pillows_old_floor".to_string(), vec3(5.0, 0.0, 0.0), vec3(1.0, 4.0, 4.0), flags::EXTEND_TEXTURE, BrushFaces::default()),
+            // Renderable::Brush("end_sky".to_string(), vec3(0.0, 5.0, 0.0), vec3(2.0, 2.0, 2.0), flags::EXTEND_TEXTURE, BrushFaces::default()),
+            Renderable::Brush("evilwatering".to_string(), vec3(3.0, 0.0, 0.0), vec3(2.0, 2.0, 2.0), flags::EXTEND_TEXTURE, BrushFaces::default()),
+            Renderable::Brush("container".to_string(), vec3(-5.0, 0.0, 0.0), vec3(1.0, 10.0, 10.0), flags::EXTEND_TEXTURE, BrushFaces::default()),
+            Renderable::Brush("sky".to_string(), vec3(2.0, 0.0, 0.0), vec3(1.0, 7.0, 7.0), flags::EXTEND_TEXTURE, BrushFaces::default()),
+            Renderable::Brush("concrete".to_string(), vec3(1.0, -2.0, 0.0), vec3(1.0, 2.0, 1.0), flags::EXTEND_TEXTURE, BrushFaces::default()),
+            Renderable::Brush("tar".to_string(), vec3(0.0, -5.0, 15.0), vec3(10.0, 1.0, 10.0), flags::EXTEND_TEXTURE, BrushFaces::default()),
+            Renderable::Brush("watering".to_string(), vec3(0.0, -4.5, 0.0), vec3(10.0, 1.0, 10.0), flags::EXTEND_TEXTURE, BrushFaces::default())
         ]
     );
 
@@ -95,7 +220,7 @@ fn main() {
         true,
         Matrix4::from_translation(vec3(0.0, 5.0, 0.0)),
         vec![
-            Renderable::Brush("trigger".to_string(), vec3(0.0, 0.0, 0.0), vec3(2.0, 2.0, 2.0), flags::EXTEND_TEXTURE)
+            Renderable::Brush("trigger".to_string(), vec3(0.0, 0.0, 0.0), vec3(2.0, 2.0, 2.0), flags::EXTEND_TEXTURE, BrushFaces::default())
         ]
     ).insert_hidden().non_solid().with_component(Component::Trigger(component::Trigger::new(
         TriggerType::SetKernelEffect {
@@ -120,7 +245,7 @@ fn main() {
         true,
         Matrix4::from_translation(vec3(0.0, 1.0, 0.0)) * Matrix4::from_nonuniform_scale(1.0, 2.0, 1.0),
         vec![
-            Renderable::Billboard("komari".to_string(), vec3(0.0, 0.0, 0.0), (1.0, 2.0), flags::FULLBRIGHT | flags::CUTOUT, false)
+            Renderable::Billboard("komari".to_string(), vec3(0.0, 0.0, 0.0), (1.0, 2.0), flags::FULLBRIGHT | flags::CUTOUT, false, Flipbook::default())
         ]
     ).collider_cuboid(vec3(0.0, 0.0, 0.0), vec3(0.125, 0.125, 0.125)).non_solid().insert_hidden();
 
@@ -128,13 +253,22 @@ fn main() {
         true,
         Matrix4::from_translation(vec3(0.0, -2.0, 0.0)),
         vec![
-            Renderable::Brush("container".to_string(), vec3(0.0, 0.0, 0.0), vec3(2.0, 4.0, 0.25), flags::EXTEND_TEXTURE)
+            Renderable::Brush("container".to_string(), vec3(0.0, 0.0, 0.0), vec3(2.0, 4.0, 0.25), flags::EXTEND_TEXTURE, BrushFaces::default())
         ]
     ).with_component(Component::Door(component::Door::new(8.0, 3.75, 200))).insert_hidden();
 
     unsafe {
-        world.scene.init(&mut texture_bank, &mut mesh_bank, &mut program_bank, &gl);
+        for warning in world.scene.init(&mut texture_bank, &mut mesh_bank, &mut program_bank, &gl) {
+            eprintln!("{}", warning);
+            world.editor_data.show_debug.push(warning);
+        }
         world.scene.post_process.resize((window::WINDOW_INIT_WIDTH as u32, window::WINDOW_INIT_HEIGHT as u32), &gl);
+        let (chain, errors) = post::load_post_effects(&mut program_bank, &gl);
+        world.scene.post_process.chain = chain;
+        for error in errors {
+            eprintln!("{}", error);
+            world.editor_data.show_debug.push(error);
+        }
         world.editor_data.selection_box_vao = Some(mesh::create_selection_cube(&gl));
 
         world.insert_model(mobile);
@@ -142,12 +276,26 @@ fn main() {
         world.insert_model(door);
         world.insert_model(trigger);
         // world.insert_model(prefab_test.as_model(&mesh_bank));
-        world.set_internal_brushes(brushes);
+        world.set_internal_brushes(brushes, &[]);
         world.set_arrows_visible(false);
         world.move_boxes_far();
         world.move_arrows_far();
         world.set_boxes_visible(false);
         world.set_model_visible(world.internal.debug_arrow, false);
+        world.mark_saved();
+    }
+
+    // Exported games start their level instead of the test level, it is loaded at the end of the first frame
+    match export::LaunchConfig::load() {
+        Some(Ok(launch)) => match LevelData::read_from_file(Path::new(&launch.level)) {
+            Ok(level) => {
+                world.load_new = Some(level);
+                world.editor_data.save_to = Some(PathBuf::from(&launch.level));
+            },
+            Err(e) => world.editor_data.show_debug.push(format!("failed to load {}: {}", launch.level, e))
+        },
+        Some(Err(e)) => world.editor_data.show_debug.push(e),
+        None => ()
     }
 
     let frame_sleep_duration = Duration::from_millis(MS_PER_FRAME);
@@ -158,35 +306,79 @@ fn main() {
     let mut drawing_box = false;
     let mut box_origin = (0, 0);
     let mut selection_box_valid = false;
+    let mut panel_window: Option<window::PanelWindow> = None;
 
     // https://github.com/grovesNL/glow/blob/main/examples/hello/src/main.rs
     let _ = event_loop.run(move |event, elwt| {
         match event {
+            Event::WindowEvent { window_id, ref event } if panel_window.as_ref().is_some_and(|panel| panel.window.id() == window_id) => {
+                let panel = panel_window.as_mut().unwrap();
+                match event {
+                    // Closed like any other window, turning the preference off closes it on the next frame
+                    WindowEvent::CloseRequested => {
+                        ui.config.preferences.panel_window = false;
+                    },
+                    WindowEvent::Resized(new_size) => {
+                        if let Some(panels) = ui.panels.as_mut() {
+                            panels.resize(new_size.width, new_size.height);
+                        }
+                    },
+                    _ => panel.input.on_window_event(event)
+                }
+            },
             Event::WindowEvent { ref event, .. } => {
                 match event {
                     WindowEvent::CloseRequested => {
-                        elwt.exit();
+                        if ui.request_quit(&world) {
+                            elwt.exit();
+                        }
                     },
                     WindowEvent::RedrawRequested => unsafe {
                         let beginning_of_frame = Instant::now();
                         let delta_time = (beginning_of_frame - last_frame).as_secs_f32();
                         last_frame = beginning_of_frame;
 
-                        if input.get_key_pressed(Key::Named(NamedKey::Control)) && input.get_key_just_pressed(Key::Character("e".into())) {
+                        if ui.config.preferences.panel_window != panel_window.is_some() {
+                            if panel_window.take().is_some() {
+                                ui.close_panels(&gl);
+                            } else {
+                                match window::create_panel_window(elwt, &gl_context, &gl_surface) {
+                                    Ok(panel) => {
+                                        let size = panel.window.inner_size();
+                                        ui.open_panels((size.width, size.height), &gl);
+                                        panel_window = Some(panel);
+                                    },
+                                    Err(e) => {
+                                        ui.show_debug(&e);
+                                        ui.config.preferences.panel_window = false;
+                                    }
+                                }
+                            }
+                        }
+                        if let Some(panel) = &panel_window {
+                            panel.window.set_visible(!ui.play_mode);
+                        }
+
+                        if input.action_just_pressed(Action::ToggleEditor) {
                             match world.scene.camera.control_sceme {
-                                CameraControlScheme::FirstPerson(..) => {
+                                CameraControlScheme::FirstPerson(..) | CameraControlScheme::Spectator => {
                                     world.scene.camera.control_sceme = CameraControlScheme::Editor;
                                     window.set_cursor_grab(CursorGrabMode::None).unwrap();
                                     window.set_cursor_visible(true);
                                     world.player.movement = PlayerMovementMode::FollowCamera;
+                                    world.player.set_crouching(false, &mut world.physical_scene);
                                     grab_cursor = false;
+                                    world.completion = None;
+                                    world.collected.clear();
                                     world.editor_data.active = true;
                                     world.scene.show_hidden_objects = true;
                                     world.do_game_logic = false;
+                                    world.reset_targets();
                                     ui.play_mode = false;
                                 },
                                 CameraControlScheme::Editor => {
                                     world.scene.camera.control_sceme = CameraControlScheme::FirstPerson(true);
+                                    world.scene.camera.set_ortho(None);
                                     window.set_cursor_grab(CursorGrabMode::Confined).unwrap();
                                     window.set_cursor_visible(false);
                                     world.player.movement = PlayerMovementMode::FirstPerson;
@@ -194,22 +386,28 @@ fn main() {
                                     world.editor_data.active = false;
                                     world.scene.show_hidden_objects = false;
                                     world.do_game_logic = true;
+                                    world.player.health = world.rules.max_health();
+                                    world.player.dead = false;
+                                    world.level_time = 0.0;
+                                    world.prepare_stopwatch();
+                                    world.player.reset_stamina(&world.rules);
+                                    world.completed_objectives.clear();
                                     world.deselect();
                                     ui.play_mode = true;
                                 }
                             }
                         }
 
-                        if input.get_key_pressed(Key::Named(NamedKey::Control)) && input.get_key_just_pressed(Key::Character("m".into())) {
+                        if input.action_just_pressed(Action::LogMeshes) {
                             println!("{}", mesh_bank.log_loaded_models());
                         }
 
-                        if input.get_key_pressed(Key::Named(NamedKey::Control)) && input.get_key_just_pressed(Key::Character("b".into())) {
+                        if input.action_just_pressed(Action::DebugBrushes) {
                             world.debug_brushes();
                         }
 
                         if let CameraControlScheme::FirstPerson(locked) = &mut world.scene.camera.control_sceme {
-                            if input.get_key_just_pressed(Key::Named(NamedKey::Escape)) && *locked {
+                            if input.action_just_pressed(Action::Cancel) && *locked {
                                 *locked = false;
                                 window.set_cursor_grab(CursorGrabMode::None).unwrap();
                                 window.set_cursor_visible(true);
@@ -217,12 +415,19 @@ fn main() {
                         }
 
                         let mouse_ray = world.get_mouse_ray(input.mouse_pos.0, input.mouse_pos.1, window.inner_size().width, window.inner_size().height);
-                        if let Some(result) = world.physical_scene.raycast(mouse_ray.0, mouse_ray.1, 100.0, &RaycastParameters::new().ignore(vec![world.player.collider]).select_foreground()) {
+                        let mouse_hit = world.physical_scene.raycast(mouse_ray.0, mouse_ray.1, world.scene.camera.pick_distance(), &RaycastParameters::new().ignore(vec![world.player.collider]).select_foreground());
+                        world.update_material_hover(if ui.inner.mouse_captured { None } else { mouse_hit.as_ref() });
+                        world.update_stamp_hover(if ui.inner.mouse_captured { None } else { mouse_hit.as_ref() });
+                        world.update_reference_hover(if ui.inner.mouse_captured { None } else { mouse_hit.as_ref() });
+                        if let Some(result) = mouse_hit {
                             if result.model.is_some() {
                                 if !ui.inner.mouse_captured {
                                     let shift_pressed = input.get_key_pressed(Key::Named(NamedKey::Shift));
+                                    let ctrl_pressed = input.get_key_pressed(Key::Named(NamedKey::Control));
                                     if input.get_mouse_button_just_released(MouseButton::Left) && !selection_box_valid && world.editor_data.drag_axis.is_none() {
-                                        world.model_released(result, shift_pressed);
+                                        if !world.material_clicked(&result, ctrl_pressed) && !world.stamp_clicked() && !world.measure_clicked() {
+                                            world.model_released(result, shift_pressed);
+                                        }
                                     } else if input.get_mouse_button_just_pressed(MouseButton::Left) {
                                         world.model_pressed(result);
                                     }
@@ -292,42 +497,149 @@ fn main() {
 
                         
                         if world.editor_data.active && !ui.inner.mouse_captured && input.scroll.abs() > 0.01 {
-                            world.scene.camera.pos -= world.scene.camera.direction * 0.05 * input.scroll;
+                            world.scene.camera.zoom(input.scroll);
                         }
 
+                        world.update_events(delta_time);
                         world.update(&input, mouse_ray, delta_time);
+                        world.play_surface_sounds();
+                        world.update_stopwatch(&input, delta_time);
+                        world.update_spectator(&input, delta_time);
+                        world.update_autosave(delta_time);
+                        if let Some(summary) = world.update_visibility_bake() {
+                            world.editor_data.show_debug.push(summary);
+                        }
+                        world.update_stats(&texture_bank, delta_time);
+                        world.update_traversal_check(delta_time);
+                        world.update_ghost(delta_time);
+                        world.update_objectives();
+                        world.update_goals();
+                        world.record_best_time(&mut ui.config);
+                        world.update_completion(&input);
                         world.scene.camera.update(&input, delta_time);
+                        world.scene.cull_statics = !world.editor_data.active;
+                        world.scene.brush_tint = if world.editor_data.active { world.editor_data.brush_tint } else { BrushTint::Off };
                         world.scene.update(&mut mesh_bank, &gl);
-
-                        world.scene.post_process.begin(&gl);
+                        world.music.update(delta_time, &ui.config.volume);
+                        world.update_sound_emitters();
+                        world.update_draw_distances();
+
+                        world.scene.prepare_text(&mut texture_bank, &gl);
+                        world.prepare_impostors(&mesh_bank, &mut program_bank, &mut texture_bank, &gl);
+                        world.scene.prepare_mobiles(&mesh_bank, &gl);
+                        world.scene.render_shadows(&mesh_bank, &mut program_bank, &gl);
+                        world.render_feeds(&mut mesh_bank, &mut program_bank, &mut texture_bank, &gl);
+                        world.render_reflection(&mesh_bank, &mut program_bank, &texture_bank, &gl);
+                        world.scene.prepare_lights(&gl);
+                        if !world.scene.safe_mode {
+                            world.scene.post_process.begin(&gl);
+                        }
                         world.scene.render(&mesh_bank, &mut program_bank, &texture_bank, &gl);
+                        world.render_ortho_grid(&mut program_bank, &gl);
+                        world.render_ground_grid(&mut program_bank, &gl);
                         if world.editor_data.show_colliders {
                             world.debug_render_colliders(&mut program_bank, &gl);
                         }
+                        world.render_material_preview(&mesh_bank, &mut program_bank, &texture_bank, &gl);
+                        world.render_ghost(&mesh_bank, &mut program_bank, &texture_bank, &gl);
+                        world.render_stamp_preview(&mut program_bank, &gl);
+                        world.render_merge_preview(&mut program_bank, &gl);
+                        world.render_heatmap(&mut program_bank, &gl);
+                        world.render_mover_paths(&mut program_bank, &gl);
+                        world.render_traversal_warnings(&mut program_bank, &gl);
+                        world.render_scale_references(&mut program_bank, &gl);
+                        world.render_note_arrows(&mut program_bank, &gl);
+                        world.render_links(&mut program_bank, &gl);
                         world.post_render(&mut program_bank, &gl);
-                        world.scene.post_process.end(&mut program_bank, &gl);
+                        if !world.scene.safe_mode {
+                            world.scene.post_process.end(&mut program_bank, &gl);
+                        }
+                        world.request_screenshot(&input, &gl);
+                        world.readback.poll(&gl);
+                        world.save_screenshots();
 
                         for line in world.editor_data.show_debug.drain(..) { ui.show_debug(&line); }
                         for line in opengl_debug.lock().unwrap().drain(..) { ui.show_debug(&line); }
-                        ui.render_and_update(&input, &mut texture_bank, &mut mesh_bank, &mut program_bank, &gl, &mut world);
+                        ui.render_and_update(&input, panel_window.as_ref().map(|panel| &panel.input), &mut texture_bank, &mut mesh_bank, &mut program_bank, &gl, &mut world);
+                        if input.keyboard_captured != ui.inner.keyboard_captured {
+                            input.keyboard_captured = ui.inner.keyboard_captured;
+                            window.set_ime_allowed(input.keyboard_captured);
+                        }
+                        if let Some((x, y)) = ui.inner.text_cursor {
+                            let scale = ui.inner.scale;
+                            window.set_ime_cursor_area(PhysicalPosition::new((x as f32 * scale) as i32, (y as f32 * scale) as i32), PhysicalSize::new((6.0 * scale) as u32, (10.0 * scale) as u32));
+                        }
 
                         gl_surface.swap_buffers(&gl_context).unwrap();
 
+                        if let (Some(panel), Some(panels)) = (panel_window.as_mut(), ui.panels.as_ref()) {
+                            if panel.input.keyboard_captured != panels.keyboard_captured {
+                                panel.input.keyboard_captured = panels.keyboard_captured;
+                                panel.window.set_ime_allowed(panels.keyboard_captured);
+                            }
+
+                            // Drawn with the same context after the main window is presented
+                            let size = panel.window.inner_size();
+                            gl_context.make_current(&panel.surface).unwrap();
+                            gl.viewport(0, 0, size.width as i32, size.height as i32);
+                            gl.clear_color(window::PANEL_BACKGROUND[0], window::PANEL_BACKGROUND[1], window::PANEL_BACKGROUND[2], 1.0);
+                            gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
+                            ui.render_panels(&texture_bank, &mut program_bank, &gl);
+                            panel.surface.swap_buffers(&gl_context).unwrap();
+
+                            let size = window.inner_size();
+                            gl_context.make_current(&gl_surface).unwrap();
+                            gl.viewport(0, 0, size.width as i32, size.height as i32);
+                            panel.input.update();
+                        }
+
                         input.update();
+                        world.end_frame();
                         if let Some(level_data) = world.load_new.take() {
+                            let unsaved = mem::take(&mut world.load_unsaved);
                             let mut new_world = World::from_save_data(level_data, &mut texture_bank, &mut mesh_bank, &mut program_bank, &gl);
                             new_world.scene.camera.control_sceme = world.scene.camera.control_sceme.clone();
                             new_world.player.movement = world.player.movement.clone();
                             new_world.editor_data.active = world.editor_data.active;
+                            new_world.do_game_logic = world.do_game_logic;
                             new_world.scene.show_hidden_objects = world.scene.show_hidden_objects;
+                            new_world.scene.safe_mode = world.scene.safe_mode;
                             new_world.editor_data.increment = world.editor_data.increment;
+                            new_world.editor_data.snapping = world.editor_data.snapping;
+                            new_world.editor_data.show_grid = world.editor_data.show_grid;
+                            new_world.editor_data.brush_tint = world.editor_data.brush_tint;
                             new_world.editor_data.save_to = world.editor_data.save_to.clone();
+                            new_world.editor_data.session = mem::take(&mut world.editor_data.session);
+                            new_world.ghosts.record = world.ghosts.record;
+                            new_world.readback = mem::take(&mut world.readback);
+                            new_world.screenshots = mem::take(&mut world.screenshots);
                             let window_size =  window.inner_size(); 
                             new_world.scene.camera.on_window_resized(window_size.width as f32, window_size.height as f32);
                             new_world.scene.post_process.resize((window_size.width, window_size.height), &gl);
+                            new_world.scene.post_process.chain = mem::take(&mut world.scene.post_process.chain);
+                            post::apply_chain_settings(&mut new_world.scene.post_process.chain, &new_world.scene.world_default_effects.chain);
                             new_world.scene.window_size = (window_size.width, window_size.height);
                             new_world.scene.ui_vao = world.scene.ui_vao;
+                            new_world.music = mem::replace(&mut world.music, MusicManager::new());
                             world = new_world;
+                            world.start_level_audio();
+                            if unsaved {
+                                world.editor_data.saved_snapshot = None;
+                            } else {
+                                world.mark_saved();
+                            }
+                            if world.do_game_logic {
+                                // The next level of a playlist starts at its spawnpoint with its own rules
+                                world.apply_rules();
+                                world.prepare_stopwatch();
+                                world.restart_level();
+                                world.player.reset_stamina(&world.rules);
+                            }
+                            ui.config.preferences.apply(&mut world, &mut ui.inner);
+                        }
+
+                        if ui.quit_confirmed() {
+                            elwt.exit();
                         }
 
                         let frame_duration = Instant::now() - beginning_of_frame;
@@ -340,18 +652,24 @@ fn main() {
                         match event.state {
                             ElementState::Pressed => {
                                 input.on_key_pressed(event.key_without_modifiers());
+                                if let Some(text) = &event.text {
+                                    input.on_text(text);
+                                }
                             },
                             ElementState::Released => {
                                 input.on_key_released(event.key_without_modifiers());
                             }
                         }
                     },
+                    WindowEvent::Ime(ime) => {
+                        input.on_ime(ime);
+                    },
                     WindowEvent::MouseInput { state, button, .. } => {
                         match state {
                             ElementState::Pressed => {
                                 input.on_mouse_button_pressed(*button);
                                 if let CameraControlScheme::FirstPerson(locked) = &mut world.scene.camera.control_sceme {
-                                    if !*locked {
+                                    if !*locked && !ui.inner.mouse_captured {
                                         *locked = true;
                                         window.set_cursor_grab(CursorGrabMode::Confined).unwrap();
                                         window.set_cursor_visible(false);
@@ -393,7 +711,7 @@ fn main() {
                         world.scene.camera.on_window_resized(new_size.width as f32, new_size.height as f32);
                         world.scene.post_process.resize((new_size.width, new_size.height), &gl);
                         world.scene.window_size = (new_size.width, new_size.height);
-                        ui.inner.screen_size = (new_size.width, new_size.height);
+                        ui.inner.resize(new_size.width, new_size.height);
                     },
                     _ => ()
                 }
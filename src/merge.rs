@@ -0,0 +1,156 @@
+use cgmath::{vec3, InnerSpace, Matrix4, Vector3};
+
+use crate::{mesh::{flags, BrushFaces}, shader::ProgramBank, world::{Renderable, World}};
+
+/// Faces closer than this count as touching
+const MERGE_EPSILON: f32 = 0.001;
+const PREVIEW_COLOR: Vector3<f32> = vec3(0.1, 0.9, 0.9);
+
+/// Brush made of several others, kept so the merge can be undone
+#[derive(Clone)]
+pub struct MergedBrush {
+    pub brush: Renderable,
+    pub parts: Vec<Renderable>
+}
+
+/// Box being grown by `plan_merge`, with the brushes it came from
+struct MergeBox {
+    material: String,
+    flags: u32,
+    faces: BrushFaces,
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+    sources: Vec<usize>
+}
+
+impl MergeBox {
+    fn matches(&self, other: &Self) -> bool {
+        self.material == other.material && self.flags == other.flags && self.faces == other.faces
+    }
+
+    /// Whether the boxes share a whole face, so together they are still a box
+    fn touches(&self, other: &Self) -> bool {
+        let close = |a: f32, b: f32| (a - b).abs() < MERGE_EPSILON;
+        (0..3).any(|axis| {
+            let others_equal = (0..3).filter(|a| *a != axis).all(|a| close(self.min[a], other.min[a]) && close(self.max[a], other.max[a]));
+            others_equal && (close(self.max[axis], other.min[axis]) || close(other.max[axis], self.min[axis]))
+        })
+    }
+
+    fn absorb(&mut self, other: MergeBox) {
+        for axis in 0..3 {
+            self.min[axis] = self.min[axis].min(other.min[axis]);
+            self.max[axis] = self.max[axis].max(other.max[axis]);
+        }
+        self.sources.extend(other.sources);
+    }
+
+    fn as_renderable(&self) -> Renderable {
+        Renderable::Brush(self.material.clone(), (self.min + self.max) / 2.0, self.max - self.min, self.flags, self.faces)
+    }
+}
+
+impl World {
+    /// Brushes `brushes` would merge into, with the indices each one is made of<br>
+    /// Only brushes with the same material, flags and alignment that share a whole face are merged<br>
+    /// Brushes need extend texture, stretched textures would change size
+    pub fn plan_merge(&self, brushes: &[usize]) -> Vec<(Renderable, Vec<usize>)> {
+        let render = &self.models[self.internal.brushes].as_ref().unwrap().render;
+        let mut boxes: Vec<MergeBox> = brushes.iter().filter_map(|brush| match &render[*brush] {
+            Renderable::Brush(material, origin, size, brush_flags, faces) if brush_flags & flags::EXTEND_TEXTURE != 0 => Some(MergeBox {
+                material: material.clone(), flags: *brush_flags, faces: *faces,
+                min: origin - size / 2.0, max: origin + size / 2.0, sources: vec![*brush]
+            }),
+            _ => None
+        }).collect();
+
+        // Merging two boxes can line the result up with a third, so go until nothing changes
+        let mut merged = true;
+        while merged {
+            merged = false;
+            let mut i = 0;
+            while i < boxes.len() {
+                let mut j = i + 1;
+                while j < boxes.len() {
+                    if boxes[i].matches(&boxes[j]) && boxes[i].touches(&boxes[j]) {
+                        let other = boxes.swap_remove(j);
+                        boxes[i].absorb(other);
+                        merged = true;
+                    } else {
+                        j += 1;
+                    }
+                }
+                i += 1;
+            }
+        }
+
+        boxes.into_iter().filter(|b| b.sources.len() > 1).map(|b| (b.as_renderable(), b.sources)).collect()
+    }
+
+    /// Replace `brushes` with as few brushes as `plan_merge` finds, returns the new brush indices<br>
+    /// The parts are remembered until the level is closed, see `split_merged_brush`
+    pub fn merge_brushes(&mut self, brushes: &[usize]) -> Vec<usize> {
+        let plan = self.plan_merge(brushes);
+        let render = &self.models[self.internal.brushes].as_ref().unwrap().render;
+        let records: Vec<MergedBrush> = plan.iter().map(|(brush, sources)| MergedBrush {
+            brush: brush.clone(),
+            parts: sources.iter().map(|source| render[*source].clone()).collect()
+        }).collect();
+
+        let mut removed: Vec<usize> = plan.iter().flat_map(|(_, sources)| sources.iter().copied()).collect();
+        // Remove from the back so earlier indices stay valid
+        removed.sort_by(|a, b| b.cmp(a));
+        for brush in removed {
+            self.remove_brush(brush);
+        }
+
+        self.editor_data.merged_brushes.extend(records);
+        self.editor_data.merge_preview.clear();
+        plan.into_iter().map(|(brush, _)| self.insert_brush(brush)).collect()
+    }
+
+    /// Every brush in the level, merged where possible, returns how many brushes were removed
+    pub fn merge_all_brushes(&mut self) -> usize {
+        let before = self.models[self.internal.brushes].as_ref().unwrap().render.len();
+        self.merge_brushes(&(0..before).collect::<Vec<_>>());
+        before - self.models[self.internal.brushes].as_ref().unwrap().render.len()
+    }
+
+    /// Index of the record for a brush made by `merge_brushes`
+    pub fn merge_record(&self, brush: usize) -> Option<usize> {
+        let Renderable::Brush(material, origin, size, ..) = &self.models[self.internal.brushes].as_ref().unwrap().render[brush] else { return None; };
+        self.editor_data.merged_brushes.iter().rposition(|record| match &record.brush {
+            Renderable::Brush(m, o, s, ..) => m == material && (o - origin).magnitude2() < MERGE_EPSILON && (s - size).magnitude2() < MERGE_EPSILON,
+            _ => false
+        })
+    }
+
+    /// Put back the brushes a merged brush was made of, returns their indices<br>
+    /// Brushes merged in an earlier session can't be split
+    pub fn split_merged_brush(&mut self, brush: usize) -> Result<Vec<usize>, String> {
+        let record = self.merge_record(brush).ok_or(String::from("that brush wasn't merged in this session"))?;
+        let record = self.editor_data.merged_brushes.remove(record);
+        self.remove_brush(brush);
+        Ok(record.parts.into_iter().map(|part| self.insert_brush(part)).collect())
+    }
+
+    /// Show what merging `brushes` would make, until the selection changes<br>
+    /// Returns how many brushes the merge would remove
+    pub fn preview_merge(&mut self, brushes: &[usize]) -> usize {
+        let plan = self.plan_merge(brushes);
+        let removed = plan.iter().map(|(_, parts)| parts.len() - 1).sum();
+        self.editor_data.merge_preview = plan.into_iter().filter_map(|(brush, _)| match brush {
+            Renderable::Brush(_, origin, size, ..) => Some((origin, size)),
+            _ => None
+        }).collect();
+        removed
+    }
+
+    /// Outline the brushes from `preview_merge`
+    pub unsafe fn render_merge_preview(&self, programs: &mut ProgramBank, gl: &glow::Context) {
+        for (origin, size) in self.editor_data.merge_preview.iter() {
+            let transform = Matrix4::from_translation(*origin) * Matrix4::from_nonuniform_scale(size.x, size.y, size.z);
+            self.scene.debug_render_box(transform, PREVIEW_COLOR, self.editor_data.selection_box_vao.unwrap(), programs, gl);
+        }
+    }
+}
@@ -1,13 +1,19 @@
-use std::{collections::HashMap, error::Error, path::PathBuf};
+use std::{collections::{hash_map::DefaultHasher, HashMap}, error::Error, hash::{Hash, Hasher}, path::PathBuf};
 
 use glow::{HasContext, NativeVertexArray};
 use itertools::izip;
+use serde::{Deserialize, Serialize};
+
+use crate::lod::MeshLod;
 
 pub struct Mesh {
     pub vao: NativeVertexArray,
     pub vao_instanced: NativeVertexArray,
+    /// Like `vao_instanced` but for the mobile instances streamed every frame, see `Scene::prepare_mobiles`
+    pub vao_streamed: NativeVertexArray,
     pub indices: usize,
-    pub material: String
+    pub material: String,
+    pub format: VertexFormat
 }
 
 pub type VertexComponent = f32;
@@ -15,13 +21,214 @@ pub type IndexComponent = u16;
 
 pub mod flags {
     pub const NONE: u32 =               0b0000;
+    /// Texture coordinates are generated from world position so the texture repeats instead of stretching
     pub const EXTEND_TEXTURE: u32 =     0b0001;
+    /// Ignore lighting
     pub const FULLBRIGHT: u32 =         0b0010;
+    /// Renderable is not drawn by either the instanced or the flat path<br>
+    /// Collision and selection are unaffected
     pub const SKIP: u32 =               0b0100;
+    /// Discard pixels with low alpha
     pub const CUTOUT: u32 =             0b1000;
+    /// Only drawn while editing, for notes and other editor helpers<br>
+    /// Static meshes are always drawn
+    pub const EDITOR_ONLY: u32 =        0b10000;
+    /// The texture is a sprite sheet played with the brush type's flipbook, see `flipbook`<br>
+    /// Billboards with a flipbook of their own animate without it
+    pub const FLIPBOOK: u32 =           0b100000;
+
+    pub const ALL: [(u32, &str); 6] = [
+        (EXTEND_TEXTURE, "extend texture"),
+        (FULLBRIGHT, "fullbright"),
+        (CUTOUT, "cutout"),
+        (SKIP, "skip"),
+        (EDITOR_ONLY, "editor only"),
+        (FLIPBOOK, "flipbook")
+    ];
+}
+
+/// A single per vertex input, attribute locations follow the order they appear in a `VertexFormat`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum VertexAttribute {
+    Position,
+    Color,
+    TexCoord,
+    Normal,
+    Tangent,
+    BoneIndices,
+    BoneWeights,
+    LightmapTexCoord,
+    /// Texture offset u, v and rotation in radians of a brush face
+    FaceOffset,
+    /// Log2 of the texture scale of a brush face
+    FaceScale
+}
+
+impl VertexAttribute {
+    /// Number of `VertexComponent`s this attribute takes up
+    pub fn size(&self) -> usize {
+        match self {
+            Self::Position | Self::Color | Self::Normal | Self::FaceOffset => 3,
+            Self::TexCoord | Self::LightmapTexCoord | Self::FaceScale => 2,
+            Self::Tangent | Self::BoneIndices | Self::BoneWeights => 4
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Position => "position",
+            Self::Color => "color",
+            Self::TexCoord => "texture coordinate",
+            Self::Normal => "normal",
+            Self::Tangent => "tangent",
+            Self::BoneIndices => "bone indices",
+            Self::BoneWeights => "bone weights",
+            Self::LightmapTexCoord => "lightmap texture coordinate",
+            Self::FaceOffset => "face offset",
+            Self::FaceScale => "face scale"
+        }
+    }
 }
 
-const VERTEX_ATTRIBUTES_COUNT: u32 = 4;
+/// Vertex attributes are bound below this location, per instance data starts here for every format
+pub const INSTANCE_LOCATION: u32 = 8;
+
+/// Layout of interleaved vertex data<br>
+/// Attributes are bound to locations 0..n in order, instance attributes start at `INSTANCE_LOCATION`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct VertexFormat {
+    pub attributes: &'static [VertexAttribute]
+}
+
+impl VertexFormat {
+    /// x, y, z, r, g, b, tx, ty, nx, ny, nz
+    pub const STANDARD: Self = Self {
+        attributes: &[VertexAttribute::Position, VertexAttribute::Color, VertexAttribute::TexCoord, VertexAttribute::Normal]
+    };
+
+    /// `STANDARD` followed by the face alignment of brushes, fu, fv, frot, fsu, fsv
+    pub const BRUSH: Self = Self {
+        attributes: &[
+            VertexAttribute::Position, VertexAttribute::Color, VertexAttribute::TexCoord, VertexAttribute::Normal,
+            VertexAttribute::FaceOffset, VertexAttribute::FaceScale
+        ]
+    };
+
+    /// Number of components in one vertex
+    pub fn stride(&self) -> usize {
+        self.attributes.iter().map(|attribute| attribute.size()).sum()
+    }
+
+    /// Offset in components of an attribute from the start of a vertex
+    pub fn offset(&self, attribute: VertexAttribute) -> Option<usize> {
+        let position = self.attributes.iter().position(|a| *a == attribute)?;
+        Some(self.attributes[..position].iter().map(|a| a.size()).sum())
+    }
+
+    pub fn has(&self, attribute: VertexAttribute) -> bool {
+        self.attributes.contains(&attribute)
+    }
+
+    /// First attribute location available for per instance data
+    pub fn instance_location(&self) -> u32 {
+        debug_assert!(self.attributes.len() as u32 <= INSTANCE_LOCATION, "Too many vertex attributes");
+        INSTANCE_LOCATION
+    }
+
+    /// Append one vertex to `data`, attributes that `value` doesn't provide are filled with zeros
+    pub fn push_vertex<'a>(&self, data: &mut Vec<VertexComponent>, mut value: impl FnMut(VertexAttribute) -> Option<&'a [VertexComponent]>) {
+        for attribute in self.attributes {
+            let size = attribute.size();
+            match value(*attribute) {
+                Some(components) => data.extend_from_slice(&components[..size]),
+                None => data.extend(std::iter::repeat(0.0).take(size))
+            }
+        }
+    }
+
+    /// Set one attribute on every vertex in `data`
+    pub fn fill_attribute(&self, data: &mut [VertexComponent], attribute: VertexAttribute, value: &[VertexComponent]) {
+        let Some(offset) = self.offset(attribute) else { return; };
+        let size = attribute.size();
+        for vertex in data.chunks_exact_mut(self.stride()) {
+            vertex[offset..offset + size].copy_from_slice(&value[..size]);
+        }
+    }
+
+    /// Point the bound vertex array at the bound array buffer using this layout
+    unsafe fn define_vertex_attributes(&self, gl: &glow::Context) {
+        let sizeof_float = core::mem::size_of::<VertexComponent>() as i32;
+        let stride = self.stride() as i32 * sizeof_float;
+        let mut offset = 0;
+        for (location, attribute) in self.attributes.iter().enumerate() {
+            gl.vertex_attrib_pointer_f32(location as u32, attribute.size() as i32, glow::FLOAT, false, stride, offset * sizeof_float);
+            gl.enable_vertex_attrib_array(location as u32);
+            offset += attribute.size() as i32;
+        }
+    }
+}
+
+/// How the texture sits on one face of a brush
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct FaceUV {
+    pub offset: [f32; 2],
+    /// Degrees
+    pub rotation: f32,
+    pub scale: [f32; 2]
+}
+
+impl Default for FaceUV {
+    fn default() -> Self {
+        Self { offset: [0.0, 0.0], rotation: 0.0, scale: [1.0, 1.0] }
+    }
+}
+
+impl FaceUV {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Values of the `FaceOffset` and `FaceScale` attributes
+    fn attributes(&self) -> ([VertexComponent; 3], [VertexComponent; 2]) {
+        (
+            [self.offset[0], self.offset[1], self.rotation.to_radians()],
+            [self.scale[0].max(0.001).log2(), self.scale[1].max(0.001).log2()]
+        )
+    }
+}
+
+/// Texture alignment of each face of a brush, in the order +X, -X, +Y, -Y, +Z, -Z
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Default, Debug)]
+pub struct BrushFaces(pub [FaceUV; 6]);
+
+impl BrushFaces {
+    pub const NAMES: [&'static str; 6] = ["+X", "-X", "+Y", "-Y", "+Z", "-Z"];
+
+    pub fn is_default(&self) -> bool {
+        self.0.iter().all(|face| face.is_default())
+    }
+
+    /// Index of the face pointing along an axis aligned `normal`
+    pub fn face_index(normal: [VertexComponent; 3]) -> usize {
+        let axis = (0..3).max_by(|a, b| normal[*a].abs().total_cmp(&normal[*b].abs())).unwrap();
+        axis * 2 + (normal[axis] < 0.0) as usize
+    }
+
+    /// Brushes with the same material and alignment share a mesh, unaligned brushes use the plain cube
+    pub fn mesh_name(&self, material: &str) -> String {
+        if self.is_default() {
+            return format!("Brush_{}", material);
+        }
+        let mut hasher = DefaultHasher::new();
+        for face in self.0.iter() {
+            for value in [face.offset[0], face.offset[1], face.rotation, face.scale[0], face.scale[1]] {
+                value.to_bits().hash(&mut hasher);
+            }
+        }
+        format!("Brush_{}#{:016x}", material, hasher.finish())
+    }
+}
 
 impl Mesh {
     pub fn load_from_obj_vcolor(name: &str, r: VertexComponent, g: VertexComponent, b: VertexComponent, gl: &glow::Context) -> Result<Vec<Self>, Box<dyn Error>> {
@@ -40,7 +247,8 @@ impl Mesh {
         for model in models.iter() {
             let mesh = &model.mesh;
 
-            // x, y, z, r, g, b, tx, ty, nx, ny, nz
+            let format = VertexFormat::STANDARD;
+            let color = [r, g, b];
             let mut mesh_data = Vec::new();
 
             assert!(!mesh.positions.is_empty(), "Mesh had no vertices");
@@ -48,15 +256,16 @@ impl Mesh {
             assert!(!mesh.normals.is_empty(), "Mesh had no normals");
 
             for (position, texture_coord, normal) in izip!(mesh.positions.chunks(3), mesh.texcoords.chunks(2), mesh.normals.chunks(3)) {
-                mesh_data.extend_from_slice(&[
-                    position[0], position[1], position[2],
-                    r, g, b,
-                    texture_coord[0], texture_coord[1],
-                    normal[0], normal[1], normal[2]
-                ]);
+                format.push_vertex(&mut mesh_data, |attribute| match attribute {
+                    VertexAttribute::Position => Some(position),
+                    VertexAttribute::Color => Some(&color[..]),
+                    VertexAttribute::TexCoord => Some(texture_coord),
+                    VertexAttribute::Normal => Some(normal),
+                    _ => None
+                });
             }
 
-            meshes.push(unsafe { Self::from_data(&mesh_data, &mesh.indices.iter().map(|i| *i as u16).collect::<Vec<IndexComponent>>(), gl) });
+            meshes.push(unsafe { Self::from_data_with_format(format, &mesh_data, &mesh.indices.iter().map(|i| *i as u16).collect::<Vec<IndexComponent>>(), gl) });
         }
 
         Ok(meshes)
@@ -68,6 +277,13 @@ impl Mesh {
 
     /// Expected layout: x, y, z, r, g, b, tx, ty, nx, ny, nz
     unsafe fn from_data(vertices: &[VertexComponent], indices: &[IndexComponent], gl: &glow::Context) -> Self {
+        Self::from_data_with_format(VertexFormat::STANDARD, vertices, indices, gl)
+    }
+
+    /// `vertices` must be laid out according to `format`
+    pub unsafe fn from_data_with_format(format: VertexFormat, vertices: &[VertexComponent], indices: &[IndexComponent], gl: &glow::Context) -> Self {
+        debug_assert!(vertices.len() % format.stride() == 0, "Vertex data doesn't match its format");
+
         let vertices_u8: &[u8] = core::slice::from_raw_parts(
             vertices.as_ptr() as *const u8,
             core::mem::size_of_val(vertices)
@@ -79,6 +295,7 @@ impl Mesh {
 
         let vao = gl.create_vertex_array().unwrap();
         let vao_instanced = gl.create_vertex_array().unwrap();
+        let vao_streamed = gl.create_vertex_array().unwrap();
         let vbo = gl.create_buffer().unwrap();
         let ebo = gl.create_buffer().unwrap();
 
@@ -90,22 +307,30 @@ impl Mesh {
         gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(ebo));
         gl.buffer_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, indices_u8, glow::STATIC_DRAW);
 
-        Self::define_vertex_attributes(gl);
+        format.define_vertex_attributes(gl);
         
         gl.bind_vertex_array(None);
 
         gl.bind_vertex_array(Some(vao_instanced));
         gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
         gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(ebo));
-        Self::define_vertex_attributes(gl);
+        format.define_vertex_attributes(gl);
         gl.bind_vertex_array(None);
         // this vao is left unfinished until static mesh data is ready
 
+        gl.bind_vertex_array(Some(vao_streamed));
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+        gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(ebo));
+        format.define_vertex_attributes(gl);
+        gl.bind_vertex_array(None);
+
         Self {
             vao,
             vao_instanced,
+            vao_streamed,
             indices: indices.len(),
-            material: "default".to_string()
+            material: "default".to_string(),
+            format
         }
     }
 
@@ -115,12 +340,7 @@ impl Mesh {
 
     pub unsafe fn create_colored_cube(r: VertexComponent, g: VertexComponent, b: VertexComponent, gl: &glow::Context) -> Self {
         let mut verts = CUBE_VERTICES.to_vec();
-
-        for i in 0..24 {
-            verts[i * 11 + 3] = r;
-            verts[i * 11 + 4] = g;
-            verts[i * 11 + 5] = b;
-        }
+        VertexFormat::STANDARD.fill_attribute(&mut verts, VertexAttribute::Color, &[r, g, b]);
 
         Self::from_data(&verts, &CUBE_INDICES, gl)
     }
@@ -131,6 +351,25 @@ impl Mesh {
         cube
     }
 
+    /// A brush cube with `faces` baked into its vertices
+    pub unsafe fn create_aligned_cube(material: &str, faces: &BrushFaces, gl: &glow::Context) -> Self {
+        let standard = VertexFormat::STANDARD;
+        let format = VertexFormat::BRUSH;
+        let normal_offset = standard.offset(VertexAttribute::Normal).unwrap();
+        let mut vertices = Vec::with_capacity(CUBE_VERTICES.len() / standard.stride() * format.stride());
+        for vertex in CUBE_VERTICES.chunks_exact(standard.stride()) {
+            let normal = [vertex[normal_offset], vertex[normal_offset + 1], vertex[normal_offset + 2]];
+            let (offset, scale) = faces.0[BrushFaces::face_index(normal)].attributes();
+            vertices.extend_from_slice(vertex);
+            vertices.extend_from_slice(&offset);
+            vertices.extend_from_slice(&scale);
+        }
+
+        let mut cube = Self::from_data_with_format(format, &vertices, &CUBE_INDICES, gl);
+        cube.material = material.to_string();
+        cube
+    }
+
     pub unsafe fn create_square(r: VertexComponent, g: VertexComponent, b: VertexComponent, gl: &glow::Context) -> Self {
         Self::from_data(&[
              0.5,  0.5, 0.0, r, g, b, 0.99, 0.99, 0.0, 0.0, -1.0,
@@ -154,65 +393,53 @@ impl Mesh {
         square
     }
 
-    unsafe fn define_vertex_attributes(gl: &glow::Context) {
-        let sizeof_float = core::mem::size_of::<f32>() as i32;
-        let stride = 11 * sizeof_float;
-        // position
-        gl.vertex_attrib_pointer_f32(0, 3, glow::FLOAT, false, stride, 0);
-        gl.enable_vertex_attrib_array(0);
-        // vertex color
-        gl.vertex_attrib_pointer_f32(1, 3, glow::FLOAT, false, stride, 3 * sizeof_float);
-        gl.enable_vertex_attrib_array(1);
-        // texture coordinate
-        gl.vertex_attrib_pointer_f32(2, 2, glow::FLOAT, false, stride, 6 * sizeof_float);
-        gl.enable_vertex_attrib_array(2);
-        // normal
-        gl.vertex_attrib_pointer_f32(3, 3, glow::FLOAT, false, stride, 8 * sizeof_float);
-        gl.enable_vertex_attrib_array(3);
-    }
-
-    pub unsafe fn define_instanced_vertex_attributes(gl: &glow::Context) {
+    /// Instance attributes are placed from `INSTANCE_LOCATION` on, past any of this mesh's vertex attributes
+    pub unsafe fn define_instanced_vertex_attributes(&self, gl: &glow::Context) {
+        let first = self.format.instance_location();
         let u32_size = core::mem::size_of::<u32>() as i32;
         let vec4_size = core::mem::size_of::<cgmath::Vector4<f32>>() as i32;
         let vec3_size = core::mem::size_of::<cgmath::Vector3<f32>>() as i32;
         let stride = u32_size + 4 * vec4_size + 3 * vec3_size;
-        gl.enable_vertex_attrib_array(VERTEX_ATTRIBUTES_COUNT);
-        gl.vertex_attrib_pointer_i32(VERTEX_ATTRIBUTES_COUNT, 1, glow::UNSIGNED_INT, stride, 0);
+        gl.enable_vertex_attrib_array(first);
+        gl.vertex_attrib_pointer_i32(first, 1, glow::UNSIGNED_INT, stride, 0);
 
         // instance model mat4
-        gl.enable_vertex_attrib_array(VERTEX_ATTRIBUTES_COUNT + 1);
-        gl.vertex_attrib_pointer_f32(VERTEX_ATTRIBUTES_COUNT + 1, 4, glow::FLOAT, false, stride, u32_size);
-        gl.enable_vertex_attrib_array(VERTEX_ATTRIBUTES_COUNT + 2);
-        gl.vertex_attrib_pointer_f32(VERTEX_ATTRIBUTES_COUNT + 2, 4, glow::FLOAT, false, stride, vec4_size + u32_size);
-        gl.enable_vertex_attrib_array(VERTEX_ATTRIBUTES_COUNT + 3);
-        gl.vertex_attrib_pointer_f32(VERTEX_ATTRIBUTES_COUNT + 3, 4, glow::FLOAT, false, stride, 2 * vec4_size + u32_size);
-        gl.enable_vertex_attrib_array(VERTEX_ATTRIBUTES_COUNT + 4);
-        gl.vertex_attrib_pointer_f32(VERTEX_ATTRIBUTES_COUNT + 4, 4, glow::FLOAT, false, stride, 3 * vec4_size + u32_size);
+        gl.enable_vertex_attrib_array(first + 1);
+        gl.vertex_attrib_pointer_f32(first + 1, 4, glow::FLOAT, false, stride, u32_size);
+        gl.enable_vertex_attrib_array(first + 2);
+        gl.vertex_attrib_pointer_f32(first + 2, 4, glow::FLOAT, false, stride, vec4_size + u32_size);
+        gl.enable_vertex_attrib_array(first + 3);
+        gl.vertex_attrib_pointer_f32(first + 3, 4, glow::FLOAT, false, stride, 2 * vec4_size + u32_size);
+        gl.enable_vertex_attrib_array(first + 4);
+        gl.vertex_attrib_pointer_f32(first + 4, 4, glow::FLOAT, false, stride, 3 * vec4_size + u32_size);
 
         let offset = 4 * vec4_size + u32_size;
         // instance normal matrix mat3
-        gl.enable_vertex_attrib_array(VERTEX_ATTRIBUTES_COUNT + 5);
-        gl.vertex_attrib_pointer_f32(VERTEX_ATTRIBUTES_COUNT + 5, 3, glow::FLOAT, false, stride, offset);
-        gl.enable_vertex_attrib_array(VERTEX_ATTRIBUTES_COUNT + 6);
-        gl.vertex_attrib_pointer_f32(VERTEX_ATTRIBUTES_COUNT + 6, 3, glow::FLOAT, false, stride, vec3_size + offset);
-        gl.enable_vertex_attrib_array(VERTEX_ATTRIBUTES_COUNT + 7);
-        gl.vertex_attrib_pointer_f32(VERTEX_ATTRIBUTES_COUNT + 7, 3, glow::FLOAT, false, stride, 2 * vec3_size + offset);
+        gl.enable_vertex_attrib_array(first + 5);
+        gl.vertex_attrib_pointer_f32(first + 5, 3, glow::FLOAT, false, stride, offset);
+        gl.enable_vertex_attrib_array(first + 6);
+        gl.vertex_attrib_pointer_f32(first + 6, 3, glow::FLOAT, false, stride, vec3_size + offset);
+        gl.enable_vertex_attrib_array(first + 7);
+        gl.vertex_attrib_pointer_f32(first + 7, 3, glow::FLOAT, false, stride, 2 * vec3_size + offset);
     
         // make these properties update per index instead of per vertex
         for i in 0..8 {
-            gl.vertex_attrib_divisor(VERTEX_ATTRIBUTES_COUNT + i, 1);
+            gl.vertex_attrib_divisor(first + i, 1);
         }
     }
 }
 
 pub struct MeshBank {
-    pub meshes: HashMap<String, Mesh>
+    pub meshes: HashMap<String, Mesh>,
+    /// Lower detail levels of meshes by mesh name, sorted by distance, see `lod`
+    pub lods: HashMap<String, Vec<MeshLod>>
 }
 
 impl MeshBank {
     pub fn new() -> Self {
         Self {
-            meshes: HashMap::new()
+            meshes: HashMap::new(),
+            lods: HashMap::new()
         }
     }
 
@@ -237,11 +464,7 @@ impl MeshBank {
     }
 
     pub fn load_from_obj(&mut self, name: &str, gl: &glow::Context) {
-        let meshes = Mesh::load_from_obj(name, gl).expect("Failed to load .obj file");
-
-        for (i, mesh) in meshes.into_iter().enumerate() {
-            self.add(mesh, &format!("File_{}{}", name, i));
-        }
+        self.load_from_obj_vcolor(name, name, 1.0, 1.0, 1.0, gl);
     }
 
     pub fn load_from_obj_vcolor(&mut self, file: &str, name: &str, r: VertexComponent, g: VertexComponent, b: VertexComponent, gl: &glow::Context) {
@@ -250,10 +473,14 @@ impl MeshBank {
         for (i, mesh) in meshes.into_iter().enumerate() {
             self.add(mesh, &format!("File_{}{}", name, i));
         }
+        for problem in self.load_lods(file, name, [r, g, b], gl) {
+            eprintln!("{}", problem);
+        }
     }
 }
 
 // https://pastebin.com/XiCprv6S
+// VertexFormat::STANDARD
 const CUBE_VERTICES: [VertexComponent; 264] = [
     // -Z
     -0.5, -0.5, -0.5, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0,  // A 0
@@ -0,0 +1,80 @@
+use serde_json::Value;
+
+/// Version written into saved levels<br>
+/// Bump this and add a migration to `MIGRATIONS` whenever the saved fields change, even ones with serde defaults, binary levels only decode with the exact layout
+pub const LEVEL_VERSION: u32 = 3;
+
+/// `MIGRATIONS[n]` turns a level of version `n + 1` into version `n + 2`
+const MIGRATIONS: [fn(&mut Value) -> Result<(), String>; LEVEL_VERSION as usize - 1] = [
+    migrate_v1_to_v2,
+    migrate_v2_to_v3
+];
+
+/// Bring a level's JSON up to `LEVEL_VERSION`, returns the version it was saved with<br>
+/// Levels saved before versioning have no version and are version 1
+pub fn migrate_level(level: &mut Value) -> Result<u32, String> {
+    let version = match level.get("version") {
+        None => 1,
+        Some(version) => version.as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .filter(|version| *version >= 1)
+            .ok_or_else(|| format!("level version {} is not a version", version))?
+    };
+    if version > LEVEL_VERSION {
+        return Err(format!("level is version {}, this editor only reads up to version {}", version, LEVEL_VERSION));
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize - 1) {
+        migration(level).map_err(|e| format!("failed to upgrade level from version {} to {}: {}", from + 1, from + 2, e))?;
+    }
+    level["version"] = Value::from(LEVEL_VERSION);
+    Ok(version)
+}
+
+/// Brushes on models got texture alignment, written as a fifth entry
+fn migrate_v1_to_v2(level: &mut Value) -> Result<(), String> {
+    let Some(models) = level.get_mut("models").and_then(Value::as_array_mut) else { return Ok(()); };
+    for (i, model) in models.iter_mut().enumerate() {
+        let Some(renderables) = model.get_mut("renderables").and_then(Value::as_array_mut) else { continue; };
+        for renderable in renderables.iter_mut() {
+            let Some(brush) = renderable.get_mut("Brush") else { continue; };
+            let fields = brush.as_array_mut().ok_or_else(|| format!("brush on model {} is not a list", i))?;
+            if fields.len() != 4 {
+                return Err(format!("brush on model {} has {} entries instead of 4", i, fields.len()));
+            }
+            fields.push(Value::Null);
+        }
+    }
+    Ok(())
+}
+
+/// Layout changes since version 2:
+/// - models got animated billboards, a new renderable after the others
+/// - point lights got `cast_shadows`
+/// - the environment got HDR settings and distance fog
+/// - levels got post effects, the chain's uniforms are always a list
+/// - completed objectives are no longer saved, they only last for a play session
+/// - brushes got persistent ids, numbered in the order the level lists them so it stays the same
+fn migrate_v2_to_v3(level: &mut Value) -> Result<(), String> {
+    if let Some(chain) = level.pointer_mut("/effects/chain").and_then(Value::as_array_mut) {
+        for effect in chain.iter_mut() {
+            let Some(values) = effect.get_mut("values").and_then(Value::as_object_mut) else { continue; };
+            for value in values.values_mut() {
+                if value.is_number() {
+                    *value = Value::Array(vec![value.take()]);
+                }
+            }
+        }
+    }
+
+    if let Some(level) = level.as_object_mut() {
+        level.remove("completed_objectives");
+    }
+
+    let Some(brushes) = level.get_mut("brushes").and_then(Value::as_array_mut) else { return Ok(()); };
+    for (i, brush) in brushes.iter_mut().enumerate() {
+        let brush = brush.as_object_mut().ok_or_else(|| format!("brush {} is not an object", i))?;
+        brush.insert(String::from("id"), Value::from(i as u64 + 1));
+    }
+    Ok(())
+}
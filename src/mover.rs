@@ -0,0 +1,189 @@
+use cgmath::{vec3, InnerSpace, Matrix4, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{common, component::Component, shader::ProgramBank, world::{Model, Selection, World}};
+
+const PATH_COLOR: Vector3<f32> = vec3(0.9, 0.8, 0.2);
+
+/// What a mover does after the last stop
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+pub enum MoverMode {
+    /// Stays at the last stop
+    Once,
+    /// Goes straight back to the first stop and around again
+    Loop,
+    /// Goes back through the stops in reverse
+    PingPong
+}
+
+impl MoverMode {
+    pub const ALL: [MoverMode; 3] = [Self::Once, Self::Loop, Self::PingPong];
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Once => "once",
+            Self::Loop => "loop",
+            Self::PingPong => "ping_pong"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+/// Moves the model between stops while playing, like an elevator or a moving platform<br>
+/// Fires `OnArrived` at each stop, takes `Start` and `Stop`
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Mover {
+    /// Offsets from where the model was placed, visited in order, the first is usually zero
+    pub stops: Vec<[f32; 3]>,
+    /// Units per second
+    pub speed: f32,
+    /// Seconds spent at each stop
+    pub wait: f32,
+    pub mode: MoverMode,
+    /// Wait for a `Start` input before moving
+    #[serde(default)]
+    pub start_stopped: bool,
+    #[serde(skip)]
+    origin: [f32; 3],
+    #[serde(skip)]
+    offset: [f32; 3],
+    #[serde(skip)]
+    started: bool,
+    /// Index of the stop it is heading to
+    #[serde(skip)]
+    next: usize,
+    #[serde(skip)]
+    reverse: bool,
+    #[serde(skip)]
+    waiting: f32,
+    #[serde(skip)]
+    pub stopped: bool
+}
+
+impl Mover {
+    pub fn new() -> Self {
+        Self {
+            stops: vec![[0.0; 3], [0.0, 4.0, 0.0]], speed: 2.0, wait: 1.0, mode: MoverMode::PingPong, start_stopped: false,
+            origin: [0.0; 3], offset: [0.0; 3], started: false, next: 0, reverse: false, waiting: 0.0, stopped: false
+        }
+    }
+
+    pub fn on_input(&mut self, input: &str) {
+        match input {
+            "Start" => self.stopped = false,
+            "Stop" => self.stopped = true,
+            _ => ()
+        }
+    }
+
+    /// Stop after `next`, `None` once a `Once` mover is done
+    fn after(&self, next: usize) -> Option<(usize, bool)> {
+        let last = self.stops.len() - 1;
+        match self.mode {
+            MoverMode::Once => (next < last).then_some((next + 1, false)),
+            MoverMode::Loop => Some(((next + 1) % self.stops.len(), false)),
+            MoverMode::PingPong => Some(match (self.reverse, next) {
+                (false, n) if n == last => (n - 1, true),
+                (true, 0) => (1, false),
+                (false, n) => (n + 1, false),
+                (true, n) => (n - 1, true)
+            })
+        }
+    }
+
+    /// Called from `Component::on_update`, moves the model and fires `OnArrived`
+    pub fn update(&mut self, mut model: Model, world: &mut World, delta_time: f32) -> Model {
+        if !world.do_game_logic {
+            if self.started {
+                self.started = false;
+                let transform = Matrix4::from_translation(self.origin.into()) * common::mat4_remove_translation(model.transform);
+                model = world.set_model_transform_external(model, transform);
+            }
+            return model;
+        }
+
+        if !self.started {
+            self.started = true;
+            self.origin = model.origin().into();
+            self.offset = self.stops.first().copied().unwrap_or_default();
+            self.next = 0;
+            self.reverse = false;
+            self.waiting = self.wait;
+            self.stopped = self.start_stopped;
+            if self.stops.len() > 1 {
+                self.next = 1;
+            }
+        }
+
+        if self.stops.len() < 2 || self.stopped || self.next >= self.stops.len() {
+            return model;
+        }
+        if self.waiting > 0.0 {
+            self.waiting -= delta_time;
+            return model;
+        }
+
+        let offset = Vector3::from(self.offset);
+        let to_stop = Vector3::from(self.stops[self.next]) - offset;
+        let step = self.speed.max(0.0) * delta_time;
+        if to_stop.magnitude() <= step {
+            self.offset = self.stops[self.next];
+            self.waiting = self.wait;
+            world.fire_output(&mut model, "OnArrived");
+            match self.after(self.next) {
+                Some((next, reverse)) => {
+                    self.next = next;
+                    self.reverse = reverse;
+                },
+                // Past the end, nothing more to do
+                None => self.next = self.stops.len()
+            }
+        } else {
+            self.offset = (offset + to_stop.normalize() * step).into();
+        }
+
+        let transform = Matrix4::from_translation(Vector3::from(self.origin) + Vector3::from(self.offset)) * common::mat4_remove_translation(model.transform);
+        world.set_model_transform_external(model, transform)
+    }
+}
+
+impl World {
+    pub fn get_mover(&self, model: usize) -> Option<Mover> {
+        self.models.get(model)?.as_ref()?.components.iter().find_map(|component| match component {
+            Component::Mover(mover) => Some(mover.clone()),
+            _ => None
+        })
+    }
+
+    pub fn set_mover(&mut self, model: usize, mover: Mover) {
+        let Some(target) = self.models[model].as_mut() else { return; };
+        for component in target.components.iter_mut() {
+            if let Component::Mover(old) = component {
+                *old = mover.clone();
+            }
+        }
+    }
+
+    /// Arrows between the stops of selected movers
+    pub unsafe fn render_mover_paths(&self, programs: &mut ProgramBank, gl: &glow::Context) {
+        if !self.editor_data.active || self.do_game_logic {
+            return;
+        }
+        let Some(selection) = &self.editor_data.selected_object else { return; };
+        for item in selection.items() {
+            let Selection::Model(model) = item else { continue; };
+            let Some(mover) = self.get_mover(model) else { continue; };
+            let origin = self.models[model].as_ref().unwrap().origin();
+            let stops: Vec<Vector3<f32>> = mover.stops.iter().map(|stop| origin + Vector3::from(*stop)).collect();
+            for pair in stops.windows(2) {
+                self.scene.debug_render_arrow(pair[0], pair[1], PATH_COLOR, self.editor_data.selection_box_vao.unwrap(), programs, gl);
+            }
+            if mover.mode == MoverMode::Loop && stops.len() > 2 {
+                self.scene.debug_render_arrow(stops[stops.len() - 1], stops[0], PATH_COLOR, self.editor_data.selection_box_vao.unwrap(), programs, gl);
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use cgmath::{vec3, Matrix4, Vector3, Zero};
+use serde::{Deserialize, Serialize};
+
+use crate::{common, component::Component, flipbook::Flipbook, mesh::flags, shader::ProgramBank, world::{Model, Renderable, TextLabel, World}};
+
+/// Colors the Notes window cycles through
+pub const NOTE_COLORS: [[f32; 3]; 4] = [[1.0, 0.85, 0.3], [1.0, 0.35, 0.3], [0.45, 0.9, 0.45], [0.45, 0.7, 1.0]];
+const NOTE_ICON_SIZE: f32 = 0.5;
+const NOTE_TEXT_HEIGHT: f32 = 0.2;
+const NOTE_FLAGS: u32 = flags::FULLBRIGHT | flags::CUTOUT | flags::EDITOR_ONLY;
+
+/// A TODO or review comment left in the level, only shown while editing<br>
+/// The model it is on shows an icon with the text above it
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Note {
+    pub text: String,
+    pub color: [f32; 3],
+    /// World position an arrow from the note points at
+    pub target: Option<[f32; 3]>
+}
+
+impl Note {
+    pub fn new(text: &str) -> Self {
+        Self { text: text.to_string(), color: NOTE_COLORS[0], target: None }
+    }
+
+    /// The next color in `NOTE_COLORS`
+    pub fn next_color(&self) -> [f32; 3] {
+        let current = NOTE_COLORS.iter().position(|color| *color == self.color).unwrap_or(NOTE_COLORS.len() - 1);
+        NOTE_COLORS[(current + 1) % NOTE_COLORS.len()]
+    }
+
+    fn renderables(&self) -> Vec<Renderable> {
+        let label = TextLabel { text: self.text.clone(), height: NOTE_TEXT_HEIGHT, color: self.color, billboard: true };
+        let above = (NOTE_ICON_SIZE + label.size().1) / 2.0;
+        vec![
+            Renderable::Billboard(String::from("note"), Vector3::zero(), (NOTE_ICON_SIZE, NOTE_ICON_SIZE), NOTE_FLAGS, false, Flipbook::default()),
+            Renderable::Text(label, vec3(0.0, above, 0.0), NOTE_FLAGS)
+        ]
+    }
+}
+
+impl World {
+    /// Insert a model showing `note` at `position`
+    pub fn place_note(&mut self, position: Vector3<f32>, note: Note) -> usize {
+        let half = NOTE_ICON_SIZE / 2.0;
+        let model = Model::new(false, Matrix4::from_translation(position), note.renderables())
+            .with_component(Component::Note(note))
+            .collider_cuboid(Vector3::zero(), vec3(half, half, half))
+            .non_solid();
+        self.insert_model(model)
+    }
+
+    /// Every note in the level with the index of its model
+    pub fn notes(&self) -> Vec<(usize, Note)> {
+        self.models.iter().enumerate().filter_map(|(i, model)| {
+            model.as_ref()?.components.iter().find_map(|component| match component {
+                Component::Note(note) => Some((i, note.clone())),
+                _ => None
+            })
+        }).collect()
+    }
+
+    /// Replace the note on `model` and redraw its icon and text
+    pub fn set_note(&mut self, model: usize, note: Note) {
+        let Some(mut taken) = self.models[model].take() else { return; };
+
+        // Note models only have the icon and text, so the collider references stay the same
+        while !taken.render.is_empty() {
+            self.scene.remove_renderable(&mut taken, taken.render.len() - 1);
+        }
+        for renderable in note.renderables() {
+            self.scene.amend_model(&mut taken, renderable);
+        }
+        for component in taken.components.iter_mut() {
+            if let Component::Note(old) = component {
+                *old = note.clone();
+            }
+        }
+
+        self.models[model] = Some(taken);
+    }
+
+    /// Lines from notes to their targets, call while editing
+    pub unsafe fn render_note_arrows(&self, programs: &mut ProgramBank, gl: &glow::Context) {
+        if !self.editor_data.active {
+            return;
+        }
+
+        for (model, note) in self.notes() {
+            let Some(target) = note.target else { continue; };
+            let start = common::translation(self.models[model].as_ref().unwrap().transform);
+            self.scene.debug_render_arrow(start, target.into(), note.color.into(), self.editor_data.selection_box_vao.unwrap(), programs, gl);
+        }
+    }
+}
+
@@ -0,0 +1,134 @@
+use cgmath::{EuclideanSpace, MetricSpace, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{component::Component, events::EventKind, world::World};
+
+/// Objectives without a completion trigger are done once the player is this close to the target
+const REACH_DISTANCE: f32 = 2.0;
+
+/// Something for the player to do, registered by putting the component on a model<br>
+/// Objectives are done one at a time, lowest `order` first
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Objective {
+    pub title: String,
+    #[serde(default)]
+    pub order: u32,
+    /// Name of the model the marker points at, the objective's own model if `None`
+    #[serde(default)]
+    pub target: Option<String>,
+    /// Name of a trigger that completes the objective when the player enters it, otherwise reaching the target does
+    #[serde(default)]
+    pub trigger: Option<String>
+}
+
+impl Objective {
+    pub fn new(title: &str) -> Self {
+        Self { title: title.to_string(), order: 0, target: None, trigger: None }
+    }
+}
+
+/// Where the HUD points for the active objective
+pub struct ObjectiveMarker {
+    pub title: String,
+    pub distance: f32,
+    /// -1 to 1 across the screen, up is positive, clamped to the edge when the target is off screen
+    pub screen: (f32, f32),
+    pub off_screen: bool
+}
+
+impl World {
+    pub fn get_objective(&self, model: usize) -> Option<Objective> {
+        self.models.get(model)?.as_ref()?.components.iter().find_map(|component| match component {
+            Component::Objective(objective) => Some(objective.clone()),
+            _ => None
+        })
+    }
+
+    pub fn set_objective(&mut self, model: usize, objective: Objective) {
+        let Some(target) = self.models[model].as_mut() else { return; };
+        for component in target.components.iter_mut() {
+            if let Component::Objective(old) = component {
+                *old = objective.clone();
+            }
+        }
+    }
+
+    /// Every objective in the level with the model it is on, in the order they are done
+    pub fn objectives(&self) -> Vec<(usize, Objective)> {
+        let mut objectives: Vec<(usize, Objective)> = (0..self.models.len())
+            .filter_map(|model| Some((model, self.get_objective(model)?)))
+            .collect();
+        objectives.sort_by(|a, b| a.1.order.cmp(&b.1.order).then_with(|| a.1.title.cmp(&b.1.title)));
+        objectives
+    }
+
+    /// The first objective that isn't complete
+    pub fn active_objective(&self) -> Option<(usize, Objective)> {
+        self.objectives().into_iter().find(|(_, objective)| !self.completed_objectives.contains(&objective.title))
+    }
+
+    pub fn complete_objective(&mut self, title: &str) {
+        if !self.completed_objectives.iter().any(|completed| completed == title) {
+            self.completed_objectives.push(title.to_string());
+            self.editor_data.show_debug.push(format!("objective complete: {}", title));
+            self.log_event(EventKind::ObjectiveCompleted, title.to_string(), None, String::new());
+        }
+    }
+
+    /// Complete whichever objective is active
+    pub fn advance_objective(&mut self) {
+        if let Some((_, objective)) = self.active_objective() {
+            self.complete_objective(&objective.title);
+        }
+    }
+
+    fn objective_target(&self, model: usize, objective: &Objective) -> Option<Vector3<f32>> {
+        let target = match &objective.target {
+            Some(name) => self.find_by_name(name)?,
+            None => model
+        };
+        self.models[target].as_ref()?;
+        Some(self.model_center(target))
+    }
+
+    /// Complete the active objective once its trigger is entered or its target is reached, only while playing
+    pub fn update_objectives(&mut self) {
+        if !self.do_game_logic {
+            return;
+        }
+        let Some((model, objective)) = self.active_objective() else { return; };
+        let done = match &objective.trigger {
+            Some(name) => self.find_by_name(name).is_some_and(|trigger| self.player_in_trigger(trigger)),
+            None => self.objective_target(model, &objective).is_some_and(|target| target.distance(self.player.position) < REACH_DISTANCE)
+        };
+        if done {
+            self.complete_objective(&objective.title);
+        }
+    }
+
+    pub fn objective_marker(&self) -> Option<ObjectiveMarker> {
+        let (model, objective) = self.active_objective()?;
+        let target = self.objective_target(model, &objective)?;
+        let camera = &self.scene.camera;
+        let clip = camera.projection * camera.view * target.extend(1.0);
+
+        // Dividing by the absolute w keeps targets behind the camera on the side they are on
+        let behind = clip.w <= 0.0;
+        let mut screen = (clip.x / clip.w.abs().max(0.0001), clip.y / clip.w.abs().max(0.0001));
+        let off_screen = behind || screen.0.abs() > 1.0 || screen.1.abs() > 1.0;
+        if off_screen {
+            if behind && screen.0.abs() < 0.0001 && screen.1.abs() < 0.0001 {
+                screen.1 = -1.0;
+            }
+            let largest = screen.0.abs().max(screen.1.abs());
+            screen = (screen.0 / largest, screen.1 / largest);
+        }
+
+        Some(ObjectiveMarker {
+            title: objective.title,
+            distance: target.distance(camera.pos.to_vec()),
+            screen,
+            off_screen
+        })
+    }
+}
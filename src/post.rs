@@ -0,0 +1,157 @@
+use std::{collections::BTreeMap, fs};
+
+use glow::HasContext;
+use serde::{Deserialize, Serialize};
+
+use crate::shader::{Program, ProgramBank, UniformValue};
+
+/// Each effect is a `{name}.frag.glsl` with a `{name}.json` manifest next to it
+pub const POST_DIRECTORY: &str = "res/shaders/post";
+/// Chain effects are drawn with the same full screen triangle strip as `screen`
+const POST_VERTEX_SHADER: &str = "res/shaders/screen.vert.glsl";
+
+fn default_max() -> f32 {
+    1.0
+}
+
+/// Parameter of a post effect, shown as a slider for each component
+#[derive(Clone, Deserialize, Debug)]
+pub struct PostUniform {
+    pub name: String,
+    pub default: UniformValue,
+    #[serde(default)]
+    pub min: f32,
+    #[serde(default = "default_max")]
+    pub max: f32
+}
+
+#[derive(Deserialize, Debug)]
+struct PostManifest {
+    /// Shown in the effects window, the file name if missing
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    enabled: bool,
+    #[serde(default)]
+    uniforms: Vec<PostUniform>
+}
+
+/// Screen effect from `res/shaders/post`, drawn after the built in effects<br>
+/// The shader gets `screenTexture`, `depthTexture`, `TexCoord`, `time` and `screenSize`, plus its own uniforms
+pub struct PostEffect {
+    /// File name without the extension
+    pub file: String,
+    pub name: String,
+    pub enabled: bool,
+    pub uniforms: Vec<PostUniform>,
+    /// Current value of each uniform, starts as the defaults
+    pub values: Vec<UniformValue>
+}
+
+impl PostEffect {
+    /// Name of the effect's program in the `ProgramBank`
+    pub fn program_name(&self) -> String {
+        format!("post/{}", self.file)
+    }
+
+    pub unsafe fn apply_uniforms(&self, program: &mut Program, gl: &glow::Context) {
+        for (uniform, value) in self.uniforms.iter().zip(self.values.iter()) {
+            value.apply(program, &uniform.name, gl);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.values = self.uniforms.iter().map(|uniform| uniform.default.clone()).collect();
+    }
+}
+
+/// A chain effect as saved in a level, matched to the loaded effects by file name
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct PostEffectSettings {
+    pub file: String,
+    pub enabled: bool,
+    /// Components of each uniform by name, uniforms the effect no longer has are ignored
+    #[serde(default)]
+    pub values: BTreeMap<String, Vec<f32>>
+}
+
+impl PostEffect {
+    pub fn settings(&self) -> PostEffectSettings {
+        PostEffectSettings {
+            file: self.file.clone(),
+            enabled: self.enabled,
+            values: self.uniforms.iter().map(|uniform| uniform.name.clone()).zip(self.values.iter().map(|value| value.components().to_vec())).collect()
+        }
+    }
+}
+
+/// Put `chain` in the order of `settings` and apply their toggles and values, effects not in `settings` go last unchanged
+pub fn apply_chain_settings(chain: &mut [PostEffect], settings: &[PostEffectSettings]) {
+    if settings.is_empty() {
+        return;
+    }
+    chain.sort_by_key(|effect| settings.iter().position(|saved| saved.file == effect.file).unwrap_or(usize::MAX));
+    for effect in chain.iter_mut() {
+        let Some(saved) = settings.iter().find(|saved| saved.file == effect.file) else { continue; };
+        effect.enabled = saved.enabled;
+        effect.reset();
+        for (uniform, value) in effect.uniforms.iter().zip(effect.values.iter_mut()) {
+            // A saved value of the wrong shape would upload the wrong uniform type
+            if let Some(saved) = saved.values.get(&uniform.name).and_then(|saved| value.with_components(saved)) {
+                *value = saved;
+            }
+        }
+    }
+}
+
+/// Compile every effect in `res/shaders/post`, sorted by file name<br>
+/// Effects that fail to load are left out and their errors returned
+pub unsafe fn load_post_effects(programs: &mut ProgramBank, gl: &glow::Context) -> (Vec<PostEffect>, Vec<String>) {
+    let mut effects = Vec::new();
+    let mut errors = Vec::new();
+    let Ok(entries) = fs::read_dir(POST_DIRECTORY) else { return (effects, errors); };
+
+    let mut files: Vec<String> = entries.flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".frag.glsl").map(str::to_string))
+        .collect();
+    files.sort();
+    for file in files {
+        match load_post_effect(&file, programs, gl) {
+            Ok(effect) => effects.push(effect),
+            Err(e) => errors.push(format!("post effect {}: {}", file, e))
+        }
+    }
+    (effects, errors)
+}
+
+unsafe fn load_post_effect(file: &str, programs: &mut ProgramBank, gl: &glow::Context) -> Result<PostEffect, String> {
+    let manifest_path = format!("{}/{}.json", POST_DIRECTORY, file);
+    let manifest: PostManifest = match fs::read_to_string(&manifest_path) {
+        Ok(json) => serde_json::from_str(&json).map_err(|e| format!("bad manifest: {}", e))?,
+        // A shader without parameters doesn't need a manifest
+        Err(_) => PostManifest { name: None, enabled: false, uniforms: Vec::new() }
+    };
+    if let Some(uniform) = manifest.uniforms.iter().find(|uniform| !uniform.default.is_valid()) {
+        return Err(format!("uniform {} needs a number or 2 to 4 numbers", uniform.name));
+    }
+
+    let vertex_src = fs::read_to_string(POST_VERTEX_SHADER).map_err(|e| format!("failed to open {}: {}", POST_VERTEX_SHADER, e))?;
+    let frag_src = fs::read_to_string(format!("{}/{}.frag.glsl", POST_DIRECTORY, file)).map_err(|e| format!("failed to open shader: {}", e))?;
+    let name = format!("post/{}", file);
+    let program = Program::try_from_vert_frag(&vertex_src, &frag_src, &name, programs.version, gl)?;
+    // Replace the old program when reloading
+    if let Some(old) = programs.programs.remove(&name) {
+        gl.delete_program(old.inner);
+    }
+    programs.add(&name, program);
+
+    let mut effect = PostEffect {
+        file: file.to_string(),
+        name: manifest.name.unwrap_or_else(|| file.to_string()),
+        enabled: manifest.enabled,
+        uniforms: manifest.uniforms,
+        values: Vec::new()
+    };
+    effect.reset();
+    Ok(effect)
+}
@@ -1,10 +1,10 @@
-use std::{fs::File, io::Read, path::Path};
+use std::{fs::{self, File}, io::Read, path::Path};
 
-use cgmath::{vec3, Matrix4, Rad};
+use cgmath::{vec3, Matrix4, Rad, SquareMatrix, Transform, Vector3, Zero};
 use itertools::Itertools;
-use serde_json as json;
+use serde_json::{self as json, json};
 
-use crate::{component::{self, Component, Trigger, TriggerType}, mesh::{flags, MeshBank}, texture::TextureBank, world::{self, Renderable, World}};
+use crate::{audio::{MusicChannel, SoundEmitter}, button::Button, common, component::{self, Component, LightAnimation, LightAnimator, Rotator, SpotLightSource, TargetAction, Trigger, TriggerType}, connection::Connection, feed::CameraFeed, flipbook::Flipbook, goal::Goal, impostor::{DrawDistance, FarMode, DEFAULT_DRAW_DISTANCE}, mesh::{flags, BrushFaces, MeshBank}, mover::{Mover, MoverMode}, objective::Objective, reflection::ReflectionPlane, render::PointLight, rules::DEFAULT_HURT_DPS, script::Script, session::EditKind, texture::TextureBank, world::{self, Renderable, Selection, TextLabel, World}};
 
 pub const HIDDEN_DEFAULT: bool = false;
 pub const SOLID_DEFAULT: bool = false;
@@ -16,11 +16,14 @@ pub const SCALE_DEFAULT: [f32; 3] = [1.0; 3];
 pub const BRUSH_SCALE_DEFAULT: [f32; 3] = [1.0; 3];
 pub const BRUSH_MATERIAL_DEFAULT: &'static str = "rust";
 pub const COMMON_SHININESS_DEFAULT: f32 = 1.0;
+pub const TEXT_HEIGHT_DEFAULT: f32 = 0.5;
 
-const ROOT_RECOGNIZED_KEYWORDS: [&'static str; 10] = [
+const ROOT_RECOGNIZED_KEYWORDS: [&'static str; 15] = [
     "hidden", "solid", "foreground", "mobile", "position",
-    "scale", "rotation", "render", "component", "__COMMENT__"
+    "scale", "rotation", "render", "component", "lights", "colliders", "name", "tags", "connections", "__COMMENT__"
 ];
+pub const LIGHT_COLOR_DEFAULT: [f32; 3] = [1.0; 3];
+pub const LIGHT_RADIUS_DEFAULT: f32 = 10.0;
 
 #[derive(Debug)]
 enum PrefabTransform {
@@ -120,6 +123,8 @@ fn parse_render_flags(flags: &json::Value) -> u32 {
                 "cutout" => flag_aggregate |= flags::CUTOUT,
                 "fullbright" => flag_aggregate |= flags::FULLBRIGHT,
                 "skip" => flag_aggregate |= flags::SKIP,
+                "editor_only" => flag_aggregate |= flags::EDITOR_ONLY,
+                "flipbook" => flag_aggregate |= flags::FLIPBOOK,
                 _ => ()
             }
         }
@@ -128,6 +133,58 @@ fn parse_render_flags(flags: &json::Value) -> u32 {
     flag_aggregate
 }
 
+fn render_flags_json(flags: u32) -> json::Value {
+    let names = [(flags::EXTEND_TEXTURE, "extend_texture"), (flags::CUTOUT, "cutout"), (flags::FULLBRIGHT, "fullbright"), (flags::SKIP, "skip"), (flags::EDITOR_ONLY, "editor_only"), (flags::FLIPBOOK, "flipbook")];
+    names.iter().filter(|(flag, _)| flags & flag != 0).map(|(_, name)| *name).collect()
+}
+
+fn matrix_json(matrix: Matrix4<f32>) -> json::Value {
+    let rows: [[f32; 4]; 4] = matrix.into();
+    json!(rows)
+}
+
+/// Inverse of `PrefabRenderable::parse`
+fn renderable_to_prefab(renderable: &Renderable) -> json::Value {
+    match renderable {
+        Renderable::Brush(material, origin, scale, flags, faces) => {
+            let mut brush = json!({
+                "type": "brush", "origin": <[f32; 3]>::from(*origin), "scale": <[f32; 3]>::from(*scale),
+                "material": material, "flags": render_flags_json(*flags)
+            });
+            if !faces.is_default() {
+                brush["faces"] = json::to_value(faces).unwrap();
+            }
+            brush
+        },
+        Renderable::Mesh(mesh, transform, flags) => json!({
+            "type": "mesh", "mesh": mesh, "transform": matrix_json(*transform), "flags": render_flags_json(*flags)
+        }),
+        Renderable::Billboard(image, position, size, flags, follow_vertical, flipbook) => {
+            let mut billboard = json!({
+                "type": "billboard", "image": image, "position": <[f32; 3]>::from(*position), "size": [size.0, size.1],
+                "flags": render_flags_json(*flags), "follow_vertical": follow_vertical
+            });
+            if flipbook.animated() {
+                billboard["frames"] = json!(flipbook.frames);
+                billboard["fps"] = json!(flipbook.fps);
+            }
+            billboard
+        },
+        Renderable::Text(label, position, flags) => json!({
+            "type": "text", "text": label.text, "position": <[f32; 3]>::from(*position), "height": label.height,
+            "color": label.color, "billboard": label.billboard, "flags": render_flags_json(*flags)
+        })
+    }
+}
+
+/// Name of the .obj file a mesh was loaded from, meshes from files are named `File_{file}{index}`
+fn obj_for_mesh(mesh: &str) -> &str {
+    match mesh.strip_prefix("File_") {
+        Some(file) => file.trim_end_matches(|c: char| c.is_ascii_digit()),
+        None => mesh
+    }
+}
+
 #[derive(Debug)]
 enum PrefabRenderable {
     Raw(Renderable),
@@ -147,9 +204,13 @@ impl PrefabRenderable {
                 // let shininess = get_f32_or_default(json, "shininess", COMMON_SHININESS_DEFAULT);
                 let flags = json.get("flags").map(|f| parse_render_flags(f)).unwrap_or(0);
                 let material = get_string_or_default(json, "material", BRUSH_MATERIAL_DEFAULT);
+                let faces = match json.get("faces") {
+                    Some(faces) => json::from_value(faces.clone()).map_err(|e| format!("Error in prefab brush faces: {}", e))?,
+                    None => BrushFaces::default()
+                };
 
                 return Ok(PrefabRenderable::Raw(Renderable::Brush(
-                    material, origin.into(), scale.into(), flags
+                    material, origin.into(), scale.into(), flags, faces
                 )));
             },
             "mesh" => {
@@ -168,12 +229,28 @@ impl PrefabRenderable {
                 let size = get_f32_array_or_default(json, "size", [1.0; 2]);
                 let flags = json.get("flags").map(|f| parse_render_flags(f)).unwrap_or(0);
                 let follow_vertical = get_bool_or_default(json, "follow_vertical", false);
+                let flipbook = Flipbook {
+                    frames: json.get("frames").and_then(json::Value::as_u64).map_or(1, |frames| frames.max(1) as u32),
+                    fps: get_f32_or_default(json, "fps", Flipbook::default().fps)
+                };
 
                 return Ok(PrefabRenderable::Raw(Renderable::Billboard(
                     image, vec3(position[0], position[1], position[2]), (size[0], size[1]),
-                    flags, follow_vertical
+                    flags, follow_vertical, flipbook
                 )));
             },
+            "text" => {
+                let position = get_f32_array_or_default(json, "position", POSITION_DEFAULT);
+                let label = TextLabel {
+                    text: get_string_or_default(json, "text", ""),
+                    height: get_f32_or_default(json, "height", TEXT_HEIGHT_DEFAULT),
+                    color: get_f32_array_or_default(json, "color", [1.0; 3]),
+                    billboard: get_bool_or_default(json, "billboard", true)
+                };
+                let flags = json.get("flags").map(|f| parse_render_flags(f)).unwrap_or(flags::FULLBRIGHT | flags::CUTOUT);
+
+                return Ok(PrefabRenderable::Raw(Renderable::Text(label, position.into(), flags)));
+            },
             "obj" => {
                 let transform = PrefabTransform::parse_within(json)?;
                 let obj = get_string_or_default(json, "obj", "error"); // TODO
@@ -199,6 +276,70 @@ impl PrefabRenderable {
 }
 
 impl Component {
+    /// Inverse of `parse_from_prefab`, `None` for components prefabs can't hold
+    pub fn to_prefab(&self) -> Option<json::Value> {
+        match self {
+            Self::Spawnpoint => Some(json!({ "type": "spawnpoint" })),
+            Self::Hurt { dps, .. } => Some(json!({ "type": "hurt", "dps": dps })),
+            Self::Goal(goal) => Some(json!({ "type": "goal", "objectives": goal.objectives, "collectibles": goal.collectibles })),
+            Self::Collectible => Some(json!({ "type": "collectible" })),
+            Self::ReflectionPlane(plane) => Some(json!({ "type": "reflection_plane", "resolution_scale": plane.resolution_scale })),
+            Self::CameraFeed(feed) => Some(json!({
+                "type": "camera_feed", "name": feed.name, "fov": feed.fov, "resolution": [feed.resolution.0, feed.resolution.1], "interval": feed.interval
+            })),
+            Self::Door(door) => Some(json!({ "type": "door", "radius": door.radius, "height": door.height, "open_time": door.open_time, "trigger": door.trigger })),
+            Self::LightAnimator(animator) => Some(json!({
+                "type": "light_animator", "animation": animator.animation.name(), "amplitude": animator.amplitude, "speed": animator.speed
+            })),
+            Self::Script(script) => Some(json!({ "type": "script", "file": script.file })),
+            Self::SoundEmitter(emitter) => Some(json!({ "type": "sound_emitter", "sound": emitter.sound, "volume": emitter.volume, "radius": emitter.radius })),
+            Self::DrawDistance(settings) => Some(json!({
+                "type": "draw_distance", "distance": settings.distance, "far": if settings.far == FarMode::Impostor { "impostor" } else { "fade" }
+            })),
+            Self::Objective(objective) => Some(json!({
+                "type": "objective", "title": objective.title, "order": objective.order, "target": objective.target, "trigger": objective.trigger
+            })),
+            Self::Button(button) => Some(json!({
+                "type": "button", "use_range": button.use_range, "press_offset": button.press_offset, "press_time": button.press_time,
+                "target": button.target, "action": button.action.name()
+            })),
+            Self::Mover(mover) => Some(json!({
+                "type": "mover", "stops": mover.stops, "speed": mover.speed, "wait": mover.wait, "mode": mover.mode.name(),
+                "start_stopped": mover.start_stopped
+            })),
+            Self::Rotator(rotator) => Some(json!({ "type": "rotator", "axis": rotator.axis, "speed": rotator.speed })),
+            Self::SpotLight(spot) => Some(json!({
+                "type": "spot_light", "color": spot.color, "radius": spot.radius, "direction": spot.direction,
+                "inner_angle": spot.inner_angle, "outer_angle": spot.outer_angle
+            })),
+            Self::Trigger(trigger) => Some(match &trigger.kind {
+                TriggerType::SetFogEffect { color, strength, max, .. } => json!({
+                    "type": "trigger", "trigger": "fog", "color": color, "strength": strength, "max": max
+                }),
+                TriggerType::SetKernelEffect { kernel, offset, .. } => json!({
+                    "type": "trigger", "trigger": "kernel", "kernel": [&kernel[0..3], &kernel[3..6], &kernel[6..9]], "offset": offset
+                }),
+                TriggerType::SetMusic { channel, track, crossfade } => json!({
+                    "type": "trigger", "trigger": "music",
+                    "channel": if *channel == MusicChannel::Music { "music" } else { "ambience" },
+                    "track": track.clone().unwrap_or_default(), "crossfade": crossfade
+                }),
+                TriggerType::CompleteObjective { title } => json!({
+                    "type": "trigger", "trigger": "objective", "title": title
+                }),
+                TriggerType::Target { target_name, action } => json!({
+                    "type": "trigger", "trigger": "target", "target": target_name, "action": action.name()
+                }),
+                TriggerType::StartTimer => json!({ "type": "trigger", "trigger": "start" }),
+                TriggerType::Test { enter, update, exit } => json!({
+                    "type": "trigger", "trigger": "test", "enter": enter, "update": update, "exit": exit
+                })
+            }),
+            // Notes are left in the level they were written in
+            Self::Dummy | Self::Note(_) => None
+        }
+    }
+
     pub fn parse_from_prefab(json: &json::Value) -> Result<Self, String> {
         let kind = json
             .get("type").map_or(Err(String::from("Error at prefab component: no type found")), |e| Ok(e))?
@@ -208,14 +349,129 @@ impl Component {
             "spawnpoint" => {
                 return Ok(Self::Spawnpoint)
             },
+            "hurt" => {
+                return Ok(Self::Hurt { dps: get_f32_or_default(json, "dps", DEFAULT_HURT_DPS), touching: false })
+            },
+            "goal" => {
+                let mut goal = Goal::default();
+                if let Some(objectives) = json.get("objectives") {
+                    goal.objectives = json::from_value(objectives.clone()).map_err(|e| format!("Error in prefab goal: bad objectives, {}", e))?;
+                }
+                goal.collectibles = get_i32_or_default(json, "collectibles", 0).max(0) as u32;
+
+                return Ok(Self::Goal(goal))
+            },
+            "collectible" => {
+                return Ok(Self::Collectible)
+            },
+            "reflection_plane" => {
+                let resolution_scale = get_f32_or_default(json, "resolution_scale", ReflectionPlane::default().resolution_scale);
+
+                return Ok(Self::ReflectionPlane(ReflectionPlane { resolution_scale }))
+            },
+            "camera_feed" => {
+                let default = CameraFeed::default();
+                let resolution = get_f32_array_or_default(json, "resolution", [default.resolution.0 as f32, default.resolution.1 as f32]);
+
+                return Ok(Self::CameraFeed(CameraFeed {
+                    name: get_string_or_default(json, "name", &default.name),
+                    fov: get_f32_or_default(json, "fov", default.fov),
+                    resolution: (resolution[0].max(1.0) as u32, resolution[1].max(1.0) as u32),
+                    interval: get_i32_or_default(json, "interval", default.interval as i32).max(1) as u32
+                }))
+            },
             "door" => {
                 let radius = get_f32_or_default(json, "radius", 8.0);
                 let height = get_f32_or_default(json, "height", 1.0);
-                let open_time = get_i32_or_default(json, "name", 60).abs() as u32;
+                // Older prefabs wrote the open time under "name"
+                let open_time = get_i32_or_default(json, "open_time", get_i32_or_default(json, "name", 60)).abs() as u32;
+
+                let mut door = component::Door::new(radius, height, open_time);
+                door.trigger = json.get("trigger").and_then(|trigger| trigger.as_str()).map(|trigger| trigger.to_string());
+
+                return Ok(Self::Door(door))
+            },
+            "light_animator" => {
+                let name = get_string_or_default(json, "animation", "flicker");
+                let animation = LightAnimation::from_name(&name).ok_or(format!("Error in prefab light animator: unknown animation {}", name))?;
+                let preset = LightAnimator::preset(animation);
+                let amplitude = get_f32_or_default(json, "amplitude", preset.amplitude);
+                let speed = get_f32_or_default(json, "speed", preset.speed);
+
+                return Ok(Self::LightAnimator(LightAnimator::new(animation, amplitude, speed)))
+            },
+            "spot_light" => {
+                let default = SpotLightSource::new(-Vector3::unit_y());
+
+                return Ok(Self::SpotLight(SpotLightSource {
+                    color: get_f32_array_or_default(json, "color", default.color),
+                    radius: get_f32_or_default(json, "radius", default.radius),
+                    direction: get_f32_array_or_default(json, "direction", default.direction),
+                    inner_angle: get_f32_or_default(json, "inner_angle", default.inner_angle),
+                    outer_angle: get_f32_or_default(json, "outer_angle", default.outer_angle)
+                }))
+            },
+            "sound_emitter" => {
+                let sound = json.get("sound").and_then(|sound| sound.as_str()).ok_or(String::from("Error in prefab sound emitter: no sound specified"))?;
+                let default = SoundEmitter::new(sound);
+
+                return Ok(Self::SoundEmitter(SoundEmitter {
+                    volume: get_f32_or_default(json, "volume", default.volume),
+                    radius: get_f32_or_default(json, "radius", default.radius),
+                    ..default
+                }))
+            },
+            "draw_distance" => {
+                let far = match get_string_or_default(json, "far", "fade").as_str() {
+                    "fade" => FarMode::Fade,
+                    "impostor" => FarMode::Impostor,
+                    other => return Err(format!("Error in prefab draw distance: unknown far mode {}", other))
+                };
+
+                return Ok(Self::DrawDistance(DrawDistance { distance: get_f32_or_default(json, "distance", DEFAULT_DRAW_DISTANCE), far }))
+            },
+            "objective" => {
+                let title = json.get("title").and_then(|title| title.as_str()).ok_or(String::from("Error in prefab objective: no title specified"))?;
+                let mut objective = Objective::new(title);
+                objective.order = get_i32_or_default(json, "order", 0).max(0) as u32;
+                objective.target = json.get("target").and_then(|target| target.as_str()).map(|target| target.to_string());
+                objective.trigger = json.get("trigger").and_then(|trigger| trigger.as_str()).map(|trigger| trigger.to_string());
+
+                return Ok(Self::Objective(objective))
+            },
+            "button" => {
+                let mut button = Button::new();
+                button.use_range = get_f32_or_default(json, "use_range", button.use_range);
+                button.press_offset = get_f32_array_or_default(json, "press_offset", button.press_offset);
+                button.press_time = get_f32_or_default(json, "press_time", button.press_time);
+                button.target = json.get("target").and_then(|target| target.as_str()).map(|target| target.to_string());
+                let action = get_string_or_default(json, "action", "open_door");
+                button.action = TargetAction::from_name(&action).ok_or(format!("Error in prefab button: unknown action {}", action))?;
+
+                return Ok(Self::Button(button))
+            },
+            "mover" => {
+                let mut mover = Mover::new();
+                if let Some(stops) = json.get("stops") {
+                    mover.stops = json::from_value(stops.clone()).map_err(|e| format!("Error in prefab mover: bad stops, {}", e))?;
+                }
+                mover.speed = get_f32_or_default(json, "speed", mover.speed);
+                mover.wait = get_f32_or_default(json, "wait", mover.wait);
+                let mode = get_string_or_default(json, "mode", mover.mode.name());
+                mover.mode = MoverMode::from_name(&mode).ok_or(format!("Error in prefab mover: unknown mode {}", mode))?;
+                mover.start_stopped = json.get("start_stopped").and_then(|stopped| stopped.as_bool()).unwrap_or(false);
+
+                return Ok(Self::Mover(mover))
+            },
+            "rotator" => {
+                let axis = get_f32_array_or_default(json, "axis", [0.0, 1.0, 0.0]);
 
-                return Ok(Self::Door(
-                    component::Door::new(radius, height, open_time)
-                ))
+                return Ok(Self::Rotator(Rotator::new(axis.into(), get_f32_or_default(json, "speed", 90.0))))
+            },
+            "script" => {
+                let file = json.get("file").and_then(|file| file.as_str()).ok_or(String::from("Error in prefab script: no file specified"))?;
+
+                return Ok(Self::Script(Script::new(file)))
             },
             "trigger" => {
                 let trigger_type = get_string_or_default(json, "trigger", "error");
@@ -247,6 +503,35 @@ impl Component {
                             offset
                         }
                     },
+                    "music" => {
+                        let channel = match get_string_or_default(json, "channel", "music").as_str() {
+                            "music" => MusicChannel::Music,
+                            "ambience" => MusicChannel::Ambience,
+                            _ => return Err(String::from("Error in prefab music trigger: channel must be music or ambience"))
+                        };
+                        let track = get_string_or_default(json, "track", "");
+                        let crossfade = get_f32_or_default(json, "crossfade", 2.0);
+
+                        TriggerType::SetMusic {
+                            channel,
+                            track: if track.is_empty() { None } else { Some(track) },
+                            crossfade
+                        }
+                    },
+                    "objective" => {
+                        let title = json.get("title").and_then(|title| title.as_str()).map(|title| title.to_string());
+
+                        TriggerType::CompleteObjective { title }
+                    },
+                    "target" => {
+                        let target_name = json.get("target").and_then(|target| target.as_str())
+                            .ok_or(String::from("Error in prefab target trigger: no target specified"))?.to_string();
+                        let action = TargetAction::from_name(&get_string_or_default(json, "action", "open_door"))
+                            .ok_or(String::from("Error in prefab target trigger: invalid action"))?;
+
+                        TriggerType::Target { target_name, action }
+                    },
+                    "start" => TriggerType::StartTimer,
                     "test" => {
                         let enter = get_string_or_default(json, "enter", "enter");
                         let update = get_string_or_default(json, "update", "update");
@@ -272,7 +557,14 @@ pub struct UserPrefab {
     pub mobile: bool,
     transform: PrefabTransform,
     pub render: Vec<PrefabRenderable>,
-    pub components: Vec<Component>
+    pub components: Vec<Component>,
+    /// Offset from the model, light
+    pub lights: Vec<(Vector3<f32>, PointLight)>,
+    /// Added on top of the brush colliders
+    pub colliders: Vec<world::ModelCollider>,
+    pub name: Option<String>,
+    pub tags: Vec<String>,
+    pub connections: Vec<Connection>
 }
 
 fn get_bool_or_default(json: &json::Value, name: &str, default: bool) -> bool {
@@ -344,9 +636,40 @@ impl UserPrefab {
             }
         }
 
+        let mut lights = Vec::new();
+        if let Some(json::Value::Array(array)) = json.get("lights") {
+            for item in array {
+                let mut light = PointLight::default(Vector3::zero());
+                light.set_color(get_f32_array_or_default(item, "color", LIGHT_COLOR_DEFAULT).into());
+                light.set_attenuation(get_f32_or_default(item, "radius", LIGHT_RADIUS_DEFAULT));
+                light.cast_shadows = get_bool_or_default(item, "shadows", false);
+                lights.push((get_f32_array_or_default(item, "offset", POSITION_DEFAULT).into(), light));
+            }
+        }
+
+        let mut colliders = Vec::new();
+        if let Some(json::Value::Array(array)) = json.get("colliders") {
+            for item in array {
+                colliders.push(parse_collider(item)?);
+            }
+        }
+
+        let name = json.get("name").and_then(json::Value::as_str).map(str::to_owned);
+        let tags = match json.get("tags") {
+            Some(json::Value::Array(array)) => array.iter().filter_map(json::Value::as_str).map(str::to_owned).collect(),
+            _ => Vec::new()
+        };
+
+        let mut connections = Vec::new();
+        if let Some(json::Value::Array(array)) = json.get("connections") {
+            for item in array {
+                connections.push(json::from_value(item.clone()).map_err(|e| format!("Error in prefab connection: {}", e))?);
+            }
+        }
+
         Ok(Self {
             hidden, solid, foreground, mobile, transform, render: renderables,
-            components
+            components, lights, colliders, name, tags, connections
         })
     }
 
@@ -357,7 +680,8 @@ impl UserPrefab {
         for renderable in self.render.iter() {
             match renderable {
                 PrefabRenderable::Raw(Renderable::Billboard(texture, ..)) => requested_textures.push(texture.to_owned()),
-                PrefabRenderable::Raw(Renderable::Mesh(mesh, ..)) => requested_meshes.push(mesh.to_owned()),
+                // Meshes that are already loaded, like the built in cube, don't need a file
+                PrefabRenderable::Raw(Renderable::Mesh(mesh, ..)) if meshes.get(mesh).is_none() => requested_meshes.push(obj_for_mesh(mesh).to_owned()),
                 PrefabRenderable::InsertObj(obj, ..) => {
                     meshes.load_from_obj(obj, gl);
                     world.loaded_models.push(obj.to_owned());
@@ -384,12 +708,52 @@ impl UserPrefab {
         model.hidden = self.hidden;
         model.solid = self.solid;
         model.components = self.components.clone();
+        model.name = self.name.clone();
+        model.tags = self.tags.clone();
+        model.connections = self.connections.clone();
+        for collider in self.colliders.iter() {
+            model = match collider {
+                world::ModelCollider::Cuboid { offset, half_extents } => model.collider_cuboid(*offset, *half_extents),
+                world::ModelCollider::TriMesh { offset, obj } => model.collider_trimesh(*offset, obj),
+                world::ModelCollider::ConvexHull { offset, obj } => model.collider_convex_hull(*offset, obj),
+                world::ModelCollider::Multiple { .. } => unreachable!()
+            };
+        }
         model
     }
 }
 
+/// `{ "type": "cuboid", "half_extents": [..] }`, or `"trimesh"` / `"convex_hull"` with an `"obj"` from `res/models`, all with an optional `"offset"`
+fn parse_collider(json: &json::Value) -> Result<world::ModelCollider, String> {
+    let offset = get_f32_array_or_default(json, "offset", POSITION_DEFAULT).into();
+    let obj = || json.get("obj").and_then(json::Value::as_str).map(str::to_owned)
+        .ok_or_else(|| String::from("Error in prefab collider: mesh colliders need an obj"));
+    match json.get("type").and_then(json::Value::as_str) {
+        Some("cuboid") => Ok(world::ModelCollider::Cuboid { offset, half_extents: get_f32_array_or_default(json, "half_extents", [0.5; 3]).into() }),
+        Some("trimesh") => Ok(world::ModelCollider::TriMesh { offset, obj: obj()? }),
+        Some("convex_hull") => Ok(world::ModelCollider::ConvexHull { offset, obj: obj()? }),
+        _ => Err(String::from("Error in prefab collider: invalid collider type"))
+    }
+}
+
+fn collider_to_prefab(collider: &world::ModelCollider, offset: Vector3<f32>) -> json::Value {
+    match collider {
+        world::ModelCollider::Cuboid { offset: collider_offset, half_extents } => json!({
+            "type": "cuboid", "offset": <[f32; 3]>::from(collider_offset + offset), "half_extents": <[f32; 3]>::from(*half_extents)
+        }),
+        world::ModelCollider::TriMesh { offset: collider_offset, obj } => json!({
+            "type": "trimesh", "offset": <[f32; 3]>::from(collider_offset + offset), "obj": obj
+        }),
+        world::ModelCollider::ConvexHull { offset: collider_offset, obj } => json!({
+            "type": "convex_hull", "offset": <[f32; 3]>::from(collider_offset + offset), "obj": obj
+        }),
+        world::ModelCollider::Multiple { .. } => unreachable!()
+    }
+}
+
 impl World {
-    pub fn insert_prefab_from_file<P: AsRef<Path>>(&mut self, textures: &mut TextureBank, meshes: &mut MeshBank, gl: &glow::Context, path: P) -> Result<usize, String> {
+    /// Load a prefab's resources and build its model without inserting it, the lights aren't added to the scene yet
+    pub fn load_prefab_from_file<P: AsRef<Path>>(&mut self, textures: &mut TextureBank, meshes: &mut MeshBank, gl: &glow::Context, path: P) -> Result<(world::Model, Vec<(Vector3<f32>, PointLight)>), String> {
         let mut file = File::open(path).map_err(|e| e.to_string())?;
         let mut data = String::new();
         file.read_to_string(&mut data).map_err(|e| e.to_string())?;
@@ -397,6 +761,138 @@ impl World {
         let prefab = UserPrefab::parse(&prefab_source)?;
 
         unsafe { prefab.load_resources(self, textures, meshes, gl); }
-        Ok(self.insert_model(prefab.as_model(meshes)))
+        let mut model = prefab.as_model(meshes);
+        // Names are unique, a second copy of a named prefab is left unnamed
+        if let Some(name) = &model.name {
+            if self.find_by_name(name).is_some() {
+                self.editor_data.show_debug.push(format!("{} is already used, the prefab was inserted without a name", name));
+                model.name = None;
+            }
+        }
+        Ok((model, prefab.lights))
+    }
+
+    pub fn insert_prefab_from_file<P: AsRef<Path>>(&mut self, textures: &mut TextureBank, meshes: &mut MeshBank, gl: &glow::Context, path: P) -> Result<usize, String> {
+        let (mut model, lights) = self.load_prefab_from_file(textures, meshes, gl, path)?;
+        for (offset, light) in lights {
+            model = model.with_light(self.scene.add_point_light(light), offset);
+        }
+        let index = self.insert_model(model);
+        self.record_selection_edit(EditKind::Created, &[Selection::Model(index)]);
+        Ok(index)
+    }
+
+    /// Write the selection to `path` as a prefab<br>
+    /// A single model keeps its rotation and scale, otherwise everything is merged into one model around the selection's center<br>
+    /// Brushes and billboards can't rotate, so merged models only keep their offset and scale for those
+    pub fn export_selection_as_prefab<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let items = self.editor_data.selected_object.as_ref().map(Selection::items).unwrap_or_default();
+        if items.is_empty() {
+            return Err(String::from("nothing is selected"));
+        }
+
+        let brushes = self.models[self.internal.brushes].as_ref().unwrap();
+        let origins: Vec<Vector3<f32>> = items.iter().map(|item| match item {
+            Selection::Brush(brush) => match &brushes.render[*brush] {
+                Renderable::Brush(_, origin, ..) => *origin,
+                _ => Vector3::zero()
+            },
+            Selection::Model(model) => common::translation(self.models[*model].as_ref().unwrap().transform),
+            Selection::Multiple(_) => unreachable!()
+        }).collect();
+        let center = origins.iter().fold(Vector3::zero(), |sum, origin| sum + origin) / origins.len() as f32;
+        let increment = self.editor_data.increment;
+        let center = vec3(common::round_to(center.x, increment), common::round_to(center.y, increment), common::round_to(center.z, increment));
+
+        let single = match items.as_slice() {
+            [Selection::Model(model)] => self.models[*model].as_ref(),
+            _ => None
+        };
+        let first_model = items.iter().find_map(|item| match item {
+            Selection::Model(model) => self.models[*model].as_ref(),
+            _ => None
+        });
+
+        let mut render = Vec::new();
+        let mut components = Vec::new();
+        let mut lights = Vec::new();
+        let mut colliders = Vec::new();
+        let mut tags: Vec<String> = Vec::new();
+        let mut connections = Vec::new();
+
+        for item in items.iter() {
+            match item {
+                Selection::Brush(brush) => {
+                    if let Renderable::Brush(material, origin, scale, flags, faces) = &brushes.render[*brush] {
+                        render.push(renderable_to_prefab(&Renderable::Brush(material.clone(), origin - center, *scale, *flags, *faces)));
+                    }
+                },
+                Selection::Model(index) => {
+                    let model = self.models[*index].as_ref().unwrap();
+                    // Relative to the prefab's own transform
+                    let relative = if single.is_some() { Matrix4::identity() } else { Matrix4::from_translation(-center) * model.transform };
+                    let offset = common::translation(relative);
+                    for renderable in model.render.iter() {
+                        let renderable = match renderable {
+                            Renderable::Mesh(mesh, transform, flags) => Renderable::Mesh(mesh.clone(), relative * transform, *flags),
+                            Renderable::Brush(material, origin, scale, flags, faces) => {
+                                let scale = relative.transform_vector(*scale);
+                                Renderable::Brush(material.clone(), origin + offset, vec3(scale.x.abs(), scale.y.abs(), scale.z.abs()), *flags, *faces)
+                            },
+                            Renderable::Billboard(image, position, size, flags, follow_vertical, flipbook) => {
+                                Renderable::Billboard(image.clone(), position + offset, *size, *flags, *follow_vertical, *flipbook)
+                            },
+                            Renderable::Text(label, position, flags) => Renderable::Text(label.clone(), position + offset, *flags)
+                        };
+                        render.push(renderable_to_prefab(&renderable));
+                    }
+
+                    components.extend(model.components.iter().filter_map(Component::to_prefab));
+                    connections.extend(model.connections.iter().filter_map(|connection| json::to_value(connection).ok()));
+                    for tag in model.tags.iter() {
+                        if !tags.contains(tag) {
+                            tags.push(tag.clone());
+                        }
+                    }
+                    if let Some(insert_collider) = &model.insert_collider {
+                        colliders.extend(insert_collider.flatten().iter().map(|collider| collider_to_prefab(collider, offset)));
+                    }
+
+                    for (light_offset, light) in model.lights.iter() {
+                        let light = &self.scene.point_lights[*light];
+                        lights.push(json!({
+                            "offset": <[f32; 3]>::from(light_offset + offset),
+                            "color": <[f32; 3]>::from(light.user_color_or_default()),
+                            "radius": light.user_attenuation_or_default(),
+                            "shadows": light.cast_shadows
+                        }));
+                    }
+                },
+                Selection::Multiple(_) => unreachable!()
+            }
+        }
+
+        // Brushes on their own make a solid, static prefab
+        let (hidden, solid, foreground, mobile) = first_model.map_or((false, true, false, false), |model| (model.hidden, model.solid, model.foreground, model.mobile));
+        let transform = single.map_or(Matrix4::identity(), |model| common::mat4_remove_translation(model.transform));
+        // A merged prefab is a new model, so only a single model keeps its name
+        let name = single.and_then(|model| model.name.clone());
+        let prefab = json!({
+            "name": name,
+            "tags": tags,
+            "hidden": hidden,
+            "solid": solid,
+            "foreground": foreground,
+            "mobile": mobile,
+            "transform": matrix_json(transform),
+            "render": render,
+            "components": components,
+            "lights": lights,
+            "colliders": colliders,
+            "connections": connections
+        });
+
+        let src = json::to_string_pretty(&prefab).map_err(|e| e.to_string())?;
+        fs::write(path, src).map_err(|e| format!("failed to save prefab: {}", e))
     }
 }
\ No newline at end of file
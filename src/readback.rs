@@ -0,0 +1,152 @@
+use std::{fs, ptr, time::{SystemTime, UNIX_EPOCH}};
+
+use glow::{HasContext, NativeBuffer, NativeFence, NativeFramebuffer};
+use image::RgbaImage;
+
+use crate::{input::{Action, Input}, world::World};
+
+pub const SCREENSHOT_DIRECTORY: &str = "res/screenshots";
+/// Requests that still haven't finished after this many polls are read anyway, waiting on the GPU
+const MAX_WAIT_POLLS: u32 = 8;
+
+pub type ReadbackId = u64;
+
+/// Pixels copied by a `Readback` request
+pub struct Capture {
+    pub id: ReadbackId,
+    /// Top row first
+    pub image: RgbaImage
+}
+
+/// Request waiting on its fence
+struct PendingRead {
+    id: ReadbackId,
+    buffer: NativeBuffer,
+    fence: NativeFence,
+    width: u32,
+    height: u32,
+    polls: u32
+}
+
+/// Reads framebuffer regions into pixel buffers without waiting for the GPU to finish drawing<br>
+/// `request` starts a copy, `poll` once a frame picks up the copies that are done, usually a frame or two later
+#[derive(Default)]
+pub struct Readback {
+    pending: Vec<PendingRead>,
+    finished: Vec<Capture>,
+    /// Buffers of finished reads and their sizes, reused by later requests
+    free: Vec<(NativeBuffer, i32)>,
+    next_id: ReadbackId
+}
+
+impl Readback {
+    /// Start copying `(x, y, width, height)` of `framebuffer`, or of the window if `None`<br>
+    /// `y` is measured from the bottom like in OpenGL, returns the id the capture will have
+    pub unsafe fn request(&mut self, framebuffer: Option<NativeFramebuffer>, region: (i32, i32, u32, u32), gl: &glow::Context) -> Result<ReadbackId, String> {
+        let (x, y, width, height) = region;
+        let size = (width * height * 4) as i32;
+        let buffer = match self.free.iter().position(|(_, free_size)| *free_size >= size) {
+            Some(free) => self.free.swap_remove(free).0,
+            None => {
+                let buffer = gl.create_buffer()?;
+                gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(buffer));
+                gl.buffer_data_size(glow::PIXEL_PACK_BUFFER, size, glow::STREAM_READ);
+                buffer
+            }
+        };
+
+        gl.bind_framebuffer(glow::READ_FRAMEBUFFER, framebuffer);
+        gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(buffer));
+        gl.read_pixels(x, y, width as i32, height as i32, glow::RGBA, glow::UNSIGNED_BYTE, glow::PixelPackData::BufferOffset(0));
+        gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+        gl.bind_framebuffer(glow::READ_FRAMEBUFFER, None);
+        let fence = gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0)?;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.push(PendingRead { id, buffer, fence, width, height, polls: 0 });
+        Ok(id)
+    }
+
+    /// Copy out the reads the GPU has finished, call once a frame
+    pub unsafe fn poll(&mut self, gl: &glow::Context) {
+        let mut i = 0;
+        while i < self.pending.len() {
+            let read = &mut self.pending[i];
+            read.polls += 1;
+            // A timeout of 0 only checks, it doesn't wait
+            let timeout = if read.polls > MAX_WAIT_POLLS { i32::MAX } else { 0 };
+            let status = gl.client_wait_sync(read.fence, glow::SYNC_FLUSH_COMMANDS_BIT, timeout);
+            if status != glow::ALREADY_SIGNALED && status != glow::CONDITION_SATISFIED {
+                i += 1;
+                continue;
+            }
+
+            let read = self.pending.swap_remove(i);
+            gl.delete_sync(read.fence);
+            let size = (read.width * read.height * 4) as i32;
+            let mut pixels = vec![0; size as usize];
+            gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(read.buffer));
+            let mapped = gl.map_buffer_range(glow::PIXEL_PACK_BUFFER, 0, size, glow::MAP_READ_BIT);
+            if !mapped.is_null() {
+                ptr::copy_nonoverlapping(mapped, pixels.as_mut_ptr(), pixels.len());
+                gl.unmap_buffer(glow::PIXEL_PACK_BUFFER);
+            }
+            gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+            self.free.push((read.buffer, size));
+
+            let image = RgbaImage::from_raw(read.width, read.height, pixels).unwrap();
+            self.finished.push(Capture { id: read.id, image: image::imageops::flip_vertical(&image) });
+        }
+    }
+
+    /// Requests `poll` hasn't picked up yet
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// The capture for `id`, once `poll` has picked it up
+    pub fn take(&mut self, id: ReadbackId) -> Option<Capture> {
+        let index = self.finished.iter().position(|capture| capture.id == id)?;
+        Some(self.finished.swap_remove(index))
+    }
+}
+
+impl World {
+    /// Capture the window when the screenshot key is pressed, call after the world is drawn and before the UI
+    pub unsafe fn request_screenshot(&mut self, input: &Input, gl: &glow::Context) {
+        if !input.action_just_pressed(Action::Screenshot) {
+            return;
+        }
+        let (width, height) = self.scene.window_size;
+        match self.readback.request(None, (0, 0, width, height), gl) {
+            Ok(id) => self.screenshots.push(id),
+            Err(e) => self.editor_data.show_debug.push(format!("failed to take screenshot: {}", e))
+        }
+    }
+
+    /// Write screenshots that have been read back to `res/screenshots`
+    pub fn save_screenshots(&mut self) {
+        let mut i = 0;
+        while i < self.screenshots.len() {
+            let Some(capture) = self.readback.take(self.screenshots[i]) else {
+                i += 1;
+                continue;
+            };
+            self.screenshots.swap_remove(i);
+            let mut image = capture.image;
+            // The window's alpha isn't meant to be seen
+            for pixel in image.pixels_mut() {
+                pixel.0[3] = 255;
+            }
+            let millis = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_millis());
+            let path = format!("{}/screenshot_{}.png", SCREENSHOT_DIRECTORY, millis);
+            let saved = fs::create_dir_all(SCREENSHOT_DIRECTORY).map_err(|e| e.to_string())
+                .and_then(|_| image.save(&path).map_err(|e| e.to_string()));
+            self.editor_data.show_debug.push(match saved {
+                Ok(()) => format!("saved screenshot to {}", path),
+                Err(e) => format!("failed to save screenshot: {}", e)
+            });
+        }
+    }
+}
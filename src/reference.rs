@@ -0,0 +1,216 @@
+use cgmath::{vec3, InnerSpace, Matrix4, MetricSpace, Vector3};
+
+use crate::{collision::RaycastResult, shader::ProgramBank, spectator::EYE_HEIGHT, world::{World, PLAYER_SIZE}};
+
+/// Length of each arm of the ruler, it crosses at the cursor
+const RULER_LENGTH: f32 = 4.0;
+const RULER_MINOR_STEP: f32 = 0.25;
+const RULER_MINOR_TICK: f32 = 0.06;
+const RULER_MAJOR_TICK: f32 = 0.15;
+/// Lifted off the surface so it isn't hidden in it
+const RULER_LIFT: f32 = 0.01;
+const RULER_COLOR: Vector3<f32> = Vector3 { x: 1.0, y: 1.0, z: 0.3 };
+const FIGURE_COLOR: Vector3<f32> = Vector3 { x: 0.3, y: 1.0, z: 0.5 };
+/// The head is centered on the player's eye
+const FIGURE_HEAD_SIZE: f32 = 0.4;
+const COLLIDER_COLOR: Vector3<f32> = Vector3 { x: 0.15, y: 0.5, z: 0.25 };
+const TEMPLATE_COLOR: Vector3<f32> = Vector3 { x: 0.4, y: 0.7, z: 1.0 };
+/// Space left on each side of and above the player in the door and corridor templates
+const TEMPLATE_CLEARANCE: f32 = 0.25;
+const CORRIDOR_LENGTH: f32 = 4.0;
+/// Surfaces facing up at least this much count as floors, the figure and templates only stand on floors
+const FLOOR_NORMAL: f32 = 0.7;
+const MEASURE_COLOR: Vector3<f32> = Vector3 { x: 1.0, y: 0.6, z: 0.9 };
+/// The X, Y and Z legs of a measurement
+const MEASURE_AXIS_COLORS: [Vector3<f32>; 3] = [
+    Vector3 { x: 1.0, y: 0.3, z: 0.3 },
+    Vector3 { x: 0.3, y: 1.0, z: 0.3 },
+    Vector3 { x: 0.3, y: 0.5, z: 1.0 }
+];
+/// Size of the boxes on the ends of a measurement
+const MEASURE_POINT_SIZE: f32 = 0.08;
+
+/// Editor overlays for keeping the scale of a level consistent, drawn where the cursor points
+#[derive(Default)]
+pub struct ScaleReferences {
+    /// Ticks every `RULER_MINOR_STEP` and longer ones every unit
+    pub ruler: bool,
+    /// Figure the size of the player
+    pub figure: bool,
+    /// Smallest door and corridor the player fits through comfortably
+    pub templates: bool,
+    /// Clicks place the two ends of a measurement instead of selecting
+    pub measure: bool,
+    /// Position and normal of the surface under the cursor
+    hover: Option<(Vector3<f32>, Vector3<f32>)>,
+    /// Ends of the measurement, the end follows the cursor until it is placed
+    measure_points: (Option<Vector3<f32>>, Option<Vector3<f32>>)
+}
+
+impl ScaleReferences {
+    pub fn any(&self) -> bool {
+        self.ruler || self.figure || self.templates || self.measure
+    }
+
+    pub fn clear_measurement(&mut self) {
+        self.measure_points = (None, None);
+    }
+
+    /// Ends of the measurement, with the cursor standing in for an end that isn't placed yet
+    fn measured(&self) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        if !self.measure {
+            return None;
+        }
+        let from = self.measure_points.0?;
+        let to = self.measure_points.1.or(self.hover.map(|(position, _)| position))?;
+        Some((from, to))
+    }
+}
+
+/// Span between the two measured points, for the label on screen
+pub struct Measurement {
+    pub delta: Vector3<f32>,
+    pub distance: f32,
+    /// Middle of the line in normalized device coordinates, none when it is behind the camera or off the screen
+    pub screen: Option<(f32, f32)>,
+    /// Whether the end is still following the cursor
+    pub placing: bool
+}
+
+/// Size of the door and corridor templates
+pub fn template_size() -> Vector3<f32> {
+    PLAYER_SIZE + vec3(TEMPLATE_CLEARANCE * 2.0, TEMPLATE_CLEARANCE, TEMPLATE_CLEARANCE * 2.0)
+}
+
+impl World {
+    pub fn update_reference_hover(&mut self, result: Option<&RaycastResult>) {
+        let references = &mut self.editor_data.references;
+        references.hover = None;
+        if !self.editor_data.active || !references.any() {
+            return;
+        }
+        let Some(result) = result else { return; };
+        let Some(model) = result.model else { return; };
+        // Not on the gizmos
+        if model != self.internal.brushes && self.internal.internal_ids.contains(&model) {
+            return;
+        }
+        references.hover = Some((result.pos, result.normal.normalize()));
+    }
+
+    /// Place the start of a new measurement, or its end if one was started<br>
+    /// Returns true if the click was used up
+    pub fn measure_clicked(&mut self) -> bool {
+        let references = &mut self.editor_data.references;
+        if !references.measure {
+            return false;
+        }
+        let Some((position, _)) = references.hover else { return false; };
+        references.measure_points = match references.measure_points {
+            (Some(from), None) => (Some(from), Some(position)),
+            _ => (Some(position), None)
+        };
+        true
+    }
+
+    pub fn measurement(&self) -> Option<Measurement> {
+        let references = &self.editor_data.references;
+        if !self.editor_data.active || self.do_game_logic {
+            return None;
+        }
+        let (from, to) = references.measured()?;
+        let camera = &self.scene.camera;
+        let clip = camera.projection * camera.view * ((from + to) / 2.0).extend(1.0);
+        let screen = (clip.x / clip.w, clip.y / clip.w);
+        let on_screen = clip.w > 0.0 && screen.0.abs() <= 1.0 && screen.1.abs() <= 1.0;
+        Some(Measurement {
+            delta: to - from,
+            distance: from.distance(to),
+            screen: if on_screen { Some(screen) } else { None },
+            placing: references.measure_points.1.is_none()
+        })
+    }
+
+    pub unsafe fn render_scale_references(&self, programs: &mut ProgramBank, gl: &glow::Context) {
+        let references = &self.editor_data.references;
+        if !self.editor_data.active || self.do_game_logic {
+            return;
+        }
+        let vao = self.editor_data.selection_box_vao.unwrap();
+
+        if let Some((from, to)) = references.measured() {
+            self.scene.debug_render_line(from, to, MEASURE_COLOR, vao, programs, gl);
+            for point in [from, to] {
+                self.scene.debug_render_box(Matrix4::from_translation(point) * Matrix4::from_scale(MEASURE_POINT_SIZE), MEASURE_COLOR, vao, programs, gl);
+            }
+            // Steps from one end to the other along X, then Y, then Z
+            let mut corner = from;
+            for (axis, color) in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()].into_iter().zip(MEASURE_AXIS_COLORS) {
+                let leg = axis * (to - from).dot(axis);
+                self.scene.debug_render_line(corner, corner + leg, color, vao, programs, gl);
+                corner += leg;
+            }
+        }
+
+        let Some((position, normal)) = references.hover else { return; };
+
+        if references.ruler {
+            // Along the two world axes that lie flattest on the surface
+            let mut axes = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()];
+            axes.sort_by(|a, b| a.dot(normal).abs().total_cmp(&b.dot(normal).abs()));
+            let origin = position + normal * RULER_LIFT;
+            for (along, across) in [(axes[0], axes[1]), (axes[1], axes[0])] {
+                self.scene.debug_render_line(origin - along * RULER_LENGTH, origin + along * RULER_LENGTH, RULER_COLOR, vao, programs, gl);
+                let steps = (RULER_LENGTH / RULER_MINOR_STEP) as i32;
+                for step in -steps..=steps {
+                    let tick = if step % (1.0 / RULER_MINOR_STEP) as i32 == 0 { RULER_MAJOR_TICK } else { RULER_MINOR_TICK };
+                    let at = origin + along * (step as f32 * RULER_MINOR_STEP);
+                    self.scene.debug_render_line(at - across * tick, at + across * tick, RULER_COLOR, vao, programs, gl);
+                }
+            }
+        }
+
+        if normal.y < FLOOR_NORMAL {
+            return;
+        }
+        // Face the camera, with the corridor leading away from it
+        let view = self.scene.camera.direction;
+        let forward = if view.x.abs() > view.z.abs() { vec3(view.x.signum(), 0.0, 0.0) } else { vec3(0.0, 0.0, view.z.signum()) };
+        let side = forward.cross(Vector3::unit_y());
+        let up = Vector3::unit_y();
+
+        if references.figure {
+            let height = PLAYER_SIZE.y;
+            let hip = position + up * (height * 0.45);
+            let shoulder = position + up * (height * 0.65);
+            let eye = position + up * (height / 2.0 + EYE_HEIGHT);
+            for (from, to) in [
+                (position + side * 0.15, hip), (position - side * 0.15, hip), (hip, shoulder),
+                (shoulder, hip + side * 0.3), (shoulder, hip - side * 0.3)
+            ] {
+                self.scene.debug_render_line(from, to, FIGURE_COLOR, vao, programs, gl);
+            }
+            let head_transform = Matrix4::from_translation(eye) * Matrix4::from_scale(FIGURE_HEAD_SIZE);
+            self.scene.debug_render_box(head_transform, FIGURE_COLOR, vao, programs, gl);
+            let collider = Matrix4::from_translation(position + up * (height / 2.0)) * Matrix4::from_nonuniform_scale(PLAYER_SIZE.x, PLAYER_SIZE.y, PLAYER_SIZE.z);
+            self.scene.debug_render_box(collider, COLLIDER_COLOR, vao, programs, gl);
+        }
+
+        if references.templates {
+            let size = template_size();
+            let half_width = side * (size.x / 2.0);
+            let top = up * size.y;
+            // Door frame around the cursor, then the corridor behind it
+            for (from, to) in [
+                (position - half_width, position - half_width + top), (position + half_width, position + half_width + top),
+                (position - half_width + top, position + half_width + top), (position - half_width, position + half_width)
+            ] {
+                self.scene.debug_render_line(from, to, TEMPLATE_COLOR, vao, programs, gl);
+                self.scene.debug_render_line(from + forward * CORRIDOR_LENGTH, to + forward * CORRIDOR_LENGTH, TEMPLATE_COLOR, vao, programs, gl);
+            }
+            for corner in [position - half_width, position + half_width, position - half_width + top, position + half_width + top] {
+                self.scene.debug_render_line(corner, corner + forward * CORRIDOR_LENGTH, TEMPLATE_COLOR, vao, programs, gl);
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, SquareMatrix, Transform, Vector3, Vector4};
+use glow::{HasContext, NativeTexture};
+use serde::{Deserialize, Serialize};
+
+use crate::{common, component::Component, mesh::MeshBank, render::Scene, shader::{Program, ProgramBank}, target::RenderTarget, texture::TextureBank, world::World};
+
+/// Texture unit of the reflection, after the cluster textures
+pub const REFLECTION_TEXTURE_UNIT: u32 = 9;
+/// Keeps the surface on the plane out of its own reflection
+const CLIP_OFFSET: f32 = 0.01;
+
+/// Mirrors the level about the plane through the model's origin facing its +Y, into the texture reflective materials blend in<br>
+/// Only the first plane in the level is drawn, reflective surfaces should lie on it
+#[derive(Clone, Copy, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct ReflectionPlane {
+    /// Size of the reflection against the window, lower is cheaper but blurrier
+    pub resolution_scale: f32
+}
+
+impl Default for ReflectionPlane {
+    fn default() -> Self {
+        Self { resolution_scale: 0.5 }
+    }
+}
+
+/// The mirrored render of the scene, see `World::render_reflection`
+pub struct Reflection {
+    target: RenderTarget,
+    /// Copy of the target, so reflective meshes in the reflection don't read what is being drawn
+    texture: NativeTexture,
+    /// Plane the mirrored render is clipped to, facing the camera
+    plane: Vector4<f32>,
+    /// Whether `texture` holds this frame's reflection
+    ready: bool
+}
+
+/// Transform mirroring points about the plane `normal`·x + `distance` = 0
+fn reflection_matrix(normal: Vector3<f32>, distance: f32) -> Matrix4<f32> {
+    let (x, y, z) = (normal.x, normal.y, normal.z);
+    Matrix4::new(
+        1.0 - 2.0 * x * x, -2.0 * y * x, -2.0 * z * x, 0.0,
+        -2.0 * x * y, 1.0 - 2.0 * y * y, -2.0 * z * y, 0.0,
+        -2.0 * x * z, -2.0 * y * z, 1.0 - 2.0 * z * z, 0.0,
+        -2.0 * distance * x, -2.0 * distance * y, -2.0 * distance * z, 1.0
+    )
+}
+
+unsafe fn create_texture(size: (u32, u32), gl: &glow::Context) -> NativeTexture {
+    let texture = gl.create_texture().unwrap();
+    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+    gl.tex_image_2d(
+        glow::TEXTURE_2D, 0, glow::RGBA8 as i32,
+        size.0 as i32, size.1 as i32,
+        0, glow::RGBA, glow::UNSIGNED_BYTE,
+        glow::PixelUnpackData::Slice(None)
+    );
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::LINEAR as i32);
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::LINEAR as i32);
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE as i32);
+    gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE as i32);
+    gl.bind_texture(glow::TEXTURE_2D, None);
+    texture
+}
+
+impl Scene {
+    /// Set the reflection uniforms of a lighting program, reflections are off in offscreen renders and the orthographic views
+    pub unsafe fn uniform_reflection(&self, program: &mut Program, gl: &glow::Context) {
+        program.uniform_1i32("reflectionTexture", REFLECTION_TEXTURE_UNIT as i32, gl);
+        let Some(reflection) = &self.reflection else {
+            program.uniform_1i32("reflection.enabled", 0, gl);
+            return;
+        };
+
+        program.uniform_4f32("clipPlane", reflection.plane, gl);
+        let enabled = reflection.ready && !self.drawing_offscreen && self.camera.ortho.is_none();
+        program.uniform_1i32("reflection.enabled", enabled as i32, gl);
+        if enabled {
+            program.uniform_2f32("reflection.screenSize", (self.window_size.0 as f32, self.window_size.1 as f32).into(), gl);
+            gl.active_texture(glow::TEXTURE0 + REFLECTION_TEXTURE_UNIT);
+            gl.bind_texture(glow::TEXTURE_2D, Some(reflection.texture));
+            gl.active_texture(glow::TEXTURE0);
+        }
+    }
+}
+
+fn reflection_plane(components: &[Component]) -> Option<&ReflectionPlane> {
+    components.iter().find_map(|component| match component {
+        Component::ReflectionPlane(plane) => Some(plane),
+        _ => None
+    })
+}
+
+impl World {
+    pub fn get_reflection_plane(&self, model: usize) -> Option<ReflectionPlane> {
+        reflection_plane(&self.models.get(model)?.as_ref()?.components).copied()
+    }
+
+    pub fn set_reflection_plane(&mut self, model: usize, settings: ReflectionPlane) {
+        let Some(target) = self.models[model].as_mut() else { return; };
+        for component in target.components.iter_mut() {
+            if let Component::ReflectionPlane(old) = component {
+                *old = settings;
+            }
+        }
+    }
+
+    /// Render the level mirrored about the reflection plane, call after `render_shadows` and before the scene is rendered<br>
+    /// Lit with the uniform lights like camera feeds, the clusters are built for the main camera
+    pub unsafe fn render_reflection(&mut self, meshes: &MeshBank, programs: &mut ProgramBank, textures: &TextureBank, gl: &glow::Context) {
+        if let Some(reflection) = self.scene.reflection.as_mut() {
+            reflection.ready = false;
+        }
+        if self.scene.safe_mode || self.scene.camera.ortho.is_some() {
+            return;
+        }
+        let Some((settings, transform)) = self.models.iter().flatten()
+            .find_map(|model| reflection_plane(&model.components).map(|plane| (*plane, model.transform))) else { return; };
+
+        let normal = (transform * Vector3::unit_y().extend(0.0)).truncate();
+        if normal.magnitude2() < 0.0001 {
+            return;
+        }
+        let mut normal = normal.normalize();
+        let point = common::translation(transform);
+        // Seen from below, the other side of the plane is reflected
+        if normal.dot(self.scene.camera.pos.to_vec() - point) < 0.0 {
+            normal = -normal;
+        }
+        let distance = -normal.dot(point);
+
+        let scale = settings.resolution_scale.clamp(0.1, 1.0);
+        let size = (((self.scene.window_size.0 as f32 * scale) as u32).max(1), ((self.scene.window_size.1 as f32 * scale) as u32).max(1));
+        if self.scene.reflection.as_ref().is_some_and(|reflection| reflection.target.size != size) {
+            let old = self.scene.reflection.take().unwrap();
+            old.target.delete(gl);
+            gl.delete_texture(old.texture);
+        }
+        if self.scene.reflection.is_none() {
+            match RenderTarget::new(size, gl) {
+                Ok(target) => self.scene.reflection = Some(Reflection { target, texture: create_texture(size, gl), plane: Vector4::unit_w(), ready: false }),
+                Err(e) => {
+                    self.editor_data.show_debug.push(format!("failed to make reflection: {}", e));
+                    return;
+                }
+            }
+        }
+
+        let mirror = reflection_matrix(normal, distance);
+        let mut camera = self.scene.camera.clone();
+        camera.view = camera.view * mirror;
+        camera.inverse_view = camera.view.invert().unwrap();
+        camera.pos = mirror.transform_point(camera.pos);
+        camera.direction = mirror.transform_vector(camera.direction);
+        let main = std::mem::replace(&mut self.scene.camera, camera);
+
+        let reflection = self.scene.reflection.as_mut().unwrap();
+        reflection.plane = normal.extend(distance - CLIP_OFFSET);
+        let fbo = reflection.target.fbo;
+
+        let show_hidden = self.scene.show_hidden_objects;
+        self.scene.show_hidden_objects = false;
+        self.scene.drawing_offscreen = true;
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+        gl.viewport(0, 0, size.0 as i32, size.1 as i32);
+        gl.enable(glow::CLIP_DISTANCE0);
+        // Mirroring turns the triangles around
+        gl.front_face(glow::CW);
+        self.scene.render(meshes, programs, textures, gl);
+        gl.front_face(glow::CCW);
+        gl.disable(glow::CLIP_DISTANCE0);
+        self.scene.drawing_offscreen = false;
+        self.scene.show_hidden_objects = show_hidden;
+        self.scene.camera = main;
+
+        let reflection = self.scene.reflection.as_mut().unwrap();
+        reflection.target.copy_to_texture(reflection.texture, gl);
+        reflection.ready = true;
+
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        gl.viewport(0, 0, self.scene.window_size.0 as i32, self.scene.window_size.1 as i32);
+    }
+}
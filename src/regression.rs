@@ -0,0 +1,148 @@
+use std::{f32, fs, path::{Path, PathBuf}};
+
+use cgmath::{point3, Point3};
+use glow::HasContext;
+use image::RgbaImage;
+
+use crate::{input::Input, mesh::MeshBank, render::CameraControlScheme, save::LevelData, shader::ProgramBank, target::RenderTarget, texture::TextureBank, window, world::World};
+
+/// Level every angle is rendered from
+const REFERENCE_LEVEL: &str = "res/levels/demo.json";
+const GOLDEN_DIRECTORY: &str = "res/tests/golden";
+/// Renders that don't match are written here with an image of where they differ
+const FAILURE_DIRECTORY: &str = "target/render-test";
+const RENDER_WIDTH: u32 = 320;
+const RENDER_HEIGHT: u32 = 240;
+/// Frames rendered before the picture is taken, so anything set up during the first update is in it
+const WARMUP_FRAMES: u32 = 3;
+/// Channels can be off by this much before a pixel counts as different, drivers don't round the same way
+const CHANNEL_TOLERANCE: u8 = 8;
+/// A render fails once more than this fraction of its pixels are different
+const MAX_DIFFERENT_PIXELS: f32 = 0.005;
+
+/// Name, camera position, yaw and pitch of the angles the reference level is rendered from
+const ANGLES: [(&str, Point3<f32>, f32, f32); 4] = [
+    ("overview", point3(0.0, 10.0, 24.0), -f32::consts::FRAC_PI_2, -0.35),
+    ("floor", point3(0.0, -4.5, 5.0), -f32::consts::FRAC_PI_2, 0.0),
+    ("east", point3(18.0, 2.0, -10.0), f32::consts::PI, -0.2),
+    ("above", point3(0.0, 18.0, -15.0), -f32::consts::FRAC_PI_2, -1.2)
+];
+
+/// Render one frame of `world` the way the main loop does, without the editor overlays
+pub unsafe fn render_frame(world: &mut World, target: &RenderTarget, meshes: &mut MeshBank, programs: &mut ProgramBank, textures: &mut TextureBank, gl: &glow::Context) {
+    world.scene.camera.update(&Input::new(), 0.0);
+    world.scene.update(meshes, gl);
+    world.update_draw_distances();
+    world.scene.prepare_text(textures, gl);
+    world.prepare_impostors(meshes, programs, textures, gl);
+    world.scene.prepare_mobiles(meshes, gl);
+    world.scene.render_shadows(meshes, programs, gl);
+    world.render_feeds(meshes, programs, textures, gl);
+    world.render_reflection(meshes, programs, textures, gl);
+    world.scene.prepare_lights(gl);
+
+    gl.viewport(0, 0, target.size.0 as i32, target.size.1 as i32);
+    if world.scene.safe_mode {
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(target.fbo));
+    } else {
+        world.scene.post_process.begin(gl);
+    }
+    world.scene.render(meshes, programs, textures, gl);
+    if !world.scene.safe_mode {
+        world.scene.post_process.end_to(Some(target.fbo), programs, gl);
+    }
+    gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+}
+
+/// Fraction of pixels with a channel more than `CHANNEL_TOLERANCE` off, and an image marking them in red
+fn compare(actual: &RgbaImage, golden: &RgbaImage) -> (f32, RgbaImage) {
+    let mut diff = RgbaImage::new(actual.width(), actual.height());
+    let mut different = 0;
+    for ((a, g), d) in actual.pixels().zip(golden.pixels()).zip(diff.pixels_mut()) {
+        let off = a.0.iter().zip(g.0.iter()).take(3).any(|(a, g)| a.abs_diff(*g) > CHANNEL_TOLERANCE);
+        if off {
+            different += 1;
+            *d = image::Rgba([255, 0, 0, 255]);
+        } else {
+            // Faded copy of the golden image so the differences can be placed
+            *d = image::Rgba([g.0[0] / 4, g.0[1] / 4, g.0[2] / 4, 255]);
+        }
+    }
+    (different as f32 / (actual.width() * actual.height()) as f32, diff)
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(GOLDEN_DIRECTORY).join(format!("{}.png", name))
+}
+
+/// Render the reference level from every angle and compare it against the golden images<br>
+/// `bless` replaces the golden images with the new renders instead, after a change that is meant to look different<br>
+/// Returns the lines of the report, or why the test couldn't run or failed
+pub unsafe fn run(bless: bool) -> Result<Vec<String>, Vec<String>> {
+    let (gl, _surface, _context, _window, _event_loop) = window::create_gl_context(false);
+    let capabilities = window::GpuCapabilities::detect(&gl);
+    let mut programs = ProgramBank::new();
+    programs.version = capabilities.shader_version();
+    let mut textures = TextureBank::new();
+    let mut meshes = MeshBank::new();
+
+    textures.load_by_name("magic_pixel", &gl).map_err(|e| vec![e.to_string()])?;
+    textures.load_by_name("evil_pixel", &gl).map_err(|e| vec![e.to_string()])?;
+    World::load_basic_meshes(&mut meshes, &gl);
+
+    let data = LevelData::read_from_file(Path::new(REFERENCE_LEVEL)).map_err(|e| vec![e])?;
+    let mut world = World::from_save_data(data, &mut textures, &mut meshes, &mut programs, &gl);
+    world.scene.safe_mode = !capabilities.missing_requirements().is_empty();
+    world.scene.window_size = (RENDER_WIDTH, RENDER_HEIGHT);
+    world.scene.post_process.resize((RENDER_WIDTH, RENDER_HEIGHT), &gl);
+    world.scene.camera.on_window_resized(RENDER_WIDTH as f32, RENDER_HEIGHT as f32);
+    world.scene.camera.control_sceme = CameraControlScheme::FirstPerson(false);
+    let target = RenderTarget::new((RENDER_WIDTH, RENDER_HEIGHT), &gl).map_err(|e| vec![e])?;
+
+    let mut report = vec![capabilities.summary()];
+    let mut failed = false;
+    if bless {
+        fs::create_dir_all(GOLDEN_DIRECTORY).map_err(|e| vec![format!("failed to create {}: {}", GOLDEN_DIRECTORY, e)])?;
+    }
+    for (name, position, yaw, pitch) in ANGLES {
+        world.scene.camera.look_from(position, yaw, pitch);
+        for _ in 0..WARMUP_FRAMES {
+            render_frame(&mut world, &target, &mut meshes, &mut programs, &mut textures, &gl);
+        }
+        let actual = target.read(&gl);
+
+        if bless {
+            actual.save(golden_path(name)).map_err(|e| vec![format!("failed to write golden image {}: {}", name, e)])?;
+            report.push(format!("{}: blessed", name));
+            continue;
+        }
+
+        let golden = match image::open(golden_path(name)) {
+            Ok(golden) => golden.to_rgba8(),
+            Err(e) => {
+                report.push(format!("{}: no golden image ({}), run `viceptica render-test --bless` to make one", name, e));
+                failed = true;
+                continue;
+            }
+        };
+        if golden.dimensions() != actual.dimensions() {
+            report.push(format!("{}: golden image is {:?} but renders are {:?}", name, golden.dimensions(), actual.dimensions()));
+            failed = true;
+            continue;
+        }
+
+        let (different, diff) = compare(&actual, &golden);
+        if different > MAX_DIFFERENT_PIXELS {
+            failed = true;
+            let _ = fs::create_dir_all(FAILURE_DIRECTORY);
+            let _ = actual.save(Path::new(FAILURE_DIRECTORY).join(format!("{}.png", name)));
+            let _ = diff.save(Path::new(FAILURE_DIRECTORY).join(format!("{}_diff.png", name)));
+            report.push(format!("{}: {:.2}% of pixels differ, render and diff written to {}", name, different * 100.0, FAILURE_DIRECTORY));
+        } else {
+            report.push(format!("{}: ok ({:.2}% of pixels differ)", name, different * 100.0));
+        }
+    }
+
+    target.delete(&gl);
+    if failed { Err(report) } else { Ok(report) }
+}
@@ -1,14 +1,45 @@
 use core::f32;
-use std::{collections::HashMap, sync::LazyLock};
+use std::{collections::{BTreeMap, HashMap}, sync::LazyLock};
 
-use cgmath::{point3, vec2, vec3, Deg, ElementWise, EuclideanSpace, InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix, Transform, Vector3, Zero};
+use cgmath::{point3, vec2, vec3, Deg, ElementWise, EuclideanSpace, InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix, Transform, Vector2, Vector3, Vector4, Zero};
 use glow::{HasContext, NativeBuffer, NativeVertexArray};
 use serde::{Deserialize, Serialize};
-use winit::{event::MouseButton, keyboard::{Key, NamedKey}};
+use winit::event::MouseButton;
 
-use crate::{collision::PhysicalProperties, common::{self, normal_matrix}, effects, input::Input, mesh::{self, flags, Mesh, MeshBank}, shader::{self, Program, ProgramBank}, texture::{Texture, TextureBank}, ui, world::{self, Model, Renderable, World}};
+use crate::{cluster::{LightClusters, MAX_CLUSTERED_LIGHTS}, collision::PhysicalProperties, common::{self, normal_matrix}, dissolve, effects, feed::{self, FeedView}, flipbook::Flipbook, shadow::{ShadowMaps, MAX_SHADOW_LIGHTS, SHADOW_TEXTURE_UNIT}, impostor::{self, Impostor}, reflection::Reflection, input::{Action, Input}, mesh::{self, flags, BrushFaces, Mesh, MeshBank}, shader::{self, Program, ProgramBank, UniformValue}, texture::{Texture, TextureBank}, tint::{BrushCategory, BrushTint}, ui, visibility::Visibility, world::{self, Model, Renderable, TextLabel, World}};
 
 const HIDDEN_MASK_SIZE: f32 = 0.5;
+pub const DEFAULT_CAMERA_SPEED: f32 = 3.5;
+pub const DEFAULT_CAMERA_SENSITIVITY: f32 = 0.007;
+pub const DEFAULT_NEAR_PLANE: f32 = 0.1;
+pub const DEFAULT_FAR_PLANE: f32 = 100.0;
+const MATERIAL_PREVIEW_ALPHA: f32 = 0.75;
+/// Texture unit of material emissive textures, after the reflection
+const EMISSIVE_TEXTURE_UNIT: u32 = 10;
+/// Half the height of the area shown by a new orthographic view
+const DEFAULT_ORTHO_HEIGHT: f32 = 10.0;
+/// Orthographic views see this far in front of and behind the camera
+const ORTHO_DEPTH: f32 = 500.0;
+/// How quickly the editor camera turns to a view preset, the remaining angle shrinks by this much every second
+const PRESET_TURN_RATE: f32 = 12.0;
+/// Keeps top and bottom views from looking straight along the up vector
+const MAX_PITCH: f32 = f32::consts::PI / 2.0 - 0.025;
+const ORTHO_BACKGROUND: [f32; 3] = [0.15, 0.15, 0.17];
+/// Grid spacing is doubled until no more lines than this fit across the view
+const MAX_GRID_LINES: f32 = 100.0;
+pub const DEFAULT_GRID_COLOR: [f32; 3] = [0.3, 0.3, 0.33];
+/// Every 8th grid line
+pub const DEFAULT_GRID_MAJOR_COLOR: [f32; 3] = [0.5, 0.5, 0.55];
+/// The ground grid covers this far around the camera
+const GROUND_GRID_RADIUS: f32 = 32.0;
+const ARROW_HEAD_SIZE: f32 = 0.3;
+/// Has to match `MAX_POINT_LIGHTS` in the shaders, only the uniform lighting of orthographic views and impostors is limited to it
+const MAX_POINT_LIGHTS: usize = 64;
+/// Has to match `MAX_SPOT_LIGHTS` in the shaders, spot lights past this are not drawn
+const MAX_SPOT_LIGHTS: usize = 16;
+/// Lights are looped over per fragment, old GPUs can't keep up with many
+const SAFE_MODE_POINT_LIGHTS: usize = 4;
+const SAFE_MODE_SPOT_LIGHTS: usize = 0;
 
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
@@ -32,7 +63,18 @@ pub struct MobileRenderData {
     pub transform: Matrix4<f32>,
     pub normal_matrix: Matrix3<f32>,
     pub draw: bool,
-    pub show_hidden: bool
+    pub show_hidden: bool,
+    /// 1 is fully drawn, see `impostor`
+    pub fade: f32,
+    /// 0 is fully drawn, 1 is dissolved away, see `dissolve`
+    pub dissolve: f32
+}
+
+impl MobileRenderData {
+    /// Whether this can be drawn from the streamed instance buffer, the instanced shader can't fade or dissolve
+    pub fn instanceable(&self) -> bool {
+        self.fade >= 1.0 && self.dissolve <= 0.0
+    }
 }
 
 static DUMMY_RENDER_DATA: LazyLock<MobileRenderData> = LazyLock::new(|| {
@@ -41,7 +83,9 @@ static DUMMY_RENDER_DATA: LazyLock<MobileRenderData> = LazyLock::new(|| {
         transform: Matrix4::identity(),
         normal_matrix: Matrix3::identity(),
         draw: false,
-        show_hidden: false
+        show_hidden: false,
+        fade: 1.0,
+        dissolve: 0.0
     }
 });
 
@@ -52,7 +96,13 @@ pub struct BillboardRenderData {
     pub draw: bool,
     pub follow_vertical: bool,
     pub size: (f32, f32),
-    pub show_hidden: bool
+    pub show_hidden: bool,
+    /// Text fixed to its model keeps this rotation instead of facing the camera
+    pub fixed_rotation: Option<Matrix3<f32>>,
+    pub fade: f32,
+    pub flipbook: Flipbook,
+    /// Current frame of `flipbook`, advanced in `Scene::update`
+    pub frame: u32
 }
 
 static DUMMY_BILLBOARD_DATA: LazyLock<BillboardRenderData> = LazyLock::new(|| {
@@ -62,7 +112,11 @@ static DUMMY_BILLBOARD_DATA: LazyLock<BillboardRenderData> = LazyLock::new(|| {
         follow_vertical: false,
         position: Vector3::zero(),
         size: (1.0, 1.0),
-        show_hidden: false
+        show_hidden: false,
+        fixed_rotation: None,
+        fade: 1.0,
+        flipbook: Flipbook::default(),
+        frame: 0
     }
 });
 
@@ -71,19 +125,50 @@ pub struct Material {
     pub diffuse: String,
     pub specular: String,
     pub shininess: f32,
-    pub physical_properties: PhysicalProperties
+    pub physical_properties: PhysicalProperties,
+    /// Only set for brush types, see `BrushTint`
+    pub category: BrushCategory,
+    /// Program drawn with instead of `flat`, see `ProgramBank::load_material_program`
+    pub shader: Option<String>,
+    /// Set on `shader` before each mesh with this material is drawn
+    pub uniforms: Vec<(String, UniformValue)>,
+    /// Played on meshes with `flags::FLIPBOOK`, only set for brush types
+    pub flipbook: Option<Flipbook>,
+    /// Current frame of `flipbook`, advanced in `Scene::update`
+    pub frame: u32,
+    /// How much of the reflection covers meshes with this material, see `reflection`, only set for brush types
+    pub reflectivity: f32,
+    /// Light the material gives off on its own, added after lighting, `emissive_color` times `emissive_strength` tints the texture<br>
+    /// Brighter than 1 it reaches the bloom, only set for brush types
+    pub emissive: String,
+    pub emissive_color: Vector3<f32>,
+    pub emissive_strength: f32
 }
 
 impl Material {
     pub fn new(diffuse: &str, specular: &str, shininess: f32) -> Self {
         Self {
-            diffuse: diffuse.to_string(), shininess, specular: specular.to_string(), physical_properties: PhysicalProperties::default()
+            diffuse: diffuse.to_string(), shininess, specular: specular.to_string(), physical_properties: PhysicalProperties::default(), category: BrushCategory::default(),
+            shader: None, uniforms: Vec::new(), flipbook: None, frame: 0, reflectivity: 0.0,
+            emissive: String::from("magic_pixel"), emissive_color: Vector3::zero(), emissive_strength: 1.0
         }
     }
 
+    /// Emissive color the texture is multiplied by, zero for materials that don't glow
+    pub fn emission(&self) -> Vector3<f32> {
+        self.emissive_color * self.emissive_strength
+    }
+
+    /// The `flipbook` uniform for meshes with this material
+    pub fn flipbook_uniform(&self) -> Vector2<f32> {
+        self.flipbook.map_or(Vector2::zero(), |flipbook| flipbook.uniform(self.frame))
+    }
+
     pub fn with_physical_properties(diffuse: &str, specular: &str, shininess: f32, physical_properties: PhysicalProperties) -> Self {
         Self {
-            diffuse: diffuse.to_string(), shininess, specular: specular.to_string(), physical_properties
+            diffuse: diffuse.to_string(), shininess, specular: specular.to_string(), physical_properties, category: BrushCategory::default(),
+            shader: None, uniforms: Vec::new(), flipbook: None, frame: 0, reflectivity: 0.0,
+            emissive: String::from("magic_pixel"), emissive_color: Vector3::zero(), emissive_strength: 1.0
         }
     }
 
@@ -116,6 +201,15 @@ pub fn attenuation_coefficients(radius: f32, attenuation_threshold: f32) -> (f32
     (FIXED_C, FIXED_L, quadratic)
 }
 
+/// Text fixed to its model follows the model's rotation, without its scale
+fn text_rotation(label: &TextLabel, model_transform: Matrix4<f32>) -> Option<Matrix3<f32>> {
+    if label.billboard {
+        return None;
+    }
+    let axis = |i: usize| model_transform[i].truncate().normalize();
+    Some(Matrix3::from_cols(axis(0), axis(1), axis(2)))
+}
+
 #[derive(Clone)]
 pub struct PointLight {
     pub position: Vector3<f32>,
@@ -126,7 +220,9 @@ pub struct PointLight {
     pub diffuse: Vector3<f32>,
     pub specular: Vector3<f32>,
     pub user_color: Option<Vector3<f32>>,
-    pub user_attenuation: Option<f32>
+    pub user_attenuation: Option<f32>,
+    /// Draws a shadow map out to its radius, see `shadow`
+    pub cast_shadows: bool
 }
 
 impl PointLight {
@@ -149,10 +245,23 @@ impl PointLight {
     }
 
     pub fn set_color(&mut self, color: Vector3<f32>) {
+        self.apply_color(color);
+        self.user_color = Some(color);
+    }
+
+    /// Tint the light's user color by `multiplier` without changing what gets saved
+    pub fn set_animated_color(&mut self, multiplier: Vector3<f32>) {
+        // Otherwise the animated diffuse would become the base color next frame
+        if self.user_color.is_none() {
+            self.user_color = Some(self.diffuse);
+        }
+        self.apply_color(self.user_color_or_default().mul_element_wise(multiplier));
+    }
+
+    fn apply_color(&mut self, color: Vector3<f32>) {
         self.ambient = color * ui::implement::USER_AMBIENT_STRENGTH;
         self.diffuse = color;
         self.specular = common::vec3_mix(color, vec3(1.0, 1.0, 1.0), ui::implement::USER_SPECULAR_BLEND) * ui::implement::USER_SPECULAR_STRENGTH;
-        self.user_color = Some(color);
     }
 
     pub fn default(position: Vector3<f32>) -> Self {
@@ -165,7 +274,44 @@ impl PointLight {
             constant, linear, quadratic,
             position,
             user_attenuation: None,
-            user_color: None
+            user_color: None,
+            cast_shadows: false
+        }
+    }
+}
+
+/// Cone of light, for flashlights and lamps<br>
+/// The cutoffs are the cosines of the angles from the center of the cone
+#[derive(Clone, Copy, Debug)]
+pub struct SpotLight {
+    pub position: Vector3<f32>,
+    pub direction: Vector3<f32>,
+    pub inner_cutoff: f32,
+    pub outer_cutoff: f32,
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+    pub ambient: Vector3<f32>,
+    pub diffuse: Vector3<f32>,
+    pub specular: Vector3<f32>
+}
+
+impl SpotLight {
+    /// Full brightness within `inner_angle` degrees of `direction`, fading out until `outer_angle`
+    pub fn new(position: Vector3<f32>, direction: Vector3<f32>, color: Vector3<f32>, radius: f32, inner_angle: f32, outer_angle: f32) -> Self {
+        let (constant, linear, quadratic) = attenuation_coefficients(radius, 0.05);
+        // The shader divides by the difference between the cutoffs
+        let inner_angle = inner_angle.min(outer_angle - 0.5);
+
+        Self {
+            position,
+            direction: direction.normalize(),
+            inner_cutoff: inner_angle.to_radians().cos(),
+            outer_cutoff: outer_angle.to_radians().cos(),
+            constant, linear, quadratic,
+            ambient: color * ui::implement::USER_AMBIENT_STRENGTH,
+            diffuse: color,
+            specular: common::vec3_mix(color, vec3(1.0, 1.0, 1.0), ui::implement::USER_SPECULAR_BLEND) * ui::implement::USER_SPECULAR_STRENGTH
         }
     }
 }
@@ -177,9 +323,39 @@ pub enum Skybox {
     NoClear
 }
 
+/// Fog drawn by the lighting shaders, part of the level unlike the fog effect of triggers
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct DistanceFog {
+    pub enabled: bool,
+    pub color: [f32; 3],
+    /// Linear fog starts at `start` and covers everything past `end`, used while `density` is 0
+    pub start: f32,
+    pub end: f32,
+    /// Exponential fog, thicker the higher
+    pub density: f32
+}
+
+impl Default for DistanceFog {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            color: [0.6, 0.6, 0.65],
+            start: 20.0,
+            end: 100.0,
+            density: 0.0
+        }
+    }
+}
+
 pub struct Environment {
     pub skybox: Skybox,
-    pub dir_light: DirLight
+    pub dir_light: DirLight,
+    /// Camera clip distances for the level
+    pub near_plane: f32,
+    pub far_plane: f32,
+    pub hdr: effects::HdrEffect,
+    pub fog: DistanceFog
 }
 
 impl Environment {
@@ -191,7 +367,11 @@ impl Environment {
                 ambient: vec3(0.3, 0.3, 0.3),
                 diffuse: vec3(0.6, 0.6, 0.6),
                 specular: vec3(0.75, 0.75, 0.75)
-            }
+            },
+            near_plane: DEFAULT_NEAR_PLANE,
+            far_plane: DEFAULT_FAR_PLANE,
+            hdr: effects::HdrEffect::default(),
+            fog: DistanceFog::default()
         }
     }
 }
@@ -207,12 +387,32 @@ pub struct Scene {
 
     /// Meshed rendered individually
     pub mobile_meshes: HashMap<String, Vec<MobileRenderData>>,
+    /// Instance buffers for mobile meshes, refilled every frame in `prepare_mobiles`, by the name of the mesh drawn
+    mobile_instance_buffers: HashMap<String, NativeBuffer>,
+    /// Instances in each of `mobile_instance_buffers` this frame
+    pub mobile_instance_counts: HashMap<String, usize>,
     pub foreground_meshes: HashMap<String, Vec<MobileRenderData>>,
     pub billboards: HashMap<String, Vec<BillboardRenderData>>,
+    /// Particles from dissolve bursts, drawn with `dissolve::PARTICLE_TEXTURE`
+    pub particles: Vec<BillboardRenderData>,
+    /// Pictures drawn in place of far models, by model index
+    pub impostors: HashMap<usize, Impostor>,
+    /// Offscreen targets of camera feeds, by model index, see `World::render_feeds`
+    pub feeds: HashMap<usize, FeedView>,
+    /// Mirrored render of the level for reflective materials, see `World::render_reflection`
+    pub reflection: Option<Reflection>,
+    /// Set while a camera feed or the reflection is rendered, which light with the uniforms instead of the clusters
+    pub drawing_offscreen: bool,
+    /// Text inserted since the last `prepare_text`, rasterized before the next render
+    pending_text: Vec<TextLabel>,
+    /// Meshes of aligned brushes inserted since the last update, by mesh name, made from the material's plain brush mesh
+    pending_brush_variants: Vec<(String, String, BrushFaces)>,
     pub camera: Camera,
     pub materials: HashMap<String, Material>,
     pub environment: Environment,
     pub point_lights: Vec<PointLight>,
+    /// Rebuilt from the models every update, see `World::gather_spot_lights`
+    pub spot_lights: Vec<SpotLight>,
 
     /// If true, `prepare_statics` will be called on the next frame
     pub statics_dirty: bool,
@@ -222,41 +422,127 @@ pub struct Scene {
     pub ui_vao: Option<NativeVertexArray>,
     pub show_hidden_objects: bool,
     pub applicable_materials: Vec<String>,
+    /// Materials that loaded with a placeholder texture or that won't tile right, with what is wrong
+    pub material_problems: Vec<(String, String)>,
     pub post_process: effects::PostProcessing,
-    pub world_default_effects: effects::DefaultEffects
+    pub world_default_effects: effects::DefaultEffects,
+    /// For old or weak GPUs, no instancing, no post processing and only a few lights
+    pub safe_mode: bool,
+    /// Baked cells brushes are culled by, see `World::start_visibility_bake`
+    visibility: Option<Visibility>,
+    /// Set while playing, the editor always draws every brush
+    pub cull_statics: bool,
+    /// Off while playing, the editor's `brush_tint` otherwise
+    pub brush_tint: BrushTint,
+    /// Cell of `visibility` the static instance buffers were culled for
+    visible_cell: Option<usize>,
+    /// Instances in each of `static_instance_buffers` after culling
+    pub static_instance_counts: HashMap<String, usize>,
+    /// Depth cubemaps of shadow casting point lights, see `Scene::render_shadows`
+    pub shadows: ShadowMaps,
+    /// Point lights sorted by where they reach on screen, see `Scene::prepare_lights`
+    pub clusters: LightClusters,
+    /// Camera position static meshes with detail levels were last sorted into levels at, see `Scene::update_lods`
+    pub lod_origin: Option<Vector3<f32>>,
+    /// Seconds since the level loaded, the `time` uniform of material shaders
+    pub time: f32
 }
 
 impl Scene {
-    /// load shaders, primitive meshes, materials
-    pub unsafe fn init(&mut self, textures: &mut TextureBank, meshes: &mut MeshBank, programs: &mut ProgramBank, gl: &glow::Context) {
+    /// load shaders, primitive meshes, materials<br>
+    /// Returns problems with the brush types file
+    pub unsafe fn init(&mut self, textures: &mut TextureBank, meshes: &mut MeshBank, programs: &mut ProgramBank, gl: &glow::Context) -> Vec<String> {
         programs.load_by_name_vf("instanced", gl).unwrap();
         programs.load_by_name_vf("flat", gl).unwrap();
         programs.load_by_name_vf("lines", gl).unwrap();
         programs.load_by_name_vf("skybox", gl).unwrap();
         programs.load_by_name_vf("screen", gl).unwrap();
+        programs.load_by_name_vf("shadow", gl).unwrap();
+        programs.load_screen_program("bloom_bright", gl).unwrap();
+        programs.load_screen_program("bloom_blur", gl).unwrap();
+        programs.load_screen_program("ssao", gl).unwrap();
+        programs.load_screen_program("ssao_blur", gl).unwrap();
+        programs.load_screen_program("fxaa", gl).unwrap();
         self.add_default_materials();
-        self.applicable_materials = world::load_brushes(textures, meshes, self, gl);
+        let (applicable_materials, warnings) = world::load_brushes(textures, meshes, programs, self, gl);
+        self.applicable_materials = applicable_materials;
         // billboards
         meshes.add(Mesh::create_square(1.0, 1.0, 1.0, gl), "quad");
         // textures.load_cubemap_by_name("field", gl).unwrap();
         // textures.load_cubemap_by_name("google", gl).unwrap();
         textures.load_cubemap_by_name("heaven", gl).unwrap();
         textures.load_by_name("stencil_hidden", gl).unwrap();
+        textures.load_by_name("note", gl).unwrap();
         self.skybox_vao = Some(mesh::create_skybox(gl));
         //textures.load_cubemap_by_name("heaven", gl).unwrap();
         //textures.load_cubemap_by_name("cloudy_sky", gl).unwrap();
 
         gl.enable(glow::DEPTH_TEST);
         gl.enable(glow::CULL_FACE);
+
+        warnings
     }
 
     pub unsafe fn update(&mut self, meshes: &mut MeshBank, gl: &glow::Context) {
+        for (name, material, faces) in self.pending_brush_variants.drain(..) {
+            if meshes.get(&name).is_some() {
+                continue;
+            }
+            let Some(texture) = meshes.get(&format!("Brush_{}", material)).map(|mesh| mesh.material.clone()) else { continue; };
+            meshes.add(Mesh::create_aligned_cube(&texture, &faces, gl), &name);
+        }
+
+        self.prepare_statics_for_camera(meshes, gl);
+
+        self.advance_flipbooks();
+
+        self.post_process.hdr = self.environment.hdr;
+        self.post_process.ssao = self.world_default_effects.ssao.clone();
+        self.post_process.projection = self.camera.projection;
+    }
+
+    /// Cull the static instance buffers and sort them into detail levels for where the camera is, then rebuffer what changed<br>
+    /// Offscreen renders from another camera call this before and after swapping it in
+    pub unsafe fn prepare_statics_for_camera(&mut self, meshes: &mut MeshBank, gl: &glow::Context) {
+        let cell = match &self.visibility {
+            Some(visibility) if self.cull_statics => visibility.cell_at(self.camera.pos.to_vec()),
+            _ => None
+        };
+        if cell != self.visible_cell {
+            self.visible_cell = cell;
+            self.static_meshes_updated = self.static_meshes.keys().cloned().collect();
+            self.statics_dirty = true;
+        }
+        self.update_lods(meshes);
+
         if self.statics_dirty {
             self.prepare_statics(meshes, gl);
             self.statics_dirty = false;
         }
     }
 
+    /// Move animated billboards and materials onto the frame for the current time
+    fn advance_flipbooks(&mut self) {
+        let time = self.time;
+        for data in self.billboards.values_mut().flatten() {
+            data.frame = data.flipbook.frame_at(time);
+        }
+        for material in self.materials.values_mut() {
+            if let Some(flipbook) = material.flipbook {
+                material.frame = flipbook.frame_at(time);
+            }
+        }
+    }
+
+    /// Rasterize the textures of newly inserted text, call before rendering
+    pub unsafe fn prepare_text(&mut self, textures: &mut TextureBank, gl: &glow::Context) {
+        for label in self.pending_text.drain(..) {
+            if let Err(e) = textures.load_text(&label.texture_name(), &label.text, label.color, gl) {
+                eprintln!("Failed to rasterize text {:?}: {}", label.text, e);
+            }
+        }
+    }
+
     unsafe fn stencil_hidden(&self, ui_program: &mut Program, textures: &TextureBank, gl: &glow::Context) {
         let hidden_stencil = textures.get("stencil_hidden").unwrap();
         gl.disable(glow::DEPTH_TEST);
@@ -289,28 +575,46 @@ impl Scene {
         gl.enable(glow::CULL_FACE);
     }
 
-    unsafe fn render_single_billboard(&self, data: &BillboardRenderData, quad: &Mesh, program: &mut Program, texture: &str, textures: &TextureBank, gl: &glow::Context) {
+    /// Rotation that turns a billboard towards the camera
+    fn billboard_rotation(&self, data: &BillboardRenderData) -> Matrix3<f32> {
         let forward = if data.follow_vertical {
             (self.camera.pos.to_vec() - data.position).normalize()
         } else {
             let mut f = -self.camera.direction;
             f.y = 0.0;
-            f.normalize()
+            // Looking straight down in the top view
+            if f.magnitude2() < 0.0001 { self.camera.up } else { f.normalize() }
         }; 
 
         let right = self.camera.up.cross(forward).normalize();
         let up = forward.cross(right);
 
-        let view_rot = Matrix3::from_cols(right, up, forward);
+        Matrix3::from_cols(right, up, forward)
+    }
 
-        let transform = Matrix4::from_translation(data.position) * Matrix4::from_nonuniform_scale(data.size.0, data.size.1, 1.0) * common::mat3_to_mat4(view_rot);
+    unsafe fn render_single_billboard(&self, data: &BillboardRenderData, quad: &Mesh, program: &mut Program, texture: &str, textures: &TextureBank, gl: &glow::Context) {
+        let scale = Matrix4::from_nonuniform_scale(data.size.0, data.size.1, 1.0);
+        let transform = match data.fixed_rotation {
+            Some(rotation) => Matrix4::from_translation(data.position) * common::mat3_to_mat4(rotation) * scale,
+            None => Matrix4::from_translation(data.position) * scale * common::mat3_to_mat4(self.billboard_rotation(data))
+        };
         program.uniform_matrix4f32("model", transform, gl);
-        program.uniform_1i32("flags", data.flags as i32, gl);
+        let flags = if data.flipbook.animated() { data.flags | flags::FLIPBOOK } else { data.flags };
+        program.uniform_1i32("flags", flags as i32, gl);
+        program.uniform_2f32("flipbook", data.flipbook.uniform(data.frame), gl);
+        program.uniform_1f32("fade", data.fade, gl);
+        program.uniform_1f32("dissolve", 0.0, gl);
         program.uniform_1f32("material.shininess", 1.0, gl);
+        // Billboards don't glow or reflect, without this they take the material of the last mesh drawn
+        program.uniform_3f32("material.emission", Vector3::zero(), gl);
+        program.uniform_1f32("material.reflectivity", 0.0, gl);
         gl.active_texture(glow::TEXTURE0);
         gl.bind_texture(glow::TEXTURE_2D, textures.get(texture).map(|s| s.inner));
         gl.active_texture(glow::TEXTURE1);
         gl.bind_texture(glow::TEXTURE_2D, textures.get("evil_pixel").map(|s| s.inner));
+        gl.active_texture(glow::TEXTURE0 + EMISSIVE_TEXTURE_UNIT);
+        gl.bind_texture(glow::TEXTURE_2D, textures.get("evil_pixel").map(|s| s.inner));
+        gl.active_texture(glow::TEXTURE0);
         gl.bind_vertex_array(Some(quad.vao));
 
         gl.draw_elements(
@@ -321,17 +625,67 @@ impl Scene {
         );
     }
 
+    /// Whether renderables with `flags` are left out of this frame
+    fn skipped(&self, flags: u32) -> bool {
+        flags & flags::SKIP != 0 || (flags & flags::EDITOR_ONLY != 0 && !self.show_hidden_objects)
+    }
+
+    /// Draw calls the next frame will make, by mesh or billboard texture
+    pub fn draw_calls(&self) -> Vec<(String, u64)> {
+        let mut calls = Vec::new();
+        if self.safe_mode {
+            for (name, data) in self.static_meshes.iter() {
+                calls.push((name.clone(), data.len() as u64));
+            }
+        } else {
+            // Detail levels of static meshes have instance buffers of their own
+            for (name, count) in self.static_instance_counts.iter() {
+                calls.push((name.clone(), (*count).min(1) as u64));
+            }
+        }
+        for (name, data) in self.mobile_meshes.iter() {
+            let count = data.iter().filter(|data| data.draw && data.fade > 0.0 && !self.skipped(data.flags) && (self.safe_mode || !data.instanceable())).count() as u64;
+            calls.push((name.clone(), count));
+        }
+        for (name, count) in self.mobile_instance_counts.iter() {
+            calls.push((name.clone(), (*count).min(1) as u64));
+        }
+        for (name, data) in self.foreground_meshes.iter() {
+            let count = data.iter().filter(|data| data.draw && data.fade > 0.0 && !self.skipped(data.flags)).count() as u64;
+            calls.push((name.clone(), count));
+        }
+        for (texture, data) in self.billboards.iter() {
+            let count = data.iter().filter(|data| data.draw && data.fade > 0.0 && !self.skipped(data.flags)).count() as u64;
+            calls.push((format!("billboard {}", texture), count));
+        }
+        let impostors = self.impostors.values().filter(|impostor| impostor.data.draw && impostor.data.fade > 0.0).count() as u64;
+        calls.push((String::from("impostors"), impostors));
+
+        calls.retain(|(_, count)| *count > 0);
+        calls
+    }
+
     /// Call while flat program is being used
     unsafe fn render_billboards(&self, meshes: &MeshBank, program: &mut Program, textures: &TextureBank, gl: &glow::Context) {
         let mesh = meshes.get("quad").expect("no quad mesh");
         
         for (texture, data) in self.billboards.iter() {
             for data in data.iter() {
-                if !data.draw { continue; }
+                if !data.draw || data.fade <= 0.0 || self.skipped(data.flags) { continue; }
                 
                 self.render_single_billboard(data, mesh, program, texture, textures, gl);
             }
         }
+
+        for (model, impostor) in self.impostors.iter() {
+            if impostor.data.draw && impostor.data.fade > 0.0 {
+                self.render_single_billboard(&impostor.data, mesh, program, &impostor::texture_name(*model), textures, gl);
+            }
+        }
+
+        for particle in self.particles.iter() {
+            self.render_single_billboard(particle, mesh, program, dissolve::PARTICLE_TEXTURE, textures, gl);
+        }
     }
 
     unsafe fn render_hidden_billboards(&self, meshes: &MeshBank, program: &mut Program, textures: &TextureBank, gl: &glow::Context) {
@@ -339,30 +693,15 @@ impl Scene {
 
         for (texture, data) in self.billboards.iter() {
             for data in data {
-                if !data.draw && data.show_hidden {
+                if !data.draw && data.show_hidden && !self.skipped(data.flags) {
                     self.render_single_billboard(data, mesh, program, texture, textures, gl);
                 }
             }
         }
     }
 
-    pub unsafe fn render(&self, meshes: &MeshBank, programs: &mut ProgramBank, textures: &TextureBank, gl: &glow::Context) {
-        // Clear screen
-        match &self.environment.skybox {
-            Skybox::SolidColor(r, g, b) => {
-                gl.clear_color(*r, *g, *b, 1.0);
-                gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
-            },
-            Skybox::Cubemap(_) => {
-                gl.clear_color(0.0, 0.0, 0.0, 1.0);
-                gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
-            },
-            Skybox::NoClear => {
-                gl.clear(glow::DEPTH_BUFFER_BIT);
-            }
-        }
-
-        // Render instanced
+    /// Static meshes, one draw call for each mesh
+    unsafe fn render_instanced(&self, meshes: &MeshBank, programs: &mut ProgramBank, textures: &TextureBank, gl: &glow::Context) {
         let instanced_program = programs.get_mut("instanced").unwrap();
         gl.use_program(Some(instanced_program.inner));
 
@@ -374,30 +713,131 @@ impl Scene {
         // Material uniforms
         instanced_program.uniform_1i32("material.diffuse", 0, gl);
         instanced_program.uniform_1i32("material.specular", 1, gl);
+        instanced_program.uniform_1i32("material.emissive", EMISSIVE_TEXTURE_UNIT as i32, gl);
 
         // Lights
-        self.uniform_lights(instanced_program, gl);
+        self.uniform_lights(instanced_program, self.clustered_lighting(), gl);
 
         // For each current static model type
         for (name, _) in self.static_instance_buffers.iter() {
+            if self.material_shader(name, meshes).is_some() {
+                continue;
+            }
             let mesh = meshes.get(name).unwrap();
-            let material = self.materials.get(&mesh.material).unwrap();
+            let count = self.static_instance_counts.get(name).copied().unwrap_or(0);
+            self.render_instances(name, mesh, mesh.vao_instanced, count, instanced_program, textures, gl);
+        }
 
-            gl.active_texture(glow::TEXTURE0);
-            gl.bind_texture(glow::TEXTURE_2D, textures.get(&material.diffuse).map(|s| s.inner));
-            gl.active_texture(glow::TEXTURE1);
-            gl.bind_texture(glow::TEXTURE_2D, textures.get(&material.specular).map(|f| f.inner));
-            gl.bind_vertex_array(Some(mesh.vao_instanced));
-            
-            instanced_program.uniform_1f32("material.shininess", material.shininess, gl);
-
-            gl.draw_elements_instanced(
-                glow::TRIANGLES,
-                mesh.indices as i32,
-                glow::UNSIGNED_SHORT,
-                0,
-                self.static_meshes.get(name).unwrap().len() as i32
+        // Mobile meshes streamed in `prepare_mobiles`
+        for (name, count) in self.mobile_instance_counts.iter() {
+            if *count == 0 {
+                continue;
+            }
+            let mesh = meshes.get(name).unwrap();
+            self.render_instances(name, mesh, mesh.vao_streamed, *count, instanced_program, textures, gl);
+        }
+        instanced_program.uniform_4f32("tint", Vector4::zero(), gl);
+    }
+
+    /// Draw `count` instances of `mesh` from the instance buffer set up on `vao`, call while the instanced program is being used
+    unsafe fn render_instances(&self, name: &str, mesh: &Mesh, vao: NativeVertexArray, count: usize, program: &mut Program, textures: &TextureBank, gl: &glow::Context) {
+        let material = self.materials.get(&mesh.material).unwrap();
+
+        gl.active_texture(glow::TEXTURE0);
+        gl.bind_texture(glow::TEXTURE_2D, textures.get(&material.diffuse).map(|s| s.inner));
+        gl.active_texture(glow::TEXTURE1);
+        gl.bind_texture(glow::TEXTURE_2D, textures.get(&material.specular).map(|f| f.inner));
+        gl.active_texture(glow::TEXTURE0 + EMISSIVE_TEXTURE_UNIT);
+        gl.bind_texture(glow::TEXTURE_2D, textures.get(&material.emissive).map(|f| f.inner));
+        gl.active_texture(glow::TEXTURE0);
+        gl.bind_vertex_array(Some(vao));
+        
+        program.uniform_1f32("material.shininess", material.shininess, gl);
+        program.uniform_1f32("material.reflectivity", material.reflectivity, gl);
+        program.uniform_3f32("material.emission", material.emission(), gl);
+        program.uniform_4f32("tint", self.tint_for(name), gl);
+        program.uniform_2f32("flipbook", material.flipbook_uniform(), gl);
+
+        gl.draw_elements_instanced(
+            glow::TRIANGLES,
+            mesh.indices as i32,
+            glow::UNSIGNED_SHORT,
+            0,
+            count as i32
+        );
+    }
+
+    /// Fill the streamed instance buffers of mobile meshes, call after anything that moves or fades them this frame<br>
+    /// Instances that are fading or dissolving and meshes with their own shader are left to be drawn individually
+    pub unsafe fn prepare_mobiles(&mut self, meshes: &MeshBank, gl: &glow::Context) {
+        for count in self.mobile_instance_counts.values_mut() {
+            *count = 0;
+        }
+        if self.safe_mode {
+            return;
+        }
+
+        let mut groups: HashMap<String, Vec<RenderData>> = HashMap::new();
+        for (name, data) in self.mobile_meshes.iter() {
+            if self.material_shader(name, meshes).is_some() {
+                continue;
+            }
+            let has_lods = meshes.has_lods(name);
+            for data in data.iter().filter(|data| data.draw && data.instanceable() && !self.skipped(data.flags)) {
+                let mesh = if has_lods { meshes.lod_mesh_name(name, self.lod_distance(data.transform)) } else { name.as_str() };
+                groups.entry(mesh.to_string()).or_default().push(RenderData { flags: data.flags, transform: data.transform, normal_matrix: data.normal_matrix });
+            }
+        }
+
+        for (name, render_data) in groups {
+            let Some(mesh) = meshes.get(&name) else { continue; };
+            let buffer = match self.mobile_instance_buffers.get(&name) {
+                Some(buffer) => *buffer,
+                None => {
+                    let buffer = gl.create_buffer().unwrap();
+                    gl.bind_vertex_array(Some(mesh.vao_streamed));
+                    gl.bind_buffer(glow::ARRAY_BUFFER, Some(buffer));
+                    mesh.define_instanced_vertex_attributes(gl);
+                    gl.bind_vertex_array(None);
+                    self.mobile_instance_buffers.insert(name.clone(), buffer);
+                    buffer
+                }
+            };
+
+            let instance_data: &[u8] = core::slice::from_raw_parts(
+                render_data.as_ptr() as *const u8,
+                core::mem::size_of_val(render_data.as_slice())
             );
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(buffer));
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, instance_data, glow::STREAM_DRAW);
+            self.mobile_instance_counts.insert(name, render_data.len());
+        }
+    }
+
+    pub unsafe fn render(&self, meshes: &MeshBank, programs: &mut ProgramBank, textures: &TextureBank, gl: &glow::Context) {
+        // Clear screen
+        match &self.environment.skybox {
+            // The skybox would only be a flat color
+            _ if self.camera.ortho.is_some() => {
+                gl.clear_color(ORTHO_BACKGROUND[0], ORTHO_BACKGROUND[1], ORTHO_BACKGROUND[2], 1.0);
+                gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
+            },
+            Skybox::SolidColor(r, g, b) => {
+                gl.clear_color(*r, *g, *b, 1.0);
+                gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
+            },
+            Skybox::Cubemap(_) => {
+                gl.clear_color(0.0, 0.0, 0.0, 1.0);
+                gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
+            },
+            Skybox::NoClear => {
+                gl.clear(glow::DEPTH_BUFFER_BIT);
+            }
+        }
+
+        // Render instanced, safe mode draws static meshes with the mobile ones instead
+        if !self.safe_mode {
+            self.render_instanced(meshes, programs, textures, gl);
         }
 
         // Render individual
@@ -412,16 +852,40 @@ impl Scene {
         // Material
         flat_program.uniform_1i32("material.diffuse", 0, gl);
         flat_program.uniform_1i32("material.specular", 1, gl);
+        flat_program.uniform_1i32("material.emissive", EMISSIVE_TEXTURE_UNIT as i32, gl);
 
         // Lights
-        self.uniform_lights(flat_program, gl);
+        self.uniform_lights(flat_program, self.clustered_lighting(), gl);
+
+        if self.safe_mode {
+            for (name, data) in self.static_meshes.iter() {
+                if self.material_shader(name, meshes).is_some() {
+                    continue;
+                }
+                let data: Vec<MobileRenderData> = data.iter().map(|data| MobileRenderData {
+                    flags: data.flags, transform: data.transform, normal_matrix: data.normal_matrix, draw: true, show_hidden: false, fade: 1.0, dissolve: 0.0
+                }).collect();
+                self.render_individual(&data, name, meshes, textures, flat_program, gl);
+            }
+        }
         
-        // For all types of mobile meshes
+        // For all types of mobile meshes, most are instanced in `render_instanced` unless in safe mode
         for (name, data) in self.mobile_meshes.iter() {
-            self.render_individual(data, name, meshes, textures, flat_program, gl);
+            if self.material_shader(name, meshes).is_some() {
+                continue;
+            }
+            if self.safe_mode {
+                self.render_individual(data, name, meshes, textures, flat_program, gl);
+            } else {
+                let individual: Vec<MobileRenderData> = data.iter().filter(|data| !data.instanceable()).copied().collect();
+                if !individual.is_empty() {
+                    self.render_individual(&individual, name, meshes, textures, flat_program, gl);
+                }
+            }
         }
 
         self.render_billboards(meshes, flat_program, textures, gl);
+        self.render_shader_materials(meshes, programs, textures, gl);
 
         if self.show_hidden_objects {
             gl.clear_stencil(0);
@@ -444,7 +908,7 @@ impl Scene {
         }
 
         // Render cubemap skybox
-        if let Skybox::Cubemap(cubemap) = &self.environment.skybox {
+        if let (Skybox::Cubemap(cubemap), None) = (&self.environment.skybox, self.camera.ortho) {
             // https://learnopengl.com/Advanced-OpenGL/Cubemaps
             gl.depth_func(glow::LEQUAL);
             let skybox_program = programs.get_mut("skybox").unwrap();
@@ -496,17 +960,44 @@ impl Scene {
         gl.enable(glow::DEPTH_TEST);
     }
 
+    pub unsafe fn debug_render_line(&self, from: Vector3<f32>, to: Vector3<f32>, color: Vector3<f32>, box_vao: NativeVertexArray, programs: &mut ProgramBank, gl: &glow::Context) {
+        self.debug_render_box(line_transform(from, to), color, box_vao, programs, gl);
+    }
+
+    /// Line from `from` to `to` with a head at `to`
+    pub unsafe fn debug_render_arrow(&self, from: Vector3<f32>, to: Vector3<f32>, color: Vector3<f32>, box_vao: NativeVertexArray, programs: &mut ProgramBank, gl: &glow::Context) {
+        let direction = to - from;
+        if direction.magnitude2() < 0.0001 {
+            return;
+        }
+
+        let back = -direction.normalize() * ARROW_HEAD_SIZE;
+        let side = if back.cross(Vector3::unit_y()).magnitude2() > 0.0001 { back.cross(Vector3::unit_y()) } else { back.cross(Vector3::unit_x()) };
+        let side = side.normalize() * ARROW_HEAD_SIZE / 2.0;
+
+        for (start, end) in [(from, to), (to, to + back + side), (to, to + back - side)] {
+            self.debug_render_box(line_transform(start, end), color, box_vao, programs, gl);
+        }
+    }
 
     #[inline]
-    unsafe fn render_single_mesh(&self, data: &MobileRenderData, textures: &TextureBank, program: &mut Program, material: &Material, mesh: &Mesh, gl: &glow::Context) {
+    pub unsafe fn render_single_mesh(&self, data: &MobileRenderData, textures: &TextureBank, program: &mut Program, material: &Material, mesh: &Mesh, gl: &glow::Context) {
         program.uniform_matrix4f32("model", data.transform, gl);
         program.uniform_matrix3f32("normal_matrix", data.normal_matrix, gl);
         program.uniform_1i32("flags", data.flags as i32, gl);
+        program.uniform_1f32("fade", data.fade, gl);
+        program.uniform_1f32("dissolve", data.dissolve, gl);
         program.uniform_1f32("material.shininess", material.shininess, gl);
+        program.uniform_1f32("material.reflectivity", material.reflectivity, gl);
+        program.uniform_3f32("material.emission", material.emission(), gl);
+        program.uniform_2f32("flipbook", material.flipbook_uniform(), gl);
         gl.active_texture(glow::TEXTURE0);
         gl.bind_texture(glow::TEXTURE_2D, textures.get(&material.diffuse).map(|s| s.inner));
         gl.active_texture(glow::TEXTURE1);
         gl.bind_texture(glow::TEXTURE_2D, textures.get(&material.specular).map(|s| s.inner));
+        gl.active_texture(glow::TEXTURE0 + EMISSIVE_TEXTURE_UNIT);
+        gl.bind_texture(glow::TEXTURE_2D, textures.get(&material.emissive).map(|s| s.inner));
+        gl.active_texture(glow::TEXTURE0);
         gl.bind_vertex_array(Some(mesh.vao));
 
         gl.draw_elements(
@@ -521,14 +1012,24 @@ impl Scene {
     unsafe fn render_individual(&self, data: &[MobileRenderData], name: &String, meshes: &MeshBank, textures: &TextureBank, program: &mut shader::Program, gl: &glow::Context) {
         let mesh = meshes.get(name).unwrap_or_else(|| panic!("Missing mesh \"{}\"", name));
         let material = self.materials.get(&mesh.material).unwrap_or_else(|| panic!("Missing material \"{}\"", mesh.material));
+        let has_lods = meshes.has_lods(name);
 
+        // Everything else drawn with this program is untinted
+        program.uniform_4f32("tint", self.tint_for(name), gl);
         for data in data.iter() {
             // Skip drawing if this is set as invisible
-            if !data.draw { continue; }
+            if !data.draw || data.fade <= 0.0 || self.skipped(data.flags) { continue; }
 
             // Set transform and flags individually instead as of part of the instance buffer
-            self.render_single_mesh(data, textures, program, material, mesh, gl);
+            if has_lods {
+                let lod = meshes.lod_mesh(name, self.lod_distance(data.transform)).unwrap_or(mesh);
+                let lod_material = self.materials.get(&lod.material).unwrap_or(material);
+                self.render_single_mesh(data, textures, program, lod_material, lod, gl);
+            } else {
+                self.render_single_mesh(data, textures, program, material, mesh, gl);
+            }
         }
+        program.uniform_4f32("tint", Vector4::zero(), gl);
     }
 
     #[inline]
@@ -537,17 +1038,71 @@ impl Scene {
         let material = self.materials.get(&mesh.material).unwrap_or_else(|| panic!("Missing material \"{}\"", mesh.material));
 
         for data in data {
-            if !data.draw && data.show_hidden {
+            if !data.draw && data.show_hidden && !self.skipped(data.flags) {
                 self.render_single_mesh(data, textures, program, material, mesh, gl);
             }
         }
     }
 
+    /// Program `mesh` is drawn with if its material has its own shader
+    pub fn material_shader<'a>(&'a self, mesh: &str, meshes: &MeshBank) -> Option<&'a str> {
+        let mesh = meshes.get(mesh)?;
+        self.materials.get(&mesh.material)?.shader.as_deref()
+    }
+
+    /// Meshes whose material has its own shader, grouped so each program is set up once<br>
+    /// Static meshes are drawn one at a time like in safe mode, they aren't instanced for these programs
+    unsafe fn render_shader_materials(&self, meshes: &MeshBank, programs: &mut ProgramBank, textures: &TextureBank, gl: &glow::Context) {
+        let mut groups: BTreeMap<&str, Vec<(&String, Vec<MobileRenderData>)>> = BTreeMap::new();
+        for (name, data) in self.static_meshes.iter() {
+            let Some(shader) = self.material_shader(name, meshes) else { continue; };
+            let data = data.iter().map(|data| MobileRenderData {
+                flags: data.flags, transform: data.transform, normal_matrix: data.normal_matrix, draw: true, show_hidden: false, fade: 1.0, dissolve: 0.0
+            }).collect();
+            groups.entry(shader).or_default().push((name, data));
+        }
+        for (name, data) in self.mobile_meshes.iter() {
+            let Some(shader) = self.material_shader(name, meshes) else { continue; };
+            groups.entry(shader).or_default().push((name, data.clone()));
+        }
+
+        for (shader, group) in groups {
+            let Some(program) = programs.get_mut(shader) else { continue; };
+            gl.use_program(Some(program.inner));
+            program.uniform_matrix4f32("view", self.camera.view, gl);
+            program.uniform_matrix4f32("projection", self.camera.projection, gl);
+            program.uniform_3f32("viewPos", self.camera.pos.to_vec(), gl);
+            program.uniform_1i32("material.diffuse", 0, gl);
+            program.uniform_1i32("material.specular", 1, gl);
+            program.uniform_1f32("time", self.time, gl);
+            self.uniform_lights(program, false, gl);
+
+            for (name, data) in group {
+                let material = &self.materials[&meshes.get(name).unwrap().material];
+                for (uniform, value) in material.uniforms.iter() {
+                    value.apply(program, uniform, gl);
+                }
+                self.render_individual(&data, name, meshes, textures, program, gl);
+            }
+        }
+    }
+
+    /// With `clustered` the point lights come from `clusters`, otherwise up to `MAX_POINT_LIGHTS` of them are uploaded as uniforms<br>
+    /// Material shaders only know the uniforms
     #[inline]
-    unsafe fn uniform_lights(&self, program: &mut shader::Program, gl: &glow::Context) {
-        program.uniform_1i32("pointLightCount", self.point_lights.len().min(64) as i32, gl);
+    unsafe fn uniform_lights(&self, program: &mut shader::Program, clustered: bool, gl: &glow::Context) {
+        let (max_point_lights, max_spot_lights) = if self.safe_mode {
+            (SAFE_MODE_POINT_LIGHTS, SAFE_MODE_SPOT_LIGHTS)
+        } else if clustered {
+            (0, MAX_SPOT_LIGHTS)
+        } else {
+            (MAX_POINT_LIGHTS, MAX_SPOT_LIGHTS)
+        };
+        self.clusters.uniform(program, clustered, self.window_size, gl);
+
+        program.uniform_1i32("pointLightCount", self.point_lights.len().min(max_point_lights) as i32, gl);
 
-        for i in 0..(self.point_lights.len().min(64)) {
+        for i in 0..(self.point_lights.len().min(max_point_lights)) {
             let light = self.point_lights.get(i).unwrap();
             program.uniform_3f32(&format!("pointLights[{}].position", i), light.position, gl);
             program.uniform_1f32(&format!("pointLights[{}].constant", i), light.constant, gl);
@@ -556,12 +1111,74 @@ impl Scene {
             program.uniform_3f32(&format!("pointLights[{}].ambient", i), light.ambient, gl);
             program.uniform_3f32(&format!("pointLights[{}].diffuse", i), light.diffuse, gl);
             program.uniform_3f32(&format!("pointLights[{}].specular", i), light.specular, gl);
+            program.uniform_1i32(&format!("pointLights[{}].shadow", i), self.shadows.map_of(i).map_or(-1, |map| map as i32), gl);
+        }
+
+        // Every unit is set even without shadows, samplers of different types can't share one
+        for map in 0..MAX_SHADOW_LIGHTS {
+            program.uniform_1i32(&format!("shadowMaps[{}]", map), (SHADOW_TEXTURE_UNIT as usize + map) as i32, gl);
+            if let (Some(cubemap), Some(light)) = (self.shadows.cubemap(map), self.shadows.casters.get(map)) {
+                program.uniform_1f32(&format!("shadowFarPlanes[{}]", map), self.point_lights[*light].user_attenuation_or_default(), gl);
+                gl.active_texture(glow::TEXTURE0 + SHADOW_TEXTURE_UNIT + map as u32);
+                gl.bind_texture(glow::TEXTURE_CUBE_MAP, Some(cubemap));
+            }
+        }
+        gl.active_texture(glow::TEXTURE0);
+
+        program.uniform_1i32("spotLightCount", self.spot_lights.len().min(max_spot_lights) as i32, gl);
+
+        for (i, light) in self.spot_lights.iter().take(max_spot_lights).enumerate() {
+            program.uniform_3f32(&format!("spotLights[{}].position", i), light.position, gl);
+            program.uniform_3f32(&format!("spotLights[{}].direction", i), light.direction, gl);
+            program.uniform_1f32(&format!("spotLights[{}].innerCutoff", i), light.inner_cutoff, gl);
+            program.uniform_1f32(&format!("spotLights[{}].outerCutoff", i), light.outer_cutoff, gl);
+            program.uniform_1f32(&format!("spotLights[{}].constant", i), light.constant, gl);
+            program.uniform_1f32(&format!("spotLights[{}].linear", i), light.linear, gl);
+            program.uniform_1f32(&format!("spotLights[{}].quadratic", i), light.quadratic, gl);
+            program.uniform_3f32(&format!("spotLights[{}].ambient", i), light.ambient, gl);
+            program.uniform_3f32(&format!("spotLights[{}].diffuse", i), light.diffuse, gl);
+            program.uniform_3f32(&format!("spotLights[{}].specular", i), light.specular, gl);
         }
 
         program.uniform_3f32("dirLight.direction", self.environment.dir_light.direction, gl);
         program.uniform_3f32("dirLight.ambient", self.environment.dir_light.ambient, gl);
         program.uniform_3f32("dirLight.diffuse", self.environment.dir_light.diffuse, gl);
         program.uniform_3f32("dirLight.specular", self.environment.dir_light.specular, gl);
+
+        // Distances in the orthographic views are from a camera far outside the level
+        let fog = &self.environment.fog;
+        program.uniform_1i32("distanceFog.enabled", (fog.enabled && self.camera.ortho.is_none()) as i32, gl);
+        program.uniform_3f32("distanceFog.color", fog.color.into(), gl);
+        program.uniform_1f32("distanceFog.start", fog.start, gl);
+        program.uniform_1f32("distanceFog.end", fog.end, gl);
+        program.uniform_1f32("distanceFog.density", fog.density, gl);
+
+        self.uniform_reflection(program, gl);
+    }
+
+    /// Draw only the meshes of `model` from `eye`, lit like the rest of the scene, see `impostor`
+    pub unsafe fn render_model_only(&self, model: &Model, eye: Point3<f32>, view: Matrix4<f32>, projection: Matrix4<f32>, meshes: &MeshBank, programs: &mut ProgramBank, textures: &TextureBank, gl: &glow::Context) {
+        let flat_program = programs.get_mut("flat").unwrap();
+        gl.use_program(Some(flat_program.inner));
+        flat_program.uniform_matrix4f32("view", view, gl);
+        flat_program.uniform_matrix4f32("projection", projection, gl);
+        flat_program.uniform_3f32("viewPos", eye.to_vec(), gl);
+        flat_program.uniform_1i32("material.diffuse", 0, gl);
+        flat_program.uniform_1i32("material.specular", 1, gl);
+        flat_program.uniform_1i32("material.emissive", EMISSIVE_TEXTURE_UNIT as i32, gl);
+        self.uniform_lights(flat_program, false, gl);
+        // Impostors are seen from other distances than they are drawn from
+        flat_program.uniform_1i32("distanceFog.enabled", 0, gl);
+        flat_program.uniform_1i32("reflection.enabled", 0, gl);
+
+        for (renderable, index) in model.render.iter().zip(model.renderable_indices.iter()) {
+            let Some(name) = renderable.get_mesh() else { continue; };
+            let (Some(mesh), Some(data)) = (meshes.get(&name), self.mobile_meshes.get(&name).and_then(|data| data.get(*index))) else { continue; };
+            let Some(material) = self.materials.get(&mesh.material) else { continue; };
+            if !data.draw || self.skipped(data.flags) { continue; }
+
+            self.render_single_mesh(&MobileRenderData { fade: 1.0, ..*data }, textures, flat_program, material, mesh, gl);
+        }
     }
 
     /// Add a static mesh to the render scene
@@ -576,29 +1193,40 @@ impl Scene {
     /// Add a mobile mesh to the render scene
     fn add_mobile_mesh(&mut self, mesh: &str, transform: Matrix4<f32>, flags: u32) {
         if let Some(transforms) = self.mobile_meshes.get_mut(mesh) {
-            transforms.push(MobileRenderData { transform, flags, draw: true, normal_matrix: normal_matrix(transform), show_hidden: false });
+            transforms.push(MobileRenderData { transform, flags, draw: true, normal_matrix: normal_matrix(transform), show_hidden: false, fade: 1.0, dissolve: 0.0 });
         } else {
-            self.mobile_meshes.insert(mesh.to_string(), vec![MobileRenderData { transform, flags, draw: true, normal_matrix: normal_matrix(transform), show_hidden: false }]);
+            self.mobile_meshes.insert(mesh.to_string(), vec![MobileRenderData { transform, flags, draw: true, normal_matrix: normal_matrix(transform), show_hidden: false, fade: 1.0, dissolve: 0.0 }]);
         }
     }
 
     /// Add a foreground mesh to the render scene (no depth test, drawn last)
     fn add_foreground_mesh(&mut self, mesh: &str, transform: Matrix4<f32>, flags: u32) {
         if let Some(transforms) = self.foreground_meshes.get_mut(mesh) {
-            transforms.push(MobileRenderData { transform, flags, draw: true, normal_matrix: normal_matrix(transform), show_hidden: false });
+            transforms.push(MobileRenderData { transform, flags, draw: true, normal_matrix: normal_matrix(transform), show_hidden: false, fade: 1.0, dissolve: 0.0 });
         } else {
-            self.foreground_meshes.insert(mesh.to_string(), vec![MobileRenderData { transform, flags, draw: true, normal_matrix: normal_matrix(transform), show_hidden: false }]);
+            self.foreground_meshes.insert(mesh.to_string(), vec![MobileRenderData { transform, flags, draw: true, normal_matrix: normal_matrix(transform), show_hidden: false, fade: 1.0, dissolve: 0.0 }]);
         }
     }
 
-    fn add_billboard(&mut self, texture: &str, position: Vector3<f32>, size: (f32, f32), flags: u32, follow_vertical: bool) {
+    pub fn add_billboard(&mut self, texture: &str, position: Vector3<f32>, size: (f32, f32), flags: u32, follow_vertical: bool, flipbook: Flipbook) {
+        let frame = flipbook.frame_at(self.time);
         if let Some(data) = self.billboards.get_mut(texture) {
-            data.push(BillboardRenderData { position, flags, size, follow_vertical, draw: true, show_hidden: false });
+            data.push(BillboardRenderData { position, flags, size, follow_vertical, draw: true, show_hidden: false, fixed_rotation: None, fade: 1.0, flipbook, frame });
         } else {
-            self.billboards.insert(texture.to_string(), vec![BillboardRenderData { position, flags, size, follow_vertical, draw: true, show_hidden: false }]);
+            self.billboards.insert(texture.to_string(), vec![BillboardRenderData { position, flags, size, follow_vertical, draw: true, show_hidden: false, fixed_rotation: None, fade: 1.0, flipbook, frame }]);
         }
     }
 
+    /// Add text as a billboard, its texture is rasterized in `prepare_text`
+    fn add_text(&mut self, label: &TextLabel, position: Vector3<f32>, flags: u32, model_transform: Matrix4<f32>) -> usize {
+        let texture = label.texture_name();
+        self.add_billboard(&texture, position, label.size(), flags, false, Flipbook::default());
+        self.pending_text.push(label.clone());
+        let data = self.billboards.get_mut(&texture).unwrap();
+        data.last_mut().unwrap().fixed_rotation = text_rotation(label, model_transform);
+        data.len() - 1
+    }
+
     fn insert_mesh_from_model(&mut self, name: &String, transform: &Matrix4<f32>, flags: u32, model: &Model, renderable_indices: &mut Vec<usize>) {
         if model.foreground {
             self.add_foreground_mesh(name, model.transform * transform, flags);
@@ -624,15 +1252,20 @@ impl Scene {
                 Renderable::Mesh(name, transform, flags) => {
                     self.insert_mesh_from_model(name, transform, *flags, model, &mut renderable_indices);
                 },
-                Renderable::Brush(texture, position, size, flags) => {
-                    let name = format!("Brush_{}", texture);
+                Renderable::Brush(material, position, size, flags, faces) => {
+                    let name = faces.mesh_name(material);
+                    self.add_brush_variant(&name, material, faces);
                     let transform = Matrix4::from_translation(*position) * Matrix4::from_nonuniform_scale(size.x, size.y, size.z);
                     self.insert_mesh_from_model(&name, &transform, *flags, model, &mut renderable_indices);
                 },
-                Renderable::Billboard(texture, position, size, flags, follow_vertical) => {
+                Renderable::Billboard(texture, position, size, flags, follow_vertical, flipbook) => {
                     let transformed_position = model.transform.transform_point(Point3::from_vec(*position)).to_vec();
-                    self.add_billboard(texture.as_str(), transformed_position, *size, *flags, *follow_vertical);
+                    self.add_billboard(texture.as_str(), transformed_position, *size, *flags, *follow_vertical, *flipbook);
                     renderable_indices.push(self.billboards.get(texture).unwrap().len() - 1);
+                },
+                Renderable::Text(label, position, flags) => {
+                    let transformed_position = model.transform.transform_point(Point3::from_vec(*position)).to_vec();
+                    renderable_indices.push(self.add_text(label, transformed_position, *flags, model.transform));
                 }
             }
         }
@@ -640,6 +1273,13 @@ impl Scene {
         renderable_indices
     }
 
+    /// Make sure the mesh for a brush alignment exists by the next update
+    fn add_brush_variant(&mut self, name: &str, material: &str, faces: &BrushFaces) {
+        if !faces.is_default() {
+            self.pending_brush_variants.push((name.to_string(), material.to_string(), *faces));
+        }
+    }
+
     /// Insert a new renderable into a preexisting model
     pub fn amend_model(&mut self, model: &mut Model, renderable: Renderable) {
         match renderable {
@@ -648,16 +1288,22 @@ impl Scene {
                 self.insert_mesh_from_model(name, &transform, flags, model, &mut renderable_indices);
                 model.renderable_indices.append(&mut renderable_indices);
             },
-            Renderable::Brush(ref material, position, size, flags) => {
-                let name = format!("Brush_{}", material);
+            Renderable::Brush(ref material, position, size, flags, ref faces) => {
+                let name = faces.mesh_name(material);
+                self.add_brush_variant(&name, material, faces);
                 let transform = Matrix4::from_translation(position) * Matrix4::from_nonuniform_scale(size.x, size.y, size.z);
                 let mut renderable_indices = Vec::new();
                 self.insert_mesh_from_model(&name, &transform, flags, model, &mut renderable_indices);
                 model.renderable_indices.append(&mut renderable_indices);
             },
-            Renderable::Billboard(ref texture, position, size, flags, follow_vertical) => {
-                self.add_billboard(texture.as_str(), position, size, flags, follow_vertical);
+            Renderable::Billboard(ref texture, position, size, flags, follow_vertical, flipbook) => {
+                let transformed_position = model.transform.transform_point(Point3::from_vec(position)).to_vec();
+                self.add_billboard(texture.as_str(), transformed_position, size, flags, follow_vertical, flipbook);
                 model.renderable_indices.push(self.billboards.get(texture).unwrap().len() - 1);
+            },
+            Renderable::Text(ref label, position, flags) => {
+                let transformed_position = model.transform.transform_point(Point3::from_vec(position)).to_vec();
+                model.renderable_indices.push(self.add_text(label, transformed_position, flags, model.transform));
             }
         }
         
@@ -678,22 +1324,28 @@ impl Scene {
     /// "Removes" a renderable (replaces it with dummy data for the time being **TODO** btw)<br>
     /// Make sure to update collider references
     pub fn remove_renderable(&mut self, model: &mut Model, index: usize) {
+        self.remove_render_data(model, index);
+        model.render.remove(index);
+        model.renderable_indices.remove(index);
+    }
+
+    /// Replace a renderable's data with dummy data but keep it in the model, for reinserting the model with `insert_model`
+    pub fn remove_render_data(&mut self, model: &Model, index: usize) {
         let data_index = model.renderable_indices[index];
         match model.render.get(index).as_ref().unwrap() {
-            Renderable::Brush(material, _, _, _) => {
-                let name = format!("Brush_{}", material);
-                self.remove_mesh(data_index, &name, model);
+            renderable @ Renderable::Brush(..) => {
+                self.remove_mesh(data_index, &renderable.get_mesh().unwrap(), model);
             },
             Renderable::Mesh(name, _, _) => {
                 self.remove_mesh(data_index, name, model);
             },
-            Renderable::Billboard(texture, _, _, _, _) => {
-                *self.billboards.get_mut(texture).unwrap().get_mut(index).unwrap() = *DUMMY_BILLBOARD_DATA;
+            Renderable::Billboard(texture, ..) => {
+                self.billboards.get_mut(texture).unwrap()[data_index] = *DUMMY_BILLBOARD_DATA;
+            },
+            Renderable::Text(label, ..) => {
+                self.billboards.get_mut(&label.texture_name()).unwrap()[data_index] = *DUMMY_BILLBOARD_DATA;
             }
         }
-
-        model.render.remove(index);
-        model.renderable_indices.remove(index);
     }
 
     pub unsafe fn load_texture_to_material(&mut self, texture: &str, textures: &mut TextureBank, gl: &glow::Context) {
@@ -707,10 +1359,46 @@ impl Scene {
         self.add_material(Material::new(diffuse, specular, 32.0), name);
     }
 
+    /// Give material `name` an emissive texture, `None` glows evenly in `color`
+    pub unsafe fn load_material_emissive(&mut self, name: &str, texture: Option<&str>, color: Vector3<f32>, strength: f32, textures: &mut TextureBank, gl: &glow::Context) {
+        let texture = match texture {
+            Some(texture) => self.load_material_texture(name, texture, "magic_pixel", textures, gl),
+            None => {
+                textures.load_by_name("magic_pixel", gl).unwrap();
+                String::from("magic_pixel")
+            }
+        };
+        let Some(material) = self.materials.get_mut(name) else { return; };
+        material.emissive = texture;
+        material.emissive_color = color;
+        material.emissive_strength = strength;
+    }
+
     pub unsafe fn load_material_diff_spec_phys(&mut self, name: &str, diffuse: &str, specular: &str, phys: PhysicalProperties, textures: &mut TextureBank, gl: &glow::Context) {
-        textures.load_by_name(diffuse, gl).unwrap();
-        textures.load_by_name(specular, gl).unwrap();
-        self.add_material(Material::with_physical_properties(diffuse, specular, 32.0, phys), name);
+        let diffuse = self.load_material_texture(name, diffuse, "evil_pixel", textures, gl);
+        let specular = self.load_material_texture(name, specular, "magic_pixel", textures, gl);
+        self.add_material(Material::with_physical_properties(&diffuse, &specular, 32.0, phys), name);
+    }
+
+    /// Load one of a material's textures, returns the texture to use<br>
+    /// A texture that fails to load is replaced by `placeholder`, problems are added to `material_problems`
+    unsafe fn load_material_texture(&mut self, material: &str, texture: &str, placeholder: &str, textures: &mut TextureBank, gl: &glow::Context) -> String {
+        // Rendered by `World::render_feeds`, nothing is drawn until the feed is
+        if feed::is_feed_texture(texture) {
+            return texture.to_string();
+        }
+        if let Err(e) = textures.load_by_name(texture, gl) {
+            self.material_problems.push((material.to_string(), format!("{} failed to load, using {}: {}", texture, placeholder, e)));
+            textures.load_by_name(placeholder, gl).unwrap();
+            return placeholder.to_string();
+        }
+
+        // Brush texture coordinates repeat the texture, which only lines up across faces for power of two sizes
+        let loaded = &textures.textures[texture];
+        if !loaded.width.is_power_of_two() || !loaded.height.is_power_of_two() {
+            self.material_problems.push((material.to_string(), format!("{} is {}x{}, not a power of two, so it won't tile evenly", texture, loaded.width, loaded.height)));
+        }
+        texture.to_string()
     }
 
     /// Mark a static mesh group for rebuffering
@@ -723,13 +1411,40 @@ impl Scene {
 
     fn update_model_transform_common(&mut self, renderable: &Renderable, index: usize, model_transform: Matrix4<f32>) {
         match renderable {
-            Renderable::Billboard(texture, position, _, _, _) => {
+            Renderable::Billboard(texture, position, ..) => {
                 self.billboards.get_mut(texture).unwrap()[index].position = model_transform.transform_point(Point3::from_vec(*position)).to_vec();
             },
+            Renderable::Text(label, position, _) => {
+                let data = &mut self.billboards.get_mut(&label.texture_name()).unwrap()[index];
+                data.position = model_transform.transform_point(Point3::from_vec(*position)).to_vec();
+                data.fixed_rotation = text_rotation(label, model_transform);
+            },
             _ => unreachable!()
         }
     }
 
+    /// Copy the flags of a renderable into its render data, static meshes are rebuffered
+    pub fn update_renderable_flags(&mut self, model: &Model, index: usize) {
+        let renderable = &model.render[index];
+        let data_index = model.renderable_indices[index];
+        let flags = renderable.flags();
+
+        if let Some(texture) = renderable.billboard_texture() {
+            self.billboards.get_mut(&texture).unwrap()[data_index].flags = flags;
+            return;
+        }
+
+        let name = renderable.get_mesh().unwrap();
+        if model.foreground {
+            self.foreground_meshes.get_mut(&name).unwrap()[data_index].flags = flags;
+        } else if model.mobile {
+            self.mobile_meshes.get_mut(&name).unwrap()[data_index].flags = flags;
+        } else {
+            self.static_meshes.get_mut(&name).unwrap()[data_index].flags = flags;
+            self.mark_static(&name);
+        }
+    }
+
     /// Just updates the transform for a mobile mesh,<br>
     /// But when updating a static mesh all other instances of the same type must be rebuffered so be careful
     pub fn update_model_transform(&mut self, model: &Model) {
@@ -737,9 +1452,9 @@ impl Scene {
             if renderable.render_as_mesh() {
                 let (mesh_transform, name) = match renderable {
                     Renderable::Mesh(name, transform, _) => (model.transform * transform, name),
-                    Renderable::Brush(texture, position, size, _) => (
+                    Renderable::Brush(material, position, size, _, faces) => (
                         model.transform * Matrix4::from_translation(*position) * Matrix4::from_nonuniform_scale(size.x, size.y, size.z),
-                        &format!("Brush_{}", texture)
+                        &faces.mesh_name(material)
                     ),
                     _ => unreachable!()
                 };
@@ -767,6 +1482,8 @@ impl Scene {
         Self {
             mobile_meshes: HashMap::new(),
             static_instance_buffers: HashMap::new(),
+            mobile_instance_buffers: HashMap::new(),
+            mobile_instance_counts: HashMap::new(),
             static_meshes: HashMap::new(),
             foreground_meshes: HashMap::new(),
             static_meshes_updated: Vec::new(),
@@ -774,22 +1491,41 @@ impl Scene {
             materials: HashMap::new(),
             environment: Environment::new(),
             point_lights: Vec::new(),
+            spot_lights: Vec::new(),
             statics_dirty: false,
             skybox_vao: None,
             billboards: HashMap::new(),
+            particles: Vec::new(),
+            impostors: HashMap::new(),
+            feeds: HashMap::new(),
+            reflection: None,
+            drawing_offscreen: false,
             window_size: (640 * 2, 480 * 2),
             ui_vao: None,
             show_hidden_objects: false,
             applicable_materials: Vec::new(),
+            material_problems: Vec::new(),
             post_process: unsafe { effects::PostProcessing::new(gl) },
-            world_default_effects: effects::DefaultEffects::new()
+            world_default_effects: effects::DefaultEffects::new(),
+            pending_text: Vec::new(),
+            pending_brush_variants: Vec::new(),
+            safe_mode: false,
+            visibility: None,
+            cull_statics: false,
+            brush_tint: BrushTint::Off,
+            visible_cell: None,
+            static_instance_counts: HashMap::new(),
+            lod_origin: None,
+            shadows: ShadowMaps::default(),
+            clusters: LightClusters::default(),
+            time: 0.0
         }
     }
 
     pub fn add_point_light(&mut self, light: PointLight) -> usize {
         self.point_lights.push(light);
 
-        if self.point_lights.len() > 64 {
+        if self.point_lights.len() > MAX_CLUSTERED_LIGHTS {
             eprintln!("Warning: Too many point lights in scene");
         }
         
@@ -799,33 +1535,75 @@ impl Scene {
     /// Rebuffers all changed static models<br>
     /// Clears `static_meshes_updated`
     pub unsafe fn prepare_statics(&mut self, meshes: &mut MeshBank, gl: &glow::Context) {
-        for updated in self.static_meshes_updated.drain(..) {
-            let new_buffer = if let Some(buffer) = self.static_instance_buffers.get_mut(&updated) {
-                gl.delete_buffer(*buffer);
-                *buffer = gl.create_buffer().unwrap();
-                buffer
-            } else {
-                let buffer = gl.create_buffer().unwrap();
-                self.static_instance_buffers.insert(updated.to_string(), buffer);
-                self.static_instance_buffers.get(&updated).unwrap()
+        let updated_meshes: Vec<String> = self.static_meshes_updated.drain(..).collect();
+        for updated in updated_meshes {
+            let render_data = self.static_meshes.get(&updated).unwrap();
+
+            // Only brushes are culled, other meshes aren't unit cubes
+            let render_data: Vec<RenderData> = match (&self.visibility, self.visible_cell) {
+                (Some(visibility), Some(cell)) if updated.starts_with("Brush_") => {
+                    render_data.iter().filter(|data| visibility.instance_visible(cell, data.transform)).copied().collect()
+                },
+                _ => render_data.clone()
             };
 
-            let render_data = self.static_meshes.get(&updated).unwrap();
+            if !meshes.has_lods(&updated) {
+                self.buffer_static_instances(&updated, &render_data, meshes, gl);
+                continue;
+            }
 
-            let instance_data: &[u8] = core::slice::from_raw_parts(
-                render_data.as_ptr() as *const u8,
-                render_data.len() * core::mem::size_of::<RenderData>()
-            );
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(*new_buffer));
-            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, instance_data, glow::STATIC_DRAW);
-        
-            let mesh = meshes.meshes.get_mut(&updated).expect("Failed to get mesh");
-            gl.bind_vertex_array(Some(mesh.vao_instanced));
-            Mesh::define_instanced_vertex_attributes(gl);
-            gl.bind_vertex_array(None);
+            // Each detail level is instanced from a buffer of its own, filled with the instances in its range
+            let mut levels: Vec<(String, Vec<RenderData>)> = meshes.lod_meshes(&updated).into_iter().map(|name| (name, Vec::new())).collect();
+            for data in render_data {
+                let level = meshes.lod_level(&updated, self.lod_distance(data.transform));
+                levels[level].1.push(data);
+            }
+            for (name, render_data) in levels {
+                if meshes.get(&name).is_some() {
+                    self.buffer_static_instances(&name, &render_data, meshes, gl);
+                }
+            }
         }
     }
 
+    /// Write the instances of a static mesh to its instance buffer
+    unsafe fn buffer_static_instances(&mut self, name: &str, render_data: &[RenderData], meshes: &mut MeshBank, gl: &glow::Context) {
+        let new_buffer = if let Some(buffer) = self.static_instance_buffers.get_mut(name) {
+            gl.delete_buffer(*buffer);
+            *buffer = gl.create_buffer().unwrap();
+            *buffer
+        } else {
+            let buffer = gl.create_buffer().unwrap();
+            self.static_instance_buffers.insert(name.to_string(), buffer);
+            buffer
+        };
+        self.static_instance_counts.insert(name.to_string(), render_data.len());
+
+        let instance_data: &[u8] = core::slice::from_raw_parts(
+            render_data.as_ptr() as *const u8,
+            core::mem::size_of_val(render_data)
+        );
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(new_buffer));
+        gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, instance_data, glow::STATIC_DRAW);
+    
+        let mesh = meshes.meshes.get_mut(name).expect("Failed to get mesh");
+        gl.bind_vertex_array(Some(mesh.vao_instanced));
+        mesh.define_instanced_vertex_attributes(gl);
+        gl.bind_vertex_array(None);
+    }
+
+    pub fn visibility(&self) -> Option<&Visibility> {
+        self.visibility.as_ref()
+    }
+
+    /// Replace the baked visibility, the static buffers are culled again next update
+    pub fn set_visibility(&mut self, visibility: Option<Visibility>) {
+        self.visibility = visibility;
+        self.visible_cell = None;
+        self.static_meshes_updated = self.static_meshes.keys().cloned().collect();
+        self.statics_dirty = true;
+    }
+
     pub fn add_material(&mut self, material: Material, name: &str) {
         self.materials.insert(name.to_string(), material);
     }
@@ -834,9 +1612,101 @@ impl Scene {
 #[derive(Clone)]
 pub enum CameraControlScheme {
     FirstPerson(bool),
-    Editor
+    Editor,
+    /// Flying freely while playing, the cursor stays locked
+    Spectator
+}
+
+/// Axis aligned editor views drawn with an orthographic projection
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OrthoView {
+    /// Looking down the Y axis
+    Top,
+    /// Looking down the Z axis
+    Front,
+    /// Looking down the X axis
+    Side
+}
+
+impl OrthoView {
+    /// Direction the camera looks in and the direction that is up on screen
+    pub fn axes(self) -> (Vector3<f32>, Vector3<f32>) {
+        match self {
+            Self::Top => (vec3(0.0, -1.0, 0.0), vec3(0.0, 0.0, -1.0)),
+            Self::Front => (vec3(0.0, 0.0, -1.0), vec3(0.0, 1.0, 0.0)),
+            Self::Side => (vec3(-1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0))
+        }
+    }
+
+    /// perspective -> top -> front -> side -> perspective
+    pub fn cycle(view: Option<Self>) -> Option<Self> {
+        match view {
+            None => Some(Self::Top),
+            Some(Self::Top) => Some(Self::Front),
+            Some(Self::Front) => Some(Self::Side),
+            Some(Self::Side) => None
+        }
+    }
+
+    pub fn name(view: Option<Self>) -> &'static str {
+        match view {
+            None => "perspective",
+            Some(Self::Top) => "top",
+            Some(Self::Front) => "front",
+            Some(Self::Side) => "side"
+        }
+    }
+}
+
+/// Axis aligned directions the editor camera can turn to
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ViewPreset {
+    Front,
+    Back,
+    Right,
+    Left,
+    Top,
+    Bottom
 }
 
+impl ViewPreset {
+    pub const ALL: [ViewPreset; 6] = [Self::Front, Self::Back, Self::Right, Self::Left, Self::Top, Self::Bottom];
+
+    pub fn action(self) -> Action {
+        match self {
+            Self::Front => Action::ViewFront,
+            Self::Back => Action::ViewBack,
+            Self::Right => Action::ViewRight,
+            Self::Left => Action::ViewLeft,
+            Self::Top => Action::ViewTop,
+            Self::Bottom => Action::ViewBottom
+        }
+    }
+
+    /// Yaw and pitch of the camera looking this way
+    fn angles(self) -> (f32, f32) {
+        match self {
+            Self::Front => (-f32::consts::PI / 2.0, 0.0),
+            Self::Back => (f32::consts::PI / 2.0, 0.0),
+            Self::Right => (f32::consts::PI, 0.0),
+            Self::Left => (0.0, 0.0),
+            Self::Top => (-f32::consts::PI / 2.0, -MAX_PITCH),
+            Self::Bottom => (-f32::consts::PI / 2.0, MAX_PITCH)
+        }
+    }
+
+    /// The orthographic view looking the same way, if there is one
+    fn ortho(self) -> Option<OrthoView> {
+        match self {
+            Self::Front => Some(OrthoView::Front),
+            Self::Right => Some(OrthoView::Side),
+            Self::Top => Some(OrthoView::Top),
+            _ => None
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Camera {
     pub pos: Point3<f32>,
     pub direction: Vector3<f32>,
@@ -851,8 +1721,18 @@ pub struct Camera {
     pub pitch: f32,
     pub yaw: f32,
     pub sensitivity: f32,
+    /// Editor only, `None` is the normal perspective view
+    pub ortho: Option<OrthoView>,
+    /// Half the height of the area an orthographic view shows
+    ortho_height: f32,
+    /// View presets switch to the matching orthographic view once the camera has turned, from the editor preferences
+    pub ortho_presets: bool,
+    /// Yaw and pitch being turned to, and the orthographic view to switch to after
+    turning: Option<(f32, f32, Option<OrthoView>)>,
     fov: f32,
-    aspect: f32
+    aspect: f32,
+    near: f32,
+    far: f32
 }
 
 impl Camera {
@@ -864,31 +1744,142 @@ impl Camera {
             up: vec3(0.0, 1.0, 0.0),
             view: Matrix4::identity(),
             inverse_view: Matrix4::identity(),
-            projection: cgmath::perspective(Deg(80.0), 640.0 / 480.0, 0.1, 100.0),
+            projection: cgmath::perspective(Deg(80.0), 640.0 / 480.0, DEFAULT_NEAR_PLANE, DEFAULT_FAR_PLANE),
             inverse_projection: Matrix4::identity(),
-            speed: 3.5,
+            speed: DEFAULT_CAMERA_SPEED,
             control_sceme: CameraControlScheme::FirstPerson(false), 
             pitch: 0.0,
             yaw: -f32::consts::PI / 2.0,
-            sensitivity: 0.007,
+            sensitivity: DEFAULT_CAMERA_SENSITIVITY,
+            ortho: None,
+            ortho_height: DEFAULT_ORTHO_HEIGHT,
+            ortho_presets: false,
+            turning: None,
             fov: 80.0,
-            aspect: 640.0 / 480.0
+            aspect: 640.0 / 480.0,
+            near: DEFAULT_NEAR_PLANE,
+            far: DEFAULT_FAR_PLANE
         };
         camera.inverse_projection = camera.projection.invert().unwrap();
         camera
     }
 
     pub fn on_window_resized(&mut self, width: f32, height: f32) {
-        self.projection = cgmath::perspective(Deg(self.fov), width / height, 0.1, 100.0);
-        self.inverse_projection = self.projection.invert().unwrap();
+        self.aspect = width / height;
+        self.update_projection();
     }
 
     pub fn set_fov(&mut self, new_fov: f32) {
         self.fov = new_fov;
-        self.projection = cgmath::perspective(Deg(self.fov), self.aspect, 0.1, 100.0);
+        self.update_projection();
+    }
+
+    /// The projection is only rebuilt if they changed, so this can be called every frame
+    pub fn set_clip_planes(&mut self, near: f32, far: f32) {
+        if near != self.near || far != self.far {
+            self.near = near;
+            self.far = far;
+            self.update_projection();
+        }
+    }
+
+    pub fn clip_planes(&self) -> (f32, f32) {
+        (self.near, self.far)
+    }
+
+    /// Nothing further than this is drawn
+    pub fn far(&self) -> f32 {
+        self.far
+    }
+
+    /// How far rays from `World::get_mouse_ray` reach, orthographic rays start behind the camera
+    pub fn pick_distance(&self) -> f32 {
+        if self.ortho.is_some() { ORTHO_DEPTH * 2.0 } else { self.far }
+    }
+
+    fn update_projection(&mut self) {
+        self.projection = if self.ortho.is_some() {
+            let (half_w, half_h) = self.ortho_half_size();
+            // Sees behind the camera too, so zooming out never clips the level
+            cgmath::ortho(-half_w, half_w, -half_h, half_h, -ORTHO_DEPTH, ORTHO_DEPTH)
+        } else {
+            cgmath::perspective(Deg(self.fov), self.aspect, self.near, self.far)
+        };
         self.inverse_projection = self.projection.invert().unwrap();
     }
 
+    /// Half the width and height of the area an orthographic view shows
+    pub fn ortho_half_size(&self) -> (f32, f32) {
+        (self.ortho_height * self.aspect, self.ortho_height)
+    }
+
+    /// Switch to an orthographic view, `None` goes back to perspective facing where it did before
+    pub fn set_ortho(&mut self, view: Option<OrthoView>) {
+        self.ortho = view;
+        if view.is_none() {
+            self.calculate_direction();
+        }
+        self.update_projection();
+    }
+
+    /// Put the camera at `pos` looking along `yaw` and `pitch`, in perspective
+    pub fn look_from(&mut self, pos: Point3<f32>, yaw: f32, pitch: f32) {
+        self.pos = pos;
+        self.yaw = yaw;
+        self.pitch = pitch.clamp(-MAX_PITCH, MAX_PITCH);
+        self.turning = None;
+        self.set_ortho(None);
+    }
+
+    /// Put the camera at `pos` looking along `direction` without going through the yaw and pitch, for cameras the player doesn't control
+    pub fn look_along(&mut self, pos: Point3<f32>, direction: Vector3<f32>) {
+        self.pos = pos;
+        self.direction = direction;
+        // Straight up or down has no right of its own
+        let world_up = if direction.cross(Vector3::unit_y()).magnitude2() > 0.0001 { Vector3::unit_y() } else { Vector3::unit_z() };
+        self.right = world_up.cross(direction).normalize();
+        self.up = direction.cross(self.right);
+        self.view = Matrix4::look_at_rh(pos, pos + direction, world_up);
+        self.inverse_view = self.view.invert().unwrap();
+    }
+
+    /// Scroll zoom, orthographic views shrink the area shown instead of moving
+    pub fn zoom(&mut self, amount: f32) {
+        if self.ortho.is_some() {
+            self.ortho_height = (self.ortho_height * (1.0 - amount * 0.01)).clamp(0.5, ORTHO_DEPTH / 2.0);
+            self.update_projection();
+        } else {
+            self.pos -= self.direction * 0.05 * amount;
+        }
+    }
+
+    /// Smoothly turn to look along `preset`, leaving an orthographic view first
+    pub fn turn_to(&mut self, preset: ViewPreset) {
+        if self.ortho.is_some() {
+            self.set_ortho(None);
+        }
+        let (yaw, pitch) = preset.angles();
+        // Turn the short way around
+        let yaw = self.yaw + (yaw - self.yaw + f32::consts::PI).rem_euclid(f32::consts::TAU) - f32::consts::PI;
+        self.turning = Some((yaw, pitch, if self.ortho_presets { preset.ortho() } else { None }));
+    }
+
+    fn update_turning(&mut self, delta_time: f32) {
+        let Some((yaw, pitch, ortho)) = self.turning else { return; };
+        let amount = (PRESET_TURN_RATE * delta_time).min(1.0);
+        self.yaw += (yaw - self.yaw) * amount;
+        self.pitch += (pitch - self.pitch) * amount;
+        if (yaw - self.yaw).abs() < 0.001 && (pitch - self.pitch).abs() < 0.001 {
+            self.yaw = yaw;
+            self.pitch = pitch;
+            self.turning = None;
+            if ortho.is_some() {
+                self.set_ortho(ortho);
+            }
+        }
+        self.calculate_direction();
+    }
+
     fn calculate_direction(&mut self) {
         self.direction.x = self.yaw.cos() * self.pitch.cos();
         self.direction.y = self.pitch.sin();
@@ -898,69 +1889,108 @@ impl Camera {
 
     pub fn mouse_movement(&mut self, dx: f64, dy: f64, input: &Input) {
         match self.control_sceme {
+            CameraControlScheme::Editor if self.ortho.is_some() => {
+                // Pan instead of turning
+                if input.get_mouse_button_pressed(MouseButton::Right) {
+                    let scale = self.ortho_height / 300.0;
+                    self.pos += (self.right * dx as f32 + self.up * dy as f32) * scale;
+                }
+            },
             CameraControlScheme::Editor => {
                 if input.get_mouse_button_pressed(MouseButton::Right) {
+                    self.turning = None;
                     self.yaw += dx as f32 * self.sensitivity;
                     self.pitch += dy as f32 * self.sensitivity;
 
-                    if self.pitch > (f32::consts::PI / 2.0) - 0.025 {
-                        self.pitch = (f32::consts::PI / 2.0) - 0.025;
-                    } else if self.pitch < (-f32::consts::PI / 2.0) + 0.025 {
-                        self.pitch = (-f32::consts::PI / 2.0) + 0.025;
-                    }
+                    self.pitch = self.pitch.clamp(-MAX_PITCH, MAX_PITCH);
 
                     self.calculate_direction();
                 }
             }
-            CameraControlScheme::FirstPerson(locked) => {
-                if locked {
-                    self.yaw += dx as f32 * self.sensitivity;
-                    self.pitch += dy as f32 * self.sensitivity;
+            CameraControlScheme::FirstPerson(true) | CameraControlScheme::Spectator => {
+                self.yaw += dx as f32 * self.sensitivity;
+                self.pitch += dy as f32 * self.sensitivity;
 
-                    if self.pitch > (f32::consts::PI / 2.0) - 0.025 {
-                        self.pitch = (f32::consts::PI / 2.0) - 0.025;
-                    } else if self.pitch < (-f32::consts::PI / 2.0) + 0.025 {
-                        self.pitch = (-f32::consts::PI / 2.0) + 0.025;
-                    }
+                self.pitch = self.pitch.clamp(-MAX_PITCH, MAX_PITCH);
 
-                    self.calculate_direction();
-                }
-            }
+                self.calculate_direction();
+            },
+            CameraControlScheme::FirstPerson(false) => ()
+        }
+    }
+
+    /// Free movement along the view direction, `distance` is how far to move this frame
+    pub fn fly(&mut self, input: &Input, distance: f32) {
+        if input.action_pressed(Action::MoveForward) {
+            self.pos += distance * self.direction.normalize();
+        }
+        if input.action_pressed(Action::MoveBack) {
+            self.pos -= distance * self.direction.normalize();
+        }
+        if input.action_pressed(Action::MoveLeft) {
+            self.pos += distance * self.up.cross(self.direction).normalize().mul_element_wise(vec3(1.0, 0.0, 1.0));
+        }
+        if input.action_pressed(Action::MoveRight) {
+            self.pos -= distance * self.up.cross(self.direction).normalize().mul_element_wise(vec3(1.0, 0.0, 1.0));
+        }
+        if input.action_pressed(Action::MoveUp) {
+            self.pos += distance * self.up.normalize();
+        }
+        if input.action_pressed(Action::MoveDown) {
+            self.pos -= distance * self.up.normalize();
         }
     }
 
     pub fn update(&mut self, input: &Input, delta_time: f32) {
         match self.control_sceme {
-            CameraControlScheme::Editor => {
-                if !input.get_key_pressed(Key::Named(NamedKey::Control)) {
-                    if input.get_key_pressed(Key::Character("w".into())) {
-                        self.pos += self.speed * delta_time * self.direction.normalize();
-                    }
-                    if input.get_key_pressed(Key::Character("s".into())) {
-                        self.pos -= self.speed * delta_time * self.direction.normalize();
-                    }
-                    if input.get_key_pressed(Key::Character("a".into())) {
-                        self.pos += self.speed * delta_time * self.up.cross(self.direction).normalize().mul_element_wise(vec3(1.0, 0.0, 1.0));
-                    }
-                    if input.get_key_pressed(Key::Character("d".into())) {
-                        self.pos -= self.speed * delta_time * self.up.cross(self.direction).normalize().mul_element_wise(vec3(1.0, 0.0, 1.0));
-                    }
-                    if input.get_key_pressed(Key::Character("e".into())) {
-                        self.pos += self.speed * delta_time * self.up.normalize();
-                    }
-                    if input.get_key_pressed(Key::Character("q".into())) {
-                        self.pos -= self.speed * delta_time * self.up.normalize();
-                    }
+            CameraControlScheme::Editor if self.ortho.is_some() => {
+                let speed = self.speed * delta_time * self.ortho_height / DEFAULT_ORTHO_HEIGHT;
+                if input.action_pressed(Action::MoveForward) {
+                    self.pos += speed * self.up;
+                }
+                if input.action_pressed(Action::MoveBack) {
+                    self.pos -= speed * self.up;
+                }
+                if input.action_pressed(Action::MoveLeft) {
+                    self.pos += speed * self.right;
+                }
+                if input.action_pressed(Action::MoveRight) {
+                    self.pos -= speed * self.right;
                 }
             },
+            CameraControlScheme::Editor => self.fly(input, self.speed * delta_time),
             // Camera is moved by the player in this state
-            CameraControlScheme::FirstPerson(_) => ()
+            CameraControlScheme::FirstPerson(_) => (),
+            // Moved by the world at the spectator's speed
+            CameraControlScheme::Spectator => ()
+        }
+
+        if matches!(self.control_sceme, CameraControlScheme::Editor) && input.action_just_pressed(Action::CycleOrthoView) {
+            self.turning = None;
+            self.set_ortho(OrthoView::cycle(self.ortho));
         }
 
-        self.right = vec3(0.0, 1.0, 0.0).cross(self.direction).normalize();
+        if matches!(self.control_sceme, CameraControlScheme::Editor) {
+            if let Some(preset) = ViewPreset::ALL.into_iter().find(|preset| input.action_just_pressed(preset.action())) {
+                self.turn_to(preset);
+            }
+            self.update_turning(delta_time);
+        } else {
+            self.turning = None;
+        }
+
+        // Same handedness as the perspective view, `right` points to the left of the screen
+        let world_up = if let Some(view) = self.ortho {
+            let (direction, up) = view.axes();
+            self.direction = direction;
+            up
+        } else {
+            vec3(0.0, 1.0, 0.0)
+        };
+        self.right = world_up.cross(self.direction).normalize();
         self.up = self.direction.cross(self.right);
 
-        self.view = Matrix4::look_at_rh(self.pos, self.pos + self.direction, vec3(0.0, 1.0, 0.0));
+        self.view = Matrix4::look_at_rh(self.pos, self.pos + self.direction, world_up);
         self.inverse_view = self.view.invert().unwrap();
     }
 }
@@ -994,31 +2024,148 @@ impl World {
         }
     }
 
+    /// Draw the hovered brush with the picked material on top of itself
+    pub unsafe fn render_material_preview(&self, meshes: &MeshBank, programs: &mut ProgramBank, textures: &TextureBank, gl: &glow::Context) {
+        let (Some(material_name), Some(hovered)) = (&self.editor_data.apply_material, self.editor_data.material_hover) else { return; };
+        let brushes = self.models[self.internal.brushes].as_ref().unwrap();
+        let Some(Renderable::Brush(_, position, size, flags, _)) = brushes.render.get(hovered) else { return; };
+        let (Some(mesh), Some(material)) = (meshes.get(&format!("Brush_{}", material_name)), self.scene.materials.get(material_name)) else { return; };
+
+        let transform = brushes.transform * Matrix4::from_translation(*position) * Matrix4::from_nonuniform_scale(size.x, size.y, size.z);
+        let data = MobileRenderData { flags: *flags, transform, normal_matrix: normal_matrix(transform), draw: true, show_hidden: false, fade: 1.0, dissolve: 0.0 };
+
+        let flat_program = programs.get_mut("flat").unwrap();
+        gl.use_program(Some(flat_program.inner));
+        gl.enable(glow::BLEND);
+        gl.blend_color(0.0, 0.0, 0.0, MATERIAL_PREVIEW_ALPHA);
+        gl.blend_func(glow::CONSTANT_ALPHA, glow::ONE_MINUS_CONSTANT_ALPHA);
+        gl.depth_func(glow::LEQUAL);
+
+        self.scene.render_single_mesh(&data, textures, flat_program, material, mesh, gl);
+
+        gl.depth_func(glow::LESS);
+        gl.disable(glow::BLEND);
+    }
+
+    /// Grid at the snapping increment behind everything in orthographic views
+    pub unsafe fn render_ortho_grid(&self, programs: &mut ProgramBank, gl: &glow::Context) {
+        let camera = &self.scene.camera;
+        if !self.editor_data.active || camera.ortho.is_none() {
+            return;
+        }
+
+        let (half_w, half_h) = camera.ortho_half_size();
+        let mut spacing = self.editor_data.increment;
+        while half_w.max(half_h) * 2.0 / spacing > MAX_GRID_LINES {
+            spacing *= 2.0;
+        }
+        // Just in front of the far plane so the level covers it
+        let center = camera.pos.to_vec() + camera.direction * (ORTHO_DEPTH - 1.0);
+        let screen_right = -camera.right;
+
+        let lines_program = programs.get_mut("lines").unwrap();
+        gl.use_program(Some(lines_program.inner));
+        gl.bind_vertex_array(self.editor_data.selection_box_vao);
+        gl.line_width(1.0);
+        lines_program.uniform_matrix4f32("view", camera.view, gl);
+        lines_program.uniform_matrix4f32("projection", camera.projection, gl);
+
+        // Lines running along `along`, spaced out across `across`
+        for (along, half_along, across, half_across) in [(camera.up, half_h, screen_right, half_w), (screen_right, half_w, camera.up, half_h)] {
+            let first = ((center.dot(across) - half_across) / spacing).floor() as i32;
+            let last = ((center.dot(across) + half_across) / spacing).ceil() as i32;
+            // A box squashed flat on two axes draws as a line
+            let length = vec3(along.x.abs(), along.y.abs(), along.z.abs()) * half_along * 2.0;
+            for i in first..=last {
+                let position = center + across * (i as f32 * spacing - center.dot(across));
+                let color = if i % 8 == 0 { self.editor_data.grid_major_color } else { self.editor_data.grid_color };
+                lines_program.uniform_3f32("color", color, gl);
+                lines_program.uniform_matrix4f32("model", Matrix4::from_translation(position) * Matrix4::from_nonuniform_scale(length.x, length.y, length.z), gl);
+                gl.draw_elements(glow::LINES, 24, glow::UNSIGNED_SHORT, 0);
+            }
+        }
+
+        gl.bind_vertex_array(None);
+    }
+
+    /// Grid at the snapping increment on the y = 0 plane around the camera, in perspective views while editing
+    pub unsafe fn render_ground_grid(&self, programs: &mut ProgramBank, gl: &glow::Context) {
+        let camera = &self.scene.camera;
+        if !self.editor_data.active || !self.editor_data.show_grid || camera.ortho.is_some() {
+            return;
+        }
+
+        let mut spacing = self.editor_data.increment;
+        while GROUND_GRID_RADIUS * 2.0 / spacing > MAX_GRID_LINES {
+            spacing *= 2.0;
+        }
+        // Lines stay put as the camera moves, the major ones on multiples of 8 * spacing
+        let center = vec3(common::floor_to(camera.pos.x, spacing), 0.0, common::floor_to(camera.pos.z, spacing));
+        let count = (GROUND_GRID_RADIUS / spacing) as i32;
+
+        let lines_program = programs.get_mut("lines").unwrap();
+        gl.use_program(Some(lines_program.inner));
+        gl.bind_vertex_array(self.editor_data.selection_box_vao);
+        gl.line_width(1.0);
+        lines_program.uniform_matrix4f32("view", camera.view, gl);
+        lines_program.uniform_matrix4f32("projection", camera.projection, gl);
+
+        // A box squashed flat on two axes draws as a line
+        for (along, across) in [(Vector3::unit_x(), Vector3::unit_z()), (Vector3::unit_z(), Vector3::unit_x())] {
+            let length = along * GROUND_GRID_RADIUS * 2.0;
+            for i in -count..=count {
+                let offset = center.dot(across) + i as f32 * spacing;
+                let position = center - across * center.dot(across) + across * offset;
+                let major = (offset / spacing).round() as i32 % 8 == 0;
+                let color = if major { self.editor_data.grid_major_color } else { self.editor_data.grid_color };
+                lines_program.uniform_3f32("color", color, gl);
+                lines_program.uniform_matrix4f32("model", Matrix4::from_translation(position) * Matrix4::from_nonuniform_scale(length.x, 0.0, length.z), gl);
+                gl.draw_elements(glow::LINES, 24, glow::UNSIGNED_SHORT, 0);
+            }
+        }
+
+        gl.bind_vertex_array(None);
+    }
+
+    /// Outline where the stamp would be placed
+    pub unsafe fn render_stamp_preview(&self, programs: &mut ProgramBank, gl: &glow::Context) {
+        if let Some((center, half_extents)) = self.editor_data.stamp.as_ref().and_then(|stamp| stamp.hover) {
+            let transform = Matrix4::from_translation(center) * Matrix4::from_nonuniform_scale(half_extents.x * 2.0, half_extents.y * 2.0, half_extents.z * 2.0);
+            self.scene.debug_render_box(transform, vec3(0.0, 1.0, 0.0), self.editor_data.selection_box_vao.unwrap(), programs, gl);
+        }
+    }
+
     pub unsafe fn debug_render_colliders(&self, programs: &mut ProgramBank, gl: &glow::Context) {
-        for collider in self.physical_scene.colliders.iter() {
-            if let Some(collider) = collider {
-                // skip player
-                if collider.model.is_none() { continue; }
-                let pos = vec3(collider.bounding.center().x, collider.bounding.center().y, collider.bounding.center().z);
-                let scale = vec3(collider.bounding.half_extents().x, collider.bounding.half_extents().y, collider.bounding.half_extents().z);
-                let model = 
-                    Matrix4::from_translation(pos) *
-                    Matrix4::from_nonuniform_scale(scale.x * 2.0, scale.y * 2.0, scale.z * 2.0);
-                self.scene.debug_render_box(model, vec3(1.0, 0.0, 0.0), self.editor_data.selection_box_vao.unwrap(), programs, gl);
-                match collider.shape {
-                    crate::ColliderShape::Cuboid(cuboid) => {
-                        let scale = vec3(cuboid.half_extents.x, cuboid.half_extents.y, cuboid.half_extents.z) * 2.0;
-                        let tna = collider.iso.to_matrix();
-                        let transform = Matrix4::new(
-                            tna.m11, tna.m12, tna.m13, tna.m14,
-                            tna.m21, tna.m22, tna.m23, tna.m24,
-                            tna.m31, tna.m32, tna.m33, tna.m34,
-                            tna.m41, tna.m42, tna.m43, tna.m44,
-                        ).transpose() * Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z);
-                        self.scene.debug_render_box(transform, vec3(0.4, 0.1, 0.8), self.editor_data.selection_box_vao.unwrap(), programs, gl);
-                    }
-                }
+        for collider in self.physical_scene.colliders() {
+            // skip player
+            if collider.model.is_none() { continue; }
+            let pos = vec3(collider.bounding.center().x, collider.bounding.center().y, collider.bounding.center().z);
+            let scale = vec3(collider.bounding.half_extents().x, collider.bounding.half_extents().y, collider.bounding.half_extents().z);
+            let model = 
+                Matrix4::from_translation(pos) *
+                Matrix4::from_nonuniform_scale(scale.x * 2.0, scale.y * 2.0, scale.z * 2.0);
+            self.scene.debug_render_box(model, vec3(1.0, 0.0, 0.0), self.editor_data.selection_box_vao.unwrap(), programs, gl);
+            match &collider.shape {
+                crate::ColliderShape::Cuboid(cuboid) => {
+                    let scale = vec3(cuboid.half_extents.x, cuboid.half_extents.y, cuboid.half_extents.z) * 2.0;
+                    let tna = collider.iso.to_matrix();
+                    let transform = Matrix4::new(
+                        tna.m11, tna.m12, tna.m13, tna.m14,
+                        tna.m21, tna.m22, tna.m23, tna.m24,
+                        tna.m31, tna.m32, tna.m33, tna.m34,
+                        tna.m41, tna.m42, tna.m43, tna.m44,
+                    ).transpose() * Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z);
+                    self.scene.debug_render_box(transform, vec3(0.4, 0.1, 0.8), self.editor_data.selection_box_vao.unwrap(), programs, gl);
+                },
+                // Mesh colliders only show their bounding box
+                _ => ()
             }
         }
     }
-}
\ No newline at end of file
+}
+
+/// Squashes the unit selection box onto the line from `from` to `to`
+fn line_transform(from: Vector3<f32>, to: Vector3<f32>) -> Matrix4<f32> {
+    let middle = (from + to) / 2.0;
+    Matrix4::from_cols((to - from).extend(0.0), Vector4::zero(), Vector4::zero(), middle.extend(1.0))
+}
@@ -0,0 +1,258 @@
+use cgmath::{vec3, EuclideanSpace, InnerSpace, Matrix4, Point3, Transform, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{common, component::Component, events::EventKind, spectator, world::{PlayerMovementMode, World}};
+
+pub const DEFAULT_PLAYER_SPEED: f32 = 5.0;
+pub const DEFAULT_JUMP_VELOCITY: f32 = 7.0;
+pub const DEFAULT_KILL_HEIGHT: f32 = -50.0;
+pub const DEFAULT_FALL_DAMAGE_VELOCITY: f32 = 20.0;
+pub const DEFAULT_MAX_HEALTH: f32 = 100.0;
+pub const DEFAULT_HURT_DPS: f32 = 25.0;
+pub const DEFAULT_SPRINT_MULTIPLIER: f32 = 1.6;
+pub const DEFAULT_SPRINT_RAMP: f32 = 0.4;
+/// Stamina comes back this much slower than it is used
+const STAMINA_REGEN: f32 = 0.5;
+/// After running out, sprinting waits until this fraction of the stamina is back
+const STAMINA_RECOVERY: f32 = 0.25;
+/// How close the player has to be to a hurt volume to be hurt by it, so solid ones count
+pub const HURT_MARGIN: f32 = 0.05;
+/// How hard a wall jump pushes away from the wall, relative to player speed
+const WALL_JUMP_PUSH: f32 = 1.5;
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Debug)]
+pub enum RespawnBehavior {
+    /// Go back to the first spawnpoint in the level
+    Spawnpoint,
+    /// Go back to where the player last stood on the ground
+    LastGround,
+    /// Never respawn
+    Disabled
+}
+
+impl RespawnBehavior {
+    pub fn cycle(&self) -> Self {
+        match self {
+            Self::Spawnpoint => Self::LastGround,
+            Self::LastGround => Self::Disabled,
+            Self::Disabled => Self::Spawnpoint
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Spawnpoint => "spawnpoint",
+            Self::LastGround => "last ground",
+            Self::Disabled => "disabled"
+        }
+    }
+}
+
+fn default_respawn() -> RespawnBehavior { RespawnBehavior::Spawnpoint }
+fn default_kill_height() -> f32 { DEFAULT_KILL_HEIGHT }
+fn default_fall_damage_velocity() -> f32 { DEFAULT_FALL_DAMAGE_VELOCITY }
+fn default_sprint_multiplier() -> f32 { DEFAULT_SPRINT_MULTIPLIER }
+fn default_sprint_ramp() -> f32 { DEFAULT_SPRINT_RAMP }
+
+/// Per level tuning of game feel, saved with the level
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct GameplayRules {
+    #[serde(default="default_respawn")]
+    pub respawn: RespawnBehavior,
+    /// Falling below this height respawns the player
+    #[serde(default="default_kill_height")]
+    pub kill_height: f32,
+    /// Landing faster than `fall_damage_velocity` kills the player
+    #[serde(default)]
+    pub fall_damage: bool,
+    #[serde(default="default_fall_damage_velocity")]
+    pub fall_damage_velocity: f32,
+    /// Overrides the player's default speed
+    #[serde(default)]
+    pub player_speed: Option<f32>,
+    /// Overrides the player's default jump velocity
+    #[serde(default)]
+    pub jump_velocity: Option<f32>,
+    /// Overrides the player's default health
+    #[serde(default)]
+    pub max_health: Option<f32>,
+    #[serde(default)]
+    pub wall_jump: bool,
+    /// Holding the sprint key speeds the player up by `sprint_multiplier`
+    #[serde(default)]
+    pub sprint: bool,
+    #[serde(default="default_sprint_multiplier")]
+    pub sprint_multiplier: f32,
+    /// Seconds to get up to full sprint speed, and to slow back down
+    #[serde(default="default_sprint_ramp")]
+    pub sprint_ramp: f32,
+    /// Seconds the player can sprint for before resting, unlimited if `None`
+    #[serde(default)]
+    pub stamina: Option<f32>,
+    /// Seconds until the level restarts
+    #[serde(default)]
+    pub time_limit: Option<f32>,
+    /// Time the player from their first move, or the start line, to a goal, see `stopwatch`
+    #[serde(default)]
+    pub timed: bool
+}
+
+impl Default for GameplayRules {
+    fn default() -> Self {
+        Self {
+            respawn: RespawnBehavior::Spawnpoint,
+            kill_height: DEFAULT_KILL_HEIGHT,
+            fall_damage: false,
+            fall_damage_velocity: DEFAULT_FALL_DAMAGE_VELOCITY,
+            player_speed: None,
+            jump_velocity: None,
+            max_health: None,
+            wall_jump: false,
+            sprint: false,
+            sprint_multiplier: DEFAULT_SPRINT_MULTIPLIER,
+            sprint_ramp: DEFAULT_SPRINT_RAMP,
+            stamina: None,
+            time_limit: None,
+            timed: false
+        }
+    }
+}
+
+impl GameplayRules {
+    pub fn player_speed(&self) -> f32 {
+        self.player_speed.unwrap_or(DEFAULT_PLAYER_SPEED)
+    }
+
+    pub fn jump_velocity(&self) -> f32 {
+        self.jump_velocity.unwrap_or(DEFAULT_JUMP_VELOCITY)
+    }
+
+    pub fn max_health(&self) -> f32 {
+        self.max_health.unwrap_or(DEFAULT_MAX_HEALTH)
+    }
+
+    /// Speed multiplier `sprint` of the way into the sprint ramp, eased at both ends
+    pub fn sprint_speed(&self, sprint: f32) -> f32 {
+        let eased = sprint * sprint * (3.0 - 2.0 * sprint);
+        1.0 + (self.sprint_multiplier - 1.0) * eased
+    }
+
+    /// Seconds of stamina the player can have, 0 when it is unlimited
+    pub fn max_stamina(&self) -> f32 {
+        self.stamina.unwrap_or(0.0)
+    }
+
+    /// Use up or regain stamina, returns whether the player can keep sprinting<br>
+    /// `exhausted` is set when stamina runs out and cleared once `STAMINA_RECOVERY` of it is back
+    pub fn update_stamina(&self, stamina: &mut f32, exhausted: &mut bool, sprinting: bool, delta_time: f32) -> bool {
+        let Some(max) = self.stamina else { return true; };
+        if sprinting && !*exhausted {
+            *stamina = (*stamina - delta_time).max(0.0);
+            *exhausted = *stamina <= 0.0;
+        } else {
+            *stamina = (*stamina + delta_time * STAMINA_REGEN).min(max);
+            if *exhausted && *stamina >= max * STAMINA_RECOVERY {
+                *exhausted = false;
+            }
+        }
+        !*exhausted
+    }
+
+    /// Velocity to apply when jumping off a wall with this normal
+    pub fn wall_jump_velocity(&self, wall_normal: Vector3<f32>) -> Vector3<f32> {
+        let away = vec3(wall_normal.x, 0.0, wall_normal.z).normalize() * self.player_speed() * WALL_JUMP_PUSH;
+        vec3(away.x, self.jump_velocity(), away.z)
+    }
+}
+
+impl World {
+    /// Transform of the first model with a spawnpoint component
+    pub fn find_spawnpoint(&self) -> Option<Matrix4<f32>> {
+        self.models.iter().flatten()
+            .find(|model| model.components.iter().any(|c| matches!(c, Component::Spawnpoint)))
+            .map(|model| model.transform)
+    }
+
+    /// Put the player back according to the level's respawn behavior, with full health<br>
+    /// At a spawnpoint the camera faces the way the spawnpoint does, along its -z
+    pub fn respawn_player(&mut self) {
+        let spawnpoint = self.find_spawnpoint();
+        let (position, facing) = match (self.rules.respawn, self.player.last_ground) {
+            (RespawnBehavior::LastGround, Some(last_ground)) => (last_ground, None),
+            (RespawnBehavior::Spawnpoint | RespawnBehavior::LastGround, _) => match spawnpoint {
+                Some(transform) => (common::translation(transform), Some(transform.transform_vector(-Vector3::unit_z()))),
+                None => return
+            },
+            (RespawnBehavior::Disabled, _) => return
+        };
+
+        self.player.set_crouching(false, &mut self.physical_scene);
+        self.teleport_player(position);
+        self.player.health = self.rules.max_health();
+        self.player.dead = false;
+        self.player.reset_stamina(&self.rules);
+        if matches!(self.player.movement, PlayerMovementMode::FirstPerson) && !self.spectating() {
+            let camera = &mut self.scene.camera;
+            let yaw = facing.filter(|facing| facing.x != 0.0 || facing.z != 0.0).map_or(camera.yaw, |facing| facing.z.atan2(facing.x));
+            camera.look_from(Point3::from_vec(position + vec3(0.0, spectator::EYE_HEIGHT, 0.0)), yaw, 0.0);
+        }
+        self.log_event(EventKind::Respawned, String::from("player"), None, self.rules.respawn.name().to_string());
+    }
+
+    /// Take `amount` of the player's health, killing them at zero, negative amounts heal
+    pub fn damage_player(&mut self, amount: f32, cause: &str) {
+        if !self.do_game_logic || self.player.dead {
+            return;
+        }
+        self.player.health = (self.player.health - amount).min(self.rules.max_health());
+        if self.player.health <= 0.0 {
+            self.kill_player(cause);
+        }
+    }
+
+    /// Respawn the player, or leave them dead where they are if respawning is disabled
+    pub fn kill_player(&mut self, cause: &str) {
+        if self.player.dead {
+            return;
+        }
+        self.player.dead = true;
+        self.player.health = 0.0;
+        self.log_event(EventKind::Died, String::from("player"), None, cause.to_string());
+        self.respawn_player();
+    }
+
+    /// Move the player to `position` and stop them
+    pub fn teleport_player(&mut self, position: Vector3<f32>) {
+        self.player.position = position;
+        self.player.velocity = vec3(0.0, 0.0, 0.0);
+        self.physical_scene.set_collider_pos(self.player.collider, position);
+    }
+
+    /// Apply the player related rules, called when rules are loaded or changed
+    pub fn apply_rules(&mut self) {
+        self.player.speed = self.rules.player_speed();
+        self.player.jump_velocity = self.rules.jump_velocity();
+    }
+
+    /// Check rules that end a life or the level, call after player physics
+    pub fn update_rules(&mut self, landing_velocity: f32, delta_time: f32) {
+        if self.player.position.y < self.rules.kill_height {
+            self.kill_player("fell out of the level");
+        }
+
+        if self.rules.fall_damage && landing_velocity > self.rules.fall_damage_velocity {
+            self.kill_player(&format!("landed at {:.1}", landing_velocity));
+        }
+
+        if self.completion.is_none() {
+            self.level_time += delta_time;
+        }
+        if let Some(limit) = self.rules.time_limit {
+            if self.level_time > limit && self.completion.is_none() {
+                self.level_time = 0.0;
+                self.editor_data.show_debug.push(String::from("out of time"));
+                self.respawn_player();
+            }
+        }
+    }
+}
@@ -1,14 +1,90 @@
+use std::{collections::{BTreeMap, BTreeSet}, fs, path::{Path, PathBuf}};
+
 use cgmath::{vec3, Matrix4, SquareMatrix, Vector3, Zero};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{audio::LevelAudio, budget::PerformanceBudgets, collision::{self, DEFAULT_CONTROL, DEFAULT_FRICTION, DEFAULT_JUMP}, component::{Component, Trigger, TriggerType}, connection::Connection, effects::{DefaultEffects, FogEffect, HdrEffect, KernelEffect, SsaoEffect}, feed, flipbook::Flipbook, mesh::{self, MeshBank}, migrate::{self, LEVEL_VERSION}, post::{PostEffect, PostEffectSettings}, render::{self, DirLight, DistanceFog, Environment, Skybox}, rules::GameplayRules, shader::{ProgramBank, UniformValue}, texture::TextureBank, tint::BrushCategory, visibility::Visibility, world::{self, Model, World}};
+
+/// Levels with this extension are saved with bincode instead of JSON, for shipping
+pub const BINARY_LEVEL_EXTENSION: &str = "vlevel";
+/// Decimal places kept for floats in saved levels, enough for any grid increment
+const SAVE_DECIMALS: i32 = 5;
+/// Autosaves kept before the oldest is overwritten
+const AUTOSAVE_COUNT: usize = 5;
+
+/// `res/levels/autosave_N.json`, 0 is the newest
+pub fn autosave_path(n: usize) -> PathBuf {
+    PathBuf::from(format!("res/levels/autosave_{}.json", n))
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LevelFormat {
+    Json,
+    PrettyJson,
+    Binary
+}
+
+impl LevelFormat {
+    /// Binary if the path has `BINARY_LEVEL_EXTENSION`, otherwise JSON
+    pub fn for_path(path: &Path, pretty: bool) -> Self {
+        if path.extension().is_some_and(|extension| extension == BINARY_LEVEL_EXTENSION) {
+            Self::Binary
+        } else if pretty {
+            Self::PrettyJson
+        } else {
+            Self::Json
+        }
+    }
+}
 
-use crate::{collision::{self, DEFAULT_CONTROL, DEFAULT_FRICTION, DEFAULT_JUMP}, component::Component, mesh::{self, MeshBank}, render::{self, DirLight, Environment, Skybox}, shader::ProgramBank, texture::TextureBank, world::{self, Model, World}};
+/// Round every float so noise in the last bits doesn't show up as a change
+fn round_floats(value: &mut Value) {
+    match value {
+        Value::Number(n) if n.is_f64() => {
+            let scale = 10f64.powi(SAVE_DECIMALS);
+            let rounded = (n.as_f64().unwrap() * scale).round() / scale;
+            // Written as 0.0 rather than -0.0
+            *value = Value::from(if rounded == 0.0 { 0.0 } else { rounded });
+        },
+        Value::Array(items) => items.iter_mut().for_each(round_floats),
+        Value::Object(fields) => fields.values_mut().for_each(round_floats),
+        _ => ()
+    }
+}
 
 #[derive(Deserialize, Serialize)]
 pub struct BrushData {
+    /// 0 in levels saved before brushes had ids, and for copied brushes
+    #[serde(default)]
+    id: u64,
     material: String,
     origin: [f32; 3],
     extents: [f32; 3],
-    flags: u32
+    flags: u32,
+    /// `None` for brushes with the default alignment
+    #[serde(default)]
+    faces: Option<mesh::BrushFaces>
+}
+
+impl BrushData {
+    pub fn from_renderable(renderable: &world::Renderable) -> Option<Self> {
+        match renderable {
+            world::Renderable::Brush(material, origin, extents, flags, faces) => Some(Self {
+                id: 0,
+                material: material.to_owned(),
+                origin: (*origin).into(),
+                extents: (*extents).into(),
+                flags: *flags,
+                faces: (!faces.is_default()).then_some(*faces)
+            }),
+            _ => None
+        }
+    }
+
+    pub fn as_renderable(&self) -> world::Renderable {
+        world::Renderable::Brush(self.material.to_owned(), self.origin.into(), self.extents.into(), self.flags, self.faces.unwrap_or_default())
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -19,14 +95,65 @@ pub struct DirLightData {
     specular: [f32; 3]
 }
 
+fn default_near_plane() -> f32 { render::DEFAULT_NEAR_PLANE }
+fn default_far_plane() -> f32 { render::DEFAULT_FAR_PLANE }
+
 #[derive(Deserialize, Serialize)]
 pub struct EnvironmentData {
     skybox: render::Skybox,
-    dir_light: DirLightData
+    dir_light: DirLightData,
+    #[serde(default="default_near_plane")]
+    near_plane: f32,
+    #[serde(default="default_far_plane")]
+    far_plane: f32,
+    #[serde(default)]
+    hdr: HdrEffect,
+    #[serde(default)]
+    fog: DistanceFog
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct FogData {
+    color: [f32; 3],
+    strength: f32,
+    max: f32
+}
+
+/// The level's default effects, see `effects::DefaultEffects`
+#[derive(Deserialize, Serialize, Default)]
+#[serde(default)]
+pub struct EffectsData {
+    fog: Option<FogData>,
+    kernel: Option<KernelEffect>,
+    ssao: Option<SsaoEffect>,
+    chain: Vec<PostEffectSettings>
+}
+
+impl EffectsData {
+    fn from_world(world: &World) -> Self {
+        let defaults = &world.scene.world_default_effects;
+        Self {
+            fog: defaults.fog.as_ref().map(|fog| FogData { color: fog.color.into(), strength: fog.strength, max: fog.max }),
+            kernel: defaults.kernel.clone(),
+            ssao: defaults.ssao.clone(),
+            chain: world.scene.post_process.chain.iter().map(PostEffect::settings).collect()
+        }
+    }
+
+    fn into_default_effects(self) -> DefaultEffects {
+        DefaultEffects {
+            fog: self.fog.map(|fog| FogEffect { color: fog.color.into(), strength: fog.strength, max: fog.max }),
+            kernel: self.kernel,
+            ssao: self.ssao,
+            chain: self.chain
+        }
+    }
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct LevelData {
+    /// See `migrate::LEVEL_VERSION`
+    version: u32,
     models: Vec<ModelData>,
     brushes: Vec<BrushData>,
     gravity: f32,
@@ -34,7 +161,138 @@ pub struct LevelData {
     materials: Vec<MaterialData>,
     environment: Option<EnvironmentData>,
     #[serde(default="Vec::new")]
-    loaded_models: Vec<String>
+    loaded_models: Vec<String>,
+    #[serde(default)]
+    rules: GameplayRules,
+    #[serde(default)]
+    audio: LevelAudio,
+    #[serde(default)]
+    budgets: PerformanceBudgets,
+    /// Baked in the editor, see `visibility`
+    #[serde(default)]
+    visibility: Option<Visibility>,
+    /// Levels to go on to after reaching a goal, see `goal`
+    #[serde(default)]
+    playlist: Vec<String>,
+    #[serde(default)]
+    effects: EffectsData
+}
+
+impl LevelData {
+    /// Read a level in either format, picked by the extension<br>
+    /// JSON levels from older versions are upgraded, binary levels have to be the current version
+    pub fn read_from_file(path: &Path) -> Result<Self, String> {
+        let bytes = fs::read(path).map_err(|e| format!("failed to open level file: {}", e))?;
+        match LevelFormat::for_path(path, false) {
+            LevelFormat::Binary => {
+                // The version is the first field, checked before the rest since older layouts don't decode
+                let version: u32 = bincode::deserialize(&bytes).map_err(|e| format!("failed to parse binary level: {}", e))?;
+                if version != LEVEL_VERSION {
+                    return Err(format!("binary level is version {} instead of {}, save it again from JSON", version, LEVEL_VERSION));
+                }
+                bincode::deserialize(&bytes).map_err(|e| format!("failed to parse binary level: {}", e))
+            },
+            _ => {
+                let mut value: Value = serde_json::from_slice(&bytes).map_err(|e| format!("failed to parse level file: {}", e))?;
+                let version = migrate::migrate_level(&mut value)?;
+                serde_json::from_value(value).map_err(|e| format!("level file doesn't match version {}: {}", version, e))
+            }
+        }
+    }
+
+    /// The level as JSON with rounded floats, the same level always gives the same value<br>
+    /// Object keys are sorted
+    pub fn to_stable_value(&self) -> Result<Value, String> {
+        let mut value = serde_json::to_value(self).map_err(|e| e.to_string())?;
+        round_floats(&mut value);
+        Ok(value)
+    }
+
+    pub fn write_to_file(&self, path: &Path, format: LevelFormat) -> Result<(), String> {
+        let bytes = match format {
+            // Rounding is only for readable diffs, binary levels skip going through JSON so large levels save quickly
+            LevelFormat::Binary => bincode::serialize(self).map_err(|e| e.to_string())?,
+            LevelFormat::Json => serde_json::to_vec(&self.to_stable_value()?).map_err(|e| e.to_string())?,
+            LevelFormat::PrettyJson => serde_json::to_vec_pretty(&self.to_stable_value()?).map_err(|e| e.to_string())?
+        };
+        fs::write(path, bytes).map_err(|e| format!("failed to save level: {}", e))
+    }
+
+    fn brush_materials(&self) -> BTreeSet<&str> {
+        let model_brushes = self.models.iter().flat_map(|model| model.renderables.iter()).filter_map(|renderable| match renderable {
+            ModelRenderableData::Brush(material, ..) => Some(material.as_str()),
+            _ => None
+        });
+        self.brushes.iter().map(|brush| brush.material.as_str()).chain(model_brushes).collect()
+    }
+
+    /// Files the level needs to be played, relative to `res/`<br>
+    /// Sounds played from scripts aren't included, they are only known by reading the scripts
+    pub fn referenced_assets(&self) -> BTreeSet<String> {
+        let mut assets = BTreeSet::new();
+        let used = self.brush_materials();
+
+        for material in self.materials.iter().filter(|material| used.contains(material.name.as_str())) {
+            assets.insert(format!("textures/{}.png", material.diffuse));
+            assets.insert(format!("textures/{}.png", material.specular));
+        }
+
+        for model in self.loaded_models.iter() {
+            assets.insert(format!("models/{}.obj", model));
+        }
+
+        for model in self.models.iter() {
+            for renderable in model.renderables.iter() {
+                if let ModelRenderableData::Billboard(texture, ..) | ModelRenderableData::AnimatedBillboard(texture, ..) = renderable {
+                    assets.insert(format!("textures/{}.png", texture));
+                }
+            }
+
+            for component in model.components.iter() {
+                match component {
+                    Component::Script(script) => { assets.insert(format!("scripts/{}", script.file)); },
+                    Component::SoundEmitter(emitter) => { assets.insert(format!("audio/{}", emitter.sound)); },
+                    Component::Trigger(trigger) => {
+                        if let TriggerType::SetMusic { track: Some(track), .. } = &trigger.kind {
+                            assets.insert(format!("audio/{}", track));
+                        }
+                    },
+                    _ => ()
+                }
+            }
+        }
+
+        if let Some(EnvironmentData { skybox: Skybox::Cubemap(cubemap), .. }) = &self.environment {
+            for face in ["px", "nx", "py", "ny", "pz", "nz"] {
+                assets.insert(format!("textures/cubemap/{}/{}.png", cubemap, face));
+            }
+        }
+
+        for track in [&self.audio.music, &self.audio.ambience].into_iter().flatten() {
+            assets.insert(format!("audio/{}", track));
+        }
+
+        assets
+    }
+
+    /// Drop what only matters while editing before the level is shipped<br>
+    /// Notes, placeholder and test components, materials no brush uses and model files no mesh uses
+    pub fn strip_editor_data(&mut self) {
+        self.models.retain(|model| !model.components.iter().any(|component| matches!(component, Component::Note(_))));
+        for model in self.models.iter_mut() {
+            model.components.retain(|component| !matches!(component, Component::Dummy | Component::Trigger(Trigger { kind: TriggerType::Test { .. }, .. })));
+        }
+
+        let used: BTreeSet<String> = self.brush_materials().into_iter().map(str::to_string).collect();
+        self.materials.retain(|material| used.contains(&material.name));
+
+        let meshes: Vec<&str> = self.models.iter().flat_map(|model| model.renderables.iter()).filter_map(|renderable| match renderable {
+            ModelRenderableData::Mesh(name, ..) => Some(name.as_str()),
+            _ => None
+        }).collect();
+        // Meshes from a model file are named `File_{file}{index}`
+        self.loaded_models.retain(|file| meshes.iter().any(|mesh| mesh.strip_prefix("File_").is_some_and(|mesh| mesh.starts_with(file.as_str()))));
+    }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -49,8 +307,11 @@ pub struct MaterialData {
 #[derive(Deserialize, Serialize, Debug)]
 pub enum ModelRenderableData {
     Mesh(String, [[f32; 4]; 4], u32),
-    Brush(String, [f32; 3], [f32; 3], u32),
-    Billboard(String, [f32; 3], [f32; 2], u32, bool)
+    Brush(String, [f32; 3], [f32; 3], u32, Option<mesh::BrushFaces>),
+    Billboard(String, [f32; 3], [f32; 2], u32, bool),
+    Text(world::TextLabel, [f32; 3], u32),
+    /// Billboards with a flipbook, the rest are saved as `Billboard` so older builds can still read them
+    AnimatedBillboard(String, [f32; 3], [f32; 2], u32, bool, Flipbook)
 }
 
 impl ModelRenderableData {
@@ -59,11 +320,17 @@ impl ModelRenderableData {
             world::Renderable::Mesh(name, transform, flags) => {
                 Self::Mesh(name.to_owned(), (*transform).into(), *flags)
             },
-            world::Renderable::Brush(material, origin, extents, flags) => {
-                Self::Brush(material.to_owned(), (*origin).into(), (*extents).into(), *flags)
+            world::Renderable::Brush(material, origin, extents, flags, faces) => {
+                Self::Brush(material.to_owned(), (*origin).into(), (*extents).into(), *flags, (!faces.is_default()).then_some(*faces))
             },
-            world::Renderable::Billboard(texture, origin, size, flags, follow_vertical) => {
+            world::Renderable::Billboard(texture, origin, size, flags, follow_vertical, flipbook) if flipbook.animated() => {
+                Self::AnimatedBillboard(texture.to_owned(), (*origin).into(), [size.0, size.1], *flags, *follow_vertical, *flipbook)
+            },
+            world::Renderable::Billboard(texture, origin, size, flags, follow_vertical, _) => {
                 Self::Billboard(texture.to_owned(), (*origin).into(), [size.0, size.1], *flags, *follow_vertical)
+            },
+            world::Renderable::Text(label, origin, flags) => {
+                Self::Text(label.clone(), (*origin).into(), *flags)
             }
         }
     }
@@ -73,11 +340,17 @@ impl ModelRenderableData {
             Self::Mesh(name, transform, flags) => {
                 world::Renderable::Mesh(name.to_owned(), (*transform).into(), *flags)
             },
-            Self::Brush(material, origin, extents, flags) => {
-                world::Renderable::Brush(material.to_owned(), (*origin).into(), (*extents).into(), *flags)
+            Self::Brush(material, origin, extents, flags, faces) => {
+                world::Renderable::Brush(material.to_owned(), (*origin).into(), (*extents).into(), *flags, faces.unwrap_or_default())
             },
             Self::Billboard(texture, origin, size, flags, follow_vertical) => {
-                world::Renderable::Billboard(texture.to_owned(), (*origin).into(), (size[0], size[1]), *flags, *follow_vertical)
+                world::Renderable::Billboard(texture.to_owned(), (*origin).into(), (size[0], size[1]), *flags, *follow_vertical, Flipbook::default())
+            },
+            Self::AnimatedBillboard(texture, origin, size, flags, follow_vertical, flipbook) => {
+                world::Renderable::Billboard(texture.to_owned(), (*origin).into(), (size[0], size[1]), *flags, *follow_vertical, *flipbook)
+            },
+            Self::Text(label, origin, flags) => {
+                world::Renderable::Text(label.clone(), (*origin).into(), *flags)
             }
         }
     }
@@ -92,7 +365,9 @@ pub enum ModelColliderData {
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub enum ModelColliderDataSingular {
-    Cuboid { offset: [f32; 3], half_extents: [f32; 3] }
+    Cuboid { offset: [f32; 3], half_extents: [f32; 3] },
+    TriMesh { offset: [f32; 3], obj: String },
+    ConvexHull { offset: [f32; 3], obj: String }
 }
 
 impl ModelColliderData {
@@ -127,6 +402,12 @@ impl ModelColliderDataSingular {
             world::ModelCollider::Cuboid { offset, half_extents } => {
                 colliders.push(ModelColliderDataSingular::Cuboid { offset: (*offset).into(), half_extents: (*half_extents).into() });
             },
+            world::ModelCollider::TriMesh { offset, obj } => {
+                colliders.push(ModelColliderDataSingular::TriMesh { offset: (*offset).into(), obj: obj.clone() });
+            },
+            world::ModelCollider::ConvexHull { offset, obj } => {
+                colliders.push(ModelColliderDataSingular::ConvexHull { offset: (*offset).into(), obj: obj.clone() });
+            },
             world::ModelCollider::Multiple { colliders: multiple } => {
                 for collider in multiple.iter() {
                     colliders.extend(ModelColliderDataSingular::from_model_collider(collider));
@@ -140,7 +421,9 @@ impl ModelColliderDataSingular {
         match self {
             Self::Cuboid { offset, half_extents } => {
                 world::ModelCollider::Cuboid { offset: (*offset).into(), half_extents: (*half_extents).into() }
-            }
+            },
+            Self::TriMesh { offset, obj } => world::ModelCollider::TriMesh { offset: (*offset).into(), obj: obj.clone() },
+            Self::ConvexHull { offset, obj } => world::ModelCollider::ConvexHull { offset: (*offset).into(), obj: obj.clone() }
         }
     }
 }
@@ -148,7 +431,9 @@ impl ModelColliderDataSingular {
 #[derive(Deserialize, Serialize, Debug)]
 pub struct PointLightData {
     attenuation: f32,
-    color: [f32; 3]
+    color: [f32; 3],
+    #[serde(default)]
+    cast_shadows: bool
 }
 
 fn dfalse() -> bool { false }
@@ -157,6 +442,15 @@ fn default_extents() -> Option<([f32; 3], [f32; 3])> { None }
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct ModelData {
+    /// 0 in levels saved before models had ids
+    #[serde(default)]
+    id: u64,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    connections: Vec<Connection>,
     transform: [[f32; 4]; 4],
     mobile: bool,
     foreground: bool,
@@ -173,7 +467,50 @@ pub struct ModelData {
 }
 
 impl ModelData {
-    pub fn insert(&self, world: &mut World) {
+    pub fn from_model(world: &World, model: &Model) -> Self {
+        let mut lights = Vec::new();
+
+        for light in model.lights.iter() {
+            let light_data = PointLightData {
+                attenuation: world.scene.point_lights[light.1].user_attenuation_or_default(),
+                color: world.scene.point_lights[light.1].user_color_or_default().into(),
+                cast_shadows: world.scene.point_lights[light.1].cast_shadows
+            };
+            lights.push((light.0.into(), light_data));
+        }
+
+        let insert_colliders = if let Some(insert) = &model.insert_collider {
+            ModelColliderData::from_model_collider(insert)
+        } else {
+            ModelColliderData::None
+        };
+
+        let mut renderables = Vec::new();
+
+        for renderable in model.render.iter() {
+            renderables.push(ModelRenderableData::from_renderable(renderable));
+        }
+
+        ModelData {
+            id: model.id,
+            name: model.name.clone(),
+            tags: model.tags.clone(),
+            connections: model.connections.clone(),
+            foreground: model.foreground,
+            mobile: model.mobile,
+            solid: model.solid,
+            transform: model.transform.into(),
+            lights,
+            insert_colliders,
+            renderables,
+            components: model.components.clone(),
+            hidden: model.hidden,
+            extents: model.extents.map(|e| ([e.0.x, e.0.y, e.0.z], [e.1.x, e.1.y, e.1.z]))
+        }
+    }
+
+    /// Returns the index of the inserted model
+    pub fn insert(&self, world: &mut World) -> usize {
         let mut render = Vec::new();
 
         for renderable in self.renderables.iter() {
@@ -183,6 +520,10 @@ impl ModelData {
         let mut model = world::Model::new(
             self.mobile, self.transform.into(), render
         );
+        model.id = self.id;
+        model.name = self.name.clone();
+        model.tags = self.tags.clone();
+        model.connections = self.connections.clone();
         model.solid = self.solid;
         model.foreground = self.foreground;
         model.hidden = self.hidden;
@@ -195,12 +536,13 @@ impl ModelData {
             let mut point_light = render::PointLight::default(Vector3::zero());
             point_light.set_attenuation(light.1.attenuation);
             point_light.set_color(light.1.color.into());
+            point_light.cast_shadows = light.1.cast_shadows;
             model = model.with_light(world.scene.add_point_light(point_light), light.0.into());
         }
 
         model.components = self.components.clone();
 
-        world.insert_model(model);
+        world.insert_model(model)
     }
 }
 
@@ -209,58 +551,22 @@ impl World {
         let mut models = Vec::new();
 
         for (i, model) in self.models.iter().enumerate() {
-            if self.internal.internal_ids.contains(&i) { continue; }
+            if self.internal.internal_ids.contains(&i) || self.editor_data.test_patch == Some(i) { continue; }
             if let Some(model) = model {
-                let transform = model.transform.into();
-                let mut lights = Vec::new();
-
-                for light in model.lights.iter() {
-                    let light_data = PointLightData {
-                        attenuation: self.scene.point_lights[light.1].user_attenuation_or_default(),
-                        color: self.scene.point_lights[light.1].user_color_or_default().into()
-                    };
-                    lights.push((light.0.into(), light_data));
-                }
-
-                let insert_colliders = if let Some(insert) = &model.insert_collider {
-                    ModelColliderData::from_model_collider(insert)
-                } else {
-                    ModelColliderData::None
-                };
-
-                let mut renderables = Vec::new();
-
-                for renderable in model.render.iter() {
-                    renderables.push(ModelRenderableData::from_renderable(renderable));
-                }
-
-                models.push(ModelData {
-                    foreground: model.foreground,
-                    mobile: model.mobile,
-                    solid: model.solid,
-                    transform,
-                    lights,
-                    insert_colliders,
-                    renderables,
-                    components: model.components.clone(),
-                    hidden: model.hidden,
-                    extents: model.extents.map(|e| ([e.0.x, e.0.y, e.0.z], [e.1.x, e.1.y, e.1.z]))
-                });
+                models.push(ModelData::from_model(self, model));
             }
         }
 
+        // Slots in `models` get reused, so their order changes between sessions
+        models.sort_by_key(|model| model.id);
+
         let mut brushes = Vec::new();
 
-        for brush in self.models[self.internal.brushes].as_ref().unwrap().render.iter() {
-            if let world::Renderable::Brush(material, origin, extents, flags) = brush {
-                brushes.push(BrushData {
-                    extents: (*extents).into(),
-                    flags: *flags,
-                    material: material.to_owned(),
-                    origin: (*origin).into()
-                });
-            }
+        for (brush, id) in self.models[self.internal.brushes].as_ref().unwrap().render.iter().zip(self.brush_ids.iter()) {
+            brushes.extend(BrushData::from_renderable(brush).map(|brush| BrushData { id: *id, ..brush }));
         }
+        // Brush indices shift as brushes are removed, their ids don't
+        brushes.sort_by_key(|brush| brush.id);
 
         let mut materials = Vec::new();
 
@@ -268,11 +574,12 @@ impl World {
             materials.push(MaterialData {
                 diffuse: material.1.diffuse.to_owned(),
                 name: material.0.to_owned(),
-                physical_properties: material.1.physical_properties,
+                physical_properties: material.1.physical_properties.clone(),
                 shininess: material.1.shininess,
                 specular: material.1.specular.to_owned()
             });
         }
+        materials.sort_by(|a, b| a.name.cmp(&b.name));
 
         let environment = EnvironmentData {
             skybox: self.scene.environment.skybox.clone(),
@@ -281,19 +588,90 @@ impl World {
                 diffuse: self.scene.environment.dir_light.diffuse.into(),
                 direction: self.scene.environment.dir_light.direction.into(),
                 specular: self.scene.environment.dir_light.ambient.into()
-            }
+            },
+            near_plane: self.scene.environment.near_plane,
+            far_plane: self.scene.environment.far_plane,
+            hdr: self.scene.environment.hdr,
+            fog: self.scene.environment.fog
         };
 
-        println!("{:?}", self.loaded_models);
         LevelData {
+            version: LEVEL_VERSION,
             air_friction: self.air_friction,
             gravity: self.gravity,
             brushes,
             models,
             materials,
             environment: Some(environment),
-            loaded_models: self.loaded_models.clone()
+            loaded_models: self.loaded_models.clone(),
+            rules: self.rules.clone(),
+            audio: self.audio.clone(),
+            budgets: self.budgets.clone(),
+            visibility: self.scene.visibility().cloned(),
+            playlist: self.playlist.clone(),
+            effects: EffectsData::from_world(self)
+        }
+    }
+
+    /// Write the level to `path`, the format is picked by the extension, and remember it as the saved state
+    pub fn save_to_file(&mut self, path: &Path) -> Result<(), String> {
+        if self.editor_data.merge_on_save {
+            // Selected brush indices don't survive the merge
+            if self.merge_all_brushes() > 0 {
+                self.deselect();
+            }
         }
+        let data = self.save_data();
+        data.write_to_file(path, LevelFormat::for_path(path, self.editor_data.pretty_levels))?;
+        self.editor_data.saved_snapshot = data.to_stable_value().ok().map(|value| value.to_string());
+        Ok(())
+    }
+
+    /// Count down to the next autosave while editing, only levels with unsaved changes are written
+    pub fn update_autosave(&mut self, delta_time: f32) {
+        if !self.editor_data.active || self.editor_data.autosave_interval <= 0.0 {
+            return;
+        }
+
+        self.editor_data.autosave_timer += delta_time;
+        if self.editor_data.autosave_timer < self.editor_data.autosave_interval {
+            return;
+        }
+        self.editor_data.autosave_timer = 0.0;
+
+        if self.has_unsaved_changes() {
+            let message = match self.autosave() {
+                Ok(()) => String::from("autosaved"),
+                Err(e) => format!("autosave failed: {}", e)
+            };
+            self.editor_data.show_debug.push(message);
+        }
+    }
+
+    /// Shift the older autosaves back one and write the level as the newest<br>
+    /// The level still counts as unsaved afterwards
+    fn autosave(&self) -> Result<(), String> {
+        for n in (0..AUTOSAVE_COUNT - 1).rev() {
+            let from = autosave_path(n);
+            if from.exists() {
+                fs::rename(&from, autosave_path(n + 1)).map_err(|e| format!("failed to rotate autosaves: {}", e))?;
+            }
+        }
+        self.save_data().write_to_file(&autosave_path(0), LevelFormat::Json)
+    }
+
+    fn snapshot(&self) -> Option<String> {
+        self.save_data().to_stable_value().ok().map(|value| value.to_string())
+    }
+
+    /// Remember the level as it is now as the saved state, call after loading
+    pub fn mark_saved(&mut self) {
+        self.editor_data.saved_snapshot = self.snapshot();
+    }
+
+    /// Whether the level changed since it was last saved or loaded
+    pub fn has_unsaved_changes(&self) -> bool {
+        self.snapshot() != self.editor_data.saved_snapshot
     }
 
     pub unsafe fn from_save_data(data: LevelData, textures: &mut TextureBank, meshes: &mut MeshBank, programs: &mut ProgramBank, gl: &glow::Context) -> Self {
@@ -305,7 +683,7 @@ impl World {
                     &material.name,
                     &material.diffuse,
                     &material.specular,
-                    material.physical_properties,
+                    material.physical_properties.clone(),
                     textures,
                     gl
                 );
@@ -320,7 +698,7 @@ impl World {
         for model in data.models.iter() {
             for render in model.renderables.iter() {
                 match render {
-                    ModelRenderableData::Billboard(texture, ..) => { textures.load_by_name(texture, gl); }, 
+                    ModelRenderableData::Billboard(texture, ..) | ModelRenderableData::AnimatedBillboard(texture, ..) => { textures.load_by_name(texture, gl); }, 
                     _ => ()
                 }
             }
@@ -331,7 +709,7 @@ impl World {
         let mut brushes = Model::new(false, Matrix4::identity(), Vec::new());
 
         for brush in data.brushes.iter() {
-            brushes.render.push(world::Renderable::Brush(brush.material.to_owned(), brush.origin.into(), brush.extents.into(), brush.flags));
+            brushes.render.push(brush.as_renderable());
         }
 
         {
@@ -342,7 +720,11 @@ impl World {
                     specular: [0.75, 0.75, 0.75],
                     direction: [-0.2, -1.0, -0.3]
                 },
-                skybox: render::Skybox::Cubemap("field".to_string())
+                skybox: render::Skybox::Cubemap("field".to_string()),
+                near_plane: render::DEFAULT_NEAR_PLANE,
+                far_plane: render::DEFAULT_FAR_PLANE,
+                hdr: HdrEffect::default(),
+                fog: DistanceFog::default()
             });
 
             if let Skybox::Cubemap(cubemap) = &environment.skybox {
@@ -357,33 +739,146 @@ impl World {
                     direction: environment.dir_light.direction.into(),
                     specular: environment.dir_light.specular.into()
                 },
-                skybox: environment.skybox.clone()
+                skybox: environment.skybox.clone(),
+                near_plane: environment.near_plane,
+                far_plane: environment.far_plane,
+                hdr: environment.hdr,
+                fog: environment.fog
             };
         }
 
-        world.scene.init(textures, meshes, programs, gl);
+        let warnings = world.scene.init(textures, meshes, programs, gl);
+        world.editor_data.show_debug.extend(warnings);
         world.editor_data.selection_box_vao = Some(mesh::create_selection_cube(gl));
-        world.set_internal_brushes(brushes);
+        world.set_internal_brushes(brushes, &data.brushes.iter().map(|brush| brush.id).collect::<Vec<_>>());
         world.set_arrows_visible(false);
         world.move_boxes_far();
         world.move_arrows_far();
         world.set_boxes_visible(false);
         world.set_model_visible(world.internal.debug_arrow, false);
+        world.rules = data.rules;
+        world.apply_rules();
+        world.audio = data.audio;
+        world.budgets = data.budgets;
+        world.scene.set_visibility(data.visibility);
+        world.playlist = data.playlist;
+        world.scene.world_default_effects = data.effects.into_default_effects();
+        world.scene.post_process.fog = world.scene.world_default_effects.fog.clone();
+        world.scene.post_process.kernel = world.scene.world_default_effects.kernel.clone();
         world.freeze = 1;
 
         world
     }
 }
 
-#[derive(Deserialize)]
+pub const BRUSH_TYPES_PATH: &str = "res/data/brush_types.json";
+/// Copy of the last brush types file that loaded without warnings
+const BRUSH_TYPES_BACKUP_PATH: &str = "res/data/brush_types.backup.json";
+const BRUSH_TYPE_FIELDS: [&str; 15] = [
+    "name", "diffuse", "specular", "friction", "control", "jump", "sound_set", "category", "shader", "uniforms", "flipbook", "reflectivity",
+    "emissive", "emissive_color", "emissive_strength"
+];
+
+#[derive(Deserialize, Default)]
 pub struct BrushMaterialsFile {
+    #[serde(default)]
     pub materials: Vec<BrushMaterialData> 
 }
 
+impl BrushMaterialsFile {
+    /// Load and validate the brush types, problems are returned as warnings instead of stopping the game<br>
+    /// Broken entries are skipped, and a file that can't be read at all falls back to the backup
+    pub fn load() -> (Self, Vec<String>) {
+        let mut warnings = Vec::new();
+        let src = match fs::read_to_string(BRUSH_TYPES_PATH) {
+            Ok(src) => src,
+            Err(e) => {
+                warnings.push(format!("failed to open brush types: {}", e));
+                return Self::load_backup(warnings);
+            }
+        };
+
+        let Some(file) = Self::validate(&src, &mut warnings) else {
+            return Self::load_backup(warnings);
+        };
+        if warnings.is_empty() {
+            if let Err(e) = fs::write(BRUSH_TYPES_BACKUP_PATH, &src) {
+                warnings.push(format!("failed to back up brush types: {}", e));
+            }
+        }
+
+        (file, warnings)
+    }
+
+    fn load_backup(mut warnings: Vec<String>) -> (Self, Vec<String>) {
+        match fs::read_to_string(BRUSH_TYPES_BACKUP_PATH).ok().and_then(|src| Self::validate(&src, &mut Vec::new())) {
+            Some(file) => {
+                warnings.push(format!("loaded brush types from {}", BRUSH_TYPES_BACKUP_PATH));
+                (file, warnings)
+            },
+            None => (Self::default(), warnings)
+        }
+    }
+
+    /// Parse `src` one entry at a time so a bad entry only loses itself, None if it isn't valid JSON
+    fn validate(src: &str, warnings: &mut Vec<String>) -> Option<Self> {
+        // serde's own errors point at the line and field of the first problem
+        if let Err(e) = serde_json::from_str::<Self>(src) {
+            warnings.push(format!("brush types: {}", e));
+        }
+        let value: Value = serde_json::from_str(src).ok()?;
+        let Some(entries) = value.get("materials").and_then(Value::as_array) else {
+            warnings.push(String::from("brush types: expected a \"materials\" list"));
+            return None;
+        };
+
+        let mut materials: Vec<BrushMaterialData> = Vec::new();
+        for (i, entry) in entries.iter().enumerate() {
+            let label = match entry.get("name").and_then(Value::as_str) {
+                Some(name) => format!("brush type \"{}\"", name),
+                None => format!("brush type {}", i)
+            };
+            if let Some(object) = entry.as_object() {
+                for field in object.keys().filter(|field| !BRUSH_TYPE_FIELDS.contains(&field.as_str())) {
+                    warnings.push(format!("{}: unknown field `{}` ignored", label, field));
+                }
+            }
+
+            let material = match BrushMaterialData::deserialize(entry) {
+                Ok(material) => material,
+                Err(e) => {
+                    warnings.push(format!("{}: {}, skipped", label, e));
+                    continue;
+                }
+            };
+            if materials.iter().any(|other| other.name == material.name) {
+                warnings.push(format!("{}: duplicate name, skipped", label));
+                continue;
+            }
+            if let Some(texture) = [&material.diffuse, &material.specular].into_iter().chain(material.emissive.as_ref()).find(|texture| !feed::is_feed_texture(texture) && !Path::new(&format!("res/textures/{}.png", texture)).exists()) {
+                warnings.push(format!("{}: texture {} not found, skipped", label, texture));
+                continue;
+            }
+            if let Some(shader) = material.shader.as_ref().filter(|shader| !Path::new(&format!("res/shaders/{}.frag.glsl", shader)).exists()) {
+                warnings.push(format!("{}: shader {} not found, skipped", label, shader));
+                continue;
+            }
+            if let Some((uniform, _)) = material.uniforms.iter().find(|(_, value)| !value.is_valid()) {
+                warnings.push(format!("{}: uniform {} should be a number or 2 to 4 numbers, skipped", label, uniform));
+                continue;
+            }
+            materials.push(material);
+        }
+
+        Some(Self { materials })
+    }
+}
+
 fn default_specular() -> String { String::from("magic_pixel") }
 fn default_friction() -> f32 { DEFAULT_FRICTION }
 fn default_control() -> f32 { DEFAULT_CONTROL }
 fn default_jump() -> f32 { DEFAULT_JUMP }
+fn default_emissive_strength() -> f32 { 1.0 }
 
 #[derive(Deserialize, Debug)]
 pub struct BrushMaterialData {
@@ -396,5 +891,83 @@ pub struct BrushMaterialData {
     #[serde(default="default_control")]
     pub control: f32,
     #[serde(default="default_jump")]
-    pub jump: f32
-}
\ No newline at end of file
+    pub jump: f32,
+    /// Footstep sounds for the brush type, see `PhysicalProperties::sound_set`
+    #[serde(default)]
+    pub sound_set: Option<String>,
+    #[serde(default)]
+    pub category: BrushCategory,
+    /// Fragment shader in `res/shaders` drawn with instead of the built in one, see `ProgramBank::load_material_program`
+    #[serde(default)]
+    pub shader: Option<String>,
+    /// Uniforms for `shader` by name
+    #[serde(default)]
+    pub uniforms: BTreeMap<String, UniformValue>,
+    /// Sprite sheet animation of `diffuse` and `specular`, played on brushes with `flags::FLIPBOOK`
+    #[serde(default)]
+    pub flipbook: Option<Flipbook>,
+    /// 0 to 1, how much of the level's reflection plane covers brushes of this type, see `reflection`
+    #[serde(default)]
+    pub reflectivity: f32,
+    /// Texture the brush type glows with, tinted by `emissive_color`, which is white by default when there is a texture
+    #[serde(default)]
+    pub emissive: Option<String>,
+    #[serde(default)]
+    pub emissive_color: Option<[f32; 3]>,
+    #[serde(default="default_emissive_strength")]
+    pub emissive_strength: f32
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Empty level with the shipped vignette in its chain, which has float and vector uniforms
+    fn vignette_level() -> LevelData {
+        serde_json::from_value(json!({
+            "version": LEVEL_VERSION,
+            "models": [],
+            "brushes": [],
+            "gravity": 9.8,
+            "air_friction": 0.0,
+            "materials": [],
+            "environment": null,
+            "effects": {
+                "chain": [{ "file": "vignette", "enabled": true, "values": { "strength": [0.75], "tint": [0.25, 0.0, 0.5] } }]
+            }
+        })).unwrap()
+    }
+
+    fn round_trip(name: &str, format: LevelFormat) {
+        let level = vignette_level();
+        let path = std::env::temp_dir().join(name);
+        level.write_to_file(&path, format).unwrap();
+        let loaded = LevelData::read_from_file(&path);
+        let _ = fs::remove_file(&path);
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.effects.chain[0].values, level.effects.chain[0].values);
+        assert_eq!(loaded.to_stable_value().unwrap(), level.to_stable_value().unwrap());
+    }
+
+    #[test]
+    fn json_level_with_chain_round_trips() {
+        round_trip("viceptica_chain_test.json", LevelFormat::Json);
+    }
+
+    #[test]
+    fn binary_level_with_chain_round_trips() {
+        round_trip(&format!("viceptica_chain_test.{}", BINARY_LEVEL_EXTENSION), LevelFormat::Binary);
+    }
+
+    #[test]
+    fn chain_numbers_upgrade_to_lists() {
+        let mut value = vignette_level().to_stable_value().unwrap();
+        value["version"] = json!(2);
+        value["effects"]["chain"][0]["values"]["strength"] = json!(0.75);
+        migrate::migrate_level(&mut value).unwrap();
+        let level: LevelData = serde_json::from_value(value).unwrap();
+        assert_eq!(level.effects.chain[0].values["strength"], vec![0.75]);
+    }
+}
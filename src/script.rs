@@ -0,0 +1,260 @@
+use std::{cell::RefCell, fs, rc::Rc};
+
+use cgmath::{vec3, Matrix4, Transform, Vector3};
+use rhai::{CallFnOptions, Dynamic, Engine, FuncArgs, Map, Scope, AST};
+use serde::{Deserialize, Serialize};
+
+use crate::{audio::MusicChannel, common, component::TargetAction, events::EventKind, world::{Model, Renderable, World}};
+
+/// A script running longer than this in one call is stopped
+const MAX_OPERATIONS: u64 = 100_000;
+const MAX_CALL_LEVELS: usize = 32;
+/// Seconds to crossfade when a script changes the music
+const SCRIPT_CROSSFADE: f32 = 1.0;
+
+/// Something a script asked for, applied to the world once the hook returns
+enum ScriptCommand {
+    Move(Vector3<f32>),
+    SetPosition(Vector3<f32>),
+    SetLights(bool),
+    PlaySound(String),
+    PlayMusic(Option<String>),
+    /// Target name or tag, whether it is a tag, `TargetAction` name
+    Fire(String, bool, String),
+    /// Fire an output on the script's model, see `connection`
+    Output(String),
+    /// Dissolve the script's model out and despawn it, see `dissolve`
+    DissolveOut,
+    Log(String)
+}
+
+/// What the registered functions can see while a hook runs
+#[derive(Default)]
+struct ScriptContext {
+    position: [f32; 3],
+    player_position: [f32; 3],
+    commands: Vec<ScriptCommand>
+}
+
+fn vector_to_array(vector: [f32; 3]) -> rhai::Array {
+    vector.iter().map(|n| Dynamic::from_float(*n)).collect()
+}
+
+/// Runs level scripts<br>
+/// Scripts can only reach the world through the functions registered here, which queue commands instead of touching it directly
+pub struct ScriptEngine {
+    engine: Engine,
+    context: Rc<RefCell<ScriptContext>>
+}
+
+impl ScriptEngine {
+    pub fn new() -> Self {
+        let mut engine = Engine::new();
+        // Keep a broken script from hanging the game
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.set_max_call_levels(MAX_CALL_LEVELS);
+
+        let context = Rc::new(RefCell::new(ScriptContext::default()));
+        let push = |context: &Rc<RefCell<ScriptContext>>| {
+            let context = context.clone();
+            move |command: ScriptCommand| context.borrow_mut().commands.push(command)
+        };
+
+        let c = context.clone();
+        engine.register_fn("position", move || vector_to_array(c.borrow().position));
+        let c = context.clone();
+        engine.register_fn("player_position", move || vector_to_array(c.borrow().player_position));
+
+        let p = push(&context);
+        engine.register_fn("move_by", move |x: f32, y: f32, z: f32| p(ScriptCommand::Move(vec3(x, y, z))));
+        let p = push(&context);
+        engine.register_fn("set_position", move |x: f32, y: f32, z: f32| p(ScriptCommand::SetPosition(vec3(x, y, z))));
+        let p = push(&context);
+        engine.register_fn("set_lights", move |on: bool| p(ScriptCommand::SetLights(on)));
+        let p = push(&context);
+        engine.register_fn("play_sound", move |sound: &str| p(ScriptCommand::PlaySound(sound.to_string())));
+        let p = push(&context);
+        engine.register_fn("play_music", move |track: &str| p(ScriptCommand::PlayMusic(Some(track.to_string()))));
+        let p = push(&context);
+        engine.register_fn("stop_music", move || p(ScriptCommand::PlayMusic(None)));
+        let p = push(&context);
+        engine.register_fn("fire", move |name: &str, action: &str| p(ScriptCommand::Fire(name.to_string(), false, action.to_string())));
+        let p = push(&context);
+        engine.register_fn("fire_tagged", move |tag: &str, action: &str| p(ScriptCommand::Fire(tag.to_string(), true, action.to_string())));
+        let p = push(&context);
+        engine.register_fn("output", move |output: &str| p(ScriptCommand::Output(output.to_string())));
+        let p = push(&context);
+        engine.register_fn("dissolve_out", move || p(ScriptCommand::DissolveOut));
+        let p = push(&context);
+        engine.on_print(move |text| p(ScriptCommand::Log(text.to_string())));
+
+        Self { engine, context }
+    }
+
+    /// Compile `res/scripts/{file}`
+    pub fn compile(&self, file: &str) -> Result<AST, String> {
+        let src = fs::read_to_string(format!("res/scripts/{}", file)).map_err(|e| format!("failed to open script {}: {}", file, e))?;
+        self.engine.compile(src).map_err(|e| format!("error in script {}: {}", file, e))
+    }
+
+    /// Call `hook` if the script defines it, with the script's state bound to `this`
+    fn call(&self, runtime: &mut ScriptRuntime, hook: &str, args: impl FuncArgs) -> Result<(), String> {
+        if !runtime.ast.iter_functions().any(|function| function.name == hook) {
+            return Ok(());
+        }
+
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut runtime.state);
+        self.engine.call_fn_with_options::<Dynamic>(options, &mut Scope::new(), &runtime.ast, hook, args)
+            .map(|_| ())
+            .map_err(|e| format!("error in {}: {}", hook, e))
+    }
+}
+
+/// Compiled script and the state it keeps between hooks
+#[derive(Clone, Debug)]
+struct ScriptRuntime {
+    ast: AST,
+    /// `this` inside hooks, starts as an empty map
+    state: Dynamic,
+    started: bool,
+    player_within: bool
+}
+
+/// Runs `res/scripts/{file}` on the model while playing<br>
+/// Hooks the script can define:
+/// - `on_insert()` - the first update after the model is added
+/// - `on_update(delta_time)` - every update
+/// - `on_trigger_enter()` - the player walked into one of the model's brushes
+/// - `on_input(name)` - a connection sent the model an input, scripts take any input
+/// - `on_surface_sound(kind, sound_set)` - the player made a `"footstep"`, `"jump"` or `"land"`, the set is `""` if the ground has none
+///
+/// Other models are reached by name or tag with `fire(name, action)` and `fire_tagged(tag, action)`, see `TargetAction` for the actions<br>
+/// `output(name)` fires an output on the model for its connections, like `"OnUser"`
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Script {
+    pub file: String,
+    #[serde(skip)]
+    runtime: Option<ScriptRuntime>
+}
+
+impl Script {
+    pub fn new(file: &str) -> Self {
+        Self { file: file.to_string(), runtime: None }
+    }
+
+    /// Compile the script, it stays inactive if that fails
+    pub fn load(&mut self, world: &mut World) {
+        self.runtime = match world.scripts.compile(&self.file) {
+            Ok(ast) => Some(ScriptRuntime { ast, state: Dynamic::from_map(Map::new()), started: false, player_within: false }),
+            Err(e) => {
+                world.editor_data.show_debug.push(e);
+                None
+            }
+        };
+    }
+
+    pub fn update(&mut self, mut model: Model, world: &mut World, delta_time: f32) -> Model {
+        let Some(runtime) = &mut self.runtime else { return model; };
+
+        let player_position = world.player_eye();
+        {
+            let mut context = world.scripts.context.borrow_mut();
+            context.position = common::translation(model.transform).into();
+            context.player_position = player_position.into();
+        }
+
+        let mut result = Ok(());
+        if !runtime.started {
+            runtime.started = true;
+            result = world.scripts.call(runtime, "on_insert", ());
+        }
+
+        let within = brushes_contain(&model, player_position);
+        if result.is_ok() && within && !runtime.player_within {
+            result = world.scripts.call(runtime, "on_trigger_enter", ());
+        }
+        runtime.player_within = within;
+
+        for input in world.arrived_inputs(model.index) {
+            if result.is_ok() {
+                result = world.scripts.call(runtime, "on_input", (input,));
+            }
+        }
+
+        for sound in world.player.surface_sounds.iter() {
+            if result.is_ok() {
+                result = world.scripts.call(runtime, "on_surface_sound", (sound.kind.name().to_string(), sound.sound_set.clone().unwrap_or_default()));
+            }
+        }
+
+        if result.is_ok() {
+            result = world.scripts.call(runtime, "on_update", (delta_time,));
+        }
+
+        if let Err(e) = result {
+            // Stop the script instead of repeating the error every frame
+            world.editor_data.show_debug.push(format!("script {} stopped, {}", self.file, e));
+            self.runtime = None;
+        }
+
+        let commands = std::mem::take(&mut world.scripts.context.borrow_mut().commands);
+        for command in commands {
+            match command {
+                ScriptCommand::Move(offset) => {
+                    let transform = Matrix4::from_translation(offset) * model.transform;
+                    model = world.set_model_transform_external(model, transform);
+                },
+                ScriptCommand::SetPosition(position) => {
+                    let transform = Matrix4::from_translation(position) * common::mat4_remove_translation(model.transform);
+                    model = world.set_model_transform_external(model, transform);
+                },
+                ScriptCommand::SetLights(on) => {
+                    for (_, light) in model.lights.iter() {
+                        world.scene.point_lights[*light].set_animated_color(common::vec3_all(if on { 1.0 } else { 0.0 }));
+                    }
+                },
+                ScriptCommand::PlaySound(sound) => {
+                    if let Err(e) = world.music.play_sound(&sound) {
+                        world.editor_data.show_debug.push(e);
+                    }
+                },
+                ScriptCommand::PlayMusic(track) => {
+                    if let Err(e) = world.music.play(MusicChannel::Music, track.as_deref(), SCRIPT_CROSSFADE) {
+                        world.editor_data.show_debug.push(e);
+                    }
+                },
+                ScriptCommand::Fire(target, tagged, action) => {
+                    let result = TargetAction::from_name(&action).ok_or_else(|| format!("{} is not an action", action))
+                        .and_then(|action| if tagged { world.fire_tagged(&target, action).map(|_| ()) } else { world.fire_target(&target, action) });
+                    let detail = match result {
+                        Ok(()) => action,
+                        Err(e) => {
+                            world.editor_data.show_debug.push(format!("script {}: {}", self.file, e));
+                            e
+                        }
+                    };
+                    let target = if tagged { format!("tag {}", target) } else { target };
+                    world.log_event(EventKind::TargetFired, model.label(), Some(target), detail);
+                },
+                ScriptCommand::Output(output) => world.fire_output(&mut model, &output),
+                ScriptCommand::DissolveOut => if let Some(index) = model.index {
+                    world.dissolve_out_at_end_of_frame(index);
+                },
+                ScriptCommand::Log(text) => world.editor_data.show_debug.push(text)
+            }
+        }
+
+        model
+    }
+}
+
+/// Whether `point` is inside any of the model's brushes
+fn brushes_contain(model: &Model, point: Vector3<f32>) -> bool {
+    model.render.iter().any(|renderable| {
+        let Renderable::Brush(_, origin, extents, ..) = renderable else { return false; };
+        let center = *origin + common::translation(model.transform);
+        let half = model.transform.transform_vector(*extents) / 2.0;
+        let (min, max) = (center - half, center + half);
+        point.x > min.x && point.y > min.y && point.z > min.z && point.x < max.x && point.y < max.y && point.z < max.z
+    })
+}
@@ -0,0 +1,160 @@
+use std::{collections::{BTreeMap, HashMap}, fs, time::{SystemTime, UNIX_EPOCH}};
+
+use cgmath::{vec3, Matrix4, Vector3};
+use serde::Serialize;
+
+use crate::{shader::ProgramBank, world::{Selection, SelectionType, World}};
+
+/// Edits are counted in cubes this big for the heatmap
+pub const HEATMAP_CELL: f32 = 4.0;
+pub const SESSION_DIRECTORY: &str = "res/sessions";
+const COLD_COLOR: Vector3<f32> = vec3(0.1, 0.3, 1.0);
+const HOT_COLOR: Vector3<f32> = vec3(1.0, 0.15, 0.05);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum EditKind {
+    Created,
+    Deleted,
+    Moved
+}
+
+/// Editing statistics for as long as the editor is open, kept across level loads<br>
+/// Only edits made while editing count, playing and loading levels don't
+#[derive(Default, Serialize)]
+pub struct SessionStats {
+    pub models_created: u32,
+    pub models_deleted: u32,
+    pub brushes_created: u32,
+    pub brushes_deleted: u32,
+    /// Objects moved, scaled or reshaped by dragging the gizmo
+    pub moves: u32,
+    /// Seconds spent with each tool, see `World::current_tool`
+    pub tool_time: BTreeMap<&'static str, f32>,
+    /// Edits in each heatmap cell, only while `record_heatmap` is on
+    #[serde(serialize_with = "serialize_heatmap")]
+    pub heatmap: HashMap<[i32; 3], u32>,
+    #[serde(skip)]
+    pub record_heatmap: bool,
+    /// Draw the heatmap in the editor, see `World::render_heatmap`
+    #[serde(skip)]
+    pub show_heatmap: bool
+}
+
+/// Cells as a list, JSON maps can't have arrays as keys
+fn serialize_heatmap<S: serde::Serializer>(heatmap: &HashMap<[i32; 3], u32>, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut cells: Vec<([f32; 3], u32)> = heatmap.iter()
+        .map(|(cell, edits)| (cell.map(|axis| (axis as f32 + 0.5) * HEATMAP_CELL), *edits))
+        .collect();
+    cells.sort_by(|a, b| b.1.cmp(&a.1));
+    serializer.collect_seq(cells.into_iter().map(|(center, edits)| HeatmapCell { center, edits }))
+}
+
+#[derive(Serialize)]
+struct HeatmapCell {
+    center: [f32; 3],
+    edits: u32
+}
+
+impl SessionStats {
+    pub fn total_edits(&self) -> u32 {
+        self.models_created + self.models_deleted + self.brushes_created + self.brushes_deleted + self.moves
+    }
+
+    /// Most edits in one cell
+    pub fn hottest(&self) -> u32 {
+        self.heatmap.values().copied().max().unwrap_or(0)
+    }
+
+    /// Write the stats to `res/sessions`, returns the file name
+    pub fn export(&self) -> Result<String, String> {
+        let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+        let path = format!("{}/session_{}.json", SESSION_DIRECTORY, seconds);
+        let json = serde_json::to_string_pretty(self).map_err(|e| format!("failed to export session stats: {}", e))?;
+        fs::create_dir_all(SESSION_DIRECTORY).map_err(|e| format!("failed to export session stats: {}", e))?;
+        fs::write(&path, json).map_err(|e| format!("failed to export session stats: {}", e))?;
+        Ok(path)
+    }
+}
+
+impl World {
+    /// Count an edit at `position`, ignored while playing or outside the editor
+    pub fn record_edit(&mut self, kind: EditKind, brush: bool, position: Vector3<f32>) {
+        if !self.editor_data.active || self.do_game_logic {
+            return;
+        }
+        let session = &mut self.editor_data.session;
+        match (kind, brush) {
+            (EditKind::Created, false) => session.models_created += 1,
+            (EditKind::Created, true) => session.brushes_created += 1,
+            (EditKind::Deleted, false) => session.models_deleted += 1,
+            (EditKind::Deleted, true) => session.brushes_deleted += 1,
+            (EditKind::Moved, _) => session.moves += 1
+        }
+        if session.record_heatmap {
+            let cell = position.map(|axis| (axis / HEATMAP_CELL).floor() as i32);
+            *session.heatmap.entry([cell.x, cell.y, cell.z]).or_default() += 1;
+        }
+    }
+
+    /// `record_edit` for each item, at the item's origin
+    pub fn record_selection_edit(&mut self, kind: EditKind, items: &[Selection]) {
+        for item in items {
+            let position = self.selection_item_origin(item);
+            self.record_edit(kind, matches!(item, Selection::Brush(_)), position);
+        }
+    }
+
+    /// Name of what the user is doing, for `SessionStats::tool_time`
+    pub fn current_tool(&self) -> &'static str {
+        if self.do_game_logic {
+            "playing"
+        } else if self.editor_data.stamp.is_some() {
+            "stamp"
+        } else if self.editor_data.apply_material.is_some() {
+            "material"
+        } else if self.editor_data.selected_object.is_none() {
+            "no selection"
+        } else {
+            match self.editor_data.selection_type {
+                SelectionType::Movement => "move",
+                SelectionType::Scaling => "scale",
+                SelectionType::Faces => "faces"
+            }
+        }
+    }
+
+    pub fn update_session(&mut self, delta_time: f32) {
+        if !self.editor_data.active && !self.do_game_logic {
+            return;
+        }
+        let tool = self.current_tool();
+        *self.editor_data.session.tool_time.entry(tool).or_default() += delta_time;
+    }
+
+    /// Color each cell that was edited from blue to red by how many edits it had
+    pub unsafe fn render_heatmap(&self, programs: &mut ProgramBank, gl: &glow::Context) {
+        let session = &self.editor_data.session;
+        if !self.editor_data.active || !session.show_heatmap {
+            return;
+        }
+        let hottest = session.hottest().max(1) as f32;
+        for (cell, edits) in session.heatmap.iter() {
+            let center = Vector3::from(cell.map(|axis| (axis as f32 + 0.5) * HEATMAP_CELL));
+            let heat = *edits as f32 / hottest;
+            let color = COLD_COLOR + (HOT_COLOR - COLD_COLOR) * heat;
+            let transform = Matrix4::from_translation(center) * Matrix4::from_scale(HEATMAP_CELL * 0.95);
+            self.scene.debug_render_box(transform, color, self.editor_data.selection_box_vao.unwrap(), programs, gl);
+        }
+    }
+}
+
+/// `12m 5s` style
+pub fn format_duration(seconds: f32) -> String {
+    let seconds = seconds as u32;
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {}m", seconds / 3600, seconds / 60 % 60)
+    }
+}
+
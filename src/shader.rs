@@ -1,9 +1,16 @@
-use std::{collections::HashMap, error::Error, fs, io::Read, path::PathBuf};
+use std::{collections::HashMap, error::Error, fs, io::Read, path::PathBuf, slice};
 
-use cgmath::{Matrix3, Matrix4, Vector2, Vector3};
+use cgmath::{Matrix3, Matrix4, Vector2, Vector3, Vector4};
 use glow::{HasContext, NativeUniformLocation};
+use serde::{Deserialize, Serialize};
 
-const SHADER_VERSION: &str = "#version 410";
+pub const SHADER_VERSION: &str = "#version 410";
+/// For contexts older than 4.1, the shaders don't use anything newer
+pub const LEGACY_SHADER_VERSION: &str = "#version 330";
+/// Used by material shaders that don't have their own vertex shader
+const MATERIAL_VERTEX_SHADER: &str = "res/shaders/flat.vert.glsl";
+/// Full screen triangle strip, used by the post processing passes
+const SCREEN_VERTEX_SHADER: &str = "res/shaders/screen.vert.glsl";
 
 pub struct Program {
     pub name: String,
@@ -13,44 +20,59 @@ pub struct Program {
 
 // https://github.com/grovesNL/glow/blob/main/examples/hello/src/main.rs
 impl Program {
-    pub unsafe fn from_vert_frag(vert: &str, frag: &str, name: &str, gl: &glow::Context) -> Self {
+    /// `version` is the `#version` line put before both sources
+    pub unsafe fn from_vert_frag(vert: &str, frag: &str, name: &str, version: &str, gl: &glow::Context) -> Self {
+        Self::try_from_vert_frag(vert, frag, name, version, gl).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like `from_vert_frag`, but compile and link errors are returned, for shaders that come from data files
+    pub unsafe fn try_from_vert_frag(vert: &str, frag: &str, name: &str, version: &str, gl: &glow::Context) -> Result<Self, String> {
         let shader_sources = [
             (glow::VERTEX_SHADER, vert),
             (glow::FRAGMENT_SHADER, frag)
         ];
 
-        let program = gl.create_program().expect("Cannot create program");
+        let program = gl.create_program()?;
         let mut shaders = Vec::with_capacity(2);
 
+        let mut error = None;
         for (shader_type, source) in shader_sources.iter() {
-            let shader = gl
-                .create_shader(*shader_type)
-                .expect("Cannot create shader");
+            let shader = gl.create_shader(*shader_type)?;
 
-            gl.shader_source(shader, &format!("{}\n{}", SHADER_VERSION, source));
+            gl.shader_source(shader, &format!("{}\n{}", version, source));
             gl.compile_shader(shader);
             if !gl.get_shader_compile_status(shader) {
-                panic!("{}", gl.get_shader_info_log(shader));
+                error = Some(gl.get_shader_info_log(shader));
             }
             gl.attach_shader(program, shader);
             shaders.push(shader);
+            if error.is_some() {
+                break;
+            }
         }
 
-        gl.link_program(program);
-        if !gl.get_program_link_status(program) {
-            panic!("{}", gl.get_program_info_log(program));
+        if error.is_none() {
+            gl.link_program(program);
+            if !gl.get_program_link_status(program) {
+                error = Some(gl.get_program_info_log(program));
+            }
         }
 
         for shader in shaders {
             gl.detach_shader(program, shader);
             gl.delete_shader(shader);
         }
+
+        if let Some(error) = error {
+            gl.delete_program(program);
+            return Err(format!("shader {}: {}", name, error));
+        }
         
-        Self {
+        Ok(Self {
             name: name.to_string(),
             inner: program,
             uniform_locations: HashMap::new()
-        }
+        })
     }
 
     unsafe fn get_uniform_location(&mut self, loc: &str, gl: &glow::Context) -> Option<&NativeUniformLocation> {
@@ -91,16 +113,75 @@ impl Program {
         let vector_as_slice: [f32; 2] = value.into();
         gl.uniform_2_f32_slice(self.get_uniform_location(loc, gl), &vector_as_slice);
     }
+
+    pub unsafe fn uniform_3i32(&mut self, loc: &str, value: Vector3<i32>, gl: &glow::Context) {
+        gl.uniform_3_i32(self.get_uniform_location(loc, gl), value.x, value.y, value.z);
+    }
+
+    pub unsafe fn uniform_4f32(&mut self, loc: &str, value: Vector4<f32>, gl: &glow::Context) {
+        let vector_as_slice: [f32; 4] = value.into();
+        gl.uniform_4_f32_slice(self.get_uniform_location(loc, gl), &vector_as_slice);
+    }
+}
+
+/// Uniform set from a data file, a number or a list of 2 to 4 numbers<br>
+/// Untagged, so it can't go in binary levels, save `components` instead
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum UniformValue {
+    Float(f32),
+    Vector(Vec<f32>)
+}
+
+impl UniformValue {
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Self::Float(_) => true,
+            Self::Vector(values) => (2..=4).contains(&values.len())
+        }
+    }
+
+    /// A float is one component
+    pub fn components(&self) -> &[f32] {
+        match self {
+            Self::Float(value) => slice::from_ref(value),
+            Self::Vector(values) => values
+        }
+    }
+
+    /// The same shape as this value with `components`, none if there are a different number of them
+    pub fn with_components(&self, components: &[f32]) -> Option<Self> {
+        match (self, components) {
+            (Self::Float(_), [value]) => Some(Self::Float(*value)),
+            (Self::Vector(values), components) if values.len() == components.len() => Some(Self::Vector(components.to_vec())),
+            _ => None
+        }
+    }
+
+    pub unsafe fn apply(&self, program: &mut Program, loc: &str, gl: &glow::Context) {
+        match self {
+            Self::Float(value) => program.uniform_1f32(loc, *value, gl),
+            Self::Vector(values) => match values.as_slice() {
+                [x, y] => program.uniform_2f32(loc, Vector2::new(*x, *y), gl),
+                [x, y, z] => program.uniform_3f32(loc, Vector3::new(*x, *y, *z), gl),
+                [x, y, z, w] => program.uniform_4f32(loc, Vector4::new(*x, *y, *z, *w), gl),
+                _ => ()
+            }
+        }
+    }
 }
 
 pub struct ProgramBank {
-    pub programs: HashMap<String, Program>
+    pub programs: HashMap<String, Program>,
+    /// Set before any program is loaded, see `window::GpuCapabilities`
+    pub version: &'static str
 }
 
 impl ProgramBank {
     pub fn new() -> Self {
         Self {
-            programs: HashMap::new()
+            programs: HashMap::new(),
+            version: SHADER_VERSION
         }
     }
 
@@ -127,7 +208,37 @@ impl ProgramBank {
         let mut frag_src = String::new();
         frag_file.read_to_string(&mut frag_src)?;
 
-        self.add(name, Program::from_vert_frag(&vertex_src, &frag_src, name, gl));
+        self.add(name, Program::from_vert_frag(&vertex_src, &frag_src, name, self.version, gl));
+        Ok(())
+    }
+
+    /// Load `res/shaders/{name}.frag.glsl` as a full screen pass, with the `screen` vertex shader
+    pub unsafe fn load_screen_program(&mut self, name: &str, gl: &glow::Context) -> Result<(), Box<dyn Error>> {
+        if self.programs.contains_key(name) {
+            return Ok(());
+        }
+
+        let vertex_src = fs::read_to_string(SCREEN_VERTEX_SHADER)?;
+        let frag_src = fs::read_to_string(format!("res/shaders/{}.frag.glsl", name))?;
+
+        self.add(name, Program::from_vert_frag(&vertex_src, &frag_src, name, self.version, gl));
+        Ok(())
+    }
+
+    /// Load `res/shaders/{name}.frag.glsl` for a material, with `{name}.vert.glsl` if there is one and the flat vertex shader otherwise<br>
+    /// The shader gets the same inputs and uniforms as `flat`, plus `time` and the material's own uniforms
+    pub unsafe fn load_material_program(&mut self, name: &str, gl: &glow::Context) -> Result<(), String> {
+        if self.programs.contains_key(name) {
+            return Ok(());
+        }
+
+        let vertex_path = PathBuf::from(format!("res/shaders/{}.vert.glsl", name));
+        let vertex_path = if vertex_path.exists() { vertex_path } else { PathBuf::from(MATERIAL_VERTEX_SHADER) };
+        let vertex_src = fs::read_to_string(&vertex_path).map_err(|e| format!("failed to open {}: {}", vertex_path.display(), e))?;
+        let frag_src = fs::read_to_string(format!("res/shaders/{}.frag.glsl", name)).map_err(|e| format!("failed to open shader {}: {}", name, e))?;
+
+        let program = Program::try_from_vert_frag(&vertex_src, &frag_src, name, self.version, gl)?;
+        self.add(name, program);
         Ok(())
     }
 }
\ No newline at end of file
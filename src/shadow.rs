@@ -0,0 +1,128 @@
+use cgmath::{perspective, vec3, Deg, EuclideanSpace, Matrix4, Point3, Vector3};
+use glow::{HasContext, NativeFramebuffer, NativeTexture};
+
+use crate::{mesh::MeshBank, render::Scene, shader::ProgramBank};
+
+/// Point lights past this many don't cast shadows, the lighting shaders have a cubemap for each
+pub const MAX_SHADOW_LIGHTS: usize = 4;
+/// Width and height of each cubemap face
+const SHADOW_MAP_SIZE: i32 = 512;
+const SHADOW_NEAR_PLANE: f32 = 0.05;
+/// Texture unit of the first shadow map, after the material's diffuse and specular
+pub const SHADOW_TEXTURE_UNIT: u32 = 2;
+
+/// Direction and up of each cubemap face, in the order of `TEXTURE_CUBE_MAP_POSITIVE_X` on
+const CUBE_FACES: [(Vector3<f32>, Vector3<f32>); 6] = [
+    (vec3(1.0, 0.0, 0.0), vec3(0.0, -1.0, 0.0)),
+    (vec3(-1.0, 0.0, 0.0), vec3(0.0, -1.0, 0.0)),
+    (vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0)),
+    (vec3(0.0, -1.0, 0.0), vec3(0.0, 0.0, -1.0)),
+    (vec3(0.0, 0.0, 1.0), vec3(0.0, -1.0, 0.0)),
+    (vec3(0.0, 0.0, -1.0), vec3(0.0, -1.0, 0.0))
+];
+
+/// Depth cubemaps of the point lights with `PointLight::cast_shadows`, drawn every frame before the scene
+#[derive(Default)]
+pub struct ShadowMaps {
+    framebuffer: Option<NativeFramebuffer>,
+    cubemaps: Vec<NativeTexture>,
+    /// Index into `Scene::point_lights` of the light each cubemap was drawn for this frame
+    pub casters: Vec<usize>
+}
+
+impl ShadowMaps {
+    /// Index into the shadow maps of point light `light`, None if it has no shadow this frame
+    pub fn map_of(&self, light: usize) -> Option<usize> {
+        self.casters.iter().position(|caster| *caster == light)
+    }
+
+    pub fn cubemap(&self, map: usize) -> Option<NativeTexture> {
+        self.cubemaps.get(map).copied()
+    }
+
+    unsafe fn create_cubemap(gl: &glow::Context) -> NativeTexture {
+        let texture = gl.create_texture().unwrap();
+        gl.bind_texture(glow::TEXTURE_CUBE_MAP, Some(texture));
+        for face in 0..6 {
+            gl.tex_image_2d(
+                glow::TEXTURE_CUBE_MAP_POSITIVE_X + face, 0, glow::DEPTH_COMPONENT24 as i32,
+                SHADOW_MAP_SIZE, SHADOW_MAP_SIZE,
+                0, glow::DEPTH_COMPONENT, glow::FLOAT,
+                glow::PixelUnpackData::Slice(None)
+            );
+        }
+        gl.tex_parameter_i32(glow::TEXTURE_CUBE_MAP, glow::TEXTURE_MIN_FILTER, glow::NEAREST as i32);
+        gl.tex_parameter_i32(glow::TEXTURE_CUBE_MAP, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+        gl.tex_parameter_i32(glow::TEXTURE_CUBE_MAP, glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE as i32);
+        gl.tex_parameter_i32(glow::TEXTURE_CUBE_MAP, glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE as i32);
+        gl.tex_parameter_i32(glow::TEXTURE_CUBE_MAP, glow::TEXTURE_WRAP_R, glow::CLAMP_TO_EDGE as i32);
+        texture
+    }
+}
+
+impl Scene {
+    /// Draw the shadow maps of the first `MAX_SHADOW_LIGHTS` shadow casting point lights, call after `prepare_mobiles`<br>
+    /// Only instanced meshes cast shadows, so nothing does in safe mode
+    pub unsafe fn render_shadows(&mut self, meshes: &MeshBank, programs: &mut ProgramBank, gl: &glow::Context) {
+        self.shadows.casters.clear();
+        if self.safe_mode {
+            return;
+        }
+        self.shadows.casters = self.point_lights.iter().enumerate()
+            .filter(|(_, light)| light.cast_shadows)
+            .map(|(i, _)| i)
+            .take(MAX_SHADOW_LIGHTS)
+            .collect();
+        if self.shadows.casters.is_empty() {
+            return;
+        }
+
+        let framebuffer = *self.shadows.framebuffer.get_or_insert_with(|| gl.create_framebuffer().unwrap());
+        while self.shadows.cubemaps.len() < self.shadows.casters.len() {
+            self.shadows.cubemaps.push(ShadowMaps::create_cubemap(gl));
+        }
+
+        let Some(program) = programs.get_mut("shadow") else { return; };
+        gl.use_program(Some(program.inner));
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+        gl.draw_buffer(glow::NONE);
+        gl.read_buffer(glow::NONE);
+        gl.viewport(0, 0, SHADOW_MAP_SIZE, SHADOW_MAP_SIZE);
+        gl.enable(glow::DEPTH_TEST);
+
+        for (map, light) in self.shadows.casters.iter().enumerate() {
+            let light = &self.point_lights[*light];
+            let far_plane = light.user_attenuation_or_default();
+            let projection = perspective(Deg(90.0), 1.0, SHADOW_NEAR_PLANE, far_plane);
+            program.uniform_3f32("lightPos", light.position, gl);
+            program.uniform_1f32("farPlane", far_plane, gl);
+
+            for (face, (direction, up)) in CUBE_FACES.iter().enumerate() {
+                gl.framebuffer_texture_2d(glow::FRAMEBUFFER, glow::DEPTH_ATTACHMENT, glow::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32, Some(self.shadows.cubemaps[map]), 0);
+                gl.clear(glow::DEPTH_BUFFER_BIT);
+                let view = Matrix4::look_to_rh(Point3::from_vec(light.position), *direction, *up);
+                program.uniform_matrix4f32("faceMatrix", projection * view, gl);
+                self.render_shadow_casters(meshes, gl);
+            }
+        }
+
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        gl.viewport(0, 0, self.window_size.0 as i32, self.window_size.1 as i32);
+    }
+
+    /// Every static and streamed mobile instance, from the buffers the scene is drawn with
+    unsafe fn render_shadow_casters(&self, meshes: &MeshBank, gl: &glow::Context) {
+        let statics = self.static_instance_counts.iter().map(|(name, count)| (name, *count, false));
+        let mobiles = self.mobile_instance_counts.iter().map(|(name, count)| (name, *count, true));
+        for (name, count, streamed) in statics.chain(mobiles) {
+            // Materials with their own shader are often see through, like forcefields
+            if count == 0 || self.material_shader(name, meshes).is_some() {
+                continue;
+            }
+            let Some(mesh) = meshes.get(name) else { continue; };
+            gl.bind_vertex_array(Some(if streamed { mesh.vao_streamed } else { mesh.vao_instanced }));
+            gl.draw_elements_instanced(glow::TRIANGLES, mesh.indices as i32, glow::UNSIGNED_SHORT, 0, count as i32);
+        }
+        gl.bind_vertex_array(None);
+    }
+}
@@ -0,0 +1,106 @@
+use cgmath::{vec3, InnerSpace, MetricSpace, Vector3, Zero};
+
+use crate::{common, world::{Renderable, Selection, World}};
+
+/// Corners further than this from every corner of the dragged selection aren't snapped to
+const VERTEX_SNAP_RANGE: f32 = 2.0;
+/// Corners off the drag's axis or plane by more than this can't be reached, so they aren't snapped to
+const VERTEX_SNAP_TOLERANCE: f32 = 0.01;
+
+/// The 8 corners of a box from its center and half extents
+fn corners(center: Vector3<f32>, half_extents: Vector3<f32>) -> [Vector3<f32>; 8] {
+    let mut corners = [Vector3::zero(); 8];
+    for (i, corner) in corners.iter_mut().enumerate() {
+        let sign = vec3(
+            if i & 1 == 0 { -1.0 } else { 1.0 },
+            if i & 2 == 0 { -1.0 } else { 1.0 },
+            if i & 4 == 0 { -1.0 } else { 1.0 }
+        );
+        *corner = center + vec3(half_extents.x * sign.x, half_extents.y * sign.y, half_extents.z * sign.z);
+    }
+    corners
+}
+
+impl World {
+    /// Bounding boxes of brushes and models outside the selection, as center and half extents
+    fn unselected_boxes(&self, selection: &[Selection]) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+        let selected_brushes: Vec<usize> = selection.iter().filter_map(|item| if let Selection::Brush(brush) = item { Some(*brush) } else { None }).collect();
+        let selected_models: Vec<usize> = selection.iter().filter_map(|item| if let Selection::Model(model) = item { Some(*model) } else { None }).collect();
+        let mut boxes = Vec::new();
+        if let Some(brushes) = self.models[self.internal.brushes].as_ref() {
+            for (index, renderable) in brushes.render.iter().enumerate() {
+                if let Renderable::Brush(_, origin, size, ..) = renderable {
+                    if !selected_brushes.contains(&index) {
+                        boxes.push((*origin, *size / 2.0));
+                    }
+                }
+            }
+        }
+        for (index, model) in self.models.iter().enumerate() {
+            let Some(model) = model else { continue; };
+            if !self.can_be_selected(index) || selected_models.contains(&index) { continue; }
+            if let Some((offset, half_extents)) = model.extents {
+                boxes.push((common::translation(model.transform) + offset, half_extents));
+            }
+        }
+        boxes
+    }
+
+    /// Half extents of the box around everything selected
+    fn selection_half_extents(&self, selection: &[Selection]) -> Vector3<f32> {
+        let (_, half_extents) = common::compose_extents(selection.iter().map(|item| match item {
+            Selection::Brush(brush) => match &self.models[self.internal.brushes].as_ref().unwrap().render[*brush] {
+                Renderable::Brush(_, origin, size, ..) => (*origin, *size / 2.0),
+                _ => unreachable!()
+            },
+            Selection::Model(model) => {
+                let model = self.models[*model].as_ref().unwrap();
+                let (offset, half_extents) = model.extents.unwrap_or((Vector3::zero(), common::vec3_all(0.5)));
+                (common::translation(model.transform) + offset, half_extents)
+            },
+            Selection::Multiple(_) => unreachable!()
+        }));
+        half_extents
+    }
+
+    /// Move the selection's box centered at `moved` so one of its corners lands on a corner of other brushes and models<br>
+    /// `constrain` keeps a correction to the directions the drag can move in, only corners it can reach exactly are snapped to
+    /// and the one needing the smallest move wins, nothing changes with no corner in range
+    pub fn snap_to_vertex(&mut self, moved: Vector3<f32>, constrain: impl Fn(Vector3<f32>) -> Vector3<f32>) -> Vector3<f32> {
+        let Some(selection) = self.editor_data.selected_object.as_ref().map(Selection::items) else { return moved; };
+        let half_extents = self.selection_half_extents(&selection);
+        let dragged = corners(moved, half_extents);
+        if self.editor_data.snap_boxes.is_none() {
+            self.editor_data.snap_boxes = Some(self.unselected_boxes(&selection));
+        }
+
+        let mut nearest: Option<(f32, Vector3<f32>)> = None;
+        for (center, other_half_extents) in self.editor_data.snap_boxes.iter().flatten() {
+            // Nothing on this box can be in range
+            if (center - moved).magnitude() > (half_extents + other_half_extents).magnitude() + VERTEX_SNAP_RANGE {
+                continue;
+            }
+            for target in corners(*center, *other_half_extents) {
+                for corner in dragged.iter() {
+                    if corner.distance(target) > VERTEX_SNAP_RANGE {
+                        continue;
+                    }
+                    let shift = target - corner;
+                    let constrained = constrain(shift);
+                    if (shift - constrained).magnitude() > VERTEX_SNAP_TOLERANCE {
+                        continue;
+                    }
+                    let distance = constrained.magnitude();
+                    if nearest.map_or(true, |(nearest, _)| distance < nearest) {
+                        nearest = Some((distance, constrained));
+                    }
+                }
+            }
+        }
+
+        match nearest {
+            Some((_, shift)) => moved + shift,
+            None => moved
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use cgmath::{vec3, Matrix4};
+
+use crate::{component::Component, dissolve::DEFAULT_DISSOLVE_TIME, world::{Model, World}};
+
+/// Where pooled models are parked while they wait to be reused
+const POOL_PARK_Y: f32 = -1000.0;
+
+struct SpawnRequest {
+    kind: SpawnKind,
+    lifetime: Option<f32>,
+    /// Dissolve in when spawned, see `World::dissolve_in`
+    dissolve: bool
+}
+
+enum SpawnKind {
+    Model(Model),
+    Pooled(String, Matrix4<f32>)
+}
+
+/// Deferred spawning, timed despawning and pooling of short lived models<br>
+/// Everything queued here is applied in `World::end_frame`, so it is safe to use while a model is taken during component update
+pub struct Spawner {
+    pending: Vec<SpawnRequest>,
+    pending_despawn: Vec<usize>,
+    /// model index, seconds left
+    timers: Vec<(usize, f32)>,
+    /// Model that gets cloned when a pool runs out of free instances
+    templates: HashMap<String, Model>,
+    /// Inserted but currently unused (hidden and parked) instances
+    free: HashMap<String, Vec<usize>>,
+    /// Pooled instances that are currently in use, model index -> pool name
+    active: HashMap<usize, String>,
+    /// Models spawned with a dissolve, they dissolve out again when despawned at the end of a frame
+    dissolving: Vec<usize>
+}
+
+impl Spawner {
+    pub fn new() -> Self {
+        Self {
+            pending: Vec::new(),
+            pending_despawn: Vec::new(),
+            timers: Vec::new(),
+            templates: HashMap::new(),
+            free: HashMap::new(),
+            active: HashMap::new(),
+            dissolving: Vec::new()
+        }
+    }
+
+    /// Tick despawn timers, expired models are queued for removal
+    pub fn update(&mut self, delta_time: f32) {
+        for (index, time) in self.timers.iter_mut() {
+            *time -= delta_time;
+            if *time <= 0.0 {
+                self.pending_despawn.push(*index);
+            }
+        }
+
+        self.timers.retain(|(_, time)| *time > 0.0);
+    }
+
+    /// Drop any timers, queued despawns or pool bookkeeping for a model that was removed
+    pub fn forget(&mut self, index: usize) {
+        self.timers.retain(|(i, _)| *i != index);
+        self.pending_despawn.retain(|i| *i != index);
+        self.active.remove(&index);
+        self.dissolving.retain(|i| *i != index);
+        for free in self.free.values_mut() {
+            free.retain(|i| *i != index);
+        }
+    }
+
+    pub fn is_pooled(&self, index: usize) -> bool {
+        self.active.contains_key(&index) || self.free.values().any(|free| free.contains(&index))
+    }
+}
+
+impl World {
+    /// Register a model to be reused by `spawn_pooled`<br>
+    /// Pooled models are always mobile since they need to be hidden while unused
+    pub fn register_pool(&mut self, name: &str, template: Model) {
+        self.spawner.templates.insert(name.to_string(), template.mobile());
+        self.spawner.free.entry(name.to_string()).or_default();
+    }
+
+    /// Insert `count` unused instances of a pool ahead of time
+    pub fn prewarm_pool(&mut self, name: &str, count: usize) {
+        for _ in 0..count {
+            if let Some(index) = self.insert_pool_instance(name) {
+                self.park_pooled(index);
+                self.spawner.free.get_mut(name).unwrap().push(index);
+            }
+        }
+    }
+
+    /// Queue a model to be inserted at the end of the frame<br>
+    /// If `lifetime` is set it will be despawned after that many seconds
+    pub fn spawn_at_end_of_frame(&mut self, model: Model, lifetime: Option<f32>) {
+        self.spawner.pending.push(SpawnRequest { kind: SpawnKind::Model(model), lifetime, dissolve: false });
+    }
+
+    /// Like `spawn_at_end_of_frame`, but the model dissolves in with a burst of particles<br>
+    /// It dissolves out again when its lifetime runs out or it's despawned with `despawn_at_end_of_frame`
+    pub fn spawn_dissolving_at_end_of_frame(&mut self, model: Model, lifetime: Option<f32>) {
+        self.spawner.pending.push(SpawnRequest { kind: SpawnKind::Model(model), lifetime, dissolve: true });
+    }
+
+    /// Queue a pooled model to be placed at `transform` at the end of the frame
+    pub fn spawn_pooled_at_end_of_frame(&mut self, pool: &str, transform: Matrix4<f32>, lifetime: Option<f32>) {
+        self.spawner.pending.push(SpawnRequest { kind: SpawnKind::Pooled(pool.to_string(), transform), lifetime, dissolve: false });
+    }
+
+    /// Despawn a model after `seconds` of game time, replaces any previous timer on that model
+    pub fn despawn_after(&mut self, index: usize, seconds: f32) {
+        self.spawner.timers.retain(|(i, _)| *i != index);
+        self.spawner.timers.push((index, seconds));
+    }
+
+    /// Queue a model for removal at the end of the frame
+    pub fn despawn_at_end_of_frame(&mut self, index: usize) {
+        self.spawner.pending_despawn.push(index);
+    }
+
+    /// Queue a model to dissolve out with a burst of particles at the end of the frame, then be despawned<br>
+    /// Safe to use while the model is taken during component update, unlike `World::dissolve_out`
+    pub fn dissolve_out_at_end_of_frame(&mut self, index: usize) {
+        if !self.spawner.dissolving.contains(&index) {
+            self.spawner.dissolving.push(index);
+        }
+        self.spawner.pending_despawn.push(index);
+    }
+
+    /// Take an instance from a pool immediately, inserting a new one if the pool is empty<br>
+    /// Returns `None` if no pool with that name was registered
+    pub fn spawn_pooled(&mut self, pool: &str, transform: Matrix4<f32>) -> Option<usize> {
+        let index = match self.spawner.free.get_mut(pool).and_then(|free| free.pop()) {
+            Some(index) => {
+                self.reset_pooled(pool, index);
+                index
+            },
+            None => self.insert_pool_instance(pool)?
+        };
+
+        self.spawner.active.insert(index, pool.to_string());
+        self.set_model_transform(index, transform);
+        Some(index)
+    }
+
+    /// Remove a model, pooled models are hidden and returned to their pool instead
+    pub fn despawn(&mut self, index: usize) {
+        self.spawner.timers.retain(|(i, _)| *i != index);
+        self.spawner.dissolving.retain(|i| *i != index);
+
+        if let Some(pool) = self.spawner.active.remove(&index) {
+            self.park_pooled(index);
+            self.spawner.free.get_mut(&pool).unwrap().push(index);
+        } else if self.models.get(index).is_some_and(|m| m.is_some()) {
+            self.remove_model(index).unwrap();
+        }
+    }
+
+    /// Apply all queued spawns and despawns, call once per frame after rendering
+    pub fn end_frame(&mut self) {
+        let despawn = std::mem::take(&mut self.spawner.pending_despawn);
+        for index in despawn {
+            if self.do_game_logic && self.spawner.dissolving.contains(&index) && !self.is_dissolving(index) {
+                self.spawner.dissolving.retain(|i| *i != index);
+                self.dissolve_out(index, DEFAULT_DISSOLVE_TIME, true);
+            } else {
+                self.despawn(index);
+            }
+        }
+
+        let pending = std::mem::take(&mut self.spawner.pending);
+        for request in pending {
+            let index = match request.kind {
+                SpawnKind::Model(model) => Some(self.insert_model(model)),
+                SpawnKind::Pooled(pool, transform) => {
+                    let index = self.spawn_pooled(&pool, transform);
+                    if index.is_none() {
+                        self.editor_data.show_debug.push(format!("no pool named {}", pool));
+                    }
+                    index
+                }
+            };
+
+            if let (Some(index), Some(lifetime)) = (index, request.lifetime) {
+                self.despawn_after(index, lifetime);
+            }
+            if let (Some(index), true) = (index, request.dissolve) {
+                self.spawner.dissolving.push(index);
+                self.dissolve_in(index, DEFAULT_DISSOLVE_TIME, true);
+            }
+        }
+    }
+
+    fn insert_pool_instance(&mut self, pool: &str) -> Option<usize> {
+        let template = self.spawner.templates.get(pool)?.clone();
+        Some(self.insert_model(template))
+    }
+
+    /// Hide an unused pooled model and move it (and its colliders) out of the way
+    fn park_pooled(&mut self, index: usize) {
+        self.set_model_visible_absolute(index, false);
+        self.set_model_transform(index, Matrix4::from_translation(vec3(0.0, POOL_PARK_Y, 0.0)));
+    }
+
+    /// Restore a parked model to the state of its template
+    fn reset_pooled(&mut self, pool: &str, index: usize) {
+        let template = self.spawner.templates.get(pool).unwrap();
+        let components = template.components.clone();
+        let hidden = template.hidden;
+
+        let mut model = self.models[index].take().unwrap();
+        model.components = components;
+        model.hidden = hidden;
+        for i in 0..model.components.len() {
+            Component::on_insert(i, &mut model, self);
+        }
+        self.models[index] = Some(model);
+
+        if !hidden {
+            self.set_model_visible_absolute(index, true);
+        }
+    }
+}
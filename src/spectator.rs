@@ -0,0 +1,80 @@
+use cgmath::{EuclideanSpace, Point3, Vector3};
+
+use crate::{common, input::{Action, Input}, render::CameraControlScheme, world::{PlayerMovementMode, World}};
+
+pub const DEFAULT_SPECTATOR_SPEED: f32 = 8.0;
+const MIN_SPECTATOR_SPEED: f32 = 1.0;
+const MAX_SPECTATOR_SPEED: f32 = 64.0;
+/// Seconds the camera takes to glide back to the player
+const RETURN_TIME: f32 = 0.4;
+/// Height of the camera above the player's position while standing, see `Player::eye_offset`
+pub const EYE_HEIGHT: f32 = 0.5;
+
+/// A free camera detached from the player while the game keeps running
+pub struct Spectator {
+    /// Adjusted with the scroll wheel while flying
+    pub speed: f32,
+    /// Where the camera started gliding back from and how long it has been gliding
+    returning: Option<(Vector3<f32>, f32)>
+}
+
+impl Default for Spectator {
+    fn default() -> Self {
+        Self { speed: DEFAULT_SPECTATOR_SPEED, returning: None }
+    }
+}
+
+impl World {
+    pub fn spectating(&self) -> bool {
+        matches!(self.player.movement, PlayerMovementMode::Spectator)
+    }
+
+    /// Where the player sees from, the camera unless it is detached
+    pub fn player_eye(&self) -> Vector3<f32> {
+        if self.spectating() {
+            self.player.position + self.player.eye_offset()
+        } else {
+            self.scene.camera.pos.to_vec()
+        }
+    }
+
+    /// Detach the camera from the player while playing, pressing the key again or cancelling glides it back
+    pub fn update_spectator(&mut self, input: &Input, delta_time: f32) {
+        let toggle = input.action_just_pressed(Action::Spectate);
+        match (self.player.movement.clone(), self.scene.camera.control_sceme.clone()) {
+            (PlayerMovementMode::FirstPerson, CameraControlScheme::FirstPerson(true)) if toggle && self.do_game_logic => {
+                self.player.movement = PlayerMovementMode::Spectator;
+                self.scene.camera.control_sceme = CameraControlScheme::Spectator;
+                self.spectator.returning = None;
+            },
+            (PlayerMovementMode::Spectator, _) => {
+                let spectator = &mut self.spectator;
+                if spectator.returning.is_none() && (toggle || input.action_just_pressed(Action::Cancel)) {
+                    spectator.returning = Some((self.scene.camera.pos.to_vec(), 0.0));
+                }
+
+                match &mut spectator.returning {
+                    Some((from, time)) => {
+                        *time += delta_time;
+                        let progress = (*time / RETURN_TIME).min(1.0);
+                        let eased = progress * progress * (3.0 - 2.0 * progress);
+                        let eye = self.player.position + self.player.eye_offset();
+                        self.scene.camera.pos = Point3::from_vec(common::vec3_mix(*from, eye, eased));
+                        if progress >= 1.0 {
+                            spectator.returning = None;
+                            self.player.movement = PlayerMovementMode::FirstPerson;
+                            self.scene.camera.control_sceme = CameraControlScheme::FirstPerson(true);
+                        }
+                    },
+                    None => {
+                        if input.scroll.abs() > 0.01 {
+                            spectator.speed = (spectator.speed * (1.0 - input.scroll * 0.005)).clamp(MIN_SPECTATOR_SPEED, MAX_SPECTATOR_SPEED);
+                        }
+                        self.scene.camera.fly(input, spectator.speed * delta_time);
+                    }
+                }
+            },
+            _ => ()
+        }
+    }
+}
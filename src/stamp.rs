@@ -0,0 +1,188 @@
+use cgmath::{vec3, Deg, ElementWise, InnerSpace, Matrix4, Transform, Point3, EuclideanSpace, Vector3};
+
+use crate::{collision::RaycastResult, common::{self, compose_extents, mat4_remove_translation}, render::PointLight, session::EditKind, world::{Model, Renderable, Selection, World}};
+
+/// Degrees the stamp turns each time R is pressed
+pub const STAMP_ANGLE_INCREMENT: f32 = 15.0;
+
+pub enum StampSource {
+    Brush(Renderable),
+    /// Template model with no translation, and copies of its lights
+    Model(Model, Vec<(Vector3<f32>, PointLight)>)
+}
+
+/// Editor tool that places copies of a brush, model or prefab wherever the user clicks
+pub struct Stamp {
+    pub source: StampSource,
+    /// Rotation around the y axis in degrees, brushes only turn in steps of 90
+    pub angle: f32,
+    /// Center and half extents of where the stamp would be placed
+    pub hover: Option<(Vector3<f32>, Vector3<f32>)>
+}
+
+impl Stamp {
+    fn rotation(&self) -> Matrix4<f32> {
+        Matrix4::from_angle_y(Deg(self.angle))
+    }
+
+    /// Brushes are axis aligned so odd quarter turns swap their x and z size instead
+    fn brush_quarter_turned(&self) -> bool {
+        ((self.angle / 90.0).round() as i32).rem_euclid(2) == 1
+    }
+
+    /// Offset from the placement position to the stamp's center, and its half extents, both after rotation
+    fn footprint(&self) -> (Vector3<f32>, Vector3<f32>) {
+        match &self.source {
+            StampSource::Brush(Renderable::Brush(_, _, size, ..)) => {
+                let half = size / 2.0;
+                if self.brush_quarter_turned() {
+                    (common::vec3_zero(), vec3(half.z, half.y, half.x))
+                } else {
+                    (common::vec3_zero(), half)
+                }
+            },
+            StampSource::Model(model, _) => {
+                let (center, half) = model.extents.unwrap_or((common::vec3_zero(), common::vec3_all(0.25)));
+                let transform = self.rotation() * model.transform;
+                let mut corners = Vec::new();
+                for x in [-1.0, 1.0] {
+                    for y in [-1.0, 1.0] {
+                        for z in [-1.0, 1.0] {
+                            let corner = center + half.mul_element_wise(vec3(x, y, z));
+                            corners.push((transform.transform_point(Point3::from_vec(corner)).to_vec(), common::vec3_zero()));
+                        }
+                    }
+                }
+                compose_extents(corners)
+            },
+            _ => unreachable!()
+        }
+    }
+}
+
+impl World {
+    /// Use the current selection as the stamp
+    pub fn stamp_from_selection(&mut self) {
+        let source = match &self.editor_data.selected_object {
+            Some(Selection::Brush(brush)) => {
+                StampSource::Brush(self.models[self.internal.brushes].as_ref().unwrap().render[*brush].clone())
+            },
+            Some(Selection::Model(model)) => self.stamp_template(*model),
+            Some(Selection::Multiple(_)) => {
+                self.editor_data.show_debug.push(String::from("can only stamp a single object"));
+                return;
+            },
+            None => return
+        };
+
+        self.set_stamp(source);
+    }
+
+    /// Use a model that isn't in the world (e.g. a loaded prefab) as the stamp
+    pub fn stamp_from_model(&mut self, mut model: Model, lights: Vec<(Vector3<f32>, PointLight)>) {
+        model.transform = mat4_remove_translation(model.transform);
+        if model.extents.is_none() {
+            model.calculate_extents();
+        }
+        self.set_stamp(StampSource::Model(model, lights));
+    }
+
+    fn set_stamp(&mut self, source: StampSource) {
+        self.editor_data.stamp = Some(Stamp { source, angle: 0.0, hover: None });
+        self.editor_data.show_debug.push(String::from("stamp set, click to place, R to rotate, Escape to stop"));
+    }
+
+    fn stamp_template(&self, index: usize) -> StampSource {
+        let model = self.models[index].as_ref().unwrap();
+        let template = Model {
+            id: 0,
+            name: None,
+            tags: model.tags.clone(),
+            connections: model.connections.clone(),
+            transform: mat4_remove_translation(model.transform),
+            solid: model.solid,
+            foreground: model.foreground,
+            extents: model.extents,
+            index: None,
+            insert_collider: model.insert_collider.clone(),
+            colliders: Vec::new(),
+            lights: Vec::new(),
+            mobile: model.mobile,
+            render: model.render.clone(),
+            renderable_indices: Vec::new(),
+            components: model.components.clone(),
+            hidden: model.hidden,
+            hidden_dirty: model.hidden_dirty
+        };
+        let lights = model.lights.iter().map(|(offset, light)| (*offset, self.scene.point_lights[*light].clone())).collect();
+
+        StampSource::Model(template, lights)
+    }
+
+    pub fn rotate_stamp(&mut self) {
+        if let Some(stamp) = &mut self.editor_data.stamp {
+            let step = if matches!(stamp.source, StampSource::Brush(_)) { 90.0 } else { STAMP_ANGLE_INCREMENT };
+            stamp.angle = (stamp.angle + step) % 360.0;
+        }
+    }
+
+    /// Find where the stamp would be placed on the surface under the mouse
+    pub fn update_stamp_hover(&mut self, result: Option<&RaycastResult>) {
+        let (increment, snapping) = (self.editor_data.increment, self.editor_data.snapping);
+        let Some(stamp) = &mut self.editor_data.stamp else { return; };
+        stamp.hover = None;
+        let Some(result) = result else { return; };
+        let Some(model) = result.model else { return; };
+        // Don't stamp onto the gizmos
+        if !self.editor_data.active || (model != self.internal.brushes && self.internal.internal_ids.contains(&model)) {
+            return;
+        }
+
+        let (_, half) = stamp.footprint();
+        let normal = result.normal.normalize();
+        let mut center = result.pos + normal * vec3(normal.x.abs(), normal.y.abs(), normal.z.abs()).dot(half);
+
+        // Snap along the surface, but keep the stamp flush against it
+        for axis in 0..3 {
+            if snapping && normal[axis].abs() < 0.5 {
+                center[axis] = common::round_to(center[axis], increment);
+            }
+        }
+
+        stamp.hover = Some((center, half));
+    }
+
+    /// Place the stamp at the hovered position<br>
+    /// Returns true if the click was used up
+    pub fn stamp_clicked(&mut self) -> bool {
+        let Some(stamp) = &self.editor_data.stamp else { return false; };
+        let Some((center, _)) = stamp.hover else { return false; };
+        let (offset, _) = stamp.footprint();
+        let rotation = stamp.rotation();
+        let quarter_turned = stamp.brush_quarter_turned();
+        let source = match &stamp.source {
+            StampSource::Brush(brush) => StampSource::Brush(brush.clone()),
+            StampSource::Model(template, lights) => StampSource::Model(template.clone(), lights.clone())
+        };
+
+        match source {
+            StampSource::Brush(Renderable::Brush(material, _, size, flags, faces)) => {
+                let size = if quarter_turned { vec3(size.z, size.y, size.x) } else { size };
+                self.insert_brush(Renderable::Brush(material, center, size, flags, faces));
+                self.record_edit(EditKind::Created, true, center);
+            },
+            StampSource::Model(mut model, lights) => {
+                model.transform = Matrix4::from_translation(center - offset) * rotation * model.transform;
+                for (light_offset, light) in lights {
+                    let light = self.scene.add_point_light(light);
+                    model.lights.push(((rotation * light_offset.extend(0.0)).truncate(), light));
+                }
+                self.insert_model(model);
+                self.record_edit(EditKind::Created, false, center);
+            },
+            _ => unreachable!()
+        }
+
+        true
+    }
+}
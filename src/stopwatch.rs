@@ -0,0 +1,135 @@
+use crate::{component::{Component, Trigger, TriggerType}, config::UserConfig, events::EventKind, input::{Action, Input}, world::World};
+
+/// Moving the player any of these ways starts the stopwatch in levels without a start line
+const START_ACTIONS: [Action; 5] = [Action::MoveForward, Action::MoveBack, Action::MoveLeft, Action::MoveRight, Action::Jump];
+
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub enum StopwatchState {
+    /// Waiting for the first move or the start line
+    #[default]
+    Waiting,
+    Running,
+    /// Stopped by a goal
+    Stopped
+}
+
+/// Times levels with `GameplayRules::timed` from the start until a goal is reached
+#[derive(Default)]
+pub struct Stopwatch {
+    pub time: f32,
+    pub state: StopwatchState,
+    /// The level has a start line, set by `World::prepare_stopwatch`
+    pub start_line: bool,
+    /// Saved level best times are kept for, None when play started with unsaved changes
+    level: Option<String>
+}
+
+/// A timed run that reached a goal, shown on the completion screen
+#[derive(Clone, Debug)]
+pub struct StopwatchRun {
+    pub time: f32,
+    /// Best time on the level before this run, filled in by `World::record_best_time`
+    pub previous_best: Option<f32>,
+    recorded: bool
+}
+
+impl StopwatchRun {
+    pub fn new(time: f32) -> Self {
+        Self { time, previous_best: None, recorded: false }
+    }
+
+    pub fn is_best(&self) -> bool {
+        self.previous_best.map_or(true, |best| self.time < best)
+    }
+}
+
+impl UserConfig {
+    /// Best stopwatch time on the level saved at `level`
+    pub fn best_time(&self, level: &str) -> Option<f32> {
+        self.best_times.get(level).copied()
+    }
+
+    /// Keep `time` if it beats the best time for `level`, returns the best time from before
+    pub fn record_best_time(&mut self, level: &str, time: f32) -> Option<f32> {
+        let previous = self.best_time(level);
+        if previous.map_or(true, |best| time < best) {
+            self.best_times.insert(level.to_string(), time);
+        }
+        previous
+    }
+}
+
+impl World {
+    /// Whether the level has a trigger that starts the stopwatch, it won't start on the first move then
+    fn has_start_line(&self) -> bool {
+        self.models.iter().flatten().any(|model| model.components.iter().any(|component| {
+            matches!(component, Component::Trigger(Trigger { kind: TriggerType::StartTimer, .. }))
+        }))
+    }
+
+    /// Start the stopwatch from 0 if it hasn't started yet, crossing the start line again doesn't restart it
+    pub fn start_stopwatch(&mut self, source: String) {
+        if !self.do_game_logic || !self.rules.timed || self.stopwatch.state != StopwatchState::Waiting {
+            return;
+        }
+        self.stopwatch.state = StopwatchState::Running;
+        self.stopwatch.time = 0.0;
+        self.log_event(EventKind::StopwatchStarted, source, None, String::new());
+    }
+
+    /// Reset the stopwatch and look up what it needs from the level, call when play starts or a level is loaded to play
+    pub fn prepare_stopwatch(&mut self) {
+        let level = self.editor_data.save_to.as_ref()
+            .filter(|_| !self.has_unsaved_changes())
+            .map(|path| path.to_string_lossy().to_string());
+        self.stopwatch = Stopwatch { start_line: self.has_start_line(), level, ..Default::default() };
+    }
+
+    pub fn reset_stopwatch(&mut self) {
+        self.stopwatch.time = 0.0;
+        self.stopwatch.state = StopwatchState::Waiting;
+    }
+
+    /// Start the stopwatch on the first move when there is no start line, and keep it running, only while playing
+    pub fn update_stopwatch(&mut self, input: &Input, delta_time: f32) {
+        if !self.do_game_logic || !self.rules.timed || self.spectating() {
+            return;
+        }
+        match self.stopwatch.state {
+            StopwatchState::Waiting => {
+                if START_ACTIONS.iter().any(|action| input.action_pressed(*action)) && !self.stopwatch.start_line {
+                    self.start_stopwatch(String::from("player"));
+                }
+            },
+            StopwatchState::Running => self.stopwatch.time += delta_time,
+            StopwatchState::Stopped => ()
+        }
+    }
+
+    /// Stop the stopwatch at a goal, returns the time if it was running
+    pub fn stop_stopwatch(&mut self) -> Option<f32> {
+        if self.stopwatch.state != StopwatchState::Running {
+            return None;
+        }
+        self.stopwatch.state = StopwatchState::Stopped;
+        Some(self.stopwatch.time)
+    }
+
+    /// Save the completed run's time to the user config if it's the best on this level<br>
+    /// Call after `update_goals`, only levels that were saved when play started keep best times
+    pub fn record_best_time(&mut self, config: &mut UserConfig) {
+        let Some(completion) = &mut self.completion else { return; };
+        let Some(run) = &mut completion.stopwatch else { return; };
+        if run.recorded {
+            return;
+        }
+        run.recorded = true;
+        let Some(level) = &self.stopwatch.level else { return; };
+        run.previous_best = config.record_best_time(level, run.time);
+        if run.is_best() {
+            if let Err(e) = config.save() {
+                self.editor_data.show_debug.push(e);
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use glow::{HasContext, NativeFramebuffer, NativeTexture};
+use image::RgbaImage;
+
+/// Offscreen target for renders that don't go to the window, like the regression renders, `frames` and camera feeds
+pub struct RenderTarget {
+    pub fbo: NativeFramebuffer,
+    color: glow::NativeRenderbuffer,
+    depth: glow::NativeRenderbuffer,
+    pub size: (u32, u32)
+}
+
+impl RenderTarget {
+    pub unsafe fn new(size: (u32, u32), gl: &glow::Context) -> Result<Self, String> {
+        let color = gl.create_renderbuffer()?;
+        gl.bind_renderbuffer(glow::RENDERBUFFER, Some(color));
+        gl.renderbuffer_storage(glow::RENDERBUFFER, glow::RGBA8, size.0 as i32, size.1 as i32);
+        let depth = gl.create_renderbuffer()?;
+        gl.bind_renderbuffer(glow::RENDERBUFFER, Some(depth));
+        gl.renderbuffer_storage(glow::RENDERBUFFER, glow::DEPTH24_STENCIL8, size.0 as i32, size.1 as i32);
+
+        let fbo = gl.create_framebuffer()?;
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(fbo));
+        gl.framebuffer_renderbuffer(glow::FRAMEBUFFER, glow::COLOR_ATTACHMENT0, glow::RENDERBUFFER, Some(color));
+        gl.framebuffer_renderbuffer(glow::FRAMEBUFFER, glow::DEPTH_STENCIL_ATTACHMENT, glow::RENDERBUFFER, Some(depth));
+        let complete = gl.check_framebuffer_status(glow::FRAMEBUFFER) == glow::FRAMEBUFFER_COMPLETE;
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+
+        if complete {
+            Ok(Self { fbo, color, depth, size })
+        } else {
+            gl.delete_framebuffer(fbo);
+            gl.delete_renderbuffer(color);
+            gl.delete_renderbuffer(depth);
+            Err(String::from("offscreen framebuffer was not complete"))
+        }
+    }
+
+    /// Pixels of the target, top row first
+    pub unsafe fn read(&self, gl: &glow::Context) -> RgbaImage {
+        let (width, height) = self.size;
+        let mut pixels = vec![0; (width * height * 4) as usize];
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.fbo));
+        gl.read_pixels(0, 0, width as i32, height as i32, glow::RGBA, glow::UNSIGNED_BYTE, glow::PixelPackData::Slice(Some(pixels.as_mut_slice())));
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        let image = RgbaImage::from_raw(width, height, pixels).unwrap();
+        image::imageops::flip_vertical(&image)
+    }
+
+    /// Copy the target into `texture`, which has to be at least as big, and rebuild its mipmaps<br>
+    /// The render is kept apart from the texture so meshes showing it can be in the render too
+    pub unsafe fn copy_to_texture(&self, texture: NativeTexture, gl: &glow::Context) {
+        gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(self.fbo));
+        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+        gl.copy_tex_sub_image_2d(glow::TEXTURE_2D, 0, 0, 0, 0, 0, self.size.0 as i32, self.size.1 as i32);
+        gl.generate_mipmap(glow::TEXTURE_2D);
+        gl.bind_texture(glow::TEXTURE_2D, None);
+        gl.bind_framebuffer(glow::READ_FRAMEBUFFER, None);
+    }
+
+    pub unsafe fn delete(self, gl: &glow::Context) {
+        gl.delete_framebuffer(self.fbo);
+        gl.delete_renderbuffer(self.color);
+        gl.delete_renderbuffer(self.depth);
+    }
+}
@@ -1,6 +1,9 @@
 use std::{collections::HashMap, error::Error, path::PathBuf};
 
 use glow::{HasContext, PixelUnpackData};
+use image::{Rgba, RgbaImage};
+
+use crate::ui::UITheme;
 
 pub struct Texture {
     pub width: u32,
@@ -64,6 +67,57 @@ impl TextureBank {
         
         let image_path = PathBuf::from(format!("res/textures/{}.png", name));
         let image = image::open(image_path)?.flipv().to_rgba8();
+        self.upload(name, &image, gl)
+    }
+
+    /// Rasterize `text` with the UI font into a texture called `name`, glyphs are tinted `color` on a transparent background<br>
+    /// Does nothing if the texture already exists
+    pub unsafe fn load_text(&mut self, name: &str, text: &str, color: [f32; 3], gl: &glow::Context) -> Result<(), Box<dyn Error>> {
+        if self.textures.contains_key(name) {
+            return Ok(());
+        }
+
+        let theme = UITheme::default();
+        let font = image::open(format!("res/textures/{}.png", theme.font_texture))?.to_rgba8();
+        let (glyph_width, glyph_height) = theme.glyph_size;
+        let lines: Vec<&str> = text.split('\n').collect();
+        let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0).max(1) as u32;
+        let tint = color.map(|c| (c.clamp(0.0, 1.0) * 255.0) as u8);
+
+        let mut image = RgbaImage::new(columns * glyph_width, lines.len() as u32 * glyph_height);
+        for (row, line) in lines.iter().enumerate() {
+            for (column, char) in line.chars().enumerate() {
+                if char == ' ' {
+                    continue;
+                }
+                let index = theme.font_chars.chars().position(|c| c == char).unwrap_or(7 + 6 * theme.font_columns);
+                let (glyph_x, glyph_y) = ((index % theme.font_columns) as u32 * glyph_width, (index / theme.font_columns) as u32 * glyph_height);
+
+                for y in 0..glyph_height {
+                    for x in 0..glyph_width {
+                        let alpha = font.get_pixel(glyph_x + x, glyph_y + y)[3];
+                        image.put_pixel(column as u32 * glyph_width + x, row as u32 * glyph_height + y, Rgba([tint[0], tint[1], tint[2], alpha]));
+                    }
+                }
+            }
+        }
+
+        self.upload(name, &image::imageops::flip_vertical(&image), gl)
+    }
+
+    /// Take ownership of a texture that was rendered to, replacing any texture called `name`
+    pub unsafe fn insert_rendered(&mut self, name: &str, inner: glow::Texture, width: u32, height: u32, gl: &glow::Context) {
+        gl.bind_texture(glow::TEXTURE_2D, Some(inner));
+        texture_settings(gl);
+        gl.generate_mipmap(glow::TEXTURE_2D);
+        gl.bind_texture(glow::TEXTURE_2D, None);
+
+        if let Some(old) = self.textures.insert(name.to_string(), Texture { width, height, name: name.to_string(), inner }) {
+            gl.delete_texture(old.inner);
+        }
+    }
+
+    unsafe fn upload(&mut self, name: &str, image: &RgbaImage, gl: &glow::Context) -> Result<(), Box<dyn Error>> {
         let width = image.width();
         let height = image.height();
         let data = image.as_flat_samples();
@@ -0,0 +1,153 @@
+use cgmath::{vec4, Vector4, Zero};
+use serde::{Deserialize, Serialize};
+
+use crate::{collision::{PhysicalProperties, DEFAULT_CONTROL, DEFAULT_FRICTION, DEFAULT_JUMP}, render::Scene};
+
+/// How strongly tinted brushes are covered by their tint color
+const TINT_STRENGTH: f32 = 0.65;
+
+/// What a brush type is used for, set per type in the brush types file
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Default, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum BrushCategory {
+    /// Walls and floors that make up the level
+    #[default]
+    Structural,
+    /// Decoration that doesn't change how the level plays
+    Detail,
+    Trigger,
+    Water,
+    Ladder
+}
+
+impl BrushCategory {
+    pub const ALL: [BrushCategory; 5] = [Self::Structural, Self::Detail, Self::Trigger, Self::Water, Self::Ladder];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Structural => "structural",
+            Self::Detail => "detail",
+            Self::Trigger => "trigger",
+            Self::Water => "water",
+            Self::Ladder => "ladder"
+        }
+    }
+
+    pub fn color(&self) -> [f32; 3] {
+        match self {
+            Self::Structural => [0.6, 0.6, 0.6],
+            Self::Detail => [0.3, 0.8, 0.35],
+            Self::Trigger => [1.0, 0.55, 0.1],
+            Self::Water => [0.2, 0.45, 1.0],
+            Self::Ladder => [0.75, 0.45, 0.2]
+        }
+    }
+}
+
+/// How a brush type's physics differ from the defaults
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum PhysicsClass {
+    /// Default physics, possibly a brush type nobody set up
+    Plain,
+    /// Little control over movement
+    Slippery,
+    /// Jumps higher than normal
+    Bouncy,
+    /// Jumps lower or slows the player down
+    Sticky,
+    /// Changed in some other way
+    Custom
+}
+
+impl PhysicsClass {
+    pub const ALL: [PhysicsClass; 5] = [Self::Plain, Self::Slippery, Self::Bouncy, Self::Sticky, Self::Custom];
+
+    pub fn of(properties: &PhysicalProperties) -> Self {
+        if properties.jump > DEFAULT_JUMP {
+            Self::Bouncy
+        } else if properties.control < DEFAULT_CONTROL * 0.5 && properties.friction >= DEFAULT_FRICTION {
+            Self::Slippery
+        } else if properties.jump < DEFAULT_JUMP || properties.friction < DEFAULT_FRICTION * 0.5 {
+            Self::Sticky
+        } else if properties.control != DEFAULT_CONTROL || properties.friction != DEFAULT_FRICTION {
+            Self::Custom
+        } else {
+            Self::Plain
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Plain => "plain",
+            Self::Slippery => "slippery",
+            Self::Bouncy => "bouncy",
+            Self::Sticky => "sticky",
+            Self::Custom => "custom"
+        }
+    }
+
+    pub fn color(&self) -> [f32; 3] {
+        match self {
+            Self::Plain => [0.6, 0.6, 0.6],
+            Self::Slippery => [0.45, 0.85, 1.0],
+            Self::Bouncy => [1.0, 0.3, 0.85],
+            Self::Sticky => [0.55, 0.35, 0.1],
+            Self::Custom => [1.0, 0.9, 0.2]
+        }
+    }
+}
+
+/// Editor view that colors brushes by their type, for auditing a level
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub enum BrushTint {
+    #[default]
+    Off,
+    Category,
+    Physics
+}
+
+impl BrushTint {
+    pub fn cycle(&self) -> Self {
+        match self {
+            Self::Off => Self::Category,
+            Self::Category => Self::Physics,
+            Self::Physics => Self::Off
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Off => "off",
+            Self::Category => "category",
+            Self::Physics => "physics"
+        }
+    }
+
+    /// Names and colors shown as a key while the view is on
+    pub fn legend(&self) -> Vec<(&'static str, [f32; 3])> {
+        match self {
+            Self::Off => Vec::new(),
+            Self::Category => BrushCategory::ALL.iter().map(|category| (category.name(), category.color())).collect(),
+            Self::Physics => PhysicsClass::ALL.iter().map(|class| (class.name(), class.color())).collect()
+        }
+    }
+}
+
+/// Brush type of a brush mesh, brush meshes are named `Brush_{type}` with an optional `#{alignment}`
+fn brush_type(mesh: &str) -> Option<&str> {
+    let name = mesh.strip_prefix("Brush_")?;
+    Some(name.split_once('#').map_or(name, |(name, _)| name))
+}
+
+impl Scene {
+    /// Tint color and strength for instances of `mesh`, zero strength for anything that isn't a brush or while the view is off
+    pub fn tint_for(&self, mesh: &str) -> Vector4<f32> {
+        let Some(material) = brush_type(mesh).and_then(|name| self.materials.get(name)) else { return Vector4::zero(); };
+        let color = match self.brush_tint {
+            BrushTint::Off => return Vector4::zero(),
+            BrushTint::Category => material.category.color(),
+            BrushTint::Physics => PhysicsClass::of(&material.physical_properties).color()
+        };
+        vec4(color[0], color[1], color[2], TINT_STRENGTH)
+    }
+}
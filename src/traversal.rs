@@ -0,0 +1,182 @@
+use cgmath::{vec3, EuclideanSpace, Matrix4, Point3, Transform, Vector3};
+
+use crate::{collision::STAIR_MAX_SIZE, shader::ProgramBank, world::{Renderable, World, PLAYER_SIZE}};
+
+/// Seconds between checks while the overlay is shown
+const CHECK_INTERVAL: f32 = 0.5;
+/// Brushes closer than this count as touching
+const TOUCH_EPSILON: f32 = 0.01;
+/// Thickness of ledge markers, they sit on the face of the step
+const MARKER_THICKNESS: f32 = 0.1;
+const LEDGE_COLOR: Vector3<f32> = vec3(1.0, 0.6, 0.0);
+const GAP_COLOR: Vector3<f32> = vec3(1.0, 0.1, 0.1);
+const CEILING_COLOR: Vector3<f32> = vec3(0.8, 0.1, 1.0);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TraversalProblem {
+    /// Too tall to walk up, but short enough that it was probably meant to be a step
+    Ledge,
+    /// Space between two walls the player is too wide for
+    Gap,
+    /// Space over a floor the player is too tall for
+    LowCeiling
+}
+
+impl TraversalProblem {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Ledge => "ledge",
+            Self::Gap => "gap",
+            Self::LowCeiling => "low ceiling"
+        }
+    }
+
+    fn color(&self) -> Vector3<f32> {
+        match self {
+            Self::Ledge => LEDGE_COLOR,
+            Self::Gap => GAP_COLOR,
+            Self::LowCeiling => CEILING_COLOR
+        }
+    }
+}
+
+/// Problem found between two brushes, with the box it covers
+#[derive(Clone, Debug)]
+pub struct TraversalWarning {
+    pub problem: TraversalProblem,
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>
+}
+
+/// Editor overlay marking where the player would get stuck, see `World::find_traversal_problems`
+#[derive(Default)]
+pub struct TraversalCheck {
+    pub show: bool,
+    pub warnings: Vec<TraversalWarning>,
+    timer: f32
+}
+
+impl TraversalCheck {
+    pub fn count(&self, problem: TraversalProblem) -> usize {
+        self.warnings.iter().filter(|warning| warning.problem == problem).count()
+    }
+}
+
+/// How much `a` and `b` overlap on `axis`, negative if there is a gap between them
+fn overlap(a: &(Vector3<f32>, Vector3<f32>), b: &(Vector3<f32>, Vector3<f32>), axis: usize) -> f32 {
+    a.1[axis].min(b.1[axis]) - a.0[axis].max(b.0[axis])
+}
+
+/// Sides of `upper` a player standing on `lower` would walk into, when `upper` is too tall to step onto
+fn ledges(lower: &(Vector3<f32>, Vector3<f32>), upper: &(Vector3<f32>, Vector3<f32>), warnings: &mut Vec<TraversalWarning>) {
+    let rise = upper.1.y - lower.1.y;
+    if rise <= STAIR_MAX_SIZE || rise > STAIR_MAX_SIZE * 2.0 || upper.0.y > lower.1.y + STAIR_MAX_SIZE {
+        return;
+    }
+    if overlap(lower, upper, 0) < -TOUCH_EPSILON || overlap(lower, upper, 2) < -TOUCH_EPSILON {
+        return;
+    }
+
+    for (axis, across) in [(0, 2), (2, 0)] {
+        let width = overlap(lower, upper, across);
+        if width <= TOUCH_EPSILON {
+            continue;
+        }
+        // The floor has to stick out past the side for the player to walk up to it
+        for (side, sticks_out) in [(upper.0[axis], lower.0[axis] < upper.0[axis] - TOUCH_EPSILON), (upper.1[axis], lower.1[axis] > upper.1[axis] + TOUCH_EPSILON)] {
+            if !sticks_out {
+                continue;
+            }
+            let mut min = vec3(0.0, lower.1.y, 0.0);
+            let mut max = vec3(0.0, upper.1.y, 0.0);
+            min[axis] = side - MARKER_THICKNESS / 2.0;
+            max[axis] = side + MARKER_THICKNESS / 2.0;
+            min[across] = lower.0[across].max(upper.0[across]);
+            max[across] = lower.1[across].min(upper.1[across]);
+            warnings.push(TraversalWarning { problem: TraversalProblem::Ledge, min, max });
+        }
+    }
+}
+
+/// Gaps between the sides of `a` and `b` and between the top of one and the bottom of the other
+fn gaps(a: &(Vector3<f32>, Vector3<f32>), b: &(Vector3<f32>, Vector3<f32>), warnings: &mut Vec<TraversalWarning>) {
+    for (axis, across) in [(0, 2), (2, 0)] {
+        let gap = -overlap(a, b, axis);
+        // Walls need to face each other and be too tall to step over
+        if gap <= TOUCH_EPSILON || gap >= PLAYER_SIZE[axis] || overlap(a, b, across) <= TOUCH_EPSILON || overlap(a, b, 1) <= STAIR_MAX_SIZE {
+            continue;
+        }
+        let mut min = vec3(0.0, a.0.y.max(b.0.y), 0.0);
+        let mut max = vec3(0.0, a.1.y.min(b.1.y), 0.0);
+        min[axis] = a.1[axis].min(b.1[axis]);
+        max[axis] = a.0[axis].max(b.0[axis]);
+        min[across] = a.0[across].max(b.0[across]);
+        max[across] = a.1[across].min(b.1[across]);
+        warnings.push(TraversalWarning { problem: TraversalProblem::Gap, min, max });
+    }
+
+    let (floor, ceiling) = if a.1.y <= b.0.y { (a, b) } else { (b, a) };
+    let height = ceiling.0.y - floor.1.y;
+    // Only where there is room to walk under it
+    if height > TOUCH_EPSILON && height < PLAYER_SIZE.y && overlap(floor, ceiling, 0) >= PLAYER_SIZE.x && overlap(floor, ceiling, 2) >= PLAYER_SIZE.z {
+        let min = vec3(floor.0.x.max(ceiling.0.x), floor.1.y, floor.0.z.max(ceiling.0.z));
+        let max = vec3(floor.1.x.min(ceiling.1.x), ceiling.0.y, floor.1.z.min(ceiling.1.z));
+        warnings.push(TraversalWarning { problem: TraversalProblem::LowCeiling, min, max });
+    }
+}
+
+impl World {
+    /// World space bounds of each solid brush
+    fn solid_brush_bounds(&self) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+        let brushes = self.models[self.internal.brushes].as_ref().unwrap();
+        brushes.render.iter().zip(brushes.colliders.iter()).filter_map(|(brush, collider)| {
+            let Renderable::Brush(_, origin, size, ..) = brush else { return None; };
+            if !collider.and_then(|collider| self.physical_scene.collider(collider)).is_some_and(|collider| collider.solid) {
+                return None;
+            }
+            let a = brushes.transform.transform_point(Point3::from_vec(origin - size / 2.0)).to_vec();
+            let b = brushes.transform.transform_point(Point3::from_vec(origin + size / 2.0)).to_vec();
+            Some((vec3(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)), vec3(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z))))
+        }).collect()
+    }
+
+    /// Ledges too tall to walk up but less than twice `STAIR_MAX_SIZE`, and gaps smaller than the player's collider<br>
+    /// Only brushes are checked, models are left out
+    pub fn find_traversal_problems(&self) -> Vec<TraversalWarning> {
+        let bounds = self.solid_brush_bounds();
+        let mut warnings = Vec::new();
+        for (i, a) in bounds.iter().enumerate() {
+            for b in bounds.iter().skip(i + 1) {
+                ledges(a, b, &mut warnings);
+                ledges(b, a, &mut warnings);
+                gaps(a, b, &mut warnings);
+            }
+        }
+        warnings
+    }
+
+    /// Check the level again every `CHECK_INTERVAL` while the overlay is shown
+    pub fn update_traversal_check(&mut self, delta_time: f32) {
+        if !self.editor_data.active || !self.editor_data.traversal.show {
+            return;
+        }
+        self.editor_data.traversal.timer -= delta_time;
+        if self.editor_data.traversal.timer > 0.0 {
+            return;
+        }
+        self.editor_data.traversal.timer = CHECK_INTERVAL;
+        self.editor_data.traversal.warnings = self.find_traversal_problems();
+    }
+
+    pub unsafe fn render_traversal_warnings(&self, programs: &mut ProgramBank, gl: &glow::Context) {
+        let traversal = &self.editor_data.traversal;
+        if !self.editor_data.active || self.do_game_logic || !traversal.show {
+            return;
+        }
+        for warning in traversal.warnings.iter() {
+            let size = warning.max - warning.min;
+            let transform = Matrix4::from_translation((warning.min + warning.max) / 2.0) * Matrix4::from_nonuniform_scale(size.x, size.y, size.z);
+            self.scene.debug_render_box(transform, warning.problem.color(), self.editor_data.selection_box_vao.unwrap(), programs, gl);
+        }
+    }
+}
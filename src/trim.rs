@@ -0,0 +1,116 @@
+use cgmath::{vec3, Vector3};
+
+use crate::{common::{self, vec3_all}, mesh::{flags, BrushFaces}, world::{Renderable, World}};
+
+/// Floors continue past an edge when a brush that high covers it to within this
+const TRIM_EPSILON: f32 = 0.001;
+pub const DEFAULT_TRIM_HEIGHT: f32 = 0.25;
+pub const DEFAULT_TRIM_THICKNESS: f32 = 0.125;
+
+/// Trim the next `World::add_trim` makes, set in the inspector
+pub struct TrimSettings {
+    pub material: String,
+    pub height: f32,
+    pub thickness: f32
+}
+
+impl Default for TrimSettings {
+    fn default() -> Self {
+        Self { material: String::from("concrete"), height: DEFAULT_TRIM_HEIGHT, thickness: DEFAULT_TRIM_THICKNESS }
+    }
+}
+
+/// Whether `outer` covers `inner` on the x and z axes
+fn covers_flat(outer: (Vector3<f32>, Vector3<f32>), inner: (Vector3<f32>, Vector3<f32>)) -> bool {
+    [0, 2].iter().all(|axis| outer.0[*axis] <= inner.0[*axis] + TRIM_EPSILON && outer.1[*axis] >= inner.1[*axis] - TRIM_EPSILON)
+}
+
+impl World {
+    /// Boxes of the trim along the edges of the top of `floor`, inside its perimeter<br>
+    /// Edges where another brush carries on the floor at the same height are left out
+    pub fn plan_trim(&self, floor: usize) -> Result<Vec<(Vector3<f32>, Vector3<f32>)>, String> {
+        let (min, max) = self.brush_bounds(floor).ok_or(String::from("that's not a brush"))?;
+        let settings = &self.editor_data.trim;
+        let (height, thickness) = (settings.height, settings.thickness);
+        if height <= 0.0 || thickness <= 0.0 {
+            return Err(String::from("the trim needs a height and thickness above 0"));
+        }
+        if max.x - min.x <= thickness * 2.0 || max.z - min.z <= thickness * 2.0 {
+            return Err(format!("the brush is too small for {} thick trim", thickness));
+        }
+
+        let count = self.models[self.internal.brushes].as_ref().unwrap().render.len();
+        let neighbours: Vec<(Vector3<f32>, Vector3<f32>)> = (0..count)
+            .filter(|brush| *brush != floor)
+            .filter_map(|brush| self.brush_bounds(brush))
+            .filter(|bounds| (bounds.1.y - max.y).abs() < TRIM_EPSILON)
+            .collect();
+        // The strip just outside each edge, as (axis, at max side)
+        let continues = |axis: usize, high: bool| {
+            let mut outside = (vec3(min.x, max.y - thickness, min.z), vec3(max.x, max.y, max.z));
+            if high {
+                outside.0[axis] = max[axis];
+                outside.1[axis] = max[axis] + thickness;
+            } else {
+                outside.0[axis] = min[axis] - thickness;
+                outside.1[axis] = min[axis];
+            }
+            neighbours.iter().any(|bounds| covers_flat(*bounds, outside))
+        };
+
+        let mut trim = Vec::new();
+        // Edges running along x go the whole way, edges along z fit between them
+        let z_edges = [(false, !continues(2, false)), (true, !continues(2, true))];
+        for (high, needed) in z_edges {
+            if needed {
+                let z = if high { max.z - thickness } else { min.z };
+                trim.push((vec3(min.x, max.y, z), vec3(max.x, max.y + height, z + thickness)));
+            }
+        }
+        let start = if z_edges[0].1 { min.z + thickness } else { min.z };
+        let end = if z_edges[1].1 { max.z - thickness } else { max.z };
+        for high in [false, true] {
+            if !continues(0, high) {
+                let x = if high { max.x - thickness } else { min.x };
+                trim.push((vec3(x, max.y, start), vec3(x + thickness, max.y + height, end)));
+            }
+        }
+
+        // Don't add trim twice
+        let material = &settings.material;
+        let render = &self.models[self.internal.brushes].as_ref().unwrap().render;
+        trim.retain(|piece| !(0..count).any(|brush| {
+            matches!(&render[brush], Renderable::Brush(m, ..) if m == material) && self.brush_bounds(brush).is_some_and(|bounds| common::boxes_overlap(bounds, *piece))
+        }));
+        Ok(trim)
+    }
+
+    /// Add trim with the settings in `editor_data.trim` around the top of `floor`, see `plan_trim`<br>
+    /// The trim is merged with trim of the same material it lines up with, returns how many edges got trim
+    pub fn add_trim(&mut self, floor: usize) -> Result<usize, String> {
+        let trim = self.plan_trim(floor)?;
+        if trim.is_empty() {
+            return Err(String::from("the brush already has trim on every open edge"));
+        }
+        if !self.scene.materials.contains_key(&self.editor_data.trim.material) {
+            return Err(format!("there is no material named {}", self.editor_data.trim.material));
+        }
+
+        let material = self.editor_data.trim.material.clone();
+        let before = self.models[self.internal.brushes].as_ref().unwrap().render.len();
+        for (min, max) in trim.iter() {
+            self.insert_brush(Renderable::Brush(material.clone(), (min + max) / 2.0, max - min, flags::EXTEND_TEXTURE, BrushFaces::default()));
+        }
+
+        // Existing trim touching the new pieces, plus the pieces themselves
+        let count = self.models[self.internal.brushes].as_ref().unwrap().render.len();
+        let render = &self.models[self.internal.brushes].as_ref().unwrap().render;
+        let grow = vec3_all(TRIM_EPSILON * 2.0);
+        let touching: Vec<usize> = (0..count).filter(|brush| {
+            *brush >= before || (matches!(&render[*brush], Renderable::Brush(m, ..) if *m == material) && self.brush_bounds(*brush)
+                .is_some_and(|bounds| trim.iter().any(|piece| common::boxes_overlap((bounds.0 - grow, bounds.1 + grow), *piece))))
+        }).collect();
+        self.merge_brushes(&touching);
+        Ok(trim.len())
+    }
+}
@@ -1,14 +1,93 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, fs, rc::Rc};
 
-use cgmath::vec2;
+use cgmath::{vec2, Vector4};
 use glow::{HasContext, NativeVertexArray};
+use serde::{Deserialize, Serialize};
 use winit::event::MouseButton;
 
 use crate::{input::Input, shader::{Program, ProgramBank}, texture::TextureBank};
 
-const FONT_CHARS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789 .!,- ?  _";
+const FONT_CHARS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789 .!,- ?  _+:/()=%'—";
 const FONT_WIDTH: usize = 10;
 // const FONT_HEIGHT: usize = 8;
+/// Name of the built in theme, it doesn't need a file in `res/themes/`
+pub const DEFAULT_THEME: &str = "default";
+const WHITE: [f32; 4] = [1.0; 4];
+
+/// Textures, metrics and colors used to draw the UI, loaded from `res/themes/<name>.json`<br>
+/// Missing fields fall back to the built in skin
+#[derive(Clone, Serialize, Deserialize, Debug)]
+#[serde(default)]
+pub struct UITheme {
+    /// Nine cell atlas with the plain, window and selection frames side by side
+    pub frame_texture: String,
+    pub slider_texture: String,
+    pub font_texture: String,
+    /// Size of one nine cell tile, also the height of window title bars
+    pub cell_size: u32,
+    /// Characters in the order they appear in the font texture
+    pub font_chars: String,
+    /// Glyphs per row in the font texture
+    pub font_columns: usize,
+    /// Width and height of a glyph, also the spacing between characters and lines
+    pub glyph_size: (u32, u32),
+    pub text_tint: [f32; 4],
+    pub frame_tint: [f32; 4],
+    pub window_tint: [f32; 4],
+    pub selection_tint: [f32; 4]
+}
+
+impl Default for UITheme {
+    fn default() -> Self {
+        Self {
+            frame_texture: String::from("ui_frame"),
+            slider_texture: String::from("slider"),
+            font_texture: String::from("font"),
+            cell_size: 16,
+            font_chars: FONT_CHARS.to_string(),
+            font_columns: FONT_WIDTH,
+            glyph_size: (6, 10),
+            text_tint: WHITE,
+            frame_tint: WHITE,
+            window_tint: WHITE,
+            selection_tint: WHITE
+        }
+    }
+}
+
+impl UITheme {
+    pub fn load(name: &str) -> Result<Self, String> {
+        if name == DEFAULT_THEME {
+            return Ok(Self::default());
+        }
+
+        let src = fs::read_to_string(format!("res/themes/{}.json", name)).map_err(|e| format!("failed to open theme {}: {}", name, e))?;
+        serde_json::from_str(&src).map_err(|e| format!("failed to parse theme {}: {}", name, e))
+    }
+
+    /// Names of every theme that can be loaded, starting with the built in one
+    pub fn list() -> Vec<String> {
+        let mut themes = vec![DEFAULT_THEME.to_string()];
+        if let Ok(dir) = fs::read_dir("res/themes") {
+            let mut found = dir
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(".json").map(str::to_string))
+                .filter(|name| name != DEFAULT_THEME)
+                .collect::<Vec<_>>();
+            found.sort();
+            themes.extend(found);
+        }
+        themes
+    }
+
+    fn frame_tint(&self, frame_type: &FrameType) -> [f32; 4] {
+        match frame_type {
+            FrameType::Simple => self.frame_tint,
+            FrameType::Interactable => self.window_tint,
+            FrameType::SelectionBox => self.selection_tint
+        }
+    }
+}
 
 #[derive(Debug)]
 enum FrameType {
@@ -31,11 +110,11 @@ pub struct SliderInteraction {
 }
 
 impl FrameType {
-    fn get_texture_origin(&self) -> (u32, u32) {
+    fn get_texture_origin(&self, cell_size: u32) -> (u32, u32) {
         match self {
             Self::Simple => (0, 0),
-            Self::Interactable => (48, 0),
-            Self::SelectionBox => (0, 48)
+            Self::Interactable => (cell_size * 3, 0),
+            Self::SelectionBox => (0, cell_size * 3)
         }
     }
 }
@@ -63,7 +142,8 @@ struct TextureLabel {
     w: u32,
     h: u32,
     tx: u32, ty: u32, tw: u32, th: u32,
-    texture: String
+    texture: String,
+    tint: [f32; 4]
 }
 
 #[derive(Debug)]
@@ -95,12 +175,16 @@ struct UINode {
     draw: ElementType,
     focus: u32,
     x: i32,
-    y: i32
+    y: i32,
+    /// Offset within a layout slot, the layout advances past this node when it is popped
+    layout_offset: Option<(i32, i32)>,
+    /// Padding of a frame that resizes to fit its children when it is popped
+    auto_size: Option<u32>
 }
 
 impl UINode {
     pub fn root() -> Self {
-        Self { children: Vec::new(), clip: (0, 0, 4096, 4096), draw: ElementType::None, focus: 0, x: 0, y: 0 }
+        Self { children: Vec::new(), clip: (0, 0, 4096, 4096), draw: ElementType::None, focus: 0, x: 0, y: 0, layout_offset: None, auto_size: None }
     }
 
     /// Recursively sort all UI nodes
@@ -113,6 +197,45 @@ impl UINode {
     }
 }
 
+/// Places elements one after another, see `UI::begin_column` and `UI::begin_row`
+struct Layout {
+    vertical: bool,
+    origin: (i32, i32),
+    /// Distance covered along the stack so far
+    length: i32,
+    /// Size of the largest element across the stack
+    breadth: u32,
+    spacing: i32,
+    /// Elements are only placed by the layout when they are added at this depth
+    depth: usize
+}
+
+impl Layout {
+    fn slot(&self) -> (i32, i32) {
+        if self.vertical {
+            (self.origin.0, self.origin.1 + self.length)
+        } else {
+            (self.origin.0 + self.length, self.origin.1)
+        }
+    }
+
+    /// Move past an element that was placed `offset` into its slot
+    fn advance(&mut self, offset: (i32, i32), size: (u32, u32)) {
+        let (along, across) = if self.vertical {
+            (offset.1 + size.1 as i32, offset.0 + size.0 as i32)
+        } else {
+            (offset.0 + size.0 as i32, offset.1 + size.1 as i32)
+        };
+        self.length += along + self.spacing;
+        self.breadth = self.breadth.max(across.max(0) as u32);
+    }
+
+    fn size(&self) -> (u32, u32) {
+        let length = (self.length - self.spacing).max(0) as u32;
+        if self.vertical { (self.breadth, length) } else { (length, self.breadth) }
+    }
+}
+
 pub struct UI {
     tree: NodePtr,
     current_node: NodePtr,
@@ -120,10 +243,21 @@ pub struct UI {
     last_modified: NodePtr,
     /// Dummy vao, the UI doesn't use any vertex data
     pub vao: NativeVertexArray,
+    /// Size of the screen in UI units, the window size divided by `scale`
     pub screen_size: (u32, u32),
+    /// Size of the window in pixels
+    physical_size: (u32, u32),
+    /// How many pixels one UI unit takes up
+    pub scale: f32,
+    pub theme: UITheme,
     pub mouse_captured: bool,
+    /// A text box has keyboard focus this frame
+    pub keyboard_captured: bool,
+    /// Screen position of the focused text box's cursor, used to place the IME window
+    pub text_cursor: Option<(i32, i32)>,
     pub inc_focus: u32,
-    current_global_origin: (i32, i32)
+    current_global_origin: (i32, i32),
+    layouts: Vec<Layout>
 }
 
 impl UI {
@@ -136,11 +270,46 @@ impl UI {
             current_node: tree.clone(),
             parent_nodes: Vec::new(),
             screen_size: (640 * 2, 480 * 2),
+            physical_size: (640 * 2, 480 * 2),
+            scale: 1.0,
+            theme: UITheme::default(),
             mouse_captured: false,
+            keyboard_captured: false,
+            text_cursor: None,
             inc_focus: 0,
             last_modified: tree.clone(),
-            current_global_origin: (0, 0)
+            current_global_origin: (0, 0),
+            layouts: Vec::new()
+        }
+    }
+
+    /// Switch to `theme`, loading any textures it needs
+    pub unsafe fn set_theme(&mut self, theme: UITheme, textures: &mut TextureBank, gl: &glow::Context) -> Result<(), String> {
+        for texture in [&theme.frame_texture, &theme.slider_texture, &theme.font_texture] {
+            textures.load_by_name(texture, gl).map_err(|e| format!("failed to load theme texture {}: {}", texture, e))?;
         }
+        self.theme = theme;
+        Ok(())
+    }
+
+    /// Call when the window is resized with its new size in pixels
+    pub fn resize(&mut self, width: u32, height: u32) {
+        self.physical_size = (width, height);
+        self.update_screen_size();
+    }
+
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+        self.update_screen_size();
+    }
+
+    fn update_screen_size(&mut self) {
+        self.screen_size = ((self.physical_size.0 as f32 / self.scale) as u32, (self.physical_size.1 as f32 / self.scale) as u32);
+    }
+
+    /// Mouse position in UI units
+    pub fn mouse_pos(&self, input: &Input) -> (f64, f64) {
+        (input.mouse_pos.0 / self.scale as f64, input.mouse_pos.1 / self.scale as f64)
     }
 
     pub fn begin(&mut self) {
@@ -149,16 +318,39 @@ impl UI {
         self.last_modified = self.tree.clone();
         self.parent_nodes.clear();
         self.mouse_captured = false;
+        self.keyboard_captured = false;
+        self.text_cursor = None;
         self.current_global_origin = (0, 0);
+        self.layouts.clear();
+    }
+
+    /// The layout that places elements added to the current node, if any
+    fn active_layout(&mut self) -> Option<&mut Layout> {
+        let depth = self.parent_nodes.len();
+        self.layouts.last_mut().filter(|layout| layout.depth == depth)
+    }
+
+    /// Turn a position into a layout slot position, returns the original offset if a layout was used
+    fn place(&mut self, element: &mut UINode) -> Option<(i32, i32)> {
+        let slot = self.active_layout()?.slot();
+        let offset = (element.x, element.y);
+        element.x += slot.0;
+        element.y += slot.1;
+        Some(offset)
     }
 
-    fn add_child(&mut self, element: UINode) {
+    fn add_child(&mut self, mut element: UINode) {
+        if let Some(offset) = self.place(&mut element) {
+            let size = self.element_size(&element.draw);
+            self.active_layout().unwrap().advance(offset, size);
+        }
         let element = node_ptr(element);
         self.current_node.as_ref().borrow_mut().children.push(element.clone());
         self.last_modified = element;
     }
 
-    fn add_child_as_current(&mut self, element: UINode) {
+    fn add_child_as_current(&mut self, mut element: UINode) {
+        element.layout_offset = self.place(&mut element);
         self.current_global_origin.0 += element.x;
         self.current_global_origin.1 += element.y;
         let element = node_ptr(element);
@@ -172,16 +364,21 @@ impl UI {
         if title.is_empty() {
             self.add_child_as_current(UINode {
                 children: Vec::new(),
-                clip: (1, 1, w - 2, h - 2),
+                layout_offset: None,
+                auto_size: None,
+                clip: (1, 1, w.saturating_sub(2), h.saturating_sub(2)),
                 draw: ElementType::NineCell(NineCell {
                     x: 0, y: 0, w, h, frame_type: frame
                 }),
                 focus: self.inc_focus, x, y
             });
         } else {
+            let bar = self.theme.cell_size;
             self.add_child_as_current(UINode {
                 children: Vec::new(),
-                clip: (1, 17, w - 2, h - 18),
+                layout_offset: None,
+                auto_size: None,
+                clip: (1, bar as i32 + 1, w.saturating_sub(2), h.saturating_sub(bar + 2)),
                 draw: ElementType::TitledNineCell(NineCell {
                     x: 0, y: 0, w, h, frame_type: frame
                 }, title.to_string()),
@@ -200,6 +397,29 @@ impl UI {
         self._frame(x, y, w, h, FrameType::SelectionBox, "");
     }
 
+    /// Size an element takes up on screen
+    fn element_size(&self, element: &ElementType) -> (u32, u32) {
+        match element {
+            ElementType::NineCell(nine_cell) | ElementType::TitledNineCell(nine_cell, _) => (nine_cell.w, nine_cell.h),
+            ElementType::TextLabel(text) => self.get_text_render_size(&text.message),
+            ElementType::TextureLabel(label) => (label.w, label.h),
+            ElementType::Slider(slider) => if slider.vertical { (8, slider.size) } else { (slider.size, 8) },
+            ElementType::None => (0, 0)
+        }
+    }
+
+    /// Right and bottom edges of everything inserted into the current frame so far, relative to the frame
+    pub fn content_extent(&self) -> (i32, i32) {
+        let mut extent = (0, 0);
+        for child in self.current_node.borrow().children.iter() {
+            let child = child.borrow();
+            let (w, h) = self.element_size(&child.draw);
+            extent.0 = extent.0.max(child.x + w as i32);
+            extent.1 = extent.1.max(child.y + h as i32);
+        }
+        extent
+    }
+
     /// Set the last inserted UI object's focus<br>Higher focus values order objects above others
     pub fn set_focus(&mut self, focus: u32) {
         self.last_modified.borrow_mut().focus = focus;
@@ -219,21 +439,22 @@ impl UI {
 
     pub fn mouse_in_frame(&self, mpx: i32, mpy: i32) -> bool {
         let clip = self.global_clip_rect();
-        mpx > clip.0 && mpx < clip.0 + clip.2 as i32 && mpy > clip.1 - 16 && mpy < clip.1 + clip.3 as i32 + 16
+        let bar = self.theme.cell_size as i32;
+        mpx > clip.0 && mpx < clip.0 + clip.2 as i32 && mpy > clip.1 - bar && mpy < clip.1 + clip.3 as i32 + bar
     }
 
     pub fn interactable_frame(&mut self, input: &Input, title: &str, x: i32, y: i32, w: u32, h: u32) -> Option<FrameInteraction> {
-        let mpx = input.mouse_pos.0 as i32;
-        let mpy = input.mouse_pos.1 as i32;
-        let gx = x + self.current_global_origin.0;
-        let gy = y + self.current_global_origin.1;
-        let mouse_within_x = mpx > gx + w as i32 - 16 && mpx < gx + w as i32 && mpy > gy && mpy < gy + 16;
-        let mouse_within_bar = mpx > gx && mpx < gx + w as i32 && mpy > gy && mpy < gy + 16;
-        let mouse_within_body = mpx > gx && mpx < gx + w as i32 && mpy > gy + 16 && mpy < gy + h as i32;
-        let mouse_within_resize = mpx > gx + w as i32 - 16 && mpx < gx + w as i32 && mpy > gy + h as i32 - 16 && mpy < gy + h as i32;
-
         self._frame(x, y, w, h, FrameType::Interactable, title);
 
+        let (mpx, mpy) = self.mouse_pos(input);
+        let (mpx, mpy) = (mpx as i32, mpy as i32);
+        let (gx, gy) = self.current_global_origin;
+        let bar = self.theme.cell_size as i32;
+        let mouse_within_x = mpx > gx + w as i32 - bar && mpx < gx + w as i32 && mpy > gy && mpy < gy + bar;
+        let mouse_within_bar = mpx > gx && mpx < gx + w as i32 && mpy > gy && mpy < gy + bar;
+        let mouse_within_body = mpx > gx && mpx < gx + w as i32 && mpy > gy + bar && mpy < gy + h as i32;
+        let mouse_within_resize = mpx > gx + w as i32 - bar && mpx < gx + w as i32 && mpy > gy + h as i32 - bar && mpy < gy + h as i32;
+
         if mouse_within_body || mouse_within_bar {
             self.mouse_captured = true;
         }
@@ -262,6 +483,8 @@ impl UI {
     pub fn text(&mut self, x: i32, y: i32, message: &str) {
         self.add_child(UINode {
             children: Vec::new(),
+            layout_offset: None,
+            auto_size: None,
             clip: (0, 0, 100, 100),
             draw: ElementType::TextLabel(TextLabel {
                 message: message.to_string(), x: 0, y: 0
@@ -274,21 +497,32 @@ impl UI {
     pub fn image(&mut self, x: i32, y: i32, w: u32, h: u32, tx: (u32, u32), tx_size: (u32, u32), texture: &str) {
         self.add_child(UINode {
             children: Vec::new(),
+            layout_offset: None,
+            auto_size: None,
             clip: (0, 0, w, h),
             draw: ElementType::TextureLabel(TextureLabel {
-                x: 0, y: 0, w, h, tx: tx.0, ty: tx.1, th: tx_size.1, tw: tx_size.0, texture: texture.to_string()
+                x: 0, y: 0, w, h, tx: tx.0, ty: tx.1, th: tx_size.1, tw: tx_size.0, texture: texture.to_string(), tint: WHITE
             }),
             focus: self.inc_focus, x, y
         });
         self.inc_focus += 1;
     }
 
+    /// Solid rectangle, `color` can be translucent
+    pub fn fill(&mut self, x: i32, y: i32, w: u32, h: u32, color: [f32; 4]) {
+        self.image(x, y, w, h, (0, 0), (1, 1), "magic_pixel");
+        if let ElementType::TextureLabel(label) = &mut self.last_modified.borrow_mut().draw {
+            label.tint = color;
+        }
+    }
+
     pub fn image_button(&mut self, input: &Input, x: i32, y: i32, w: u32, h: u32, tx: (u32, u32), tx_size: (u32, u32), texture: &str) -> bool {
         self.image(x, y, w, h, tx, tx_size, texture);
-        let mpx = input.mouse_pos.0 as i32;
-        let mpy = input.mouse_pos.1 as i32;
-        let gx = x + self.current_global_origin.0;
-        let gy = y + self.current_global_origin.1;
+        let (mpx, mpy) = self.mouse_pos(input);
+        let (mpx, mpy) = (mpx as i32, mpy as i32);
+        // Read the position back in case a layout moved the image
+        let gx = self.last_modified.borrow().x + self.current_global_origin.0;
+        let gy = self.last_modified.borrow().y + self.current_global_origin.1;
         if self.mouse_in_clip_rect(mpx, mpy) && mpx > gx && mpx < gx + w as i32 && mpy > gy && mpy < gy + h as i32 {
             // println!("in");
             self.mouse_captured = true;
@@ -300,9 +534,35 @@ impl UI {
         false
     }
 
+    /// Single line text box, `cursor` is a character index and is only given while the box has keyboard focus<br>
+    /// Returns true if the box was clicked
+    pub fn text_box(&mut self, input: &Input, x: i32, y: i32, w: u32, text: &str, cursor: Option<usize>) -> bool {
+        self.frame(x, y, w, 20);
+        let clicked = self.image_button(input, 1, 1, w - 2, 18, (0, 0), (1, 1), "evil_pixel");
+
+        // Scroll long text so the cursor stays visible
+        let glyph_width = self.theme.glyph_size.0;
+        let visible = (w.saturating_sub(8) / glyph_width) as usize;
+        let first = cursor.map(|cursor| cursor.saturating_sub(visible)).unwrap_or(0);
+        let shown: String = text.chars().skip(first).take(visible).collect();
+        self.text(4, 5, &shown);
+
+        if let Some(cursor) = cursor {
+            let cursor_x = 4 + (cursor - first) as i32 * glyph_width as i32;
+            self.text(cursor_x, 7, "_");
+            self.keyboard_captured = true;
+            self.text_cursor = Some((self.current_global_origin.0 + cursor_x, self.current_global_origin.1 + 5));
+        }
+        self.pop();
+
+        clicked
+    }
+
     fn _slider(&mut self, input: &Input, x: i32, y: i32, size: u32, progress: u32, vertical: bool) -> SliderInteraction {
         self.add_child(UINode {
             children: Vec::new(),
+            layout_offset: None,
+            auto_size: None,
             clip: (0, 0, if vertical { 32 } else { size }, if vertical { size } else { 32 }),
             draw: ElementType::Slider(Slider {
                 x: 0, y: 0, size, slider_pos: progress, vertical
@@ -311,10 +571,10 @@ impl UI {
         });
         self.inc_focus += 1;
 
-        let mpx = input.mouse_pos.0 as i32;
-        let mpy = input.mouse_pos.1 as i32;
-        let mut gx = x + self.current_global_origin.0;
-        let mut gy = y + self.current_global_origin.1;
+        let (mpx, mpy) = self.mouse_pos(input);
+        let (mpx, mpy) = (mpx as i32, mpy as i32);
+        let mut gx = self.last_modified.borrow().x + self.current_global_origin.0;
+        let mut gy = self.last_modified.borrow().y + self.current_global_origin.1;
 
         let progress = if vertical { (mpy - gy).max(0).min(size as i32) as u32 } else { (mpx - gx).max(0).min(size as i32) as u32 };
 
@@ -352,13 +612,90 @@ impl UI {
 
     pub fn pop(&mut self) {
         assert!(!self.parent_nodes.is_empty(), "pop() was called on the root node");
-        self.current_global_origin.0 -= self.current_node.borrow().x;
-        self.current_global_origin.1 -= self.current_node.borrow().y;
+        let auto_size = self.current_node.borrow().auto_size;
+        if let Some(padding) = auto_size {
+            let extent = self.content_extent();
+            let size = ((extent.0 + padding as i32).max(2) as u32, (extent.1 + padding as i32).max(2) as u32);
+            let mut node = self.current_node.borrow_mut();
+            if let ElementType::NineCell(nine_cell) = &mut node.draw {
+                (nine_cell.w, nine_cell.h) = size;
+            }
+            node.clip = (1, 1, size.0 - 2, size.1 - 2);
+        }
+
+        let popped = self.current_node.clone();
+        self.current_global_origin.0 -= popped.borrow().x;
+        self.current_global_origin.1 -= popped.borrow().y;
         self.current_node = self.parent_nodes.pop().unwrap();
+
+        let popped = popped.borrow();
+        if let Some(offset) = popped.layout_offset {
+            let size = self.element_size(&popped.draw);
+            if let Some(layout) = self.active_layout() {
+                layout.advance(offset, size);
+            }
+        }
+    }
+
+    /// Stack the following elements top to bottom, `spacing` apart<br>
+    /// Positions given to elements in a stack are offsets into their slot, stacks can be nested
+    pub fn begin_column(&mut self, x: i32, y: i32, spacing: i32) {
+        self.begin_layout(x, y, spacing, true);
+    }
+
+    /// Stack the following elements left to right, `spacing` apart
+    pub fn begin_row(&mut self, x: i32, y: i32, spacing: i32) {
+        self.begin_layout(x, y, spacing, false);
+    }
+
+    fn begin_layout(&mut self, x: i32, y: i32, spacing: i32, vertical: bool) {
+        let origin = self.active_layout().map_or((x, y), |layout| {
+            let slot = layout.slot();
+            (slot.0 + x, slot.1 + y)
+        });
+        self.layouts.push(Layout { vertical, origin, length: 0, breadth: 0, spacing, depth: self.parent_nodes.len() });
+    }
+
+    /// Finish the innermost stack, returns its size
+    pub fn end_layout(&mut self) -> (u32, u32) {
+        let layout = self.layouts.pop().expect("end_layout() was called without a layout");
+        let size = layout.size();
+        if let Some(parent) = self.active_layout() {
+            let slot = parent.slot();
+            parent.advance((layout.origin.0 - slot.0, layout.origin.1 - slot.1), size);
+        }
+        size
+    }
+
+    /// Leave a gap in the current stack
+    pub fn space(&mut self, amount: i32) {
+        if let Some(layout) = self.active_layout() {
+            layout.length += amount;
+        }
+    }
+
+    /// Frame that grows to fit everything inserted into it before it is popped, plus `padding`
+    pub fn auto_frame(&mut self, x: i32, y: i32, padding: u32) {
+        self._frame(x, y, 0, 0, FrameType::Simple, "");
+        let mut node = self.current_node.borrow_mut();
+        node.auto_size = Some(padding);
+        // Nothing is clipped until the final size is known
+        node.clip = (1, 1, 4096, 4096);
+    }
+
+    /// Button sized to fit its label
+    pub fn text_button(&mut self, input: &Input, x: i32, y: i32, label: &str) -> bool {
+        let (w, h) = self.get_text_render_size(label);
+        self.frame(x, y, w + 10, h + 10);
+            let clicked = self.image_button(input, 1, 1, w + 8, h + 8, (0, 0), (1, 1), "evil_pixel");
+            self.text(5, 5, label);
+        self.pop();
+        clicked
     }
 
     unsafe fn render_texture_label(label: &TextureLabel, local_offset: (i32, i32), textures: &TextureBank, ui_program: &mut Program, gl: &glow::Context) {
         let texture = textures.get(&label.texture).unwrap();
+        ui_program.uniform_4f32("tint", Vector4::from(label.tint), gl);
         gl.bind_texture(glow::TEXTURE_2D, Some(texture.inner));
         ui_program.uniform_2f32("texSize", vec2(texture.width as f32, texture.height as f32), gl);
 
@@ -369,41 +706,47 @@ impl UI {
         gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
     }
 
-    unsafe fn render_nine_cell(nine_cell: &NineCell, local_offset: (i32, i32), textures: &TextureBank, ui_program: &mut Program, gl: &glow::Context) {
-        let frame_texture = textures.get("ui_frame").unwrap();
+    unsafe fn render_nine_cell(&self, nine_cell: &NineCell, local_offset: (i32, i32), textures: &TextureBank, ui_program: &mut Program, gl: &glow::Context) {
+        let cell = self.theme.cell_size as f32;
+        let frame_texture = textures.get(&self.theme.frame_texture).unwrap();
         gl.bind_texture(glow::TEXTURE_2D, Some(frame_texture.inner));
         ui_program.uniform_2f32("texSize", vec2(frame_texture.width as f32, frame_texture.height as f32), gl);
-        ui_program.uniform_2f32("textureScale", vec2(16.0, 16.0), gl);
+        ui_program.uniform_2f32("textureScale", vec2(cell, cell), gl);
+        ui_program.uniform_4f32("tint", Vector4::from(self.theme.frame_tint(&nine_cell.frame_type)), gl);
         
         let x = (nine_cell.x + local_offset.0) as f32;
         let y = (nine_cell.y + local_offset.1) as f32;
-        let width = nine_cell.w.max(33) as f32 - 32.0;
-        let height = nine_cell.h.max(33) as f32 - 32.0;
+        let width = (nine_cell.w as f32).max(cell * 2.0 + 1.0) - cell * 2.0;
+        let height = (nine_cell.h as f32).max(cell * 2.0 + 1.0) - cell * 2.0;
 
         // Hire me
-        let tx_origin = nine_cell.frame_type.get_texture_origin();
+        let tx_origin = nine_cell.frame_type.get_texture_origin(self.theme.cell_size);
         let mut ty = tx_origin.1 as f32;
-        for (y, h) in [(y, 16.0), (y + 16.0, height), (y + 16.0 + height, 16.0)] {
+        for (y, h) in [(y, cell), (y + cell, height), (y + cell + height, cell)] {
             let mut tx = tx_origin.0 as f32;
-            for (x, w) in [(x, 16.0), (x + 16.0, width), (x + 16.0 + width, 16.0)] {
+            for (x, w) in [(x, cell), (x + cell, width), (x + cell + width, cell)] {
                 ui_program.uniform_2f32("pos", vec2(x, y), gl);
                 ui_program.uniform_2f32("scale", vec2(w, h), gl);
                 ui_program.uniform_2f32("texturePos", vec2(tx, ty), gl);
 
                 gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
 
-                tx += 16.0;
+                tx += cell;
             }
-            ty += 16.0;
+            ty += cell;
         }
     }
 
-    unsafe fn render_text_label(text: &TextLabel, local_offset: (i32, i32), textures: &TextureBank, ui_program: &mut Program, gl: &glow::Context) {
-        let font_texture = textures.get("font").unwrap();
+    unsafe fn render_text_label(&self, text: &TextLabel, local_offset: (i32, i32), textures: &TextureBank, ui_program: &mut Program, gl: &glow::Context) {
+        let theme = &self.theme;
+        let (glyph_width, glyph_height) = (theme.glyph_size.0 as i32, theme.glyph_size.1 as i32);
+        let font_texture = textures.get(&theme.font_texture).unwrap();
         gl.bind_texture(glow::TEXTURE_2D, Some(font_texture.inner));
         ui_program.uniform_2f32("texSize", vec2(font_texture.width as f32, font_texture.height as f32), gl);
-        ui_program.uniform_2f32("scale", vec2(6.0, 9.5), gl);
-        ui_program.uniform_2f32("textureScale", vec2(6.0, 9.5), gl);
+        // Half a pixel is cut off the bottom so the next row of glyphs doesn't bleed in
+        ui_program.uniform_2f32("scale", vec2(glyph_width as f32, glyph_height as f32 - 0.5), gl);
+        ui_program.uniform_2f32("textureScale", vec2(glyph_width as f32, glyph_height as f32 - 0.5), gl);
+        ui_program.uniform_4f32("tint", Vector4::from(theme.text_tint), gl);
 
         let mut x = text.x + local_offset.0;
         let mut y = text.y + local_offset.1;
@@ -411,40 +754,41 @@ impl UI {
         for char in text.message.chars() {
             if char == '\n' {
                 x = text.x + local_offset.0;
-                y += 10;
+                y += glyph_height;
                 continue;
             } else if char == ' ' {
-                x += 6;
+                x += glyph_width;
                 continue;
             }
 
-            let char_pos = if let Some(index) = FONT_CHARS.find(char) {
-                (index % FONT_WIDTH, index / FONT_WIDTH)
+            let char_pos = if let Some(index) = theme.font_chars.chars().position(|c| c == char) {
+                (index % theme.font_columns, index / theme.font_columns)
             } else {
                 (7, 6)
             };
 
             ui_program.uniform_2f32("pos", vec2(x as f32, y as f32), gl);
-            ui_program.uniform_2f32("texturePos", vec2(char_pos.0 as f32 * 6.0, char_pos.1 as f32 * 10.0), gl);
+            ui_program.uniform_2f32("texturePos", vec2((char_pos.0 as i32 * glyph_width) as f32, (char_pos.1 as i32 * glyph_height) as f32), gl);
             gl.draw_arrays(glow::TRIANGLE_STRIP, 0, 4);
 
-            x += 6;
+            x += glyph_width;
         }   
     }
 
-    pub fn get_text_render_size(text: &str) -> (u32, u32) {
+    pub fn get_text_render_size(&self, text: &str) -> (u32, u32) {
+        let (glyph_width, glyph_height) = self.theme.glyph_size;
         let mut width = 0;
         let mut cur_line_width = 0;
-        let mut height = 10;
+        let mut height = glyph_height;
 
         for char in text.chars() {
             if char == '\n' {
-                height += 10;
+                height += glyph_height;
                 cur_line_width = 0;
                 continue;
             }
 
-            cur_line_width += 6;
+            cur_line_width += glyph_width;
             if cur_line_width > width {
                 width = cur_line_width;
             }
@@ -453,11 +797,12 @@ impl UI {
         (width, height)
     }
 
-    unsafe fn render_slider(slider: &Slider, local_offset: (i32, i32), textures: &TextureBank, ui_program: &mut Program, gl: &glow::Context) {
+    unsafe fn render_slider(&self, slider: &Slider, local_offset: (i32, i32), textures: &TextureBank, ui_program: &mut Program, gl: &glow::Context) {
         let x = slider.x + local_offset.0;
         let y = slider.y + local_offset.1;
 
-        let slider_texture = textures.get("slider").unwrap();
+        let slider_texture = textures.get(&self.theme.slider_texture).unwrap();
+        ui_program.uniform_4f32("tint", Vector4::from(self.theme.frame_tint), gl);
         gl.bind_texture(glow::TEXTURE_2D, Some(slider_texture.inner));
         ui_program.uniform_2f32("texSize", vec2(slider_texture.width as f32, slider_texture.height as f32), gl);
 
@@ -511,26 +856,32 @@ impl UI {
 
     unsafe fn render_node(&self, node: &NodePtr, local_offset: (i32, i32), clip: (i32, i32, u32, u32), textures: &TextureBank, ui_program: &mut Program, gl: &glow::Context) {
         let element = node.borrow();
-        gl.scissor(clip.0, self.screen_size.1 as i32 - clip.1 - clip.3 as i32, clip.2 as i32, clip.3 as i32);
+        // Clip rects are in UI units but the scissor box is in pixels
+        gl.scissor(
+            (clip.0 as f32 * self.scale) as i32,
+            self.physical_size.1 as i32 - ((clip.1 + clip.3 as i32) as f32 * self.scale) as i32,
+            (clip.2 as f32 * self.scale).ceil() as i32,
+            (clip.3 as f32 * self.scale).ceil() as i32
+        );
         match &element.draw {
             ElementType::TextureLabel(label) => {
                 Self::render_texture_label(label, local_offset, textures, ui_program, gl);
             },
             ElementType::NineCell(nine_cell) => {
-                Self::render_nine_cell(nine_cell, local_offset, textures, ui_program, gl);
+                self.render_nine_cell(nine_cell, local_offset, textures, ui_program, gl);
             },
             ElementType::TextLabel(text) => {
-                Self::render_text_label(text, local_offset, textures, ui_program, gl);
+                self.render_text_label(text, local_offset, textures, ui_program, gl);
             },
             ElementType::TitledNineCell(nine_cell, title) => {
-                Self::render_nine_cell(nine_cell, local_offset, textures, ui_program, gl);
-                Self::render_text_label(&TextLabel {
+                self.render_nine_cell(nine_cell, local_offset, textures, ui_program, gl);
+                self.render_text_label(&TextLabel {
                     x: nine_cell.x + 4, y: nine_cell.y + 2,
                     message: title.to_owned()
                 }, local_offset, textures, ui_program, gl);
             },
             ElementType::Slider(slider) => {
-                Self::render_slider(slider, local_offset, textures, ui_program, gl);
+                self.render_slider(slider, local_offset, textures, ui_program, gl);
             }
             ElementType::None => ()
         }
@@ -555,6 +906,8 @@ impl UI {
         gl.disable(glow::CULL_FACE);
         gl.disable(glow::DEPTH_TEST);
         gl.enable(glow::SCISSOR_TEST);
+        gl.enable(glow::BLEND);
+        gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
 
         let ui_program = programs.get_mut("ui").unwrap();
 
@@ -572,32 +925,67 @@ impl UI {
         gl.enable(glow::CULL_FACE);
         gl.enable(glow::DEPTH_TEST);
         gl.disable(glow::SCISSOR_TEST);
+        gl.disable(glow::BLEND);
     }
 }
 
 pub mod implement {
     use core::f32;
-    use std::{ffi::OsString, fs::File, io::{Read, Write}};
+    use std::{ffi::OsString, future::Future, mem, path::PathBuf, pin::Pin, slice, sync::mpsc::{self, Receiver, TryRecvError}, thread};
 
-    use cgmath::{vec3, Matrix4, Vector3, Zero};
-    use rfd::FileDialog;
-    use winit::event::MouseButton;
+    use cgmath::{vec3, EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Vector3, Zero};
+    use rfd::{AsyncFileDialog, FileHandle};
+    use winit::{event::MouseButton, keyboard::{Key, NamedKey}};
 
-    use crate::{common::{self, round_to}, input::Input, mesh::{flags, MeshBank}, render::PointLight, shader::ProgramBank, texture::TextureBank, ui::{FrameInteraction, SliderInteraction, FONT_CHARS, UI}, world::{Model, Renderable, World}};
+    use crate::{audio::{SoundEmitter, VolumeSettings}, budget::Budget, button::Button, collision::PhysicalProperties, common::{self, round_to}, component::{Component, Door, LightAnimation, Rotator, SpotLightSource, TargetAction, Trigger, TriggerType}, connection::Connection, diff, effects::{FogEffect, KernelEffect, SsaoEffect, KERNEL_PRESETS, MSAA_SAMPLE_COUNTS}, events::EventKind, export, feed::{self, CameraFeed, MAX_FEED_RESOLUTION}, generate, ghost::{GhostRun, GHOST_DIRECTORY}, goal::Goal, post, reference::{self, Measurement}, impostor::{DrawDistance, FarMode}, mover::{Mover, MoverMode}, config::{EditorPreferences, LightPreset, UserConfig}, input::{Action, Input}, mesh::{flags, BrushFaces, FaceUV, MeshBank}, notes::Note, objective::{Objective, ObjectiveMarker}, reflection::ReflectionPlane, rules, render::{CameraControlScheme, OrthoView, PointLight}, save::{self, LevelData, BINARY_LEVEL_EXTENSION}, session::{self, EditKind}, shadow::MAX_SHADOW_LIGHTS, stopwatch::StopwatchState, shader::{ProgramBank, UniformValue}, texture::TextureBank, traversal::TraversalProblem, ui::{FrameInteraction, SliderInteraction, UITheme, FONT_CHARS, UI}, visibility, world::{Model, Renderable, Selection, TextLabel, World}};
 
     const MATERIAL_FRAME_SIZE: u32 = 100;
+    /// Steps of the face alignment steppers, offsets are in texture repeats
+    const FACE_OFFSET_STEP: f32 = 0.125;
+    const FACE_ROTATION_STEP: f32 = 15.0;
+    const FACE_SCALE_STEP: f32 = 0.25;
+    /// Range of the snapping increment, it is halved and doubled to stay on powers of two
+    const MIN_INCREMENT: f32 = 1.0 / 64.0;
+    const MAX_INCREMENT: f32 = 16.0;
+    /// Objective markers stay this far inside the edge of the screen
+    const OBJECTIVE_MARKER_MARGIN: i32 = 24;
+    const OBJECTIVE_MARKER_COLOR: [f32; 4] = [1.0, 0.85, 0.2, 1.0];
+    /// Health bar in the bottom right corner while playing
+    const HEALTH_BAR_SIZE: (u32, u32) = (120, 8);
+    const HEALTH_BAR_COLOR: [f32; 4] = [0.85, 0.1, 0.1, 1.0];
+    const HEALTH_BAR_BACK_COLOR: [f32; 4] = [0.1, 0.1, 0.1, 0.6];
+    const STAMINA_BAR_COLOR: [f32; 4] = [0.2, 0.75, 0.3, 1.0];
+    const STAMINA_BAR_TIRED_COLOR: [f32; 4] = [0.4, 0.45, 0.4, 1.0];
+    /// Shown in the middle of the screen once a goal is reached
+    const COMPLETION_SIZE: (u32, u32) = (260, 118);
+    /// Empty space left after the last element of a scrolled window
+    const SCROLL_PADDING: i32 = 24;
+    /// How far back the camera is put when going to a note
+    const NOTE_VIEW_DISTANCE: f32 = 3.0;
+    /// Characters of a note shown in the Notes window
+    const NOTE_PREVIEW_LENGTH: usize = 40;
+    /// Characters per line of load errors in the Save/Load window
+    const LEVEL_ERROR_WIDTH: usize = 36;
+    /// Newest events listed in the Events window and over the game
+    const EVENTS_SHOWN: usize = 100;
+    const EVENTS_OVERLAY_SHOWN: usize = 12;
 
     pub const USER_AMBIENT_STRENGTH: f32 = 0.3;
     pub const USER_SPECULAR_STRENGTH: f32 = 0.7;
     pub const USER_SPECULAR_BLEND: f32 = 0.75;
     const USER_RADIUS_FACTOR: f32 = 1.0 / 5.0;
+    /// Spot light angle sliders go up to 90 degrees
+    const SPOT_ANGLE_FACTOR: f32 = 90.0 / 200.0;
 
     pub struct VicepticaUI {
         pub inner: UI,
         editor: EditorModeUI,
         play: PlayModeUI,
         /// true - play mode, false - editor
-        pub play_mode: bool
+        pub play_mode: bool,
+        pub config: UserConfig,
+        /// UI of the panel window while it is open, see `window::PanelWindow`
+        pub panels: Option<UI>
     }
 
     #[derive(PartialEq)]
@@ -606,7 +994,24 @@ pub mod implement {
         MaterialPicker,
         LightEditor,
         SaveLoad,
-        Environment
+        Environment,
+        Inspector,
+        LevelProperties,
+        LightPalette,
+        Audio,
+        Preferences,
+        LevelDiff,
+        Notes,
+        SpotLight,
+        MaterialProblems,
+        Performance,
+        Outliner,
+        Ghosts,
+        Events,
+        Connections,
+        Session,
+        Effects,
+        ScaleReferences
     }
 
     impl EditorWindowType {
@@ -616,23 +1021,126 @@ pub mod implement {
                 Self::MaterialPicker => "Materials",
                 Self::LightEditor => "Light Properties",
                 Self::SaveLoad => "Save and Load",
-                Self::Environment => "Environment Properties"
+                Self::Environment => "Environment Properties",
+                Self::Inspector => "Inspector",
+                Self::LevelProperties => "Level Properties",
+                Self::LightPalette => "Light Palette",
+                Self::Audio => "Audio",
+                Self::Preferences => "Preferences",
+                Self::LevelDiff => "Compare Levels",
+                Self::Notes => "Notes",
+                Self::SpotLight => "Spot Light",
+                Self::MaterialProblems => "Degraded Materials",
+                Self::Performance => "Performance Budgets",
+                Self::Outliner => "Outliner",
+                Self::Ghosts => "Ghosts",
+                Self::Events => "Events",
+                Self::Connections => "Connections",
+                Self::Session => "Session Statistics",
+                Self::Effects => "Effects",
+                Self::ScaleReferences => "Scale References"
+            }
+        }
+
+        /// Drawn in the panel window while it is open
+        fn detachable(&self) -> bool {
+            matches!(self, Self::MaterialPicker | Self::Inspector | Self::Notes | Self::LightPalette | Self::Outliner)
+        }
+    }
+
+    #[derive(Clone, Copy)]
+    enum MaterialSort {
+        Name,
+        Friction,
+        Control,
+        Jump
+    }
+
+    impl MaterialSort {
+        fn cycle(&self) -> Self {
+            match self {
+                Self::Name => Self::Friction,
+                Self::Friction => Self::Control,
+                Self::Control => Self::Jump,
+                Self::Jump => Self::Name
+            }
+        }
+
+        fn name(&self) -> &str {
+            match self {
+                Self::Name => "name",
+                Self::Friction => "friction",
+                Self::Control => "control",
+                Self::Jump => "jump"
+            }
+        }
+    }
+
+    /// Break `text` into lines of at most `width` characters, at spaces where possible
+    fn wrap_text(text: &str, width: usize) -> String {
+        let mut lines: Vec<String> = Vec::new();
+        let mut line = String::new();
+        for word in text.split(' ') {
+            if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+                lines.push(std::mem::take(&mut line));
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+            while line.chars().count() > width {
+                let split = line.char_indices().nth(width).unwrap().0;
+                let rest = line.split_off(split);
+                lines.push(std::mem::replace(&mut line, rest));
             }
         }
+        lines.push(line);
+        lines.join("\n")
+    }
+
+    /// `filter` is either part of the material's name or a comparison like `friction<0.5`, `jump>1` or `control=1`
+    fn material_matches(filter: &str, name: &str, properties: &PhysicalProperties) -> bool {
+        let filter = filter.trim();
+        if let Some(at) = filter.find(['<', '>', '=']) {
+            let value = match filter[..at].trim() {
+                "friction" => properties.friction,
+                "control" => properties.control,
+                "jump" => properties.jump,
+                _ => return name.contains(filter)
+            };
+            let Ok(target) = filter[at + 1..].trim().parse::<f32>() else { return true; };
+            return match &filter[at..at + 1] {
+                "<" => value < target,
+                ">" => value > target,
+                _ => (value - target).abs() < 0.001
+            };
+        }
+
+        name.contains(filter)
     }
 
     struct EditorWindow {
         window_type: EditorWindowType,
+        /// Position in the coordinates of the window it is drawn in
         position: (i32, i32),
+        /// Position in the other window, kept for when the window moves back
+        other_position: (i32, i32),
+        /// Drawn in the panel window
+        detached: bool,
         scale: (u32, u32),
         dragging: bool,
         scaling: bool,
         drag_origin: (i32, i32),
         scale_origin: (u32, u32),
+        /// Scroll position, both axes are zero or negative
         offset: (f32, f32),
-        scroll_max: f32,
+        /// How far the contents can be scrolled on each axis, measured from the last frame's contents
+        scroll_max: (f32, f32),
+        /// A scrollbar is being dragged, true for the vertical one
+        scrollbar_drag: Option<bool>,
         focus: u32,
-        sliders: SliderManager
+        sliders: SliderManager,
+        text_fields: TextFieldManager
     }
 
     impl EditorWindow {
@@ -641,15 +1149,35 @@ pub mod implement {
                 dragging: false,
                 scaling: false,
                 position: pos,
+                other_position: pos,
+                detached: false,
                 scale,
                 window_type,
                 drag_origin: (0, 0),
                 scale_origin: (0, 0),
                 offset: (0.0, 0.0),
-                scroll_max: 10000.0,
+                scroll_max: (0.0, 0.0),
+                scrollbar_drag: None,
                 focus: 0,
-                sliders: SliderManager::new()
+                sliders: SliderManager::new(),
+                text_fields: TextFieldManager::new()
+            }
+        }
+
+        /// Switch to the coordinates of the panel window when `detached`, or the main window otherwise<br>
+        /// Keeps the title bar inside `screen_size` so a window doesn't come back somewhere it can't be reached
+        fn set_detached(&mut self, detached: bool, screen_size: (u32, u32)) {
+            if self.detached == detached {
+                return;
             }
+            self.detached = detached;
+            std::mem::swap(&mut self.position, &mut self.other_position);
+            self.dragging = false;
+            self.scaling = false;
+            self.position = (
+                self.position.0.min(screen_size.0 as i32 - 48).max(48 - self.scale.0 as i32),
+                self.position.1.min(screen_size.1 as i32 - 24).max(0)
+            );
         }
 
         fn slider(&mut self, input: &Input, x: i32, y: i32, size: u32, ui: &mut UI) -> u32 {
@@ -663,6 +1191,92 @@ pub mod implement {
             self.sliders.add_slider(ui.vertical_slider(input, x, y, size, progress));
             size - progress
         }
+
+        /// Horizontal slider for `value` between `min` and `max`, returns the new value while it is being dragged<br>
+        /// Follows `value` the rest of the time, so sliders can come and go
+        fn value_slider(&mut self, input: &Input, x: i32, y: i32, size: u32, value: f32, min: f32, max: f32, ui: &mut UI) -> Option<f32> {
+            let index = self.sliders.current_slider;
+            let level = (((value - min) / (max - min)).clamp(0.0, 1.0) * size as f32) as u32;
+            if index >= self.sliders.slider_levels.len() {
+                self.sliders.slider_levels.push(level);
+                self.sliders.sliders.push(SliderInteraction { clicked: false, progress: level });
+            } else if self.sliders.active_slider != Some(index) {
+                self.sliders.slider_levels[index] = level;
+            }
+            let progress = self.slider(input, x, y, size, ui);
+            (self.sliders.active_slider == Some(index)).then(|| min + (max - min) * progress as f32 / size.max(1) as f32)
+        }
+
+        /// Measure the contents drawn so far and add scrollbars for any axis that doesn't fit<br>
+        /// Call last, before the window's frame is popped
+        fn scrollbars(&mut self, input: &Input, ui: &mut UI) {
+            let bar = ui.theme.cell_size;
+            let (ox, oy) = (self.offset.0 as i32, self.offset.1 as i32);
+            let extent = ui.content_extent();
+            self.scroll_max = (
+                (extent.0 - ox + SCROLL_PADDING - self.scale.0 as i32).max(0) as f32,
+                (extent.1 - oy + SCROLL_PADDING - self.scale.1 as i32).max(0) as f32
+            );
+            // Resizing the window can leave it scrolled past the end
+            self.offset = (self.offset.0.max(-self.scroll_max.0), self.offset.1.max(-self.scroll_max.1));
+
+            if input.get_mouse_button_released(MouseButton::Left) {
+                self.scrollbar_drag = None;
+            }
+
+            for vertical in [true, false] {
+                let max = if vertical { self.scroll_max.1 } else { self.scroll_max.0 };
+                if max <= 0.0 {
+                    continue;
+                }
+
+                // Stop short of the resize corner
+                let size = if vertical { self.scale.1.saturating_sub(bar * 2 + 4) } else { self.scale.0.saturating_sub(bar + 4) };
+                let offset = if vertical { &mut self.offset.1 } else { &mut self.offset.0 };
+                let progress = ((-*offset / max) * size as f32) as u32;
+                let interaction = if vertical {
+                    ui.vertical_slider(input, self.scale.0 as i32 - 12, bar as i32 + 2, size, progress)
+                } else {
+                    ui.slider(input, 2, self.scale.1 as i32 - 12, size, progress)
+                };
+
+                if interaction.clicked {
+                    self.scrollbar_drag = Some(vertical);
+                }
+                if self.scrollbar_drag == Some(vertical) {
+                    *offset = -(interaction.progress as f32 / size.max(1) as f32) * max;
+                }
+            }
+        }
+
+        /// Shows `value` until clicked, then edits a copy of it<br>
+        /// Returns the edited text when Enter is pressed
+        fn text_field(&mut self, input: &Input, x: i32, y: i32, w: u32, value: &str, ui: &mut UI) -> Option<String> {
+            let fields = &mut self.text_fields;
+            let index = fields.current_field;
+            fields.current_field += 1;
+
+            let mut submitted = None;
+            if fields.focused == Some(index) && fields.edit(input) {
+                submitted = Some(fields.contents.clone());
+            }
+
+            let focused = fields.focused == Some(index);
+            let clicked = if focused {
+                let (text, cursor) = fields.display(input);
+                ui.text_box(input, x, y, w, &text, Some(cursor))
+            } else {
+                ui.text_box(input, x, y, w, value, None)
+            };
+
+            if clicked && !focused {
+                fields.focus(index, value);
+            } else if !clicked && focused && input.get_mouse_button_just_pressed(MouseButton::Left) {
+                fields.focused = None;
+            }
+
+            submitted
+        }
     }
 
     impl VicepticaUI {
@@ -671,7 +1285,31 @@ pub mod implement {
                 inner: unsafe { UI::new(gl) },
                 editor: EditorModeUI::new(),
                 play: PlayModeUI::new(),
-                play_mode: true
+                play_mode: true,
+                config: UserConfig::load(),
+                panels: None
+            }
+        }
+
+        /// Start drawing detachable windows into the panel window, `size` is its size in pixels
+        pub unsafe fn open_panels(&mut self, size: (u32, u32), gl: &glow::Context) {
+            let mut panels = UI::new(gl);
+            panels.theme = self.inner.theme.clone();
+            panels.set_scale(self.inner.scale);
+            panels.resize(size.0, size.1);
+            self.panels = Some(panels);
+        }
+
+        pub unsafe fn close_panels(&mut self, gl: &glow::Context) {
+            if let Some(panels) = self.panels.take() {
+                gl.delete_vertex_array(panels.vao);
+            }
+        }
+
+        /// Call with the panel window's surface current, there is nothing to show in play mode
+        pub unsafe fn render_panels(&self, textures: &TextureBank, programs: &mut ProgramBank, gl: &glow::Context) {
+            if let Some(panels) = self.panels.as_ref().filter(|_| !self.play_mode) {
+                panels.render(textures, programs, gl);
             }
         }
 
@@ -680,30 +1318,69 @@ pub mod implement {
             self.editor.show_debug(message);
         }
 
+        /// Asks first if the level has unsaved changes, returns true if it is fine to quit right away<br>
+        /// Only asks in the editor
+        pub fn request_quit(&mut self, world: &World) -> bool {
+            if self.play_mode || !world.has_unsaved_changes() {
+                return true;
+            }
+            self.editor.open_modal(Modal::confirm("Quit", "Quit without saving?", ModalAction::Quit));
+            false
+        }
+
+        /// The user agreed to quit without saving
+        pub fn quit_confirmed(&self) -> bool {
+            self.editor.quit
+        }
+
         pub fn selection_box(&mut self, x: i32, y: i32, w: u32, h: u32) {
             if !self.play_mode {
-                self.editor.selection_box = Some((x, y, w, h));
+                let scale = self.inner.scale;
+                self.editor.selection_box = Some(((x as f32 / scale) as i32, (y as f32 / scale) as i32, (w as f32 / scale) as u32, (h as f32 / scale) as u32));
             }
         }
 
         pub unsafe fn init(&mut self, textures: &mut TextureBank, programs: &mut ProgramBank, gl: &glow::Context) {
             programs.load_by_name_vf("ui", gl).unwrap();
             textures.load_by_name("ui_buttons", gl).unwrap();
-            textures.load_by_name("ui_frame", gl).unwrap();
-            textures.load_by_name("font", gl).unwrap();
-            textures.load_by_name("slider", gl).unwrap();
             textures.load_by_name("important", gl).unwrap();
+
+            let theme = UITheme::load(&self.config.preferences.theme).unwrap_or_else(|e| {
+                self.show_debug(&e);
+                UITheme::default()
+            });
+            if let Err(e) = self.inner.set_theme(theme, textures, gl) {
+                self.show_debug(&e);
+                self.inner.set_theme(UITheme::default(), textures, gl).unwrap();
+            }
         }
 
-        pub unsafe fn render_and_update(&mut self, input: &Input, textures: &mut TextureBank, meshes: &mut MeshBank, programs: &mut ProgramBank, gl: &glow::Context, world: &mut World) {
+        /// `panel_input` is the panel window's input while it is open
+        pub unsafe fn render_and_update(&mut self, input: &Input, panel_input: Option<&Input>, textures: &mut TextureBank, meshes: &mut MeshBank, programs: &mut ProgramBank, gl: &glow::Context, world: &mut World) {
+            if let Some(panels) = self.panels.as_mut() {
+                panels.set_scale(self.inner.scale);
+            }
+
             if let Some(light) = world.editor_data.open_light_ui.take() {
                 self.on_light_selected(light, world);
             }
 
+            // Shown once after each level load, the materials are only checked when they load
+            if !world.editor_data.material_problems_shown && !world.scene.material_problems.is_empty() {
+                world.editor_data.material_problems_shown = true;
+                if self.editor.find_first_window_of_type(EditorWindowType::MaterialProblems).is_none() {
+                    self.editor.add_window(EditorWindow::new(EditorWindowType::MaterialProblems, (100, 100), (500, 300)));
+                }
+            }
+
+            if let Some(count) = world.editor_data.confirm_delete.take() {
+                self.editor.open_modal(Modal::confirm("Delete", &format!("Delete {} objects?", count), ModalAction::DeleteSelection));
+            }
+
             if self.play_mode {
-                // todo
+                self.play.render_and_update(input, textures, programs, gl, &mut self.inner, &mut self.config, world);
             } else {
-                self.editor.render_and_update(input, textures, meshes, programs, gl, &mut self.inner, world);
+                self.editor.render_and_update(input, self.panels.as_mut().zip(panel_input), textures, meshes, programs, gl, &mut self.inner, &mut self.config, world);
             }
 
             if let Some(light_window) = self.editor.find_first_window_of_type(EditorWindowType::LightEditor) {
@@ -721,6 +1398,37 @@ pub mod implement {
                     self.editor.close_all_windows_of_type(EditorWindowType::LightEditor);
                 }
             }
+
+            if let Some(spot_window) = self.editor.find_first_window_of_type(EditorWindowType::SpotLight) {
+                let selected = match world.editor_data.selected_object {
+                    Some(Selection::Model(model)) if world.get_spot_light(model).is_some() => Some(model),
+                    _ => None
+                };
+
+                if selected != self.editor.spot_light_target {
+                    // Load the newly selected light into the sliders instead of overwriting it
+                    self.editor.spot_light_target = selected;
+                    if let Some(spot) = selected.and_then(|model| world.get_spot_light(model)) {
+                        let levels = vec![
+                            200 - (spot.color[0] * 200.0) as u32, 200 - (spot.color[1] * 200.0) as u32, 200 - (spot.color[2] * 200.0) as u32,
+                            200 - (spot.radius / USER_RADIUS_FACTOR) as u32,
+                            200 - (spot.inner_angle / SPOT_ANGLE_FACTOR) as u32, 200 - (spot.outer_angle / SPOT_ANGLE_FACTOR) as u32
+                        ];
+                        self.editor.set_window_sliders(spot_window, levels);
+                    }
+                } else if let Some(model) = selected {
+                    let levels = &self.editor.windows[spot_window].sliders.slider_levels;
+                    let value = |i: usize| 200.0 - levels[i] as f32;
+                    let mut spot = world.get_spot_light(model).unwrap();
+                    spot.color = [value(0) / 200.0, value(1) / 200.0, value(2) / 200.0];
+                    spot.radius = value(3) * USER_RADIUS_FACTOR;
+                    spot.inner_angle = value(4) * SPOT_ANGLE_FACTOR;
+                    spot.outer_angle = value(5) * SPOT_ANGLE_FACTOR;
+                    world.set_spot_light(model, spot);
+                }
+            } else {
+                self.editor.spot_light_target = None;
+            }
         }
 
         pub fn on_light_selected(&mut self, light: usize, world: &World) {
@@ -731,17 +1439,129 @@ pub mod implement {
             if let Some(current) = self.editor.find_first_window_of_type(EditorWindowType::LightEditor) {
                 self.editor.set_window_sliders(current, light_data);
             } else {
-                self.editor.add_window_with_sliders(EditorWindow::new(EditorWindowType::LightEditor, (100, 100), (250, 300)), light_data);
+                self.editor.add_window_with_sliders(EditorWindow::new(EditorWindowType::LightEditor, (100, 100), (250, 330)), light_data);
             }
         }
     }
 
+    /// What to do with the path once a file dialog closes
+    enum DialogPurpose {
+        InsertPrefab,
+        StampPrefab,
+        LoadLevel,
+        LoadSkybox,
+        /// Compare the level being edited against a file
+        CompareLevel,
+        /// Folder to export the current level to
+        ExportLevel,
+        /// Where to save the selection as a prefab
+        SavePrefab,
+        /// Generator script to run, its output is inserted where the camera is aimed
+        RunGenerator
+    }
+
+    /// A native file dialog waited on from its own thread so the editor keeps rendering while it is open
+    struct PendingDialog {
+        purpose: DialogPurpose,
+        result: Receiver<Option<PathBuf>>
+    }
+
+    type DialogFuture = Pin<Box<dyn Future<Output = Option<FileHandle>> + Send>>;
+
+    /// A file dialog that isn't shown yet, only one is open at a time, see `EditorModeUI::open_dialog`
+    struct DialogRequest {
+        purpose: DialogPurpose,
+        /// Shows the dialog, creating the future is what opens it
+        show: Box<dyn FnOnce() -> DialogFuture>
+    }
+
+    impl DialogRequest {
+        fn new<F: Future<Output = Option<FileHandle>> + Send + 'static>(purpose: DialogPurpose, show: impl FnOnce() -> F + 'static) -> Self {
+            Self { purpose, show: Box::new(move || Box::pin(show())) }
+        }
+    }
+
+    impl PendingDialog {
+        /// `dialog` has to be created on the main thread, only waiting for it happens elsewhere
+        fn open(purpose: DialogPurpose, dialog: DialogFuture) -> Self {
+            let (sender, result) = mpsc::channel();
+            thread::spawn(move || {
+                let path = pollster::block_on(dialog).map(|handle| handle.path().to_path_buf());
+                // The editor may have been closed in the meantime
+                let _ = sender.send(path);
+            });
+            Self { purpose, result }
+        }
+    }
+
+    enum ModalButtons {
+        Ok,
+        OkCancel,
+        YesNo
+    }
+
+    /// What happens when a modal is confirmed
+    enum ModalAction {
+        Dismiss,
+        DeleteSelection,
+        /// Save to `res/levels/` under the name typed into the prompt
+        SaveLevelAs,
+        /// Load a level, discarding unsaved changes
+        LoadLevel(PathBuf),
+        /// Place a sign with the text typed into the prompt
+        PlaceText(Vector3<f32>),
+        /// Replace the level with the newest autosave, keeping the save path
+        RestoreAutosave,
+        Quit
+    }
+
+    /// A message box drawn above everything else, takes all input until it is answered
+    struct Modal {
+        title: String,
+        message: String,
+        buttons: ModalButtons,
+        prompt: Option<TextFieldManager>,
+        action: ModalAction
+    }
+
+    impl Modal {
+        fn message(title: &str, message: &str) -> Self {
+            Self { title: title.to_string(), message: message.to_string(), buttons: ModalButtons::Ok, prompt: None, action: ModalAction::Dismiss }
+        }
+
+        fn confirm(title: &str, message: &str, action: ModalAction) -> Self {
+            Self { title: title.to_string(), message: message.to_string(), buttons: ModalButtons::YesNo, prompt: None, action }
+        }
+
+        /// A modal with a text field, already focused and filled with `value`
+        fn prompt(title: &str, message: &str, value: &str, action: ModalAction) -> Self {
+            let mut field = TextFieldManager::new();
+            field.focus(0, value);
+            Self { title: title.to_string(), message: message.to_string(), buttons: ModalButtons::OkCancel, prompt: Some(field), action }
+        }
+    }
+
     struct EditorModeUI {
         windows: Vec<EditorWindow>,
         mouse_action_origin: (f64, f64),
         highest_focus: u32,
         debug_output: Vec<(String, u32)>,
-        selection_box: Option<(i32, i32, u32, u32)>
+        selection_box: Option<(i32, i32, u32, u32)>,
+        material_sort: MaterialSort,
+        material_filter: String,
+        /// Face of the selected brush the alignment controls change, `None` for every face
+        align_face: Option<usize>,
+        modal: Option<Modal>,
+        /// Set once the user has agreed to quit
+        quit: bool,
+        dialog: Option<PendingDialog>,
+        diff_report: Vec<String>,
+        /// Why the last level failed to load, shown in the Save/Load window
+        level_error: Option<String>,
+        /// Save as writes binary levels instead of JSON
+        save_binary: bool,
+        /// Model the spot light window's sliders were last loaded from
+        spot_light_target: Option<usize>
     }
 
     impl EditorModeUI {
@@ -751,7 +1571,17 @@ pub mod implement {
                 windows: vec![/*EditorWindow::new(EditorWindowType::LightEditor, (100, 100), (400, 400))*/],
                 highest_focus: 0,
                 debug_output: Vec::new(),
-                selection_box: None
+                selection_box: None,
+                material_sort: MaterialSort::Name,
+                material_filter: String::new(),
+                align_face: None,
+                modal: None,
+                quit: false,
+                dialog: None,
+                diff_report: Vec::new(),
+                level_error: None,
+                save_binary: false,
+                spot_light_target: None
             }
         }
 
@@ -759,63 +1589,306 @@ pub mod implement {
             self.debug_output.push((message.to_string(), 1000));
         }
 
-        pub fn add_window(&mut self, mut window: EditorWindow) {
-            window.focus = self.highest_focus + 1;
-            self.highest_focus += 1;
-            self.windows.push(window);
-        }
-
-        pub fn focus_window(&mut self, window: usize) {
-            self.windows[window].focus = self.highest_focus + 1;
-            self.highest_focus += 1;
+        /// Replaces any modal that is already open
+        fn open_modal(&mut self, modal: Modal) {
+            self.modal = Some(modal);
         }
 
-        fn toggle_window(&mut self, window_type: EditorWindowType) {
-            let mut open = None;
-            for (i, window) in self.windows.iter().enumerate() {
-                if window.window_type == window_type {
-                    open = Some(i);
-                }
-            }
-
-            if let Some(i) = open {
-                self.windows.remove(i);
+        /// Only one file dialog can be open at a time
+        fn open_dialog(&mut self, request: DialogRequest) {
+            if self.dialog.is_some() {
+                self.show_debug("a file dialog is already open");
             } else {
-                self.add_window(EditorWindow::new(window_type, (100, 100), (400, 400)));
+                self.dialog = Some(PendingDialog::open(request.purpose, (request.show)()));
             }
         }
 
-        fn toggle_window_with_sliders(&mut self, window_type: EditorWindowType, sliders: Vec<u32>) {
-            let mut open = None;
-            for (i, window) in self.windows.iter().enumerate() {
-                if window.window_type == window_type {
-                    open = Some(i);
+        /// Act on the file dialog's result once it closes
+        unsafe fn poll_dialog(&mut self, textures: &mut TextureBank, meshes: &mut MeshBank, gl: &glow::Context, world: &mut World) {
+            let Some(dialog) = &self.dialog else { return; };
+            let path = match dialog.result.try_recv() {
+                Ok(path) => path,
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => None
+            };
+            let purpose = self.dialog.take().unwrap().purpose;
+            let Some(path) = path else { return; };
+
+            match purpose {
+                DialogPurpose::InsertPrefab => {
+                    match world.insert_prefab_from_file(textures, meshes, gl, path) {
+                        Ok(index) => {
+                            let position = vec3(round_to(world.player.position.x, 0.25), round_to(world.player.position.y, 0.25), round_to(world.player.position.z, 0.25));
+                            world.set_model_transform(index, Matrix4::from_translation(position) * world.models[index].as_ref().unwrap().transform);
+                        },
+                        Err(msg) => self.show_debug(&msg)
+                    }
+                },
+                DialogPurpose::StampPrefab => {
+                    match world.load_prefab_from_file(textures, meshes, gl, path) {
+                        Ok((model, lights)) => world.stamp_from_model(model, lights),
+                        Err(msg) => self.show_debug(&msg)
+                    }
+                },
+                DialogPurpose::LoadLevel => {
+                    if world.has_unsaved_changes() {
+                        self.open_modal(Modal::confirm("Unsaved Changes", "Discard unsaved changes and load?", ModalAction::LoadLevel(path)));
+                    } else {
+                        match self.load_level(world, path) {
+                            Ok(()) => self.show_debug("new level loaded"),
+                            Err(e) => {
+                                eprintln!("{}", e);
+                                self.show_debug(&e);
+                            }
+                        }
+                    }
+                },
+                DialogPurpose::LoadSkybox => {
+                    let error_string = OsString::from("error");
+                    let skybox = path.file_name().unwrap_or(&error_string).to_string_lossy().to_string();
+                    if !textures.cubemaps.contains_key(&skybox) {
+                        if let Err(e) = textures.load_cubemap_by_name(&skybox, gl) {
+                            self.show_debug(&format!("{}", e));
+                            return;
+                        }
+                    }
+                    world.scene.environment.skybox = crate::render::Skybox::Cubemap(skybox.clone());
+                    self.show_debug(&format!("loaded skybox {}", skybox));
+                },
+                DialogPurpose::CompareLevel => self.diff_report = Self::level_diff_report(diff::load_level_value(&path), world),
+                DialogPurpose::SavePrefab => {
+                    match world.export_selection_as_prefab(&path) {
+                        Ok(()) => self.show_debug(&format!("saved prefab {}", path.display())),
+                        Err(e) => self.show_debug(&e)
+                    }
+                },
+                DialogPurpose::ExportLevel => {
+                    let Some(level) = world.editor_data.save_to.clone() else { return; };
+                    match export::export_project(&[level], &path) {
+                        Ok(report) => self.show_debug(&report.summary()),
+                        Err(e) => self.show_debug(&e)
+                    }
+                },
+                DialogPurpose::RunGenerator => {
+                    match generate::run_generator(&path) {
+                        Ok(builder) => {
+                            for line in builder.log.iter() {
+                                self.show_debug(line);
+                            }
+                            let offset = world.aim_point();
+                            match world.insert_generated(&builder, offset, textures, meshes, gl) {
+                                Ok(count) => self.show_debug(&format!("generated {} objects", count)),
+                                Err(e) => self.show_debug(&e)
+                            }
+                        },
+                        Err(e) => self.show_debug(&e)
+                    }
                 }
             }
+        }
 
-            if let Some(i) = open {
-                self.windows.remove(i);
-            } else {
-                self.add_window_with_sliders(EditorWindow::new(window_type, (100, 100), (400, 400)), sliders);
+        /// Lines for the Compare Levels window, the changes from `old` to the level being edited
+        fn level_diff_report(old: Result<serde_json::Value, String>, world: &World) -> Vec<String> {
+            let current = world.save_data().to_stable_value();
+            match old.and_then(|old| Ok(diff::diff_levels(&old, &current?))) {
+                Ok(report) if report.is_empty() => vec![String::from("no changes")],
+                Ok(report) => report,
+                Err(e) => vec![e]
             }
         }
 
-        pub fn close_all_windows_of_type(&mut self, kind: EditorWindowType) {
-            self.windows.retain(|window| window.window_type != kind);
+        /// Read a level file and queue it to replace the current level
+        fn load_level(&mut self, world: &mut World, path: PathBuf) -> Result<(), String> {
+            let level = LevelData::read_from_file(&path).map_err(|e| format!("{}: {}", path.display(), e));
+            self.level_error = level.as_ref().err().cloned();
+            world.load_new = Some(level?);
+            world.editor_data.save_to = Some(path);
+            Ok(())
         }
 
-        pub fn add_window_with_sliders(&mut self, mut window: EditorWindow, sliders: Vec<u32>) {
-            for slider in &sliders {
-                window.sliders.slider_levels.push(*slider);
-                window.sliders.sliders.push(SliderInteraction { clicked: false, progress: *slider });
-            }
-            self.add_window(window);
+        fn save_level(world: &mut World, path: PathBuf) -> Result<(), String> {
+            world.save_to_file(&path)?;
+            world.editor_data.save_to = Some(path);
+            Ok(())
         }
 
-        pub fn set_window_sliders(&mut self, window: usize, sliders: Vec<u32>) {
-            for i in 0..sliders.len() {
-                self.windows[window].sliders.slider_levels[i] = sliders[i];
-                self.windows[window].sliders.sliders[i] = SliderInteraction { clicked: false, progress: sliders[i] };
+        /// Draw the open modal over the dimmed editor and carry out its action once it is answered
+        fn update_modal(&mut self, input: &Input, ui: &mut UI, world: &mut World) {
+            let Some(modal) = &mut self.modal else { return; };
+
+            let (screen_w, screen_h) = ui.screen_size;
+            ui.fill(0, 0, screen_w, screen_h, [0.0, 0.0, 0.0, 0.5]);
+            ui.set_focus(u32::MAX - 1);
+
+            let (message_w, message_h) = ui.get_text_render_size(&modal.message);
+            let w = message_w.max(300) + 20;
+            let h = message_h + if modal.prompt.is_some() { 104 } else { 76 };
+            let x = (screen_w as i32 - w as i32) / 2;
+            let y = (screen_h as i32 - h as i32) / 2;
+
+            // Some(true) when confirmed, Some(false) when cancelled
+            let mut answer = None;
+            if let Some(FrameInteraction::Close) = ui.interactable_frame(input, &modal.title, x, y, w, h) {
+                answer = Some(false);
+            }
+            ui.set_focus(u32::MAX);
+                ui.text(10, 24, &modal.message);
+                let mut bottom = 24 + message_h as i32 + 10;
+
+                if let Some(field) = &mut modal.prompt {
+                    if field.edit(input) {
+                        answer = Some(true);
+                    } else if field.focused.is_none() {
+                        answer = Some(false);
+                    }
+                    let (text, cursor) = field.display(input);
+                    ui.text_box(input, 10, bottom, w - 20, &text, Some(cursor));
+                    bottom += 28;
+                } else if input.get_text_key_just_pressed(Key::Named(NamedKey::Enter)) {
+                    answer = Some(true);
+                } else if input.get_text_key_just_pressed(Key::Named(NamedKey::Escape)) {
+                    answer = Some(false);
+                }
+
+                let labels: &[&str] = match modal.buttons {
+                    ModalButtons::Ok => &["OK"],
+                    ModalButtons::OkCancel => &["OK", "Cancel"],
+                    ModalButtons::YesNo => &["Yes", "No"]
+                };
+                ui.begin_row(10, bottom, 8);
+                for (i, label) in labels.iter().enumerate() {
+                    if ui.text_button(input, 0, 0, label) {
+                        answer = Some(i == 0);
+                    }
+                }
+                ui.end_layout();
+            ui.pop();
+
+            // Nothing behind the modal gets input while it is open
+            ui.mouse_captured = true;
+            ui.keyboard_captured = true;
+
+            let Some(confirmed) = answer else { return; };
+            let modal = self.modal.take().unwrap();
+            if !confirmed {
+                return;
+            }
+
+            match modal.action {
+                ModalAction::Dismiss => (),
+                ModalAction::DeleteSelection => world.delete_selection(),
+                ModalAction::SaveLevelAs => {
+                    let name = modal.prompt.map(|field| field.contents).unwrap_or_default();
+                    let name = name.trim().trim_end_matches(".json");
+                    // Typing the binary extension saves a binary level too
+                    let binary_extension = format!(".{}", BINARY_LEVEL_EXTENSION);
+                    let file = if self.save_binary || name.ends_with(&binary_extension) {
+                        format!("{}{}", name.trim_end_matches(&binary_extension), binary_extension)
+                    } else {
+                        format!("{}.json", name)
+                    };
+                    let name = name.trim_end_matches(&binary_extension);
+                    if let Err(e) = common::plain_file_name(name) {
+                        self.show_debug(&e);
+                    } else if let Err(e) = Self::save_level(world, PathBuf::from(format!("res/levels/{}", file))) {
+                        self.show_debug(&e);
+                    } else {
+                        self.show_debug("level saved successfully");
+                    }
+                },
+                ModalAction::LoadLevel(path) => {
+                    match self.load_level(world, path) {
+                        Ok(()) => self.show_debug("new level loaded"),
+                        Err(e) => self.show_debug(&e)
+                    }
+                },
+                ModalAction::PlaceText(position) => {
+                    let text = modal.prompt.map(|field| field.contents).unwrap_or_default();
+                    if text.trim().is_empty() {
+                        self.show_debug("text can't be empty");
+                    } else {
+                        Self::place_text(world, position, TextLabel::new(&text));
+                    }
+                },
+                ModalAction::RestoreAutosave => {
+                    match LevelData::read_from_file(&save::autosave_path(0)) {
+                        Ok(level) => {
+                            world.load_new = Some(level);
+                            world.load_unsaved = true;
+                            self.level_error = None;
+                            self.show_debug("autosave restored");
+                        },
+                        Err(e) => {
+                            self.show_debug(&e);
+                            self.level_error = Some(e);
+                        }
+                    }
+                },
+                ModalAction::Quit => self.quit = true
+            }
+        }
+
+        pub fn add_window(&mut self, mut window: EditorWindow) {
+            window.focus = self.highest_focus + 1;
+            self.highest_focus += 1;
+            self.windows.push(window);
+        }
+
+        pub fn focus_window(&mut self, window: usize) {
+            self.windows[window].focus = self.highest_focus + 1;
+            self.highest_focus += 1;
+        }
+
+        fn toggle_window(&mut self, window_type: EditorWindowType) {
+            let mut open = None;
+            for (i, window) in self.windows.iter().enumerate() {
+                if window.window_type == window_type {
+                    open = Some(i);
+                }
+            }
+
+            if let Some(i) = open {
+                self.windows.remove(i);
+            } else {
+                // The grid colors and undo depth go below the other preferences
+                let size = if window_type == EditorWindowType::Preferences { (400, 580) } else { (400, 400) };
+                self.add_window(EditorWindow::new(window_type, (100, 100), size));
+            }
+        }
+
+        fn toggle_window_with_sliders(&mut self, window_type: EditorWindowType, sliders: Vec<u32>) {
+            let mut open = None;
+            for (i, window) in self.windows.iter().enumerate() {
+                if window.window_type == window_type {
+                    open = Some(i);
+                }
+            }
+
+            if let Some(i) = open {
+                self.windows.remove(i);
+            } else {
+                // The environment's clip planes and fog go below the sun and skybox
+                let size = if window_type == EditorWindowType::Environment { (420, 600) } else { (400, 400) };
+                self.add_window_with_sliders(EditorWindow::new(window_type, (100, 100), size), sliders);
+            }
+        }
+
+        pub fn close_all_windows_of_type(&mut self, kind: EditorWindowType) {
+            self.windows.retain(|window| window.window_type != kind);
+        }
+
+        pub fn add_window_with_sliders(&mut self, mut window: EditorWindow, sliders: Vec<u32>) {
+            for slider in &sliders {
+                window.sliders.slider_levels.push(*slider);
+                window.sliders.sliders.push(SliderInteraction { clicked: false, progress: *slider });
+            }
+            self.add_window(window);
+        }
+
+        pub fn set_window_sliders(&mut self, window: usize, sliders: Vec<u32>) {
+            for i in 0..sliders.len() {
+                self.windows[window].sliders.slider_levels[i] = sliders[i];
+                self.windows[window].sliders.sliders[i] = SliderInteraction { clicked: false, progress: sliders[i] };
             }
         }
 
@@ -833,15 +1906,141 @@ pub mod implement {
             ui.image_button(input, x, y, 32, 32, (tx, ty), (32, 32), "ui_buttons")
         }
 
-        pub unsafe fn render_and_update(&mut self, input: &Input, textures: &mut TextureBank, meshes: &mut MeshBank, programs: &mut ProgramBank, gl: &glow::Context, ui: &mut UI, world: &mut World) {            
+        /// Insert a small fixture model with a spot light pointing along `direction`
+        fn place_spot_light(world: &mut World, position: Vector3<f32>, direction: Vector3<f32>) -> usize {
+            world.insert_model(Model::new(
+                false, Matrix4::from_translation(position),
+                vec![
+                    Renderable::Mesh("blank_cube".to_string(), Matrix4::from_scale(0.25), flags::FULLBRIGHT),
+                ]
+            ).with_component(Component::SpotLight(SpotLightSource::new(direction)))
+            .collider_cuboid(Vector3::zero(), vec3(0.125, 0.125, 0.125)))
+        }
+
+        /// Insert a model showing `label`, with a thin collider so it can be selected
+        fn place_text(world: &mut World, position: Vector3<f32>, label: TextLabel) -> usize {
+            let (width, height) = label.size();
+            world.insert_model(Model::new(
+                false, Matrix4::from_translation(position),
+                vec![Renderable::Text(label, Vector3::zero(), flags::FULLBRIGHT | flags::CUTOUT)]
+            ).collider_cuboid(Vector3::zero(), vec3(width / 2.0, height / 2.0, 0.05)))
+        }
+
+        /// Toggle box with a label, returns true when clicked
+        fn checkbox(ui: &mut UI, input: &Input, x: i32, y: i32, checked: bool, label: &str) -> bool {
+            ui.begin_row(x, y, 6);
+                ui.frame(0, 0, 16, 16);
+                    let clicked = ui.image_button(input, 1, 1, 14, 14, (0, 0), (1, 1), "evil_pixel");
+                    if checked {
+                        ui.text(5, 3, "x");
+                    }
+                ui.pop();
+                ui.text(0, 3, label);
+            ui.end_layout();
+            clicked
+        }
+
+        /// Distance next to the middle of the measuring line, hidden while it is off the screen
+        fn measurement_label(ui: &mut UI, measurement: &Measurement) {
+            let Some(screen) = measurement.screen else { return; };
+            let x = ((screen.0 + 1.0) / 2.0 * ui.screen_size.0 as f32) as i32;
+            let y = ((1.0 - screen.1) / 2.0 * ui.screen_size.1 as f32) as i32;
+            let delta = measurement.delta;
+            ui.text(x + 8, y - 14, &format!("{:.2}", measurement.distance));
+            ui.text(x + 8, y, &format!("x {:.2} y {:.2} z {:.2}", delta.x.abs(), delta.y.abs(), delta.z.abs()));
+        }
+
+        /// Checkbox for a value shared by several objects, `None` is drawn as mixed
+        fn mixed_checkbox(ui: &mut UI, input: &Input, x: i32, y: i32, checked: Option<bool>, label: &str) -> bool {
+            ui.begin_row(x, y, 6);
+                ui.frame(0, 0, 16, 16);
+                    let clicked = ui.image_button(input, 1, 1, 14, 14, (0, 0), (1, 1), "evil_pixel");
+                    match checked {
+                        Some(true) => ui.text(5, 3, "x"),
+                        None => ui.text(5, 3, "—"),
+                        Some(false) => ()
+                    }
+                ui.pop();
+                ui.text(0, 3, label);
+            ui.end_layout();
+            clicked
+        }
+
+        /// Label with - and + buttons, returns how much the value should change
+        fn stepper(ui: &mut UI, input: &Input, x: i32, y: i32, label: &str, step: f32) -> f32 {
+            let mut change = 0.0;
+            ui.begin_row(x, y, 4);
+                ui.frame(0, 0, 16, 16);
+                    if ui.image_button(input, 1, 1, 14, 14, (0, 0), (1, 1), "evil_pixel") {
+                        change -= step;
+                    }
+                    ui.text(5, 3, "-");
+                ui.pop();
+                ui.frame(0, 0, 16, 16);
+                    if ui.image_button(input, 1, 1, 14, 14, (0, 0), (1, 1), "evil_pixel") {
+                        change += step;
+                    }
+                    ui.text(5, 3, "+");
+                ui.pop();
+                ui.text(2, 3, label);
+            ui.end_layout();
+            change
+        }
+
+        /// Select the brushes a brush operation made
+        fn select_csg_result(world: &mut World, result: Option<Result<Vec<usize>, String>>, debug_messages: &mut Vec<String>) {
+            match result {
+                Some(Ok(brushes)) => {
+                    world.deselect();
+                    for brush in brushes {
+                        world.select_or_append_brush(brush);
+                    }
+                    world.set_arrows_visible(true);
+                },
+                Some(Err(e)) => debug_messages.push(e),
+                None => ()
+            }
+        }
+
+        /// Steppers for each volume bus, returns true if any of them changed
+        fn volume_controls(ui: &mut UI, input: &Input, x: i32, y: i32, volume: &mut VolumeSettings) -> bool {
+            let mut changed = false;
+            let buses = [("Master", &mut volume.master), ("Music", &mut volume.music), ("Effects", &mut volume.sfx)];
+            ui.begin_column(x, y, 8);
+            for (label, level) in buses {
+                let change = Self::stepper(ui, input, 0, 0, &format!("{}: {:.0}%", label, *level * 100.0), 0.1);
+                if change != 0.0 {
+                    *level = (((*level + change) * 10.0).round() / 10.0).clamp(0.0, 1.0);
+                    changed = true;
+                }
+            }
+            ui.end_layout();
+            changed
+        }
+
+        /// `panels` is the panel window's UI and input, detachable windows are drawn there instead
+        pub unsafe fn render_and_update(&mut self, input: &Input, panels: Option<(&mut UI, &Input)>, textures: &mut TextureBank, meshes: &mut MeshBank, programs: &mut ProgramBank, gl: &glow::Context, ui: &mut UI, config: &mut UserConfig, world: &mut World) {            
             ui.begin();
 
+            self.poll_dialog(textures, meshes, gl, world);
+
+            // While a modal is open everything behind it is drawn as if nothing was pressed
+            let modal_input = input;
+            let blocked = Input::new();
+            let modal_open = self.modal.is_some();
+            let input = if modal_open { &blocked } else { input };
+            let mut panels = panels.map(|(panel_ui, panel_input)| {
+                panel_ui.begin();
+                (panel_ui, if modal_open { &blocked } else { panel_input })
+            });
+
             if !self.debug_output.is_empty() {
                 let screen_edge = ui.screen_size.0 - 8;
                 let mut y = 8;
+                let mut copied = false;
 
                 for (line, life) in self.debug_output.iter_mut() {
-                    let size = UI::get_text_render_size(line);
+                    let size = ui.get_text_render_size(line);
                     let mut x_mod = 0;
 
                     if *life < 25 {
@@ -854,25 +2053,46 @@ pub mod implement {
                         }
                     }
 
-                    ui.text((screen_edge - size.0) as i32 + x_mod, y, line);
+                    let x = (screen_edge - size.0) as i32 + x_mod;
+                    ui.text(x, y, line);
+                    let (mpx, mpy) = ui.mouse_pos(input);
+                    let (mpx, mpy) = (mpx as i32, mpy as i32);
+                    if mpx > x && mpx < x + size.0 as i32 && mpy > y && mpy < y + size.1 as i32 {
+                        ui.mouse_captured = true;
+                        // Click a message to copy it
+                        if input.get_mouse_button_just_pressed(MouseButton::Left) && input.copy_to_clipboard(line) {
+                            copied = true;
+                        }
+                    }
                     y += size.1 as i32 + 4;
 
                     *life -= 1;
                 }
 
                 self.debug_output.retain(|line| line.1 > 0);
+                if copied {
+                    self.show_debug("copied to clipboard");
+                }
+            }
+
+            if let Some(measurement) = world.measurement() {
+                Self::measurement_label(ui, &measurement);
             }
 
             let rounded_camera_pos = vec3(round_to(world.player.position.x, 0.25), round_to(world.player.position.y, 0.25), round_to(world.player.position.z, 0.25));
             let mut debug_messages = Vec::new();
+            let mut modal = None;
+            let mut dialog = None;
 
             if Self::draw_ui_button(ui, input, 0, 200, 0, 0) {
                 world.insert_brush(Renderable::Brush(
                     "concrete".to_string(), 
                     rounded_camera_pos, 
                     vec3(1.0, 1.0, 1.0), 
-                    flags::EXTEND_TEXTURE
+                    flags::EXTEND_TEXTURE,
+                    BrushFaces::default()
                 ));
+                world.record_edit(EditKind::Created, true, rounded_camera_pos);
             }
 
             if Self::draw_ui_button(ui, input, 0, 200 + 32, 32, 0) {
@@ -883,14 +2103,7 @@ pub mod implement {
             }
 
             if Self::draw_ui_button(ui, input, 0, 200 + 96, 96, 0) {
-                let light = world.scene.add_point_light(PointLight::default(vec3(0.0, 0.0, 0.0)));
-                world.insert_model(Model::new(
-                    false, Matrix4::from_translation(rounded_camera_pos),
-                    vec![
-                        Renderable::Mesh("blank_cube".to_string(), Matrix4::from_translation(vec3(0.0, 0.0, 0.0)) * Matrix4::from_scale(0.25), flags::FULLBRIGHT),
-                    ]
-                ).with_light(light, vec3(0.0, 0.0, 0.0))
-                .collider_cuboid(Vector3::zero(), vec3(0.125, 0.125, 0.125)));
+                world.place_light(rounded_camera_pos, PointLight::default(vec3(0.0, 0.0, 0.0)));
             }
             if Self::draw_ui_button(ui, input, 0, 200 + 128, 128, 0) {
                 self.toggle_window(EditorWindowType::SaveLoad);
@@ -905,22 +2118,118 @@ pub mod implement {
                 world.toggle_hide_selection();
             }
             if Self::draw_ui_button(ui, input, 0, 200 + 128 + 96, 32, 32) {
-                let load_file = FileDialog::new()
+                let load_file = AsyncFileDialog::new()
                     .add_filter("JSON files", &["json"])
                     .set_directory("/res/levels/")
-                    .set_title("Load Prefab")
-                    .pick_file();
-                if let Some(path) = load_file {
-                    match world.insert_prefab_from_file(textures, meshes, gl, path) {
-                        Result::Ok(index) => {
-                            world.set_model_transform(index, Matrix4::from_translation(rounded_camera_pos) * world.models[index].as_ref().unwrap().transform);
-                        },
-                        Result::Err(msg) => {
-                            debug_messages.push(msg);
+                    .set_title("Load Prefab");
+                self.open_dialog(DialogRequest::new(DialogPurpose::InsertPrefab, move || load_file.pick_file()));
+            }
+
+            if Self::draw_ui_button(ui, input, 0, 200 + 128 + 128, 64, 32) {
+                self.toggle_window(EditorWindowType::Inspector);
+            }
+            if Self::draw_ui_button(ui, input, 0, 200 + 128 + 192, 128, 32) {
+                self.toggle_window(EditorWindowType::LevelProperties);
+            }
+            if Self::draw_ui_button(ui, input, 0, 200 + 128 + 224, 0, 64) {
+                self.toggle_window(EditorWindowType::LightPalette);
+            }
+            if Self::draw_ui_button(ui, input, 0, 200 + 128 + 256, 32, 64) {
+                self.toggle_window(EditorWindowType::Audio);
+            }
+            if Self::draw_ui_button(ui, input, 0, 200 + 128 + 288, 64, 64) {
+                self.toggle_window(EditorWindowType::Preferences);
+            }
+            if Self::draw_ui_button(ui, input, 0, 200 + 128 + 320, 96, 64) {
+                self.toggle_window(EditorWindowType::LevelDiff);
+            }
+            if Self::draw_ui_button(ui, input, 0, 200 + 128 + 384, 160, 64) {
+                self.toggle_window(EditorWindowType::Notes);
+            }
+            if Self::draw_ui_button(ui, input, 0, 200 + 128 + 416, 0, 96) {
+                self.toggle_window_with_sliders(EditorWindowType::SpotLight, vec![0; 6]);
+            }
+            if Self::draw_ui_button(ui, input, 0, 200 + 128 + 352, 128, 64) {
+                self.open_modal(Modal::prompt("Place Text", "Text", "", ModalAction::PlaceText(rounded_camera_pos)));
+            }
+            if Self::draw_ui_button(ui, input, 0, 200 + 128 + 160, 96, 32) {
+                let load_file = AsyncFileDialog::new()
+                    .add_filter("JSON files", &["json"])
+                    .set_directory("/res/levels/")
+                    .set_title("Stamp Prefab");
+                self.open_dialog(DialogRequest::new(DialogPurpose::StampPrefab, move || load_file.pick_file()));
+            }
+
+            // Status bar
+            ui.begin_row(4, ui.screen_size.1 as i32 - 28, 8);
+                if ui.text_button(input, 0, 0, &format!("Gizmo: {}", world.editor_data.gizmo_space.name())) {
+                    world.editor_data.gizmo_space = world.editor_data.gizmo_space.toggle();
+                }
+                let camera = &mut world.scene.camera;
+                if ui.text_button(input, 0, 0, &format!("View: {}", OrthoView::name(camera.ortho))) {
+                    camera.set_ortho(OrthoView::cycle(camera.ortho));
+                }
+                if ui.text_button(input, 0, 0, "-") {
+                    world.editor_data.increment = (world.editor_data.increment / 2.0).max(MIN_INCREMENT);
+                }
+                ui.text(0, 5, &format!("Increment: {}", world.editor_data.increment));
+                if ui.text_button(input, 0, 0, "+") {
+                    world.editor_data.increment = (world.editor_data.increment * 2.0).min(MAX_INCREMENT);
+                }
+                if ui.text_button(input, 0, 0, if world.editor_data.snapping { "Snap: on" } else { "Snap: off" }) {
+                    world.editor_data.snapping = !world.editor_data.snapping;
+                }
+                if ui.text_button(input, 0, 0, if world.editor_data.show_grid { "Grid: on" } else { "Grid: off" }) {
+                    world.editor_data.show_grid = !world.editor_data.show_grid;
+                }
+                if ui.text_button(input, 0, 0, &format!("Tint: {}", world.editor_data.brush_tint.name())) {
+                    world.editor_data.brush_tint = world.editor_data.brush_tint.cycle();
+                }
+                let traversal = &mut world.editor_data.traversal;
+                if ui.text_button(input, 0, 0, if traversal.show { "Traversal: on" } else { "Traversal: off" }) {
+                    traversal.show = !traversal.show;
+                    traversal.warnings.clear();
+                }
+                if traversal.show {
+                    for problem in [TraversalProblem::Ledge, TraversalProblem::Gap, TraversalProblem::LowCeiling] {
+                        let count = traversal.count(problem);
+                        if count > 0 {
+                            ui.text(0, 5, &format!("{} {}", count, problem.name()));
                         }
                     }
                 }
-            }
+                for (name, color) in world.editor_data.brush_tint.legend() {
+                    ui.fill(0, 5, 10, 10, [color[0], color[1], color[2], 1.0]);
+                    ui.text(0, 5, name);
+                }
+                if ui.text_button(input, 0, 0, "Outliner") {
+                    self.toggle_window(EditorWindowType::Outliner);
+                }
+                if ui.text_button(input, 0, 0, "Ghosts") {
+                    self.toggle_window(EditorWindowType::Ghosts);
+                }
+                if ui.text_button(input, 0, 0, "Events") {
+                    self.toggle_window(EditorWindowType::Events);
+                }
+                if ui.text_button(input, 0, 0, "Connections") {
+                    self.toggle_window(EditorWindowType::Connections);
+                }
+                if ui.text_button(input, 0, 0, "Stats") {
+                    self.toggle_window(EditorWindowType::Session);
+                }
+                if ui.text_button(input, 0, 0, "Effects") {
+                    self.toggle_window(EditorWindowType::Effects);
+                }
+                if ui.text_button(input, 0, 0, "Scale") {
+                    self.toggle_window(EditorWindowType::ScaleReferences);
+                }
+                for usage in world.editor_data.stats.iter() {
+                    ui.fill(0, 5, 10, 10, usage.status().color());
+                    if ui.text_button(input, 0, 0, &format!("{} {}/{}", usage.budget.name(), usage.budget.format(usage.used), usage.budget.format(usage.limit))) {
+                        self.toggle_window(EditorWindowType::Performance);
+                    }
+                }
+            ui.end_layout();
 
             if let Some((x, y, w, h)) = self.selection_box {
                 ui.selection_frame(x, y, w, h);
@@ -928,6 +2237,7 @@ pub mod implement {
             }
 
             let mut interaction_highest_focus = 0;
+            let mut action_origin = (0.0, 0.0);
             let mut begin_drag = None;
             let mut begin_resize = None;
             let mut close = None;
@@ -935,11 +2245,19 @@ pub mod implement {
             let mut contents_clicked = None;
 
             for (i, window) in self.windows.iter_mut().enumerate() {
+                let detached = panels.is_some() && window.window_type.detachable();
+                let (ui, input) = match panels.as_mut() {
+                    Some((panel_ui, panel_input)) if detached => (&mut **panel_ui, *panel_input),
+                    _ => (&mut *ui, input)
+                };
+                window.set_detached(detached, ui.screen_size);
+
                 if window.dragging {
                     if input.get_mouse_button_released(MouseButton::Left) {
                         window.dragging = false;
                     } else {
-                        let diff = (input.mouse_pos.0 - self.mouse_action_origin.0, input.mouse_pos.1 - self.mouse_action_origin.1);
+                        let mouse_pos = ui.mouse_pos(input);
+                        let diff = (mouse_pos.0 - self.mouse_action_origin.0, mouse_pos.1 - self.mouse_action_origin.1);
                         window.position = (window.drag_origin.0 + diff.0 as i32, window.drag_origin.1 + diff.1 as i32);
                     }
                 }
@@ -948,7 +2266,8 @@ pub mod implement {
                     if input.get_mouse_button_released(MouseButton::Left) {
                         window.scaling = false;
                     } else {
-                        let diff = (input.mouse_pos.0 - self.mouse_action_origin.0, input.mouse_pos.1 - self.mouse_action_origin.1);
+                        let mouse_pos = ui.mouse_pos(input);
+                        let diff = (mouse_pos.0 - self.mouse_action_origin.0, mouse_pos.1 - self.mouse_action_origin.1);
                         window.scale = (
                             (window.scale_origin.0 as i32 + diff.0 as i32).max(48) as u32,
                             (window.scale_origin.1 as i32 + diff.1 as i32).max(48) as u32
@@ -957,6 +2276,7 @@ pub mod implement {
                 }
 
                 window.sliders.reset();
+                window.text_fields.reset();
 
                 if let Some(interaction) = ui.interactable_frame(input, window.window_type.title(), window.position.0, window.position.1, window.scale.0, window.scale.1) {
                     if window.focus >= interaction_highest_focus {
@@ -973,6 +2293,7 @@ pub mod implement {
                             },
                             FrameInteraction::DragBegin => {
                                 begin_drag = Some(i);
+                                action_origin = ui.mouse_pos(input);
                                 window.drag_origin = window.position;
                             },
                             FrameInteraction::OtherContentsClicked => {
@@ -980,6 +2301,7 @@ pub mod implement {
                             },
                             FrameInteraction::ResizeBegin => {
                                 begin_resize = Some(i);
+                                action_origin = ui.mouse_pos(input);
                                 window.scale_origin = window.scale;
                             },
                             FrameInteraction::Scroll(offset) => {
@@ -1013,17 +2335,96 @@ pub mod implement {
                         }
                     },
                     EditorWindowType::MaterialPicker => {
+                        ui.text(4, 18, "Click a brush to apply\nCtrl click to also apply to the selection");
+                        ui.frame(4, 44, 120, 20);
+                            if ui.image_button(input, 1, 1, 118, 18, (0, 0), (1, 1), "evil_pixel") {
+                                self.material_sort = self.material_sort.cycle();
+                            }
+                            ui.text(4, 5, &format!("Sort: {}", self.material_sort.name()));
+                        ui.pop();
+                        if let Some(filter) = window.text_field(input, 130, 44, 200, &self.material_filter, ui) {
+                            self.material_filter = filter;
+                        }
+                        ui.text(4, 68, "Filter by name or e.g. friction<0.5\nShown as friction/control/jump");
                         let rows = (window.scale.0 / MATERIAL_FRAME_SIZE).max(1);
                         let mut x = 0;
-                        let mut y = 16;
+                        let mut y = 94;
+
+                        if let Some(brush) = world.get_selected_brush() {
+                            let Renderable::Brush(.., faces) = world.models[world.internal.brushes].as_ref().unwrap().render[brush] else { unreachable!() };
+                            let chosen: Vec<usize> = self.align_face.map_or((0..6).collect(), |face| vec![face]);
+                            let shown = faces.0[chosen[0]];
+                            let mut new_faces = faces;
+
+                            ui.begin_column(4, y, 4);
+                                ui.begin_row(0, 0, 4);
+                                    if ui.text_button(input, 0, 0, &format!("Align: {}", self.align_face.map_or("every face", |face| BrushFaces::NAMES[face]))) {
+                                        self.align_face = match self.align_face {
+                                            None => Some(0),
+                                            Some(5) => None,
+                                            Some(face) => Some(face + 1)
+                                        };
+                                    }
+                                    if ui.text_button(input, 0, 0, "Reset") {
+                                        for face in chosen.iter() {
+                                            new_faces.0[*face] = FaceUV::default();
+                                        }
+                                    }
+                                ui.end_layout();
+                                let offset_u = Self::stepper(ui, input, 0, 0, &format!("Offset U: {}", shown.offset[0]), FACE_OFFSET_STEP);
+                                let offset_v = Self::stepper(ui, input, 0, 0, &format!("Offset V: {}", shown.offset[1]), FACE_OFFSET_STEP);
+                                let rotation = Self::stepper(ui, input, 0, 0, &format!("Rotation: {}", shown.rotation), FACE_ROTATION_STEP);
+                                let scale_u = Self::stepper(ui, input, 0, 0, &format!("Scale U: {}", shown.scale[0]), FACE_SCALE_STEP);
+                                let scale_v = Self::stepper(ui, input, 0, 0, &format!("Scale V: {}", shown.scale[1]), FACE_SCALE_STEP);
+                            let (_, height) = ui.end_layout();
+                            y += height as i32 + 8;
+
+                            for face in chosen.iter() {
+                                let face = &mut new_faces.0[*face];
+                                face.offset[0] += offset_u;
+                                face.offset[1] += offset_v;
+                                face.rotation = (face.rotation + rotation).rem_euclid(360.0);
+                                face.scale[0] = (face.scale[0] + scale_u).max(FACE_SCALE_STEP);
+                                face.scale[1] = (face.scale[1] + scale_v).max(FACE_SCALE_STEP);
+                            }
+                            if new_faces != faces {
+                                let new_brush = world.set_brush_faces(brush, new_faces);
+                                world.deselect();
+                                world.select_brush(new_brush);
+                                world.set_arrows_visible(true);
+                            }
+                        }
 
-                        for (i, material) in world.scene.applicable_materials.iter().enumerate() {
-                            let texture = textures.textures.get(material).unwrap();
+                        let mut materials: Vec<(&String, PhysicalProperties)> = world.scene.applicable_materials.iter()
+                            .map(|material| (material, world.scene.materials.get(material).map(|m| m.physical_properties.clone()).unwrap_or_default()))
+                            .filter(|(material, properties)| material_matches(&self.material_filter, material, properties))
+                            .collect();
+                        match self.material_sort {
+                            MaterialSort::Name => materials.sort_by(|a, b| a.0.cmp(b.0)),
+                            MaterialSort::Friction => materials.sort_by(|a, b| a.1.friction.total_cmp(&b.1.friction)),
+                            MaterialSort::Control => materials.sort_by(|a, b| a.1.control.total_cmp(&b.1.control)),
+                            MaterialSort::Jump => materials.sort_by(|a, b| a.1.jump.total_cmp(&b.1.jump))
+                        }
+
+                        let mut test_patch = None;
+                        for (i, (material, properties)) in materials.iter().enumerate() {
+                            let texture = textures.textures.get(*material).unwrap();
                             ui.frame(x, y, MATERIAL_FRAME_SIZE, MATERIAL_FRAME_SIZE);
                             let draw_pos = MATERIAL_FRAME_SIZE / 2 - 32;
+                            let picked = world.editor_data.apply_material.as_ref() == Some(*material);
                             if ui.image_button(input, draw_pos as i32, draw_pos as i32, 64, 64, (0, 0), (texture.width, texture.height), material) {
-                                world.editor_data.apply_material = Some(material.to_string());
+                                world.editor_data.apply_material = if picked { None } else { Some(material.to_string()) };
                             }
+                            if picked {
+                                ui.text(4, 4, "picked");
+                            }
+                            ui.frame(MATERIAL_FRAME_SIZE as i32 - 34, 2, 32, 14);
+                                if ui.image_button(input, 1, 1, 30, 12, (0, 0), (1, 1), "evil_pixel") {
+                                    test_patch = Some(material.to_string());
+                                }
+                                ui.text(4, 2, "test");
+                            ui.pop();
+                            ui.text(4, draw_pos as i32 + 66, &format!("{:.1}/{:.1}/{:.1}", properties.friction, properties.control, properties.jump));
                             ui.pop();
                             if (i + 1) % rows as usize == 0 {
                                 x = 0;
@@ -1032,6 +2433,11 @@ pub mod implement {
                                 x += MATERIAL_FRAME_SIZE as i32;
                             }
                         }
+
+                        if let Some(material) = test_patch {
+                            world.spawn_test_patch(&material);
+                            debug_messages.push(format!("placed a {} test patch", material));
+                        }
                     },
                     EditorWindowType::LightEditor => {
                         let _ = window.vertical_slider(input, 20, 50, 200, ui);
@@ -1042,61 +2448,127 @@ pub mod implement {
                         ui.text(14 + 100, 20, "Blue");
                         let _ = window.vertical_slider(input, 170, 50, 200, ui);
                         ui.text(6 + 150, 20, "Strength");
+
+                        if let Some(owner) = world.editor_data.light_selected.and_then(|light| world.light_owner(light)) {
+                            let animation = world.get_light_animation(owner);
+                            ui.frame(10, 260, 150, 20);
+                                if ui.image_button(input, 1, 1, 148, 18, (0, 0), (1, 1), "evil_pixel") {
+                                    // none -> each animation in turn -> none
+                                    let next = match animation {
+                                        None => Some(LightAnimation::ALL[0]),
+                                        Some(current) => LightAnimation::ALL.iter().position(|a| *a == current).and_then(|i| LightAnimation::ALL.get(i + 1).copied())
+                                    };
+                                    world.set_light_animation(owner, next);
+                                }
+                                ui.text(4, 5, &format!("Animation: {}", animation.map_or("none", |a| a.name())));
+                            ui.pop();
+                            ui.frame(166, 260, 70, 20);
+                                if ui.image_button(input, 1, 1, 68, 18, (0, 0), (1, 1), "evil_pixel") {
+                                    world.editor_data.preview_light_animation = !world.editor_data.preview_light_animation;
+                                }
+                                ui.text(4, 5, if world.editor_data.preview_light_animation { "Pause" } else { "Play" });
+                            ui.pop();
+                        }
+
+                        if let Some(light) = world.editor_data.light_selected {
+                            let cast_shadows = world.scene.point_lights[light].cast_shadows;
+                            if ui.text_button(input, 10, 290, &format!("Shadows: {}", if cast_shadows { "on" } else { "off" })) {
+                                world.scene.point_lights[light].cast_shadows = !cast_shadows;
+                                let casters = world.scene.point_lights.iter().filter(|light| light.cast_shadows).count();
+                                if !cast_shadows && casters > MAX_SHADOW_LIGHTS {
+                                    debug_messages.push(format!("only the first {} shadow casting lights get shadows", MAX_SHADOW_LIGHTS));
+                                }
+                            }
+                        }
                     },
                     EditorWindowType::SaveLoad => {
                         ui.frame(8, 24, 100, 38);
                             if ui.image_button(input, 1, 1, 98, 36, (0, 0), (1, 1), "evil_pixel") {
-                                if world.editor_data.save_to.is_none() {
-                                    world.editor_data.save_to = FileDialog::new()
-                                        .add_filter("JSON files", &["json"])
-                                        .set_directory("/res/levels/")
-                                        .save_file();
-                                }
-                                
-                                if let Some(path) = &world.editor_data.save_to {
-                                    let save_data = world.save_data();
-                                    let json_level = serde_json::to_string(&save_data).unwrap();
-                                    let mut file = File::create(path);
-                                    if let Ok(file) = &mut file {
-                                        file.write_all(json_level.as_bytes()).unwrap();
-                                        debug_messages.push("level saved successfully".to_string());
-                                    } else {
-                                        debug_messages.push("failed to open or create save file".to_string());
-                                        eprintln!("Failed to open or create save file");
+                                if let Some(path) = world.editor_data.save_to.clone() {
+                                    match Self::save_level(world, path) {
+                                        Ok(()) => debug_messages.push("level saved successfully".to_string()),
+                                        Err(e) => {
+                                            eprintln!("{}", e);
+                                            debug_messages.push(e);
+                                        }
                                     }
+                                } else {
+                                    modal = Some(Modal::prompt("Save Level", "Level name", "", ModalAction::SaveLevelAs));
                                 }
                             }
                             ui.text(4, 12, "Save");
                         ui.pop();
 
+                        if ui.text_button(input, 116, 24, "Save as") {
+                            let name = world.editor_data.save_to.as_ref().and_then(|path| path.file_stem()).map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+                            modal = Some(Modal::prompt("Save Level", "Level name", &name, ModalAction::SaveLevelAs));
+                        }
+
                         ui.frame(8, 24 + 38 + 8, 100, 38);
                             if ui.image_button(input, 1, 1, 98, 36, (0, 0), (1, 1), "evil_pixel") {
-                                let load_file = FileDialog::new()
-                                    .add_filter("JSON files", &["json"])
-                                    .set_directory("/res/levels/")
-                                    .pick_file();
-
-                                if let Some(load_file) = load_file {
-                                    let mut file = File::open(&load_file);
-                                    if let Ok(file) = &mut file {
-                                        let mut data = String::new();
-                                        file.read_to_string(&mut data).expect("Error reading file data");
-                                        let save_data = serde_json::from_str(data.as_str()).unwrap();
-                                        // let archived = rkyv::access::<ArchivedLevelData, rkyv::rancor::Error>(&data.as_slice()).unwrap();
-                                        // let save_data = rkyv::deserialize::<LevelData, rkyv::rancor::Error>(archived).unwrap();
-                                        //let archived = rkyv::access::<data_fix::ArchivedLevelDataOld, rkyv::rancor::Error>(&data.as_slice()).unwrap();
-                                        //let save_data = rkyv::deserialize::<data_fix::LevelDataOld, rkyv::rancor::Error>(archived).unwrap().into_new();
-                                        world.load_new = Some(save_data);
-                                        world.editor_data.save_to = Some(load_file);
-                                        debug_messages.push("new level loaded".to_string());
-                                    } else {
-                                        debug_messages.push("failed to open level file".to_string());
-                                        eprintln!("Failed to open level file")
-                                    }
-                                }
+                                let load_file = AsyncFileDialog::new()
+                                    .add_filter("Levels", &["json", BINARY_LEVEL_EXTENSION])
+                                    .set_directory("/res/levels/");
+                                dialog = Some(DialogRequest::new(DialogPurpose::LoadLevel, move || load_file.pick_file()));
                             }
                             ui.text(4, 12, "Load");
                         ui.pop();
+
+                        if ui.text_button(input, 190, 24, "Save prefab") {
+                            if world.editor_data.selected_object.is_none() {
+                                debug_messages.push(String::from("select something to save as a prefab"));
+                            } else {
+                                let file = AsyncFileDialog::new()
+                                    .add_filter("JSON files", &["json"])
+                                    .set_directory("/res/data/prefabs/")
+                                    .set_title("Save Prefab");
+                                dialog = Some(DialogRequest::new(DialogPurpose::SavePrefab, move || file.save_file()));
+                            }
+                        }
+
+                        if ui.text_button(input, 116, 24 + 38 + 8, "Export") {
+                            // Exports the file, so changes have to be saved first
+                            if world.editor_data.save_to.is_none() || world.has_unsaved_changes() {
+                                debug_messages.push(String::from("save the level before exporting it"));
+                            } else {
+                                let folder = AsyncFileDialog::new()
+                                    .set_title("Export Level");
+                                dialog = Some(DialogRequest::new(DialogPurpose::ExportLevel, move || folder.pick_folder()));
+                            }
+                        }
+
+                        if ui.text_button(input, 190, 24 + 38 + 8, "Restore autosave") {
+                            if save::autosave_path(0).exists() {
+                                modal = Some(Modal::confirm("Restore Autosave", "Replace the level with the newest autosave?", ModalAction::RestoreAutosave));
+                            } else {
+                                debug_messages.push(String::from("there are no autosaves yet"));
+                            }
+                        }
+
+                        ui.text(8, 24 + 92, "Save path");
+                        let save_path = world.editor_data.save_to.as_ref().map(|path| path.to_string_lossy().to_string()).unwrap_or_default();
+                        if let Some(path) = window.text_field(input, 8, 24 + 106, 300, &save_path, ui) {
+                            world.editor_data.save_to = if path.trim().is_empty() { None } else { Some(PathBuf::from(path.trim())) };
+                        }
+
+                        if ui.text_button(input, 8, 24 + 134, if self.save_binary { "Save as: binary" } else { "Save as: JSON" }) {
+                            self.save_binary = !self.save_binary;
+                        }
+                        if self.save_binary {
+                            ui.text(130, 24 + 139, "Faster, but can't be diffed");
+                        }
+
+                        if ui.text_button(input, 8, 24 + 164, "Run generator") {
+                            let file = AsyncFileDialog::new()
+                                .add_filter("Generator scripts", &["rhai"])
+                                .set_directory("/res/scripts/")
+                                .set_title("Run Generator");
+                            dialog = Some(DialogRequest::new(DialogPurpose::RunGenerator, move || file.pick_file()));
+                        }
+
+                        if let Some(error) = &self.level_error {
+                            ui.text(8, 24 + 196, &format!("Last load failed:\n{}", wrap_text(error, LEVEL_ERROR_WIDTH)));
+                        }
                     },
                     EditorWindowType::Environment => {
                         ui.text(14, 20, "Sun Color");
@@ -1119,25 +2591,9 @@ pub mod implement {
                         ui.pop();
                         ui.frame(8, 350, 100, 38);
                             if ui.image_button(input, 2, 2, 96, 36, (0, 0), (1, 1), "evil_pixel") {
-                                let skybox_folder = FileDialog::new()
-                                    .set_directory("/res/textures/cubemap/")
-                                    .pick_folder();
-
-                                if let Some(skybox_folder) = skybox_folder {
-                                    let error_string = OsString::from("error");
-                                    let skybox = skybox_folder.file_name().unwrap_or(&error_string).to_str().unwrap();
-                                    if !textures.cubemaps.contains_key(skybox) {
-                                        if let Err(e) = textures.load_cubemap_by_name(skybox, gl) {
-                                            debug_messages.push(format!("{}", e));
-                                        } else {
-                                            world.scene.environment.skybox = crate::render::Skybox::Cubemap(skybox.to_string());
-                                            debug_messages.push(format!("loaded skybox {}", skybox));
-                                        }
-                                    } else {
-                                        world.scene.environment.skybox = crate::render::Skybox::Cubemap(skybox.to_string());
-                                        debug_messages.push(format!("loaded skybox {}", skybox));
-                                    }
-                                }
+                                let skybox_folder = AsyncFileDialog::new()
+                                    .set_directory("/res/textures/cubemap/");
+                                dialog = Some(DialogRequest::new(DialogPurpose::LoadSkybox, move || skybox_folder.pick_folder()));
                             }
                             ui.text(4, 8, "Load skybox");
                         ui.pop();
@@ -1153,39 +2609,1417 @@ pub mod implement {
                             }
                             ui.text(4, 8, "idgaf skybox");
                         ui.pop();
-                    }
-                }
-                window.sliders.end_of_loop(input);
-
-                ui.pop();
-            }
-            for message in debug_messages.drain(..) {
-                self.show_debug(&message);
-            }
 
-            if let Some(close) = close {
-                self.windows.remove(close);
-            }
+                        let environment = &mut world.scene.environment;
+                        ui.begin_column(ox + 8, oy + 400, 8);
+                        let near = Self::stepper(ui, input, 0, 0, &format!("Near plane: {}", environment.near_plane), 1.0);
+                        // Halving and doubling keeps depth precision reasonable at every scale
+                        if near > 0.0 {
+                            environment.near_plane = (environment.near_plane * 2.0).min(environment.far_plane / 2.0);
+                        } else if near < 0.0 {
+                            environment.near_plane = (environment.near_plane / 2.0).max(0.0125);
+                        }
+                        let far = Self::stepper(ui, input, 0, 0, &format!("Far plane: {}", environment.far_plane), 50.0);
+                        environment.far_plane = (environment.far_plane + far).clamp(environment.near_plane * 2.0, 5000.0);
+                        ui.end_layout();
+
+                        let hdr = &mut environment.hdr;
+                        ui.begin_column(ox + 180, oy + 20, 8);
+                        hdr.exposure = (hdr.exposure + Self::stepper(ui, input, 0, 0, &format!("Exposure: {:.2}", hdr.exposure), 0.25)).clamp(0.25, 8.0);
+                        if Self::checkbox(ui, input, 0, 0, hdr.tone_mapping, "Tone mapping") {
+                            hdr.tone_mapping = !hdr.tone_mapping;
+                        }
+                        if Self::checkbox(ui, input, 0, 0, hdr.bloom, "Bloom") {
+                            hdr.bloom = !hdr.bloom;
+                        }
+                        if hdr.bloom {
+                            hdr.bloom_threshold = (hdr.bloom_threshold + Self::stepper(ui, input, 0, 0, &format!("Threshold: {:.2}", hdr.bloom_threshold), 0.1)).max(0.0);
+                            hdr.bloom_intensity = (hdr.bloom_intensity + Self::stepper(ui, input, 0, 0, &format!("Intensity: {:.2}", hdr.bloom_intensity), 0.1)).clamp(0.0, 4.0);
+                            let passes = Self::stepper(ui, input, 0, 0, &format!("Blur passes: {}", hdr.bloom_passes), 1.0);
+                            hdr.bloom_passes = (hdr.bloom_passes as i32 + passes as i32).clamp(1, 8) as u32;
+                        }
+                        ui.space(4);
+                        let effects = &mut world.scene.world_default_effects;
+                        if Self::checkbox(ui, input, 0, 0, effects.ssao.is_some(), "Ambient occlusion") {
+                            effects.ssao = if effects.ssao.is_some() { None } else { Some(SsaoEffect::default()) };
+                        }
+                        if let Some(ssao) = &mut effects.ssao {
+                            ssao.radius = (ssao.radius + Self::stepper(ui, input, 0, 0, &format!("Radius: {:.2}", ssao.radius), 0.1)).clamp(0.1, 4.0);
+                            ssao.strength = (ssao.strength + Self::stepper(ui, input, 0, 0, &format!("Strength: {:.1}", ssao.strength), 0.5)).clamp(0.5, 8.0);
+                        }
+                        ui.end_layout();
 
-            if let Some(drag) = begin_drag {
-                self.windows[drag].dragging = true;
-                self.mouse_action_origin = input.mouse_pos;
-                self.focus_window(drag);
-            }
+                        let fog = &mut world.scene.environment.fog;
+                        ui.begin_column(ox + 200, oy + 400, 8);
+                        if Self::checkbox(ui, input, 0, 0, fog.enabled, "Distance fog") {
+                            fog.enabled = !fog.enabled;
+                        }
+                        if fog.enabled {
+                            for (c, label) in ["red", "green", "blue"].iter().enumerate() {
+                                fog.color[c] = (fog.color[c] + Self::stepper(ui, input, 0, 0, &format!("Fog {}: {:.2}", label, fog.color[c]), 0.05)).clamp(0.0, 1.0);
+                            }
+                            let density = Self::stepper(ui, input, 0, 0, &format!("Density: {}", if fog.density > 0.0 { format!("{:.3}", fog.density) } else { String::from("linear") }), 0.005);
+                            fog.density = (fog.density + density).clamp(0.0, 1.0);
+                            // Stepping down can leave a tiny remainder instead of landing on linear
+                            if fog.density < 0.001 {
+                                fog.density = 0.0;
+                            }
+                            if fog.density <= 0.0 {
+                                fog.start = (fog.start + Self::stepper(ui, input, 0, 0, &format!("Start: {}", fog.start), 5.0)).clamp(0.0, fog.end);
+                                fog.end = (fog.end + Self::stepper(ui, input, 0, 0, &format!("End: {}", fog.end), 10.0)).clamp(fog.start + 1.0, 5000.0);
+                            }
+                        }
+                        ui.end_layout();
+                    },
+                    EditorWindowType::LevelProperties => {
+                        let old_rules = world.rules.clone();
+                        let rules = &mut world.rules;
 
-            if let Some(resize) = begin_resize {
-                self.windows[resize].scaling = true;
-                self.mouse_action_origin = input.mouse_pos;
-                self.focus_window(resize);
-            }
+                        ui.begin_column(ox + 10, oy + 20, 8);
+                        if ui.text_button(input, 0, 0, &format!("Respawn: {}", rules.respawn.name())) {
+                            rules.respawn = rules.respawn.cycle();
+                        }
+                        ui.space(4);
 
-            if let Some((i, offset)) = scroll {
-                self.windows[i].offset.1 = (self.windows[i].offset.1 - offset).min(0.0).max(-self.windows[i].scroll_max);
-            }
+                        rules.kill_height += Self::stepper(ui, input, 0, 0, &format!("Kill height: {:.1}", rules.kill_height), 5.0);
 
-            if let Some(clicked) = contents_clicked {
-                self.focus_window(clicked);
-            }
+                        if Self::checkbox(ui, input, 0, 0, rules.fall_damage, "Fall damage") {
+                            rules.fall_damage = !rules.fall_damage;
+                        }
+                        if rules.fall_damage {
+                            rules.fall_damage_velocity = (rules.fall_damage_velocity + Self::stepper(ui, input, 0, 0, &format!("Fatal landing speed: {:.1}", rules.fall_damage_velocity), 1.0)).max(1.0);
+                        }
+
+                        if Self::checkbox(ui, input, 0, 0, rules.player_speed.is_some(), "Override player speed") {
+                            rules.player_speed = if rules.player_speed.is_some() { None } else { Some(rules.player_speed()) };
+                        }
+                        if let Some(speed) = &mut rules.player_speed {
+                            *speed = (*speed + Self::stepper(ui, input, 0, 0, &format!("Speed: {:.1}", speed), 0.5)).max(0.5);
+                        }
+
+                        if Self::checkbox(ui, input, 0, 0, rules.jump_velocity.is_some(), "Override jump") {
+                            rules.jump_velocity = if rules.jump_velocity.is_some() { None } else { Some(rules.jump_velocity()) };
+                        }
+                        if let Some(jump) = &mut rules.jump_velocity {
+                            *jump = (*jump + Self::stepper(ui, input, 0, 0, &format!("Jump: {:.1}", jump), 0.5)).max(0.0);
+                        }
+
+                        if Self::checkbox(ui, input, 0, 0, rules.max_health.is_some(), "Override health") {
+                            rules.max_health = if rules.max_health.is_some() { None } else { Some(rules.max_health()) };
+                        }
+                        if let Some(health) = &mut rules.max_health {
+                            *health = (*health + Self::stepper(ui, input, 0, 0, &format!("Health: {:.0}", health), 10.0)).max(1.0);
+                        }
+
+                        if Self::checkbox(ui, input, 0, 0, rules.wall_jump, "Wall jump") {
+                            rules.wall_jump = !rules.wall_jump;
+                        }
+
+                        if Self::checkbox(ui, input, 0, 0, rules.sprint, "Sprint") {
+                            rules.sprint = !rules.sprint;
+                        }
+                        if rules.sprint {
+                            rules.sprint_multiplier = (rules.sprint_multiplier + Self::stepper(ui, input, 0, 0, &format!("Sprint speed: x{:.1}", rules.sprint_multiplier), 0.1)).max(1.0);
+                            rules.sprint_ramp = (rules.sprint_ramp + Self::stepper(ui, input, 0, 0, &format!("Sprint ramp: {:.1}s", rules.sprint_ramp), 0.1)).max(0.0);
+                            if Self::checkbox(ui, input, 0, 0, rules.stamina.is_some(), "Limited stamina") {
+                                rules.stamina = if rules.stamina.is_some() { None } else { Some(5.0) };
+                            }
+                            if let Some(stamina) = &mut rules.stamina {
+                                *stamina = (*stamina + Self::stepper(ui, input, 0, 0, &format!("Stamina: {:.0}s", stamina), 1.0)).max(1.0);
+                            }
+                        }
+
+                        if Self::checkbox(ui, input, 0, 0, rules.time_limit.is_some(), "Time limit") {
+                            rules.time_limit = if rules.time_limit.is_some() { None } else { Some(60.0) };
+                        }
+                        if let Some(limit) = &mut rules.time_limit {
+                            *limit = (*limit + Self::stepper(ui, input, 0, 0, &format!("Seconds: {:.0}", limit), 5.0)).max(5.0);
+                        }
+
+                        if Self::checkbox(ui, input, 0, 0, rules.timed, "Timed challenge") {
+                            rules.timed = !rules.timed;
+                        }
+
+                        ui.space(4);
+                        for budget in Budget::ALL {
+                            let unit = if budget == Budget::TextureMemory { "MiB" } else { "" };
+                            let limit = budget.limit_mut(&mut world.budgets);
+                            let changed = *limit as f32 + Self::stepper(ui, input, 0, 0, &format!("{} budget: {}{}", budget.name(), limit, unit), budget.step());
+                            *limit = changed.max(budget.step()) as u32;
+                        }
+
+                        ui.space(4);
+                        match (&world.editor_data.visibility_bake, world.scene.visibility()) {
+                            (Some(bake), _) => ui.text(0, 0, &format!("Baking visibility: {:.0}%", bake.progress() * 100.0)),
+                            (None, Some(visibility)) => ui.text(0, 0, &format!("Visibility baked for {} cells, bake again after editing brushes", visibility.cell_count())),
+                            (None, None) => ui.text(0, 0, "Visibility not baked, every brush is drawn")
+                        }
+                        ui.begin_row(0, 0, 4);
+                            if world.editor_data.visibility_bake.is_none() && ui.text_button(input, 0, 0, "Bake visibility") {
+                                if let Err(e) = world.start_visibility_bake(visibility::DEFAULT_CELL_SIZE) {
+                                    debug_messages.push(e);
+                                }
+                            }
+                            if world.scene.visibility().is_some() && ui.text_button(input, 0, 0, "Clear") {
+                                world.scene.set_visibility(None);
+                            }
+                        ui.end_layout();
+                        let objectives = world.objectives();
+                        if objectives.is_empty() {
+                            ui.text(0, 0, "No objectives, add them to models in the inspector");
+                        }
+                        for (_, objective) in objectives.iter() {
+                            let done = world.completed_objectives.contains(&objective.title);
+                            ui.text(0, 0, &format!("{} {}: {}", if done { "Done" } else { "Todo" }, objective.order, objective.title));
+                        }
+                        if !world.completed_objectives.is_empty() && ui.text_button(input, 0, 0, "Reset objectives") {
+                            world.completed_objectives.clear();
+                        }
+                        let (_, height) = ui.end_layout();
+
+                        // Level paths, the one after this level is loaded when a goal is reached
+                        let y = oy + 28 + height as i32;
+                        ui.text(ox + 10, y + 5, "Playlist");
+                        if let Some(entered) = window.text_field(input, ox + 70, y, 300, &world.playlist.join(", "), ui) {
+                            world.playlist = entered.split(',').map(str::trim).filter(|path| !path.is_empty()).map(str::to_owned).collect();
+                        }
+                        ui.text(ox + 10, y + 26, &format!("Next level: {}", world.next_level().unwrap_or_else(|| String::from("none, the level restarts"))));
+
+                        if world.rules != old_rules {
+                            world.apply_rules();
+                        }
+                    },
+                    EditorWindowType::LightPalette => {
+                        let mut y = oy + 20;
+                        let mut remove = None;
+
+                        for (i, preset) in config.light_presets.iter().enumerate() {
+                            ui.frame(ox + 10, y, 50, 20);
+                                if ui.image_button(input, 1, 1, 48, 18, (0, 0), (1, 1), "evil_pixel") {
+                                    world.place_light(rounded_camera_pos, preset.light(vec3(0.0, 0.0, 0.0)));
+                                }
+                                ui.text(4, 5, "Place");
+                            ui.pop();
+                            ui.frame(ox + 64, y, 20, 20);
+                                if ui.image_button(input, 1, 1, 18, 18, (0, 0), (1, 1), "evil_pixel") {
+                                    remove = Some(i);
+                                }
+                                ui.text(7, 5, "x");
+                            ui.pop();
+                            ui.text(ox + 90, y + 5, &format!("{} ({:.2} {:.2} {:.2}, radius {:.1})", preset.name, preset.color[0], preset.color[1], preset.color[2], preset.radius));
+                            y += 24;
+                        }
+                        y += 8;
+
+                        let mut changed = false;
+                        if let Some(i) = remove {
+                            config.light_presets.remove(i);
+                            changed = true;
+                        }
+
+                        if let Some(light) = world.editor_data.light_selected {
+                            ui.text(ox + 10, y + 5, "Save selected light as");
+                            if let Some(name) = window.text_field(input, ox + 150, y, 150, "", ui) {
+                                if !name.trim().is_empty() {
+                                    config.light_presets.push(LightPreset::from_light(name.trim(), &world.scene.point_lights[light]));
+                                    changed = true;
+                                }
+                            }
+                        } else {
+                            ui.text(ox + 10, y + 5, "Select a light to save it as a preset");
+                        }
+
+                        if changed {
+                            if let Err(e) = config.save() {
+                                debug_messages.push(e);
+                            }
+                        }
+                    },
+                    EditorWindowType::SpotLight => {
+                        if ui.text_button(input, 10, 24, "Place spot light") {
+                            Self::place_spot_light(world, rounded_camera_pos, world.scene.camera.direction);
+                        }
+
+                        if let Some(model) = self.spot_light_target {
+                            for (i, name) in ["Red", "Green", "Blue", "Radius", "Inner", "Outer"].iter().enumerate() {
+                                let x = 20 + i as i32 * 50;
+                                let _ = window.vertical_slider(input, x, 80, 200, ui);
+                                ui.text(x - 6, 60, name);
+                            }
+
+                            if ui.text_button(input, 140, 24, "Aim at camera direction") {
+                                let transform = world.models[model].as_ref().unwrap().transform;
+                                let local = common::mat4_remove_translation(transform).invert().map(|inverse| inverse.transform_vector(world.scene.camera.direction));
+                                if let (Some(direction), Some(mut spot)) = (local, world.get_spot_light(model)) {
+                                    spot.direction = direction.normalize().into();
+                                    world.set_spot_light(model, spot);
+                                }
+                            }
+                        } else {
+                            ui.text(10, 56, "Select a spot light to edit it");
+                        }
+                    },
+                    EditorWindowType::Notes => {
+                        let mut y = oy + 20;
+                        ui.text(ox + 10, y + 5, "New note");
+                        if let Some(text) = window.text_field(input, ox + 70, y, 250, "", ui) {
+                            if !text.trim().is_empty() {
+                                world.place_note(rounded_camera_pos, Note::new(text.trim()));
+                            }
+                        }
+                        y += 28;
+
+                        let notes = world.notes();
+                        if notes.is_empty() {
+                            ui.text(ox + 10, y + 5, "No notes in this level");
+                        }
+                        for (model, mut note) in notes {
+                            let position = common::translation(world.models[model].as_ref().unwrap().transform);
+                            let mut changed = false;
+                            ui.begin_row(ox + 10, y, 4);
+                                if ui.text_button(input, 0, 0, "Go to") {
+                                    world.scene.camera.pos = Point3::from_vec(position - world.scene.camera.direction * NOTE_VIEW_DISTANCE);
+                                    world.select_model(model);
+                                }
+                                if ui.text_button(input, 0, 0, "Color") {
+                                    note.color = note.next_color();
+                                    changed = true;
+                                }
+                                let arrow = if note.target.is_some() { "No arrow" } else { "Point here" };
+                                if ui.text_button(input, 0, 0, arrow) {
+                                    note.target = if note.target.is_some() { None } else { Some(rounded_camera_pos.into()) };
+                                    changed = true;
+                                }
+                                if ui.text_button(input, 0, 0, "x") {
+                                    if let Err(e) = world.remove_model(model) {
+                                        debug_messages.push(e);
+                                    }
+                                }
+                                let first_line = note.text.lines().next().unwrap_or_default();
+                                ui.text(0, 5, &first_line.chars().take(NOTE_PREVIEW_LENGTH).collect::<String>());
+                            ui.end_layout();
+                            if changed {
+                                world.set_note(model, note);
+                            }
+                            y += 24;
+                        }
+                    },
+                    EditorWindowType::Audio => {
+                        let mut changed = false;
+
+                        ui.begin_column(ox + 10, oy + 20, 4);
+                        ui.begin_row(0, 0, 8);
+                            // Labels and fields are separate columns so the fields line up
+                            ui.begin_column(0, 5, 14);
+                                ui.text(0, 0, "Level music");
+                                ui.text(0, 0, "Ambience");
+                            ui.end_layout();
+                            ui.begin_column(0, 0, 4);
+                                if let Some(track) = window.text_field(input, 0, 0, 200, world.audio.music.as_deref().unwrap_or(""), ui) {
+                                    world.audio.music = if track.trim().is_empty() { None } else { Some(track.trim().to_string()) };
+                                    changed = true;
+                                }
+                                if let Some(track) = window.text_field(input, 0, 0, 200, world.audio.ambience.as_deref().unwrap_or(""), ui) {
+                                    world.audio.ambience = if track.trim().is_empty() { None } else { Some(track.trim().to_string()) };
+                                    changed = true;
+                                }
+                            ui.end_layout();
+                        ui.end_layout();
+                        ui.text(0, 5, "Tracks are loaded from res/audio/");
+                        ui.space(8);
+
+                        // Play the new tracks right away so they can be heard while editing
+                        if changed {
+                            world.start_level_audio();
+                        }
+
+                        if Self::volume_controls(ui, input, 0, 0, &mut config.volume) {
+                            if let Err(e) = config.save() {
+                                debug_messages.push(e);
+                            }
+                        }
+                        ui.end_layout();
+                    },
+                    EditorWindowType::Preferences => {
+                        let prefs = &mut config.preferences;
+                        let before = prefs.clone();
+
+                        ui.begin_column(ox + 10, oy + 20, 8);
+                        prefs.ui_scale = (prefs.ui_scale + Self::stepper(ui, input, 0, 0, &format!("UI scale: {:.2}", prefs.ui_scale), 0.25)).clamp(0.5, 3.0);
+                        prefs.camera_speed = (prefs.camera_speed + Self::stepper(ui, input, 0, 0, &format!("Camera speed: {:.1}", prefs.camera_speed), 0.5)).max(0.5);
+                        prefs.camera_sensitivity = (prefs.camera_sensitivity + Self::stepper(ui, input, 0, 0, &format!("Mouse sensitivity: {:.3}", prefs.camera_sensitivity), 0.001)).max(0.001);
+                        if ui.text_button(input, 0, 0, &format!("Indent saved levels: {}", if prefs.pretty_levels { "on" } else { "off" })) {
+                            prefs.pretty_levels = !prefs.pretty_levels;
+                        }
+                        let autosave = if prefs.autosave_minutes == 0 { String::from("off") } else { format!("every {} min", prefs.autosave_minutes) };
+                        let change = Self::stepper(ui, input, 0, 0, &format!("Autosave: {}", autosave), 1.0);
+                        prefs.autosave_minutes = (prefs.autosave_minutes as i32 + change as i32).clamp(0, 60) as u32;
+                        let view_distance = if prefs.view_distance <= 0.0 { String::from("level's") } else { format!("{}", prefs.view_distance) };
+                        prefs.view_distance = (prefs.view_distance + Self::stepper(ui, input, 0, 0, &format!("Editor view distance: {}", view_distance), 100.0)).clamp(0.0, 5000.0);
+                        if ui.text_button(input, 0, 0, &format!("Panel window: {}", if prefs.panel_window { "on" } else { "off" })) {
+                            prefs.panel_window = !prefs.panel_window;
+                        }
+                        if ui.text_button(input, 0, 0, &format!("Orthographic view presets: {}", if prefs.ortho_presets { "on" } else { "off" })) {
+                            prefs.ortho_presets = !prefs.ortho_presets;
+                        }
+                        if ui.text_button(input, 0, 0, &format!("Merge brushes on save: {}", if prefs.merge_on_save { "on" } else { "off" })) {
+                            prefs.merge_on_save = !prefs.merge_on_save;
+                        }
+                        let antialiasing = match prefs.msaa_samples {
+                            0 => String::from("off"),
+                            samples if world.scene.post_process.fxaa() => format!("{}x MSAA (FXAA fallback)", samples),
+                            samples => format!("{}x MSAA", samples)
+                        };
+                        if ui.text_button(input, 0, 0, &format!("Antialiasing: {}", antialiasing)) {
+                            let current = MSAA_SAMPLE_COUNTS.iter().position(|samples| *samples == prefs.msaa_samples);
+                            prefs.msaa_samples = MSAA_SAMPLE_COUNTS[current.map_or(0, |i| (i + 1) % MSAA_SAMPLE_COUNTS.len())];
+                        }
+                        // Increments are kept to powers of two so brushes line up
+                        let change = Self::stepper(ui, input, 0, 0, &format!("Default increment: {}", prefs.default_increment), 1.0);
+                        if change > 0.0 {
+                            prefs.default_increment = (prefs.default_increment * 2.0).min(MAX_INCREMENT);
+                        } else if change < 0.0 {
+                            prefs.default_increment = (prefs.default_increment / 2.0).max(MIN_INCREMENT);
+                        }
+
+                        if ui.text_button(input, 0, 0, &format!("Theme: {}", prefs.theme)) {
+                            let themes = UITheme::list();
+                            let current = themes.iter().position(|theme| *theme == prefs.theme);
+                            prefs.theme = themes[current.map_or(0, |i| (i + 1) % themes.len())].clone();
+                        }
+                        for (c, label) in ["red", "green", "blue"].iter().enumerate() {
+                            prefs.grid_color[c] = (prefs.grid_color[c] + Self::stepper(ui, input, 0, 0, &format!("Grid {}: {:.2}", label, prefs.grid_color[c]), 0.05)).clamp(0.0, 1.0);
+                        }
+                        for (c, label) in ["red", "green", "blue"].iter().enumerate() {
+                            prefs.grid_major_color[c] = (prefs.grid_major_color[c] + Self::stepper(ui, input, 0, 0, &format!("Major grid {}: {:.2}", label, prefs.grid_major_color[c]), 0.05)).clamp(0.0, 1.0);
+                        }
+                        let undo = if prefs.undo_depth == 0 { String::from("off") } else { format!("{} edits", prefs.undo_depth) };
+                        let change = Self::stepper(ui, input, 0, 0, &format!("Undo: {}", undo), 10.0);
+                        prefs.undo_depth = (prefs.undo_depth as i32 + change as i32).clamp(0, 500) as u32;
+                        ui.space(8);
+
+                        if ui.text_button(input, 0, 0, "Reset to defaults") {
+                            *prefs = EditorPreferences::default();
+                        }
+                        ui.end_layout();
+
+                        if *prefs != before {
+                            if prefs.theme != before.theme {
+                                if let Err(e) = UITheme::load(&prefs.theme).and_then(|theme| ui.set_theme(theme, textures, gl)) {
+                                    debug_messages.push(e);
+                                    prefs.theme = before.theme.clone();
+                                }
+                            }
+                            prefs.apply(world, ui);
+                            if prefs.default_increment != before.default_increment {
+                                world.editor_data.increment = prefs.default_increment;
+                            }
+                            if let Err(e) = config.save() {
+                                debug_messages.push(e);
+                            }
+                        }
+                    },
+                    EditorWindowType::LevelDiff => {
+                        ui.begin_row(ox + 10, oy + 20, 8);
+                        if ui.text_button(input, 0, 0, "Unsaved changes") {
+                            let saved = world.editor_data.saved_snapshot.as_deref()
+                                .ok_or(String::from("the level has not been saved or loaded"))
+                                .and_then(|saved| serde_json::from_str(saved).map_err(|e| e.to_string()));
+                            self.diff_report = Self::level_diff_report(saved, world);
+                        }
+                        if ui.text_button(input, 0, 0, "Compare with file") {
+                            let file = AsyncFileDialog::new()
+                                .add_filter("Levels", &["json", BINARY_LEVEL_EXTENSION])
+                                .set_directory("/res/levels/")
+                                .set_title("Compare Level");
+                            dialog = Some(DialogRequest::new(DialogPurpose::CompareLevel, move || file.pick_file()));
+                        }
+                        ui.end_layout();
+
+                        let mut y = oy + 52;
+                        for line in self.diff_report.iter() {
+                            ui.text(ox + 10, y, line);
+                            y += 14;
+                        }
+                    },
+                    EditorWindowType::MaterialProblems => {
+                        ui.text(ox + 10, oy + 20, "These materials loaded with problems:");
+                        let mut y = oy + 40;
+                        for (material, problem) in world.scene.material_problems.iter() {
+                            ui.text(ox + 10, y, &format!("{}: {}", material, problem));
+                            y += 14;
+                        }
+                    },
+                    EditorWindowType::Outliner => {
+                        let selected = world.editor_data.selected_object.as_ref().map(|selection| selection.items()).unwrap_or_default();
+                        let summary = match selected.as_slice() {
+                            [] => String::from("Nothing selected"),
+                            [Selection::Model(model)] => format!("Selected {}", world.model_label(*model)),
+                            [Selection::Brush(brush)] => format!("Selected brush {}", brush),
+                            items => format!("{} objects selected", items.len())
+                        };
+                        ui.text(ox + 10, oy + 20, &summary);
+                        ui.text(ox + 10, oy + 36, "Shift click to select more");
+
+                        let mut rows = Vec::new();
+                        for (index, model) in world.models.iter().enumerate() {
+                            let Some(model) = model else { continue; };
+                            if !world.can_be_selected(index) { continue; }
+                            let contents = match model.render.first() {
+                                Some(Renderable::Mesh(name, ..)) => format!("mesh {}", name),
+                                Some(Renderable::Brush(material, ..)) => format!("brush {}", material),
+                                Some(Renderable::Billboard(texture, ..)) => format!("billboard {}", texture),
+                                Some(Renderable::Text(text, ..)) => format!("text {}", text.text),
+                                None => String::from("empty")
+                            };
+                            rows.push((Selection::Model(index), format!("{}  {}", world.model_label(index), contents)));
+                        }
+                        if let Some(brushes) = world.models[world.internal.brushes].as_ref() {
+                            for (index, brush) in brushes.render.iter().enumerate() {
+                                if let Renderable::Brush(material, ..) = brush {
+                                    rows.push((Selection::Brush(index), format!("brush {}  {}", index, material)));
+                                }
+                            }
+                        }
+
+                        let mut y = oy + 56;
+                        for (item, label) in rows {
+                            let is_selected = selected.iter().any(|selected| match (selected, &item) {
+                                (Selection::Model(a), Selection::Model(b)) | (Selection::Brush(a), Selection::Brush(b)) => a == b,
+                                _ => false
+                            });
+                            let label = if is_selected { format!("> {}", label) } else { label };
+                            if ui.text_button(input, ox + 10, y, &label) {
+                                let shift = input.get_key_pressed(Key::Named(NamedKey::Shift));
+                                match item {
+                                    Selection::Model(model) if shift && !is_selected => world.select_or_append_model(model),
+                                    Selection::Brush(brush) if shift && !is_selected => world.select_or_append_brush(brush),
+                                    Selection::Model(model) if !shift => {
+                                        world.deselect();
+                                        world.select_model(model);
+                                    },
+                                    Selection::Brush(brush) if !shift => {
+                                        world.deselect();
+                                        world.select_brush(brush);
+                                    },
+                                    _ => ()
+                                }
+                                world.set_arrows_visible(true);
+                            }
+                            y += 22;
+                        }
+                    },
+                    EditorWindowType::Ghosts => {
+                        let mut y = oy + 20;
+                        if ui.text_button(input, ox + 10, y, &format!("Record runs: {}", if world.ghosts.record { "on" } else { "off" })) {
+                            world.ghosts.record = !world.ghosts.record;
+                        }
+                        y += 24;
+                        match &world.ghosts.last_run {
+                            Some(run) => ui.text(ox + 10, y, &format!("Last run: {:.1}s", run.duration())),
+                            None if world.ghosts.record => ui.text(ox + 10, y, "Play the level to record a run"),
+                            None => ui.text(ox + 10, y, "Turn on recording and play the level to record a run")
+                        }
+                        y += 16;
+                        if world.ghosts.last_run.is_some() {
+                            ui.text(ox + 10, y + 5, "Save as");
+                            if let Some(name) = window.text_field(input, ox + 70, y, 186, "", ui) {
+                                match world.save_last_run(&name) {
+                                    Ok(()) => debug_messages.push(format!("saved ghost {}", name.trim())),
+                                    Err(e) => debug_messages.push(e)
+                                }
+                            }
+                            y += 24;
+                        }
+
+                        match &world.ghosts.playback {
+                            Some(run) => ui.text(ox + 10, y, &format!("Racing a {:.1}s run", run.duration())),
+                            None => ui.text(ox + 10, y, "Not racing a ghost")
+                        }
+                        y += 16;
+                        ui.begin_row(ox + 10, y, 8);
+                            if world.ghosts.last_run.is_some() && ui.text_button(input, 0, 0, "Race last run") {
+                                world.ghosts.playback = world.ghosts.last_run.clone();
+                            }
+                            if world.ghosts.playback.is_some() && ui.text_button(input, 0, 0, "Stop racing") {
+                                world.ghosts.playback = None;
+                            }
+                        ui.end_layout();
+                        y += 28;
+
+                        ui.text(ox + 10, y, &format!("Saved for this level in {}", GHOST_DIRECTORY));
+                        y += 16;
+                        for name in world.saved_ghosts().to_vec() {
+                            if ui.text_button(input, ox + 10, y, &name) {
+                                match GhostRun::load(&name) {
+                                    Ok(run) => world.ghosts.playback = Some(run),
+                                    Err(e) => debug_messages.push(e)
+                                }
+                            }
+                            y += 22;
+                        }
+                    },
+                    EditorWindowType::Events => {
+                        let log = &mut world.events;
+                        let mut y = oy + 20;
+                        ui.begin_row(ox + 10, y, 8);
+                            if ui.text_button(input, 0, 0, &format!("Show while playing: {}", if log.overlay { "on" } else { "off" })) {
+                                log.overlay = !log.overlay;
+                            }
+                            if ui.text_button(input, 0, 0, "Clear") {
+                                log.events.clear();
+                            }
+                        ui.end_layout();
+                        y += 28;
+
+                        // Two columns of filters
+                        for (i, kind) in EventKind::ALL.into_iter().enumerate() {
+                            let shown = !log.hidden.contains(&kind);
+                            if Self::mixed_checkbox(ui, input, ox + 10 + (i % 2) as i32 * 180, y + (i / 2) as i32 * 20, Some(shown), kind.name()) {
+                                log.toggle_kind(kind);
+                            }
+                        }
+                        y += EventKind::ALL.len().div_ceil(2) as i32 * 20 + 8;
+
+                        if log.events.is_empty() {
+                            ui.text(ox + 10, y, "Play the level to log events");
+                        }
+                        for event in log.visible().take(EVENTS_SHOWN) {
+                            ui.text(ox + 10, y, &event.describe());
+                            y += 14;
+                        }
+                    },
+                    EditorWindowType::Session => {
+                        let stats = &mut world.editor_data.session;
+                        let mut y = oy + 20;
+                        let lines = [
+                            format!("Models created: {}, deleted: {}", stats.models_created, stats.models_deleted),
+                            format!("Brushes created: {}, deleted: {}", stats.brushes_created, stats.brushes_deleted),
+                            format!("Moved or scaled: {}", stats.moves),
+                            format!("Total edits: {}", stats.total_edits())
+                        ];
+                        for line in lines {
+                            ui.text(ox + 10, y, &line);
+                            y += 14;
+                        }
+                        y += 6;
+                        ui.text(ox + 10, y, "Time in each tool:");
+                        y += 14;
+                        for (tool, time) in stats.tool_time.iter() {
+                            ui.text(ox + 20, y, &format!("{}: {}", tool, session::format_duration(*time)));
+                            y += 14;
+                        }
+                        y += 6;
+
+                        if Self::checkbox(ui, input, ox + 10, y, stats.record_heatmap, "Record heatmap") {
+                            stats.record_heatmap = !stats.record_heatmap;
+                        }
+                        y += 20;
+                        if Self::checkbox(ui, input, ox + 10, y, stats.show_heatmap, "Show heatmap") {
+                            stats.show_heatmap = !stats.show_heatmap;
+                        }
+                        y += 20;
+                        ui.text(ox + 10, y, &format!("{} cells edited, the busiest {} times", stats.heatmap.len(), stats.hottest()));
+                        y += 16;
+                        ui.begin_row(ox + 10, y, 8);
+                            if ui.text_button(input, 0, 0, "Export") {
+                                match stats.export() {
+                                    Ok(path) => debug_messages.push(format!("exported session stats to {}", path)),
+                                    Err(e) => debug_messages.push(e)
+                                }
+                            }
+                            if ui.text_button(input, 0, 0, "Clear heatmap") {
+                                stats.heatmap.clear();
+                            }
+                        ui.end_layout();
+                    },
+                    EditorWindowType::Effects => {
+                        let mut y = oy + 20;
+                        if ui.text_button(input, ox + 10, y, &format!("Reload {}", post::POST_DIRECTORY)) {
+                            let (mut chain, errors) = post::load_post_effects(programs, gl);
+                            // Keep the order and settings of effects that were already loaded
+                            let old = mem::take(&mut world.scene.post_process.chain);
+                            chain.sort_by_key(|effect| old.iter().position(|old| old.file == effect.file).unwrap_or(usize::MAX));
+                            for effect in chain.iter_mut() {
+                                if let Some(old) = old.iter().find(|old| old.file == effect.file) {
+                                    effect.enabled = old.enabled;
+                                    if old.uniforms.iter().map(|u| &u.name).eq(effect.uniforms.iter().map(|u| &u.name)) {
+                                        effect.values = old.values.clone();
+                                    }
+                                }
+                            }
+                            debug_messages.push(format!("loaded {} post effects", chain.len()));
+                            debug_messages.extend(errors);
+                            world.scene.post_process.chain = chain;
+                        }
+                        y += 28;
+
+                        // Built in effects are the level's defaults, saved with it
+                        let defaults = &mut world.scene.world_default_effects;
+                        let post_process = &mut world.scene.post_process;
+                        if Self::checkbox(ui, input, ox + 10, y, defaults.fog.is_some(), "Fog") {
+                            defaults.fog = if defaults.fog.is_some() { None } else { Some(FogEffect { color: vec3(0.5, 0.5, 0.55), strength: 64.0, max: 0.9 }) };
+                            post_process.fog = defaults.fog.clone();
+                        }
+                        y += 22;
+                        if let Some(fog) = &mut defaults.fog {
+                            let before = (fog.color, fog.strength, fog.max);
+                            for (c, label) in ["red", "green", "blue"].iter().enumerate() {
+                                ui.text(ox + 20, y, &format!("{}: {:.2}", label, fog.color[c]));
+                                if let Some(changed) = window.value_slider(input, ox + 170, y + 4, 150, fog.color[c], 0.0, 1.0, ui) {
+                                    fog.color[c] = changed;
+                                }
+                                y += 18;
+                            }
+                            ui.text(ox + 20, y, &format!("strength: {:.0}", fog.strength));
+                            if let Some(changed) = window.value_slider(input, ox + 170, y + 4, 150, fog.strength, 1.0, 128.0, ui) {
+                                fog.strength = changed;
+                            }
+                            y += 18;
+                            ui.text(ox + 20, y, &format!("max: {:.2}", fog.max));
+                            if let Some(changed) = window.value_slider(input, ox + 170, y + 4, 150, fog.max, 0.0, 1.0, ui) {
+                                fog.max = changed;
+                            }
+                            y += 26;
+                            if before != (fog.color, fog.strength, fog.max) {
+                                post_process.fog = Some(fog.clone());
+                            }
+                        }
+
+                        if Self::checkbox(ui, input, ox + 10, y, defaults.kernel.is_some(), "Kernel") {
+                            defaults.kernel = if defaults.kernel.is_some() { None } else { Some(KernelEffect { kernel: KERNEL_PRESETS[0].1, offset: 1.0 / 300.0 }) };
+                            post_process.kernel = defaults.kernel.clone();
+                        }
+                        if let Some(kernel) = &mut defaults.kernel {
+                            if ui.text_button(input, ox + 220, y, kernel.preset().unwrap_or("custom")) {
+                                let current = KERNEL_PRESETS.iter().position(|(_, preset)| *preset == kernel.kernel);
+                                kernel.kernel = KERNEL_PRESETS[current.map_or(0, |i| (i + 1) % KERNEL_PRESETS.len())].1;
+                                post_process.kernel = Some(kernel.clone());
+                            }
+                            y += 22;
+                            ui.text(ox + 20, y, &format!("offset: {:.4}", kernel.offset));
+                            if let Some(changed) = window.value_slider(input, ox + 170, y + 4, 150, kernel.offset, 0.0005, 0.01, ui) {
+                                kernel.offset = changed;
+                                post_process.kernel = Some(kernel.clone());
+                            }
+                            y += 26;
+                        } else {
+                            y += 22;
+                        }
+
+                        y += 8;
+
+                        let chain = &mut world.scene.post_process.chain;
+                        if chain.is_empty() {
+                            ui.text(ox + 10, y, "No effects, put a .frag.glsl and a .json manifest");
+                            ui.text(ox + 10, y + 14, &format!("in {} and reload", post::POST_DIRECTORY));
+                        }
+                        let mut swap = None;
+                        for i in 0..chain.len() {
+                            let effect = &mut chain[i];
+                            if Self::checkbox(ui, input, ox + 10, y, effect.enabled, &effect.name) {
+                                effect.enabled = !effect.enabled;
+                            }
+                            ui.begin_row(ox + 220, y, 4);
+                                if i > 0 && ui.text_button(input, 0, 0, "Up") {
+                                    swap = Some((i - 1, i));
+                                }
+                                if i + 1 < chain.len() && ui.text_button(input, 0, 0, "Down") {
+                                    swap = Some((i, i + 1));
+                                }
+                                if ui.text_button(input, 0, 0, "Reset") {
+                                    chain[i].reset();
+                                }
+                            ui.end_layout();
+                            y += 22;
+
+                            let effect = &mut chain[i];
+                            for (uniform, value) in effect.uniforms.iter().zip(effect.values.iter_mut()) {
+                                let components = match value {
+                                    UniformValue::Float(value) => slice::from_mut(value),
+                                    UniformValue::Vector(values) => values.as_mut_slice()
+                                };
+                                let count = components.len();
+                                for (c, component) in components.iter_mut().enumerate() {
+                                    let label = if count > 1 { format!("{}.{}", uniform.name, ["x", "y", "z", "w"][c]) } else { uniform.name.clone() };
+                                    ui.text(ox + 20, y, &format!("{}: {:.2}", label, component));
+                                    if let Some(changed) = window.value_slider(input, ox + 170, y + 4, 150, *component, uniform.min, uniform.max, ui) {
+                                        *component = changed;
+                                    }
+                                    y += 18;
+                                }
+                            }
+                            y += 8;
+                        }
+                        if let Some((a, b)) = swap {
+                            chain.swap(a, b);
+                        }
+                    },
+                    EditorWindowType::ScaleReferences => {
+                        let references = &mut world.editor_data.references;
+                        let mut y = oy + 20;
+                        if Self::checkbox(ui, input, ox + 10, y, references.ruler, "Ruler") {
+                            references.ruler = !references.ruler;
+                        }
+                        y += 22;
+                        if Self::checkbox(ui, input, ox + 10, y, references.figure, "Player figure") {
+                            references.figure = !references.figure;
+                        }
+                        y += 22;
+                        if Self::checkbox(ui, input, ox + 10, y, references.templates, "Door and corridor templates") {
+                            references.templates = !references.templates;
+                        }
+                        y += 22;
+                        if Self::checkbox(ui, input, ox + 10, y, references.measure, "Measure between clicks") {
+                            references.measure = !references.measure;
+                            references.clear_measurement();
+                        }
+                        y += 26;
+                        let size = reference::template_size();
+                        ui.text(ox + 10, y, &format!("Door: {:.2} wide, {:.2} tall", size.x, size.y));
+                        ui.text(ox + 10, y + 14, "Drawn on the surface under the cursor");
+                        y += 34;
+                        match world.measurement() {
+                            Some(measurement) => {
+                                let delta = measurement.delta;
+                                ui.text(ox + 10, y, &format!("Distance: {:.2}{}", measurement.distance, if measurement.placing { " (click to place the end)" } else { "" }));
+                                ui.text(ox + 10, y + 14, &format!("X: {:.2}  Y: {:.2}  Z: {:.2}", delta.x.abs(), delta.y.abs(), delta.z.abs()));
+                            },
+                            None if world.editor_data.references.measure => ui.text(ox + 10, y, "Click a surface to start measuring"),
+                            None => ()
+                        }
+                    },
+                    EditorWindowType::Connections => {
+                        let mut y = oy + 20;
+                        if let Some(Selection::Model(model)) = world.editor_data.selected_object {
+                            let outputs = world.model_outputs(model);
+                            ui.text(ox + 10, y, &format!("Outputs: {}", if outputs.is_empty() { String::from("none") } else { outputs.join(", ") }));
+                            y += 20;
+
+                            let mut connections = world.models[model].as_ref().unwrap().connections.clone();
+                            let mut remove = None;
+                            for (i, connection) in connections.iter_mut().enumerate() {
+                                let fields = [("Output", connection.output.clone()), ("Target", connection.target.clone()), ("Input", connection.input.clone())];
+                                for (field, (label, value)) in fields.into_iter().enumerate() {
+                                    ui.text(ox + 10, y + 5, label);
+                                    if let Some(entered) = window.text_field(input, ox + 66, y, 190, &value, ui) {
+                                        let entered = entered.trim().to_string();
+                                        match field {
+                                            0 => connection.output = entered,
+                                            1 => connection.target = entered,
+                                            _ => connection.input = entered
+                                        }
+                                    }
+                                    y += 24;
+                                }
+                                connection.delay = (connection.delay + Self::stepper(ui, input, ox + 10, y, &format!("Delay: {:.1}s", connection.delay), 0.5)).max(0.0);
+                                if Self::mixed_checkbox(ui, input, ox + 160, y, Some(connection.once), "Once") {
+                                    connection.once = !connection.once;
+                                }
+                                if ui.text_button(input, ox + 230, y, "Remove") {
+                                    remove = Some(i);
+                                }
+                                y += 24;
+                                let hint = match world.find_by_name(&connection.target) {
+                                    Some(target) => format!("{} takes {}", connection.target, world.model_inputs(target).join(", ")),
+                                    None => format!("No model is named {}", connection.target)
+                                };
+                                ui.text(ox + 10, y, &hint);
+                                y += 24;
+                            }
+                            if let Some(i) = remove {
+                                connections.remove(i);
+                            }
+                            if ui.text_button(input, ox + 10, y, "Add connection") {
+                                connections.push(Connection::new(outputs.first().copied().unwrap_or("OnUser")));
+                            }
+                            world.models[model].as_mut().unwrap().connections = connections;
+                        } else {
+                            ui.text(ox + 10, y, "Select a model to edit its connections");
+                        }
+                    },
+                    EditorWindowType::Performance => {
+                        let mut y = oy + 20;
+                        for usage in world.editor_data.stats.iter() {
+                            ui.fill(ox + 10, y, 10, 10, usage.status().color());
+                            ui.text(ox + 26, y, &format!("{}: {} of {}", usage.budget.name(), usage.budget.format(usage.used), usage.budget.format(usage.limit)));
+                            y += 16;
+                            for (name, amount) in usage.offenders.iter() {
+                                ui.text(ox + 26, y, &format!("{}  {}", usage.budget.format(*amount), name));
+                                y += 14;
+                            }
+                            y += 8;
+                        }
+                        ui.text(ox + 10, y, "Budgets are set in Level Properties");
+                    },
+                    EditorWindowType::Inspector => {
+                        // Conversions go first so the renderables listed below are up to date
+                        let mut y = oy + 20;
+                        match world.editor_data.selected_object {
+                            Some(Selection::Brush(brush)) => {
+                                ui.frame(ox + 10, y, 150, 20);
+                                    if ui.image_button(input, 1, 1, 148, 18, (0, 0), (1, 1), "evil_pixel") {
+                                        let model = world.make_brush_unique(brush);
+                                        world.deselect();
+                                        world.select_model(model);
+                                        world.set_arrows_visible(true);
+                                    }
+                                    ui.text(4, 5, "Make unique");
+                                ui.pop();
+                                y += 28;
+                            },
+                            Some(Selection::Model(model)) => {
+                                ui.frame(ox + 10, y, 150, 20);
+                                    if ui.image_button(input, 1, 1, 148, 18, (0, 0), (1, 1), "evil_pixel") {
+                                        match world.absorb_brush_model(model) {
+                                            Ok(brush) => {
+                                                world.deselect();
+                                                world.select_brush(brush);
+                                                world.set_arrows_visible(true);
+                                            },
+                                            Err(e) => debug_messages.push(e)
+                                        }
+                                    }
+                                    ui.text(4, 5, "Convert to brush");
+                                ui.pop();
+                                y += 28;
+                            },
+                            _ => ()
+                        }
+
+                        let items = world.editor_data.selected_object.as_ref().map(|selection| selection.items()).unwrap_or_default();
+                        if items.is_empty() {
+                            ui.text(ox + 10, y, "Nothing selected");
+                        } else {
+                            if items.len() > 1 {
+                                ui.text(ox + 10, y, &format!("{} objects selected", items.len()));
+                                y += 16;
+                            }
+
+                            // Position, a field shows its value only if every selected object shares it
+                            let origins: Vec<Vector3<f32>> = items.iter().map(|item| world.selection_item_origin(item)).collect();
+                            for (axis, name) in ["X", "Y", "Z"].iter().enumerate() {
+                                let first = origins[0][axis];
+                                let shared = origins.iter().all(|origin| (origin[axis] - first).abs() < 0.001);
+                                let value = if shared { format!("{}", first) } else { String::from("—") };
+                                ui.text(ox + 10, y + 5, name);
+                                if let Some(entered) = window.text_field(input, ox + 24, y, 120, &value, ui) {
+                                    match entered.trim().parse::<f32>() {
+                                        Ok(value) => world.set_selection_axis(axis, value),
+                                        Err(_) => debug_messages.push(format!("{} is not a number", entered))
+                                    }
+                                }
+                                y += 24;
+                            }
+                            y += 4;
+
+                            if items.iter().any(|item| matches!(item, Selection::Model(_))) {
+                                let components = [
+                                    ("Add spawnpoint", Component::Spawnpoint),
+                                    ("Add door", Component::Door(Door::new(8.0, 1.0, 60))),
+                                    ("Add sound emitter", Component::SoundEmitter(SoundEmitter::new(""))),
+                                    ("Add draw distance", Component::DrawDistance(DrawDistance::new())),
+                                    ("Add objective", Component::Objective(Objective::new("Objective"))),
+                                    ("Add button", Component::Button(Button::new())),
+                                    ("Add mover", Component::Mover(Mover::new())),
+                                    ("Add rotator", Component::Rotator(Rotator::new(Vector3::unit_y(), 90.0))),
+                                    ("Add hurt volume", Component::Hurt { dps: rules::DEFAULT_HURT_DPS, touching: false }),
+                                    ("Add goal", Component::Goal(Goal::default())),
+                                    ("Add collectible", Component::Collectible),
+                                    ("Add camera feed", Component::CameraFeed(CameraFeed::default())),
+                                    ("Add reflection plane", Component::ReflectionPlane(ReflectionPlane::default())),
+                                    ("Add start line", Component::Trigger(Trigger::new(TriggerType::StartTimer)))
+                                ];
+                                for (name, component) in components {
+                                    ui.frame(ox + 10, y, 150, 20);
+                                        if ui.image_button(input, 1, 1, 148, 18, (0, 0), (1, 1), "evil_pixel") {
+                                            let added = world.add_component_to_selection(component);
+                                            debug_messages.push(format!("added component to {} models", added));
+                                        }
+                                        ui.text(4, 5, name);
+                                    ui.pop();
+                                    y += 24;
+                                }
+                                y += 4;
+                            }
+
+                            if let [Selection::Model(model)] = items.as_slice() {
+                                let name = world.models[*model].as_ref().unwrap().name.clone().unwrap_or_default();
+                                ui.text(ox + 10, y + 5, "Name");
+                                if let Some(entered) = window.text_field(input, ox + 56, y, 200, &name, ui) {
+                                    let entered = entered.trim();
+                                    if let Err(e) = world.set_model_name(*model, (!entered.is_empty()).then(|| entered.to_string())) {
+                                        debug_messages.push(e);
+                                    }
+                                }
+                                y += 24;
+
+                                let tags = world.models[*model].as_ref().unwrap().tags.join(", ");
+                                ui.text(ox + 10, y + 5, "Tags");
+                                if let Some(entered) = window.text_field(input, ox + 56, y, 200, &tags, ui) {
+                                    let tags = entered.split(',').map(str::trim).filter(|tag| !tag.is_empty()).map(str::to_owned).collect();
+                                    world.models[*model].as_mut().unwrap().tags = tags;
+                                }
+                                y += 24;
+
+                                if let Some(mut door) = world.get_door(*model) {
+                                    ui.text(ox + 10, y + 5, "Trigger");
+                                    if let Some(entered) = window.text_field(input, ox + 70, y, 186, door.trigger.as_deref().unwrap_or(""), ui) {
+                                        let entered = entered.trim();
+                                        door.trigger = (!entered.is_empty()).then(|| entered.to_string());
+                                        world.set_door(*model, door);
+                                    }
+                                    y += 24;
+                                }
+
+                                if let Some((target_name, action)) = world.get_trigger_target(*model) {
+                                    ui.text(ox + 10, y + 5, "Fires");
+                                    if let Some(entered) = window.text_field(input, ox + 70, y, 186, &target_name, ui) {
+                                        world.set_trigger_target(*model, entered.trim().to_string(), action);
+                                    }
+                                    y += 24;
+                                    if ui.text_button(input, ox + 10, y, &format!("Action: {}", action.name())) {
+                                        let next = TargetAction::ALL[(TargetAction::ALL.iter().position(|a| *a == action).unwrap() + 1) % TargetAction::ALL.len()];
+                                        world.set_trigger_target(*model, target_name, next);
+                                    }
+                                    y += 24;
+                                }
+
+                                if let Some(mut button) = world.get_button(*model) {
+                                    ui.text(ox + 10, y + 5, "Presses");
+                                    if let Some(entered) = window.text_field(input, ox + 70, y, 186, button.target.as_deref().unwrap_or(""), ui) {
+                                        let entered = entered.trim();
+                                        button.target = (!entered.is_empty()).then(|| entered.to_string());
+                                    }
+                                    y += 24;
+                                    if ui.text_button(input, ox + 10, y, &format!("Action: {}", button.action.name())) {
+                                        button.action = TargetAction::ALL[(TargetAction::ALL.iter().position(|a| *a == button.action).unwrap() + 1) % TargetAction::ALL.len()];
+                                    }
+                                    y += 24;
+                                    ui.begin_column(ox + 10, y, 4);
+                                    button.use_range = (button.use_range + Self::stepper(ui, input, 0, 0, &format!("Use range: {:.1}", button.use_range), 0.5)).max(0.5);
+                                    button.press_time = (button.press_time + Self::stepper(ui, input, 0, 0, &format!("Press time: {:.1}s", button.press_time), 0.1)).max(0.1);
+                                    ui.end_layout();
+                                    y += 48;
+                                    world.set_button(*model, button);
+                                }
+
+                                if let Some(dps) = world.get_hurt_dps(*model) {
+                                    let change = Self::stepper(ui, input, ox + 10, y, &format!("Hurts: {:.0} per second", dps), 5.0);
+                                    if change != 0.0 {
+                                        world.set_hurt_dps(*model, dps + change);
+                                    }
+                                    y += 24;
+                                }
+
+                                if let Some(mut goal) = world.get_goal(*model) {
+                                    ui.text(ox + 10, y + 5, "Needs");
+                                    if let Some(entered) = window.text_field(input, ox + 56, y, 200, &goal.objectives.join(", "), ui) {
+                                        goal.objectives = entered.split(',').map(str::trim).filter(|title| !title.is_empty()).map(str::to_owned).collect();
+                                    }
+                                    y += 24;
+                                    let change = Self::stepper(ui, input, ox + 10, y, &format!("Collectibles: {} of {}", goal.collectibles, world.collectible_count()), 1.0);
+                                    goal.collectibles = (goal.collectibles as f32 + change).max(0.0) as u32;
+                                    y += 24;
+                                    world.set_goal(*model, goal);
+                                }
+
+                                if let Some(mut rotator) = world.get_rotator(*model) {
+                                    ui.text(ox + 10, y + 5, "Axis");
+                                    let value = format!("{} {} {}", rotator.axis[0], rotator.axis[1], rotator.axis[2]);
+                                    if let Some(entered) = window.text_field(input, ox + 56, y, 200, &value, ui) {
+                                        let parsed: Vec<f32> = entered.split_whitespace().filter_map(|axis| axis.parse().ok()).collect();
+                                        match parsed[..] {
+                                            [ax, ay, az] => rotator.axis = [ax, ay, az],
+                                            _ => debug_messages.push(format!("{} is not three numbers", entered))
+                                        }
+                                    }
+                                    y += 24;
+                                    rotator.speed += Self::stepper(ui, input, ox + 10, y, &format!("Spin: {:.0} degrees/s", rotator.speed), 15.0);
+                                    y += 24;
+                                    world.set_rotator(*model, rotator);
+                                }
+
+                                if let Some(mut mover) = world.get_mover(*model) {
+                                    ui.text(ox + 10, y, "Stops, relative to the model");
+                                    y += 16;
+                                    let mut removed = None;
+                                    for (i, stop) in mover.stops.iter_mut().enumerate() {
+                                        let value = format!("{} {} {}", stop[0], stop[1], stop[2]);
+                                        if let Some(entered) = window.text_field(input, ox + 10, y, 200, &value, ui) {
+                                            let parsed: Vec<f32> = entered.split_whitespace().filter_map(|axis| axis.parse().ok()).collect();
+                                            match parsed[..] {
+                                                [sx, sy, sz] => *stop = [sx, sy, sz],
+                                                _ => debug_messages.push(format!("{} is not three numbers", entered))
+                                            }
+                                        }
+                                        if ui.text_button(input, ox + 216, y, "x") {
+                                            removed = Some(i);
+                                        }
+                                        y += 24;
+                                    }
+                                    if let Some(i) = removed {
+                                        mover.stops.remove(i);
+                                    }
+                                    if ui.text_button(input, ox + 10, y, "Add stop") {
+                                        let last = mover.stops.last().copied().unwrap_or_default();
+                                        mover.stops.push([last[0], last[1] + 2.0, last[2]]);
+                                    }
+                                    y += 24;
+                                    if ui.text_button(input, ox + 10, y, &format!("Mode: {}", mover.mode.name())) {
+                                        mover.mode = MoverMode::ALL[(MoverMode::ALL.iter().position(|m| *m == mover.mode).unwrap() + 1) % MoverMode::ALL.len()];
+                                    }
+                                    y += 24;
+                                    ui.begin_column(ox + 10, y, 4);
+                                    mover.speed = (mover.speed + Self::stepper(ui, input, 0, 0, &format!("Speed: {:.1}", mover.speed), 0.5)).max(0.5);
+                                    mover.wait = (mover.wait + Self::stepper(ui, input, 0, 0, &format!("Wait: {:.1}s", mover.wait), 0.5)).max(0.0);
+                                    ui.end_layout();
+                                    y += 48;
+                                    if Self::checkbox(ui, input, ox + 10, y, mover.start_stopped, "Wait for Start input") {
+                                        mover.start_stopped = !mover.start_stopped;
+                                    }
+                                    y += 24;
+                                    world.set_mover(*model, mover);
+                                }
+
+                                if let Some(mut objective) = world.get_objective(*model) {
+                                    let fields = [("Objective", objective.title.clone()), ("Target", objective.target.clone().unwrap_or_default()), ("Done by", objective.trigger.clone().unwrap_or_default())];
+                                    for (field, (label, value)) in fields.into_iter().enumerate() {
+                                        ui.text(ox + 10, y + 5, label);
+                                        if let Some(entered) = window.text_field(input, ox + 76, y, 180, &value, ui) {
+                                            let entered = entered.trim().to_string();
+                                            match field {
+                                                0 if !entered.is_empty() => objective.title = entered,
+                                                1 => objective.target = (!entered.is_empty()).then_some(entered),
+                                                2 => objective.trigger = (!entered.is_empty()).then_some(entered),
+                                                _ => ()
+                                            }
+                                        }
+                                        y += 24;
+                                    }
+                                    objective.order = (objective.order as f32 + Self::stepper(ui, input, ox + 10, y, &format!("Order: {}", objective.order), 1.0)).max(0.0) as u32;
+                                    y += 24;
+                                    world.set_objective(*model, objective);
+                                }
+
+                                for link in world.links().into_iter().filter(|link| link.source == *model && link.target.is_none()) {
+                                    ui.text(ox + 10, y, &format!("No model is named {}", link.name));
+                                    y += 16;
+                                }
+
+                                let (rotation, scale) = common::rotation_scale(world.models[*model].as_ref().unwrap().transform);
+                                for (row, (label, current)) in [("Rotation", rotation), ("Scale", scale)].into_iter().enumerate() {
+                                    ui.text(ox + 10, y, label);
+                                    y += 14;
+                                    for (axis, name) in ["X", "Y", "Z"].iter().enumerate() {
+                                        let x = ox + 10 + axis as i32 * 84;
+                                        ui.text(x, y + 5, name);
+                                        if let Some(entered) = window.text_field(input, x + 14, y, 64, &format!("{:.2}", current[axis]), ui) {
+                                            match entered.trim().parse::<f32>() {
+                                                Ok(value) => {
+                                                    let (mut rotation, mut scale) = (rotation, scale);
+                                                    if row == 0 { rotation[axis] = value; } else { scale[axis] = value; }
+                                                    world.set_model_rotation_scale(*model, rotation, scale);
+                                                },
+                                                Err(_) => debug_messages.push(format!("{} is not a number", entered))
+                                            }
+                                        }
+                                    }
+                                    y += 24;
+                                }
+
+                                let (solid, mobile, foreground) = {
+                                    let current = world.models[*model].as_ref().unwrap();
+                                    (current.solid, current.mobile, current.foreground)
+                                };
+                                if Self::checkbox(ui, input, ox + 10, y, solid, "Solid") {
+                                    world.set_model_solid(*model, !solid);
+                                }
+                                y += 20;
+                                if Self::checkbox(ui, input, ox + 10, y, mobile, "Mobile") {
+                                    if let Err(e) = world.set_model_render_mode(*model, !mobile, foreground) {
+                                        debug_messages.push(e);
+                                    }
+                                }
+                                y += 20;
+                                if Self::checkbox(ui, input, ox + 10, y, foreground, "Foreground") {
+                                    if let Err(e) = world.set_model_render_mode(*model, mobile, !foreground) {
+                                        debug_messages.push(e);
+                                    }
+                                }
+                                y += 24;
+
+                                if let Some(mut door) = world.get_door(*model) {
+                                    ui.begin_column(ox + 10, y, 4);
+                                    door.radius = (door.radius + Self::stepper(ui, input, 0, 0, &format!("Door radius: {:.1}", door.radius), 0.5)).max(0.5);
+                                    door.height = (door.height + Self::stepper(ui, input, 0, 0, &format!("Door height: {:.2}", door.height), 0.25)).max(0.25);
+                                    door.open_time = (door.open_time as f32 + Self::stepper(ui, input, 0, 0, &format!("Open time: {} frames", door.open_time), 10.0)).max(1.0) as u32;
+                                    ui.end_layout();
+                                    y += 72;
+                                    world.set_door(*model, door);
+                                }
+
+                                if let Some(mut emitter) = world.get_sound_emitter(*model) {
+                                    ui.text(ox + 10, y + 5, "Sound");
+                                    if let Some(sound) = window.text_field(input, ox + 56, y, 200, &emitter.sound, ui) {
+                                        emitter.sound = sound.trim().to_string();
+                                    }
+                                    y += 24;
+                                    ui.begin_column(ox + 10, y, 4);
+                                    emitter.volume = (emitter.volume + Self::stepper(ui, input, 0, 0, &format!("Volume: {:.0}%", emitter.volume * 100.0), 0.1)).clamp(0.0, 2.0);
+                                    emitter.radius = (emitter.radius + Self::stepper(ui, input, 0, 0, &format!("Hearing radius: {:.0}", emitter.radius), 1.0)).max(1.0);
+                                    ui.end_layout();
+                                    y += 48;
+                                    world.set_sound_emitter(*model, emitter);
+                                }
+
+                                if let Some(mut settings) = world.get_draw_distance(*model) {
+                                    ui.begin_column(ox + 10, y, 4);
+                                    settings.distance = (settings.distance + Self::stepper(ui, input, 0, 0, &format!("Draw distance: {:.0}", settings.distance), 5.0)).max(5.0);
+                                    let far = if settings.far == FarMode::Impostor { "Far: impostor" } else { "Far: fade out" };
+                                    if ui.text_button(input, 0, 0, far) {
+                                        settings.far = if settings.far == FarMode::Impostor { FarMode::Fade } else { FarMode::Impostor };
+                                    }
+                                    ui.end_layout();
+                                    y += 48;
+                                    if !world.models[*model].as_ref().unwrap().mobile {
+                                        ui.text(ox + 10, y, "Only mobile models fade");
+                                        y += 16;
+                                    }
+                                    world.set_draw_distance(*model, settings);
+                                }
+
+                                if let Some(mut feed) = world.get_camera_feed(*model) {
+                                    ui.text(ox + 10, y + 5, "Feed");
+                                    if let Some(entered) = window.text_field(input, ox + 56, y, 200, &feed.name, ui) {
+                                        feed.name = entered.trim().to_string();
+                                    }
+                                    y += 24;
+                                    ui.text(ox + 10, y, &format!("Materials show it with diffuse {}", feed::texture_name(&feed.name)));
+                                    y += 16;
+                                    ui.begin_column(ox + 10, y, 4);
+                                    feed.fov = (feed.fov + Self::stepper(ui, input, 0, 0, &format!("Field of view: {:.0}", feed.fov), 5.0)).clamp(10.0, 150.0);
+                                    let size = (feed.resolution.0 as f32 + Self::stepper(ui, input, 0, 0, &format!("Resolution: {}x{}", feed.resolution.0, feed.resolution.1), 64.0)).clamp(64.0, MAX_FEED_RESOLUTION as f32) as u32;
+                                    // Keeps the aspect of resolutions set in the level file
+                                    if size != feed.resolution.0 {
+                                        feed.resolution = (size, (size as f32 * feed.resolution.1 as f32 / feed.resolution.0 as f32).round().max(1.0) as u32);
+                                    }
+                                    feed.interval = (feed.interval as f32 + Self::stepper(ui, input, 0, 0, &format!("Every {} frames", feed.interval), 1.0)).clamp(1.0, 60.0) as u32;
+                                    ui.end_layout();
+                                    y += 72;
+                                    world.set_camera_feed(*model, feed);
+                                }
+
+                                if let Some(mut plane) = world.get_reflection_plane(*model) {
+                                    ui.begin_column(ox + 10, y, 4);
+                                    plane.resolution_scale = (plane.resolution_scale + Self::stepper(ui, input, 0, 0, &format!("Reflection resolution: {:.0}%", plane.resolution_scale * 100.0), 0.125)).clamp(0.125, 1.0);
+                                    ui.end_layout();
+                                    y += 24;
+                                    ui.text(ox + 10, y, "Reflects along the model's +Y");
+                                    y += 16;
+                                    world.set_reflection_plane(*model, plane);
+                                }
+                            }
+
+                            if let [Selection::Brush(brush)] = items.as_slice() {
+                                let Renderable::Brush(material, origin, size, ..) = world.models[world.internal.brushes].as_ref().unwrap().render[*brush].clone() else { unreachable!() };
+                                ui.text(ox + 10, y, "Size");
+                                y += 14;
+                                for (axis, name) in ["X", "Y", "Z"].iter().enumerate() {
+                                    let x = ox + 10 + axis as i32 * 84;
+                                    ui.text(x, y + 5, name);
+                                    if let Some(entered) = window.text_field(input, x + 14, y, 64, &format!("{}", size[axis]), ui) {
+                                        match entered.trim().parse::<f32>() {
+                                            Ok(value) if value > 0.0 => {
+                                                let mut size = size;
+                                                size[axis] = value;
+                                                world.set_brush_origin_scale(*brush, origin, Some(size));
+                                            },
+                                            _ => debug_messages.push(format!("{} is not a size", entered))
+                                        }
+                                    }
+                                }
+                                y += 24;
+
+                                ui.text(ox + 10, y + 5, "Material");
+                                if let Some(entered) = window.text_field(input, ox + 70, y, 186, &material, ui) {
+                                    let entered = entered.trim();
+                                    if world.scene.materials.contains_key(entered) {
+                                        let new_brush = world.set_brush_material(*brush, entered.to_string());
+                                        world.deselect();
+                                        world.select_brush(new_brush);
+                                        world.set_arrows_visible(true);
+                                    } else {
+                                        debug_messages.push(format!("there is no material named {}", entered));
+                                    }
+                                }
+                                y += 28;
+
+                                // Walls are as thick as the snapping increment
+                                ui.begin_row(ox + 10, y, 4);
+                                    let result = if ui.text_button(input, 0, 0, "Hollow") {
+                                        Some(world.hollow_brush(*brush, world.editor_data.increment))
+                                    } else if ui.text_button(input, 0, 0, "Carve overlapping") {
+                                        Some(world.carve_with_brush(*brush))
+                                    } else if world.merge_record(*brush).is_some() && ui.text_button(input, 0, 0, "Split merged") {
+                                        Some(world.split_merged_brush(*brush))
+                                    } else {
+                                        None
+                                    };
+                                ui.end_layout();
+                                y += 28;
+                                Self::select_csg_result(world, result, &mut debug_messages);
+
+                                // Baseboards around the top of a floor
+                                ui.text(ox + 10, y + 5, "Trim");
+                                if let Some(entered) = window.text_field(input, ox + 70, y, 186, &world.editor_data.trim.material, ui) {
+                                    world.editor_data.trim.material = entered.trim().to_string();
+                                }
+                                y += 24;
+                                let increment = world.editor_data.increment;
+                                let trim = &mut world.editor_data.trim;
+                                trim.height = (trim.height + Self::stepper(ui, input, ox + 10, y, &format!("Height: {}", trim.height), increment)).max(increment);
+                                y += 20;
+                                trim.thickness = (trim.thickness + Self::stepper(ui, input, ox + 10, y, &format!("Thickness: {}", trim.thickness), increment)).max(increment);
+                                y += 20;
+                                if ui.text_button(input, ox + 10, y, "Add trim") {
+                                    match world.add_trim(*brush) {
+                                        Ok(edges) => {
+                                            world.record_edit(EditKind::Created, true, origin);
+                                            debug_messages.push(format!("added trim along {} edges", edges));
+                                            // Merging moves brushes around, the old index may be something else now
+                                            world.deselect();
+                                        },
+                                        Err(e) => debug_messages.push(e)
+                                    }
+                                }
+                                y += 28;
+                            }
+
+                            if let [Selection::Brush(brush), Selection::Brush(cutter)] = items.as_slice() {
+                                if ui.text_button(input, ox + 10, y, "Subtract second from first") {
+                                    let result = world.subtract_brush(*brush, *cutter);
+                                    Self::select_csg_result(world, Some(result), &mut debug_messages);
+                                }
+                                y += 28;
+                            }
+
+                            let brushes: Vec<usize> = items.iter().filter_map(|item| match item {
+                                Selection::Brush(brush) => Some(*brush),
+                                _ => None
+                            }).collect();
+                            if brushes.len() > 1 {
+                                ui.begin_row(ox + 10, y, 4);
+                                    let result = if ui.text_button(input, 0, 0, "Merge brushes") {
+                                        let merged = world.merge_brushes(&brushes);
+                                        Some(if merged.is_empty() { Err(String::from("none of the brushes can be merged")) } else { Ok(merged) })
+                                    } else {
+                                        None
+                                    };
+                                    if ui.text_button(input, 0, 0, "Preview merge") {
+                                        let removed = world.preview_merge(&brushes);
+                                        debug_messages.push(format!("{} brushes would become {}", brushes.len(), brushes.len() - removed));
+                                    }
+                                ui.end_layout();
+                                y += 28;
+                                Self::select_csg_result(world, result, &mut debug_messages);
+                            }
+                        }
+
+                        let targets = world.selected_renderables();
+                        if items.len() > 1 && !targets.is_empty() {
+                            // Flags that only some of the renderables have are shown as mixed
+                            ui.text(ox + 10, y, &format!("{} renderables", targets.len()));
+                            y += 16;
+                            for (flag, name) in flags::ALL {
+                                let set = targets.iter().filter(|(model, renderable)| world.models[*model].as_ref().unwrap().render[*renderable].flags() & flag != 0).count();
+                                let state = if set == 0 { Some(false) } else if set == targets.len() { Some(true) } else { None };
+                                if Self::mixed_checkbox(ui, input, ox + 10, y, state, name) {
+                                    world.set_selection_flag(flag, state != Some(true));
+                                }
+                                y += 20;
+                            }
+                        } else {
+                            for (model, renderable) in targets {
+                                let current = &world.models[model].as_ref().unwrap().render[renderable];
+                                let label = match current {
+                                    Renderable::Mesh(name, ..) => format!("Mesh {}", name),
+                                    Renderable::Brush(material, ..) => format!("Brush {}", material),
+                                    Renderable::Billboard(texture, ..) => format!("Billboard {}", texture),
+                                    Renderable::Text(text, ..) => format!("Text {}", text.text)
+                                };
+                                let current_flags = current.flags();
+
+                                ui.frame(ox + 10, y, 40, 16);
+                                    if ui.image_button(input, 1, 1, 38, 14, (0, 0), (1, 1), "evil_pixel") {
+                                        let name = label.split_once(' ').map(|(_, name)| name).unwrap_or(&label);
+                                        if input.copy_to_clipboard(name) {
+                                            debug_messages.push(format!("copied {}", name));
+                                        }
+                                    }
+                                    ui.text(5, 3, "Copy");
+                                ui.pop();
+                                ui.text(ox + 56, y + 3, &label);
+                                y += 20;
+
+                                for (flag, name) in flags::ALL {
+                                    if Self::checkbox(ui, input, ox + 10, y, current_flags & flag != 0, name) {
+                                        world.set_renderable_flags(model, renderable, current_flags ^ flag);
+                                    }
+                                    y += 20;
+                                }
+
+                                y += 8;
+                            }
+                        }
+                    }
+                }
+                window.sliders.end_of_loop(input);
+                window.scrollbars(input, ui);
+
+                ui.pop();
+            }
+            for message in debug_messages.drain(..) {
+                self.show_debug(&message);
+            }
+            if let Some(modal) = modal {
+                self.open_modal(modal);
+            }
+            if let Some(dialog) = dialog {
+                self.open_dialog(dialog);
+            }
+
+            if let Some(close) = close {
+                self.windows.remove(close);
+            }
+
+            if let Some(drag) = begin_drag {
+                self.windows[drag].dragging = true;
+                self.mouse_action_origin = action_origin;
+                self.focus_window(drag);
+            }
+
+            if let Some(resize) = begin_resize {
+                self.windows[resize].scaling = true;
+                self.mouse_action_origin = action_origin;
+                self.focus_window(resize);
+            }
+
+            if let Some((i, offset)) = scroll {
+                let window = &mut self.windows[i];
+                // Shift turns the wheel sideways
+                if input.get_key_pressed(Key::Named(NamedKey::Shift)) {
+                    window.offset.0 = (window.offset.0 - offset).min(0.0).max(-window.scroll_max.0);
+                } else {
+                    window.offset.1 = (window.offset.1 - offset).min(0.0).max(-window.scroll_max.1);
+                }
+            }
+
+            if let Some(clicked) = contents_clicked {
+                self.focus_window(clicked);
+            }
+
+            self.update_modal(modal_input, ui, world);
 
             ui.render(textures, programs, gl);
         }
@@ -1239,6 +4073,100 @@ pub mod implement {
         }
     }
 
+    struct TextFieldManager {
+        /// Contents of the focused field
+        contents: String,
+        focused: Option<usize>,
+        /// Character index into `contents`
+        cursor: usize,
+        current_field: usize
+    }
+
+    impl TextFieldManager {
+        fn reset(&mut self) {
+            self.current_field = 0;
+        }
+
+        fn focus(&mut self, field: usize, value: &str) {
+            self.focused = Some(field);
+            self.contents = value.to_string();
+            self.cursor = self.contents.chars().count();
+        }
+
+        fn byte_index(&self, cursor: usize) -> usize {
+            self.contents.char_indices().nth(cursor).map(|(i, _)| i).unwrap_or(self.contents.len())
+        }
+
+        fn insert(&mut self, text: &str) {
+            let at = self.byte_index(self.cursor);
+            self.contents.insert_str(at, text);
+            self.cursor += text.chars().count();
+        }
+
+        /// Contents with any IME composition spliced in at the cursor, and where to draw the cursor
+        fn display(&self, input: &Input) -> (String, usize) {
+            let mut text = self.contents.clone();
+            text.insert_str(self.byte_index(self.cursor), &input.ime_preedit);
+            (text, self.cursor + input.ime_preedit.chars().count())
+        }
+
+        /// Apply this frame's typing to the focused field, returns true when Enter is pressed
+        fn edit(&mut self, input: &Input) -> bool {
+            let ctrl = input.get_text_key_pressed(Key::Named(NamedKey::Control));
+            let length = self.contents.chars().count();
+
+            if ctrl && input.get_text_key_just_pressed(Key::Character("v".into())) {
+                if let Some(pasted) = input.paste_from_clipboard() {
+                    self.insert(&pasted.replace(['\n', '\r'], ""));
+                }
+            } else if ctrl && input.get_text_key_just_pressed(Key::Character("c".into())) {
+                input.copy_to_clipboard(&self.contents);
+            } else if !input.text.is_empty() {
+                self.insert(&input.text);
+            }
+
+            if input.get_text_key_just_pressed(Key::Named(NamedKey::Backspace)) && self.cursor > 0 {
+                self.cursor -= 1;
+                let at = self.byte_index(self.cursor);
+                self.contents.remove(at);
+            }
+            if input.get_text_key_just_pressed(Key::Named(NamedKey::Delete)) && self.cursor < length {
+                let at = self.byte_index(self.cursor);
+                self.contents.remove(at);
+            }
+            if input.get_text_key_just_pressed(Key::Named(NamedKey::ArrowLeft)) {
+                self.cursor = self.cursor.saturating_sub(1);
+            }
+            if input.get_text_key_just_pressed(Key::Named(NamedKey::ArrowRight)) {
+                self.cursor = (self.cursor + 1).min(self.contents.chars().count());
+            }
+            if input.get_text_key_just_pressed(Key::Named(NamedKey::Home)) {
+                self.cursor = 0;
+            }
+            if input.get_text_key_just_pressed(Key::Named(NamedKey::End)) {
+                self.cursor = self.contents.chars().count();
+            }
+
+            if input.get_text_key_just_pressed(Key::Named(NamedKey::Escape)) {
+                self.focused = None;
+            } else if input.get_text_key_just_pressed(Key::Named(NamedKey::Enter)) {
+                self.focused = None;
+                return true;
+            }
+
+            false
+        }
+
+        fn new() -> Self {
+            Self {
+                contents: String::new(),
+                focused: None,
+                cursor: 0,
+                current_field: 0
+            }
+        }
+    }
+
     struct PlayModeUI {
 
     }
@@ -1247,5 +4175,149 @@ pub mod implement {
         pub fn new() -> Self {
             Self {}
         }
+
+        pub unsafe fn render_and_update(&mut self, input: &Input, textures: &mut TextureBank, programs: &mut ProgramBank, gl: &glow::Context, ui: &mut UI, config: &mut UserConfig, world: &mut World) {
+            ui.begin();
+
+            // The game is paused while escape has released the cursor
+            if let CameraControlScheme::FirstPerson(false) = world.scene.camera.control_sceme {
+                let (w, h) = (200, 110);
+                let x = (ui.screen_size.0 as i32 - w as i32) / 2;
+                let y = (ui.screen_size.1 as i32 - h as i32) / 2;
+                ui.frame(x, y, w, h);
+                    ui.text(10, 10, "Paused");
+                    if EditorModeUI::volume_controls(ui, input, 10, 30, &mut config.volume) {
+                        if let Err(e) = config.save() {
+                            world.editor_data.show_debug.push(e);
+                        }
+                    }
+                ui.pop();
+            }
+
+            if let Some(marker) = world.objective_marker() {
+                Self::objective_marker(ui, &marker);
+            }
+
+            if world.interaction.looked_at.is_some() {
+                let prompt = format!("[{}] Use", input.action_key_name(Action::Interact));
+                let x = (ui.screen_size.0 as i32 - prompt.chars().count() as i32 * 6) / 2;
+                ui.text(x, ui.screen_size.1 as i32 / 2 + 16, &prompt);
+            }
+
+            if world.events.overlay {
+                let mut y = ui.screen_size.1 as i32 - 20;
+                for event in world.events.visible().take(EVENTS_OVERLAY_SHOWN) {
+                    ui.text(10, y, &event.describe());
+                    y -= 14;
+                }
+            }
+
+            if world.rules.timed && !world.spectating() {
+                let time = format!("{:.2}", world.stopwatch.time);
+                let x = (ui.screen_size.0 as i32 - time.chars().count() as i32 * 6) / 2;
+                ui.text(x, 10, &time);
+                if world.stopwatch.state == StopwatchState::Waiting {
+                    let hint = if world.stopwatch.start_line { "Cross the start line" } else { "Move to start" };
+                    let x = (ui.screen_size.0 as i32 - hint.chars().count() as i32 * 6) / 2;
+                    ui.text(x, 24, hint);
+                }
+            }
+
+            let collectibles = world.collectible_count();
+            if collectibles > 0 && !world.spectating() {
+                ui.text(10, 10, &format!("Collected {}/{}", world.collected.len(), collectibles));
+            }
+
+            if let Some(completion) = &world.completion {
+                let (w, h) = COMPLETION_SIZE;
+                let x = (ui.screen_size.0 as i32 - w as i32) / 2;
+                let y = (ui.screen_size.1 as i32 - h as i32) / 2;
+                ui.frame(x, y, w, h);
+                    ui.text(10, 10, "Level complete");
+                    ui.text(10, 30, &format!("Time: {:.2}s", completion.time));
+                    let mut line = 44;
+                    if completion.collectibles > 0 {
+                        ui.text(10, line, &format!("Collectibles: {}/{}", completion.collected, completion.collectibles));
+                        line += 14;
+                    }
+                    if let Some(run) = &completion.stopwatch {
+                        ui.text(10, line, &format!("Stopwatch: {:.2}s", run.time));
+                        // Best times are kept by level path, so unsaved levels don't have one
+                        if world.editor_data.save_to.is_some() {
+                            let best = match run.previous_best {
+                                Some(best) if !run.is_best() => format!("Best: {:.2}s", best),
+                                Some(best) => format!("New best! Was {:.2}s", best),
+                                None => String::from("New best!")
+                            };
+                            ui.text(10, line + 14, &best);
+                        }
+                    }
+                    let next = match &completion.next_level {
+                        Some(level) => format!("[{}] Next level: {}", input.action_key_name(Action::Jump), level),
+                        None => format!("[{}] Play again", input.action_key_name(Action::Jump))
+                    };
+                    ui.text(10, h as i32 - 20, &next);
+                ui.pop();
+            }
+
+            if !world.spectating() {
+                let (w, h) = HEALTH_BAR_SIZE;
+                let x = ui.screen_size.0 as i32 - w as i32 - 10;
+                let y = ui.screen_size.1 as i32 - h as i32 - 10;
+                let fraction = (world.player.health / world.rules.max_health()).clamp(0.0, 1.0);
+                ui.fill(x, y, w, h, HEALTH_BAR_BACK_COLOR);
+                ui.fill(x, y, (w as f32 * fraction) as u32, h, HEALTH_BAR_COLOR);
+                let label = if world.player.dead { String::from("Dead") } else { format!("{:.0}", world.player.health.max(0.0).ceil()) };
+                ui.text(x, y - 14, &label);
+
+                // Stamina goes above the health bar, dimmed while the player is too tired to sprint
+                if world.rules.sprint && world.rules.stamina.is_some() {
+                    let fraction = (world.player.stamina / world.rules.max_stamina()).clamp(0.0, 1.0);
+                    let y = y - h as i32 - 20;
+                    ui.fill(x, y, w, h, HEALTH_BAR_BACK_COLOR);
+                    ui.fill(x, y, (w as f32 * fraction) as u32, h, if world.player.exhausted { STAMINA_BAR_TIRED_COLOR } else { STAMINA_BAR_COLOR });
+                }
+            }
+
+            if world.spectating() {
+                ui.text(10, 10, &format!("Spectating, speed {:.1} (scroll to change)", world.spectator.speed));
+                ui.text(10, 24, "Spectate again or Escape to return to the player");
+            }
+
+            ui.render(textures, programs, gl);
+        }
+
+        /// Square over the target, or an arrow on the edge of the screen pointing towards it
+        fn objective_marker(ui: &mut UI, marker: &ObjectiveMarker) {
+            let (w, h) = (ui.screen_size.0 as f32, ui.screen_size.1 as f32);
+            let margin = OBJECTIVE_MARKER_MARGIN as f32;
+            let x = ((marker.screen.0 + 1.0) / 2.0 * w).clamp(margin, w - margin);
+            let y = ((1.0 - marker.screen.1) / 2.0 * h).clamp(margin, h - margin);
+
+            if marker.off_screen {
+                // Tip at the edge, the shaft and wings trail back towards the middle of the screen
+                let length = (marker.screen.0 * marker.screen.0 + marker.screen.1 * marker.screen.1).sqrt().max(0.0001);
+                let (dx, dy) = (marker.screen.0 / length, -marker.screen.1 / length);
+                for step in 0..6 {
+                    let back = step as f32 * 3.0;
+                    ui.fill((x - dx * back) as i32 - 2, (y - dy * back) as i32 - 2, 4, 4, OBJECTIVE_MARKER_COLOR);
+                    if (1..4).contains(&step) {
+                        for side in [-1.0, 1.0] {
+                            let (wx, wy) = (x - dx * back - dy * back * side, y - dy * back + dx * back * side);
+                            ui.fill(wx as i32 - 2, wy as i32 - 2, 4, 4, OBJECTIVE_MARKER_COLOR);
+                        }
+                    }
+                }
+            } else {
+                ui.fill(x as i32 - 4, y as i32 - 4, 8, 8, OBJECTIVE_MARKER_COLOR);
+            }
+
+            // The label goes on the side facing the middle of the screen
+            let label = format!("{} {:.0}m", marker.title, marker.distance);
+            let text_width = label.chars().count() as i32 * 6;
+            let text_x = (x as i32 - text_width / 2).clamp(4, (w as i32 - text_width - 4).max(4));
+            let text_y = if y > h / 2.0 { y as i32 - 24 } else { y as i32 + 14 };
+            ui.text(text_x, text_y, &label);
+        }
     }
 }
\ No newline at end of file
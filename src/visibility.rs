@@ -0,0 +1,271 @@
+use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc}, thread::{self, JoinHandle}};
+
+use cgmath::{vec3, Matrix4, Transform, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{common, mesh::flags, world::{Renderable, World}};
+
+pub const DEFAULT_CELL_SIZE: f32 = 4.0;
+/// Rays cast between two cells before they are decided to be hidden from each other
+const SAMPLES: u32 = 12;
+/// Baking time grows with the square of the cell count, use bigger cells past this
+const MAX_CELLS: usize = 2048;
+/// Brushes with these flags don't block the view
+const SEE_THROUGH: u32 = flags::SKIP | flags::CUTOUT | flags::EDITOR_ONLY;
+
+/// Which cells of a grid over the brushes can see each other, baked in the editor and saved with the level<br>
+/// Static brushes are only drawn if they touch a cell the camera's cell can see, everything is drawn when the camera is outside the grid
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Visibility {
+    origin: [f32; 3],
+    cell_size: f32,
+    dims: [u32; 3],
+    /// Sorted indices of the cells each cell can see, `None` for cells inside brushes
+    visible: Vec<Option<Vec<u32>>>
+}
+
+impl Visibility {
+    pub fn cell_at(&self, point: Vector3<f32>) -> Option<usize> {
+        let local = (point - Vector3::from(self.origin)) / self.cell_size;
+        let mut index = 0;
+        for axis in (0..3).rev() {
+            if local[axis] < 0.0 || local[axis] >= self.dims[axis] as f32 {
+                return None;
+            }
+            index = index * self.dims[axis] as usize + local[axis] as usize;
+        }
+        // Inside a brush, probably flying through a wall
+        self.visible[index].as_ref()?;
+        Some(index)
+    }
+
+    /// Whether a unit cube put through `transform` could be seen from `cell`
+    pub fn instance_visible(&self, cell: usize, transform: Matrix4<f32>) -> bool {
+        let (min, max) = transformed_cube(transform);
+        // Grown by a cell, so thick walls are drawn from the rooms next to them
+        let grow = common::vec3_all(self.cell_size);
+        self.box_visible(cell, min - grow, max + grow)
+    }
+
+    fn box_visible(&self, cell: usize, min: Vector3<f32>, max: Vector3<f32>) -> bool {
+        let Some(Some(visible)) = self.visible.get(cell) else { return true; };
+        let origin = Vector3::from(self.origin);
+        let mut first = [0; 3];
+        let mut last = [0; 3];
+        for axis in 0..3 {
+            let low = ((min[axis] - origin[axis]) / self.cell_size).floor();
+            let high = ((max[axis] - origin[axis]) / self.cell_size).floor();
+            // Reaching outside the grid, where nothing is known
+            if low < 0.0 || high >= self.dims[axis] as f32 {
+                return true;
+            }
+            first[axis] = low as u32;
+            last[axis] = high as u32;
+        }
+
+        for z in first[2]..=last[2] {
+            for y in first[1]..=last[1] {
+                for x in first[0]..=last[0] {
+                    let index = (z * self.dims[1] + y) * self.dims[0] + x;
+                    if visible.binary_search(&index).is_ok() {
+                        return true;
+                    }
+                }
+            }
+        }
+        false
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.visible.len()
+    }
+
+    fn cell_min(&self, index: usize) -> Vector3<f32> {
+        let x = index % self.dims[0] as usize;
+        let y = (index / self.dims[0] as usize) % self.dims[1] as usize;
+        let z = index / (self.dims[0] * self.dims[1]) as usize;
+        Vector3::from(self.origin) + vec3(x as f32, y as f32, z as f32) * self.cell_size
+    }
+}
+
+/// Visibility being baked on a worker thread, see `World::start_visibility_bake`
+pub struct VisibilityBake {
+    /// Cells whose visibility is worked out so far
+    done: Arc<AtomicUsize>,
+    count: usize,
+    worker: JoinHandle<(Visibility, String)>
+}
+
+impl VisibilityBake {
+    /// 0 to 1
+    pub fn progress(&self) -> f32 {
+        self.done.load(Ordering::Relaxed) as f32 / self.count as f32
+    }
+}
+
+/// Bounds of the -0.5 to 0.5 cube after `transform`
+fn transformed_cube(transform: Matrix4<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let mut min = common::vec3_all(f32::MAX);
+    let mut max = common::vec3_all(f32::MIN);
+    for corner in 0..8 {
+        let local = vec3(
+            if corner & 1 == 0 { -0.5 } else { 0.5 },
+            if corner & 2 == 0 { -0.5 } else { 0.5 },
+            if corner & 4 == 0 { -0.5 } else { 0.5 }
+        );
+        let point = transform.transform_vector(local) + common::translation(transform);
+        for axis in 0..3 {
+            min[axis] = min[axis].min(point[axis]);
+            max[axis] = max[axis].max(point[axis]);
+        }
+    }
+    (min, max)
+}
+
+fn point_in_box(point: Vector3<f32>, (min, max): (Vector3<f32>, Vector3<f32>)) -> bool {
+    (0..3).all(|axis| point[axis] > min[axis] && point[axis] < max[axis])
+}
+
+/// Whether the segment from `from` to `to` passes through the box, slab test
+fn segment_hits_box(from: Vector3<f32>, to: Vector3<f32>, (min, max): (Vector3<f32>, Vector3<f32>)) -> bool {
+    let direction = to - from;
+    let (mut enter, mut exit) = (0.0f32, 1.0f32);
+    for axis in 0..3 {
+        if direction[axis].abs() < 0.00001 {
+            if from[axis] <= min[axis] || from[axis] >= max[axis] {
+                return false;
+            }
+            continue;
+        }
+        let a = (min[axis] - from[axis]) / direction[axis];
+        let b = (max[axis] - from[axis]) / direction[axis];
+        enter = enter.max(a.min(b));
+        exit = exit.min(a.max(b));
+        if enter >= exit {
+            return false;
+        }
+    }
+    true
+}
+
+/// Same points every bake, so baking an unchanged level gives the same result
+fn sample(seed: u32) -> Vector3<f32> {
+    let mut state = seed.wrapping_mul(747796405).wrapping_add(2891336453);
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        (state % 10000) as f32 / 10000.0
+    };
+    vec3(next(), next(), next())
+}
+
+impl World {
+    /// Occluding brushes as world space boxes
+    fn occluders(&self) -> Vec<(Vector3<f32>, Vector3<f32>)> {
+        let brushes = self.models[self.internal.brushes].as_ref().unwrap();
+        brushes.render.iter().filter_map(|renderable| match renderable {
+            Renderable::Brush(_, position, size, brush_flags, _) if brush_flags & SEE_THROUGH == 0 => {
+                let transform = brushes.transform * Matrix4::from_translation(*position) * Matrix4::from_nonuniform_scale(size.x, size.y, size.z);
+                Some(transformed_cube(transform))
+            },
+            _ => None
+        }).collect()
+    }
+
+    /// Split the brushes' bounds into cells of `cell_size` and start finding which cells can see each other on a worker thread<br>
+    /// Has to be done again after the brushes change, brushes changed during the bake aren't in it
+    pub fn start_visibility_bake(&mut self, cell_size: f32) -> Result<(), String> {
+        if self.editor_data.visibility_bake.is_some() {
+            return Err(String::from("visibility is already being baked"));
+        }
+        let occluders = self.occluders();
+        if occluders.is_empty() {
+            return Err(String::from("there are no brushes to bake visibility for"));
+        }
+
+        let mut min = common::vec3_all(f32::MAX);
+        let mut max = common::vec3_all(f32::MIN);
+        for (low, high) in occluders.iter() {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(low[axis]);
+                max[axis] = max[axis].max(high[axis]);
+            }
+        }
+        let dims = [0, 1, 2].map(|axis| (((max[axis] - min[axis]) / cell_size).ceil() as u32).max(1));
+        let count = dims.iter().product::<u32>() as usize;
+        if count > MAX_CELLS {
+            return Err(format!("{} cells is too many to bake, use cells bigger than {}", count, cell_size));
+        }
+
+        let visibility = Visibility { origin: min.into(), cell_size, dims, visible: Vec::with_capacity(count) };
+        let done = Arc::new(AtomicUsize::new(0));
+        let worker_done = done.clone();
+        let worker = thread::spawn(move || bake(visibility, occluders, &worker_done));
+        self.editor_data.visibility_bake = Some(VisibilityBake { done, count, worker });
+        Ok(())
+    }
+
+    /// Use the baked visibility once the worker is done, returns its summary
+    pub fn update_visibility_bake(&mut self) -> Option<String> {
+        if !self.editor_data.visibility_bake.as_ref()?.worker.is_finished() {
+            return None;
+        }
+        let bake = self.editor_data.visibility_bake.take()?;
+        match bake.worker.join() {
+            Ok((visibility, summary)) => {
+                self.scene.set_visibility(Some(visibility));
+                Some(summary)
+            },
+            Err(_) => Some(String::from("baking visibility failed"))
+        }
+    }
+}
+
+/// Fill in which of `visibility`'s cells can see each other, counting finished cells in `done`
+fn bake(mut visibility: Visibility, occluders: Vec<(Vector3<f32>, Vector3<f32>)>, done: &AtomicUsize) -> (Visibility, String) {
+    let count = visibility.dims.iter().product::<u32>() as usize;
+    let cell_size = visibility.cell_size;
+
+    // Points in each open cell that aren't inside a brush
+    let samples: Vec<Option<Vec<Vector3<f32>>>> = (0..count).map(|cell| {
+        let corner = visibility.cell_min(cell);
+        let center = corner + common::vec3_all(cell_size / 2.0);
+        if occluders.iter().any(|occluder| point_in_box(center, *occluder)) {
+            return None;
+        }
+        let points: Vec<Vector3<f32>> = (0..SAMPLES)
+            .map(|i| corner + sample(cell as u32 * SAMPLES + i) * cell_size)
+            .filter(|point| !occluders.iter().any(|occluder| point_in_box(*point, *occluder)))
+            .collect();
+        Some(if points.is_empty() { vec![center] } else { points })
+    }).collect();
+
+    let mut visible: Vec<Option<Vec<u32>>> = samples.iter().map(|points| points.as_ref().map(|_| Vec::new())).collect();
+    for a in 0..count {
+        done.store(a, Ordering::Relaxed);
+        let Some(from) = &samples[a] else { continue; };
+        visible[a].as_mut().unwrap().push(a as u32);
+        for b in (a + 1)..count {
+            let Some(to) = &samples[b] else { continue; };
+            let sees = (0..SAMPLES as usize).any(|i| {
+                let (start, end) = (from[i % from.len()], to[(i * 7 + 3) % to.len()]);
+                !occluders.iter().any(|occluder| segment_hits_box(start, end, *occluder))
+            });
+            if sees {
+                visible[a].as_mut().unwrap().push(b as u32);
+                visible[b].as_mut().unwrap().push(a as u32);
+            }
+        }
+    }
+    for cell in visible.iter_mut().flatten() {
+        cell.sort();
+    }
+
+    let open: Vec<&Vec<u32>> = visible.iter().flatten().collect();
+    let average = open.iter().map(|cell| cell.len()).sum::<usize>() as f32 / open.len().max(1) as f32;
+    let summary = format!("baked visibility for {} cells, {} open, each sees {:.0} on average", count, open.len(), average);
+
+    visibility.visible = visible;
+    (visibility, summary)
+}
@@ -1,20 +1,35 @@
 use std::num::NonZeroU32;
 
-use glutin::{config::{ConfigTemplateBuilder, GlConfig}, context::{ContextApi, ContextAttributesBuilder, GlProfile}, display::GetGlDisplay, prelude::{GlDisplay, NotCurrentGlContext}, surface::{GlSurface, SwapInterval, WindowSurface}};
+use glow::HasContext;
+use glutin::{config::{ConfigTemplateBuilder, GetGlConfig, GlConfig}, context::{ContextApi, ContextAttributesBuilder, GlProfile, PossiblyCurrentContext}, display::GetGlDisplay, prelude::{GlDisplay, NotCurrentGlContext, PossiblyCurrentGlContext}, surface::{GlSurface, Surface, SwapInterval, WindowSurface}};
 use glutin_winit::{DisplayBuilder, GlWindow};
 use raw_window_handle::HasRawWindowHandle;
-use winit::event_loop::EventLoop;
+use winit::event_loop::{EventLoop, EventLoopWindowTarget};
+
+use crate::{input::Input, shader};
 
 pub const WINDOW_INIT_WIDTH: f32 = 640.0 * 2.0;
 pub const WINDOW_INIT_HEIGHT: f32 = 480.0 * 2.0;
+const PANEL_WINDOW_WIDTH: f32 = 480.0;
+const PANEL_WINDOW_HEIGHT: f32 = 720.0;
+pub const PANEL_BACKGROUND: [f32; 3] = [0.12, 0.12, 0.14];
+
+/// Context versions tried in order
+const CONTEXT_VERSIONS: [(u8, u8); 2] = [(4, 1), (3, 3)];
+/// Below this the editor starts in safe mode
+const REQUIRED_GL_VERSION: (u32, u32) = (4, 1);
+/// Textures the editor loads are up to this wide
+const REQUIRED_TEXTURE_SIZE: i32 = 4096;
 
 pub type ProgramContext = (glow::Context, glutin::surface::Surface<WindowSurface>, glutin::context::PossiblyCurrentContext, winit::window::Window, EventLoop<()>);
 
+/// The window stays hidden if not `visible`, for rendering offscreen
 // https://github.com/grovesNL/glow/blob/main/examples/hello/src/main.rs
-pub unsafe fn create_gl_context() -> ProgramContext {
+pub unsafe fn create_gl_context(visible: bool) -> ProgramContext {
     let event_loop = winit::event_loop::EventLoopBuilder::new().build().unwrap();
     let window_builder = winit::window::WindowBuilder::new()
         .with_title("VICEPTICA")
+        .with_visible(visible)
         .with_inner_size(winit::dpi::LogicalSize::new(WINDOW_INIT_WIDTH, WINDOW_INIT_HEIGHT));
 
     let template = ConfigTemplateBuilder::new()
@@ -38,18 +53,24 @@ pub unsafe fn create_gl_context() -> ProgramContext {
 
     let gl_display = gl_config.display();
     
-    // gl version 4.1 
-    let context_attributes = ContextAttributesBuilder::new()
-        .with_context_api(ContextApi::OpenGl(Some(glutin::context::Version {
-            major: 4,
-            minor: 1
-        })))
-        .with_profile(GlProfile::Core)
-        .build(raw_window_handle);
-
-    let not_current_gl_context = gl_display
-            .create_context(&gl_config, &context_attributes)
-            .unwrap();
+    // gl version 4.1, old drivers get 3.3 and safe mode
+    let not_current_gl_context = CONTEXT_VERSIONS.iter().find_map(|(major, minor)| {
+        let context_attributes = ContextAttributesBuilder::new()
+            .with_context_api(ContextApi::OpenGl(Some(glutin::context::Version {
+                major: *major,
+                minor: *minor
+            })))
+            .with_profile(GlProfile::Core)
+            .build(raw_window_handle);
+
+        match gl_display.create_context(&gl_config, &context_attributes) {
+            Ok(context) => Some(context),
+            Err(e) => {
+                eprintln!("Failed to create a GL {}.{} context: {}", major, minor, e);
+                None
+            }
+        }
+    }).expect("No supported GL context, VICEPTICA needs at least OpenGL 3.3");
 
     let window = window.unwrap();
 
@@ -73,4 +94,90 @@ pub unsafe fn create_gl_context() -> ProgramContext {
         window,
         event_loop
     )
-}
\ No newline at end of file
+}
+
+/// Second OS window editor panels can be moved to, drawn with the main window's context
+pub struct PanelWindow {
+    pub window: winit::window::Window,
+    pub surface: Surface<WindowSurface>,
+    /// Only gets this window's events, the main window's input never sees them
+    pub input: Input
+}
+
+/// Open the panel window, `main_surface` is current again afterwards
+pub unsafe fn create_panel_window(elwt: &EventLoopWindowTarget<()>, gl_context: &PossiblyCurrentContext, main_surface: &Surface<WindowSurface>) -> Result<PanelWindow, String> {
+    let window_builder = winit::window::WindowBuilder::new()
+        .with_title("VICEPTICA panels")
+        .with_inner_size(winit::dpi::LogicalSize::new(PANEL_WINDOW_WIDTH, PANEL_WINDOW_HEIGHT));
+
+    // Same config as the main window so the context can draw to both
+    let gl_config = gl_context.config();
+    let window = glutin_winit::finalize_window(elwt, window_builder, &gl_config).map_err(|e| format!("failed to open panel window: {}", e))?;
+    let attrs = window.build_surface_attributes(Default::default());
+    let surface = gl_config.display()
+        .create_window_surface(&gl_config, &attrs)
+        .map_err(|e| format!("failed to open panel window: {}", e))?;
+
+    // Waiting for vsync on both windows would halve the frame rate
+    gl_context.make_current(&surface).map_err(|e| format!("failed to open panel window: {}", e))?;
+    let _ = surface.set_swap_interval(gl_context, SwapInterval::DontWait);
+    gl_context.make_current(main_surface).map_err(|e| e.to_string())?;
+
+    Ok(PanelWindow { window, surface, input: Input::new() })
+}
+
+/// What the driver reported on startup, decides whether the editor runs in safe mode
+pub struct GpuCapabilities {
+    pub version: (u32, u32),
+    pub vendor: String,
+    pub renderer: String,
+    pub max_texture_size: i32,
+    pub extensions: usize,
+    /// `glDebugMessageCallback` is core in 4.3, otherwise it needs `GL_KHR_debug`
+    pub debug_output: bool
+}
+
+impl GpuCapabilities {
+    pub unsafe fn detect(gl: &glow::Context) -> Self {
+        let version = gl.version();
+        let extensions = gl.supported_extensions();
+        Self {
+            version: (version.major, version.minor),
+            vendor: gl.get_parameter_string(glow::VENDOR),
+            renderer: gl.get_parameter_string(glow::RENDERER),
+            max_texture_size: gl.get_parameter_i32(glow::MAX_TEXTURE_SIZE),
+            extensions: extensions.len(),
+            debug_output: (version.major, version.minor) >= (4, 3) || extensions.contains("GL_KHR_debug")
+        }
+    }
+
+    /// Why the full renderer can't run, empty if it can
+    pub fn missing_requirements(&self) -> Vec<String> {
+        let mut missing = Vec::new();
+        if self.version < REQUIRED_GL_VERSION {
+            missing.push(format!("OpenGL {}.{} (have {}.{})", REQUIRED_GL_VERSION.0, REQUIRED_GL_VERSION.1, self.version.0, self.version.1));
+        }
+        if self.max_texture_size < REQUIRED_TEXTURE_SIZE {
+            missing.push(format!("{}px textures (max {}px)", REQUIRED_TEXTURE_SIZE, self.max_texture_size));
+        }
+        missing
+    }
+
+    pub fn summary(&self) -> String {
+        format!(
+            "OpenGL {}.{} on {} ({}), max texture size {}, {} extensions{}",
+            self.version.0, self.version.1, self.renderer, self.vendor,
+            self.max_texture_size, self.extensions,
+            if self.debug_output { ", debug output" } else { "" }
+        )
+    }
+
+    /// `#version` line the shaders are compiled with
+    pub fn shader_version(&self) -> &'static str {
+        if self.version >= REQUIRED_GL_VERSION {
+            shader::SHADER_VERSION
+        } else {
+            shader::LEGACY_SHADER_VERSION
+        }
+    }
+}
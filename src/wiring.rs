@@ -0,0 +1,255 @@
+use cgmath::{vec3, Vector3, Zero};
+
+use crate::{common, component::{Component, Door, TargetAction, Trigger, TriggerType}, shader::ProgramBank, world::{Selection, World}};
+
+/// References to names no model has point straight up this far
+const BROKEN_LINK_LENGTH: f32 = 1.5;
+const BROKEN_LINK_COLOR: [f32; 3] = [1.0, 0.2, 0.2];
+
+/// What a reference from one model to another means
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LinkKind {
+    /// A door opening while the player is in a trigger
+    OpenedBy,
+    /// An objective's marker pointing at a model
+    Marks,
+    /// An objective completed by entering a trigger
+    CompletedBy,
+    /// A trigger doing something to a model, see `TargetAction`
+    Fires,
+    /// A connection sending an input, see `connection`
+    Sends
+}
+
+impl LinkKind {
+    pub fn color(&self) -> Vector3<f32> {
+        match self {
+            LinkKind::OpenedBy => vec3(0.3, 1.0, 0.4),
+            LinkKind::Marks => vec3(1.0, 0.85, 0.2),
+            LinkKind::CompletedBy => vec3(0.3, 0.6, 1.0),
+            LinkKind::Fires => vec3(1.0, 0.4, 0.9),
+            LinkKind::Sends => vec3(0.3, 0.9, 0.9)
+        }
+    }
+}
+
+/// Change made by a target trigger, put back when play stops
+pub enum TargetUndo {
+    /// Model and whether it was hidden before
+    Hidden(usize, bool),
+    /// Model whose lights were toggled
+    Lights(usize)
+}
+
+/// A component on `source` referring to another model by name
+pub struct Link {
+    pub source: usize,
+    /// `None` if no model has the name
+    pub target: Option<usize>,
+    pub name: String,
+    pub kind: LinkKind
+}
+
+impl World {
+    pub fn find_by_name(&self, name: &str) -> Option<usize> {
+        self.models.iter().position(|model| model.as_ref().is_some_and(|model| model.name.as_deref() == Some(name)))
+    }
+
+    /// Every model with `tag`
+    pub fn find_by_tag(&self, tag: &str) -> Vec<usize> {
+        self.models.iter().enumerate()
+            .filter(|(_, model)| model.as_ref().is_some_and(|model| model.has_tag(tag)))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Rename `model`, fails if another model already has the name
+    pub fn set_model_name(&mut self, model: usize, name: Option<String>) -> Result<(), String> {
+        if let Some(name) = &name {
+            if self.find_by_name(name).is_some_and(|other| other != model) {
+                return Err(format!("another model is already named {}", name));
+            }
+        }
+        if let Some(target) = self.models[model].as_mut() {
+            target.name = name;
+        }
+        Ok(())
+    }
+
+    pub fn get_door(&self, model: usize) -> Option<Door> {
+        self.models.get(model)?.as_ref()?.components.iter().find_map(|component| match component {
+            Component::Door(door) => Some(door.clone()),
+            _ => None
+        })
+    }
+
+    pub fn set_door(&mut self, model: usize, door: Door) {
+        let Some(target) = self.models[model].as_mut() else { return; };
+        for component in target.components.iter_mut() {
+            if let Component::Door(old) = component {
+                *old = door.clone();
+            }
+        }
+    }
+
+    /// Target name and action of the target trigger on `model`
+    pub fn get_trigger_target(&self, model: usize) -> Option<(String, TargetAction)> {
+        self.models.get(model)?.as_ref()?.components.iter().find_map(|component| match component {
+            Component::Trigger(Trigger { kind: TriggerType::Target { target_name, action }, .. }) => Some((target_name.clone(), *action)),
+            _ => None
+        })
+    }
+
+    pub fn set_trigger_target(&mut self, model: usize, name: String, new_action: TargetAction) {
+        let Some(target) = self.models[model].as_mut() else { return; };
+        for component in target.components.iter_mut() {
+            if let Component::Trigger(Trigger { kind: TriggerType::Target { target_name, action }, .. }) = component {
+                *target_name = name.clone();
+                *action = new_action;
+            }
+        }
+    }
+
+    /// Do `action` to the model named `name`, for target triggers
+    pub fn fire_target(&mut self, name: &str, action: TargetAction) -> Result<(), String> {
+        let target = self.find_by_name(name).ok_or_else(|| format!("trigger target {} doesn't exist", name))?;
+        self.fire_at(target, action)
+    }
+
+    /// Do `action` to every model with `tag`, returns how many there were<br>
+    /// Models the action doesn't apply to are skipped
+    pub fn fire_tagged(&mut self, tag: &str, action: TargetAction) -> Result<usize, String> {
+        let targets = self.find_by_tag(tag);
+        if targets.is_empty() {
+            return Err(format!("no model is tagged {}", tag));
+        }
+        let mut errors = Vec::new();
+        for target in targets.iter() {
+            if let Err(e) = self.fire_at(*target, action) {
+                errors.push(e);
+            }
+        }
+        if errors.len() == targets.len() { Err(errors.join(", ")) } else { Ok(targets.len() - errors.len()) }
+    }
+
+    /// Do `action` to `target`, for target triggers and connections
+    pub fn fire_at(&mut self, target: usize, action: TargetAction) -> Result<(), String> {
+        let name = self.model_label(target);
+        let model = self.models[target].as_mut().unwrap();
+        match action {
+            TargetAction::OpenDoor => {
+                let mut found = false;
+                for component in model.components.iter_mut() {
+                    if let Component::Door(door) = component {
+                        door.held_open = true;
+                        found = true;
+                    }
+                }
+                if !found {
+                    return Err(format!("{} has no door", name));
+                }
+            },
+            TargetAction::ToggleLights => {
+                let lights: Vec<usize> = model.lights.iter().map(|(_, light)| *light).collect();
+                if lights.is_empty() {
+                    return Err(format!("{} has no lights", name));
+                }
+                let on = lights.iter().any(|light| self.scene.point_lights[*light].diffuse != Vector3::zero());
+                for light in lights {
+                    self.scene.point_lights[light].set_animated_color(common::vec3_all(if on { 0.0 } else { 1.0 }));
+                }
+                self.target_undo.push(TargetUndo::Lights(target));
+            },
+            TargetAction::Teleport => {
+                let position = model.origin();
+                self.teleport_player(position);
+            },
+            TargetAction::Hide | TargetAction::Show => {
+                if !model.mobile {
+                    return Err(format!("{} can't be hidden, it isn't mobile", name));
+                }
+                let hidden = action == TargetAction::Hide;
+                if model.hidden != hidden {
+                    self.target_undo.push(TargetUndo::Hidden(target, model.hidden));
+                    self.set_model_hidden(target, hidden);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Put back what target triggers changed while playing, call when going back to the editor
+    pub fn reset_targets(&mut self) {
+        while let Some(undo) = self.target_undo.pop() {
+            match undo {
+                TargetUndo::Hidden(model, hidden) => if self.models.get(model).is_some_and(Option::is_some) {
+                    self.set_model_hidden(model, hidden);
+                },
+                TargetUndo::Lights(model) => if let Some(Some(model)) = self.models.get(model) {
+                    for (_, light) in model.lights.iter() {
+                        self.scene.point_lights[*light].set_animated_color(common::vec3_all(1.0));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether the player is inside the trigger on `model`
+    pub fn player_in_trigger(&self, model: usize) -> bool {
+        let Some(Some(model)) = self.models.get(model) else { return false; };
+        model.components.iter().any(|component| matches!(component, Component::Trigger(trigger) if trigger.player_within))
+    }
+
+    /// Every reference between models in the level
+    pub fn links(&self) -> Vec<Link> {
+        let mut links = Vec::new();
+        for (source, model) in self.models.iter().enumerate() {
+            let Some(model) = model else { continue; };
+            for component in model.components.iter() {
+                for (name, kind) in component.references() {
+                    links.push(Link { source, target: self.find_by_name(name), name: name.to_string(), kind });
+                }
+            }
+            for connection in model.connections.iter() {
+                links.push(Link { source, target: self.find_by_name(&connection.target), name: connection.target.clone(), kind: LinkKind::Sends });
+            }
+        }
+        links
+    }
+
+    /// Arrows for the references of the selected models and the references to them, call while editing
+    pub unsafe fn render_links(&self, programs: &mut ProgramBank, gl: &glow::Context) {
+        if !self.editor_data.active {
+            return;
+        }
+        let selected: Vec<usize> = self.editor_data.selected_object.as_ref().map(|selection| selection.items()).unwrap_or_default()
+            .into_iter()
+            .filter_map(|item| match item {
+                Selection::Model(model) => Some(model),
+                _ => None
+            })
+            .collect();
+        if selected.is_empty() {
+            return;
+        }
+
+        for link in self.links() {
+            if !selected.contains(&link.source) && !link.target.is_some_and(|target| selected.contains(&target)) {
+                continue;
+            }
+
+            let from = self.model_center(link.source);
+            let (to, color) = match link.target {
+                Some(target) => (self.model_center(target), link.kind.color()),
+                None => (from + Vector3::unit_y() * BROKEN_LINK_LENGTH, BROKEN_LINK_COLOR.into())
+            };
+            self.scene.debug_render_arrow(from, to, color, self.editor_data.selection_box_vao.unwrap(), programs, gl);
+        }
+    }
+
+    pub fn model_center(&self, model: usize) -> Vector3<f32> {
+        let model = self.models[model].as_ref().unwrap();
+        let offset = model.extents.map_or(Vector3::zero(), |(offset, _)| offset);
+        common::translation(model.transform) + offset
+    }
+}
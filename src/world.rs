@@ -1,42 +1,101 @@
 use core::f32;
-use std::{fs, io::Read, path::PathBuf};
+use std::{collections::HashSet, hash::{DefaultHasher, Hash, Hasher}, path::PathBuf};
 
-use cgmath::{vec3, vec4, AbsDiffEq, ElementWise, EuclideanSpace, InnerSpace, Matrix4, Point3, Quaternion, Rad, Rotation, SquareMatrix, Vector3, Zero};
+use cgmath::{vec3, vec4, AbsDiffEq, ElementWise, EuclideanSpace, InnerSpace, Matrix, Matrix3, Matrix4, Point3, Quaternion, Rad, Rotation, SquareMatrix, Vector3, Zero};
 use glow::NativeVertexArray;
+use serde::{Deserialize, Serialize};
 use winit::{event::MouseButton, keyboard::{Key, NamedKey}};
 
-use crate::{collision::{Collider, PhysicalProperties, PhysicalScene, RaycastResult}, common::{self, compose_extents, mat4_remove_translation, translation, vec3_all, vec3_div_compwise, vec3_zero}, component::Component, input::Input, mesh::{flags, Mesh, MeshBank}, render::{self, Camera, Scene}, save::{self, LevelData}, shader::ProgramBank, texture::TextureBank};
+use crate::{audio::{LevelAudio, MusicManager}, budget::{BudgetUsage, PerformanceBudgets}, button::Interaction, collision::{Collider, PhysicalProperties, PhysicalScene, RaycastResult}, common::{self, compose_extents, mat4_remove_translation, translation, vec3_all, vec3_div_compwise, vec3_zero}, component::{Component, LightAnimation, LightAnimator, Rotator, SpotLightSource}, connection::{Connection, ConnectionQueue}, dissolve::Dissolves, events::EventLog, flipbook::Flipbook, footstep::{self, SurfaceSound, SurfaceSoundKind}, ghost::Ghosts, goal::LevelCompletion, input::{Action, Input}, merge::MergedBrush, mesh::{flags, BrushFaces, Mesh, MeshBank}, render::{self, Camera, Scene}, rules::{self, GameplayRules}, save::{self, LevelData}, script::ScriptEngine, shader::ProgramBank, spawn::Spawner, session::{EditKind, SessionStats}, spectator::{self, Spectator}, prefab, readback::{Readback, ReadbackId}, reference::ScaleReferences, stamp::Stamp, stopwatch::Stopwatch, texture::TextureBank, tint::BrushTint, traversal::TraversalCheck, trim::TrimSettings, ui::UITheme, visibility::VisibilityBake, wiring::TargetUndo};
 
 pub const DEFAULT_INCREMENT: f32 = 0.25;
+/// Full size of the player's collider
+pub const PLAYER_SIZE: Vector3<f32> = Vector3 { x: 0.5, y: 2.0, z: 0.5 };
+/// Height of the player's collider while crouching
+pub const CROUCH_HEIGHT: f32 = 1.0;
+/// Fraction of the player's speed kept while crouching
+const CROUCH_SPEED: f32 = 0.5;
+/// Size of the pad spawned by the material picker's test button
+const TEST_PATCH_SIZE: Vector3<f32> = Vector3 { x: 4.0, y: 0.25, z: 4.0 };
+/// Seconds of play before a test patch disappears
+const TEST_PATCH_LIFETIME: f32 = 30.0;
+/// Deleting more objects than this at once asks for confirmation
+const DELETE_CONFIRM_THRESHOLD: usize = 8;
 
 const ARROW_LOWEST_Y: f32 = -1.435;
 const ARROW_HEIGHT: f32 = 3.471;
+const ARROW_SCALE: f32 = 0.5;
+const BOX_SCALE: f32 = 0.25;
+/// Gizmos this far from the camera are drawn at `ARROW_SCALE` and `BOX_SCALE`, they grow and shrink with distance from there
+const GIZMO_REFERENCE_DISTANCE: f32 = 12.0;
+const GIZMO_MIN_SCALE: f32 = 0.15;
+/// Scales typed into the inspector are clamped to this, a zero scale can't be undone
+const MIN_MODEL_SCALE: f32 = 0.01;
 const EPSILON: f32 = 0.005;
 const COYOTE: u32 = 3;
 
+#[derive(Clone)]
 pub enum Selection {
     Brush(usize),
     Model(usize),
     Multiple(Vec<Selection>)
 }
 
+impl Selection {
+    /// The brushes and models in this selection, with multiple selections flattened
+    pub fn items(&self) -> Vec<Selection> {
+        match self {
+            Self::Multiple(multiple) => multiple.iter().flat_map(|selection| selection.items()).collect(),
+            single => vec![single.clone()]
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub enum SelectionType {
     Movement,
     Scaling,
+    /// Arrows on each face of a single brush, dragging one or the face itself moves only that face along its normal
+    Faces,
     // Rotation
 }
 
 #[derive(Clone, Copy)]
 pub enum DragAxis {
-    X, Y, Z
+    X, Y, Z,
+    /// Free movement along `drag_plane`, used by the plane and center handles
+    Plane
+}
+
+/// Which axes the movement gizmo follows
+#[derive(Clone, Copy, PartialEq)]
+pub enum GizmoSpace {
+    World,
+    /// Follow the selected model's rotation
+    Local
+}
+
+impl GizmoSpace {
+    pub fn toggle(&self) -> Self {
+        match self {
+            Self::World => Self::Local,
+            Self::Local => Self::World
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::World => "world",
+            Self::Local => "local"
+        }
+    }
 }
 
 impl SelectionType {
     pub fn cycle(&self) -> Self {
         match self {
             Self::Movement => Self::Scaling,
-            Self::Scaling => Self::Movement,
+            Self::Scaling | Self::Faces => Self::Movement,
             // _ => Self::Movement
         }
     }
@@ -46,6 +105,7 @@ pub struct EditorModeData {
     pub active: bool,
     pub selected_object: Option<Selection>,
     pub selection_type: SelectionType,
+    pub gizmo_space: GizmoSpace,
     pub drag_axis: Option<DragAxis>,
     pub init_drag_along_plane: Option<Vector3<f32>>,
     pub drag_distance: Option<f32>,
@@ -53,18 +113,75 @@ pub struct EditorModeData {
     pub drag_object_scale: Option<Vector3<f32>>,
     pub drag_object_sign: Option<bool>,
     pub drag_plane: Option<Vector3<f32>>,
+    /// Snapped offset from where a plane drag started
+    pub drag_offset: Option<Vector3<f32>>,
+    /// Boxes of the brushes and models not being dragged, gathered once per drag, see `World::snap_to_vertex`
+    pub snap_boxes: Option<Vec<(Vector3<f32>, Vector3<f32>)>>,
     pub increment: f32,
+    /// Dragging and stamping snap to `increment`
+    pub snapping: bool,
+    /// Grid on the ground plane at the increment, see `World::render_ground_grid`
+    pub show_grid: bool,
+    /// From the editor preferences
+    pub grid_color: Vector3<f32>,
+    pub grid_major_color: Vector3<f32>,
     pub selection_box_pos: Vector3<f32>,
     pub selection_box_scale: Vector3<f32>,
     pub selection_box_vao: Option<NativeVertexArray>,
     pub selection_box_visible: bool,
+    /// Material picked in the material picker, clicking a brush applies it
     pub apply_material: Option<String>,
+    /// Brush under the mouse while a material is picked
+    pub material_hover: Option<usize>,
+    pub stamp: Option<Stamp>,
+    /// Temporary pad for trying out a material's physics, never saved
+    pub test_patch: Option<usize>,
     pub light_selected: Option<usize>,
     pub open_light_ui: Option<usize>,
     pub save_to: Option<PathBuf>,
     pub show_debug: Vec<String>,
     pub multiple_selection_offsets: Vec<Vector3<f32>>,
-    pub show_colliders: bool
+    pub show_colliders: bool,
+    /// Run light animations while editing
+    pub preview_light_animation: bool,
+    /// Deleting a selection this big asks for confirmation first, the UI takes this and shows a prompt
+    pub confirm_delete: Option<usize>,
+    /// The level as it was last saved or loaded, used to detect unsaved changes
+    pub saved_snapshot: Option<String>,
+    /// Edits that can be undone, from the editor preferences
+    pub undo_depth: usize,
+    /// Indent saved JSON levels, from the editor preferences
+    pub pretty_levels: bool,
+    /// Seconds between autosaves, 0 turns them off
+    pub autosave_interval: f32,
+    pub autosave_timer: f32,
+    /// The degraded materials window was opened for this level already
+    pub material_problems_shown: bool,
+    /// Minimum far plane while editing, from the editor preferences
+    pub view_distance: f32,
+    /// Usage of each performance budget, see `World::update_stats`
+    pub stats: Vec<BudgetUsage>,
+    pub stats_timer: f32,
+    /// Selection copied with `World::copy_selection`, serialized like a level
+    pub clipboard: Option<String>,
+    /// Color brushes by category or physics while editing
+    pub brush_tint: BrushTint,
+    /// Brushes merged this session, so they can be split again
+    pub merged_brushes: Vec<MergedBrush>,
+    /// Origin and size of the brushes a merge would make, see `World::preview_merge`
+    pub merge_preview: Vec<(Vector3<f32>, Vector3<f32>)>,
+    /// Merge the level's brushes before saving, from the editor preferences
+    pub merge_on_save: bool,
+    /// Editing statistics, see `session`
+    pub session: SessionStats,
+    /// Visibility being baked in the background, see `World::start_visibility_bake`
+    pub visibility_bake: Option<VisibilityBake>,
+    /// Ledges and gaps the player can't get past, see `traversal`
+    pub traversal: TraversalCheck,
+    /// Ruler, player figure and door templates at the cursor, see `reference`
+    pub references: ScaleReferences,
+    /// Material and size of trim added with `World::add_trim`
+    pub trim: TrimSettings
 }
 
 impl EditorModeData {
@@ -95,10 +212,53 @@ pub struct World {
     pub internal: InternalModels,
     pub editor_data: EditorModeData,
     pub load_new: Option<LevelData>,
+    /// `load_new` isn't what is saved at `save_to`, like a restored autosave, so it starts with unsaved changes
+    pub load_unsaved: bool,
     /// this many frames will be ignored
     pub freeze: u32,
     pub do_game_logic: bool,
-    pub loaded_models: Vec<String>
+    pub loaded_models: Vec<String>,
+    pub spawner: Spawner,
+    pub rules: GameplayRules,
+    pub budgets: PerformanceBudgets,
+    /// Seconds since play started, or since the time limit last ran out
+    pub level_time: f32,
+    /// Tracks that start playing when the level loads
+    pub audio: LevelAudio,
+    pub music: MusicManager,
+    pub scripts: ScriptEngine,
+    /// Given to the next model that is inserted without an id
+    pub next_model_id: u64,
+    /// Ids of the models in `models`, copies that kept their id are found with this
+    model_ids: HashSet<u64>,
+    /// Stay the same across saves and loads like model ids, in the same order as the internal brushes model's renderables
+    pub brush_ids: Vec<u64>,
+    /// Given to the next brush that is inserted without an id
+    pub next_brush_id: u64,
+    pub ghosts: Ghosts,
+    pub spectator: Spectator,
+    /// Titles of the objectives done so far in this play session
+    pub completed_objectives: Vec<String>,
+    /// Changes target triggers made while playing, see `World::reset_targets`
+    pub target_undo: Vec<TargetUndo>,
+    pub events: EventLog,
+    pub connections: ConnectionQueue,
+    pub dissolves: Dissolves,
+    pub interaction: Interaction,
+    /// Framebuffer reads waiting on the GPU, see `readback`
+    pub readback: Readback,
+    /// Screenshots that haven't been read back yet
+    pub screenshots: Vec<ReadbackId>,
+    /// Paths of the levels played in order after a goal is reached, saved with the level
+    pub playlist: Vec<String>,
+    /// Models whose collectibles were picked up since play started
+    pub collected: Vec<usize>,
+    /// Set once a goal is reached, the completion screen is shown until the player goes on
+    pub completion: Option<LevelCompletion>,
+    /// Last reason given for not finishing at a goal, so it isn't repeated every frame
+    pub goal_blocked_message: Option<String>,
+    /// Only runs in levels with `GameplayRules::timed`
+    pub stopwatch: Stopwatch
 }
 
 #[derive(Default)]
@@ -117,19 +277,23 @@ pub struct InternalModels {
     pub box_ny: usize,
     pub box_pz: usize,
     pub box_nz: usize,
+    pub plane_xy: usize,
+    pub plane_xz: usize,
+    pub plane_yz: usize,
+    /// Drags parallel to the screen
+    pub gizmo_center: usize,
     /// Tested against to check if a selection is valid
     pub internal_ids: Vec<usize>,
-    /// IDs of all internal movement arrows
+    /// IDs of all internal movement arrows and handles
     pub arrows: Vec<usize>,
     /// IDs of all internal scaling boxes
     pub boxes: Vec<usize>
 }
 
-pub unsafe fn load_brushes(textures: &mut TextureBank, meshes: &mut MeshBank, scene: &mut Scene, gl: &glow::Context) -> Vec<String> {
-    let mut brush_types_file = fs::File::open("res/data/brush_types.json").expect("Could not find brush types data file");
-    let mut brush_types_src = String::new();
-    brush_types_file.read_to_string(&mut brush_types_src).unwrap();
-    let brush_types = serde_json::from_str::<save::BrushMaterialsFile>(&brush_types_src).expect("Failed to parse brush types data file");
+/// Load the brush types into `scene`, returns their names and any problems with the file<br>
+/// Brush types with a shader that won't compile are drawn normally, the error goes to `material_problems`
+pub unsafe fn load_brushes(textures: &mut TextureBank, meshes: &mut MeshBank, programs: &mut ProgramBank, scene: &mut Scene, gl: &glow::Context) -> (Vec<String>, Vec<String>) {
+    let (brush_types, warnings) = save::BrushMaterialsFile::load();
     let mut applicable_types = Vec::new();
 
     for brush_type in brush_types.materials.iter() {
@@ -140,16 +304,38 @@ pub unsafe fn load_brushes(textures: &mut TextureBank, meshes: &mut MeshBank, sc
             PhysicalProperties {
                 friction: brush_type.friction,
                 control: brush_type.control,
-                jump: brush_type.jump
+                jump: brush_type.jump,
+                sound_set: brush_type.sound_set.clone()
             },
             textures,
             gl
         );
-        meshes.add(Mesh::create_material_cube(&brush_type.diffuse, gl), &format!("Brush_{}", brush_type.name));
+        let shader = match &brush_type.shader {
+            Some(shader) => match programs.load_material_program(shader, gl) {
+                Ok(()) => Some(shader.clone()),
+                Err(e) => {
+                    scene.material_problems.push((brush_type.name.clone(), e));
+                    None
+                }
+            },
+            None => None
+        };
+        let material = scene.materials.get_mut(&brush_type.name).unwrap();
+        material.category = brush_type.category;
+        material.shader = shader;
+        material.uniforms = brush_type.uniforms.iter().map(|(name, value)| (name.clone(), value.clone())).collect();
+        material.flipbook = brush_type.flipbook.filter(Flipbook::animated);
+        material.reflectivity = brush_type.reflectivity.clamp(0.0, 1.0);
+        if brush_type.emissive.is_some() || brush_type.emissive_color.is_some() {
+            let color = brush_type.emissive_color.unwrap_or([1.0; 3]);
+            scene.load_material_emissive(&brush_type.name, brush_type.emissive.as_deref(), color.into(), brush_type.emissive_strength.max(0.0), textures, gl);
+        }
+        // The mesh refers to the brush type, so brush types sharing a texture keep their own material
+        meshes.add(Mesh::create_material_cube(&brush_type.name, gl), &format!("Brush_{}", brush_type.name));
         applicable_types.push(brush_type.name.to_owned());
     }
 
-    applicable_types
+    (applicable_types, warnings)
 }
 
 impl World {
@@ -165,34 +351,92 @@ impl World {
             editor_data: EditorModeData {
                 selected_object: None,
                 selection_type: SelectionType::Movement,
+                gizmo_space: GizmoSpace::World,
                 active: false,
                 drag_axis: None,
                 init_drag_along_plane: None,
                 drag_object_origin: None,
                 drag_distance: None,
                 increment: DEFAULT_INCREMENT,
+                snapping: true,
+                show_grid: true,
+                grid_color: render::DEFAULT_GRID_COLOR.into(),
+                grid_major_color: render::DEFAULT_GRID_MAJOR_COLOR.into(),
                 selection_box_pos: Vector3::zero(),
                 selection_box_scale: vec3(1.0, 1.0, 1.0),
                 selection_box_visible: false,
                 selection_box_vao: None,
                 drag_plane: None,
+                drag_offset: None,
+                snap_boxes: None,
                 drag_object_scale: None,
                 drag_object_sign: None,
                 apply_material: None,
+                material_hover: None,
+                stamp: None,
+                test_patch: None,
                 light_selected: None,
                 open_light_ui: None,
                 save_to: None,
                 show_debug: Vec::new(),
                 multiple_selection_offsets: Vec::new(),
-                show_colliders: false
+                show_colliders: false,
+                preview_light_animation: false,
+                confirm_delete: None,
+                saved_snapshot: None,
+                undo_depth: 0,
+                pretty_levels: false,
+                autosave_interval: 0.0,
+                autosave_timer: 0.0,
+                material_problems_shown: false,
+                view_distance: 0.0,
+                stats: Vec::new(),
+                stats_timer: 0.0,
+                clipboard: None,
+                brush_tint: BrushTint::Off,
+                merged_brushes: Vec::new(),
+                merge_preview: Vec::new(),
+                merge_on_save: false,
+                session: SessionStats::default(),
+                visibility_bake: None,
+                traversal: TraversalCheck::default(),
+                references: ScaleReferences::default(),
+                trim: TrimSettings::default()
             },
             load_new: None,
+            load_unsaved: false,
             freeze: 0,
             do_game_logic: true,
-            loaded_models: Vec::new()
+            loaded_models: Vec::new(),
+            spawner: Spawner::new(),
+            rules: GameplayRules::default(),
+            budgets: PerformanceBudgets::default(),
+            level_time: 0.0,
+            audio: LevelAudio::default(),
+            music: MusicManager::new(),
+            scripts: ScriptEngine::new(),
+            next_model_id: 1,
+            model_ids: HashSet::new(),
+            brush_ids: Vec::new(),
+            next_brush_id: 1,
+            ghosts: Ghosts::default(),
+            spectator: Spectator::default(),
+            completed_objectives: Vec::new(),
+            target_undo: Vec::new(),
+            events: EventLog::new(),
+            connections: ConnectionQueue::default(),
+            dissolves: Dissolves::default(),
+            interaction: Interaction::default(),
+            readback: Readback::default(),
+            screenshots: Vec::new(),
+            playlist: Vec::new(),
+            collected: Vec::new(),
+            completion: None,
+            goal_blocked_message: None,
+            stopwatch: Stopwatch::default()
         };
 
-        world.player.collider = world.physical_scene.add_collider(Collider::cuboid(Vector3::zero(), vec3(0.5, 2.0, 0.5), Vector3::zero(), Matrix4::identity()));
+        world.player.collider = world.physical_scene.add_collider(Collider::cuboid(Vector3::zero(), PLAYER_SIZE, Vector3::zero(), Matrix4::identity()));
 
         world
     }
@@ -206,6 +450,7 @@ impl World {
             meshes.add(Mesh::create_colored_cube(1.0, 0.0, 0.0, gl), "cubered");
             meshes.add(Mesh::create_colored_cube(0.0, 1.0, 0.0, gl), "cubegreen");
             meshes.add(Mesh::create_colored_cube(0.0, 0.0, 1.0, gl), "cubeblue");
+            meshes.add(Mesh::create_colored_cube(1.0, 1.0, 1.0, gl), "cubewhite");
         }
 
         // Collider is slightly larger than the arrow to make them less annoying to click
@@ -223,6 +468,11 @@ impl World {
         self.internal.box_ny = self.insert_model(Model::new(true, Matrix4::identity(), vec![ Renderable::Mesh("cubeblue".to_string(), Matrix4::identity(), flags::FULLBRIGHT) ]).collider_cuboid(Vector3::zero(), vec3(0.6, 0.6, 0.6)).foreground().non_solid());
         self.internal.box_pz = self.insert_model(Model::new(true, Matrix4::identity(), vec![ Renderable::Mesh("cubegreen".to_string(), Matrix4::identity(), flags::FULLBRIGHT) ]).collider_cuboid(Vector3::zero(), vec3(0.6, 0.6, 0.6)).foreground().non_solid());
         self.internal.box_nz = self.insert_model(Model::new(true, Matrix4::identity(), vec![ Renderable::Mesh("cubegreen".to_string(), Matrix4::identity(), flags::FULLBRIGHT) ]).collider_cuboid(Vector3::zero(), vec3(0.6, 0.6, 0.6)).foreground().non_solid());
+        // Plane handles are flat cubes colored after the axis they don't move along
+        self.internal.plane_xy = self.insert_model(Model::new(true, Matrix4::identity(), vec![ Renderable::Mesh("cubegreen".to_string(), Matrix4::from_nonuniform_scale(1.0, 1.0, 0.1), flags::FULLBRIGHT) ]).collider_cuboid(Vector3::zero(), vec3(0.6, 0.6, 0.1)).foreground().non_solid());
+        self.internal.plane_xz = self.insert_model(Model::new(true, Matrix4::identity(), vec![ Renderable::Mesh("cubeblue".to_string(), Matrix4::from_nonuniform_scale(1.0, 0.1, 1.0), flags::FULLBRIGHT) ]).collider_cuboid(Vector3::zero(), vec3(0.6, 0.1, 0.6)).foreground().non_solid());
+        self.internal.plane_yz = self.insert_model(Model::new(true, Matrix4::identity(), vec![ Renderable::Mesh("cubered".to_string(), Matrix4::from_nonuniform_scale(0.1, 1.0, 1.0), flags::FULLBRIGHT) ]).collider_cuboid(Vector3::zero(), vec3(0.1, 0.6, 0.6)).foreground().non_solid());
+        self.internal.gizmo_center = self.insert_model(Model::new(true, Matrix4::identity(), vec![ Renderable::Mesh("cubewhite".to_string(), Matrix4::identity(), flags::FULLBRIGHT) ]).collider_cuboid(Vector3::zero(), vec3(0.6, 0.6, 0.6)).foreground().non_solid());
     
         self.internal.internal_ids.extend(vec![
             self.internal.arrow_px, self.internal.arrow_py, self.internal.arrow_pz,
@@ -230,11 +480,15 @@ impl World {
             self.internal.brushes, self.internal.debug_arrow,
             self.internal.box_px, self.internal.box_py, self.internal.box_pz,
             self.internal.box_nx, self.internal.box_ny, self.internal.box_nz,
+            self.internal.plane_xy, self.internal.plane_xz, self.internal.plane_yz,
+            self.internal.gizmo_center
         ]);
         self.internal.arrows = vec![
             self.internal.arrow_px, self.internal.arrow_nx, 
             self.internal.arrow_py, self.internal.arrow_ny,
-            self.internal.arrow_pz, self.internal.arrow_nz
+            self.internal.arrow_pz, self.internal.arrow_nz,
+            self.internal.plane_xy, self.internal.plane_xz,
+            self.internal.plane_yz, self.internal.gizmo_center
         ];
         self.internal.boxes = vec![
             self.internal.box_px, self.internal.box_nx, 
@@ -250,7 +504,7 @@ impl World {
                 self.set_boxes_visible(true);
                 self.move_arrows_far();
             },
-            SelectionType::Scaling => {
+            SelectionType::Scaling | SelectionType::Faces => {
                 self.set_arrows_visible(true);
                 self.set_boxes_visible(false);
                 self.move_boxes_far();
@@ -345,26 +599,100 @@ impl World {
         }
     }
 
+    /// Moves a brush out of the internal brushes model into its own mobile model, returns the new model index<br>
+    /// The brush keeps its exact world transform and the physical properties of its collider
     pub fn make_brush_unique(&mut self, index: usize) -> usize {
-        let mut brush = self.models[self.internal.brushes].as_ref().unwrap().render[index].clone();
-        self.remove_brush(index);
-        let mut model_transform = Matrix4::identity();
-        if let Renderable::Brush(_, origin, _, _) = &mut brush {
+        let brushes = self.models[self.internal.brushes].as_ref().unwrap();
+        let mut brush = brushes.render[index].clone();
+        let collider = brushes.colliders[index].and_then(|c| self.physical_scene.collider(c));
+        let properties = collider.map(|c| c.physical_properties.clone());
+        let solid = collider.map(|c| c.solid).unwrap_or(true);
+
+        let mut model_transform = brushes.transform;
+        if let Renderable::Brush(_, origin, ..) = &mut brush {
             model_transform = model_transform * Matrix4::from_translation(*origin);
             *origin = Vector3::zero();
         }
 
-        let model = Model::new(true, model_transform, vec![brush]);
-
+        self.remove_brush(index);
+        let mut model = Model::new(true, model_transform, vec![brush]);
+        model.solid = solid;
         let index = self.insert_model(model);
-        self.recalculate_colliders(index);
+
+        if let Some(properties) = properties {
+            self.set_renderable_physical_properties(index, 0, properties);
+        }
+
         index
     }
 
+    /// Moves a model made of a single brush back into the internal brushes model, returns the new brush index<br>
+    /// Fails if the model has anything a plain brush can't represent, like rotation, components or lights
+    pub fn absorb_brush_model(&mut self, index: usize) -> Result<usize, String> {
+        let brushes_transform = self.models[self.internal.brushes].as_ref().unwrap().transform;
+        let model = self.models.get(index).and_then(|m| m.as_ref()).ok_or("No model at that index")?;
+
+        let Some(Renderable::Brush(material, origin, size, flags, faces)) = model.render.first() else {
+            return Err(String::from("model is not a brush"));
+        };
+        if model.render.len() > 1 || model.insert_collider.is_some() {
+            return Err(String::from("model has more than one brush or collider"));
+        }
+        if !model.components.is_empty() || !model.lights.is_empty() {
+            return Err(String::from("model has components or lights"));
+        }
+        if model.hidden || model.foreground {
+            return Err(String::from("hidden and foreground models can't be brushes"));
+        }
+
+        // Brushes are axis aligned, so only translation and scale can be folded into the brush
+        let relative = brushes_transform.invert().ok_or("brushes model transform is not invertible")? * model.transform;
+        let linear = common::mat4_to_mat3(relative);
+        for (column, row) in [(0, 1), (0, 2), (1, 0), (1, 2), (2, 0), (2, 1)] {
+            if linear[column][row].abs() > EPSILON {
+                return Err(String::from("rotated models can't be brushes"));
+            }
+        }
+        let scale = vec3(linear.x.x, linear.y.y, linear.z.z);
+        let new_origin = common::translation(relative) + scale.mul_element_wise(*origin);
+        let new_size = vec3(size.x * scale.x.abs(), size.y * scale.y.abs(), size.z * scale.z.abs());
+        let brush = Renderable::Brush(material.clone(), new_origin, new_size, *flags, *faces);
+
+        let collider = model.colliders.first().copied().flatten().and_then(|c| self.physical_scene.collider(c));
+        let properties = collider.map(|c| c.physical_properties.clone());
+        let solid = collider.map(|c| c.solid).unwrap_or(true);
+
+        self.remove_model(index)?;
+        let brush_index = self.insert_brush(brush);
+
+        let brush_collider = self.models[self.internal.brushes].as_ref().unwrap().colliders[brush_index];
+        if let Some(collider) = brush_collider.and_then(|c| self.physical_scene.collider_mut(c)) {
+            if let Some(properties) = properties {
+                collider.physical_properties = properties;
+            }
+            collider.solid = solid;
+        }
+
+        Ok(brush_index)
+    }
+
+    /// Override the physical properties of the collider belonging to a renderable
+    pub fn set_renderable_physical_properties(&mut self, model: usize, renderable: usize, properties: PhysicalProperties) {
+        let collider = self.models[model].as_ref().unwrap().colliders.get(renderable).copied().flatten();
+        if let Some(collider) = collider.and_then(|c| self.physical_scene.collider_mut(c)) {
+            collider.physical_properties = properties;
+        }
+    }
+
     fn toggle_hide_model(&mut self, index: usize) {
-        let new_visible_state = self.models[index].as_ref().unwrap().hidden;
-        self.set_model_visible(index, new_visible_state);
-        self.models[index].as_mut().unwrap().hidden = !new_visible_state;
+        let hidden = self.models[index].as_ref().unwrap().hidden;
+        self.set_model_hidden(index, !hidden);
+    }
+
+    /// Hidden models are only drawn while editing, only mobile models can be hidden
+    pub fn set_model_hidden(&mut self, index: usize, hidden: bool) {
+        self.set_model_visible(index, !hidden);
+        self.models[index].as_mut().unwrap().hidden = hidden;
     }
 
     fn toggle_hide_brush(&mut self, index: usize) -> usize {
@@ -399,10 +727,21 @@ impl World {
         }
     }
 
-    fn can_be_selected(&self, model: usize) -> bool {
+    pub fn can_be_selected(&self, model: usize) -> bool {
         !self.internal.internal_ids.contains(&model)
     }
 
+    /// The model's name, or its index if it has none
+    pub fn model_label(&self, index: usize) -> String {
+        if index == self.internal.brushes {
+            return String::from("brushes");
+        }
+        match self.models.get(index).and_then(|model| model.as_ref()) {
+            Some(model) => model.label(),
+            None => format!("model {}", index)
+        }
+    }
+
     fn get_models_or_brushes_within_rect(&self, x0: i32, y0: i32, x1: i32, y1: i32, window_width: u32, window_height: u32, brushes: bool) -> Vec<usize> {
         let to_clip = self.scene.camera.projection * self.scene.camera.view;
         let mut models_in_box = Vec::new();
@@ -413,7 +752,7 @@ impl World {
 
         if brushes {
             for (i, brush) in self.models[self.internal.brushes].as_ref().unwrap().render.iter().enumerate() {
-                if let Renderable::Brush(_, pos, size, _) = brush {
+                if let Renderable::Brush(_, pos, size, ..) = brush {
                     let pos = vec4(pos.x, pos.y, pos.z, 1.0);
                     let clip = to_clip * pos;
                     let ndc = clip.truncate() / clip.w;
@@ -452,27 +791,50 @@ impl World {
     pub fn model_pressed(&mut self, result: RaycastResult) {
         if self.editor_data.active {
             if let Some(model) = result.model {
+                let [x, y, z] = self.gizmo_axes();
+                let direction = self.scene.camera.direction;
+                // Drag on whichever of the other two axes' planes faces the camera the most
+                let facing = |a: Vector3<f32>, b: Vector3<f32>| if direction.dot(a).abs() > direction.dot(b).abs() { a } else { b };
+
                 if model == self.internal.arrow_nx || model == self.internal.arrow_px || model == self.internal.box_nx || model == self.internal.box_px {
                     self.editor_data.drag_axis = Some(DragAxis::X);
-                    if self.scene.camera.direction.dot(Vector3::unit_y()).abs() > self.scene.camera.direction.dot(Vector3::unit_z()).abs() {
-                        self.editor_data.drag_plane = Some(Vector3::unit_y());
-                    } else {
-                        self.editor_data.drag_plane = Some(Vector3::unit_z());
-                    }
+                    self.editor_data.drag_plane = Some(facing(y, z));
                 } else if model == self.internal.arrow_ny || model == self.internal.arrow_py || model == self.internal.box_ny || model == self.internal.box_py {
                     self.editor_data.drag_axis = Some(DragAxis::Y);
-                    if self.scene.camera.direction.dot(Vector3::unit_x()).abs() > self.scene.camera.direction.dot(Vector3::unit_z()).abs() {
-                        self.editor_data.drag_plane = Some(Vector3::unit_x());
-                    } else {
-                        self.editor_data.drag_plane = Some(Vector3::unit_z());
-                    }
+                    self.editor_data.drag_plane = Some(facing(x, z));
                 } else if model == self.internal.arrow_nz || model == self.internal.arrow_pz || model == self.internal.box_nz || model == self.internal.box_pz {
                     self.editor_data.drag_axis = Some(DragAxis::Z);
-                    if self.scene.camera.direction.dot(Vector3::unit_x()).abs() > self.scene.camera.direction.dot(Vector3::unit_y()).abs() {
-                        self.editor_data.drag_plane = Some(Vector3::unit_x());
+                    self.editor_data.drag_plane = Some(facing(x, y));
+                } else if model == self.internal.plane_xy {
+                    self.editor_data.drag_axis = Some(DragAxis::Plane);
+                    self.editor_data.drag_plane = Some(z);
+                } else if model == self.internal.plane_xz {
+                    self.editor_data.drag_axis = Some(DragAxis::Plane);
+                    self.editor_data.drag_plane = Some(y);
+                } else if model == self.internal.plane_yz {
+                    self.editor_data.drag_axis = Some(DragAxis::Plane);
+                    self.editor_data.drag_plane = Some(x);
+                } else if model == self.internal.gizmo_center {
+                    self.editor_data.drag_axis = Some(DragAxis::Plane);
+                    self.editor_data.drag_plane = Some(direction);
+                } else if model == self.internal.brushes && self.editor_data.selection_type == SelectionType::Faces && result.renderable.is_some() && result.renderable == self.editor_data.get_selected_brush() {
+                    // Grab the clicked face of the selected brush, the same as its arrow
+                    let normal = result.normal;
+                    let (axis, plane) = if normal.x.abs() >= normal.y.abs() && normal.x.abs() >= normal.z.abs() {
+                        (DragAxis::X, facing(y, z))
+                    } else if normal.y.abs() >= normal.z.abs() {
+                        (DragAxis::Y, facing(x, z))
                     } else {
-                        self.editor_data.drag_plane = Some(Vector3::unit_y());
-                    }
+                        (DragAxis::Z, facing(x, y))
+                    };
+                    let along = match axis {
+                        DragAxis::X => normal.x,
+                        DragAxis::Y => normal.y,
+                        _ => normal.z
+                    };
+                    self.editor_data.drag_axis = Some(axis);
+                    self.editor_data.drag_plane = Some(plane);
+                    self.editor_data.drag_object_sign = Some(along > 0.0);
                 }
 
                 if model == self.internal.arrow_px || model == self.internal.arrow_py || model == self.internal.arrow_pz || model == self.internal.box_px || model == self.internal.box_py || model == self.internal.box_pz {
@@ -510,6 +872,89 @@ impl World {
         }
     }
 
+    /// Track which brush the material preview should be drawn on
+    pub fn update_material_hover(&mut self, result: Option<&RaycastResult>) {
+        self.editor_data.material_hover = None;
+        if self.editor_data.active && self.editor_data.apply_material.is_some() {
+            if let Some(result) = result {
+                if result.model == Some(self.internal.brushes) {
+                    self.editor_data.material_hover = result.renderable;
+                }
+            }
+        }
+    }
+
+    /// Apply the picked material to the clicked brush, or to it and every selected brush if `whole_selection` is set<br>
+    /// Returns true if the click was used up
+    pub fn material_clicked(&mut self, result: &RaycastResult, whole_selection: bool) -> bool {
+        if !self.editor_data.active { return false; }
+        let Some(material) = self.editor_data.apply_material.clone() else { return false; };
+        if result.model != Some(self.internal.brushes) { return false; }
+        let Some(clicked) = result.renderable else { return false; };
+
+        let mut targets = vec![clicked];
+        if whole_selection {
+            match &self.editor_data.selected_object {
+                Some(Selection::Brush(brush)) => targets.push(*brush),
+                Some(Selection::Multiple(multiple)) => {
+                    for selection in multiple {
+                        if let Selection::Brush(brush) = selection {
+                            targets.push(*brush);
+                        }
+                    }
+                },
+                _ => ()
+            }
+        }
+        targets.sort();
+        targets.dedup();
+
+        let new_indices = self.set_brushes_material(&targets, &material);
+        let remap = |brush: usize| {
+            if let Ok(i) = targets.binary_search(&brush) {
+                new_indices[i]
+            } else {
+                brush - targets.iter().filter(|target| **target < brush).count()
+            }
+        };
+
+        match &mut self.editor_data.selected_object {
+            Some(Selection::Brush(brush)) => *brush = remap(*brush),
+            Some(Selection::Multiple(multiple)) => {
+                for selection in multiple.iter_mut() {
+                    if let Selection::Brush(brush) = selection {
+                        *brush = remap(*brush);
+                    }
+                }
+            },
+            _ => ()
+        }
+
+        self.editor_data.material_hover = None;
+        true
+    }
+
+    /// Place a walkable pad of `material` in front of the camera, replacing any previous one<br>
+    /// It is removed after some time in play mode and is never saved with the level
+    pub fn spawn_test_patch(&mut self, material: &str) {
+        if let Some(previous) = self.editor_data.test_patch.take() {
+            self.despawn(previous);
+        }
+
+        let camera = &self.scene.camera;
+        let forward = vec3(camera.direction.x, 0.0, camera.direction.z);
+        let forward = if forward.magnitude2() > EPSILON { forward.normalize() } else { vec3(0.0, 0.0, -1.0) };
+        let center = camera.pos.to_vec() + forward * (TEST_PATCH_SIZE.z / 2.0 + 1.0) - vec3(0.0, 2.0, 0.0);
+        let center = vec3(common::round_to(center.x, self.editor_data.increment), common::round_to(center.y, self.editor_data.increment), common::round_to(center.z, self.editor_data.increment));
+
+        let index = self.insert_model(Model::new(
+            false, Matrix4::from_translation(center),
+            vec![Renderable::Brush(material.to_string(), vec3_zero(), TEST_PATCH_SIZE, flags::EXTEND_TEXTURE, BrushFaces::default())]
+        ));
+        self.despawn_after(index, TEST_PATCH_LIFETIME);
+        self.editor_data.test_patch = Some(index);
+    }
+
     pub fn air_clicked(&mut self) {
         self.deselect();
         self.editor_data.selected_object = None;
@@ -530,30 +975,35 @@ impl World {
     }
 
     pub fn insert_model(&mut self, mut model: Model) -> usize {
+        // Copies of a model keep its id, so they need a new one
+        if model.id == 0 || self.model_ids.contains(&model.id) {
+            model.id = self.next_model_id;
+        }
+        self.next_model_id = self.next_model_id.max(model.id + 1);
+        self.model_ids.insert(model.id);
+
         for light in model.lights.iter() {
             let position = light.0 + common::translation(model.transform);// (model.transform * vec4(0.0, 0.0, 0.0, 1.0)).xyz();
             self.scene.point_lights[light.1].position = position;
         }
 
-        for i in 0..self.models.len() {
-            if self.models[i].is_none() {
-                model.index = Some(i);
-                self.pre_insert_model(&mut model);
-                self.models[i] = Some(model);
-                return i;
-            }
-        }
-
         let hidden = model.hidden;
-        model.index = Some(self.models.len());
+        let index = match self.models.iter().position(Option::is_none) {
+            Some(free) => free,
+            None => {
+                self.models.push(None);
+                self.models.len() - 1
+            }
+        };
+        model.index = Some(index);
         self.pre_insert_model(&mut model);
-        self.models.push(Some(model));
+        self.models[index] = Some(model);
 
         if hidden {
-            self.set_model_visible(self.models.len() - 1, false);
+            self.set_model_visible(index, false);
         }
 
-        self.models.len() - 1
+        index
     }
 
     pub fn remove_model(&mut self, index: usize) -> Result<(), String> {
@@ -564,6 +1014,11 @@ impl World {
         }
 
         if let Some(mut model) = self.models[index].take() {
+            self.model_ids.remove(&model.id);
+            self.spawner.forget(index);
+            if self.editor_data.test_patch == Some(index) {
+                self.editor_data.test_patch = None;
+            }
             for i in 0..model.renderable_indices.len() {
                 self.scene.remove_renderable(&mut model, i);
             }
@@ -578,6 +1033,91 @@ impl World {
         }
     }
 
+    /// Index of the model a point light is attached to
+    pub fn light_owner(&self, light: usize) -> Option<usize> {
+        self.models.iter().position(|model| model.as_ref().is_some_and(|model| model.lights.iter().any(|(_, l)| *l == light)))
+    }
+
+    pub fn get_light_animation(&self, model: usize) -> Option<LightAnimation> {
+        self.models[model].as_ref()?.components.iter().find_map(|component| match component {
+            Component::LightAnimator(animator) => Some(animator.animation),
+            _ => None
+        })
+    }
+
+    /// Replace the model's light animator, `None` removes it and restores its lights
+    pub fn set_light_animation(&mut self, model: usize, animation: Option<LightAnimation>) {
+        let Some(target) = self.models[model].as_mut() else { return; };
+        target.components.retain(|component| !matches!(component, Component::LightAnimator(_)));
+        if let Some(animation) = animation {
+            target.components.push(Component::LightAnimator(LightAnimator::preset(animation)));
+        }
+
+        for (_, light) in target.lights.iter() {
+            self.scene.point_lights[*light].set_animated_color(vec3_all(1.0));
+        }
+    }
+
+    pub fn get_spot_light(&self, model: usize) -> Option<SpotLightSource> {
+        self.models.get(model)?.as_ref()?.components.iter().find_map(|component| match component {
+            Component::SpotLight(spot) => Some(*spot),
+            _ => None
+        })
+    }
+
+    /// Replace the model's spot light, it shows up on the next update
+    pub fn set_spot_light(&mut self, model: usize, spot: SpotLightSource) {
+        let Some(target) = self.models[model].as_mut() else { return; };
+        for component in target.components.iter_mut() {
+            if let Component::SpotLight(old) = component {
+                *old = spot;
+            }
+        }
+    }
+
+    pub fn get_rotator(&self, model: usize) -> Option<Rotator> {
+        self.models.get(model)?.as_ref()?.components.iter().find_map(|component| match component {
+            Component::Rotator(rotator) => Some(rotator.clone()),
+            _ => None
+        })
+    }
+
+    pub fn set_rotator(&mut self, model: usize, rotator: Rotator) {
+        let Some(target) = self.models[model].as_mut() else { return; };
+        for component in target.components.iter_mut() {
+            if let Component::Rotator(old) = component {
+                *old = rotator.clone();
+            }
+        }
+    }
+
+    /// Damage per second of the model's hurt volume
+    pub fn get_hurt_dps(&self, model: usize) -> Option<f32> {
+        self.models.get(model)?.as_ref()?.components.iter().find_map(|component| match component {
+            Component::Hurt { dps, .. } => Some(*dps),
+            _ => None
+        })
+    }
+
+    pub fn set_hurt_dps(&mut self, model: usize, new_dps: f32) {
+        let Some(target) = self.models[model].as_mut() else { return; };
+        for component in target.components.iter_mut() {
+            if let Component::Hurt { dps, .. } = component {
+                *dps = new_dps;
+            }
+        }
+    }
+
+    /// Spot lights belong to components, so the scene's list is rebuilt from the models
+    fn gather_spot_lights(&mut self) {
+        self.scene.spot_lights = self.models.iter().flatten().flat_map(|model| {
+            model.components.iter().filter_map(|component| match component {
+                Component::SpotLight(spot) => Some(spot.light(model.transform)),
+                _ => None
+            })
+        }).collect();
+    }
+
     /// This also removes the point light from the model TODO: this breaks on level load
     pub fn remove_point_light(&mut self, light: usize) {    
         let mut removed = false;
@@ -611,11 +1151,12 @@ impl World {
 
     pub fn set_brush_origin_scale(&mut self, brush_index: usize, new_origin: Vector3<f32>, new_scale: Option<Vector3<f32>>) {
         let brush = self.models[self.internal.brushes].as_mut().unwrap().render.get_mut(brush_index).unwrap();
+        let name = brush.get_mesh();
 
-        if let Renderable::Brush(material, origin, scale, _) = brush {
+        if let Renderable::Brush(_, origin, scale, ..) = brush {
             *origin = new_origin;
             *scale = new_scale.unwrap_or(*scale);
-            let name = format!("Brush_{}", material);
+            let name = name.unwrap();
             self.scene.mark_static(&name);
             // this counts on the transform of self.internal.brushes being identity
             let transform = Matrix4::from_translation(*origin) * Matrix4::from_nonuniform_scale(scale.x, scale.y, scale.z);
@@ -627,7 +1168,8 @@ impl World {
     }
 
     /// scale a model on an anchor
-    fn scale_model_anchored_nonuniform(&mut self, index: usize, new_scale: Vector3<f32>, anchor: Vector3<f32>, axis: Vector3<f32>) {
+    /// `axis` picks the component of the extents to change, `frame` holds the directions of the gizmo axes the scale is along
+    fn scale_model_anchored_nonuniform(&mut self, index: usize, new_scale: Vector3<f32>, anchor: Vector3<f32>, axis: Vector3<f32>, frame: Matrix3<f32>) {
         let model = self.models[index].take().unwrap();
 
         let old_extent = model.extents.unwrap().1.dot(axis).abs();
@@ -638,7 +1180,9 @@ impl World {
         let new_transform = 
             Matrix4::from_translation(previous_pos) *
             Matrix4::from_translation(anchor) * 
+            Matrix4::from(frame) *
             Matrix4::from_nonuniform_scale(scale_factor.x, scale_factor.y, scale_factor.z) * 
+            Matrix4::from(frame.transpose()) *
             Matrix4::from_translation(-anchor) *
             mat4_remove_translation(model.transform);
 
@@ -652,7 +1196,7 @@ impl World {
         
         for collider in model.colliders.iter_mut() {
             if let Some(collider) = collider {
-                self.physical_scene.colliders[*collider].as_mut().unwrap().set_transform(transform);
+                self.physical_scene.collider_mut(*collider).unwrap().set_transform(transform);
             }
         }
 
@@ -695,36 +1239,236 @@ impl World {
             }
             
             //self.scene.amend_model(self.models[self.internal.brushes].as_mut().unwrap(), new_brush);
-            self.remove_brush(brush_index);
-            self.insert_brush(new_brush)
+            let id = self.remove_brush(brush_index);
+            self.insert_brush_with_id(new_brush, id)
         } else {
             panic!("Non-brush in internal brush model");
         }
     }
 
+    /// Brushes with different alignments use different meshes, so this also moves the brush, returns the new brush index
+    pub fn set_brush_faces(&mut self, brush_index: usize, new_faces: BrushFaces) -> usize {
+        let mut brush = self.models[self.internal.brushes].as_ref().unwrap().render[brush_index].clone();
+        let Renderable::Brush(.., faces) = &mut brush else { panic!("Non-brush in internal brush model"); };
+        if *faces == new_faces {
+            return brush_index;
+        }
+        *faces = new_faces;
+
+        let collider = self.models[self.internal.brushes].as_ref().unwrap().colliders[brush_index].and_then(|c| self.physical_scene.collider(c));
+        let properties = collider.map(|c| c.physical_properties.clone());
+        let id = self.remove_brush(brush_index);
+        let new_index = self.insert_brush_with_id(brush, id);
+        if let Some(properties) = properties {
+            self.set_renderable_physical_properties(self.internal.brushes, new_index, properties);
+        }
+        new_index
+    }
+
+    /// Set the material of several brushes at once, returns the new indices in the same order as `brushes`<br>
+    /// `brushes` must not contain duplicates
+    pub fn set_brushes_material(&mut self, brushes: &[usize], material: &str) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..brushes.len()).collect();
+        // Remove from the back so earlier indices stay valid
+        order.sort_by(|a, b| brushes[*b].cmp(&brushes[*a]));
+
+        let mut removed = vec![None; brushes.len()];
+        for i in order {
+            let mut brush = self.models[self.internal.brushes].as_ref().unwrap().render[brushes[i]].clone();
+            if let Renderable::Brush(old_material, ..) = &mut brush {
+                *old_material = material.to_string();
+            }
+            let id = self.remove_brush(brushes[i]);
+            removed[i] = Some((brush, id));
+        }
+
+        removed.into_iter().map(|removed| {
+            let (brush, id) = removed.unwrap();
+            self.insert_brush_with_id(brush, id)
+        }).collect()
+    }
+
+    /// Corners of a brush
+    pub fn brush_bounds(&self, brush: usize) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        match self.models[self.internal.brushes].as_ref()?.render.get(brush)? {
+            Renderable::Brush(_, origin, size, ..) => Some((*origin - *size / 2.0, *origin + *size / 2.0)),
+            _ => None
+        }
+    }
+
+    /// Replace `brushes` with the parts of them outside of `cut`, returns the indices of the new brushes
+    fn cut_brushes(&mut self, brushes: &[usize], cut: (Vector3<f32>, Vector3<f32>)) -> Vec<usize> {
+        let mut order = brushes.to_vec();
+        // Remove from the back so earlier indices stay valid
+        order.sort_by(|a, b| b.cmp(a));
+
+        let mut pieces = Vec::new();
+        for brush in order {
+            if let Renderable::Brush(material, origin, size, flags, faces) = self.models[self.internal.brushes].as_ref().unwrap().render[brush].clone() {
+                for (min, max) in common::box_difference(origin - size / 2.0, origin + size / 2.0, cut) {
+                    pieces.push(Renderable::Brush(material.clone(), (min + max) / 2.0, max - min, flags, faces));
+                }
+                self.remove_brush(brush);
+            }
+        }
+        pieces.into_iter().map(|piece| self.insert_brush(piece)).collect()
+    }
+
+    /// Replace a brush with six walls `thickness` thick around its inside, returns the new brush indices
+    pub fn hollow_brush(&mut self, brush: usize, thickness: f32) -> Result<Vec<usize>, String> {
+        let (min, max) = self.brush_bounds(brush).ok_or(String::from("that's not a brush"))?;
+        if (0..3).any(|axis| max[axis] - min[axis] <= thickness * 2.0) {
+            return Err(format!("the brush is too thin to hollow with {} thick walls", thickness));
+        }
+        Ok(self.cut_brushes(&[brush], (min + vec3_all(thickness), max - vec3_all(thickness))))
+    }
+
+    /// Cut the volume of `cutter` out of `brush`, which is split into the cuboids that are left<br>
+    /// `cutter` is kept, its index may change, returns the new brush indices
+    pub fn subtract_brush(&mut self, brush: usize, cutter: usize) -> Result<Vec<usize>, String> {
+        let bounds = self.brush_bounds(brush).ok_or(String::from("that's not a brush"))?;
+        let cut = self.brush_bounds(cutter).ok_or(String::from("the cutter is not a brush"))?;
+        if brush == cutter || !common::boxes_overlap(bounds, cut) {
+            return Err(String::from("the brushes don't overlap"));
+        }
+        Ok(self.cut_brushes(&[brush], cut))
+    }
+
+    /// Subtract `cutter` from every brush it overlaps, then remove it<br>
+    /// Returns the indices of the new brushes
+    pub fn carve_with_brush(&mut self, cutter: usize) -> Result<Vec<usize>, String> {
+        let cut = self.brush_bounds(cutter).ok_or(String::from("the cutter is not a brush"))?;
+        let count = self.models[self.internal.brushes].as_ref().unwrap().render.len();
+        let hit: Vec<usize> = (0..count)
+            .filter(|brush| *brush != cutter && self.brush_bounds(*brush).is_some_and(|bounds| common::boxes_overlap(bounds, cut)))
+            .collect();
+        if hit.is_empty() {
+            return Err(String::from("the cutter doesn't overlap any brushes"));
+        }
+
+        let pieces = self.cut_brushes(&hit, cut);
+        // The pieces went in after the cutter, which moved down by the number of brushes removed before it
+        let cutter = cutter - hit.iter().filter(|brush| **brush < cutter).count();
+        self.remove_brush(cutter);
+        Ok(pieces.into_iter().map(|piece| piece - 1).collect())
+    }
+
+    /// Change the render flags of a single renderable in a model
+    pub fn set_renderable_flags(&mut self, model: usize, renderable: usize, flags: u32) {
+        if let Some(model) = self.models.get_mut(model).and_then(|m| m.as_mut()) {
+            if let Some(target) = model.render.get_mut(renderable) {
+                target.set_flags(flags);
+                self.scene.update_renderable_flags(model, renderable);
+            }
+        }
+    }
+
+    pub fn set_brush_flags(&mut self, brush_index: usize, flags: u32) {
+        self.set_renderable_flags(self.internal.brushes, brush_index, flags);
+    }
+
+    /// Every renderable in the selection as (model, renderable)
+    pub fn selected_renderables(&self) -> Vec<(usize, usize)> {
+        let Some(selection) = &self.editor_data.selected_object else { return Vec::new(); };
+        selection.items().iter().flat_map(|item| match item {
+            Selection::Brush(brush) => vec![(self.internal.brushes, *brush)],
+            Selection::Model(model) => (0..self.models[*model].as_ref().unwrap().render.len()).map(|i| (*model, i)).collect(),
+            Selection::Multiple(_) => unreachable!()
+        }).collect()
+    }
+
+    /// Position of a single selected brush or model
+    pub fn selection_item_origin(&self, item: &Selection) -> Vector3<f32> {
+        match item {
+            Selection::Brush(brush) => {
+                if let Renderable::Brush(_, origin, ..) = self.models[self.internal.brushes].as_ref().unwrap().render[*brush] { origin } else { unreachable!() }
+            },
+            Selection::Model(model) => self.models[*model].as_ref().unwrap().origin(),
+            Selection::Multiple(_) => unreachable!()
+        }
+    }
+
+    /// Move every selected brush and model so one axis of its origin is `value`, the other axes are kept
+    pub fn set_selection_axis(&mut self, axis: usize, value: f32) {
+        let Some(selection) = &self.editor_data.selected_object else { return; };
+        for item in selection.items() {
+            let mut origin = self.selection_item_origin(&item);
+            origin[axis] = value;
+            self.transform_selection(origin, &item);
+        }
+    }
+
+    /// Set or clear a render flag on every selected renderable
+    pub fn set_selection_flag(&mut self, flag: u32, set: bool) {
+        for (model, renderable) in self.selected_renderables() {
+            let current = self.models[model].as_ref().unwrap().render[renderable].flags();
+            let new_flags = if set { current | flag } else { current & !flag };
+            if new_flags != current {
+                self.set_renderable_flags(model, renderable, new_flags);
+            }
+        }
+    }
+
+    /// Add a copy of `component` to every selected model, brushes are skipped<br>
+    /// Returns how many models it was added to
+    pub fn add_component_to_selection(&mut self, component: Component) -> usize {
+        let Some(selection) = &self.editor_data.selected_object else { return 0; };
+        let mut added = 0;
+        for item in selection.items() {
+            if let Selection::Model(index) = item {
+                let mut model = self.models[index].take().unwrap();
+                model.components.push(component.clone());
+                Component::on_insert(model.components.len() - 1, &mut model, self);
+                self.models[index] = Some(model);
+                added += 1;
+            }
+        }
+
+        added
+    }
+
     pub fn debug_brushes(&self) {
         println!("{:?}", self.models[self.internal.brushes].as_ref().unwrap().render);
     }
 
     /// This places the brush inside the internal brushes model instead of making a new model for each brush
     pub fn insert_brush(&mut self, brush: Renderable) -> usize {
+        self.insert_brush_with_id(brush, 0)
+    }
+
+    /// Insert a brush that keeps the id of one it replaces, 0 gives it a new id
+    fn insert_brush_with_id(&mut self, brush: Renderable, id: u64) -> usize {
+        let id = if id == 0 { self.new_brush_id() } else { id };
         match brush {
-            Renderable::Brush(ref material, position, size, _) => {
+            Renderable::Brush(ref material, position, size, ..) => {
                 let model = self.models.get_mut(self.internal.brushes).unwrap().as_mut().unwrap();
                 // let model_position: Vector3<f32> = (model.transform * vec4(0.0, 0.0, 0.0, 1.0)).xyz();
-                let properties = self.scene.materials.get(material).unwrap().physical_properties;
+                let properties = self.scene.materials.get(material).unwrap().physical_properties.clone();
                 let mut collider = Collider::cuboid(position, size, Vector3::zero(), model.transform);
                 collider.physical_properties = properties;
                 collider.renderable = Some(model.render.len());
                 collider.model = Some(self.internal.brushes);
                 model.colliders.push(Some(self.physical_scene.add_collider(collider)));
                 self.scene.amend_model(model, brush);
+                self.brush_ids.push(id);
                 model.render.len() - 1
             },
             _ => panic!("thats not a brush")
         }
     }
 
+    /// Insert a light fixture model with `light` attached
+    pub fn place_light(&mut self, position: Vector3<f32>, light: render::PointLight) -> usize {
+        let light = self.scene.add_point_light(light);
+        self.insert_model(Model::new(
+            false, Matrix4::from_translation(position),
+            vec![
+                Renderable::Mesh("blank_cube".to_string(), Matrix4::from_scale(0.25), flags::FULLBRIGHT),
+            ]
+        ).with_light(light, vec3(0.0, 0.0, 0.0))
+        .collider_cuboid(Vector3::zero(), vec3(0.125, 0.125, 0.125)))
+    }
+
     // oh my god i love rust
     pub fn set_model_transform(&mut self, index: usize, new_transform: Matrix4<f32>) {
         {
@@ -743,6 +1487,57 @@ impl World {
         }
     }
 
+    /// Rebuild a model's transform from a rotation in degrees and a scale, keeping its position
+    pub fn set_model_rotation_scale(&mut self, index: usize, rotation: Vector3<f32>, scale: Vector3<f32>) {
+        let model = self.models[index].as_ref().unwrap();
+        let (_, old_scale) = common::rotation_scale(model.transform);
+        let scale = scale.map(|axis| axis.max(MIN_MODEL_SCALE));
+        let transform = common::compose_transform(translation(model.transform), rotation, scale);
+        self.set_model_transform(index, transform);
+        // The selection box follows the scale
+        let model = self.models[index].as_mut().unwrap();
+        model.extents = model.extents.map(|(offset, half)| (offset, vec3_div_compwise(half, old_scale).mul_element_wise(scale)));
+    }
+
+    pub fn set_model_solid(&mut self, index: usize, solid: bool) {
+        let model = self.models[index].as_mut().unwrap();
+        model.solid = solid;
+        for collider in model.colliders.iter().flatten() {
+            if let Some(collider) = self.physical_scene.collider_mut(*collider) {
+                collider.solid = solid;
+            }
+        }
+    }
+
+    /// Move a model's meshes between the static, mobile and foreground lists<br>
+    /// Fails for hidden models becoming static, since only mobile models can be hidden
+    pub fn set_model_render_mode(&mut self, index: usize, mobile: bool, foreground: bool) -> Result<(), String> {
+        let mut model = self.models[index].take().unwrap();
+        if model.hidden && !mobile && !foreground {
+            self.models[index] = Some(model);
+            return Err(String::from("hidden models have to stay mobile"));
+        }
+
+        for renderable in 0..model.render.len() {
+            self.scene.remove_render_data(&model, renderable);
+        }
+        model.mobile = mobile;
+        model.foreground = foreground;
+        model.renderable_indices = self.scene.insert_model(&model);
+        for collider in model.colliders.iter().flatten() {
+            if let Some(collider) = self.physical_scene.collider_mut(*collider) {
+                collider.foreground = foreground;
+            }
+        }
+
+        let hidden = model.hidden;
+        self.models[index] = Some(model);
+        if hidden {
+            self.set_model_visible(index, false);
+        }
+        Ok(())
+    }
+
     /// only use this during component update or any other time the model has been taken
     pub fn set_model_transform_external(&mut self, model: Model, new_transform: Matrix4<f32>) -> Model {
         let index = model.index.unwrap();
@@ -756,22 +1551,69 @@ impl World {
         self.models[index].as_ref().map(|o| o.transform)
     }
 
+    /// How much to scale gizmos at `position` so they stay about the same size on screen
+    fn gizmo_scale(&self, position: Vector3<f32>) -> f32 {
+        if self.scene.camera.ortho.is_some() {
+            // Distance doesn't change the size of anything in orthographic views
+            return self.scene.camera.ortho_half_size().1 / GIZMO_REFERENCE_DISTANCE;
+        }
+        (self.scene.camera.pos.to_vec() - position).magnitude() / GIZMO_REFERENCE_DISTANCE
+    }
+
+    /// Axes the movement gizmo points along, the selected model's own axes in local space
+    pub fn gizmo_axes(&self) -> [Vector3<f32>; 3] {
+        if self.editor_data.gizmo_space == GizmoSpace::Local {
+            if let Some(Selection::Model(model)) = &self.editor_data.selected_object {
+                if let Some(model) = self.models[*model].as_ref() {
+                    let transform = model.transform;
+                    return [transform.x.truncate().normalize(), transform.y.truncate().normalize(), transform.z.truncate().normalize()];
+                }
+            }
+        }
+
+        [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()]
+    }
+
     fn position_arrows(&mut self, position: Vector3<f32>, scale: Vector3<f32>) {
-        self.set_model_transform(self.internal.arrow_px, Matrix4::from_translation(position + vec3(scale.x, 0.0, 0.0)) * Matrix4::from_axis_angle(Vector3::unit_z(), Rad(-f32::consts::PI / 2.0)) * Matrix4::from_scale(0.5));
-        self.set_model_transform(self.internal.arrow_nx, Matrix4::from_translation(position - vec3(scale.x, 0.0, 0.0)) * Matrix4::from_axis_angle(Vector3::unit_z(), Rad(f32::consts::PI / 2.0)) * Matrix4::from_scale(0.5));
-        self.set_model_transform(self.internal.arrow_py, Matrix4::from_translation(position + vec3(0.0, scale.y, 0.0)) * Matrix4::from_scale(0.5));
-        self.set_model_transform(self.internal.arrow_ny, Matrix4::from_translation(position - vec3(0.0, scale.y, 0.0)) * Matrix4::from_axis_angle(Vector3::unit_x(), Rad(f32::consts::PI)) * Matrix4::from_scale(0.5));
-        self.set_model_transform(self.internal.arrow_pz, Matrix4::from_translation(position + vec3(0.0, 0.0, scale.z)) * Matrix4::from_axis_angle(Vector3::unit_x(), Rad(f32::consts::PI / 2.0)) * Matrix4::from_scale(0.5));
-        self.set_model_transform(self.internal.arrow_nz, Matrix4::from_translation(position - vec3(0.0, 0.0, scale.z)) * Matrix4::from_axis_angle(Vector3::unit_x(), Rad(-f32::consts::PI / 2.0)) * Matrix4::from_scale(0.5));
+        let size = ARROW_SCALE * self.gizmo_scale(position).max(GIZMO_MIN_SCALE);
+        // Keep the base of the arrow the same distance from the selection as it grows
+        let scale = scale + vec3_all(-ARROW_LOWEST_Y * (size - ARROW_SCALE));
+        let [x, y, z] = self.gizmo_axes();
+        let rotation = Matrix4::from(Matrix3::from_cols(x, y, z));
+        self.set_model_transform(self.internal.arrow_px, Matrix4::from_translation(position + x * scale.x) * rotation * Matrix4::from_axis_angle(Vector3::unit_z(), Rad(-f32::consts::PI / 2.0)) * Matrix4::from_scale(size));
+        self.set_model_transform(self.internal.arrow_nx, Matrix4::from_translation(position - x * scale.x) * rotation * Matrix4::from_axis_angle(Vector3::unit_z(), Rad(f32::consts::PI / 2.0)) * Matrix4::from_scale(size));
+        self.set_model_transform(self.internal.arrow_py, Matrix4::from_translation(position + y * scale.y) * rotation * Matrix4::from_scale(size));
+        self.set_model_transform(self.internal.arrow_ny, Matrix4::from_translation(position - y * scale.y) * rotation * Matrix4::from_axis_angle(Vector3::unit_x(), Rad(f32::consts::PI)) * Matrix4::from_scale(size));
+        self.set_model_transform(self.internal.arrow_pz, Matrix4::from_translation(position + z * scale.z) * rotation * Matrix4::from_axis_angle(Vector3::unit_x(), Rad(f32::consts::PI / 2.0)) * Matrix4::from_scale(size));
+        self.set_model_transform(self.internal.arrow_nz, Matrix4::from_translation(position - z * scale.z) * rotation * Matrix4::from_axis_angle(Vector3::unit_x(), Rad(-f32::consts::PI / 2.0)) * Matrix4::from_scale(size));
+
+        // Plane handles sit between the positive axes near the center
+        let offset = size * 2.0;
+        self.set_model_transform(self.internal.plane_xy, Matrix4::from_translation(position + (x + y) * offset) * rotation * Matrix4::from_scale(size));
+        self.set_model_transform(self.internal.plane_xz, Matrix4::from_translation(position + (x + z) * offset) * rotation * Matrix4::from_scale(size));
+        self.set_model_transform(self.internal.plane_yz, Matrix4::from_translation(position + (y + z) * offset) * rotation * Matrix4::from_scale(size));
+        self.set_model_transform(self.internal.gizmo_center, Matrix4::from_translation(position) * rotation * Matrix4::from_scale(size / 2.0));
+    }
+
+    /// Arrows standing on each face of a box with `half_extents`, the plane and center handles are put away
+    fn position_face_handles(&mut self, position: Vector3<f32>, half_extents: Vector3<f32>) {
+        self.position_arrows(position, half_extents);
+        for handle in [self.internal.plane_xy, self.internal.plane_xz, self.internal.plane_yz, self.internal.gizmo_center] {
+            self.set_model_transform(handle, Matrix4::from_translation(vec3(0.0, -1000.0, 0.0)));
+        }
     }
 
     fn position_boxes(&mut self, position: Vector3<f32>, scale: Vector3<f32>) {
-        self.set_model_transform(self.internal.box_px, Matrix4::from_translation(position + vec3(scale.x, 0.0, 0.0)) * Matrix4::from_scale(0.25));
-        self.set_model_transform(self.internal.box_nx, Matrix4::from_translation(position - vec3(scale.x, 0.0, 0.0)) * Matrix4::from_scale(0.25));
-        self.set_model_transform(self.internal.box_py, Matrix4::from_translation(position + vec3(0.0, scale.y, 0.0)) * Matrix4::from_scale(0.25));
-        self.set_model_transform(self.internal.box_ny, Matrix4::from_translation(position - vec3(0.0, scale.y, 0.0)) * Matrix4::from_scale(0.25));
-        self.set_model_transform(self.internal.box_pz, Matrix4::from_translation(position + vec3(0.0, 0.0, scale.z)) * Matrix4::from_scale(0.25));
-        self.set_model_transform(self.internal.box_nz, Matrix4::from_translation(position - vec3(0.0, 0.0, scale.z)) * Matrix4::from_scale(0.25));
+        let size = BOX_SCALE * self.gizmo_scale(position).max(GIZMO_MIN_SCALE);
+        let scale = scale + vec3_all((size - BOX_SCALE) / 2.0);
+        let [x, y, z] = self.gizmo_axes();
+        let rotation = Matrix4::from(Matrix3::from_cols(x, y, z)) * Matrix4::from_scale(size);
+        self.set_model_transform(self.internal.box_px, Matrix4::from_translation(position + x * scale.x) * rotation);
+        self.set_model_transform(self.internal.box_nx, Matrix4::from_translation(position - x * scale.x) * rotation);
+        self.set_model_transform(self.internal.box_py, Matrix4::from_translation(position + y * scale.y) * rotation);
+        self.set_model_transform(self.internal.box_ny, Matrix4::from_translation(position - y * scale.y) * rotation);
+        self.set_model_transform(self.internal.box_pz, Matrix4::from_translation(position + z * scale.z) * rotation);
+        self.set_model_transform(self.internal.box_nz, Matrix4::from_translation(position - z * scale.z) * rotation);
     }
 
     pub fn move_arrows_far(&mut self) {
@@ -808,7 +1650,8 @@ impl World {
             SelectionType::Scaling => {
                 scale -= vec3_all(0.8);
                 self.position_boxes(position, scale);
-            }
+            },
+            SelectionType::Faces => unreachable!()
         }
 
         self.editor_data.selection_box_visible = true;
@@ -828,6 +1671,10 @@ impl World {
                 scale -= vec3_all(0.8);
                 self.position_boxes(position, scale);
                 self.editor_data.selection_box_scale = scale - vec3_all(0.2);
+            },
+            SelectionType::Faces => {
+                self.position_face_handles(position, scale - vec3_all(1.0));
+                self.editor_data.selection_box_scale = scale - vec3_all(1.0);
             }
         }
     
@@ -859,7 +1706,8 @@ impl World {
             SelectionType::Scaling => {
                 scale -= vec3_all(0.8);
                 self.position_boxes(position, scale);
-            }
+            },
+            SelectionType::Faces => unreachable!()
         }
 
         self.editor_data.selection_box_visible = true;
@@ -871,7 +1719,7 @@ impl World {
             self.editor_data.multiple_selection_offsets.push(
                 match selection {
                     Selection::Brush(index) => {
-                        if let Renderable::Brush(_, pos, _, _) = self.models[self.internal.brushes].as_ref().unwrap().render[*index] { pos - position } else { unreachable!() }
+                        if let Renderable::Brush(_, pos, ..) = self.models[self.internal.brushes].as_ref().unwrap().render[*index] { pos - position } else { unreachable!() }
                     },
                     Selection::Model(model) => {
                         common::translation(self.models[*model].as_ref().unwrap().transform) - position
@@ -885,17 +1733,32 @@ impl World {
     fn get_brush_adornment_transform(&self, brush_index: usize) -> (Vector3<f32>, Vector3<f32>) {
         let brushes = self.models.get(self.internal.brushes).unwrap().as_ref().unwrap();
         let brush = brushes.render.get(brush_index).unwrap();
-        if let Renderable::Brush(_, position, scale, _) = brush {
+        if let Renderable::Brush(_, position, scale, ..) = brush {
             return (*position, (*scale / 2.0) + vec3(1.0, 1.0, 1.0))
         }
         unreachable!();
     }
 
-    /// Provide a model for the world's internal brushes
-    pub fn set_internal_brushes(&mut self, model: Model) {
+    /// Provide a model for the world's internal brushes<br>
+    /// `ids` are the saved ids of its brushes, brushes past the end or with a repeated or 0 id get new ones
+    pub fn set_internal_brushes(&mut self, model: Model, ids: &[u64]) {
         self.internal.internal_ids.remove(self.internal.internal_ids.iter().position(|i| *i == self.internal.brushes).expect("Brushes model was not present"));
+        let count = model.render.len();
         self.internal.brushes = self.insert_model(model);
         self.internal.internal_ids.push(self.internal.brushes);
+
+        self.next_brush_id = self.next_brush_id.max(ids.iter().max().map_or(1, |id| id + 1));
+        let mut seen = HashSet::new();
+        self.brush_ids = (0..count).map(|i| match ids.get(i) {
+            Some(&id) if id != 0 && seen.insert(id) => id,
+            _ => self.new_brush_id()
+        }).collect();
+    }
+
+    fn new_brush_id(&mut self) -> u64 {
+        let id = self.next_brush_id;
+        self.next_brush_id += 1;
+        id
     }
 
     pub fn debug_arrow(&mut self, start: Vector3<f32>, end: Vector3<f32>) {
@@ -912,6 +1775,29 @@ impl World {
         );
     }
 
+    /// Remove everything that is selected
+    pub fn delete_selection(&mut self) {
+        let Some(selection) = self.editor_data.selected_object.clone() else { return; };
+        self.record_selection_edit(EditKind::Deleted, &selection.items());
+        for item in selection.items() {
+            match item {
+                Selection::Brush(brush) => { self.remove_brush(brush); },
+                Selection::Model(model) => self.remove_model(model).unwrap(),
+                Selection::Multiple(_) => unreachable!()
+            }
+        }
+        self.deselect();
+    }
+
+    /// Round to the increment if snapping is on
+    pub fn snap(&self, value: f32) -> f32 {
+        if self.editor_data.snapping {
+            common::round_to(value, self.editor_data.increment)
+        } else {
+            value
+        }
+    }
+
     /// Hide selection arrows, move them away, hide selection box
     pub fn deselect(&mut self) {
         self.editor_data.selected_object = None;
@@ -921,6 +1807,7 @@ impl World {
         self.move_arrows_far();
         self.move_boxes_far();
         self.editor_data.light_selected = None;
+        self.editor_data.merge_preview.clear();
     }
 
     fn set_model_visible_hidden(&mut self, model: usize, visible: bool, show_hidden: bool) {
@@ -937,9 +1824,9 @@ impl World {
                     }  
                 }
 
-                if let Renderable::Billboard(tex, ..) = renderable {
-                    self.scene.billboards.get_mut(tex).unwrap()[*index].draw = visible;
-                    self.scene.billboards.get_mut(tex).unwrap()[*index].show_hidden = show_hidden;
+                if let Some(tex) = renderable.billboard_texture() {
+                    self.scene.billboards.get_mut(&tex).unwrap()[*index].draw = visible;
+                    self.scene.billboards.get_mut(&tex).unwrap()[*index].show_hidden = show_hidden;
                 }
             }
         }
@@ -958,6 +1845,12 @@ impl World {
         let x = (2.0 * x as f32) / window_width as f32 - 1.0;
         let y = 1.0 - (2.0 * y as f32) / window_height as f32;
         let ray_clip = vec4(x, y, -1.0, 1.0);
+        if self.scene.camera.ortho.is_some() {
+            // Every ray points the same way, starting on the near plane under the mouse
+            let near = self.scene.camera.inverse_projection * ray_clip;
+            let origin = self.scene.camera.inverse_view * vec4(near.x, near.y, near.z, 1.0);
+            return (origin.xyz(), self.scene.camera.direction);
+        }
         let mut ray_eye = self.scene.camera.inverse_projection * ray_clip;
         ray_eye.z = -1.0;
         ray_eye.w = 0.0;
@@ -981,7 +1874,7 @@ impl World {
         }
     }
 
-    fn drag_along_axis(&mut self, model_origin: Vector3<f32>, mouse_ray: (Vector3<f32>, Vector3<f32>), axis: Vector3<f32>, plane: Vector3<f32>, axis_func: fn(Vector3<f32>) -> f32) {
+    fn drag_along_axis(&mut self, model_origin: Vector3<f32>, mouse_ray: (Vector3<f32>, Vector3<f32>), axis: Vector3<f32>, plane: Vector3<f32>, vertex_snap: bool) {
         let d = -model_origin.dot(plane); // ????
 
         let t = -((mouse_ray.0.dot(plane) + d) / mouse_ray.1.dot(plane));
@@ -992,35 +1885,77 @@ impl World {
             match self.editor_data.init_drag_along_plane {
                 Some(pos) => {
                     let diff = intersection - pos;
-                    let along_axis = common::round_to(axis_func(diff), self.editor_data.increment);
+                    let mut along_axis = self.snap(diff.dot(axis));
+                    if vertex_snap {
+                        let start = self.editor_data.drag_object_origin.unwrap();
+                        along_axis = (self.snap_to_vertex(start + axis * along_axis, |shift| axis * shift.dot(axis)) - start).dot(axis);
+                    }
                     if along_axis.abs_diff_ne(&self.editor_data.drag_distance.unwrap(), EPSILON) {
                         self.editor_data.drag_distance = Some(along_axis);
                         let new_origin = self.editor_data.drag_object_origin.unwrap() + axis * along_axis;
+                        self.move_selection(new_origin);
+                    }
+                },
+                None => { 
+                    self.editor_data.init_drag_along_plane = Some(intersection);
+                    self.editor_data.drag_distance = Some(0.0);
+                    self.editor_data.drag_object_origin = Some(model_origin);
+                }
+            }
+        }
+    }
 
-                        let selection = self.editor_data.selected_object.take().unwrap();
+    /// Move freely along `plane`, the offset is snapped to the increment along each gizmo axis
+    fn drag_along_plane(&mut self, model_origin: Vector3<f32>, mouse_ray: (Vector3<f32>, Vector3<f32>), plane: Vector3<f32>, vertex_snap: bool) {
+        let d = -model_origin.dot(plane);
+        let t = -((mouse_ray.0.dot(plane) + d) / mouse_ray.1.dot(plane));
 
-                        match &selection {
-                            Selection::Brush(_) | Selection::Model(_) => self.transform_selection(new_origin, &selection),
-                            Selection::Multiple(multiple) => {
-                                for (i, selection) in multiple.iter().enumerate() {
-                                    self.transform_selection(new_origin + self.editor_data.multiple_selection_offsets[i], selection);
-                                }
-                            }
-                        }
+        if t > 0.0 {
+            let intersection = mouse_ray.0 + mouse_ray.1 * t;
 
-                        self.editor_data.selected_object = Some(selection);
+            match self.editor_data.init_drag_along_plane {
+                Some(pos) => {
+                    let diff = intersection - pos;
+                    let mut offset = self.gizmo_axes().iter().fold(Vector3::zero(), |offset, axis| offset + axis * self.snap(diff.dot(*axis)));
+                    if vertex_snap {
+                        let start = self.editor_data.drag_object_origin.unwrap();
+                        offset = self.snap_to_vertex(start + offset, |shift| shift - plane * shift.dot(plane)) - start;
+                    }
+                    if offset.abs_diff_ne(&self.editor_data.drag_offset.unwrap(), EPSILON) {
+                        self.editor_data.drag_offset = Some(offset);
+                        self.move_selection(self.editor_data.drag_object_origin.unwrap() + offset);
                     }
                 },
-                None => { 
+                None => {
                     self.editor_data.init_drag_along_plane = Some(intersection);
-                    self.editor_data.drag_distance = Some(0.0);
+                    self.editor_data.drag_offset = Some(Vector3::zero());
                     self.editor_data.drag_object_origin = Some(model_origin);
                 }
             }
         }
     }
 
+    /// Move the selection so its center is at `new_origin`
+    fn move_selection(&mut self, new_origin: Vector3<f32>) {
+        let selection = self.editor_data.selected_object.take().unwrap();
+
+        match &selection {
+            Selection::Brush(_) | Selection::Model(_) => self.transform_selection(new_origin, &selection),
+            Selection::Multiple(multiple) => {
+                for (i, selection) in multiple.iter().enumerate() {
+                    self.transform_selection(new_origin + self.editor_data.multiple_selection_offsets[i], selection);
+                }
+            }
+        }
+
+        self.editor_data.selected_object = Some(selection);
+    }
+
+    /// `axis` is the world axis the scale would be along in world space, it is turned to the gizmo's axis in local space
     fn scale_along_axis(&mut self, model_origin: Vector3<f32>, model_scale: Vector3<f32>, uniform: bool, mouse_ray: (Vector3<f32>, Vector3<f32>), axis: Vector3<f32>, plane: Vector3<f32>, axis_func: fn(Vector3<f32>) -> f32) {
+        let [x, y, z] = self.gizmo_axes();
+        let frame = Matrix3::from_cols(x, y, z);
+        let direction = frame * axis;
         let d = -model_origin.dot(plane);
         let t = -((mouse_ray.0.dot(plane) + d) / mouse_ray.1.dot(plane));
 
@@ -1031,7 +1966,7 @@ impl World {
             match self.editor_data.init_drag_along_plane {
                 Some(pos) => {
                     let diff = intersection - pos;
-                    let mut along_axis = common::round_to(axis_func(diff), self.editor_data.increment);
+                    let mut along_axis = self.snap(diff.dot(direction));
                     if !matches!(self.editor_data.selected_object, Some(Selection::Brush(_))) {
                         along_axis /= 2.0;
                     }
@@ -1041,12 +1976,12 @@ impl World {
                         let axis_sign = if self.editor_data.drag_object_sign.unwrap() { 1.0f32 } else { -1.0 };
                         let (new_scale, new_origin) = {
                             let mut new_scale = self.editor_data.drag_object_scale.unwrap() + axis * along_axis * axis_sign;
-                            let mut new_origin = self.editor_data.drag_object_origin.unwrap() + (axis * along_axis / 2.0);
+                            let mut new_origin = self.editor_data.drag_object_origin.unwrap() + (direction * along_axis / 2.0);
 
                             if axis_func(new_scale) < self.editor_data.increment {
                                 self.editor_data.drag_distance = Some(old_drag_distance);
                                 new_scale = self.editor_data.drag_object_scale.unwrap() + axis * old_drag_distance * axis_sign;
-                                new_origin = self.editor_data.drag_object_origin.unwrap() + (axis * old_drag_distance / 2.0);
+                                new_origin = self.editor_data.drag_object_origin.unwrap() + (direction * old_drag_distance / 2.0);
                             }
 
                             (new_scale, new_origin)
@@ -1057,10 +1992,11 @@ impl World {
                                 self.set_brush_origin_scale(*brush, new_origin, Some(new_scale));
                             },
                             Selection::Model(model) => {
+                                let anchor = frame * axis.mul_element_wise(model_scale) * -axis_sign;
                                 if uniform {
-                                    self.scale_model_anchored(*model, new_scale, axis.mul_element_wise(model_scale) * -axis_sign, axis);
+                                    self.scale_model_anchored(*model, new_scale, anchor, axis);
                                 } else {
-                                    self.scale_model_anchored_nonuniform(*model, new_scale, axis.mul_element_wise(model_scale) * -axis_sign, axis);
+                                    self.scale_model_anchored_nonuniform(*model, new_scale, anchor, axis, frame);
                                 }
                             },
                             Selection::Multiple(_) => todo!()
@@ -1078,10 +2014,47 @@ impl World {
         }
     }
 
+    /// Move the face of `brush` on the dragged side of `axis` along its normal, the opposite face stays where it is<br>
+    /// The brush can't get thinner than the increment
+    fn drag_face(&mut self, brush: usize, model_origin: Vector3<f32>, mouse_ray: (Vector3<f32>, Vector3<f32>), axis: Vector3<f32>, plane: Vector3<f32>) {
+        let d = -model_origin.dot(plane);
+        let t = -((mouse_ray.0.dot(plane) + d) / mouse_ray.1.dot(plane));
+        if t <= 0.0 {
+            return;
+        }
+        let intersection = mouse_ray.0 + mouse_ray.1 * t;
+        let normal = if self.editor_data.drag_object_sign.unwrap() { axis } else { -axis };
+
+        match self.editor_data.init_drag_along_plane {
+            Some(pos) => {
+                let distance = self.snap((intersection - pos).dot(normal));
+                let start_size = self.editor_data.drag_object_scale.unwrap();
+                if distance.abs_diff_eq(&self.editor_data.drag_distance.unwrap(), EPSILON) || start_size.dot(axis) + distance < self.editor_data.increment {
+                    return;
+                }
+                self.editor_data.drag_distance = Some(distance);
+                let origin = self.editor_data.drag_object_origin.unwrap() + normal * (distance / 2.0);
+                self.set_brush_origin_scale(brush, origin, Some(start_size + axis * distance));
+            },
+            None => {
+                let Some((min, max)) = self.brush_bounds(brush) else { return; };
+                self.editor_data.init_drag_along_plane = Some(intersection);
+                self.editor_data.drag_distance = Some(0.0);
+                self.editor_data.drag_object_origin = Some((min + max) / 2.0);
+                self.editor_data.drag_object_scale = Some(max - min);
+            }
+        }
+    }
+
     fn duplicate_model(&mut self, model: usize) -> usize {
         let model = self.models.get(model).unwrap().as_ref().unwrap();
 
         let mut new_model = Model {
+            id: 0,
+            // Names have to stay unique for references to them to work
+            name: None,
+            tags: model.tags.clone(),
+            connections: model.connections.clone(),
             transform: model.transform,
             solid: model.solid,
             foreground: model.foreground, 
@@ -1106,7 +2079,8 @@ impl World {
         self.insert_model(new_model)
     }
 
-    pub fn remove_brush(&mut self, brush_index: usize) {
+    /// Returns the id the brush had
+    pub fn remove_brush(&mut self, brush_index: usize) -> u64 {
         let brushes = self.models.get_mut(self.internal.brushes).unwrap().as_mut().unwrap();
         self.scene.remove_renderable(brushes, brush_index);
         if let Some(collider) = brushes.colliders[brush_index] {
@@ -1117,7 +2091,7 @@ impl World {
 
         // This is only local to the brushes model
         for collider in brushes.colliders.iter().flatten() {
-            if let Some(collider) = self.physical_scene.colliders.get_mut(*collider).unwrap() {
+            if let Some(collider) = self.physical_scene.collider_mut(*collider) {
                 if let Some(ref mut renderable) = collider.renderable {
                     if *renderable > brush_index {
                         *renderable -= 1;
@@ -1125,6 +2099,16 @@ impl World {
                 }
             }
         }
+
+        self.brush_ids.remove(brush_index)
+    }
+
+    /// Use the level's clip planes, the editor can see further if the preferences say so
+    fn update_clip_planes(&mut self) {
+        let environment = &self.scene.environment;
+        let far = if self.editor_data.active { environment.far_plane.max(self.editor_data.view_distance) } else { environment.far_plane };
+        self.scene.camera.set_clip_planes(environment.near_plane, far);
+        self.scene.post_process.clip_planes = self.scene.camera.clip_planes();
     }
 
     pub fn update(&mut self, input: &Input, mouse_ray: (Vector3<f32>, Vector3<f32>), delta_time: f32) {
@@ -1133,7 +2117,12 @@ impl World {
             return;
         }
 
-        self.player.update(&self.scene.camera, input);
+        // The player stands still on the completion screen
+        if self.completion.is_none() {
+            self.player.update(&self.scene.camera, input, &self.rules, &mut self.physical_scene, delta_time);
+        }
+        self.update_clip_planes();
+        self.update_session(delta_time);
 
         let mut set_visible = Vec::new();
 
@@ -1153,92 +2142,116 @@ impl World {
 
         // Change selection type with number keys
         if self.editor_data.selected_object.is_some() {
-            if input.get_key_just_pressed(Key::Character("1".into())) {
+            if input.action_just_pressed(Action::MoveTool) {
                 if self.editor_data.selection_type != SelectionType::Movement {
                     self.editor_data.selection_type = SelectionType::Movement;
                     self.set_boxes_visible(false);
                     self.move_boxes_far();
                     self.set_arrows_visible(true);
                 }
-            } else if input.get_key_just_pressed(Key::Character("2".into())) {
+            } else if input.action_just_pressed(Action::ScaleTool) {
                 if self.editor_data.selection_type != SelectionType::Scaling {
                     self.editor_data.selection_type = SelectionType::Scaling;
                     self.set_arrows_visible(false);
                     self.move_arrows_far();
                     self.set_boxes_visible(true);
                 }
+            } else if input.action_just_pressed(Action::FaceTool) && self.editor_data.get_selected_brush().is_some() {
+                if self.editor_data.selection_type != SelectionType::Faces {
+                    self.editor_data.selection_type = SelectionType::Faces;
+                    self.set_boxes_visible(false);
+                    self.move_boxes_far();
+                    self.set_arrows_visible(true);
+                }
             }
         }
 
+        // Faces can only be edited on a single brush
+        if self.editor_data.selection_type == SelectionType::Faces && self.editor_data.get_selected_brush().is_none() {
+            self.editor_data.selection_type = SelectionType::Movement;
+        }
+
         let mut selection = self.editor_data.selected_object.take();
-        let mut selection_changed = false;
 
         if let Some(selected) = &selection {
             match selected {
                 Selection::Brush(brush) => {
-                    if let Some(material) = self.editor_data.apply_material.take() {
-                        // for brush in self.models[self.internal.brushes].as_ref().unwrap().render.iter() { println!("{:?}", brush) }
-                        let new_index = self.set_brush_material(*brush, material);
-                        // for brush in self.models[self.internal.brushes].as_ref().unwrap().render.iter() { println!("{:?}", brush) }
-                        self.select_brush(new_index);
-                        selection_changed = true;
-                    } else {
-                        match self.editor_data.selection_type {
-                            SelectionType::Movement => self.adorn_brush(*brush, SelectionType::Movement),
-                            SelectionType::Scaling => self.adorn_brush(*brush, SelectionType::Scaling),
-                        }
+                    match self.editor_data.selection_type {
+                        SelectionType::Movement => self.adorn_brush(*brush, SelectionType::Movement),
+                        SelectionType::Scaling => self.adorn_brush(*brush, SelectionType::Scaling),
+                        SelectionType::Faces => self.adorn_brush(*brush, SelectionType::Faces),
                     }
                 },
                 Selection::Model(model) => {
                     match self.editor_data.selection_type {
                         SelectionType::Movement => self.adorn_model(*model, SelectionType::Movement),
                         SelectionType::Scaling => self.adorn_model(*model, SelectionType::Scaling),
+                        SelectionType::Faces => unreachable!(),
                     }
                 },
                 Selection::Multiple(multiple) => {
                     match self.editor_data.selection_type {
                         SelectionType::Movement => self.adorn_multiple(multiple, SelectionType::Movement),
                         SelectionType::Scaling => self.adorn_multiple(multiple, SelectionType::Scaling),
+                        SelectionType::Faces => unreachable!(),
                     }
                 }
             }
 
             // Delete selected
-            if input.get_key_just_pressed(Key::Named(NamedKey::Delete)) || input.get_key_just_pressed(Key::Named(NamedKey::Backspace)) {
-                match selected {
-                    Selection::Brush(brush) => {
-                        self.remove_brush(*brush);
-                        self.deselect();
-                        selection = None;
-                    },
-                    Selection::Model(model) => {
-                        self.remove_model(*model).unwrap();
-                        self.deselect();
-                        selection = None;
-                    },
-                    Selection::Multiple(multiple) => {
-                        for multiple_selection in multiple {
-                            match multiple_selection {
-                                Selection::Brush(brush) => self.remove_brush(*brush),
-                                Selection::Model(model) => self.remove_model(*model).unwrap(),
-                                Selection::Multiple(_) => unreachable!()
-                            }
-                        }
-                        self.deselect();
-                        selection = None;
-                    }
+            if input.action_just_pressed(Action::DeleteSelection) {
+                let count = selected.items().len();
+                if count > DELETE_CONFIRM_THRESHOLD {
+                    self.editor_data.confirm_delete = Some(count);
+                } else {
+                    self.editor_data.selected_object = selection.take();
+                    self.delete_selection();
                 }
             }
         }
-        if !selection_changed {
-            self.editor_data.selected_object = selection;
+        self.editor_data.selected_object = selection;
+
+        if input.action_just_pressed(Action::Cancel) {
+            self.editor_data.apply_material = None;
+            self.editor_data.stamp = None;
+        }
+
+        // Switch the gizmo between world and local axes
+        if self.editor_data.active && input.action_just_pressed(Action::ToggleGizmoSpace) {
+            self.editor_data.gizmo_space = self.editor_data.gizmo_space.toggle();
+        }
+
+        // Stamp tool
+        if self.editor_data.active {
+            if input.action_just_pressed(Action::StampSelection) {
+                self.stamp_from_selection();
+            }
+            if input.action_just_pressed(Action::RotateStamp) {
+                self.rotate_stamp();
+            }
+        }
+
+        // Copy and paste
+        if self.editor_data.active {
+            if input.action_just_pressed(Action::CopySelection) {
+                match self.copy_selection() {
+                    Ok(copied) => self.editor_data.show_debug.push(format!("copied {} objects", copied)),
+                    Err(e) => self.editor_data.show_debug.push(e)
+                }
+            }
+            if input.action_just_pressed(Action::PasteClipboard) {
+                match self.paste_clipboard() {
+                    Ok(pasted) => self.editor_data.show_debug.push(format!("pasted {} objects", pasted)),
+                    Err(e) => self.editor_data.show_debug.push(e)
+                }
+            }
         }
-        self.editor_data.apply_material = None;
 
         // Duplicate
-        if input.get_key_pressed(Key::Named(NamedKey::Control)) && input.get_key_just_pressed(Key::Character("d".into())) {
+        if input.action_just_pressed(Action::Duplicate) {
             if self.editor_data.selected_object.is_some() {
                 let selection = self.editor_data.selected_object.take().unwrap();
+                self.record_selection_edit(EditKind::Created, &selection.items());
 
                 let mut new_selection: Option<Vec<Selection>> = None;
                 match &selection {
@@ -1290,10 +2303,15 @@ impl World {
 
         // Disable dragging if lmb is let go
         if self.editor_data.drag_axis.is_some() && input.get_mouse_button_released(MouseButton::Left) {
+            if let Some(selection) = self.editor_data.selected_object.clone() {
+                self.record_selection_edit(EditKind::Moved, &selection.items());
+            }
             self.editor_data.drag_axis = None;
             self.editor_data.drag_object_origin = None;
             self.editor_data.drag_object_scale = None;
             self.editor_data.drag_distance = None;
+            self.editor_data.drag_offset = None;
+            self.editor_data.snap_boxes = None;
             self.editor_data.init_drag_along_plane = None;
         }
 
@@ -1322,10 +2340,13 @@ impl World {
                 // t < 0, intersects behind ray. t == 0, ray is perpendicular 
                 match self.editor_data.selection_type {
                     SelectionType::Movement => {
+                        let [x, y, z] = self.gizmo_axes();
+                        let vertex_snap = input.action_pressed(Action::VertexSnap);
                         match drag {
-                            DragAxis::X => self.drag_along_axis(model_origin, mouse_ray, Vector3::unit_x(), self.editor_data.drag_plane.unwrap(), |v| v.x),
-                            DragAxis::Y => self.drag_along_axis(model_origin, mouse_ray, Vector3::unit_y(), self.editor_data.drag_plane.unwrap(), |v| v.y),
-                            DragAxis::Z => self.drag_along_axis(model_origin, mouse_ray, Vector3::unit_z(), self.editor_data.drag_plane.unwrap(), |v| v.z),
+                            DragAxis::X => self.drag_along_axis(model_origin, mouse_ray, x, self.editor_data.drag_plane.unwrap(), vertex_snap),
+                            DragAxis::Y => self.drag_along_axis(model_origin, mouse_ray, y, self.editor_data.drag_plane.unwrap(), vertex_snap),
+                            DragAxis::Z => self.drag_along_axis(model_origin, mouse_ray, z, self.editor_data.drag_plane.unwrap(), vertex_snap),
+                            DragAxis::Plane => self.drag_along_plane(model_origin, mouse_ray, self.editor_data.drag_plane.unwrap(), vertex_snap)
                         }
                     },
                     SelectionType::Scaling => {
@@ -1334,6 +2355,18 @@ impl World {
                             DragAxis::X => self.scale_along_axis(model_origin, model_scale, uniform, mouse_ray, Vector3::unit_x(), self.editor_data.drag_plane.unwrap(), |v| v.x),
                             DragAxis::Y => self.scale_along_axis(model_origin, model_scale, uniform, mouse_ray, Vector3::unit_y(), self.editor_data.drag_plane.unwrap(), |v| v.y),
                             DragAxis::Z => self.scale_along_axis(model_origin, model_scale, uniform, mouse_ray, Vector3::unit_z(), self.editor_data.drag_plane.unwrap(), |v| v.z),
+                            // Plane handles only exist on the movement gizmo
+                            DragAxis::Plane => ()
+                        }
+                    },
+                    SelectionType::Faces => {
+                        let brush = self.editor_data.get_selected_brush().unwrap();
+                        let plane = self.editor_data.drag_plane.unwrap();
+                        match drag {
+                            DragAxis::X => self.drag_face(brush, model_origin, mouse_ray, Vector3::unit_x(), plane),
+                            DragAxis::Y => self.drag_face(brush, model_origin, mouse_ray, Vector3::unit_y(), plane),
+                            DragAxis::Z => self.drag_face(brush, model_origin, mouse_ray, Vector3::unit_z(), plane),
+                            DragAxis::Plane => ()
                         }
                     },
                     // _ => ()
@@ -1344,30 +2377,42 @@ impl World {
         }
 
         match self.player.movement {
-            PlayerMovementMode::FirstPerson => {
+            PlayerMovementMode::FirstPerson | PlayerMovementMode::Spectator => {
                 self.player.velocity += -Vector3::unit_y() * (self.gravity * delta_time);
+                let falling_speed = -self.player.velocity.y;
                 let result = self.physical_scene.move_and_slide(self.player.collider, self.player.velocity * delta_time);
                 self.player.position = result.final_position;
                 self.player.velocity = result.velocity / delta_time;
 
                 let mut grounded = false;
                 let mut ground = None;
+                self.player.wall_normal = None;
                 for (i, normal) in result.normals.iter().enumerate() {
-                    if normal.normalize().dot(Vector3::unit_y()) > 0.75 {
+                    let normal = normal.normalize();
+                    if normal.dot(Vector3::unit_y()) > 0.75 {
                         grounded = true;
-                        ground = Some(*result.materials.get(i).unwrap());
+                        ground = result.materials.get(i).cloned();
                         break;
+                    } else if normal.dot(Vector3::unit_y()).abs() < 0.3 {
+                        self.player.wall_normal = Some(normal);
                     }
                 }
                 if grounded {
-                    self.player.velocity *= ground.unwrap().friction;
+                    self.player.velocity *= ground.as_ref().unwrap().friction;
                     self.player.ground = ground;
                     self.player.coyote = COYOTE;
+                    self.player.last_ground = Some(self.player.position);
                 } else {
                     self.player.velocity *= self.air_friction;
                 }
 
-                self.scene.camera.pos = Point3::from_vec(self.player.position + vec3(0.0, 0.5, 0.0));
+                if self.do_game_logic {
+                    self.update_rules(if grounded { falling_speed } else { 0.0 }, delta_time);
+                }
+
+                if matches!(self.player.movement, PlayerMovementMode::FirstPerson) {
+                    self.scene.camera.pos = Point3::from_vec(self.player.position + self.player.eye_offset());
+                }
             },
             PlayerMovementMode::FollowCamera => {
                 self.player.position = self.scene.camera.pos.to_vec();
@@ -1376,17 +2421,27 @@ impl World {
             }
         }
 
+        self.update_interaction(input);
+        self.update_connections(delta_time);
+        self.scene.time += delta_time;
+        self.scene.post_process.time = self.scene.time;
         for i in 0..self.models.len() {
             if self.models[i].is_some() {
                 let mut model = self.models[i].take().unwrap();
 
                 for j in 0..model.components.len() {
-                    model = Component::on_update(j, model, self);
+                    model = Component::on_update(j, model, self, delta_time);
                 }
 
                 self.models[i] = Some(model);
             }
         }
+        self.update_dissolves(delta_time);
+        self.gather_spot_lights();
+
+        if self.do_game_logic {
+            self.spawner.update(delta_time);
+        }
     }
 
     pub unsafe fn load_basic_meshes(meshes: &mut MeshBank, gl: &glow::Context) {
@@ -1400,22 +2455,26 @@ impl World {
 #[derive(Clone, Debug)]
 pub enum Renderable {
     Mesh(String, Matrix4<f32>, u32),
-    Brush(String, Vector3<f32>, Vector3<f32>, u32),
-    Billboard(String, Vector3<f32>, (f32, f32), u32, bool)
+    /// Material, center, size, flags and texture alignment
+    Brush(String, Vector3<f32>, Vector3<f32>, u32, BrushFaces),
+    /// Texture, position, size, flags, whether it tilts to face the camera vertically and its sprite sheet animation
+    Billboard(String, Vector3<f32>, (f32, f32), u32, bool, Flipbook),
+    /// Drawn like a billboard from a texture the text is rasterized into
+    Text(TextLabel, Vector3<f32>, u32)
 }
 
 impl Renderable {
     pub fn get_mesh(&self) -> Option<String> {
         match self {
             Self::Mesh(s, _, _) => Some(s.to_owned()),
-            Self::Brush(s, _, _, _) => Some(format!("Brush_{}", s)),
+            Self::Brush(material, _, _, _, faces) => Some(faces.mesh_name(material)),
             _ => None
         }
     }
 
     pub fn get_parry_extents(&self) -> Option<parry3d::bounding_volume::Aabb> {
         match self {
-            Self::Brush(_, pos, extents, _) => {
+            Self::Brush(_, pos, extents, ..) => {
                 Some(parry3d::bounding_volume::Aabb::from_half_extents(
                     parry3d::na::Point3::new(pos.x, pos.y, pos.z), 
                     parry3d::na::Vector3::new(extents.x / 2.0, extents.y / 2.0, extents.z / 2.0)
@@ -1427,7 +2486,7 @@ impl Renderable {
 
     pub fn get_extents(&self) -> Option<(Vector3<f32>, Vector3<f32>)> {
         match self {
-            Self::Brush(_, pos, extents, _) => {
+            Self::Brush(_, pos, extents, ..) => {
                 Some((*pos, extents / 2.0))
             },
             _ => None
@@ -1437,12 +2496,71 @@ impl Renderable {
     pub fn render_as_mesh(&self) -> bool {
         matches!(self, Self::Mesh(..) | Self::Brush(..))
     }
+
+    /// Key into `Scene::billboards` for billboards and text
+    pub fn billboard_texture(&self) -> Option<String> {
+        match self {
+            Self::Billboard(texture, ..) => Some(texture.to_owned()),
+            Self::Text(label, ..) => Some(label.texture_name()),
+            _ => None
+        }
+    }
+
+    pub fn flags(&self) -> u32 {
+        match self {
+            Self::Mesh(_, _, flags) | Self::Brush(_, _, _, flags, _) | Self::Billboard(_, _, _, flags, ..) | Self::Text(_, _, flags) => *flags
+        }
+    }
+
+    pub fn set_flags(&mut self, new_flags: u32) {
+        match self {
+            Self::Mesh(_, _, flags) | Self::Brush(_, _, _, flags, _) | Self::Billboard(_, _, _, flags, ..) | Self::Text(_, _, flags) => *flags = new_flags
+        }
+    }
+}
+
+/// Text drawn in the world with the UI font, for signs and labels
+#[derive(Clone, PartialEq, Serialize, Deserialize, Debug)]
+pub struct TextLabel {
+    pub text: String,
+    /// World units per line of text
+    pub height: f32,
+    pub color: [f32; 3],
+    /// Face the camera, otherwise the text is fixed to the model facing its +Z
+    pub billboard: bool
+}
+
+impl TextLabel {
+    pub fn new(text: &str) -> Self {
+        Self { text: text.to_string(), height: prefab::TEXT_HEIGHT_DEFAULT, color: [1.0; 3], billboard: true }
+    }
+
+    /// Labels with the same text and color share a texture
+    pub fn texture_name(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.text.hash(&mut hasher);
+        self.color.map(f32::to_bits).hash(&mut hasher);
+        format!("Text_{:x}", hasher.finish())
+    }
+
+    /// Width and height in world units
+    pub fn size(&self) -> (f32, f32) {
+        let (glyph_width, glyph_height) = UITheme::default().glyph_size;
+        let lines = self.text.split('\n');
+        let rows = lines.clone().count() as f32;
+        let columns = lines.map(|line| line.chars().count()).max().unwrap_or(0).max(1) as f32;
+        (columns * self.height * glyph_width as f32 / glyph_height as f32, rows * self.height)
+    }
 }
 
 // TODO: PhysicalProperties
 #[derive(Clone)]
 pub enum ModelCollider {
     Cuboid { offset: Vector3<f32>, half_extents: Vector3<f32> },
+    /// Triangles of `res/models/{obj}.obj`, hollow, best for static props
+    TriMesh { offset: Vector3<f32>, obj: String },
+    /// Convex hull of the vertices of `res/models/{obj}.obj`
+    ConvexHull { offset: Vector3<f32>, obj: String },
     Multiple { colliders: Vec<ModelCollider> }
 }
 
@@ -1450,8 +2568,8 @@ impl ModelCollider {
     /// this does clone the collider
     pub fn flatten(&self) -> Vec<ModelCollider> {
         match self {
-            Self::Cuboid { .. } => vec![self.clone()],
-            Self::Multiple { colliders } => colliders.clone()
+            Self::Multiple { colliders } => colliders.clone(),
+            _ => vec![self.clone()]
         }
     }
 
@@ -1466,6 +2584,15 @@ impl ModelCollider {
 
 #[derive(Clone)]
 pub struct Model {
+    /// Stays the same across saves and loads, levels list models in this order<br>
+    /// 0 until the model is inserted
+    pub id: u64,
+    /// Other models refer to this one by name, see `wiring`
+    pub name: Option<String>,
+    /// Unlike names, several models can share a tag, see `World::find_by_tag`
+    pub tags: Vec<String>,
+    /// Inputs sent to other models when the components fire outputs, see `connection`
+    pub connections: Vec<Connection>,
     pub transform: Matrix4<f32>,
     pub render: Vec<Renderable>,
     pub mobile: bool,
@@ -1487,6 +2614,10 @@ pub struct Model {
 impl Model {
     pub fn new(mobile: bool, transform: Matrix4<f32>, renderables: Vec<Renderable>) -> Self {
         Self {
+            id: 0,
+            name: None,
+            tags: Vec::new(),
+            connections: Vec::new(),
             transform,
             render: renderables,
             mobile,
@@ -1509,22 +2640,7 @@ impl Model {
 
         meshes.get(&format!("File_{}0", file))?;
 
-        let mut model = Self {
-            mobile: false,
-            render: Vec::new(),
-            renderable_indices: Vec::new(),
-            transform: Matrix4::identity(),
-            colliders: Vec::new(),
-            index: None,
-            insert_collider: None,
-            foreground: false,
-            solid: true,
-            extents: None,
-            lights: Vec::new(),
-            components: Vec::new(),
-            hidden: false,
-            hidden_dirty: false
-        };
+        let mut model = Self::new(false, Matrix4::identity(), Vec::new());
 
         while meshes.get(&format!("File_{}{}", file, current_index)).is_some() {
             model.render.push(Renderable::Mesh(format!("File_{}{}", file, current_index), Matrix4::identity(), 0));
@@ -1538,6 +2654,14 @@ impl Model {
         vec3(self.transform.w.x, self.transform.w.y, self.transform.w.z)
     }
 
+    /// Name, or index for models without one
+    pub fn label(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => format!("model {}", self.index.unwrap_or_default())
+        }
+    }
+
     pub fn calculate_extents(&mut self) {
         let mut extents = compose_extents(
             self.render.iter().filter_map(|r| r.get_extents())
@@ -1565,14 +2689,18 @@ impl Model {
     pub fn fullbright(mut self) -> Self {
         for renderable in self.render.iter_mut() {
             match renderable {
-                Renderable::Brush(_, _, _, flags) => *flags |= flags::FULLBRIGHT,
+                Renderable::Brush(_, _, _, flags, _) => *flags |= flags::FULLBRIGHT,
                 Renderable::Mesh(_, _, flags) => *flags |= flags::FULLBRIGHT,
-                Renderable::Billboard(_, _, _, flags, _) => *flags |= flags::FULLBRIGHT
+                Renderable::Billboard(_, _, _, flags, ..) | Renderable::Text(_, _, flags) => *flags |= flags::FULLBRIGHT
             }
         }
         self
     }
 
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+
     pub fn with_component(mut self, component: Component) -> Self {
         self.components.push(component);
         self
@@ -1628,6 +2756,16 @@ impl Model {
         self
     }
 
+    pub fn collider_trimesh(mut self, offset: Vector3<f32>, obj: &str) -> Self {
+        self.insert_collider(ModelCollider::TriMesh { offset, obj: obj.to_string() });
+        self
+    }
+
+    pub fn collider_convex_hull(mut self, offset: Vector3<f32>, obj: &str) -> Self {
+        self.insert_collider(ModelCollider::ConvexHull { offset, obj: obj.to_string() });
+        self
+    }
+
     pub fn non_solid(mut self) -> Self {
         self.solid = false;
         self
@@ -1642,7 +2780,9 @@ impl Model {
 #[derive(Clone)]
 pub enum PlayerMovementMode {
     FollowCamera,
-    FirstPerson
+    FirstPerson,
+    /// Still simulated but not controlled, the camera flies on its own
+    Spectator
 }
 
 pub struct Player {
@@ -1654,7 +2794,30 @@ pub struct Player {
     pub movement: PlayerMovementMode,
     pub ground: Option<PhysicalProperties>,
     pub air_control: f32,
-    pub coyote: u32
+    pub coyote: u32,
+    /// Where the player last stood on solid ground
+    pub last_ground: Option<Vector3<f32>>,
+    /// Normal of a wall the player is touching while in the air
+    pub wall_normal: Option<Vector3<f32>>,
+    /// Reset to the level's max health on respawn, see `World::damage_player`
+    pub health: f32,
+    /// Killed and not respawned yet, only stays set when respawning is disabled
+    pub dead: bool,
+    /// The collider is `CROUCH_HEIGHT` tall instead of `PLAYER_SIZE.y`
+    pub crouching: bool,
+    /// How far into the sprint ramp the player is, from 0 to 1
+    pub sprint: f32,
+    /// Seconds of sprinting left, only used when the level limits stamina
+    pub stamina: f32,
+    /// Ran out of stamina and can't sprint until some comes back
+    pub exhausted: bool,
+    /// Footsteps, jumps and landings from the last update, see `footstep`
+    pub surface_sounds: Vec<SurfaceSound>,
+    /// Distance walked since the last footstep
+    stride: f32,
+    /// Off the ground as of the last update, and how fast the player was falling then
+    airborne: bool,
+    fall_speed: f32
 }
 
 impl Player {
@@ -1663,18 +2826,65 @@ impl Player {
             collider: 0,
             position: vec3(0.0, 0.0, 0.0),
             velocity: Vector3::zero(),
-            jump_velocity: 7.0,
-            speed: 5.0,
+            jump_velocity: rules::DEFAULT_JUMP_VELOCITY,
+            speed: rules::DEFAULT_PLAYER_SPEED,
             movement: PlayerMovementMode::FirstPerson,
             ground: None,
             air_control: 0.01,
-            coyote: 0
+            coyote: 0,
+            last_ground: None,
+            wall_normal: None,
+            health: rules::DEFAULT_MAX_HEALTH,
+            dead: false,
+            crouching: false,
+            sprint: 0.0,
+            stamina: 0.0,
+            exhausted: false,
+            surface_sounds: Vec::new(),
+            stride: 0.0,
+            airborne: false,
+            fall_speed: 0.0
+        }
+    }
+
+    pub fn reset_stamina(&mut self, rules: &GameplayRules) {
+        self.stamina = rules.max_stamina();
+        self.exhausted = false;
+        self.sprint = 0.0;
+    }
+
+    pub fn height(&self) -> f32 {
+        if self.crouching { CROUCH_HEIGHT } else { PLAYER_SIZE.y }
+    }
+
+    /// From the center of the collider to the camera, the eye stays as far below the top while crouching
+    pub fn eye_offset(&self) -> Vector3<f32> {
+        vec3(0.0, self.height() / 2.0 - (PLAYER_SIZE.y / 2.0 - spectator::EYE_HEIGHT), 0.0)
+    }
+
+    /// Swap the collider for one of the new height, the feet stay where they are
+    pub fn set_crouching(&mut self, crouching: bool, physical_scene: &mut PhysicalScene) {
+        if crouching == self.crouching {
+            return;
         }
+        let old_height = self.height();
+        self.crouching = crouching;
+        self.position.y += (self.height() - old_height) / 2.0;
+        let size = vec3(PLAYER_SIZE.x, self.height(), PLAYER_SIZE.z);
+        physical_scene.set_collider(self.collider, Collider::cuboid(Vector3::zero(), size, Vector3::zero(), Matrix4::from_translation(self.position)));
+    }
+
+    /// Whether the space a standing player would take up above the crouched collider is empty
+    fn room_to_stand(&self, physical_scene: &mut PhysicalScene) -> bool {
+        let extra = PLAYER_SIZE.y - CROUCH_HEIGHT;
+        let center = self.position + vec3(0.0, (CROUCH_HEIGHT + extra) / 2.0, 0.0);
+        let above = Collider::cuboid(Vector3::zero(), vec3(PLAYER_SIZE.x, extra, PLAYER_SIZE.z), Vector3::zero(), Matrix4::from_translation(center));
+        !physical_scene.overlaps_solid(&above, self.collider)
     }
 
     fn control(&self) -> f32 {
         if self.coyote > 0 {
-            if let Some(ground) = self.ground {
+            if let Some(ground) = &self.ground {
                 ground.control
             } else {
                 self.air_control
@@ -1684,30 +2894,68 @@ impl Player {
         }
     }
 
-    pub fn update(&mut self, camera: &Camera, input: &Input) {
+    fn emit_surface_sound(&mut self, kind: SurfaceSoundKind) {
+        let sound_set = self.ground.as_ref().and_then(|ground| ground.sound_set.clone());
+        self.surface_sounds.push(SurfaceSound { kind, sound_set });
+    }
+
+    /// Footsteps every `footstep::STRIDE` walked on the ground, and landings hard enough to hear
+    fn update_footsteps(&mut self, delta_time: f32) {
+        // Only set while the last physics step found ground
+        let grounded = self.coyote == COYOTE;
+        if grounded {
+            if self.airborne && self.fall_speed > footstep::LAND_SPEED {
+                self.emit_surface_sound(SurfaceSoundKind::Land);
+                self.stride = 0.0;
+            }
+            self.stride += vec3(self.velocity.x, 0.0, self.velocity.z).magnitude() * delta_time;
+            if self.stride >= footstep::STRIDE {
+                self.stride = 0.0;
+                self.emit_surface_sound(SurfaceSoundKind::Footstep);
+            }
+        } else {
+            self.fall_speed = -self.velocity.y;
+        }
+        self.airborne = !grounded;
+    }
+
+    pub fn update(&mut self, camera: &Camera, input: &Input, rules: &GameplayRules, physical_scene: &mut PhysicalScene, delta_time: f32) {
+        self.surface_sounds.clear();
         match self.movement {
             PlayerMovementMode::FirstPerson => {
+                self.update_footsteps(delta_time);
+                if input.action_pressed(Action::Crouch) {
+                    self.set_crouching(true, physical_scene);
+                } else if self.crouching && self.room_to_stand(physical_scene) {
+                    self.set_crouching(false, physical_scene);
+                }
+
                 let norm_dir = camera.direction.normalize();
                 let projected_forward = vec3(norm_dir.x, 0.0, norm_dir.z);
                 let mut movement_vector = Vector3::zero();
                 let control = self.control();
-                if !input.get_key_pressed(Key::Named(NamedKey::Control)) {
-                    if input.get_key_pressed(Key::Character("w".into())) {
-                        movement_vector += projected_forward.normalize();
-                    }
-                    if input.get_key_pressed(Key::Character("s".into())) {
-                        movement_vector -= projected_forward.normalize();
-                    }
-                    if input.get_key_pressed(Key::Character("a".into())) {
-                        movement_vector += camera.up.cross(camera.direction).normalize().mul_element_wise(vec3(1.0, 0.0, 1.0));
-                    }
-                    if input.get_key_pressed(Key::Character("d".into())) {
-                        movement_vector -= camera.up.cross(camera.direction).normalize().mul_element_wise(vec3(1.0, 0.0, 1.0));
-                    }
+                if input.action_pressed(Action::MoveForward) {
+                    movement_vector += projected_forward.normalize();
+                }
+                if input.action_pressed(Action::MoveBack) {
+                    movement_vector -= projected_forward.normalize();
+                }
+                if input.action_pressed(Action::MoveLeft) {
+                    movement_vector += camera.up.cross(camera.direction).normalize().mul_element_wise(vec3(1.0, 0.0, 1.0));
+                }
+                if input.action_pressed(Action::MoveRight) {
+                    movement_vector -= camera.up.cross(camera.direction).normalize().mul_element_wise(vec3(1.0, 0.0, 1.0));
                 }
 
-                if movement_vector.magnitude2() > 0.01 {
-                    let desired_velocity = movement_vector.normalize() * self.speed;
+                let moving = movement_vector.magnitude2() > 0.01;
+                let wants_sprint = rules.sprint && moving && !self.crouching && input.action_pressed(Action::Sprint);
+                let sprinting = rules.update_stamina(&mut self.stamina, &mut self.exhausted, wants_sprint, delta_time) && wants_sprint;
+                let ramp = delta_time / rules.sprint_ramp.max(0.01);
+                self.sprint = if sprinting { (self.sprint + ramp).min(1.0) } else { (self.sprint - ramp).max(0.0) };
+
+                if moving {
+                    let speed = if self.crouching { self.speed * CROUCH_SPEED } else { self.speed * rules.sprint_speed(self.sprint) };
+                    let desired_velocity = movement_vector.normalize() * speed;
                     let controlled_velocity = desired_velocity * control;
 
                     if desired_velocity.x < 0.0 {
@@ -1736,13 +2984,21 @@ impl Player {
                 }
 
                 if self.coyote > 0 {
-                    if input.get_key_just_pressed(Key::Named(NamedKey::Space)) {
-                        self.velocity.y = self.jump_velocity * self.ground.map(|s| s.jump).unwrap_or(1.0);
+                    if input.action_just_pressed(Action::Jump) {
+                        self.velocity.y = self.jump_velocity * self.ground.as_ref().map(|s| s.jump).unwrap_or(1.0);
+                        self.emit_surface_sound(SurfaceSoundKind::Jump);
                     }
                     self.coyote -= 1;
+                } else if rules.wall_jump {
+                    if let Some(wall_normal) = self.wall_normal {
+                        if input.action_just_pressed(Action::Jump) {
+                            self.velocity = rules.wall_jump_velocity(wall_normal);
+                            self.wall_normal = None;
+                        }
+                    }
                 }
             },
-            PlayerMovementMode::FollowCamera => ()
+            PlayerMovementMode::FollowCamera | PlayerMovementMode::Spectator => ()
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,20 @@
+//! Render regression test, run with `cargo test --features render-tests`<br>
+//! After a change that is meant to look different, update the golden images with `cargo run -- render-test --bless`
+#![cfg(feature = "render-tests")]
+
+use std::process::Command;
+
+#[test]
+fn renders_match_golden_images() {
+    let output = Command::new(env!("CARGO_BIN_EXE_viceptica"))
+        .arg("render-test")
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .expect("failed to start viceptica");
+    assert!(
+        output.status.success(),
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}